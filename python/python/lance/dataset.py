@@ -660,6 +660,7 @@ class LanceDataset(pa.dataset.Dataset):
         older_than: Optional[timedelta] = None,
         *,
         delete_unverified: bool = False,
+        keep_tagged: bool = True,
     ) -> CleanupStats:
         """
         Cleans up old versions of the dataset.
@@ -688,20 +689,28 @@ class LanceDataset(pa.dataset.Dataset):
             This should only be set to True if you can guarantee that no other process
             is currently working on this dataset.  Otherwise the dataset could be put
             into a corrupted state.
+
+        keep_tagged: bool, default True
+            Versions pointed to by tags are kept regardless of their age.  If
+            keep_tagged is False then they are removed like any other version, and
+            so are their tags.
         """
         if older_than is None:
             older_than = timedelta(days=14)
         return self._ds.cleanup_old_versions(
-            td_to_micros(older_than), delete_unverified
+            td_to_micros(older_than), delete_unverified, keep_tagged
         )
 
     def create_scalar_index(
         self,
         column: str,
-        index_type: Literal["BTREE"],
+        index_type: Literal[
+            "BTREE", "BITMAP", "NGRAM", "BLOOMFILTER", "LABEL_LIST", "JSON"
+        ],
         name: Optional[str] = None,
         *,
         replace: bool = True,
+        json_paths: Optional[List[str]] = None,
     ):
         """Create a scalar index on a column.
 
@@ -754,9 +763,28 @@ class LanceDataset(pa.dataset.Dataset):
         that use scalar indices will either have a ``ScalarIndexQuery`` relation or a
         ``MaterializeIndex`` operator.
 
-        Currently, the only type of scalar index available is ``BTREE``. This index
-        combines is inspired by the btree data structure although only the first few
-        layers of the btree are cached in memory.
+        There are four types of scalar index:
+
+        * ``BTREE``. This index is inspired by the btree data structure although only
+          the first few layers of the btree are cached in memory.
+        * ``BITMAP``. This index keeps a bitmap of the rows of each distinct value in
+          memory. It suits columns with few distinct values, e.g., a category, a
+          label or a status.
+        * ``NGRAM``. This index keeps a bitmap of the rows which contain each 3
+          characters of a string column.  It only speeds up substring filters, e.g.,
+          ``my_col LIKE '%lance%'`` or ``contains(my_col, 'lance')``.
+        * ``BLOOMFILTER``. This index keeps a bloom filter of the values of each
+          fragment.  It speeds up the point lookups, e.g., ``my_col = 'abc'`` or
+          ``my_col IN ('abc', 'def')``, on columns with many distinct values, e.g., a
+          uuid or a user id, by skipping the fragments without the values.
+        * ``LABEL_LIST``. This index keeps a bitmap of the rows whose list contains
+          each label, of a list column, e.g., tags.  It speeds up the filters
+          ``array_has_any(my_col, ['a', 'b'])``, ``array_has_all(my_col, ['a', 'b'])``
+          and ``array_has(my_col, 'a')``.
+        * ``JSON``. This index keeps a bitmap of the rows of each value at some
+          JSON paths, given by ``json_paths``, of a string column of JSON documents.
+          It speeds up the filters on the paths, e.g.,
+          ``json_extract(my_col, '$.type') = 'a'``.
 
         **Experimental API**
 
@@ -764,14 +792,19 @@ class LanceDataset(pa.dataset.Dataset):
         ----------
         column : str
             The column to be indexed.  Must be a boolean, integer, float,
-            or string column.
+            or string column, or a list column for a ``LABEL_LIST`` index.  A field
+            of a struct column is given by its path, e.g., ``"metadata.source"``.
         index_type : str
-            The type of the index.  Only ``"BTREE"`` is supported now.
+            The type of the index.  ``"BTREE"``, ``"BITMAP"``, ``"NGRAM"``,
+            ``"BLOOMFILTER"``, ``"LABEL_LIST"`` or ``"JSON"``.
         name : str, optional
             The index name. If not provided, it will be generated from the
             column name.
         replace : bool, default True
             Replace the existing index if it exists.
+        json_paths : list of str, optional
+            The JSON paths to index, e.g., ``["$.type", "$.user.id"]``.  Required by
+            a ``JSON`` index.
 
         Examples
         --------
@@ -803,11 +836,17 @@ class LanceDataset(pa.dataset.Dataset):
             )
 
         column = column[0]
-        if column not in self.schema.names:
+        field = _nested_field(self.schema, column)
+        if field is None:
             raise KeyError(f"{column} not found in schema")
 
-        field = self.schema.field(column)
-        if (
+        index_type = index_type.upper()
+        if index_type == "LABEL_LIST":
+            if not pa.types.is_list(field.type) and not pa.types.is_large_list(
+                field.type
+            ):
+                raise TypeError(f"LABEL_LIST index column {column} must be a list")
+        elif (
             not pa.types.is_integer(field.type)
             and not pa.types.is_floating(field.type)
             and not pa.types.is_boolean(field.type)
@@ -817,16 +856,34 @@ class LanceDataset(pa.dataset.Dataset):
                 f"Scalar index column {column} must be int, float, bool, or str"
             )
 
-        index_type = index_type.upper()
-        if index_type != "BTREE":
+        if index_type not in [
+            "BTREE",
+            "BITMAP",
+            "NGRAM",
+            "BLOOMFILTER",
+            "LABEL_LIST",
+            "JSON",
+        ]:
             raise NotImplementedError(
                 (
-                    'Only "BTREE" is supported for ',
-                    f"index_type.  Received {index_type}",
+                    'Only "BTREE", "BITMAP", "NGRAM", "BLOOMFILTER", "LABEL_LIST" and '
+                    f'"JSON" are supported for index_type.  Received {index_type}',
                 )
             )
+        if index_type == "NGRAM" and not pa.types.is_string(field.type):
+            raise TypeError(f"NGRAM index column {column} must be str")
+        if index_type == "BLOOMFILTER" and not (
+            pa.types.is_integer(field.type) or pa.types.is_string(field.type)
+        ):
+            raise TypeError(f"BLOOMFILTER index column {column} must be int or str")
+        if index_type == "JSON":
+            if not pa.types.is_string(field.type):
+                raise TypeError(f"JSON index column {column} must be str")
+            if not json_paths:
+                raise ValueError("JSON index requires json_paths")
 
-        self._ds.create_index([column], index_type, name, replace)
+        kwargs = {"json_paths": json_paths} if index_type == "JSON" else None
+        self._ds.create_index([column], index_type, name, replace, kwargs)
 
     def create_index(
         self,
@@ -885,6 +942,29 @@ class LanceDataset(pa.dataset.Dataset):
             Must have feature 'opq' enabled in Rust.
         - **max_opq_iterations**: the maximum number of iterations for training OPQ.
         - **ivf_centroids**: K-mean centroids for IVF clustering.
+        - **ivf_init**: how to initialize the IVF centroids before training,
+            "random" (default) or "kmeans++". Not used with ``ivf_centroids``.
+        - **seed**: seed of the random generator, to build the same index
+            from the same data.
+        - **mini_batch_size**: train IVF with mini-batches of this many vectors
+            streamed from the dataset, instead of loading the training sample
+            in memory.
+        - **max_partition_skew**: after training IVF, split the partitions
+            larger than this many times the average partition size, and drop
+            the smallest ones. Must be at least 1.0.
+        - **use_residual**: quantize the residuals of the vectors to their
+            IVF centroids (default), or the vectors themselves if False.
+            Used by IVF_PQ and IVF_SQ.
+        - **sample_rate**: number of vectors sampled per centroid to train IVF
+            and PQ. Defaults to 256.
+        - **max_training_rows**: max number of vectors sampled to train IVF
+            and PQ, regardless of ``sample_rate``.
+        - **num_threads**: max number of threads to train IVF and PQ, and to
+            assign the vectors to partitions. Defaults to the number of CPUs.
+        - **shuffle_memory_budget**: max bytes of the shuffled vectors buffered
+            in memory before they are spilled to disk. Defaults to 512 MiB.
+        - **num_parallel_partitions**: max number of partitions written
+            concurrently by the shuffle. Defaults to the number of CPUs.
 
         If ``index_type`` is "DISKANN", then the following parameters are optional:
 
@@ -956,9 +1036,11 @@ class LanceDataset(pa.dataset.Dataset):
                     f"Vector column {c} must be FixedSizeListArray "
                     f"1-dimensional FixedShapeTensorArray, got {field.type}"
                 )
-            if not pa.types.is_floating(field.type.value_type):
+            if not pa.types.is_floating(field.type.value_type) and not pa.types.is_int8(
+                field.type.value_type
+            ):
                 raise TypeError(
-                    f"Vector column {c} must have floating value type, "
+                    f"Vector column {c} must have floating or int8 value type, "
                     f"got {field.type.value_type}"
                 )
 
@@ -1027,6 +1109,19 @@ class LanceDataset(pa.dataset.Dataset):
         self._ds.create_index(column, index_type, name, replace, kwargs)
         return LanceDataset(self.uri, index_cache_size=index_cache_size)
 
+    def drop_index(self, name: str):
+        """Drop an index from the dataset.
+
+        The index is removed in a new version of the dataset. Its files are
+        deleted by :meth:`cleanup_old_versions` once no version refers to them.
+
+        Parameters
+        ----------
+        name: str
+            The name of the index to drop.
+        """
+        self._ds.drop_index(name)
+
     @staticmethod
     def _commit(
         base_uri: Union[str, Path],
@@ -1777,6 +1872,16 @@ class DatasetOptimizer:
         the new data to existing partitions.  This means an update is much quicker
         than retraining the entire index but may have less accuracy (especially
         if the new data exhibits new patterns, concepts, or trends)
+
+        An index may consist of several deltas, which are searched together.
+
+        Parameters
+        ----------
+        num_indices_to_merge: int, optional
+            The number of the latest deltas of each index to merge with the new
+            data into a single index file. ``0`` indexes the new data into a new
+            delta without rewriting the existing ones. By default, all the deltas
+            are merged.
         """
         self._dataset._ds.optimize_indices(**kwargs)
 
@@ -1931,6 +2036,19 @@ def _coerce_reader(
         )
 
 
+def _nested_field(schema: pa.Schema, path: str) -> Optional[pa.Field]:
+    """The field at a path like ``"metadata.source"``, or None if there is not one."""
+    names = path.split(".")
+    if names[0] not in schema.names:
+        return None
+    field = schema.field(names[0])
+    for name in names[1:]:
+        if not pa.types.is_struct(field.type) or field.type.get_field_index(name) < 0:
+            return None
+        field = field.type[field.type.get_field_index(name)]
+    return field
+
+
 def _validate_schema(schema: pa.Schema):
     """
     Make sure the metadata is valid utf8
@@ -32,17 +32,22 @@ use lance::dataset::{
 };
 use lance::index::IndexParams;
 use lance::index::{
-    scalar::ScalarIndexParams,
+    scalar::{ScalarIndexParams, ScalarIndexType},
     vector::{diskann::DiskANNParams, VectorIndexParams},
-    DatasetIndexExt,
+    DatasetIndexExt, OptimizeOptions,
 };
 use lance_arrow::as_fixed_size_list_array;
-use lance_core::{datatypes::Schema, format::Fragment, io::object_store::ObjectStoreParams};
+use lance_core::{
+    datatypes::Schema,
+    format::Fragment,
+    io::{commit::CommitConfig, object_store::ObjectStoreParams},
+};
 use lance_index::{
     vector::{ivf::IvfBuildParams, pq::PQBuildParams},
     IndexType,
 };
 use lance_linalg::distance::MetricType;
+use lance_linalg::kmeans::KMeanInit;
 use pyo3::exceptions::PyStopIteration;
 use pyo3::prelude::*;
 use pyo3::types::PySet;
@@ -166,11 +171,14 @@ impl Dataset {
         let mut params = ReadParams {
             index_cache_size: index_cache_size.unwrap_or(DEFAULT_INDEX_CACHE_SIZE),
             metadata_cache_size: metadata_cache_size.unwrap_or(DEFAULT_METADATA_CACHE_SIZE),
+            index_cache_max_bytes: None,
+            index_cache_ttl: None,
             session: None,
             store_options: Some(ObjectStoreParams {
                 block_size,
                 ..Default::default()
             }),
+            commit_retries: CommitConfig::default().num_retries,
         };
 
         if let Some(commit_handler) = commit_handler {
@@ -222,21 +230,14 @@ impl Dataset {
 
     /// Load index metadata
     fn load_indices(self_: PyRef<'_, Self>) -> PyResult<Vec<PyObject>> {
-        let index_metadata = RT
-            .block_on(Some(self_.py()), self_.ds.load_indices())?
+        let indices = RT
+            .block_on(Some(self_.py()), self_.ds.list_indices())?
             .map_err(|err| PyValueError::new_err(err.to_string()))?;
         let py = self_.py();
-        Ok(index_metadata
-            .iter()
+        Ok(indices
+            .into_iter()
             .map(|idx| {
                 let dict = PyDict::new(py);
-                let schema = self_.ds.schema();
-                let field_names = schema
-                    .project_by_ids(idx.fields.as_slice())
-                    .fields
-                    .iter()
-                    .map(|f| f.name.clone())
-                    .collect::<Vec<_>>();
 
                 let fragment_set = PySet::empty(py).unwrap();
                 if let Some(bitmap) = &idx.fragment_bitmap {
@@ -245,14 +246,10 @@ impl Dataset {
                     }
                 }
 
-                dict.set_item("name", idx.name.clone()).unwrap();
-                // TODO: once we add more than vector indices, we need to:
-                // 1. Change protos and write path to persist index type
-                // 2. Use the new field from idx instead of hard coding it to Vector
-                dict.set_item("type", IndexType::Vector.to_string())
-                    .unwrap();
+                dict.set_item("name", idx.name).unwrap();
+                dict.set_item("type", idx.index_type.to_string()).unwrap();
                 dict.set_item("uuid", idx.uuid.to_string()).unwrap();
-                dict.set_item("fields", field_names).unwrap();
+                dict.set_item("fields", idx.columns).unwrap();
                 dict.set_item("version", idx.dataset_version).unwrap();
                 dict.set_item("fragment_ids", fragment_set).unwrap();
                 dict.to_object(py)
@@ -578,12 +575,14 @@ impl Dataset {
         &self,
         older_than_micros: i64,
         delete_unverified: Option<bool>,
+        keep_tagged: Option<bool>,
     ) -> PyResult<CleanupStats> {
         let older_than = Duration::microseconds(older_than_micros);
         let cleanup_stats = RT
             .block_on(
                 None,
-                self.ds.cleanup_old_versions(older_than, delete_unverified),
+                self.ds
+                    .cleanup_old_versions(older_than, delete_unverified, keep_tagged),
             )?
             .map_err(|err| PyIOError::new_err(err.to_string()))?;
         Ok(CleanupStats {
@@ -592,9 +591,24 @@ impl Dataset {
         })
     }
 
-    fn optimize_indices(&mut self, _kwargs: Option<&PyDict>) -> PyResult<()> {
+    #[pyo3(signature = (**kwargs))]
+    fn optimize_indices(&mut self, kwargs: Option<&PyDict>) -> PyResult<()> {
         let mut new_self = self.ds.as_ref().clone();
-        RT.block_on(None, new_self.optimize_indices())?
+        let mut options = OptimizeOptions::default();
+        if let Some(kwargs) = kwargs {
+            if let Some(n) = kwargs.get_item("num_indices_to_merge") {
+                options.num_indices_to_merge = Some(PyAny::downcast::<PyInt>(n)?.extract()?);
+            }
+        }
+        RT.block_on(None, new_self.optimize_indices(&options))?
+            .map_err(|err| PyIOError::new_err(err.to_string()))?;
+        self.ds = Arc::new(new_self);
+        Ok(())
+    }
+
+    fn drop_index(&mut self, name: &str) -> PyResult<()> {
+        let mut new_self = self.ds.as_ref().clone();
+        RT.block_on(None, new_self.drop_index(name))?
             .map_err(|err| PyIOError::new_err(err.to_string()))?;
         self.ds = Arc::new(new_self);
         Ok(())
@@ -609,7 +623,9 @@ impl Dataset {
         kwargs: Option<&PyDict>,
     ) -> PyResult<()> {
         let idx_type = match index_type.to_uppercase().as_str() {
-            "BTREE" => IndexType::Scalar,
+            "BTREE" | "BITMAP" | "NGRAM" | "BLOOMFILTER" | "LABEL_LIST" | "JSON" => {
+                IndexType::Scalar
+            }
             "IVF_PQ" | "DISKANN" => IndexType::Vector,
             _ => {
                 return Err(PyValueError::new_err(format!(
@@ -621,6 +637,17 @@ impl Dataset {
         // Only VectorParams are supported.
         let params: Box<dyn IndexParams> = match index_type.to_uppercase().as_str() {
             "BTREE" => Box::<ScalarIndexParams>::default(),
+            "BITMAP" => Box::new(ScalarIndexParams::new(ScalarIndexType::Bitmap)),
+            "NGRAM" => Box::new(ScalarIndexParams::new(ScalarIndexType::NGram)),
+            "BLOOMFILTER" => Box::new(ScalarIndexParams::new(ScalarIndexType::BloomFilter)),
+            "LABEL_LIST" => Box::new(ScalarIndexParams::new(ScalarIndexType::LabelList)),
+            "JSON" => {
+                let paths = kwargs
+                    .and_then(|kwargs| kwargs.get_item("json_paths"))
+                    .ok_or_else(|| PyValueError::new_err("JSON index requires json_paths"))?
+                    .extract::<Vec<String>>()?;
+                Box::new(ScalarIndexParams::json(paths))
+            }
             "IVF_PQ" => {
                 let mut ivf_params = IvfBuildParams::default();
                 let mut pq_params = PQBuildParams::default();
@@ -644,12 +671,6 @@ impl Dataset {
                     };
 
                     if let Some(o) = kwargs.get_item("use_opq") {
-                        #[cfg(not(feature = "opq"))]
-                        if PyAny::downcast::<PyBool>(o)?.extract()? {
-                            return Err(PyValueError::new_err(
-                                "Feature 'opq' is not installed.".to_string(),
-                            ));
-                        }
                         pq_params.use_opq = PyAny::downcast::<PyBool>(o)?.extract()?
                     };
 
@@ -657,6 +678,64 @@ impl Dataset {
                         pq_params.max_opq_iters = PyAny::downcast::<PyInt>(o)?.extract()?
                     };
 
+                    if let Some(i) = kwargs.get_item("ivf_init") {
+                        ivf_params.init = match i.to_string().to_lowercase().as_str() {
+                            "random" => KMeanInit::Random,
+                            "kmeans++" => KMeanInit::KMeanPlusPlus,
+                            _ => {
+                                return Err(PyValueError::new_err(format!(
+                                    "Unknown IVF initialization method: {i}"
+                                )))
+                            }
+                        }
+                    };
+
+                    if let Some(n) = kwargs.get_item("mini_batch_size") {
+                        ivf_params.mini_batch_size = Some(PyAny::downcast::<PyInt>(n)?.extract()?)
+                    };
+
+                    if let Some(s) = kwargs.get_item("max_partition_skew") {
+                        ivf_params.max_partition_skew = Some(s.extract()?)
+                    };
+
+                    if let Some(r) = kwargs.get_item("use_residual") {
+                        ivf_params.use_residual = PyAny::downcast::<PyBool>(r)?.extract()?
+                    };
+
+                    if let Some(n) = kwargs.get_item("sample_rate") {
+                        let sample_rate: usize = PyAny::downcast::<PyInt>(n)?.extract()?;
+                        ivf_params.sample_rate = sample_rate;
+                        pq_params.sample_rate = sample_rate;
+                    };
+
+                    if let Some(n) = kwargs.get_item("max_training_rows") {
+                        let max_rows: usize = PyAny::downcast::<PyInt>(n)?.extract()?;
+                        ivf_params.max_training_rows = Some(max_rows);
+                        pq_params.max_training_rows = Some(max_rows);
+                    };
+
+                    if let Some(n) = kwargs.get_item("num_threads") {
+                        let num_threads: usize = PyAny::downcast::<PyInt>(n)?.extract()?;
+                        ivf_params.num_threads = Some(num_threads);
+                        pq_params.num_threads = Some(num_threads);
+                    };
+
+                    if let Some(n) = kwargs.get_item("shuffle_memory_budget") {
+                        ivf_params.shuffle_memory_budget =
+                            Some(PyAny::downcast::<PyInt>(n)?.extract()?)
+                    };
+
+                    if let Some(n) = kwargs.get_item("num_parallel_partitions") {
+                        ivf_params.num_parallel_partitions =
+                            Some(PyAny::downcast::<PyInt>(n)?.extract()?)
+                    };
+
+                    if let Some(s) = kwargs.get_item("seed") {
+                        let seed: u64 = PyAny::downcast::<PyInt>(s)?.extract()?;
+                        ivf_params.seed = Some(seed);
+                        pq_params.seed = Some(seed);
+                    };
+
                     if let Some(c) = kwargs.get_item("ivf_centroids") {
                         let batch = RecordBatch::from_pyarrow(c)?;
                         if "_ivf_centroids" != batch.schema().field(0).name() {
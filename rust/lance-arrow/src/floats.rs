@@ -21,6 +21,7 @@ use std::{
 };
 
 use arrow_array::{
+    cast::AsArray,
     types::{Float16Type, Float32Type, Float64Type},
     Array, Float16Array, Float32Array, Float64Array,
 };
@@ -198,3 +199,46 @@ pub fn coerce_float_vector(input: &Float32Array, float_type: FloatType) -> Resul
         ))),
     }
 }
+
+/// Convert a float array to a float32 array.
+pub fn to_float32_array(input: &dyn Array) -> Result<Float32Array> {
+    match input.data_type() {
+        DataType::Float16 => Ok(Float32Array::from_iter_values(
+            input
+                .as_primitive::<Float16Type>()
+                .values()
+                .iter()
+                .map(|v| v.to_f32()),
+        )),
+        DataType::Float32 => Ok(input.as_primitive::<Float32Type>().clone()),
+        DataType::Float64 => Ok(Float32Array::from_iter_values(
+            input
+                .as_primitive::<Float64Type>()
+                .values()
+                .iter()
+                .map(|v| *v as f32),
+        )),
+        _ => Err(crate::ArrowError::InvalidArgumentError(format!(
+            "Expect a float array, got {}",
+            input.data_type()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_float32_array() {
+        let values = vec![0.5_f32, -1.0, 2.25];
+        let f16s = Float16Array::from_iter_values(values.iter().map(|v| f16::from_f32(*v)));
+        assert_eq!(to_float32_array(&f16s).unwrap().values(), values.as_slice());
+
+        let f64s = Float64Array::from_iter_values(values.iter().map(|v| *v as f64));
+        assert_eq!(to_float32_array(&f64s).unwrap().values(), values.as_slice());
+
+        let ints = arrow_array::Int32Array::from(vec![1, 2]);
+        assert!(to_float32_array(&ints).is_err());
+    }
+}
@@ -225,6 +225,9 @@ pub trait FixedSizeListArrayExt {
     /// assert_eq!(sampled.values().len(), 160);
     /// ```
     fn sample(&self, n: usize) -> Result<FixedSizeListArray>;
+
+    /// Sample `n` rows from the [FixedSizeListArray] with a random generator.
+    fn sample_with(&self, n: usize, rng: &mut impl Rng) -> Result<FixedSizeListArray>;
 }
 
 impl FixedSizeListArrayExt for FixedSizeListArray {
@@ -236,11 +239,15 @@ impl FixedSizeListArrayExt for FixedSizeListArray {
     }
 
     fn sample(&self, n: usize) -> Result<FixedSizeListArray> {
+        let mut rng = SmallRng::from_entropy();
+        self.sample_with(n, &mut rng)
+    }
+
+    fn sample_with(&self, n: usize, rng: &mut impl Rng) -> Result<FixedSizeListArray> {
         if n >= self.len() {
             return Ok(self.clone());
         }
-        let mut rng = SmallRng::from_entropy();
-        let chosen = (0..self.len() as u32).choose_multiple(&mut rng, n);
+        let chosen = (0..self.len() as u32).choose_multiple(rng, n);
         take(self, &UInt32Array::from(chosen), None).map(|arr| arr.as_fixed_size_list().clone())
     }
 }
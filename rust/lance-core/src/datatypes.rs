@@ -26,7 +26,7 @@ mod schema;
 
 use crate::format::pb;
 use crate::{Error, Result};
-pub use field::Field;
+pub use field::{Field, BLOB_KEY, DEFAULT_VALUE_KEY, UNIQUE_KEY};
 pub use schema::Schema;
 
 /// LogicalType is a string presentation of arrow type.
@@ -36,6 +36,17 @@ use crate::{
     Error, Result,
 };
 
+/// The key of the field metadata holding the default value of a column, a SQL literal
+/// read for the rows of the fragments written before the column was added.
+pub const DEFAULT_VALUE_KEY: &str = "lance:default_value";
+
+/// The key of the field metadata marking the unique key column of a dataset.
+pub const UNIQUE_KEY: &str = "lance:unique";
+
+/// The key of the field metadata marking a binary column whose values are stored out
+/// of line, in blob files next to its data files.
+pub const BLOB_KEY: &str = "lance:blob";
+
 /// Lance Schema Field
 ///
 #[derive(Debug, Clone, PartialEq)]
@@ -75,6 +86,46 @@ impl Field {
             .map(String::as_str)
     }
 
+    /// The default value of the column, see [`DEFAULT_VALUE_KEY`].
+    pub fn default_value(&self) -> Option<&str> {
+        self.metadata.get(DEFAULT_VALUE_KEY).map(String::as_str)
+    }
+
+    pub fn set_default_value(&mut self, value: Option<String>) {
+        match value {
+            Some(value) => self.metadata.insert(DEFAULT_VALUE_KEY.to_string(), value),
+            None => self.metadata.remove(DEFAULT_VALUE_KEY),
+        };
+    }
+
+    /// Whether the field is the unique key of the dataset, see [`UNIQUE_KEY`].
+    pub fn is_unique(&self) -> bool {
+        self.metadata.get(UNIQUE_KEY).map(String::as_str) == Some("true")
+    }
+
+    pub fn set_unique(&mut self, unique: bool) {
+        if unique {
+            self.metadata
+                .insert(UNIQUE_KEY.to_string(), "true".to_string());
+        } else {
+            self.metadata.remove(UNIQUE_KEY);
+        }
+    }
+
+    /// Whether the values of the field are stored in blob files, see [`BLOB_KEY`].
+    pub fn is_blob(&self) -> bool {
+        self.metadata.get(BLOB_KEY).map(String::as_str) == Some("true")
+    }
+
+    pub fn set_blob(&mut self, blob: bool) {
+        if blob {
+            self.metadata
+                .insert(BLOB_KEY.to_string(), "true".to_string());
+        } else {
+            self.metadata.remove(BLOB_KEY);
+        }
+    }
+
     pub fn child(&self, name: &str) -> Option<&Self> {
         self.children.iter().find(|f| f.name == name)
     }
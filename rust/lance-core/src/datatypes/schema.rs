@@ -244,6 +244,26 @@ impl Schema {
         None
     }
 
+    /// Get the path of the field `id`, e.g., `a.b` for the field `b` of the struct field
+    /// `a`, which can be passed to [Self::field] and [Self::project].
+    pub fn field_path(&self, id: impl Into<i32>) -> Option<String> {
+        fn path_of(field: &Field, id: i32) -> Option<Vec<&str>> {
+            if field.id == id {
+                return Some(vec![field.name.as_str()]);
+            }
+            field.children.iter().find_map(|child| {
+                let mut path = path_of(child, id)?;
+                path.insert(0, field.name.as_str());
+                Some(path)
+            })
+        }
+        let id = id.into();
+        self.fields
+            .iter()
+            .find_map(|field| path_of(field, id))
+            .map(|path| path.join("."))
+    }
+
     // TODO: pub(crate)
     pub fn mut_field_by_id(&mut self, id: impl Into<i32>) -> Option<&mut Field> {
         let id = id.into();
@@ -904,5 +924,10 @@ mod tests {
 
         let field = schema.field_by_id(3).unwrap();
         assert_eq!(field.name, "f2");
+
+        assert_eq!(schema.field_path(0).unwrap(), "a");
+        assert_eq!(schema.field_path(3).unwrap(), "b.f2");
+        assert_eq!(schema.field(&schema.field_path(4).unwrap()).unwrap().id, 4);
+        assert!(schema.field_path(10).is_none());
     }
 }
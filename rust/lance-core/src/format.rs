@@ -22,12 +22,14 @@ mod index;
 mod manifest;
 mod metadata;
 mod page_table;
+mod row_id_sequence;
 
 pub use fragment::*;
 pub use index::Index;
 pub use manifest::{Manifest, WriterVersion};
 pub use metadata::{Metadata, StatisticsMetadata};
 pub use page_table::{PageInfo, PageTable};
+pub use row_id_sequence::{RowIdSegment, RowIdSequence};
 
 use crate::{Error, Result};
 
@@ -19,7 +19,7 @@ use serde::{Deserialize, Serialize};
 
 use crate::datatypes::Schema;
 use crate::error::Result;
-use crate::format::pb;
+use crate::format::{pb, RowIdSequence};
 
 /// Lance Data File
 ///
@@ -132,6 +132,16 @@ pub struct Fragment {
     /// unknown. This is only optional for legacy reasons. All new tables should
     /// have this set.
     pub physical_rows: Option<usize>,
+
+    /// The stable row ids of the rows of the fragment, including the deleted
+    /// ones. This is only set if the dataset uses stable row ids.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub row_id_sequence: Option<RowIdSequence>,
+
+    /// The values of the partition columns of the rows of the fragment, formatted
+    /// as strings, if it was written by a partitioned write.
+    #[serde(skip_serializing_if = "Vec::is_empty", default)]
+    pub partition_values: Vec<Option<String>>,
 }
 
 impl Fragment {
@@ -141,6 +151,8 @@ impl Fragment {
             files: vec![],
             deletion_file: None,
             physical_rows: None,
+            row_id_sequence: None,
+            partition_values: Vec::new(),
         }
     }
 
@@ -172,6 +184,8 @@ impl Fragment {
             files: vec![DataFile::new(path, schema)],
             deletion_file: None,
             physical_rows,
+            row_id_sequence: None,
+            partition_values: Vec::new(),
         }
     }
 
@@ -200,6 +214,12 @@ impl From<&pb::DataFragment> for Fragment {
             files: p.files.iter().map(DataFile::from).collect(),
             deletion_file: p.deletion_file.as_ref().map(DeletionFile::from),
             physical_rows,
+            row_id_sequence: p.row_id_sequence.as_ref().map(RowIdSequence::from),
+            partition_values: p
+                .partition_values
+                .iter()
+                .map(|value| value.value.clone())
+                .collect(),
         }
     }
 }
@@ -223,6 +243,14 @@ impl From<&Fragment> for pb::DataFragment {
             files: f.files.iter().map(pb::DataFile::from).collect(),
             deletion_file,
             physical_rows: f.physical_rows.unwrap_or_default() as u64,
+            row_id_sequence: f.row_id_sequence.as_ref().map(pb::RowIdSequence::from),
+            partition_values: f
+                .partition_values
+                .iter()
+                .map(|value| pb::PartitionValue {
+                    value: value.clone(),
+                })
+                .collect(),
         }
     }
 }
@@ -336,6 +364,11 @@ mod tests {
         let proto = pb::DataFragment::from(&fragment);
         let fragment2 = Fragment::from(&proto);
         assert_eq!(fragment, fragment2);
+
+        fragment.partition_values = vec![Some("2024-01-01".to_string()), None];
+        let proto = pb::DataFragment::from(&fragment);
+        let fragment2 = Fragment::from(&proto);
+        assert_eq!(fragment, fragment2);
     }
 
     #[test]
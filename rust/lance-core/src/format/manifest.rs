@@ -69,6 +69,15 @@ pub struct Manifest {
 
     /// The path to the transaction file, relative to the root of the dataset
     pub transaction_file: Option<String>,
+
+    /// The next stable row id to assign, if the dataset uses stable row ids
+    pub next_row_id: u64,
+
+    /// The key-value configuration of the dataset
+    pub config: HashMap<String, String>,
+
+    /// The columns the data files are partitioned by, if any
+    pub partition_columns: Vec<String>,
 }
 
 impl Manifest {
@@ -86,6 +95,9 @@ impl Manifest {
             writer_feature_flags: 0,
             max_fragment_id: 0,
             transaction_file: None,
+            next_row_id: 0,
+            config: HashMap::new(),
+            partition_columns: Vec::new(),
         }
     }
 
@@ -107,6 +119,9 @@ impl Manifest {
             writer_feature_flags: 0, // These will be set on commit
             max_fragment_id: previous.max_fragment_id,
             transaction_file: None,
+            next_row_id: previous.next_row_id,
+            config: previous.config.clone(),
+            partition_columns: previous.partition_columns.clone(),
         }
     }
 
@@ -287,6 +302,9 @@ impl From<pb::Manifest> for Manifest {
             } else {
                 Some(p.transaction_file)
             },
+            next_row_id: p.next_row_id,
+            config: p.config,
+            partition_columns: p.partition_columns,
         }
     }
 }
@@ -324,6 +342,9 @@ impl From<&Manifest> for pb::Manifest {
             writer_feature_flags: m.writer_feature_flags,
             max_fragment_id: m.max_fragment_id,
             transaction_file: m.transaction_file.clone().unwrap_or_default(),
+            next_row_id: m.next_row_id,
+            config: m.config.clone(),
+            partition_columns: m.partition_columns.clone(),
         }
     }
 }
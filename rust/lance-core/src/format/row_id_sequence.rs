@@ -0,0 +1,218 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::pb;
+
+/// A run of stable row ids.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RowIdSegment {
+    /// The contiguous ids of the range.
+    Range(Range<u64>),
+    /// Arbitrary ids.
+    Array(Vec<u64>),
+}
+
+impl RowIdSegment {
+    pub fn len(&self) -> usize {
+        match self {
+            Self::Range(range) => (range.end - range.start) as usize,
+            Self::Array(values) => values.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, offset: usize) -> Option<u64> {
+        match self {
+            Self::Range(range) => {
+                let id = range.start + offset as u64;
+                (id < range.end).then_some(id)
+            }
+            Self::Array(values) => values.get(offset).copied(),
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+        match self {
+            Self::Range(range) => Box::new(range.clone()),
+            Self::Array(values) => Box::new(values.iter().copied()),
+        }
+    }
+}
+
+/// The stable row ids of the rows of a fragment, in the order of the rows.
+///
+/// The ids are stored as runs of contiguous ids where possible, so the sequence of
+/// a fragment written at once is a single range.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowIdSequence(pub Vec<RowIdSegment>);
+
+impl RowIdSequence {
+    pub fn len(&self) -> usize {
+        self.0.iter().map(RowIdSegment::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(RowIdSegment::is_empty)
+    }
+
+    /// The stable row id of the row at `offset` in the fragment.
+    pub fn get(&self, mut offset: usize) -> Option<u64> {
+        for segment in &self.0 {
+            if offset < segment.len() {
+                return segment.get(offset);
+            }
+            offset -= segment.len();
+        }
+        None
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        self.0.iter().flat_map(RowIdSegment::iter)
+    }
+}
+
+impl From<Range<u64>> for RowIdSequence {
+    fn from(range: Range<u64>) -> Self {
+        Self(vec![RowIdSegment::Range(range)])
+    }
+}
+
+impl FromIterator<u64> for RowIdSequence {
+    /// Collect the ids, keeping the runs of at least two contiguous ids as ranges.
+    fn from_iter<T: IntoIterator<Item = u64>>(iter: T) -> Self {
+        let mut segments = Vec::new();
+        let mut values = Vec::new();
+        let mut run = 0..0;
+        for id in iter {
+            if !run.is_empty() && run.end == id {
+                run.end += 1;
+            } else {
+                push_run(
+                    &mut segments,
+                    &mut values,
+                    std::mem::replace(&mut run, id..id + 1),
+                );
+            }
+        }
+        push_run(&mut segments, &mut values, run);
+        if !values.is_empty() {
+            segments.push(RowIdSegment::Array(values));
+        }
+        Self(segments)
+    }
+}
+
+/// Push a run of contiguous ids, either as a range or to the pending `values`.
+fn push_run(segments: &mut Vec<RowIdSegment>, values: &mut Vec<u64>, run: Range<u64>) {
+    if run.end - run.start < 2 {
+        values.extend(run);
+        return;
+    }
+    if !values.is_empty() {
+        segments.push(RowIdSegment::Array(std::mem::take(values)));
+    }
+    segments.push(RowIdSegment::Range(run));
+}
+
+impl From<&pb::RowIdSequence> for RowIdSequence {
+    fn from(p: &pb::RowIdSequence) -> Self {
+        Self(
+            p.segments
+                .iter()
+                .filter_map(|segment| match &segment.segment {
+                    Some(pb::row_id_sequence::segment::Segment::Range(range)) => {
+                        Some(RowIdSegment::Range(range.start..range.end))
+                    }
+                    Some(pb::row_id_sequence::segment::Segment::Array(array)) => {
+                        Some(RowIdSegment::Array(array.values.clone()))
+                    }
+                    None => None,
+                })
+                .collect(),
+        )
+    }
+}
+
+impl From<&RowIdSequence> for pb::RowIdSequence {
+    fn from(sequence: &RowIdSequence) -> Self {
+        Self {
+            segments: sequence
+                .0
+                .iter()
+                .map(|segment| pb::row_id_sequence::Segment {
+                    segment: Some(match segment {
+                        RowIdSegment::Range(range) => pb::row_id_sequence::segment::Segment::Range(
+                            pb::row_id_sequence::Range {
+                                start: range.start,
+                                end: range.end,
+                            },
+                        ),
+                        RowIdSegment::Array(values) => {
+                            pb::row_id_sequence::segment::Segment::Array(
+                                pb::row_id_sequence::Array {
+                                    values: values.clone(),
+                                },
+                            )
+                        }
+                    }),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_iter() {
+        let sequence = RowIdSequence::from_iter([3, 4, 5, 9, 1, 2, 7]);
+        assert_eq!(
+            sequence.0,
+            vec![
+                RowIdSegment::Range(3..6),
+                RowIdSegment::Array(vec![9]),
+                RowIdSegment::Range(1..3),
+                RowIdSegment::Array(vec![7]),
+            ]
+        );
+        assert_eq!(sequence.len(), 7);
+        assert_eq!(sequence.iter().collect::<Vec<_>>(), [3, 4, 5, 9, 1, 2, 7]);
+        assert_eq!(sequence.get(3), Some(9));
+        assert_eq!(sequence.get(5), Some(2));
+        assert_eq!(sequence.get(7), None);
+
+        assert!(RowIdSequence::from_iter([]).is_empty());
+        assert_eq!(
+            RowIdSequence::from_iter(10..20),
+            RowIdSequence::from(10..20)
+        );
+    }
+
+    #[test]
+    fn test_roundtrip_row_id_sequence() {
+        let sequence = RowIdSequence::from_iter([3, 4, 5, 9, 1, 2, 7]);
+        let proto = pb::RowIdSequence::from(&sequence);
+        assert_eq!(RowIdSequence::from(&proto), sequence);
+    }
+}
@@ -455,6 +455,9 @@ impl Debug for RenameCommitHandler {
 
 #[derive(Debug, Clone)]
 pub struct CommitConfig {
+    /// The number of times a commit is retried after a concurrent commit, on top
+    /// of the first attempt, so a commit is attempted at most `num_retries + 1`
+    /// times. Zero disables the retries.
     pub num_retries: u32,
     // TODO: add isolation_level
 }
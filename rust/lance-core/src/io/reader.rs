@@ -16,6 +16,7 @@
 
 // Standard
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ops::{Range, RangeTo};
 use std::sync::Arc;
 
@@ -50,7 +51,7 @@ use crate::io::utils::{read_metadata_offset, read_struct_from_buf};
 use crate::{
     cache::FileMetadataCache,
     datatypes::{Field, Schema},
-    encodings::{dictionary::DictionaryDecoder, AsyncIndex},
+    encodings::{dictionary::DictionaryDecoder, AsyncIndex, Encoding},
     format::{pb, Fragment, Index, Manifest, Metadata, PageInfo, PageTable, MAGIC},
     io::{
         object_store::ObjectStore, read_fixed_stride_array, read_message, read_struct,
@@ -262,7 +263,7 @@ impl FileReader {
                         &fragment
                             .files
                             .iter()
-                            .find(|f| path.to_string().ends_with(&f.path))
+                            .find(|f| path.to_string().ends_with(f.path.trim_start_matches('/')))
                             .ok_or_else(|| Error::Internal {
                                 message: format!(
                                     "File {} not found in fragment {:?}",
@@ -361,7 +362,8 @@ impl FileReader {
                         reader,
                         stats_meta.page_table_position,
                         stats_meta.leaf_field_ids.len() as i32,
-                        metadata.num_batches() as i32,
+                        // The statistics of all the pages are written as one batch.
+                        1,
                         0,
                     )
                     .await?,
@@ -538,8 +540,39 @@ impl FileReader {
         Ok(tokio::task::spawn_blocking(move || concat_batches(&schema, &batches)).await??)
     }
 
+    /// The schema of the page-level statistics of the fields `field_ids`, or None if the
+    /// file has no statistics of any of them.
+    ///
+    /// The statistics of the field with id `i` are the struct field named `i`, whose
+    /// children are `null_count`, `min_value` and `max_value`.
+    pub fn page_stats_schema(&self, field_ids: &[i32]) -> Option<Schema> {
+        let stats_schema = &self.metadata.stats_metadata.as_ref()?.schema;
+        let fields = stats_schema
+            .fields
+            .iter()
+            .filter(|field| {
+                field
+                    .name
+                    .parse::<i32>()
+                    .is_ok_and(|id| field_ids.contains(&id))
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        if fields.is_empty() {
+            None
+        } else {
+            Some(Schema {
+                fields,
+                metadata: stats_schema.metadata.clone(),
+            })
+        }
+    }
+
+    /// Read the page-level statistics of `projection`, a projection of the statistics
+    /// schema, e.g., from [Self::page_stats_schema]. Each row is the statistics of a page.
     pub async fn read_page_stats(&self, projection: &Schema) -> Result<Option<RecordBatch>> {
         if let Some(stats_page_table) = self.stats_page_table.as_ref() {
+            // We box this because otherwise we get a higher-order lifetime error.
             let arrays = futures::stream::iter(&projection.fields)
                 .map(|field| async move {
                     read_array(
@@ -553,6 +586,7 @@ impl FileReader {
                 })
                 .buffered(num_cpus::get())
                 .try_collect::<Vec<_>>()
+                .boxed()
                 .await?;
 
             let schema = ArrowSchema::from(projection);
@@ -562,6 +596,66 @@ impl FileReader {
             Ok(None)
         }
     }
+
+    /// The number of bytes of each of the fields `field_ids`, which must be all the
+    /// fields stored in the file.
+    ///
+    /// The pages are written back to back, so each page spans from the end of the page
+    /// written before it to the end of its own data. This includes the values of a
+    /// var-length page, which are written before the offsets it points to.
+    pub fn field_sizes(&self, field_ids: &[i32]) -> HashMap<i32, usize> {
+        let mut pages = Vec::new();
+        for field_id in field_ids {
+            let Some(field) = self.schema().field_by_id(*field_id) else {
+                continue;
+            };
+            for batch_id in 0..self.num_batches() as i32 {
+                if let Some(page) = self.page_table.get(*field_id, batch_id) {
+                    let end = page.position + page_data_size(field, page.length);
+                    pages.push((page.position, end, *field_id));
+                }
+            }
+        }
+        pages.sort_unstable();
+
+        let mut sizes = field_ids
+            .iter()
+            .map(|id| (*id, 0))
+            .collect::<HashMap<_, _>>();
+        let mut last_end = 0;
+        for (_, end, field_id) in pages {
+            *sizes.entry(field_id).or_default() += end.saturating_sub(last_end);
+            last_end = last_end.max(end);
+        }
+        sizes
+    }
+}
+
+/// The number of bytes written at the position of a page of `length` items.
+fn page_data_size(field: &Field, length: usize) -> usize {
+    match (&field.encoding, field.data_type()) {
+        // The offsets, as 64-bit positions in the file.
+        (Some(Encoding::VarBinary), _) => (length + 1) * 8,
+        (Some(Encoding::Dictionary), DataType::Dictionary(key_type, _)) => {
+            length * key_type.byte_width()
+        }
+        (Some(Encoding::Plain), data_type) => plain_data_size(&data_type, length),
+        _ => 0,
+    }
+}
+
+fn plain_data_size(data_type: &DataType, length: usize) -> usize {
+    match data_type {
+        DataType::Null => 0,
+        DataType::Boolean => (length + 7) / 8,
+        DataType::FixedSizeList(items, size) => {
+            plain_data_size(items.data_type(), length * *size as usize)
+        }
+        // The offsets of a list page, which are written with one more item than the list.
+        DataType::List(_) => length * 4,
+        DataType::LargeList(_) => length * 8,
+        _ => length * data_type.byte_width(),
+    }
 }
 
 /// Stream desired full batches from the file.
@@ -1695,6 +1789,55 @@ mod tests {
         assert_eq!(batch, actual_batch);
     }
 
+    #[tokio::test]
+    async fn test_field_sizes() {
+        let arrow_schema = ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int64, false),
+            ArrowField::new("b", DataType::Boolean, false),
+            ArrowField::new("s", DataType::Utf8, false),
+            ArrowField::new(
+                "l",
+                DataType::List(Arc::new(ArrowField::new("item", DataType::Int32, true))),
+                false,
+            ),
+        ]);
+        let schema = Schema::try_from(&arrow_schema).unwrap();
+
+        let store = ObjectStore::memory();
+        let path = Path::from("/field_sizes");
+        let mut file_writer = FileWriter::try_new(&store, &path, schema, &Default::default())
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            let mut list_builder = ListBuilder::new(Int32Builder::new());
+            for i in 0..10 {
+                list_builder.values().append_slice(&vec![i; i as usize]);
+                list_builder.append(true);
+            }
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(Int64Array::from_iter_values(0..10)),
+                Arc::new(BooleanArray::from_iter((0..10).map(|i| Some(i % 2 == 0)))),
+                Arc::new(StringArray::from_iter_values(
+                    (0..10).map(|i| "x".repeat(i)),
+                )),
+                Arc::new(list_builder.finish()),
+            ];
+            let batch = RecordBatch::try_new(Arc::new(arrow_schema.clone()), columns).unwrap();
+            file_writer.write(&[batch]).await.unwrap();
+        }
+        file_writer.finish().await.unwrap();
+
+        let reader = FileReader::try_new(&store, &path).await.unwrap();
+        let field_ids = reader.schema().field_ids();
+        let sizes = reader.field_sizes(&field_ids);
+        let size_of = |name: &str| sizes[&reader.schema().field(name).unwrap().id];
+        assert_eq!(size_of("i"), 2 * 10 * 8);
+        assert_eq!(size_of("b"), 2 * 2);
+        assert_eq!(size_of("s"), 2 * (45 + 11 * 8));
+        assert_eq!(size_of("l"), 2 * 11 * 4);
+        assert_eq!(size_of("l.item"), 2 * 45 * 4);
+    }
+
     #[tokio::test]
     async fn test_read_ranges() {
         // create a record batch with a null array column
@@ -14,6 +14,8 @@
 
 mod statistics;
 
+pub use statistics::can_collect_statistics;
+
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -60,8 +62,7 @@ pub struct FileWriter {
     batch_id: i32,
     page_table: PageTable,
     metadata: Metadata,
-    // Just for testing purposes.
-    // TODO: replace this with stats collection logic.
+    /// The page-level statistics, written to the footer of the file.
     stats: Option<RecordBatch>,
     stats_collector: Option<statistics::StatisticsCollector>,
 }
@@ -69,6 +70,11 @@ pub struct FileWriter {
 #[derive(Debug, Clone, Default)]
 pub struct FileWriterOptions {
     /// The field ids to collect statistics for.
+    ///
+    /// The minimum, the maximum and the number of nulls of each page, i.e., each batch,
+    /// of these fields are written to the file, see [FileReader::read_page_stats].
+    ///
+    /// [FileReader::read_page_stats]: crate::io::FileReader::read_page_stats
     pub collect_stats_for_fields: Vec<i32>,
 }
 
@@ -174,12 +180,10 @@ impl FileWriter {
 
     pub async fn finish(&mut self) -> Result<usize> {
         // Finish the statistics
-        let _statistics = self
-            .stats_collector
-            .as_mut()
-            .map(|collector| collector.finish());
+        if let Some(stats_collector) = self.stats_collector.as_mut() {
+            self.stats = Some(stats_collector.finish()?);
+        }
 
-        // TODO: write the statistics to the file
         self.write_footer().await?;
         self.object_writer.shutdown().await?;
         let num_rows = self
@@ -516,8 +520,7 @@ impl FileWriter {
     }
 
     // For testing purposes, set statistics
-    // TODO: remove this once we have stats collection logic.
-    #[allow(dead_code)]
+    #[cfg(test)]
     fn set_statistics(&mut self, stats: RecordBatch) {
         self.stats = Some(stats);
     }
@@ -1066,7 +1069,54 @@ mod tests {
 
         file_writer.finish().await.unwrap();
 
-        // TODO: read and test statistics
+        let reader = FileReader::try_new(&store, &path).await.unwrap();
+        assert!(reader.page_stats_schema(&[2, 3]).is_none());
+        let stats_schema = reader.page_stats_schema(&[0, 1]).unwrap();
+        let stats = reader
+            .read_page_stats(&stats_schema)
+            .await
+            .unwrap()
+            .unwrap();
+
+        let stats_field = |name: &str, data_type: DataType| {
+            ArrowField::new(
+                name,
+                DataType::Struct(ArrowFields::from(vec![
+                    ArrowField::new("null_count", DataType::Int64, false),
+                    ArrowField::new("min_value", data_type.clone(), true),
+                    ArrowField::new("max_value", data_type, true),
+                ])),
+                false,
+            )
+        };
+        let stats_array = |null_counts: Vec<i64>, min: Vec<i64>, max: Vec<i64>| {
+            Arc::new(StructArray::from(vec![
+                (
+                    Arc::new(ArrowField::new("null_count", DataType::Int64, false)),
+                    Arc::new(Int64Array::from(null_counts)) as ArrayRef,
+                ),
+                (
+                    Arc::new(ArrowField::new("min_value", DataType::Int64, true)),
+                    Arc::new(Int64Array::from(min)) as ArrayRef,
+                ),
+                (
+                    Arc::new(ArrowField::new("max_value", DataType::Int64, true)),
+                    Arc::new(Int64Array::from(max)) as ArrayRef,
+                ),
+            ])) as ArrayRef
+        };
+        let expected = RecordBatch::try_new(
+            Arc::new(ArrowSchema::new(vec![
+                stats_field("0", DataType::Int64),
+                stats_field("1", DataType::Int64),
+            ])),
+            vec![
+                stats_array(vec![0, 0], vec![1, 5], vec![3, 6]),
+                stats_array(vec![0, 0], vec![4, 10], vec![6, 11]),
+            ],
+        )
+        .unwrap();
+        assert_eq!(stats, expected);
     }
 
     async fn read_file_as_one_batch(object_store: &ObjectStore, path: &Path) -> RecordBatch {
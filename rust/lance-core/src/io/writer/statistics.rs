@@ -561,6 +561,35 @@ fn get_temporal_statistics(arrays: &[&ArrayRef]) -> StatisticsRow {
     }
 }
 
+/// Whether the statistics of a column of `data_type` can be collected.
+pub fn can_collect_statistics(data_type: &DataType) -> bool {
+    matches!(
+        data_type,
+        DataType::Boolean
+            | DataType::Int8
+            | DataType::UInt8
+            | DataType::Int16
+            | DataType::UInt16
+            | DataType::Int32
+            | DataType::UInt32
+            | DataType::Int64
+            | DataType::UInt64
+            | DataType::Float32
+            | DataType::Float64
+            | DataType::Date32
+            | DataType::Date64
+            | DataType::Time32(TimeUnit::Second | TimeUnit::Millisecond)
+            | DataType::Time64(TimeUnit::Microsecond | TimeUnit::Nanosecond)
+            | DataType::Timestamp(_, _)
+            | DataType::Duration(_)
+            | DataType::Decimal128(_, _)
+            | DataType::Binary
+            | DataType::LargeBinary
+            | DataType::Utf8
+            | DataType::LargeUtf8
+    )
+}
+
 pub fn collect_statistics(arrays: &[&ArrayRef]) -> StatisticsRow {
     if arrays.is_empty() {
         panic!("No arrays to collect statistics from");
@@ -630,8 +659,9 @@ impl StatisticsCollector {
             let max_value = Arc::new(builder.max_value.finish());
             let struct_fields = vec![
                 ArrowField::new("null_count", DataType::Int64, false),
-                ArrowField::new("min_value", field.data_type(), field.nullable),
-                ArrowField::new("max_value", field.data_type(), field.nullable),
+                // The bounds of a page without any valid value are null.
+                ArrowField::new("min_value", field.data_type(), true),
+                ArrowField::new("max_value", field.data_type(), true),
             ];
 
             let stats = StructArray::new(
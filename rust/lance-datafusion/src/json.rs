@@ -0,0 +1,213 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Extraction of values from JSON strings, i.e., the `json_extract` function
+
+use std::fmt;
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, Array, ArrayRef, StringArray};
+use arrow_schema::DataType;
+use datafusion_common::{DataFusionError, ScalarValue};
+use datafusion_expr::{
+    ColumnarValue, ReturnTypeFunction, ScalarFunctionImplementation, ScalarUDF, Signature,
+    TypeSignature, Volatility,
+};
+use lance_core::{Error, Result};
+use serde_json::Value;
+use snafu::{location, Location};
+
+/// Name of the function extracting a JSON path from a JSON string column, e.g.,
+/// `json_extract(metadata, '$.type')`.
+pub const JSON_EXTRACT: &str = "json_extract";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum PathStep {
+    Key(String),
+    Index(usize),
+}
+
+/// A path to a value of JSON documents, e.g., `$.user.name`, `$["user"]["name"]` or
+/// `$.tags[0]`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JsonPath {
+    path: String,
+    steps: Vec<PathStep>,
+}
+
+impl JsonPath {
+    pub fn try_new(path: &str) -> Result<Self> {
+        let invalid = |reason: &str| {
+            Error::invalid_input(format!("Invalid JSON path '{path}': {reason}"), location!())
+        };
+        let mut rest = path
+            .strip_prefix('$')
+            .ok_or_else(|| invalid("must start with '$'"))?;
+        let mut steps = Vec::new();
+        while !rest.is_empty() {
+            if let Some(after_dot) = rest.strip_prefix('.') {
+                let end = after_dot.find(['.', '[']).unwrap_or(after_dot.len());
+                if end == 0 {
+                    return Err(invalid("empty key"));
+                }
+                steps.push(PathStep::Key(after_dot[..end].to_string()));
+                rest = &after_dot[end..];
+            } else if let Some(after_bracket) = rest.strip_prefix('[') {
+                let end = after_bracket
+                    .find(']')
+                    .ok_or_else(|| invalid("unclosed '['"))?;
+                let subscript = &after_bracket[..end];
+                let quoted = ['"', '\''].iter().find_map(|quote| {
+                    subscript
+                        .strip_prefix(*quote)
+                        .and_then(|s| s.strip_suffix(*quote))
+                });
+                let step = match quoted {
+                    Some(key) => PathStep::Key(key.to_string()),
+                    None => PathStep::Index(
+                        subscript
+                            .parse()
+                            .map_err(|_| invalid("subscript must be a key or an index"))?,
+                    ),
+                };
+                steps.push(step);
+                rest = &after_bracket[end + 1..];
+            } else {
+                return Err(invalid("expected '.' or '['"));
+            }
+        }
+        Ok(Self {
+            path: path.to_string(),
+            steps,
+        })
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.path
+    }
+
+    /// The value at this path of the `json` document.
+    ///
+    /// Strings are returned without quotes, and the other values as JSON. Returns `None`
+    /// if the document is not valid JSON, or the value is missing or null.
+    pub fn extract(&self, json: &str) -> Option<String> {
+        let document = serde_json::from_str::<Value>(json).ok()?;
+        let value = self
+            .steps
+            .iter()
+            .try_fold(&document, |value, step| match step {
+                PathStep::Key(key) => value.get(key),
+                PathStep::Index(idx) => value.get(idx),
+            })?;
+        match value {
+            Value::Null => None,
+            Value::String(value) => Some(value.clone()),
+            value => Some(value.to_string()),
+        }
+    }
+
+    /// The values at this path of the JSON documents of a string array.
+    pub fn extract_array(&self, documents: &dyn Array) -> Result<StringArray> {
+        match documents.data_type() {
+            DataType::Utf8 => Ok(documents
+                .as_string::<i32>()
+                .iter()
+                .map(|json| json.and_then(|json| self.extract(json)))
+                .collect()),
+            DataType::LargeUtf8 => Ok(documents
+                .as_string::<i64>()
+                .iter()
+                .map(|json| json.and_then(|json| self.extract(json)))
+                .collect()),
+            data_type => Err(Error::invalid_input(
+                format!("{JSON_EXTRACT} requires a string column, got {data_type}"),
+                location!(),
+            )),
+        }
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path)
+    }
+}
+
+fn json_extract(args: &[ColumnarValue]) -> datafusion_common::Result<ColumnarValue> {
+    let [documents, ColumnarValue::Scalar(ScalarValue::Utf8(Some(path)))] = args else {
+        return Err(DataFusionError::Execution(format!(
+            "{JSON_EXTRACT} requires a string column and a JSON path literal"
+        )));
+    };
+    let path = JsonPath::try_new(path)?;
+    match documents {
+        ColumnarValue::Array(documents) => Ok(ColumnarValue::Array(Arc::new(
+            path.extract_array(documents.as_ref())?,
+        ) as ArrayRef)),
+        ColumnarValue::Scalar(ScalarValue::Utf8(json) | ScalarValue::LargeUtf8(json)) => {
+            Ok(ColumnarValue::Scalar(ScalarValue::Utf8(
+                json.as_ref().and_then(|json| path.extract(json)),
+            )))
+        }
+        ColumnarValue::Scalar(value) => Err(DataFusionError::Execution(format!(
+            "{JSON_EXTRACT} requires a string column, got {}",
+            value.data_type()
+        ))),
+    }
+}
+
+/// The `json_extract(json, path)` function, which returns the value at the `path` of
+/// each `json` string, see [JsonPath::extract].
+pub fn json_extract_udf() -> ScalarUDF {
+    let signature = Signature::one_of(
+        vec![
+            TypeSignature::Exact(vec![DataType::Utf8, DataType::Utf8]),
+            TypeSignature::Exact(vec![DataType::LargeUtf8, DataType::Utf8]),
+        ],
+        Volatility::Immutable,
+    );
+    let return_type: ReturnTypeFunction = Arc::new(|_| Ok(Arc::new(DataType::Utf8)));
+    let fun: ScalarFunctionImplementation = Arc::new(json_extract);
+    ScalarUDF::new(JSON_EXTRACT, &signature, &return_type, &fun)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_path() {
+        let json =
+            r#"{"type": "a", "user": {"name": "x", "age": 3}, "tags": ["t1", "t2"], "n": null}"#;
+        let cases = [
+            ("$.type", Some("a")),
+            ("$.user.name", Some("x")),
+            ("$[\"user\"]['age']", Some("3")),
+            ("$.tags[1]", Some("t2")),
+            ("$.tags", Some(r#"["t1","t2"]"#)),
+            ("$.tags[2]", None),
+            ("$.n", None),
+            ("$.missing", None),
+        ];
+        for (path, expected) in cases {
+            let path = JsonPath::try_new(path).unwrap();
+            assert_eq!(path.extract(json).as_deref(), expected, "path: {path}");
+        }
+        assert_eq!(JsonPath::try_new("$.type").unwrap().extract("{"), None);
+
+        for path in ["type", "$.", "$[0", "$[x]", "$x"] {
+            assert!(JsonPath::try_new(path).is_err(), "path: {path}");
+        }
+    }
+}
@@ -1,3 +1,4 @@
 pub mod chunker;
 pub mod exec;
 pub mod expr;
+pub mod json;
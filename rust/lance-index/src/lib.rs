@@ -22,6 +22,7 @@ use async_trait::async_trait;
 use lance_core::Result;
 use roaring::RoaringBitmap;
 
+pub mod progress;
 pub mod scalar;
 pub mod vector;
 
@@ -43,6 +44,9 @@ pub trait Index: Send + Sync {
     fn statistics(&self) -> Result<String>;
     /// Get the type of the index
     fn index_type(&self) -> IndexType;
+    /// Approximate number of bytes the index holds in memory, used to bound the
+    /// size of the index cache.
+    fn memory_size(&self) -> usize;
     /// Read through the index and determine which fragment ids are covered by the index
     ///
     /// This is a kind of slow operation.  It's better to use the fragment_bitmap.  This
@@ -51,9 +55,12 @@ pub trait Index: Send + Sync {
 }
 
 /// Index Type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IndexType {
     // Preserve 0-100 for simple indices.
     Scalar = 0,
+    /// Inverted index for full text search.
+    Inverted = 1,
     // 100+ and up for vector index.
     /// Flat vector index.
     Vector = 100,
@@ -63,6 +70,7 @@ impl std::fmt::Display for IndexType {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         match self {
             Self::Scalar => write!(f, "Scalar"),
+            Self::Inverted => write!(f, "Inverted"),
             Self::Vector => write!(f, "Vector"),
         }
     }
@@ -0,0 +1,56 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Progress of building an index.
+
+/// Progress of building an IVF index.
+///
+/// The shuffler reports the rows assigned to partitions and the buffers spilled
+/// to disk, then each partition is reported once it is written to the index file.
+///
+/// This might be called concurrently by the shuffler. Therefore, the methods require
+/// non-exclusive access to `self`.
+///
+/// This is an experimental API and may change in the future.
+pub trait IndexBuildProgress: std::fmt::Debug + Sync + Send {
+    /// `num_rows` more rows were assigned to their partitions.
+    fn rows_shuffled(&self, num_rows: usize);
+
+    /// `num_bytes` of buffered rows of `partition_id` were spilled to disk.
+    fn partition_flushed(&self, partition_id: u32, num_bytes: usize);
+
+    /// `partition_id`, with `num_rows` rows, was written to the index file.
+    fn partition_indexed(&self, partition_id: u32, num_rows: usize);
+}
+
+/// By default, Progress tracker is Noop.
+#[derive(Debug, Clone, Default)]
+pub struct NoopIndexBuildProgress {}
+
+impl NoopIndexBuildProgress {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl IndexBuildProgress for NoopIndexBuildProgress {
+    #[inline]
+    fn rows_shuffled(&self, _num_rows: usize) {}
+
+    #[inline]
+    fn partition_flushed(&self, _partition_id: u32, _num_bytes: usize) {}
+
+    #[inline]
+    fn partition_indexed(&self, _partition_id: u32, _num_rows: usize) {}
+}
@@ -27,10 +27,16 @@ use nohash_hasher::IntMap;
 
 use crate::Index;
 
+pub mod bitmap;
+pub mod bloomfilter;
 pub mod btree;
 pub mod expression;
 pub mod flat;
+pub mod inverted;
+pub mod json;
+pub mod label_list;
 pub mod lance_format;
+pub mod ngram;
 
 /// Trait for storing an index (or parts of an index) into storage
 #[async_trait]
@@ -93,6 +99,26 @@ pub enum ScalarQuery {
     Equals(ScalarValue),
     /// Retrieve all row ids where the value is null
     IsNull(),
+    /// Retrieve the row ids where the string value may contain all the given substrings
+    ///
+    /// The results can include rows which do not contain the substrings, so they must
+    /// be filtered afterwards.
+    Contains(Vec<String>),
+    /// Retrieve all row ids where the list value contains any of the given labels
+    HasAnyLabel(Vec<ScalarValue>),
+    /// Retrieve all row ids where the list value contains all of the given labels
+    HasAllLabels(Vec<ScalarValue>),
+    /// Retrieve all row ids where the value at the given JSON path, e.g., `$.type`, of
+    /// the JSON string value satisfies the query
+    JsonPath(String, Box<Self>),
+}
+
+fn fmt_values(values: &[ScalarValue]) -> String {
+    values
+        .iter()
+        .map(|val| val.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
 }
 
 impl ScalarQuery {
@@ -118,21 +144,33 @@ impl ScalarQuery {
                 }
             },
             Self::IsIn(values) => {
+                format!("{} IN [{}]", col, fmt_values(values))
+            }
+            Self::IsNull() => {
+                format!("{} IS NULL", col)
+            }
+            Self::Equals(val) => {
+                format!("{} = {}", col, val)
+            }
+            Self::Contains(substrings) => {
                 format!(
-                    "{} IN [{}]",
+                    "contains({}, [{}])",
                     col,
-                    values
+                    substrings
                         .iter()
-                        .map(|val| val.to_string())
+                        .map(|substring| format!("'{}'", substring))
                         .collect::<Vec<_>>()
                         .join(",")
                 )
             }
-            Self::IsNull() => {
-                format!("{} IS NULL", col)
+            Self::HasAnyLabel(labels) => {
+                format!("array_has_any({}, [{}])", col, fmt_values(labels))
             }
-            Self::Equals(val) => {
-                format!("{} = {}", col, val)
+            Self::HasAllLabels(labels) => {
+                format!("array_has_all({}, [{}])", col, fmt_values(labels))
+            }
+            Self::JsonPath(path, query) => {
+                query.fmt_with_col(&format!("json_extract({}, '{}')", col, path))
             }
         }
     }
@@ -164,4 +202,12 @@ pub trait ScalarIndex: Send + Sync + std::fmt::Debug + Index {
         new_data: SendableRecordBatchStream,
         dest_store: &dyn IndexStore,
     ) -> Result<()>;
+
+    /// Estimate the fraction of the indexed rows which satisfy the query, from the
+    /// statistics kept in memory, without searching the index
+    ///
+    /// Returns None if the index cannot tell, e.g., for the queries it does not support
+    fn estimate_selectivity(&self, _query: &ScalarQuery) -> Option<f64> {
+        None
+    }
 }
@@ -0,0 +1,338 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, collections::BTreeMap, ops::Bound, sync::Arc};
+
+use arrow_array::{cast::AsArray, BinaryArray, BooleanArray, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use datafusion_common::ScalarValue;
+use futures::TryStreamExt;
+use lance_core::{Error, Result};
+use nohash_hasher::IntMap;
+use roaring::{RoaringBitmap, RoaringTreemap};
+use serde::Serialize;
+use snafu::{location, Location};
+
+use crate::{Index, IndexType};
+
+use super::{btree::OrderableScalarValue, IndexStore, ScalarIndex, ScalarQuery};
+
+/// Name of the file of a bitmap index, which is also used to tell a bitmap index
+/// from a btree index.
+pub const BITMAP_LOOKUP_NAME: &str = "bitmap_page_lookup.lance";
+
+/// A bitmap index keeps a bitmap of the row ids of each distinct value, including
+/// null, of the column.
+///
+/// All the bitmaps are kept in memory, so it suits columns with few distinct values,
+/// e.g., a category, a label or a status. Queries are answered by combining the
+/// bitmaps of the matching values, without reading any data.
+#[derive(Clone, Debug)]
+pub struct BitmapIndex {
+    /// The bitmap of each value, null first.
+    pub(super) bitmaps: BTreeMap<OrderableScalarValue, RoaringTreemap>,
+    pub(super) value_type: DataType,
+}
+
+impl BitmapIndex {
+    pub(super) fn try_from_serialized(data: RecordBatch) -> Result<Self> {
+        let value_type = data.schema().field(0).data_type().clone();
+        let keys = data.column(0);
+        let is_null = data.column(1).as_boolean();
+        let serialized = data.column(2).as_binary::<i32>();
+        let mut bitmaps = BTreeMap::new();
+        for idx in 0..data.num_rows() {
+            let key = if is_null.value(idx) {
+                ScalarValue::try_from(&value_type)?
+            } else {
+                ScalarValue::try_from_array(keys, idx)?
+            };
+            let bitmap = RoaringTreemap::deserialize_from(serialized.value(idx))?;
+            bitmaps.insert(OrderableScalarValue(key), bitmap);
+        }
+        Ok(Self {
+            bitmaps,
+            value_type,
+        })
+    }
+
+    /// Add the values and row ids of `batch` to `bitmaps`.
+    pub(super) fn add_batch(
+        bitmaps: &mut BTreeMap<OrderableScalarValue, RoaringTreemap>,
+        batch: &RecordBatch,
+    ) -> Result<()> {
+        let values = batch.column(0);
+        let row_ids = batch
+            .column(1)
+            .as_primitive::<arrow_array::types::UInt64Type>();
+        for (idx, row_id) in row_ids.values().iter().enumerate() {
+            let key = ScalarValue::try_from_array(values, idx)?;
+            bitmaps
+                .entry(OrderableScalarValue(key))
+                .or_default()
+                .insert(*row_id);
+        }
+        Ok(())
+    }
+
+    /// Write the bitmaps to the file `file_name` of `dest_store`.
+    pub(super) async fn write(
+        bitmaps: &BTreeMap<OrderableScalarValue, RoaringTreemap>,
+        value_type: &DataType,
+        dest_store: &dyn IndexStore,
+        file_name: &str,
+    ) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("keys", value_type.clone(), true),
+            // The lance format does not keep the nulls of all the types.
+            Field::new("is_null", DataType::Boolean, false),
+            Field::new("bitmaps", DataType::Binary, false),
+        ]));
+        let keys = if bitmaps.is_empty() {
+            arrow_array::new_empty_array(value_type)
+        } else {
+            ScalarValue::iter_to_array(bitmaps.keys().map(|key| key.0.clone()))?
+        };
+        let serialized = bitmaps
+            .values()
+            .map(|bitmap| {
+                let mut bytes = Vec::with_capacity(bitmap.serialized_size());
+                bitmap.serialize_into(&mut bytes)?;
+                Ok(bytes)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let is_null = BooleanArray::from_iter(bitmaps.keys().map(|key| Some(key.0.is_null())));
+        let serialized = BinaryArray::from_iter_values(serialized);
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![keys, Arc::new(is_null), Arc::new(serialized)],
+        )?;
+
+        let mut writer = dest_store.new_index_file(file_name, schema).await?;
+        writer.write_record_batch(batch).await?;
+        writer.finish().await
+    }
+
+    /// Load the bitmaps from the file `file_name` of `store`.
+    pub(super) async fn load_file(store: &dyn IndexStore, file_name: &str) -> Result<Self> {
+        let lookup_file = store.open_index_file(file_name).await?;
+        if lookup_file.num_batches().await != 1 {
+            return Err(Error::Internal {
+                message: "bitmap index must have exactly one batch".into(),
+                location: location!(),
+            });
+        }
+        let serialized = lookup_file.read_record_batch(0).await?;
+        Self::try_from_serialized(serialized)
+    }
+
+    /// The bitmaps with the row ids remapped, without the emptied bitmaps.
+    pub(super) fn remapped_bitmaps(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+    ) -> BTreeMap<OrderableScalarValue, RoaringTreemap> {
+        self.bitmaps
+            .iter()
+            .map(|(key, bitmap)| {
+                let remapped = bitmap
+                    .iter()
+                    .filter_map(|row_id| mapping.get(&row_id).copied().unwrap_or(Some(row_id)))
+                    .collect::<RoaringTreemap>();
+                (key.clone(), remapped)
+            })
+            .filter(|(_, bitmap)| !bitmap.is_empty())
+            .collect()
+    }
+
+    fn in_range(
+        key: &OrderableScalarValue,
+        lower: &Bound<ScalarValue>,
+        upper: &Bound<ScalarValue>,
+    ) -> bool {
+        let above_lower = match lower {
+            Bound::Unbounded => true,
+            Bound::Included(lower) => *key >= OrderableScalarValue(lower.clone()),
+            Bound::Excluded(lower) => *key > OrderableScalarValue(lower.clone()),
+        };
+        let below_upper = match upper {
+            Bound::Unbounded => true,
+            Bound::Included(upper) => *key <= OrderableScalarValue(upper.clone()),
+            Bound::Excluded(upper) => *key < OrderableScalarValue(upper.clone()),
+        };
+        above_lower && below_upper
+    }
+}
+
+/// Train a bitmap index from a stream of batches, whose first column is the values
+/// and second column is the row ids, and write it to `index_store`.
+pub async fn train_bitmap_index(
+    mut data: SendableRecordBatchStream,
+    index_store: &dyn IndexStore,
+) -> Result<()> {
+    let value_type = data.schema().field(0).data_type().clone();
+    let mut bitmaps = BTreeMap::new();
+    while let Some(batch) = data.try_next().await? {
+        BitmapIndex::add_batch(&mut bitmaps, &batch)?;
+    }
+    BitmapIndex::write(&bitmaps, &value_type, index_store, BITMAP_LOOKUP_NAME).await
+}
+
+#[derive(Serialize)]
+struct BitmapStatistics {
+    num_bitmaps: usize,
+}
+
+#[async_trait]
+impl Index for BitmapIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Scalar
+    }
+
+    fn memory_size(&self) -> usize {
+        self.bitmaps
+            .values()
+            .map(|bitmap| bitmap.serialized_size())
+            .sum()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        serde_json::to_string(&BitmapStatistics {
+            num_bitmaps: self.bitmaps.len(),
+        })
+        .map_err(|err| err.into())
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        // The high 32 bits of a row id are its fragment id.
+        let mut frag_ids = RoaringBitmap::new();
+        for bitmap in self.bitmaps.values() {
+            frag_ids.extend(bitmap.bitmaps().map(|(frag_id, _)| frag_id));
+        }
+        Ok(frag_ids)
+    }
+}
+
+#[async_trait]
+impl ScalarIndex for BitmapIndex {
+    async fn search(&self, query: &ScalarQuery) -> Result<UInt64Array> {
+        let row_ids = match query {
+            ScalarQuery::Equals(value) => self
+                .bitmaps
+                .get(&OrderableScalarValue(value.clone()))
+                .cloned()
+                .unwrap_or_default(),
+            ScalarQuery::IsIn(values) => values
+                .iter()
+                .filter_map(|value| self.bitmaps.get(&OrderableScalarValue(value.clone())))
+                .fold(RoaringTreemap::new(), |acc, bitmap| acc | bitmap),
+            ScalarQuery::IsNull() => self
+                .bitmaps
+                .iter()
+                .filter(|(key, _)| key.0.is_null())
+                .fold(RoaringTreemap::new(), |acc, (_, bitmap)| acc | bitmap),
+            ScalarQuery::Range(lower, upper) => self
+                .bitmaps
+                .iter()
+                .filter(|(key, _)| !key.0.is_null() && Self::in_range(key, lower, upper))
+                .fold(RoaringTreemap::new(), |acc, (_, bitmap)| acc | bitmap),
+            ScalarQuery::Contains(_) => {
+                return Err(Error::Index {
+                    message: "Bitmap index does not support substring queries".to_string(),
+                    location: location!(),
+                })
+            }
+            ScalarQuery::HasAnyLabel(_) | ScalarQuery::HasAllLabels(_) => {
+                return Err(Error::Index {
+                    message: "Bitmap index does not support label queries".to_string(),
+                    location: location!(),
+                })
+            }
+            ScalarQuery::JsonPath(..) => {
+                return Err(Error::Index {
+                    message: "Bitmap index does not support JSON path queries".to_string(),
+                    location: location!(),
+                })
+            }
+        };
+        Ok(UInt64Array::from_iter_values(row_ids))
+    }
+
+    fn estimate_selectivity(&self, query: &ScalarQuery) -> Option<f64> {
+        // Each row is in the bitmap of one value, so the sizes of the bitmaps add up.
+        let num_rows = self.bitmaps.values().map(RoaringTreemap::len).sum::<u64>();
+        if num_rows == 0 {
+            return None;
+        }
+        let num_matches_of = |value: &ScalarValue| {
+            self.bitmaps
+                .get(&OrderableScalarValue(value.clone()))
+                .map_or(0, RoaringTreemap::len)
+        };
+        let num_matches = match query {
+            ScalarQuery::Equals(value) => num_matches_of(value),
+            ScalarQuery::IsIn(values) => values.iter().map(num_matches_of).sum(),
+            ScalarQuery::IsNull() => self
+                .bitmaps
+                .iter()
+                .filter(|(key, _)| key.0.is_null())
+                .map(|(_, bitmap)| bitmap.len())
+                .sum(),
+            ScalarQuery::Range(lower, upper) => self
+                .bitmaps
+                .iter()
+                .filter(|(key, _)| !key.0.is_null() && Self::in_range(key, lower, upper))
+                .map(|(_, bitmap)| bitmap.len())
+                .sum(),
+            _ => return None,
+        };
+        Some((num_matches as f64 / num_rows as f64).min(1.0))
+    }
+
+    async fn load(store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
+        Ok(Arc::new(
+            Self::load_file(store.as_ref(), BITMAP_LOOKUP_NAME).await?,
+        ))
+    }
+
+    async fn remap(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let bitmaps = self.remapped_bitmaps(mapping);
+        Self::write(&bitmaps, &self.value_type, dest_store, BITMAP_LOOKUP_NAME).await
+    }
+
+    async fn update(
+        &self,
+        mut new_data: SendableRecordBatchStream,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let mut bitmaps = self.bitmaps.clone();
+        while let Some(batch) = new_data.try_next().await? {
+            Self::add_batch(&mut bitmaps, &batch)?;
+        }
+        Self::write(&bitmaps, &self.value_type, dest_store, BITMAP_LOOKUP_NAME).await
+    }
+}
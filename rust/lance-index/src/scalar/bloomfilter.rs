@@ -0,0 +1,418 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, collections::BTreeMap, sync::Arc};
+
+use arrow_array::{cast::AsArray, types::UInt64Type, Array, BinaryArray, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use datafusion_common::ScalarValue;
+use futures::TryStreamExt;
+use lance_core::{Error, Result};
+use nohash_hasher::IntMap;
+use roaring::{RoaringBitmap, RoaringTreemap};
+use serde::Serialize;
+use snafu::{location, Location};
+
+use crate::{Index, IndexType};
+
+use super::{IndexStore, ScalarIndex, ScalarQuery};
+
+/// Name of the file of a bloom filter index, which is also used to tell a bloom filter
+/// index from the other scalar indices.
+pub const BLOOM_FILTER_NAME: &str = "bloom_filters.lance";
+
+/// Number of bits of a bloom filter per indexed value, which, with [NUM_HASHES],
+/// gives a false positive rate of about 1%.
+const BITS_PER_VALUE: usize = 10;
+/// Number of bits set in a bloom filter for each value.
+const NUM_HASHES: u64 = 7;
+
+/// Whether a bloom filter index can be built on a column of `data_type`.
+///
+/// Only the types whose equality is the equality of their bytes are supported, so
+/// floats, which have two zeros, are not.
+pub fn supports_bloom_filter(data_type: &DataType) -> bool {
+    data_type.is_integer()
+        || matches!(
+            data_type,
+            DataType::Date32
+                | DataType::Date64
+                | DataType::Timestamp(_, _)
+                | DataType::Utf8
+                | DataType::LargeUtf8
+                | DataType::Binary
+                | DataType::LargeBinary
+                | DataType::FixedSizeBinary(_)
+        )
+}
+
+/// 64-bit FNV-1a, which, unlike the hashers of the standard library, is stable across
+/// releases, so the filters can be persisted.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The hashes of the values of `array`, None for nulls.
+fn hash_values(array: &dyn Array) -> Result<Vec<Option<u64>>> {
+    let hash = |bytes: Option<&[u8]>| bytes.map(fnv1a);
+    let hashes = match array.data_type() {
+        DataType::Utf8 => array
+            .as_string::<i32>()
+            .iter()
+            .map(|v| hash(v.map(str::as_bytes)))
+            .collect(),
+        DataType::LargeUtf8 => array
+            .as_string::<i64>()
+            .iter()
+            .map(|v| hash(v.map(str::as_bytes)))
+            .collect(),
+        DataType::Binary => array.as_binary::<i32>().iter().map(hash).collect(),
+        DataType::LargeBinary => array.as_binary::<i64>().iter().map(hash).collect(),
+        DataType::FixedSizeBinary(_) => array.as_fixed_size_binary().iter().map(hash).collect(),
+        data_type if supports_bloom_filter(data_type) => {
+            // The remaining types are fixed width, hash the bytes of each value.
+            let width = data_type.primitive_width().unwrap();
+            let data = array.to_data();
+            let values = &data.buffers()[0].as_slice()[data.offset() * width..];
+            (0..array.len())
+                .map(|idx| {
+                    hash(
+                        array
+                            .is_valid(idx)
+                            .then(|| &values[idx * width..(idx + 1) * width]),
+                    )
+                })
+                .collect()
+        }
+        data_type => {
+            return Err(Error::Index {
+                message: format!("Bloom filter index does not support {data_type}"),
+                location: location!(),
+            })
+        }
+    };
+    Ok(hashes)
+}
+
+/// The bit positions of a value with hash `hash`, in a filter of `num_bits` bits.
+///
+/// The positions are derived from two halves of the hash, i.e., double hashing.
+fn bit_positions(hash: u64, num_bits: usize) -> impl Iterator<Item = usize> {
+    let h1 = hash & 0xffffffff;
+    let h2 = (hash >> 32) | 1;
+    (0..NUM_HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % num_bits as u64) as usize)
+}
+
+/// A bloom filter of the values of a block of rows.
+#[derive(Clone, Debug, PartialEq)]
+struct BloomFilter {
+    bits: Vec<u8>,
+}
+
+impl BloomFilter {
+    fn with_capacity(num_values: usize) -> Self {
+        let num_bytes = ((num_values * BITS_PER_VALUE + 7) / 8).max(1);
+        Self {
+            bits: vec![0; num_bytes],
+        }
+    }
+
+    fn num_bits(&self) -> usize {
+        self.bits.len() * 8
+    }
+
+    fn insert(&mut self, hash: u64) {
+        for pos in bit_positions(hash, self.num_bits()) {
+            self.bits[pos / 8] |= 1 << (pos % 8);
+        }
+    }
+
+    /// Whether the value of `hash` may have been inserted, false means it has not.
+    fn may_contain(&self, hash: u64) -> bool {
+        bit_positions(hash, self.num_bits()).all(|pos| self.bits[pos / 8] & (1 << (pos % 8)) != 0)
+    }
+}
+
+/// The rows of a fragment, when the index was built, and the bloom filter of their values.
+#[derive(Clone, Debug)]
+struct Block {
+    row_ids: RoaringTreemap,
+    filter: BloomFilter,
+}
+
+/// A bloom filter index keeps a bloom filter of the values of each fragment.
+///
+/// It answers equality and set membership queries, e.g., `user_id = 'abc'`, with the
+/// rows of the fragments which may contain the values, so that the point lookups on
+/// a high cardinality column skip most fragments.  The results are candidates, to be
+/// filtered afterwards.
+#[derive(Clone, Debug)]
+pub struct BloomFilterIndex {
+    blocks: Vec<Block>,
+}
+
+impl BloomFilterIndex {
+    fn try_from_serialized(data: RecordBatch) -> Result<Self> {
+        let row_ids = data.column(0).as_binary::<i32>();
+        let filters = data.column(1).as_binary::<i32>();
+        let blocks = (0..data.num_rows())
+            .map(|idx| {
+                Ok(Block {
+                    row_ids: RoaringTreemap::deserialize_from(row_ids.value(idx))?,
+                    filter: BloomFilter {
+                        bits: filters.value(idx).to_vec(),
+                    },
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { blocks })
+    }
+
+    /// Build a block for each fragment of the values and row ids of `data`.
+    async fn build_blocks(mut data: SendableRecordBatchStream) -> Result<Vec<Block>> {
+        // The row ids and the hashes of the non-null values of each fragment.
+        let mut fragments = BTreeMap::<u32, (RoaringTreemap, Vec<u64>)>::new();
+        while let Some(batch) = data.try_next().await? {
+            let hashes = hash_values(batch.column(0))?;
+            let row_ids = batch.column(1).as_primitive::<UInt64Type>();
+            for (hash, row_id) in hashes.into_iter().zip(row_ids.values().iter()) {
+                // The high 32 bits of a row id are its fragment id.
+                let (fragment_rows, fragment_hashes) =
+                    fragments.entry((row_id >> 32) as u32).or_default();
+                fragment_rows.insert(*row_id);
+                fragment_hashes.extend(hash);
+            }
+        }
+        Ok(fragments
+            .into_values()
+            .map(|(row_ids, hashes)| {
+                let mut filter = BloomFilter::with_capacity(hashes.len());
+                hashes.into_iter().for_each(|hash| filter.insert(hash));
+                Block { row_ids, filter }
+            })
+            .collect())
+    }
+
+    async fn write(blocks: &[Block], dest_store: &dyn IndexStore) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("row_ids", DataType::Binary, false),
+            Field::new("filters", DataType::Binary, false),
+        ]));
+        let row_ids = blocks
+            .iter()
+            .map(|block| {
+                let mut bytes = Vec::with_capacity(block.row_ids.serialized_size());
+                block.row_ids.serialize_into(&mut bytes)?;
+                Ok(bytes)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let filters = blocks.iter().map(|block| block.filter.bits.as_slice());
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(BinaryArray::from_iter_values(row_ids)),
+                Arc::new(BinaryArray::from_iter_values(filters)),
+            ],
+        )?;
+
+        let mut writer = dest_store.new_index_file(BLOOM_FILTER_NAME, schema).await?;
+        writer.write_record_batch(batch).await?;
+        writer.finish().await
+    }
+
+    /// The rows of the blocks which may contain any of `values`.
+    fn candidates(&self, values: &[ScalarValue]) -> Result<RoaringTreemap> {
+        if values.is_empty() {
+            return Ok(RoaringTreemap::new());
+        }
+        let array = ScalarValue::iter_to_array(values.iter().cloned())?;
+        let hashes = hash_values(array.as_ref())?
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>();
+        Ok(self
+            .blocks
+            .iter()
+            .filter(|block| hashes.iter().any(|hash| block.filter.may_contain(*hash)))
+            .fold(RoaringTreemap::new(), |acc, block| acc | &block.row_ids))
+    }
+}
+
+/// Train a bloom filter index from a stream of batches, whose first column is the
+/// values and second column is the row ids, and write it to `index_store`.
+pub async fn train_bloom_filter_index(
+    data: SendableRecordBatchStream,
+    index_store: &dyn IndexStore,
+) -> Result<()> {
+    let blocks = BloomFilterIndex::build_blocks(data).await?;
+    BloomFilterIndex::write(&blocks, index_store).await
+}
+
+#[derive(Serialize)]
+struct BloomFilterStatistics {
+    num_blocks: usize,
+    num_bytes: usize,
+}
+
+#[async_trait]
+impl Index for BloomFilterIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Scalar
+    }
+
+    fn memory_size(&self) -> usize {
+        self.blocks
+            .iter()
+            .map(|block| block.row_ids.serialized_size() + block.filter.bits.len())
+            .sum()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        serde_json::to_string(&BloomFilterStatistics {
+            num_blocks: self.blocks.len(),
+            num_bytes: self
+                .blocks
+                .iter()
+                .map(|block| block.filter.bits.len())
+                .sum(),
+        })
+        .map_err(|err| err.into())
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        // The high 32 bits of a row id are its fragment id.
+        let mut frag_ids = RoaringBitmap::new();
+        for block in &self.blocks {
+            frag_ids.extend(block.row_ids.bitmaps().map(|(frag_id, _)| frag_id));
+        }
+        Ok(frag_ids)
+    }
+}
+
+#[async_trait]
+impl ScalarIndex for BloomFilterIndex {
+    async fn search(&self, query: &ScalarQuery) -> Result<UInt64Array> {
+        let row_ids = match query {
+            ScalarQuery::Equals(value) => self.candidates(std::slice::from_ref(value))?,
+            ScalarQuery::IsIn(values) => self.candidates(values)?,
+            _ => {
+                return Err(Error::Index {
+                    message: format!(
+                        "Bloom filter index only supports equality queries, got {}",
+                        query.fmt_with_col("column")
+                    ),
+                    location: location!(),
+                })
+            }
+        };
+        Ok(UInt64Array::from_iter_values(row_ids))
+    }
+
+    async fn load(store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
+        let filters_file = store.open_index_file(BLOOM_FILTER_NAME).await?;
+        if filters_file.num_batches().await != 1 {
+            return Err(Error::Internal {
+                message: "bloom filter index must have exactly one batch".into(),
+                location: location!(),
+            });
+        }
+        let serialized = filters_file.read_record_batch(0).await?;
+        Ok(Arc::new(Self::try_from_serialized(serialized)?))
+    }
+
+    async fn remap(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        // The values are not kept, so a block keeps its filter, which still covers the
+        // values of its rows wherever they are moved.
+        let blocks = self
+            .blocks
+            .iter()
+            .map(|block| Block {
+                row_ids: block
+                    .row_ids
+                    .iter()
+                    .filter_map(|row_id| mapping.get(&row_id).copied().unwrap_or(Some(row_id)))
+                    .collect(),
+                filter: block.filter.clone(),
+            })
+            .filter(|block| !block.row_ids.is_empty())
+            .collect::<Vec<_>>();
+        Self::write(&blocks, dest_store).await
+    }
+
+    async fn update(
+        &self,
+        new_data: SendableRecordBatchStream,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let mut blocks = self.blocks.clone();
+        blocks.extend(Self::build_blocks(new_data).await?);
+        Self::write(&blocks, dest_store).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::{Int64Array, StringArray};
+
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let values = StringArray::from_iter_values((0..1000).map(|i| format!("user-{i}")));
+        let mut filter = BloomFilter::with_capacity(values.len());
+        for hash in hash_values(&values).unwrap().into_iter().flatten() {
+            filter.insert(hash);
+        }
+        // No false negatives.
+        for hash in hash_values(&values).unwrap().into_iter().flatten() {
+            assert!(filter.may_contain(hash));
+        }
+        // Few false positives.
+        let others = StringArray::from_iter_values((1000..2000).map(|i| format!("user-{i}")));
+        let false_positives = hash_values(&others)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .filter(|hash| filter.may_contain(*hash))
+            .count();
+        assert!(false_positives < 50, "{false_positives}");
+    }
+
+    #[test]
+    fn test_hash_values() {
+        let array = Int64Array::from(vec![Some(1), None, Some(1)]);
+        let hashes = hash_values(&array.slice(1, 2)).unwrap();
+        assert_eq!(hashes[0], None);
+        assert_eq!(
+            hashes[1],
+            hash_values(&ScalarValue::Int64(Some(1)).to_array()).unwrap()[0]
+        );
+    }
+}
@@ -60,7 +60,7 @@ const BTREE_PAGES_NAME: &str = "page_data.lance";
 
 /// Wraps a ScalarValue and implements Ord (ScalarValue only implements PartialOrd)
 #[derive(Clone, Debug)]
-struct OrderableScalarValue(ScalarValue);
+pub(crate) struct OrderableScalarValue(pub(crate) ScalarValue);
 
 impl Display for OrderableScalarValue {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -794,6 +794,18 @@ impl Index for BTreeIndex {
         IndexType::Scalar
     }
 
+    fn memory_size(&self) -> usize {
+        // Pages are loaded on demand, only the page lookup stays in memory.
+        let num_pages = self
+            .page_lookup
+            .tree
+            .values()
+            .map(|p| p.len())
+            .sum::<usize>();
+        num_pages * std::mem::size_of::<PageRecord>()
+            + self.page_lookup.null_pages.len() * std::mem::size_of::<u32>()
+    }
+
     fn statistics(&self) -> Result<String> {
         let min = self
             .page_lookup
@@ -841,6 +853,24 @@ impl ScalarIndex for BTreeIndex {
                 .page_lookup
                 .pages_in(values.iter().map(|val| OrderableScalarValue(val.clone()))),
             ScalarQuery::IsNull() => self.page_lookup.pages_null(),
+            ScalarQuery::Contains(_) => {
+                return Err(Error::Index {
+                    message: "BTree index does not support substring queries".to_string(),
+                    location: location!(),
+                })
+            }
+            ScalarQuery::HasAnyLabel(_) | ScalarQuery::HasAllLabels(_) => {
+                return Err(Error::Index {
+                    message: "BTree index does not support label queries".to_string(),
+                    location: location!(),
+                })
+            }
+            ScalarQuery::JsonPath(..) => {
+                return Err(Error::Index {
+                    message: "BTree index does not support JSON path queries".to_string(),
+                    location: location!(),
+                })
+            }
         };
         let sub_index_reader = self.store.open_index_file(BTREE_PAGES_NAME).await?;
         let page_tasks = pages
@@ -865,6 +895,31 @@ impl ScalarIndex for BTreeIndex {
         Ok(UInt64Array::from_iter_values(all_row_ids))
     }
 
+    fn estimate_selectivity(&self, query: &ScalarQuery) -> Option<f64> {
+        // The pages are the only statistics in memory, so a single page tells nothing.
+        let num_pages = self.page_lookup.all_page_ids().len();
+        if num_pages < 2 {
+            return None;
+        }
+        let num_matched_pages = match query {
+            ScalarQuery::Equals(val) => self
+                .page_lookup
+                .pages_eq(&OrderableScalarValue(val.clone()))
+                .len(),
+            ScalarQuery::Range(start, end) => self
+                .page_lookup
+                .pages_between((wrap_bound(start).as_ref(), wrap_bound(end).as_ref()))
+                .len(),
+            ScalarQuery::IsIn(values) => self
+                .page_lookup
+                .pages_in(values.iter().map(|val| OrderableScalarValue(val.clone())))
+                .len(),
+            ScalarQuery::IsNull() => self.page_lookup.pages_null().len(),
+            _ => return None,
+        };
+        Some(num_matched_pages as f64 / num_pages as f64)
+    }
+
     async fn load(store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
         let page_lookup_file = store.open_index_file(BTREE_LOOKUP_NAME).await?;
         let serialized_lookup = page_lookup_file.read_record_batch(0).await?;
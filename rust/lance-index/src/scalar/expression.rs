@@ -18,16 +18,19 @@ use arrow_schema::DataType;
 use async_recursion::async_recursion;
 use async_trait::async_trait;
 use datafusion_common::ScalarValue;
-use datafusion_expr::{expr::InList, Between, BinaryExpr, Expr, Operator};
+use datafusion_expr::{
+    expr::InList, expr::Like, expr::ScalarFunction, Between, BinaryExpr, BuiltinScalarFunction,
+    Expr, GetFieldAccess, GetIndexedField, Operator,
+};
 
 use futures::join;
 use lance_core::{
     utils::mask::{RowIdMask, RowIdTreeMap},
     Result,
 };
-use lance_datafusion::expr::safe_coerce_scalar;
+use lance_datafusion::{expr::safe_coerce_scalar, json::JSON_EXTRACT};
 
-use super::{ScalarIndex, ScalarQuery};
+use super::{ngram::NGRAM_LENGTH, ScalarIndex, ScalarQuery};
 
 /// An indexed expression consists of a scalar index query with a post-scan filter
 ///
@@ -190,8 +193,11 @@ impl IndexedExpression {
 /// This is used during the evaluation of an index expression
 #[async_trait]
 pub trait ScalarIndexLoader: Send + Sync {
-    /// Load the index with the given name
-    async fn load_index(&self, name: &str) -> Result<Arc<dyn ScalarIndex>>;
+    /// Load the deltas of the index with the given name
+    ///
+    /// Each delta indexes different rows, so the results of a query are the union of
+    /// the results of the deltas.
+    async fn load_indices(&self, name: &str) -> Result<Vec<Arc<dyn ScalarIndex>>>;
 }
 
 /// This represents a lookup into one or more scalar indices
@@ -244,9 +250,10 @@ impl ScalarIndexExpr {
                 Ok(lhs_result? | rhs_result?)
             }
             Self::Query(column, query) => {
-                let index = index_loader.load_index(column).await?;
-                let allow_list = index.search(query).await?;
-                let allow_list = RowIdTreeMap::from_iter(allow_list.values().iter());
+                let mut allow_list = RowIdTreeMap::new();
+                for index in index_loader.load_indices(column).await? {
+                    allow_list.extend(index.search(query).await?.values().iter());
+                }
                 Ok(RowIdMask {
                     block_list: None,
                     allow_list: Some(allow_list),
@@ -254,24 +261,113 @@ impl ScalarIndexExpr {
             }
         }
     }
+
+    /// Estimate the fraction of the rows which satisfy the expression, from the
+    /// statistics of the indices
+    ///
+    /// The queries are assumed to be independent.  Returns None if any of the indices
+    /// cannot tell.
+    pub fn estimate_selectivity(&self, index_info: &dyn IndexInformationProvider) -> Option<f64> {
+        match self {
+            Self::Not(inner) => Some(1.0 - inner.estimate_selectivity(index_info)?),
+            Self::And(lhs, rhs) => {
+                Some(lhs.estimate_selectivity(index_info)? * rhs.estimate_selectivity(index_info)?)
+            }
+            Self::Or(lhs, rhs) => {
+                let lhs = lhs.estimate_selectivity(index_info)?;
+                let rhs = rhs.estimate_selectivity(index_info)?;
+                Some(lhs + rhs - lhs * rhs)
+            }
+            Self::Query(column, query) => index_info.estimate_selectivity(column, query),
+        }
+    }
 }
 
-// Extract a column from the expression, if it is a column, or None
-fn maybe_column(expr: &Expr) -> Option<&str> {
+/// The largest estimated fraction of the rows an index query may match to be searched
+///
+/// Searching an index for most of the rows and then taking them is more expensive than
+/// scanning the rows, skipping the fragments by their zone maps, and filtering them.
+pub const MAX_INDEX_SELECTIVITY: f64 = 0.5;
+
+// Extract a column from the expression, if it is a column, or a field of a struct column,
+// e.g., `metadata.source`, or None
+fn maybe_column(expr: &Expr) -> Option<String> {
     match expr {
-        Expr::Column(col) => Some(&col.name),
+        Expr::Column(col) => Some(col.name.clone()),
+        Expr::GetIndexedField(GetIndexedField {
+            expr,
+            field:
+                GetFieldAccess::NamedStructField {
+                    name: ScalarValue::Utf8(Some(name)),
+                },
+        }) => Some(format!("{}.{}", maybe_column(expr)?, name)),
         _ => None,
     }
 }
 
-// Extract a column from the expression, if it is a column, and we have an index for that column, or None
+// The values at a JSON path are compared as strings
+static JSON_VALUE_TYPE: DataType = DataType::Utf8;
+
+// A column with a scalar index, or a JSON path of a column with a JSON index
+struct IndexedColumn<'a> {
+    column: String,
+    json_path: Option<&'a str>,
+}
+
+impl IndexedColumn<'_> {
+    fn index_query(&self, query: ScalarQuery) -> IndexedExpression {
+        let query = match self.json_path {
+            Some(path) => ScalarQuery::JsonPath(path.to_string(), Box::new(query)),
+            None => query,
+        };
+        IndexedExpression::index_query(self.column.clone(), query)
+    }
+}
+
+// Extract a column and a JSON path from the expression, if it is `json_extract` of a column
+// with a JSON index of that path, or None
+fn maybe_json_path<'a>(
+    expr: &'a Expr,
+    index_info: &dyn IndexInformationProvider,
+) -> Option<IndexedColumn<'a>> {
+    let Expr::ScalarUDF(udf) = expr else {
+        return None;
+    };
+    if udf.fun.name != JSON_EXTRACT {
+        return None;
+    }
+    let [json, Expr::Literal(ScalarValue::Utf8(Some(path)))] = udf.args.as_slice() else {
+        return None;
+    };
+    let column = maybe_column(json)?;
+    index_info
+        .has_json_index(&column, path)
+        .then_some(IndexedColumn {
+            column,
+            json_path: Some(path),
+        })
+}
+
+// Extract a column from the expression, if it is a column, and we have an index for that column,
+// or a JSON path of a column with a JSON index, or None
 fn maybe_indexed_column<'a, 'b>(
     expr: &'a Expr,
     index_info: &'b dyn IndexInformationProvider,
-) -> Option<(&'a str, &'b DataType)> {
-    let col = maybe_column(expr)?;
-    let data_type = index_info.get_index(col);
-    data_type.map(|ty| (col, ty))
+) -> Option<(IndexedColumn<'a>, &'b DataType)> {
+    if let Some(json_path) = maybe_json_path(expr, index_info) {
+        return Some((json_path, &JSON_VALUE_TYPE));
+    }
+    let column = maybe_column(expr)?;
+    let data_type = index_info.get_index(&column);
+    data_type.map(|ty| {
+        (
+            IndexedColumn {
+                column,
+                json_path: None,
+            },
+            ty,
+        )
+    })
 }
 
 // Extract a literal scalar value from an expression, if it is a literal, or None
@@ -307,7 +403,7 @@ fn visit_between(
     let high = maybe_scalar(&between.high, col_type)?;
 
     let query = ScalarQuery::Range(Bound::Included(low.clone()), Bound::Included(high.clone()));
-    let indexed_expr = IndexedExpression::index_query(column.to_string(), query);
+    let indexed_expr = column.index_query(query);
     if between.negated {
         indexed_expr.maybe_not()
     } else {
@@ -315,15 +411,45 @@ fn visit_between(
     }
 }
 
+// Extract a column from the expression, if it is a column with a bloom filter index, or None
+fn maybe_bloom_filter_column<'b>(
+    expr: &Expr,
+    index_info: &'b dyn IndexInformationProvider,
+) -> Option<(String, &'b DataType)> {
+    let col = maybe_column(expr)?;
+    let data_type = index_info.get_bloom_filter_index(&col);
+    data_type.map(|ty| (col, ty))
+}
+
+// A point lookup on a column with a bloom filter index is a search for the fragments
+// which may contain the values, whose results are refined by the lookup itself
+fn bloom_filter_lookup(column: &str, query: ScalarQuery, expr: Expr) -> IndexedExpression {
+    IndexedExpression {
+        scalar_query: Some(ScalarIndexExpr::Query(column.to_string(), query)),
+        refine_expr: Some(expr),
+    }
+}
+
 fn visit_in_list(
     in_list: &InList,
     index_info: &dyn IndexInformationProvider,
 ) -> Option<IndexedExpression> {
-    let (column, col_type) = maybe_indexed_column(&in_list.expr, index_info)?;
+    let Some((column, col_type)) = maybe_indexed_column(&in_list.expr, index_info) else {
+        if in_list.negated {
+            return None;
+        }
+        let (column, col_type) = maybe_bloom_filter_column(&in_list.expr, index_info)?;
+        let values = maybe_scalar_list(&in_list.list, col_type)?;
+        return Some(bloom_filter_lookup(
+            &column,
+            ScalarQuery::IsIn(values),
+            Expr::InList(in_list.clone()),
+        ));
+    };
     let values = maybe_scalar_list(&in_list.list, col_type)?;
 
     let query = ScalarQuery::IsIn(values);
-    let indexed_expr = IndexedExpression::index_query(column.to_string(), query);
+    let indexed_expr = column.index_query(query);
     if in_list.negated {
         indexed_expr.maybe_not()
     } else {
@@ -340,10 +466,7 @@ fn visit_is_bool(
     if *col_type != DataType::Boolean {
         None
     } else {
-        Some(IndexedExpression::index_query(
-            column.to_string(),
-            ScalarQuery::Equals(ScalarValue::Boolean(Some(value))),
-        ))
+        Some(column.index_query(ScalarQuery::Equals(ScalarValue::Boolean(Some(value)))))
     }
 }
 
@@ -353,7 +476,7 @@ fn visit_is_null(
     negated: bool,
 ) -> Option<IndexedExpression> {
     let (column, _) = maybe_indexed_column(expr, index_info)?;
-    let indexed_expr = IndexedExpression::index_query(column.to_string(), ScalarQuery::IsNull());
+    let indexed_expr = column.index_query(ScalarQuery::IsNull());
     if negated {
         indexed_expr.maybe_not()
     } else {
@@ -386,17 +509,50 @@ fn visit_comparison(
     let left_col = maybe_indexed_column(&expr.left, index_info);
     if let Some((column, col_type)) = left_col {
         let scalar = maybe_scalar(&expr.right, col_type)?;
-        Some(IndexedExpression::index_query(
-            column.to_string(),
-            visit_comparison_normalized(scalar, &expr.op),
-        ))
+        Some(column.index_query(visit_comparison_normalized(scalar, &expr.op)))
     } else {
         let (column, col_type) = maybe_indexed_column(&expr.right, index_info)?;
         let scalar = maybe_scalar(&expr.left, col_type)?;
-        Some(IndexedExpression::index_query(
-            column.to_string(),
-            visit_comparison_normalized(scalar, &expr.op),
-        ))
+        Some(column.index_query(visit_comparison_normalized(scalar, &expr.op)))
+    }
+}
+
+fn visit_bloom_filter_eq(
+    expr: &BinaryExpr,
+    index_info: &dyn IndexInformationProvider,
+) -> Option<IndexedExpression> {
+    let ((column, col_type), value) = match maybe_bloom_filter_column(&expr.left, index_info) {
+        Some(column) => (column, &expr.right),
+        None => (
+            maybe_bloom_filter_column(&expr.right, index_info)?,
+            &expr.left,
+        ),
+    };
+    let scalar = maybe_scalar(value, col_type)?;
+    Some(bloom_filter_lookup(
+        &column,
+        ScalarQuery::Equals(scalar),
+        Expr::BinaryExpr(expr.clone()),
+    ))
+}
+
+// Whether the index query of `side` is estimated to match too many rows to be searched,
+// when the index query of `other` narrows down the rows more, or as far as we know
+fn is_unselective(
+    side: &Option<IndexedExpression>,
+    other: &Option<IndexedExpression>,
+    index_info: &dyn IndexInformationProvider,
+) -> bool {
+    let selectivity = |side: &Option<IndexedExpression>| {
+        side.as_ref()
+            .and_then(|side| side.scalar_query.as_ref())
+            .map(|query| query.estimate_selectivity(index_info))
+    };
+    match (selectivity(side), selectivity(other)) {
+        (Some(Some(side)), Some(other)) => {
+            side > MAX_INDEX_SELECTIVITY && other.map_or(true, |other| other < side)
+        }
+        _ => false,
     }
 }
 
@@ -404,8 +560,15 @@ fn visit_and(
     expr: &BinaryExpr,
     index_info: &dyn IndexInformationProvider,
 ) -> Option<IndexedExpression> {
-    let left = visit_node(&expr.left, index_info);
-    let right = visit_node(&expr.right, index_info);
+    let mut left = visit_node(&expr.left, index_info);
+    let mut right = visit_node(&expr.right, index_info);
+    // An unselective side refines the rows found by the index query of the other side
+    // instead of being searched
+    if is_unselective(&left, &right, index_info) {
+        left = None;
+    } else if is_unselective(&right, &left, index_info) {
+        right = None;
+    }
     match (left, right) {
         (Some(left), Some(right)) => Some(left.and(right)),
         (Some(left), None) => Some(left.refine((*expr.right).clone())),
@@ -421,6 +584,21 @@ fn visit_or(
     let left = visit_node(&expr.left, index_info);
     let right = visit_node(&expr.right, index_info);
     match (left, right) {
+        // If both sides are refined by their whole expressions, e.g., point lookups with
+        // bloom filters, the index queries only narrow down the candidates, and so does
+        // the union of them
+        (Some(left), Some(right))
+            if left.refine_expr.as_ref() == Some(expr.left.as_ref())
+                && right.refine_expr.as_ref() == Some(expr.right.as_ref()) =>
+        {
+            Some(IndexedExpression {
+                scalar_query: Some(ScalarIndexExpr::Or(
+                    Box::new(left.scalar_query?),
+                    Box::new(right.scalar_query?),
+                )),
+                refine_expr: Some(Expr::BinaryExpr(expr.clone())),
+            })
+        }
         (Some(left), Some(right)) => left.maybe_or(right),
         // If one side can use an index and the other side cannot then
         // we must abandon the entire thing.  For example, consider the
@@ -438,9 +616,12 @@ fn visit_binary_expr(
     index_info: &dyn IndexInformationProvider,
 ) -> Option<IndexedExpression> {
     match &expr.op {
-        Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq | Operator::Eq => {
+        Operator::Lt | Operator::LtEq | Operator::Gt | Operator::GtEq => {
             visit_comparison(expr, index_info)
         }
+        Operator::Eq => {
+            visit_comparison(expr, index_info).or_else(|| visit_bloom_filter_eq(expr, index_info))
+        }
         // visit_comparison will maybe create an Eq query which we negate
         Operator::NotEq => visit_comparison(expr, index_info).and_then(|node| node.maybe_not()),
         Operator::And => visit_and(expr, index_info),
@@ -449,6 +630,97 @@ fn visit_binary_expr(
     }
 }
 
+// A `LIKE` with a literal pattern on a column with an n-gram index is a search for the
+// substrings between the wildcards, whose results are refined by the `LIKE` itself
+fn visit_like(
+    like: &Like,
+    expr: &Expr,
+    index_info: &dyn IndexInformationProvider,
+) -> Option<IndexedExpression> {
+    if like.negated || like.escape_char.is_some() {
+        return None;
+    }
+    let column = maybe_column(&like.expr)?;
+    index_info.get_ngram_index(&column)?;
+    let (Expr::Literal(ScalarValue::Utf8(Some(pattern)))
+    | Expr::Literal(ScalarValue::LargeUtf8(Some(pattern)))) = like.pattern.as_ref()
+    else {
+        return None;
+    };
+    let substrings = pattern
+        .split(['%', '_'])
+        .filter(|substring| !substring.is_empty())
+        .map(|substring| substring.to_string())
+        .collect::<Vec<_>>();
+    // Substrings shorter than an n-gram would match every row
+    if substrings
+        .iter()
+        .all(|substring| substring.chars().count() < NGRAM_LENGTH)
+    {
+        return None;
+    }
+    Some(IndexedExpression {
+        scalar_query: Some(ScalarIndexExpr::Query(
+            column,
+            ScalarQuery::Contains(substrings),
+        )),
+        refine_expr: Some(expr.clone()),
+    })
+}
+
+// Extract the labels of a list literal, e.g., `['a', 'b']`, or None if the list is
+// empty or has a null label
+fn maybe_label_list(expr: &Expr, label_type: &DataType) -> Option<Vec<ScalarValue>> {
+    let labels = match expr {
+        Expr::Literal(ScalarValue::List(Some(values), _)) => values
+            .iter()
+            .map(|value| safe_coerce_scalar(value, label_type))
+            .collect::<Option<Vec<_>>>()?,
+        Expr::ScalarFunction(ScalarFunction {
+            fun: BuiltinScalarFunction::MakeArray,
+            args,
+        }) => maybe_scalar_list(args, label_type)?,
+        _ => return None,
+    };
+    if labels.is_empty() || labels.iter().any(|label| label.is_null()) {
+        return None;
+    }
+    Some(labels)
+}
+
+// `array_has`, `array_has_any` and `array_has_all` of a column with a label list index
+// and literal labels
+fn visit_scalar_function(
+    func: &ScalarFunction,
+    index_info: &dyn IndexInformationProvider,
+) -> Option<IndexedExpression> {
+    let [list, labels] = func.args.as_slice() else {
+        return None;
+    };
+    let column = maybe_column(list)?;
+    let label_type = match index_info.get_label_list_index(&column)? {
+        DataType::List(item) | DataType::LargeList(item) => item.data_type(),
+        _ => return None,
+    };
+    let query = match func.fun {
+        BuiltinScalarFunction::ArrayHas => {
+            let label = maybe_scalar(labels, label_type)?;
+            if label.is_null() {
+                return None;
+            }
+            ScalarQuery::HasAnyLabel(vec![label])
+        }
+        BuiltinScalarFunction::ArrayHasAny => {
+            ScalarQuery::HasAnyLabel(maybe_label_list(labels, label_type)?)
+        }
+        BuiltinScalarFunction::ArrayHasAll => {
+            ScalarQuery::HasAllLabels(maybe_label_list(labels, label_type)?)
+        }
+        _ => return None,
+    };
+    Some(IndexedExpression::index_query(column, query))
+}
+
 fn visit_node(expr: &Expr, index_info: &dyn IndexInformationProvider) -> Option<IndexedExpression> {
     match expr {
         Expr::Between(between) => visit_between(between, index_info),
@@ -459,6 +731,8 @@ fn visit_node(expr: &Expr, index_info: &dyn IndexInformationProvider) -> Option<
         Expr::IsNotNull(expr) => visit_is_null(expr.as_ref(), index_info, true),
         Expr::Not(expr) => visit_not(expr.as_ref(), index_info),
         Expr::BinaryExpr(binary_expr) => visit_binary_expr(binary_expr, index_info),
+        Expr::Like(like) => visit_like(like, expr, index_info),
+        Expr::ScalarFunction(func) => visit_scalar_function(func, index_info),
         _ => None,
     }
 }
@@ -467,6 +741,44 @@ fn visit_node(expr: &Expr, index_info: &dyn IndexInformationProvider) -> Option<
 pub trait IndexInformationProvider {
     /// Check if an index exists for `col` and, if so, return the data type of col
     fn get_index(&self, col: &str) -> Option<&DataType>;
+
+    /// Check if an n-gram index exists for `col` and, if so, return the data type of col
+    ///
+    /// An n-gram index only answers substring queries, so it is not returned by `get_index`
+    fn get_ngram_index(&self, _col: &str) -> Option<&DataType> {
+        None
+    }
+
+    /// Check if a bloom filter index exists for `col` and, if so, return the data type of col
+    ///
+    /// A bloom filter index only answers point lookups, so it is not returned by `get_index`
+    fn get_bloom_filter_index(&self, _col: &str) -> Option<&DataType> {
+        None
+    }
+
+    /// Check if a label list index exists for `col` and, if so, return the data type of col
+    ///
+    /// A label list index only answers label queries on list columns, so it is not returned
+    /// by `get_index`
+    fn get_label_list_index(&self, _col: &str) -> Option<&DataType> {
+        None
+    }
+
+    /// Check if a JSON index of the JSON `path`, e.g., `$.type`, exists for `col`
+    ///
+    /// A JSON index only answers queries on `json_extract(col, path)`, so it is not returned
+    /// by `get_index`
+    fn has_json_index(&self, _col: &str, _path: &str) -> bool {
+        false
+    }
+
+    /// Estimate the fraction of the rows which satisfy the `query` on `col`, from the
+    /// statistics of its index, see [ScalarIndex::estimate_selectivity]
+    ///
+    /// Returns None if there are no statistics, in which case the index is always searched
+    fn estimate_selectivity(&self, _col: &str, _query: &ScalarQuery) -> Option<f64> {
+        None
+    }
 }
 
 /// Attempt to split a filter expression into a search of scalar indexes and an
@@ -475,7 +787,20 @@ pub fn apply_scalar_indices(
     expr: Expr,
     index_info: &dyn IndexInformationProvider,
 ) -> IndexedExpression {
-    visit_node(&expr, index_info).unwrap_or(IndexedExpression::refine_only(expr))
+    match visit_node(&expr, index_info) {
+        // A scan filtering the rows is cheaper than an index query matching most of them
+        Some(indexed)
+            if indexed.scalar_query.as_ref().is_some_and(|query| {
+                query
+                    .estimate_selectivity(index_info)
+                    .is_some_and(|selectivity| selectivity > MAX_INDEX_SELECTIVITY)
+            }) =>
+        {
+            IndexedExpression::refine_only(expr)
+        }
+        Some(indexed) => indexed,
+        None => IndexedExpression::refine_only(expr),
+    }
 }
 
 #[cfg(test)]
@@ -486,10 +811,12 @@ mod tests {
     use arrow_schema::{DataType, Field, Schema};
     use datafusion_common::{config::ConfigOptions, TableReference};
     use datafusion_common::{Column, DFSchema, ScalarValue};
-    use datafusion_expr::{AggregateUDF, Expr, ScalarUDF, TableSource, WindowUDF};
+    use datafusion_expr::{col, lit, AggregateUDF, Expr, ScalarUDF, TableSource, WindowUDF};
     use datafusion_sql::planner::{ContextProvider, PlannerContext, SqlToRel};
     use datafusion_sql::sqlparser::{dialect::PostgreSqlDialect, parser::Parser};
 
+    use lance_datafusion::json::json_extract_udf;
+
     use crate::scalar::expression::apply_scalar_indices;
     use crate::scalar::ScalarQuery;
 
@@ -497,6 +824,11 @@ mod tests {
 
     struct MockIndexInfoProvider {
         indexed_columns: HashMap<String, DataType>,
+        ngram_columns: HashMap<String, DataType>,
+        bloom_filter_columns: HashMap<String, DataType>,
+        label_list_columns: HashMap<String, DataType>,
+        json_paths: HashMap<String, Vec<String>>,
+        selectivities: HashMap<String, f64>,
     }
 
     impl MockIndexInfoProvider {
@@ -507,14 +839,85 @@ mod tests {
                         .into_iter()
                         .map(|(s, ty)| (s.to_string(), ty)),
                 ),
+                ngram_columns: HashMap::new(),
+                bloom_filter_columns: HashMap::new(),
+                label_list_columns: HashMap::new(),
+                json_paths: HashMap::new(),
+                selectivities: HashMap::new(),
             }
         }
+
+        fn with_ngram_columns(mut self, ngram_columns: Vec<(&str, DataType)>) -> Self {
+            self.ngram_columns = ngram_columns
+                .into_iter()
+                .map(|(s, ty)| (s.to_string(), ty))
+                .collect();
+            self
+        }
+
+        fn with_bloom_filter_columns(
+            mut self,
+            bloom_filter_columns: Vec<(&str, DataType)>,
+        ) -> Self {
+            self.bloom_filter_columns = bloom_filter_columns
+                .into_iter()
+                .map(|(s, ty)| (s.to_string(), ty))
+                .collect();
+            self
+        }
+
+        fn with_label_list_columns(mut self, label_list_columns: Vec<(&str, DataType)>) -> Self {
+            self.label_list_columns = label_list_columns
+                .into_iter()
+                .map(|(s, ty)| (s.to_string(), ty))
+                .collect();
+            self
+        }
+
+        fn with_json_paths(mut self, json_paths: Vec<(&str, Vec<&str>)>) -> Self {
+            self.json_paths = json_paths
+                .into_iter()
+                .map(|(s, paths)| (s.to_string(), paths.into_iter().map(String::from).collect()))
+                .collect();
+            self
+        }
+
+        /// The estimated selectivity of any query on each column
+        fn with_selectivities(mut self, selectivities: Vec<(&str, f64)>) -> Self {
+            self.selectivities = selectivities
+                .into_iter()
+                .map(|(s, selectivity)| (s.to_string(), selectivity))
+                .collect();
+            self
+        }
     }
 
     impl IndexInformationProvider for MockIndexInfoProvider {
         fn get_index(&self, col: &str) -> Option<&DataType> {
             self.indexed_columns.get(col)
         }
+
+        fn get_ngram_index(&self, col: &str) -> Option<&DataType> {
+            self.ngram_columns.get(col)
+        }
+
+        fn get_bloom_filter_index(&self, col: &str) -> Option<&DataType> {
+            self.bloom_filter_columns.get(col)
+        }
+
+        fn get_label_list_index(&self, col: &str) -> Option<&DataType> {
+            self.label_list_columns.get(col)
+        }
+
+        fn has_json_index(&self, col: &str, path: &str) -> bool {
+            self.json_paths
+                .get(col)
+                .is_some_and(|paths| paths.iter().any(|p| p == path))
+        }
+
+        fn estimate_selectivity(&self, col: &str, _query: &ScalarQuery) -> Option<f64> {
+            self.selectivities.get(col).copied()
+        }
     }
 
     struct MockContextProvider {}
@@ -528,8 +931,9 @@ mod tests {
             todo!()
         }
 
-        fn get_function_meta(&self, _: &str) -> Option<std::sync::Arc<ScalarUDF>> {
-            todo!()
+        fn get_function_meta(&self, name: &str) -> Option<std::sync::Arc<ScalarUDF>> {
+            // The built-in functions are looked up after the user-defined ones
+            (name == JSON_EXTRACT).then(|| Arc::new(json_extract_udf()))
         }
 
         fn get_aggregate_meta(&self, _: &str) -> Option<std::sync::Arc<AggregateUDF>> {
@@ -560,6 +964,23 @@ mod tests {
             Field::new("aisle", DataType::UInt32, false),
             Field::new("on_sale", DataType::Boolean, false),
             Field::new("price", DataType::Float32, false),
+            Field::new(
+                "tags",
+                DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+                true,
+            ),
+            Field::new("metadata", DataType::Utf8, true),
+            Field::new(
+                "origin",
+                DataType::Struct(
+                    vec![
+                        Field::new("source", DataType::Utf8, true),
+                        Field::new("version", DataType::UInt32, true),
+                    ]
+                    .into(),
+                ),
+                true,
+            ),
         ]);
         let dialect = PostgreSqlDialect {};
         let mut parser = Parser::new(&dialect).try_with_sql(expr).unwrap();
@@ -791,4 +1212,326 @@ mod tests {
         // Non-normalized arithmetic (can use expression simplification)
         check_no_index(&index_info, "aisle + 3 < 10")
     }
+    #[test]
+    fn test_like_expressions() {
+        let index_info = MockIndexInfoProvider::new(vec![("aisle", DataType::UInt32)])
+            .with_ngram_columns(vec![("color", DataType::Utf8)]);
+
+        let color = Expr::Column(Column::new_unqualified("color"));
+        let check_contains = |expr: &str, refine: Expr, substrings: &[&str]| {
+            check(
+                &index_info,
+                expr,
+                Some(IndexedExpression {
+                    scalar_query: Some(ScalarIndexExpr::Query(
+                        "color".to_string(),
+                        ScalarQuery::Contains(substrings.iter().map(|s| s.to_string()).collect()),
+                    )),
+                    refine_expr: Some(refine),
+                }),
+            );
+        };
+        check_contains(
+            "color LIKE '%blue%'",
+            color.clone().like(datafusion_expr::lit("%blue%")),
+            &["blue"],
+        );
+        check_contains(
+            "color LIKE 'dark%blu_'",
+            color.clone().like(datafusion_expr::lit("dark%blu_")),
+            &["dark", "blu"],
+        );
+        check_contains(
+            "color ILIKE '%Blue%'",
+            color.clone().ilike(datafusion_expr::lit("%Blue%")),
+            &["Blue"],
+        );
+
+        // Too short to have any n-gram
+        check_no_index(&index_info, "color LIKE '%bl%'");
+        check_no_index(&index_info, "color NOT LIKE '%blue%'");
+        // The n-gram index does not answer other queries
+        check_no_index(&index_info, "color = 'blue'");
+        check_no_index(&index_info, "aisle = 10 OR color LIKE '%blue%'");
+        check(
+            &index_info,
+            "aisle = 10 AND color LIKE '%blue%'",
+            Some(IndexedExpression {
+                scalar_query: Some(ScalarIndexExpr::And(
+                    Box::new(ScalarIndexExpr::Query(
+                        "aisle".to_string(),
+                        ScalarQuery::Equals(ScalarValue::UInt32(Some(10))),
+                    )),
+                    Box::new(ScalarIndexExpr::Query(
+                        "color".to_string(),
+                        ScalarQuery::Contains(vec!["blue".to_string()]),
+                    )),
+                )),
+                refine_expr: Some(color.like(datafusion_expr::lit("%blue%"))),
+            }),
+        );
+    }
+    #[test]
+    fn test_bloom_filter_expressions() {
+        let index_info = MockIndexInfoProvider::new(vec![("aisle", DataType::UInt32)])
+            .with_bloom_filter_columns(vec![("color", DataType::Utf8)]);
+
+        let color = Expr::Column(Column::new_unqualified("color"));
+        let blue = ScalarValue::Utf8(Some("blue".to_string()));
+        let red = ScalarValue::Utf8(Some("red".to_string()));
+        check(
+            &index_info,
+            "color = 'blue'",
+            Some(bloom_filter_lookup(
+                "color",
+                ScalarQuery::Equals(blue.clone()),
+                color.clone().eq(datafusion_expr::lit("blue")),
+            )),
+        );
+        check(
+            &index_info,
+            "color IN ('blue', 'red')",
+            Some(bloom_filter_lookup(
+                "color",
+                ScalarQuery::IsIn(vec![blue.clone(), red.clone()]),
+                color.clone().in_list(
+                    vec![datafusion_expr::lit("blue"), datafusion_expr::lit("red")],
+                    false,
+                ),
+            )),
+        );
+        check(
+            &index_info,
+            "color = 'blue' OR color = 'red'",
+            Some(IndexedExpression {
+                scalar_query: Some(ScalarIndexExpr::Or(
+                    Box::new(ScalarIndexExpr::Query(
+                        "color".to_string(),
+                        ScalarQuery::Equals(blue.clone()),
+                    )),
+                    Box::new(ScalarIndexExpr::Query(
+                        "color".to_string(),
+                        ScalarQuery::Equals(red.clone()),
+                    )),
+                )),
+                refine_expr: Some(
+                    color
+                        .clone()
+                        .eq(datafusion_expr::lit("blue"))
+                        .or(color.clone().eq(datafusion_expr::lit("red"))),
+                ),
+            }),
+        );
+        check_no_index(&index_info, "color = 'blue' OR aisle = 10");
+        // A bloom filter only tells which fragments may contain a value
+        check_no_index(&index_info, "color <> 'blue'");
+        check_no_index(&index_info, "color NOT IN ('blue', 'red')");
+        check_no_index(&index_info, "color < 'blue'");
+        check_no_index(&index_info, "color IS NULL");
+    }
+
+    #[test]
+    fn test_label_list_expressions() {
+        let tags_type = DataType::List(Arc::new(Field::new("item", DataType::Utf8, true)));
+        let index_info = MockIndexInfoProvider::new(vec![("aisle", DataType::UInt32)])
+            .with_label_list_columns(vec![("tags", tags_type)]);
+
+        let utf8 = |val: &str| ScalarValue::Utf8(Some(val.to_string()));
+        check_simple(
+            &index_info,
+            "array_has_any(tags, ['sale', 'new'])",
+            "tags",
+            ScalarQuery::HasAnyLabel(vec![utf8("sale"), utf8("new")]),
+        );
+        check_simple(
+            &index_info,
+            "array_has_all(tags, ['sale', 'new'])",
+            "tags",
+            ScalarQuery::HasAllLabels(vec![utf8("sale"), utf8("new")]),
+        );
+        check_simple(
+            &index_info,
+            "array_has(tags, 'sale')",
+            "tags",
+            ScalarQuery::HasAnyLabel(vec![utf8("sale")]),
+        );
+        check_simple_negated(
+            &index_info,
+            "NOT array_has_any(tags, ['sale'])",
+            "tags",
+            ScalarQuery::HasAnyLabel(vec![utf8("sale")]),
+        );
+        check(
+            &index_info,
+            "array_has_all(tags, ['sale']) AND aisle = 10",
+            Some(IndexedExpression {
+                scalar_query: Some(ScalarIndexExpr::And(
+                    Box::new(ScalarIndexExpr::Query(
+                        "tags".to_string(),
+                        ScalarQuery::HasAllLabels(vec![utf8("sale")]),
+                    )),
+                    Box::new(ScalarIndexExpr::Query(
+                        "aisle".to_string(),
+                        ScalarQuery::Equals(ScalarValue::UInt32(Some(10))),
+                    )),
+                )),
+                refine_expr: None,
+            }),
+        );
+        check_no_index(&index_info, "array_has_any(tags, [])");
+        // `color` has no label list index
+        check_no_index(&index_info, "array_has(['sale'], color)");
+    }
+
+    #[test]
+    fn test_json_expressions() {
+        let index_info = MockIndexInfoProvider::new(vec![("aisle", DataType::UInt32)])
+            .with_json_paths(vec![("metadata", vec!["$.type", "$.user.name"])]);
+
+        let utf8 = |val: &str| ScalarValue::Utf8(Some(val.to_string()));
+        let json_query = |path: &str, query: ScalarQuery| {
+            ScalarQuery::JsonPath(path.to_string(), Box::new(query))
+        };
+        check_simple(
+            &index_info,
+            "json_extract(metadata, '$.type') = 'shoe'",
+            "metadata",
+            json_query("$.type", ScalarQuery::Equals(utf8("shoe"))),
+        );
+        check_simple(
+            &index_info,
+            "json_extract(metadata, '$.user.name') > 'x'",
+            "metadata",
+            json_query(
+                "$.user.name",
+                ScalarQuery::Range(Bound::Excluded(utf8("x")), Bound::Unbounded),
+            ),
+        );
+        check_simple(
+            &index_info,
+            "json_extract(metadata, '$.type') IN ('shoe', 'hat')",
+            "metadata",
+            json_query("$.type", ScalarQuery::IsIn(vec![utf8("shoe"), utf8("hat")])),
+        );
+        check_simple(
+            &index_info,
+            "json_extract(metadata, '$.type') IS NULL",
+            "metadata",
+            json_query("$.type", ScalarQuery::IsNull()),
+        );
+        check_simple_negated(
+            &index_info,
+            "json_extract(metadata, '$.type') <> 'shoe'",
+            "metadata",
+            json_query("$.type", ScalarQuery::Equals(utf8("shoe"))),
+        );
+        check(
+            &index_info,
+            "json_extract(metadata, '$.type') = 'shoe' AND aisle = 10",
+            Some(IndexedExpression {
+                scalar_query: Some(ScalarIndexExpr::And(
+                    Box::new(ScalarIndexExpr::Query(
+                        "metadata".to_string(),
+                        json_query("$.type", ScalarQuery::Equals(utf8("shoe"))),
+                    )),
+                    Box::new(ScalarIndexExpr::Query(
+                        "aisle".to_string(),
+                        ScalarQuery::Equals(ScalarValue::UInt32(Some(10))),
+                    )),
+                )),
+                refine_expr: None,
+            }),
+        );
+        // `$.color` is not indexed
+        check_no_index(&index_info, "json_extract(metadata, '$.color') = 'red'");
+        check_no_index(&index_info, "json_extract(color, '$.type') = 'shoe'");
+    }
+
+    #[test]
+    fn test_nested_field_expressions() {
+        let index_info = MockIndexInfoProvider::new(vec![
+            ("origin.source", DataType::Utf8),
+            ("origin.version", DataType::UInt32),
+        ]);
+
+        check_simple(
+            &index_info,
+            "origin.source = 'web'",
+            "origin.source",
+            ScalarQuery::Equals(ScalarValue::Utf8(Some("web".to_string()))),
+        );
+        check_simple(
+            &index_info,
+            "origin.version > 3",
+            "origin.version",
+            ScalarQuery::Range(
+                Bound::Excluded(ScalarValue::UInt32(Some(3))),
+                Bound::Unbounded,
+            ),
+        );
+        check_simple_negated(
+            &index_info,
+            "origin.source IS NOT NULL",
+            "origin.source",
+            ScalarQuery::IsNull(),
+        );
+        // The struct column itself is not indexed
+        check_no_index(&index_info, "origin IS NULL");
+    }
+
+    #[test]
+    fn test_selectivity_expressions() {
+        let index_info = MockIndexInfoProvider::new(vec![
+            ("color", DataType::Utf8),
+            ("aisle", DataType::UInt32),
+            ("on_sale", DataType::Boolean),
+        ])
+        .with_selectivities(vec![("color", 0.9), ("aisle", 0.1)]);
+
+        // An index query matching most of the rows is not searched
+        check_no_index(&index_info, "color = 'blue'");
+        check_no_index(&index_info, "color = 'blue' AND price > 10");
+        check_no_index(&index_info, "color = 'blue' OR aisle = 5");
+        check_simple_negated(
+            &index_info,
+            "color != 'blue'",
+            "color",
+            ScalarQuery::Equals(ScalarValue::Utf8(Some("blue".to_string()))),
+        );
+        // Without statistics the index is always searched
+        check_simple(
+            &index_info,
+            "on_sale = true",
+            "on_sale",
+            ScalarQuery::Equals(ScalarValue::Boolean(Some(true))),
+        );
+
+        // The unselective side refines the results of the other side
+        let aisle_query = IndexedExpression::index_query(
+            "aisle".to_string(),
+            ScalarQuery::Equals(ScalarValue::UInt32(Some(5))),
+        );
+        check(
+            &index_info,
+            "color = 'blue' AND aisle = 5",
+            Some(aisle_query.refine(col("color").eq(lit("blue")))),
+        );
+        check(
+            &index_info,
+            "aisle = 5 AND color = 'blue' AND on_sale = true",
+            Some(IndexedExpression {
+                scalar_query: Some(ScalarIndexExpr::And(
+                    Box::new(ScalarIndexExpr::Query(
+                        "aisle".to_string(),
+                        ScalarQuery::Equals(ScalarValue::UInt32(Some(5))),
+                    )),
+                    Box::new(ScalarIndexExpr::Query(
+                        "on_sale".to_string(),
+                        ScalarQuery::Equals(ScalarValue::Boolean(Some(true))),
+                    )),
+                )),
+                refine_expr: Some(col("color").eq(lit("blue"))),
+            }),
+        );
+    }
 }
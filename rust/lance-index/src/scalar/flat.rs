@@ -22,10 +22,11 @@ use async_trait::async_trait;
 
 use datafusion::physical_plan::SendableRecordBatchStream;
 use datafusion_physical_expr::expressions::{in_list, lit, Column};
-use lance_core::{format::RowAddress, Result};
+use lance_core::{format::RowAddress, Error, Result};
 use nohash_hasher::IntMap;
 use roaring::RoaringBitmap;
 use serde::Serialize;
+use snafu::{location, Location};
 
 use crate::{Index, IndexType};
 
@@ -152,6 +153,10 @@ impl Index for FlatIndex {
         IndexType::Scalar
     }
 
+    fn memory_size(&self) -> usize {
+        self.data.get_array_memory_size()
+    }
+
     fn statistics(&self) -> Result<String> {
         serde_json::to_string(&FlatStatistics {
             num_values: self.data.num_rows() as u32,
@@ -180,6 +185,24 @@ impl ScalarIndex for FlatIndex {
         let predicate = match query {
             ScalarQuery::Equals(value) => arrow_ord::cmp::eq(self.values(), &value.to_scalar())?,
             ScalarQuery::IsNull() => arrow::compute::is_null(self.values())?,
+            ScalarQuery::Contains(_) => {
+                return Err(Error::Index {
+                    message: "Flat index does not support substring queries".to_string(),
+                    location: location!(),
+                })
+            }
+            ScalarQuery::HasAnyLabel(_) | ScalarQuery::HasAllLabels(_) => {
+                return Err(Error::Index {
+                    message: "Flat index does not support label queries".to_string(),
+                    location: location!(),
+                })
+            }
+            ScalarQuery::JsonPath(..) => {
+                return Err(Error::Index {
+                    message: "Flat index does not support JSON path queries".to_string(),
+                    location: location!(),
+                })
+            }
             ScalarQuery::IsIn(values) => {
                 let choices = values
                     .iter()
@@ -0,0 +1,577 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Inverted index for full text search, ranked by BM25.
+
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
+
+use arrow_array::{
+    builder::{ListBuilder, UInt32Builder, UInt64Builder},
+    cast::AsArray,
+    types::{UInt32Type, UInt64Type},
+    Array, ArrayRef, RecordBatch, StringArray, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::TryStreamExt;
+use lance_core::{Error, Result};
+use nohash_hasher::IntMap;
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
+use snafu::{location, Location};
+
+use crate::{Index, IndexType};
+
+use super::IndexStore;
+
+/// Name of the file of the tokens and their posting lists, which is also used to
+/// tell an inverted index from the other indices.
+pub const INVERTED_TOKENS_NAME: &str = "inverted_tokens.lance";
+/// Name of the file of the number of tokens of each document.
+const INVERTED_DOCS_NAME: &str = "inverted_docs.lance";
+/// Key of the tokenizer config in the metadata of the docs file.
+const TOKENIZER_META_KEY: &str = "tokenizer";
+
+/// BM25 parameters, see <https://en.wikipedia.org/wiki/Okapi_BM25>.
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// Common English words, which are dropped if [TokenizerConfig::remove_stop_words]
+/// is set.
+const STOP_WORDS: &[&str] = &[
+    "a", "an", "and", "are", "as", "at", "be", "but", "by", "for", "if", "in", "into", "is", "it",
+    "no", "not", "of", "on", "or", "such", "that", "the", "their", "then", "there", "these",
+    "they", "this", "to", "was", "will", "with",
+];
+
+/// How the text is split into tokens, at build time and at query time.
+///
+/// The text is split on the characters which are not alphanumeric.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenizerConfig {
+    /// Lower case the tokens, so that the search is case insensitive.
+    pub lower_case: bool,
+
+    /// Drop the tokens longer than this number of characters, e.g., hashes or
+    /// base64 strings.
+    pub max_token_length: Option<usize>,
+
+    /// Drop common English words, e.g., "the" or "of".
+    pub remove_stop_words: bool,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            lower_case: true,
+            max_token_length: Some(40),
+            remove_stop_words: false,
+        }
+    }
+}
+
+impl TokenizerConfig {
+    pub fn lower_case(&mut self, lower_case: bool) -> &mut Self {
+        self.lower_case = lower_case;
+        self
+    }
+
+    pub fn max_token_length(&mut self, max_token_length: Option<usize>) -> &mut Self {
+        self.max_token_length = max_token_length;
+        self
+    }
+
+    pub fn remove_stop_words(&mut self, remove_stop_words: bool) -> &mut Self {
+        self.remove_stop_words = remove_stop_words;
+        self
+    }
+
+    /// Split `text` into tokens.
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|token| !token.is_empty())
+            .filter(|token| {
+                self.max_token_length
+                    .map_or(true, |max_len| token.chars().count() <= max_len)
+            })
+            .map(|token| {
+                if self.lower_case {
+                    token.to_lowercase()
+                } else {
+                    token.to_string()
+                }
+            })
+            .filter(|token| {
+                !self.remove_stop_words || !STOP_WORDS.contains(&token.to_lowercase().as_str())
+            })
+            .collect()
+    }
+}
+
+/// The documents, i.e., rows, which contain a token.
+#[derive(Debug, Clone, Default)]
+struct PostingList {
+    row_ids: Vec<u64>,
+    /// Number of occurrences of the token in each document.
+    frequencies: Vec<u32>,
+}
+
+/// An inverted index maps each token of a string column to the rows which contain it.
+///
+/// The whole index is kept in memory. A query is split into tokens like the indexed
+/// text, and the rows which contain any of them are ranked by BM25.
+#[derive(Debug, Clone)]
+pub struct InvertedIndex {
+    tokenizer: TokenizerConfig,
+    /// The position of each token in `postings`.
+    tokens: HashMap<String, u32>,
+    postings: Vec<PostingList>,
+    /// Number of tokens of each document.
+    docs: IntMap<u64, u32>,
+    total_tokens: u64,
+}
+
+impl InvertedIndex {
+    fn new(tokenizer: TokenizerConfig) -> Self {
+        Self {
+            tokenizer,
+            tokens: HashMap::new(),
+            postings: vec![],
+            docs: IntMap::default(),
+            total_tokens: 0,
+        }
+    }
+
+    pub fn tokenizer(&self) -> &TokenizerConfig {
+        &self.tokenizer
+    }
+
+    /// Add a document. Null documents are not indexed.
+    fn add_document(&mut self, row_id: u64, text: &str) {
+        let tokens = self.tokenizer.tokenize(text);
+        let mut frequencies = HashMap::<String, u32>::new();
+        for token in &tokens {
+            *frequencies.entry(token.clone()).or_default() += 1;
+        }
+        for (token, frequency) in frequencies {
+            let next_id = self.postings.len() as u32;
+            let id = *self.tokens.entry(token).or_insert(next_id);
+            if id == next_id {
+                self.postings.push(PostingList::default());
+            }
+            let posting = &mut self.postings[id as usize];
+            posting.row_ids.push(row_id);
+            posting.frequencies.push(frequency);
+        }
+        self.docs.insert(row_id, tokens.len() as u32);
+        self.total_tokens += tokens.len() as u64;
+    }
+
+    /// Add the documents of a batch, whose first column is the text and second
+    /// column is the row ids.
+    fn add_batch(&mut self, batch: &RecordBatch) -> Result<()> {
+        let row_ids = batch.column(1).as_primitive::<UInt64Type>();
+        let texts = batch.column(0);
+        let mut add = |texts: &mut dyn Iterator<Item = Option<&str>>| {
+            for (text, row_id) in texts.zip(row_ids.values().iter()) {
+                if let Some(text) = text {
+                    self.add_document(*row_id, text);
+                }
+            }
+        };
+        match texts.data_type() {
+            DataType::Utf8 => add(&mut texts.as_string::<i32>().iter()),
+            DataType::LargeUtf8 => add(&mut texts.as_string::<i64>().iter()),
+            data_type => {
+                return Err(Error::Index {
+                    message: format!("Inverted index requires a string column, got {data_type}"),
+                    location: location!(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    async fn write(&self, dest_store: &dyn IndexStore) -> Result<()> {
+        let mut tokens = vec![""; self.postings.len()];
+        for (token, id) in &self.tokens {
+            tokens[*id as usize] = token;
+        }
+        let mut row_ids = ListBuilder::new(UInt64Builder::new());
+        let mut frequencies = ListBuilder::new(UInt32Builder::new());
+        for posting in &self.postings {
+            row_ids.values().append_slice(&posting.row_ids);
+            row_ids.append(true);
+            frequencies.values().append_slice(&posting.frequencies);
+            frequencies.append(true);
+        }
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(StringArray::from(tokens)),
+            Arc::new(row_ids.finish()),
+            Arc::new(frequencies.finish()),
+        ];
+        let schema = Arc::new(Schema::new(
+            ["tokens", "row_ids", "frequencies"]
+                .iter()
+                .zip(&columns)
+                .map(|(name, column)| Field::new(*name, column.data_type().clone(), false))
+                .collect::<Vec<_>>(),
+        ));
+        let batch = RecordBatch::try_new(schema.clone(), columns)?;
+        let mut writer = dest_store
+            .new_index_file(INVERTED_TOKENS_NAME, schema)
+            .await?;
+        writer.write_record_batch(batch).await?;
+        writer.finish().await?;
+
+        let (doc_ids, num_tokens): (Vec<u64>, Vec<u32>) =
+            self.docs.iter().map(|(k, v)| (*k, *v)).unzip();
+        let schema = Arc::new(
+            Schema::new(vec![
+                Field::new("row_ids", DataType::UInt64, false),
+                Field::new("num_tokens", DataType::UInt32, false),
+            ])
+            .with_metadata(HashMap::from([(
+                TOKENIZER_META_KEY.to_string(),
+                serde_json::to_string(&self.tokenizer)?,
+            )])),
+        );
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(UInt64Array::from(doc_ids)),
+                Arc::new(UInt32Array::from(num_tokens)),
+            ],
+        )?;
+        let mut writer = dest_store
+            .new_index_file(INVERTED_DOCS_NAME, schema)
+            .await?;
+        writer.write_record_batch(batch).await?;
+        writer.finish().await
+    }
+
+    /// Load the inverted index from storage.
+    pub async fn load(store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
+        let docs = store
+            .open_index_file(INVERTED_DOCS_NAME)
+            .await?
+            .read_record_batch(0)
+            .await?;
+        let tokenizer = docs
+            .schema()
+            .metadata()
+            .get(TOKENIZER_META_KEY)
+            .ok_or_else(|| Error::Index {
+                message: "Inverted index is missing the tokenizer config".to_string(),
+                location: location!(),
+            })
+            .and_then(|config| Ok(serde_json::from_str(config)?))?;
+        let mut index = Self::new(tokenizer);
+        for (row_id, num_tokens) in docs
+            .column(0)
+            .as_primitive::<UInt64Type>()
+            .values()
+            .iter()
+            .zip(docs.column(1).as_primitive::<UInt32Type>().values())
+        {
+            index.docs.insert(*row_id, *num_tokens);
+            index.total_tokens += *num_tokens as u64;
+        }
+
+        let tokens = store
+            .open_index_file(INVERTED_TOKENS_NAME)
+            .await?
+            .read_record_batch(0)
+            .await?;
+        let row_ids = tokens.column(1).as_list::<i32>();
+        let frequencies = tokens.column(2).as_list::<i32>();
+        for (id, token) in tokens.column(0).as_string::<i32>().iter().enumerate() {
+            index
+                .tokens
+                .insert(token.unwrap_or_default().to_string(), id as u32);
+            index.postings.push(PostingList {
+                row_ids: row_ids
+                    .value(id)
+                    .as_primitive::<UInt64Type>()
+                    .values()
+                    .to_vec(),
+                frequencies: frequencies
+                    .value(id)
+                    .as_primitive::<UInt32Type>()
+                    .values()
+                    .to_vec(),
+            });
+        }
+        Ok(Arc::new(index))
+    }
+
+    /// The distinct tokens of a query.
+    pub fn query_tokens(&self, query: &str) -> Vec<String> {
+        let mut seen = HashSet::new();
+        self.tokenizer
+            .tokenize(query)
+            .into_iter()
+            .filter(|token| seen.insert(token.clone()))
+            .collect()
+    }
+
+    fn num_docs(&self) -> usize {
+        self.docs.len()
+    }
+
+    fn avg_doc_length(&self) -> f32 {
+        if self.docs.is_empty() {
+            0.0
+        } else {
+            self.total_tokens as f32 / self.docs.len() as f32
+        }
+    }
+
+    /// Inverse document frequency of a token in `num_matches` of the documents.
+    fn idf(&self, num_matches: usize) -> f32 {
+        let num_docs = self.num_docs() as f32;
+        let num_matches = num_matches as f32;
+        (1.0 + (num_docs - num_matches + 0.5) / (num_matches + 0.5)).ln()
+    }
+
+    fn bm25(&self, idf: f32, frequency: u32, doc_length: u32, avg_doc_length: f32) -> f32 {
+        let frequency = frequency as f32;
+        let norm = if avg_doc_length > 0.0 {
+            doc_length as f32 / avg_doc_length
+        } else {
+            1.0
+        };
+        idf * frequency * (K1 + 1.0) / (frequency + K1 * (1.0 - B + B * norm))
+    }
+
+    /// Search the documents which contain any of `tokens`, see [Self::query_tokens].
+    ///
+    /// Returns the row ids and the BM25 scores of the documents accepted by
+    /// `is_allowed`, the best scored first, at most `limit` of them.
+    pub fn bm25_search(
+        &self,
+        tokens: &[String],
+        limit: Option<usize>,
+        is_allowed: impl Fn(u64) -> bool,
+    ) -> (Vec<u64>, Vec<f32>) {
+        let avg_doc_length = self.avg_doc_length();
+        let mut scores = IntMap::<u64, f32>::default();
+        for token in tokens {
+            let Some(id) = self.tokens.get(token) else {
+                continue;
+            };
+            let posting = &self.postings[*id as usize];
+            let idf = self.idf(posting.row_ids.len());
+            for (row_id, frequency) in posting.row_ids.iter().zip(&posting.frequencies) {
+                if !is_allowed(*row_id) {
+                    continue;
+                }
+                let doc_length = self.docs.get(row_id).copied().unwrap_or_default();
+                *scores.entry(*row_id).or_default() +=
+                    self.bm25(idf, *frequency, doc_length, avg_doc_length);
+            }
+        }
+        let mut results = scores.into_iter().collect::<Vec<_>>();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        if let Some(limit) = limit {
+            results.truncate(limit);
+        }
+        results.into_iter().unzip()
+    }
+
+    /// BM25 score of a document which is not in the index, e.g., a row appended after
+    /// the index was built, with the statistics of the indexed documents.
+    pub fn bm25_score(&self, tokens: &[String], text: &str) -> f32 {
+        let doc_tokens = self.tokenizer.tokenize(text);
+        let avg_doc_length = self.avg_doc_length();
+        tokens
+            .iter()
+            .map(|token| {
+                let frequency = doc_tokens.iter().filter(|t| *t == token).count() as u32;
+                if frequency == 0 {
+                    return 0.0;
+                }
+                let num_matches = self
+                    .tokens
+                    .get(token)
+                    .map_or(0, |id| self.postings[*id as usize].row_ids.len());
+                self.bm25(
+                    self.idf(num_matches),
+                    frequency,
+                    doc_tokens.len() as u32,
+                    avg_doc_length,
+                )
+            })
+            .sum()
+    }
+
+    /// Remap the row ids, creating a new remapped version of this index in `dest_store`.
+    pub async fn remap(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let remap = |row_id: &u64| mapping.get(row_id).copied().unwrap_or(Some(*row_id));
+        let mut index = self.clone();
+        for posting in index.postings.iter_mut() {
+            let (row_ids, frequencies) = posting
+                .row_ids
+                .iter()
+                .zip(&posting.frequencies)
+                .filter_map(|(row_id, frequency)| remap(row_id).map(|new_id| (new_id, *frequency)))
+                .unzip();
+            posting.row_ids = row_ids;
+            posting.frequencies = frequencies;
+        }
+        index.docs = self
+            .docs
+            .iter()
+            .filter_map(|(row_id, num_tokens)| remap(row_id).map(|new_id| (new_id, *num_tokens)))
+            .collect();
+        index.total_tokens = index.docs.values().map(|n| *n as u64).sum();
+        index.write(dest_store).await
+    }
+
+    /// Add the new documents, creating an updated version of this index in `dest_store`.
+    pub async fn update(
+        &self,
+        mut new_data: SendableRecordBatchStream,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let mut index = self.clone();
+        while let Some(batch) = new_data.try_next().await? {
+            index.add_batch(&batch)?;
+        }
+        index.write(dest_store).await
+    }
+}
+
+/// Train an inverted index from a stream of batches, whose first column is the text
+/// and second column is the row ids, and write it to `index_store`.
+pub async fn train_inverted_index(
+    mut data: SendableRecordBatchStream,
+    tokenizer: TokenizerConfig,
+    index_store: &dyn IndexStore,
+) -> Result<()> {
+    let mut index = InvertedIndex::new(tokenizer);
+    while let Some(batch) = data.try_next().await? {
+        index.add_batch(&batch)?;
+    }
+    index.write(index_store).await
+}
+
+#[derive(Serialize)]
+struct InvertedIndexStatistics {
+    num_tokens: usize,
+    num_docs: usize,
+    tokenizer: TokenizerConfig,
+}
+
+#[async_trait]
+impl Index for InvertedIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Inverted
+    }
+
+    fn memory_size(&self) -> usize {
+        let tokens = self.tokens.keys().map(|t| t.len()).sum::<usize>();
+        let postings = self
+            .postings
+            .iter()
+            .map(|p| p.row_ids.len() * (std::mem::size_of::<u64>() + std::mem::size_of::<u32>()))
+            .sum::<usize>();
+        tokens
+            + postings
+            + self.docs.len() * (std::mem::size_of::<u64>() + std::mem::size_of::<u32>())
+    }
+
+    fn statistics(&self) -> Result<String> {
+        serde_json::to_string(&InvertedIndexStatistics {
+            num_tokens: self.tokens.len(),
+            num_docs: self.docs.len(),
+            tokenizer: self.tokenizer.clone(),
+        })
+        .map_err(|err| err.into())
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        // The high 32 bits of a row id are its fragment id.
+        Ok(self
+            .docs
+            .keys()
+            .map(|row_id| (row_id >> 32) as u32)
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokenize() {
+        let mut config = TokenizerConfig::default();
+        assert_eq!(
+            config.tokenize("The Lance format, for ML-ready data!"),
+            vec!["the", "lance", "format", "for", "ml", "ready", "data"]
+        );
+        config
+            .lower_case(false)
+            .max_token_length(Some(5))
+            .remove_stop_words(true);
+        assert_eq!(
+            config.tokenize("The Lance format, for ML-ready data!"),
+            vec!["Lance", "ML", "ready", "data"]
+        );
+    }
+
+    #[test]
+    fn test_bm25_ranking() {
+        let mut index = InvertedIndex::new(TokenizerConfig::default());
+        index.add_document(0, "lance is a columnar format");
+        index.add_document(1, "lance lance lance");
+        index.add_document(2, "a format for vectors and a format for tables");
+        index.add_document(3, "nothing to see here");
+
+        let tokens = index.query_tokens("Lance FORMAT lance");
+        assert_eq!(tokens, vec!["lance", "format"]);
+        let (row_ids, scores) = index.bm25_search(&tokens, None, |_| true);
+        // The only document with both tokens is ranked first.
+        assert_eq!(row_ids[0], 0);
+        assert_eq!(row_ids.len(), 3);
+        assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+
+        let (row_ids, _) = index.bm25_search(&tokens, Some(1), |row_id| row_id != 0);
+        assert_eq!(row_ids.len(), 1);
+        assert_ne!(row_ids[0], 0);
+
+        // An unindexed document is scored like an indexed one.
+        let score = index.bm25_score(&tokens, "lance is a columnar format");
+        assert!((score - scores[0]).abs() < 1e-6);
+        assert_eq!(index.bm25_score(&tokens, "nothing"), 0.0);
+    }
+}
@@ -0,0 +1,245 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, collections::BTreeMap, sync::Arc};
+
+use arrow_array::{cast::AsArray, RecordBatch, StringArray, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::TryStreamExt;
+use lance_core::{Error, Result};
+use lance_datafusion::json::JsonPath;
+use nohash_hasher::IntMap;
+use roaring::{RoaringBitmap, RoaringTreemap};
+use serde::Serialize;
+use snafu::{location, Location};
+
+use crate::{Index, IndexType};
+
+use super::{
+    bitmap::BitmapIndex, btree::OrderableScalarValue, IndexStore, ScalarIndex, ScalarQuery,
+};
+
+/// Name of the file listing the paths of a JSON index, which is also used to tell a
+/// JSON index from the other scalar indices.
+pub const JSON_PATHS_NAME: &str = "json_paths.lance";
+
+/// Name of the file of the bitmaps of the `idx`-th path of a JSON index.
+fn json_lookup_name(idx: usize) -> String {
+    format!("json_lookup_{idx}.lance")
+}
+
+type Bitmaps = BTreeMap<OrderableScalarValue, RoaringTreemap>;
+
+/// A JSON index keeps a bitmap of the rows of each value at some paths, e.g., `$.type`,
+/// of a string column of JSON documents.
+///
+/// It answers [ScalarQuery::JsonPath] queries, e.g., from `json_extract(col, '$.type') = 'x'`
+/// filters, for the paths chosen when the index is created. The values are compared as
+/// strings, the same as the results of `json_extract`.
+#[derive(Clone, Debug)]
+pub struct JsonIndex {
+    paths: Vec<JsonPath>,
+    /// The values at each of the paths, stored the same way as a bitmap index.
+    values: Vec<BitmapIndex>,
+}
+
+impl JsonIndex {
+    /// The indexed paths.
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.paths.iter().map(JsonPath::as_str)
+    }
+
+    /// Add the documents and row ids of `batch` to the `bitmaps` of each of the `paths`.
+    fn add_batch(paths: &[JsonPath], bitmaps: &mut [Bitmaps], batch: &RecordBatch) -> Result<()> {
+        for (path, bitmaps) in paths.iter().zip(bitmaps.iter_mut()) {
+            let values = path.extract_array(batch.column(0).as_ref())?;
+            let values = RecordBatch::try_from_iter(vec![
+                ("values", Arc::new(values) as _),
+                ("row_ids", batch.column(1).clone()),
+            ])?;
+            BitmapIndex::add_batch(bitmaps, &values)?;
+        }
+        Ok(())
+    }
+
+    async fn write(
+        paths: &[JsonPath],
+        bitmaps: &[Bitmaps],
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "paths",
+            DataType::Utf8,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(StringArray::from_iter_values(
+                paths.iter().map(JsonPath::as_str),
+            ))],
+        )?;
+        let mut writer = dest_store.new_index_file(JSON_PATHS_NAME, schema).await?;
+        writer.write_record_batch(batch).await?;
+        writer.finish().await?;
+
+        for (idx, bitmaps) in bitmaps.iter().enumerate() {
+            BitmapIndex::write(bitmaps, &DataType::Utf8, dest_store, &json_lookup_name(idx))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn bitmaps(&self) -> Vec<Bitmaps> {
+        self.values
+            .iter()
+            .map(|values| values.bitmaps.clone())
+            .collect()
+    }
+}
+
+/// Train a JSON index of the values at the `paths`, from a stream of batches, whose first
+/// column is the JSON documents and second column is the row ids, and write it to
+/// `index_store`.
+pub async fn train_json_index(
+    mut data: SendableRecordBatchStream,
+    paths: &[String],
+    index_store: &dyn IndexStore,
+) -> Result<()> {
+    if paths.is_empty() {
+        return Err(Error::Index {
+            message: "JSON index requires at least one path".to_string(),
+            location: location!(),
+        });
+    }
+    let paths = paths
+        .iter()
+        .map(|path| JsonPath::try_new(path))
+        .collect::<Result<Vec<_>>>()?;
+    let mut bitmaps = vec![Bitmaps::new(); paths.len()];
+    while let Some(batch) = data.try_next().await? {
+        JsonIndex::add_batch(&paths, &mut bitmaps, &batch)?;
+    }
+    JsonIndex::write(&paths, &bitmaps, index_store).await
+}
+
+#[derive(Serialize)]
+struct JsonStatistics<'a> {
+    paths: Vec<&'a str>,
+    num_values: Vec<usize>,
+}
+
+#[async_trait]
+impl Index for JsonIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Scalar
+    }
+
+    fn memory_size(&self) -> usize {
+        self.values.iter().map(BitmapIndex::memory_size).sum()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        serde_json::to_string(&JsonStatistics {
+            paths: self.paths().collect(),
+            num_values: self
+                .values
+                .iter()
+                .map(|values| values.bitmaps.len())
+                .collect(),
+        })
+        .map_err(|err| err.into())
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        // Every indexed row has a value, maybe null, at each path.
+        match self.values.first() {
+            Some(values) => values.calculate_included_frags().await,
+            None => Ok(RoaringBitmap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl ScalarIndex for JsonIndex {
+    async fn search(&self, query: &ScalarQuery) -> Result<UInt64Array> {
+        let ScalarQuery::JsonPath(path, query) = query else {
+            return Err(Error::Index {
+                message: format!(
+                    "JSON index only supports JSON path queries, got {}",
+                    query.fmt_with_col("column")
+                ),
+                location: location!(),
+            });
+        };
+        let Some(idx) = self.paths.iter().position(|p| p.as_str() == path) else {
+            return Err(Error::Index {
+                message: format!("JSON index does not have the path '{path}'"),
+                location: location!(),
+            });
+        };
+        self.values[idx].search(query).await
+    }
+
+    async fn load(store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
+        let paths_file = store.open_index_file(JSON_PATHS_NAME).await?;
+        let paths = paths_file.read_record_batch(0).await?;
+        let paths = paths
+            .column(0)
+            .as_string::<i32>()
+            .iter()
+            .flatten()
+            .map(JsonPath::try_new)
+            .collect::<Result<Vec<_>>>()?;
+        let mut values = Vec::with_capacity(paths.len());
+        for idx in 0..paths.len() {
+            values.push(BitmapIndex::load_file(store.as_ref(), &json_lookup_name(idx)).await?);
+        }
+        Ok(Arc::new(Self { paths, values }))
+    }
+
+    async fn remap(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let bitmaps = self
+            .values
+            .iter()
+            .map(|values| values.remapped_bitmaps(mapping))
+            .collect::<Vec<_>>();
+        Self::write(&self.paths, &bitmaps, dest_store).await
+    }
+
+    async fn update(
+        &self,
+        mut new_data: SendableRecordBatchStream,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let mut bitmaps = self.bitmaps();
+        while let Some(batch) = new_data.try_next().await? {
+            Self::add_batch(&self.paths, &mut bitmaps, &batch)?;
+        }
+        Self::write(&self.paths, &bitmaps, dest_store).await
+    }
+}
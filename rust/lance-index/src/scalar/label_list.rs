@@ -0,0 +1,245 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, collections::BTreeMap, sync::Arc};
+
+use arrow_array::{
+    cast::AsArray, types::UInt64Type, Array, GenericListArray, OffsetSizeTrait, RecordBatch,
+    UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use datafusion_common::ScalarValue;
+use futures::TryStreamExt;
+use lance_core::{Error, Result};
+use nohash_hasher::IntMap;
+use roaring::{RoaringBitmap, RoaringTreemap};
+use serde::Serialize;
+use snafu::{location, Location};
+
+use crate::{Index, IndexType};
+
+use super::{
+    bitmap::BitmapIndex, btree::OrderableScalarValue, IndexStore, ScalarIndex, ScalarQuery,
+};
+
+/// Name of the file of a label list index, which is also used to tell a label list index
+/// from the other scalar indices.
+pub const LABEL_LIST_LOOKUP_NAME: &str = "label_list_lookup.lance";
+
+/// Whether a label list index can be created on a column of `data_type`, i.e., a list
+/// of non-nested values.
+pub fn supports_label_list(data_type: &DataType) -> bool {
+    match data_type {
+        DataType::List(item) | DataType::LargeList(item) => !item.data_type().is_nested(),
+        _ => false,
+    }
+}
+
+/// A label list index keeps a bitmap of the rows whose list contains each label, of a
+/// list column, e.g., the tags of the rows.
+///
+/// It answers [ScalarQuery::HasAnyLabel] and [ScalarQuery::HasAllLabels] queries, e.g.,
+/// from `array_has_any` and `array_has_all` filters, with the union or the intersection
+/// of the bitmaps of the labels.
+#[derive(Clone, Debug)]
+pub struct LabelListIndex {
+    /// The bitmaps of the labels, stored the same way as a bitmap index.
+    labels: BitmapIndex,
+}
+
+/// Flatten the lists of `lists`, with the row id of its list for each label.
+fn flatten_lists<O: OffsetSizeTrait>(
+    lists: &GenericListArray<O>,
+    row_ids: &UInt64Array,
+) -> Result<RecordBatch> {
+    let offsets = lists.value_offsets();
+    let mut indices = Vec::with_capacity(lists.values().len());
+    let mut label_row_ids = Vec::with_capacity(lists.values().len());
+    for (idx, row_id) in row_ids.values().iter().enumerate() {
+        if lists.is_null(idx) {
+            continue;
+        }
+        let (start, end) = (offsets[idx].as_usize(), offsets[idx + 1].as_usize());
+        indices.extend(start as u64..end as u64);
+        label_row_ids.extend(std::iter::repeat(*row_id).take(end - start));
+    }
+    let labels =
+        arrow_select::take::take(lists.values().as_ref(), &UInt64Array::from(indices), None)?;
+    let schema = Schema::new(vec![
+        Field::new("values", labels.data_type().clone(), true),
+        Field::new("row_ids", DataType::UInt64, false),
+    ]);
+    Ok(RecordBatch::try_new(
+        Arc::new(schema),
+        vec![labels, Arc::new(UInt64Array::from(label_row_ids))],
+    )?)
+}
+
+impl LabelListIndex {
+    /// Add the lists and row ids of `batch` to `bitmaps`. Null lists are not indexed.
+    fn add_batch(
+        bitmaps: &mut BTreeMap<OrderableScalarValue, RoaringTreemap>,
+        batch: &RecordBatch,
+    ) -> Result<()> {
+        let row_ids = batch.column(1).as_primitive::<UInt64Type>();
+        let labels = match batch.column(0).data_type() {
+            DataType::List(_) => flatten_lists(batch.column(0).as_list::<i32>(), row_ids)?,
+            DataType::LargeList(_) => flatten_lists(batch.column(0).as_list::<i64>(), row_ids)?,
+            data_type => {
+                return Err(Error::Index {
+                    message: format!("Label list index requires a list column, got {data_type}"),
+                    location: location!(),
+                })
+            }
+        };
+        BitmapIndex::add_batch(bitmaps, &labels)
+    }
+
+    fn bitmap(&self, label: &ScalarValue) -> Option<&RoaringTreemap> {
+        self.labels
+            .bitmaps
+            .get(&OrderableScalarValue(label.clone()))
+    }
+}
+
+/// Train a label list index from a stream of batches, whose first column is the lists
+/// and second column is the row ids, and write it to `index_store`.
+pub async fn train_label_list_index(
+    mut data: SendableRecordBatchStream,
+    index_store: &dyn IndexStore,
+) -> Result<()> {
+    let label_type = match data.schema().field(0).data_type() {
+        DataType::List(item) | DataType::LargeList(item) => item.data_type().clone(),
+        data_type => {
+            return Err(Error::Index {
+                message: format!("Label list index requires a list column, got {data_type}"),
+                location: location!(),
+            })
+        }
+    };
+    let mut bitmaps = BTreeMap::new();
+    while let Some(batch) = data.try_next().await? {
+        LabelListIndex::add_batch(&mut bitmaps, &batch)?;
+    }
+    BitmapIndex::write(&bitmaps, &label_type, index_store, LABEL_LIST_LOOKUP_NAME).await
+}
+
+#[derive(Serialize)]
+struct LabelListStatistics {
+    num_labels: usize,
+}
+
+#[async_trait]
+impl Index for LabelListIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Scalar
+    }
+
+    fn memory_size(&self) -> usize {
+        self.labels.memory_size()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        serde_json::to_string(&LabelListStatistics {
+            num_labels: self.labels.bitmaps.len(),
+        })
+        .map_err(|err| err.into())
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        self.labels.calculate_included_frags().await
+    }
+}
+
+#[async_trait]
+impl ScalarIndex for LabelListIndex {
+    async fn search(&self, query: &ScalarQuery) -> Result<UInt64Array> {
+        let row_ids = match query {
+            ScalarQuery::HasAnyLabel(labels) => labels
+                .iter()
+                .filter_map(|label| self.bitmap(label))
+                .fold(RoaringTreemap::new(), |acc, bitmap| acc | bitmap),
+            ScalarQuery::HasAllLabels(labels) => {
+                let mut bitmaps = labels.iter().map(|label| self.bitmap(label));
+                match bitmaps.next() {
+                    Some(Some(first)) => bitmaps
+                        .try_fold(first.clone(), |acc, bitmap| {
+                            bitmap.map(|bitmap| acc & bitmap)
+                        })
+                        .unwrap_or_default(),
+                    // A label without any row, or no label at all, matches no row.
+                    _ => RoaringTreemap::new(),
+                }
+            }
+            _ => {
+                return Err(Error::Index {
+                    message: format!(
+                        "Label list index only supports label queries, got {}",
+                        query.fmt_with_col("column")
+                    ),
+                    location: location!(),
+                })
+            }
+        };
+        Ok(UInt64Array::from_iter_values(row_ids))
+    }
+
+    async fn load(store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
+        let labels = BitmapIndex::load_file(store.as_ref(), LABEL_LIST_LOOKUP_NAME).await?;
+        Ok(Arc::new(Self { labels }))
+    }
+
+    async fn remap(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let bitmaps = self.labels.remapped_bitmaps(mapping);
+        BitmapIndex::write(
+            &bitmaps,
+            &self.labels.value_type,
+            dest_store,
+            LABEL_LIST_LOOKUP_NAME,
+        )
+        .await
+    }
+
+    async fn update(
+        &self,
+        mut new_data: SendableRecordBatchStream,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let mut bitmaps = self.labels.bitmaps.clone();
+        while let Some(batch) = new_data.try_next().await? {
+            Self::add_batch(&mut bitmaps, &batch)?;
+        }
+        BitmapIndex::write(
+            &bitmaps,
+            &self.labels.value_type,
+            dest_store,
+            LABEL_LIST_LOOKUP_NAME,
+        )
+        .await
+    }
+}
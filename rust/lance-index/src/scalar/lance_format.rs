@@ -149,8 +149,11 @@ mod tests {
     use std::{ops::Bound, path::Path};
 
     use crate::scalar::{
+        bitmap::{train_bitmap_index, BitmapIndex},
         btree::{train_btree_index, BTreeIndex, BtreeTrainingSource},
         flat::FlatIndexMetadata,
+        json::{train_json_index, JsonIndex},
+        label_list::{train_label_list_index, LabelListIndex},
         ScalarIndex, ScalarQuery,
     };
 
@@ -252,6 +255,24 @@ mod tests {
             .unwrap();
 
         assert_eq!(100, row_ids.len());
+
+        // The selectivity is estimated by the pages which may match
+        let selectivity = |query| index.estimate_selectivity(&query).unwrap();
+        assert_eq!(
+            selectivity(ScalarQuery::Range(
+                Bound::Unbounded,
+                Bound::Excluded(ScalarValue::Int32(Some(100))),
+            )),
+            0.01
+        );
+        assert_eq!(
+            selectivity(ScalarQuery::Range(
+                Bound::Included(ScalarValue::Int32(Some(0))),
+                Bound::Unbounded,
+            )),
+            1.0
+        );
+        assert_eq!(selectivity(ScalarQuery::IsNull()), 0.0);
     }
 
     #[tokio::test]
@@ -623,4 +644,401 @@ mod tests {
                 .is_err()
         );
     }
+
+    #[tokio::test]
+    async fn test_bitmap_index() {
+        let index_dir = tempdir().unwrap();
+        let index_store = test_store(&index_dir);
+        let data = gen()
+            .col(
+                Some("values".to_string()),
+                array::cycle_utf8_literals(&["US", "CN", "FR"]),
+            )
+            .col(Some("row_ids".to_string()), array::step::<UInt64Type>())
+            .into_reader_rows(RowCount::from(100), BatchCount::from(3));
+        train_bitmap_index(
+            reader_to_stream(Box::new(data)).unwrap().0,
+            index_store.as_ref(),
+        )
+        .await
+        .unwrap();
+        let index = BitmapIndex::load(index_store).await.unwrap();
+
+        let utf8 = |val: &str| ScalarValue::Utf8(Some(val.to_string()));
+        let row_ids = index
+            .search(&ScalarQuery::Equals(utf8("FR")))
+            .await
+            .unwrap();
+        assert_eq!(100, row_ids.len());
+        assert!(row_ids.values().iter().all(|row_id| row_id % 3 == 2));
+        let row_ids = index
+            .search(&ScalarQuery::Equals(utf8("DE")))
+            .await
+            .unwrap();
+        assert_eq!(0, row_ids.len());
+        let row_ids = index
+            .search(&ScalarQuery::IsIn(vec![utf8("US"), utf8("CN"), utf8("DE")]))
+            .await
+            .unwrap();
+        assert_eq!(200, row_ids.len());
+        let row_ids = index
+            .search(&ScalarQuery::Range(
+                Bound::Excluded(utf8("CN")),
+                Bound::Unbounded,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(200, row_ids.len());
+
+        // The selectivity is estimated by the sizes of the bitmaps
+        let selectivity = |query| index.estimate_selectivity(&query);
+        assert_eq!(
+            selectivity(ScalarQuery::Equals(utf8("FR"))),
+            Some(1.0 / 3.0)
+        );
+        assert_eq!(
+            selectivity(ScalarQuery::IsIn(vec![utf8("US"), utf8("CN"), utf8("DE")])),
+            Some(2.0 / 3.0)
+        );
+        assert_eq!(
+            selectivity(ScalarQuery::Contains(vec!["U".to_string()])),
+            None
+        );
+
+        // Updated with more rows and remapped
+        let data = gen()
+            .col(
+                Some("values".to_string()),
+                array::cycle_utf8_literals(&["DE"]),
+            )
+            .col(
+                Some("row_ids".to_string()),
+                array::step_custom::<UInt64Type>(300, 1),
+            )
+            .into_reader_rows(RowCount::from(10), BatchCount::from(1));
+        let updated_index_dir = tempdir().unwrap();
+        let updated_index_store = test_store(&updated_index_dir);
+        index
+            .update(
+                reader_to_stream(Box::new(data)).unwrap().0,
+                updated_index_store.as_ref(),
+            )
+            .await
+            .unwrap();
+        let updated_index = BitmapIndex::load(updated_index_store).await.unwrap();
+        let row_ids = updated_index
+            .search(&ScalarQuery::Equals(utf8("DE")))
+            .await
+            .unwrap();
+        assert_eq!((300..310).collect::<Vec<_>>(), row_ids.values().to_vec());
+
+        let mapping = nohash_hasher::IntMap::from_iter([(300, Some(1000)), (301, None)]);
+        let remapped_index_dir = tempdir().unwrap();
+        let remapped_index_store = test_store(&remapped_index_dir);
+        updated_index
+            .remap(&mapping, remapped_index_store.as_ref())
+            .await
+            .unwrap();
+        let remapped_index = BitmapIndex::load(remapped_index_store).await.unwrap();
+        let row_ids = remapped_index
+            .search(&ScalarQuery::Equals(utf8("DE")))
+            .await
+            .unwrap();
+        assert_eq!(
+            (302..310).chain([1000]).collect::<Vec<_>>(),
+            row_ids.values().to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bitmap_index_nulls() {
+        let tempdir = tempdir().unwrap();
+        let index_store = test_store(&tempdir);
+        let batch = RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("values", DataType::Int32, true),
+                Field::new("row_ids", DataType::UInt64, false),
+            ])),
+            vec![
+                Arc::new(arrow_array::Int32Array::from(vec![
+                    Some(1),
+                    None,
+                    Some(2),
+                    None,
+                    Some(3),
+                ])),
+                Arc::new(UInt64Array::from_iter_values(0..5)),
+            ],
+        )
+        .unwrap();
+        let schema = batch.schema();
+        let data = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        train_bitmap_index(
+            reader_to_stream(Box::new(data)).unwrap().0,
+            index_store.as_ref(),
+        )
+        .await
+        .unwrap();
+        let index = BitmapIndex::load(index_store).await.unwrap();
+
+        let row_ids = index.search(&ScalarQuery::IsNull()).await.unwrap();
+        assert_eq!(vec![1, 3], row_ids.values().to_vec());
+        // Nulls are never in a range.
+        let row_ids = index
+            .search(&ScalarQuery::Range(
+                Bound::Unbounded,
+                Bound::Included(ScalarValue::Int32(Some(2))),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(vec![0, 2], row_ids.values().to_vec());
+    }
+
+    fn tags_batch(tags: &[Option<Vec<&str>>], row_ids: std::ops::Range<u64>) -> RecordBatch {
+        let mut builder =
+            arrow_array::builder::ListBuilder::new(arrow_array::builder::StringBuilder::new());
+        for list in tags {
+            match list {
+                Some(list) => {
+                    for tag in list {
+                        builder.values().append_value(tag);
+                    }
+                    builder.append(true);
+                }
+                None => builder.append(false),
+            }
+        }
+        let tags = builder.finish();
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("values", arrow_array::Array::data_type(&tags).clone(), true),
+                Field::new("row_ids", DataType::UInt64, false),
+            ])),
+            vec![
+                Arc::new(tags),
+                Arc::new(UInt64Array::from_iter_values(row_ids)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_label_list_index() {
+        let index_dir = tempdir().unwrap();
+        let index_store = test_store(&index_dir);
+        let batch = tags_batch(
+            &[
+                Some(vec!["sale", "new"]),
+                Some(vec!["new"]),
+                None,
+                Some(vec![]),
+                Some(vec!["sale", "clearance"]),
+            ],
+            0..5,
+        );
+        let schema = batch.schema();
+        let data = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        train_label_list_index(
+            reader_to_stream(Box::new(data)).unwrap().0,
+            index_store.as_ref(),
+        )
+        .await
+        .unwrap();
+        let index = LabelListIndex::load(index_store).await.unwrap();
+
+        let utf8 = |val: &str| ScalarValue::Utf8(Some(val.to_string()));
+        let search = |index: Arc<LabelListIndex>, query: ScalarQuery| async move {
+            let mut row_ids = index.search(&query).await.unwrap().values().to_vec();
+            row_ids.sort();
+            row_ids
+        };
+        assert_eq!(
+            vec![0, 1, 4],
+            search(
+                index.clone(),
+                ScalarQuery::HasAnyLabel(vec![utf8("new"), utf8("clearance")])
+            )
+            .await
+        );
+        assert_eq!(
+            vec![0],
+            search(
+                index.clone(),
+                ScalarQuery::HasAllLabels(vec![utf8("sale"), utf8("new")])
+            )
+            .await
+        );
+        assert!(search(
+            index.clone(),
+            ScalarQuery::HasAllLabels(vec![utf8("sale"), utf8("unknown")])
+        )
+        .await
+        .is_empty());
+        assert!(index
+            .search(&ScalarQuery::Equals(utf8("sale")))
+            .await
+            .is_err());
+
+        // Updated with more rows and remapped
+        let batch = tags_batch(&[Some(vec!["new", "sale"])], 5..6);
+        let schema = batch.schema();
+        let data = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let updated_index_dir = tempdir().unwrap();
+        let updated_index_store = test_store(&updated_index_dir);
+        index
+            .update(
+                reader_to_stream(Box::new(data)).unwrap().0,
+                updated_index_store.as_ref(),
+            )
+            .await
+            .unwrap();
+        let updated_index = LabelListIndex::load(updated_index_store).await.unwrap();
+        assert_eq!(
+            vec![0, 5],
+            search(
+                updated_index.clone(),
+                ScalarQuery::HasAllLabels(vec![utf8("sale"), utf8("new")])
+            )
+            .await
+        );
+
+        let mapping = nohash_hasher::IntMap::from_iter([(0, None), (5, Some(100))]);
+        let remapped_index_dir = tempdir().unwrap();
+        let remapped_index_store = test_store(&remapped_index_dir);
+        updated_index
+            .remap(&mapping, remapped_index_store.as_ref())
+            .await
+            .unwrap();
+        let remapped_index = LabelListIndex::load(remapped_index_store).await.unwrap();
+        assert_eq!(
+            vec![100],
+            search(
+                remapped_index,
+                ScalarQuery::HasAllLabels(vec![utf8("sale"), utf8("new")])
+            )
+            .await
+        );
+    }
+
+    fn json_batch(documents: &[Option<&str>], row_ids: std::ops::Range<u64>) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new("values", DataType::Utf8, true),
+                Field::new("row_ids", DataType::UInt64, false),
+            ])),
+            vec![
+                Arc::new(arrow_array::StringArray::from(documents.to_vec())),
+                Arc::new(UInt64Array::from_iter_values(row_ids)),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_json_index() {
+        let index_dir = tempdir().unwrap();
+        let index_store = test_store(&index_dir);
+        let batch = json_batch(
+            &[
+                Some(r#"{"type": "shoe", "size": 42}"#),
+                Some(r#"{"type": "hat"}"#),
+                None,
+                Some("not json"),
+                Some(r#"{"type": "shoe", "size": 40}"#),
+            ],
+            0..5,
+        );
+        let schema = batch.schema();
+        let data = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        train_json_index(
+            reader_to_stream(Box::new(data)).unwrap().0,
+            &["$.type".to_string(), "$.size".to_string()],
+            index_store.as_ref(),
+        )
+        .await
+        .unwrap();
+        let index = JsonIndex::load(index_store).await.unwrap();
+        assert_eq!(vec!["$.type", "$.size"], index.paths().collect::<Vec<_>>());
+
+        let utf8 = |val: &str| ScalarValue::Utf8(Some(val.to_string()));
+        let json_query = |path: &str, query: ScalarQuery| {
+            ScalarQuery::JsonPath(path.to_string(), Box::new(query))
+        };
+        let search = |index: Arc<JsonIndex>, query: ScalarQuery| async move {
+            let mut row_ids = index.search(&query).await.unwrap().values().to_vec();
+            row_ids.sort();
+            row_ids
+        };
+        assert_eq!(
+            vec![0, 4],
+            search(
+                index.clone(),
+                json_query("$.type", ScalarQuery::Equals(utf8("shoe")))
+            )
+            .await
+        );
+        assert_eq!(
+            vec![0],
+            search(
+                index.clone(),
+                json_query(
+                    "$.size",
+                    ScalarQuery::Range(Bound::Excluded(utf8("40")), Bound::Unbounded)
+                )
+            )
+            .await
+        );
+        assert_eq!(
+            vec![1, 2, 3],
+            search(index.clone(), json_query("$.size", ScalarQuery::IsNull())).await
+        );
+        assert!(index
+            .search(&json_query("$.color", ScalarQuery::IsNull()))
+            .await
+            .is_err());
+        assert!(index
+            .search(&ScalarQuery::Equals(utf8("shoe")))
+            .await
+            .is_err());
+
+        // Updated with more rows and remapped
+        let batch = json_batch(&[Some(r#"{"type": "shoe"}"#)], 5..6);
+        let schema = batch.schema();
+        let data = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let updated_index_dir = tempdir().unwrap();
+        let updated_index_store = test_store(&updated_index_dir);
+        index
+            .update(
+                reader_to_stream(Box::new(data)).unwrap().0,
+                updated_index_store.as_ref(),
+            )
+            .await
+            .unwrap();
+        let updated_index = JsonIndex::load(updated_index_store).await.unwrap();
+        assert_eq!(
+            vec![0, 4, 5],
+            search(
+                updated_index.clone(),
+                json_query("$.type", ScalarQuery::Equals(utf8("shoe")))
+            )
+            .await
+        );
+
+        let mapping = nohash_hasher::IntMap::from_iter([(0, None), (5, Some(100))]);
+        let remapped_index_dir = tempdir().unwrap();
+        let remapped_index_store = test_store(&remapped_index_dir);
+        updated_index
+            .remap(&mapping, remapped_index_store.as_ref())
+            .await
+            .unwrap();
+        let remapped_index = JsonIndex::load(remapped_index_store).await.unwrap();
+        assert_eq!(
+            vec![4, 100],
+            search(
+                remapped_index,
+                json_query("$.type", ScalarQuery::Equals(utf8("shoe")))
+            )
+            .await
+        );
+    }
 }
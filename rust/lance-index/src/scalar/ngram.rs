@@ -0,0 +1,297 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{
+    any::Any,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
+
+use arrow_array::{cast::AsArray, types::UInt64Type, BinaryArray, RecordBatch, StringArray};
+use arrow_array::{Array, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::TryStreamExt;
+use lance_core::{Error, Result};
+use nohash_hasher::IntMap;
+use roaring::{RoaringBitmap, RoaringTreemap};
+use serde::Serialize;
+use snafu::{location, Location};
+
+use crate::{Index, IndexType};
+
+use super::{IndexStore, ScalarIndex, ScalarQuery};
+
+/// Name of the file of an n-gram index, which is also used to tell an n-gram index
+/// from the other scalar indices.
+pub const NGRAM_POSTINGS_NAME: &str = "ngram_postings.lance";
+
+/// Number of characters of an n-gram.
+pub const NGRAM_LENGTH: usize = 3;
+
+/// The key of the bitmap of all the indexed rows, which is not an n-gram.
+const ALL_ROWS_KEY: &str = "";
+
+/// The lower cased n-grams of `text`.
+///
+/// The characters are lower cased one by one, so that the n-grams of a substring of
+/// a text are always n-grams of the text.
+fn ngrams(text: &str) -> BTreeSet<String> {
+    let chars = text
+        .chars()
+        .flat_map(|c| c.to_lowercase())
+        .collect::<Vec<_>>();
+    chars
+        .windows(NGRAM_LENGTH)
+        .map(|window| window.iter().collect())
+        .collect()
+}
+
+/// An n-gram index keeps a bitmap of the rows whose value contains each n-gram, i.e.,
+/// each [NGRAM_LENGTH] characters, of a string column.
+///
+/// It answers [ScalarQuery::Contains] queries, e.g., from `LIKE '%lance%'` filters,
+/// with the rows that contain all the n-grams of the substrings. The n-grams are case
+/// insensitive, and a row can contain all the n-grams but not the substring, so the
+/// results are candidates, to be filtered afterwards.
+#[derive(Clone, Debug)]
+pub struct NGramIndex {
+    /// The bitmap of each n-gram, and of all the rows under [ALL_ROWS_KEY].
+    bitmaps: BTreeMap<String, RoaringTreemap>,
+}
+
+impl NGramIndex {
+    fn try_from_serialized(data: RecordBatch) -> Result<Self> {
+        let ngrams = data.column(0).as_string::<i32>();
+        let serialized = data.column(1).as_binary::<i32>();
+        let mut bitmaps = BTreeMap::new();
+        for idx in 0..data.num_rows() {
+            let bitmap = RoaringTreemap::deserialize_from(serialized.value(idx))?;
+            bitmaps.insert(ngrams.value(idx).to_string(), bitmap);
+        }
+        Ok(Self { bitmaps })
+    }
+
+    /// Add the values and row ids of `batch` to `bitmaps`. Nulls are not indexed.
+    fn add_batch(
+        bitmaps: &mut BTreeMap<String, RoaringTreemap>,
+        batch: &RecordBatch,
+    ) -> Result<()> {
+        let row_ids = batch.column(1).as_primitive::<UInt64Type>();
+        let values = batch.column(0);
+        let mut add = |values: &mut dyn Iterator<Item = Option<&str>>| {
+            for (value, row_id) in values.zip(row_ids.values().iter()) {
+                let Some(value) = value else {
+                    continue;
+                };
+                for ngram in ngrams(value) {
+                    bitmaps.entry(ngram).or_default().insert(*row_id);
+                }
+                bitmaps
+                    .entry(ALL_ROWS_KEY.to_string())
+                    .or_default()
+                    .insert(*row_id);
+            }
+        };
+        match values.data_type() {
+            DataType::Utf8 => add(&mut values.as_string::<i32>().iter()),
+            DataType::LargeUtf8 => add(&mut values.as_string::<i64>().iter()),
+            data_type => {
+                return Err(Error::Index {
+                    message: format!("N-gram index requires a string column, got {data_type}"),
+                    location: location!(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    async fn write(
+        bitmaps: &BTreeMap<String, RoaringTreemap>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("ngrams", DataType::Utf8, false),
+            Field::new("bitmaps", DataType::Binary, false),
+        ]));
+        let serialized = bitmaps
+            .values()
+            .map(|bitmap| {
+                let mut bytes = Vec::with_capacity(bitmap.serialized_size());
+                bitmap.serialize_into(&mut bytes)?;
+                Ok(bytes)
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(StringArray::from_iter_values(bitmaps.keys())),
+                Arc::new(BinaryArray::from_iter_values(serialized)),
+            ],
+        )?;
+
+        let mut writer = dest_store
+            .new_index_file(NGRAM_POSTINGS_NAME, schema)
+            .await?;
+        writer.write_record_batch(batch).await?;
+        writer.finish().await
+    }
+
+    /// The rows which contain all the n-grams of all the `substrings`.
+    fn candidates(&self, substrings: &[String]) -> RoaringTreemap {
+        let all_rows = self.bitmaps.get(ALL_ROWS_KEY).cloned().unwrap_or_default();
+        substrings
+            .iter()
+            .flat_map(|substring| ngrams(substring))
+            .collect::<BTreeSet<_>>()
+            .iter()
+            .fold(all_rows, |acc, ngram| match self.bitmaps.get(ngram) {
+                Some(bitmap) => acc & bitmap,
+                None => RoaringTreemap::new(),
+            })
+    }
+}
+
+/// Train an n-gram index from a stream of batches, whose first column is the strings
+/// and second column is the row ids, and write it to `index_store`.
+pub async fn train_ngram_index(
+    mut data: SendableRecordBatchStream,
+    index_store: &dyn IndexStore,
+) -> Result<()> {
+    let mut bitmaps = BTreeMap::new();
+    while let Some(batch) = data.try_next().await? {
+        NGramIndex::add_batch(&mut bitmaps, &batch)?;
+    }
+    NGramIndex::write(&bitmaps, index_store).await
+}
+
+#[derive(Serialize)]
+struct NGramStatistics {
+    num_ngrams: usize,
+}
+
+#[async_trait]
+impl Index for NGramIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Scalar
+    }
+
+    fn memory_size(&self) -> usize {
+        self.bitmaps
+            .iter()
+            .map(|(ngram, bitmap)| ngram.len() + bitmap.serialized_size())
+            .sum()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        serde_json::to_string(&NGramStatistics {
+            num_ngrams: self.bitmaps.len().saturating_sub(1),
+        })
+        .map_err(|err| err.into())
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        // The high 32 bits of a row id are its fragment id.
+        Ok(self
+            .bitmaps
+            .get(ALL_ROWS_KEY)
+            .map(|bitmap| bitmap.bitmaps().map(|(frag_id, _)| frag_id).collect())
+            .unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl ScalarIndex for NGramIndex {
+    async fn search(&self, query: &ScalarQuery) -> Result<UInt64Array> {
+        match query {
+            ScalarQuery::Contains(substrings) => {
+                Ok(UInt64Array::from_iter_values(self.candidates(substrings)))
+            }
+            _ => Err(Error::Index {
+                message: format!(
+                    "N-gram index only supports substring queries, got {}",
+                    query.fmt_with_col("column")
+                ),
+                location: location!(),
+            }),
+        }
+    }
+
+    async fn load(store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
+        let postings_file = store.open_index_file(NGRAM_POSTINGS_NAME).await?;
+        if postings_file.num_batches().await != 1 {
+            return Err(Error::Internal {
+                message: "n-gram index must have exactly one batch".into(),
+                location: location!(),
+            });
+        }
+        let serialized = postings_file.read_record_batch(0).await?;
+        Ok(Arc::new(Self::try_from_serialized(serialized)?))
+    }
+
+    async fn remap(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let bitmaps = self
+            .bitmaps
+            .iter()
+            .map(|(ngram, bitmap)| {
+                let remapped = bitmap
+                    .iter()
+                    .filter_map(|row_id| mapping.get(&row_id).copied().unwrap_or(Some(row_id)))
+                    .collect::<RoaringTreemap>();
+                (ngram.clone(), remapped)
+            })
+            .filter(|(_, bitmap)| !bitmap.is_empty())
+            .collect();
+        Self::write(&bitmaps, dest_store).await
+    }
+
+    async fn update(
+        &self,
+        mut new_data: SendableRecordBatchStream,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        let mut bitmaps = self.bitmaps.clone();
+        while let Some(batch) = new_data.try_next().await? {
+            Self::add_batch(&mut bitmaps, &batch)?;
+        }
+        Self::write(&bitmaps, dest_store).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ngrams() {
+        assert_eq!(
+            ngrams("LanCe"),
+            BTreeSet::from(["lan".to_string(), "anc".to_string(), "nce".to_string()])
+        );
+        assert!(ngrams("ab").is_empty());
+    }
+}
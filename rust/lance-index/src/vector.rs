@@ -15,24 +15,35 @@
 //! Vector Index
 //!
 
-use arrow_array::ArrayRef;
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::Float32Type, ArrayRef, BooleanArray};
+use arrow_select::filter::filter;
+use lance_core::{Error, Result};
 use lance_linalg::distance::MetricType;
+use snafu::{location, Location};
 
 pub mod flat;
+pub mod fusion;
 pub mod ivf;
 pub mod kmeans;
 pub mod pq;
 pub mod residual;
+pub mod sparse;
+pub mod sq;
+pub mod trace;
 pub mod transform;
 pub mod utils;
 
 // TODO: Make these crate private once the migration from lance to lance-index is done.
 pub const PQ_CODE_COLUMN: &str = "__pq_code";
+pub const SQ_CODE_COLUMN: &str = "__sq_code";
 pub const PART_ID_COLUMN: &str = "__ivf_part_id";
 pub const DIST_COL: &str = "_distance";
 
 use super::pb;
 pub use residual::RESIDUAL_COLUMN;
+use trace::SearchTrace;
 
 /// Query parameters for the vector indices
 #[derive(Debug, Clone)]
@@ -53,6 +64,39 @@ pub struct Query {
     /// TODO: should we support fraction / float number here?
     pub refine_factor: Option<u32>,
 
+    /// The size of the dynamic candidate list used by graph indices
+    /// (`ef` in HNSW, `L` in DiskANN).
+    ///
+    /// If not set, the graph index picks its own default.
+    pub ef_search: Option<usize>,
+
+    /// The number of vertices to expand concurrently in each round of a graph search.
+    ///
+    /// Only used by the on-disk graph index (DiskANN). A wider beam issues more
+    /// concurrent reads to the object store per round.
+    pub beam_width: Option<usize>,
+
+    /// If set, IVF indices pick the number of partitions to probe for each query to reach
+    /// this recall, from the statistics of the partitions gathered at build time, instead
+    /// of probing `nprobes` partitions.
+    pub target_recall: Option<f32>,
+
+    /// If set, IVF indices probe the nearest partitions of each query as long as the
+    /// number of vectors to scan is within this budget, instead of probing `nprobes`
+    /// partitions. At least one partition is always probed.
+    pub max_probe_rows: Option<usize>,
+
+    /// If set, the stages of the search record how they find the results in the trace.
+    pub trace: Option<Arc<SearchTrace>>,
+
+    /// If set, only the results with a distance `>= lower_bound` are returned.
+    pub lower_bound: Option<f32>,
+
+    /// If set, only the results with a distance `< upper_bound` are returned.
+    ///
+    /// IVF indices also skip the partitions that can not have any vector within it.
+    pub upper_bound: Option<f32>,
+
     /// Distance metric type
     pub metric_type: MetricType,
 
@@ -60,6 +104,36 @@ pub struct Query {
     pub use_index: bool,
 }
 
+impl Query {
+    /// Whether the query limits the range of the distances.
+    pub fn has_distance_range(&self) -> bool {
+        self.lower_bound.is_some() || self.upper_bound.is_some()
+    }
+
+    /// Whether `distance` is within `[lower_bound, upper_bound)`.
+    pub fn in_range(&self, distance: f32) -> bool {
+        self.lower_bound.map_or(true, |lb| distance >= lb)
+            && self.upper_bound.map_or(true, |ub| distance < ub)
+    }
+
+    /// Keep the `distances` and their `row_ids` that are within the distance range.
+    pub fn filter_distance_range(
+        &self,
+        distances: ArrayRef,
+        row_ids: ArrayRef,
+    ) -> Result<(ArrayRef, ArrayRef)> {
+        if !self.has_distance_range() {
+            return Ok((distances, row_ids));
+        }
+        let mask = distances
+            .as_primitive::<Float32Type>()
+            .iter()
+            .map(|d| Some(d.is_some_and(|d| self.in_range(d))))
+            .collect::<BooleanArray>();
+        Ok((filter(&distances, &mask)?, filter(&row_ids, &mask)?))
+    }
+}
+
 impl From<pb::VectorMetricType> for MetricType {
     fn from(proto: pb::VectorMetricType) -> Self {
         match proto {
@@ -70,12 +144,21 @@ impl From<pb::VectorMetricType> for MetricType {
     }
 }
 
-impl From<MetricType> for pb::VectorMetricType {
-    fn from(mt: MetricType) -> Self {
+impl TryFrom<MetricType> for pb::VectorMetricType {
+    type Error = Error;
+
+    fn try_from(mt: MetricType) -> Result<Self> {
         match mt {
-            MetricType::L2 => Self::L2,
-            MetricType::Cosine => Self::Cosine,
-            MetricType::Dot => Self::Dot,
+            MetricType::L2 => Ok(Self::L2),
+            MetricType::Cosine => Ok(Self::Cosine),
+            MetricType::Dot => Ok(Self::Dot),
+            MetricType::Custom(name) => Err(Error::Index {
+                message: format!(
+                    "Custom distance {} can not be persisted in an index",
+                    name.as_str()
+                ),
+                location: location!(),
+            }),
         }
     }
 }
@@ -18,14 +18,15 @@
 use std::sync::Arc;
 
 use arrow_array::{
-    cast::AsArray, make_array, Array, ArrayRef, FixedSizeListArray, RecordBatch, StructArray,
+    cast::AsArray, make_array, types::Float32Type, Array, ArrayRef, BooleanArray,
+    FixedSizeListArray, Float32Array, ListArray, RecordBatch, StructArray,
 };
 use arrow_ord::sort::sort_to_indices;
 use arrow_schema::{DataType, Field as ArrowField, SchemaRef, SortOptions};
-use arrow_select::{concat::concat, take::take};
+use arrow_select::{concat::concat, nullif::nullif, take::take};
 use futures::{
     future,
-    stream::{repeat_with, StreamExt, TryStreamExt},
+    stream::{self, repeat_with, StreamExt, TryStreamExt},
 };
 use lance_arrow::*;
 use lance_core::{io::RecordBatchStream, Error, Result, ROW_ID};
@@ -33,40 +34,120 @@ use lance_linalg::distance::DistanceType;
 use snafu::{location, Location};
 use tracing::instrument;
 
-use super::{Query, DIST_COL};
+use super::{
+    sparse::{is_sparse_vector_type, sparse_dot_distance},
+    Query, DIST_COL,
+};
+
+/// Max number of rows whose distances are computed in one task.
+const CHUNK_SIZE: usize = 8192;
 
 fn distance_field() -> ArrowField {
     ArrowField::new(DIST_COL, DataType::Float32, true)
 }
 
+/// The `k` rows with the smallest distances among the batches pushed so far.
+///
+/// The pushed batches are merged whenever they hold `2 * k` rows, so the memory
+/// footprint does not grow with the number of rows searched.
+struct TopK {
+    k: usize,
+    batches: Vec<RecordBatch>,
+    num_rows: usize,
+}
+
+impl TopK {
+    fn new(k: usize) -> Self {
+        Self {
+            k,
+            batches: vec![],
+            num_rows: 0,
+        }
+    }
+
+    fn push(&mut self, batch: RecordBatch) -> Result<()> {
+        self.num_rows += batch.num_rows();
+        self.batches.push(batch);
+        if self.batches.len() > 1 && self.num_rows >= 2 * self.k {
+            self.merge()?;
+        }
+        Ok(())
+    }
+
+    fn merge(&mut self) -> Result<()> {
+        let batch = concat_batches(&self.batches[0].schema(), &self.batches)?;
+        let distances = batch.column_by_name(DIST_COL).unwrap();
+        let indices = sort_to_indices(distances, None, Some(self.k))?;
+
+        let struct_arr = StructArray::from(batch);
+        let selected: RecordBatch = take(&struct_arr, &indices, None)?.as_struct().into();
+        self.num_rows = selected.num_rows();
+        self.batches = vec![selected];
+        Ok(())
+    }
+
+    /// The top `k` rows, or `None` if no batch was pushed.
+    fn finish(mut self) -> Result<Option<RecordBatch>> {
+        if self.batches.is_empty() {
+            return Ok(None);
+        }
+        self.merge()?;
+        Ok(self.batches.pop())
+    }
+}
+
+/// Split `batch` into chunks of at most `chunk_size` rows.
+fn split_batch(batch: RecordBatch, chunk_size: usize) -> Vec<RecordBatch> {
+    if batch.num_rows() <= chunk_size {
+        return vec![batch];
+    }
+    (0..batch.num_rows())
+        .step_by(chunk_size)
+        .map(|offset| batch.slice(offset, std::cmp::min(chunk_size, batch.num_rows() - offset)))
+        .collect()
+}
+
+/// Exhaustive search of the `query.k` rows closest to the query.
+///
+/// The distances are computed in parallel chunks of the input batches, and the top
+/// `k` rows are kept as the chunks complete, so that only a bounded number of rows
+/// is held in memory.
 #[instrument(level = "debug", skip_all)]
 pub async fn flat_search(
     stream: impl RecordBatchStream + 'static,
     query: &Query,
 ) -> Result<RecordBatch> {
     let input_schema = stream.schema();
-    let batches = stream
+    let top_k = stream
         .try_filter(|batch| future::ready(batch.num_rows() > 0))
+        .map_ok(|batch| {
+            stream::iter(
+                split_batch(batch, CHUNK_SIZE)
+                    .into_iter()
+                    .map(Ok::<_, Error>),
+            )
+        })
+        .try_flatten()
         .zip(repeat_with(|| query.metric_type))
         .map(|(batch, mt)| async move { flat_search_batch(query, mt, batch?).await })
-        .buffer_unordered(16)
-        .try_collect::<Vec<_>>()
+        .buffer_unordered(num_cpus::get())
+        .try_fold(TopK::new(query.k), |mut top_k, batch| async move {
+            top_k.push(batch)?;
+            Ok(top_k)
+        })
         .await?;
 
-    if batches.is_empty() {
-        let schema_with_distance = input_schema.try_with_column(distance_field())?;
-        return Ok(RecordBatch::new_empty(schema_with_distance.into()));
+    match top_k.finish()? {
+        Some(batch) => Ok(batch),
+        // The candidates of a refine or a merge already have the distance column.
+        None if input_schema.column_with_name(DIST_COL).is_some() => {
+            Ok(RecordBatch::new_empty(input_schema))
+        }
+        None => {
+            let schema_with_distance = input_schema.try_with_column(distance_field())?;
+            Ok(RecordBatch::new_empty(schema_with_distance.into()))
+        }
     }
-
-    // TODO: waiting to do this until the end adds quite a bit of latency. We should
-    // do this in a streaming fashion. See also: https://github.com/lancedb/lance/issues/1324
-    let batch = concat_batches(&batches[0].schema(), &batches)?;
-    let distances = batch.column_by_name(DIST_COL).unwrap();
-    let indices = sort_to_indices(distances, None, Some(query.k))?;
-
-    let struct_arr = StructArray::from(batch);
-    let selected_arr = take(&struct_arr, &indices, None)?;
-    Ok(selected_arr.as_struct().into())
 }
 
 #[instrument(level = "debug", skip(query, batch))]
@@ -90,7 +171,6 @@ async fn flat_search_batch(
 
     // A selection vector may have been applied to _rowid column, so we need to
     // push that onto vectors if possible.
-    let vectors = as_fixed_size_list_array(vectors.as_ref()).clone();
     let validity_buffer = if let Some(rowids) = batch.column_by_name(ROW_ID) {
         rowids.nulls().map(|nulls| nulls.buffer().clone())
     } else {
@@ -98,15 +178,40 @@ async fn flat_search_batch(
     };
 
     let vectors = vectors
-        .into_data()
+        .to_data()
         .into_builder()
         .null_bit_buffer(validity_buffer)
         .build()
         .map(make_array)?;
-    let vectors = as_fixed_size_list_array(vectors.as_ref()).clone();
 
+    let query = query.clone();
     tokio::task::spawn_blocking(move || {
-        let distances = mt.arrow_batch_func()(key.as_ref(), &vectors)? as ArrayRef;
+        let mut distances = if is_sparse_vector_type(vectors.data_type()) {
+            // Sparse vectors are always ranked by dot product.
+            sparse_dot_distance(key.as_ref(), vectors.as_ref())?
+        } else if let Some(multivectors) = vectors.as_list_opt::<i32>() {
+            let queries = key.as_fixed_size_list_opt().ok_or_else(|| Error::Schema {
+                message: format!(
+                    "column {} is a multivector column, the query must be a list of vectors, got {}",
+                    query.column,
+                    key.data_type()
+                ),
+                location: location!(),
+            })?;
+            multivector_distance(queries, multivectors, mt)?
+        } else {
+            mt.arrow_batch_func()(key.as_ref(), as_fixed_size_list_array(vectors.as_ref()))?
+                as ArrayRef
+        };
+        if query.has_distance_range() {
+            // The distances out of the range are set to nulls, so they are not selected.
+            let out_of_range = distances
+                .as_primitive::<Float32Type>()
+                .iter()
+                .map(|d| Some(d.is_some_and(|d| !query.in_range(d))))
+                .collect::<BooleanArray>();
+            distances = nullif(&distances, &out_of_range)?;
+        }
 
         // We don't want any nulls in result, so limit to k or the number of valid values.
         let k = std::cmp::min(k, distances.len() - distances.null_count());
@@ -126,6 +231,47 @@ async fn flat_search_batch(
     .unwrap()
 }
 
+/// Compute the distances from the query vectors to the multivector of each row,
+/// i.e., the late interaction of ColBERT.
+///
+/// Each query vector is matched to its closest vector in the row, and the distances
+/// are summed over the query vectors. With [`DistanceType::Dot`], this is the
+/// negative MaxSim score, so the rows are ranked in the order of MaxSim.
+///
+/// Null rows and rows without any vector have null distances.
+pub fn multivector_distance(
+    queries: &FixedSizeListArray,
+    vectors: &ListArray,
+    distance_type: DistanceType,
+) -> Result<ArrayRef> {
+    let distance_func = distance_type.arrow_batch_func();
+    let distances = (0..vectors.len())
+        .map(|i| {
+            if vectors.is_null(i) {
+                return Ok(None);
+            }
+            let row = vectors.value(i);
+            let row = row.as_fixed_size_list_opt().ok_or_else(|| Error::Schema {
+                message: format!(
+                    "multivector column must be a list of vectors, got {}",
+                    vectors.data_type()
+                ),
+                location: location!(),
+            })?;
+            if row.is_empty() {
+                return Ok(None);
+            }
+            let mut sum = 0.0;
+            for query in (0..queries.len()).map(|j| queries.value(j)) {
+                let dists = distance_func(query.as_ref(), row)?;
+                sum += dists.values().iter().copied().fold(f32::INFINITY, f32::min);
+            }
+            Ok(Some(sum))
+        })
+        .collect::<Result<Float32Array>>()?;
+    Ok(Arc::new(distances))
+}
+
 fn concat_batches<'a>(
     schema: &SchemaRef,
     input_batches: impl IntoIterator<Item = &'a RecordBatch>,
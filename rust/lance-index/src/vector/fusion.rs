@@ -0,0 +1,234 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fusion of the rankings of a hybrid search.
+//!
+//! A hybrid search retrieves candidates with a dense vector search and with a sparse
+//! vector search, i.e., over SPLADE or BM25-weighted embeddings, and fuses both
+//! rankings into one. The fused results are scored, the higher the better.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::Float32Type, Float32Array, RecordBatch, UInt64Array};
+use arrow_schema::{DataType, Field, Schema};
+use lance_core::{Error, Result, ROW_ID, ROW_ID_FIELD};
+use snafu::{location, Location};
+
+use super::DIST_COL;
+
+/// Column of the fused scores of a hybrid search.
+pub const SCORE_COL: &str = "_score";
+
+/// How to fuse the rankings of a hybrid search.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FusionMethod {
+    /// Reciprocal rank fusion: a result scores `1 / (k + rank)` in each ranking it
+    /// appears in, with ranks starting from 1.
+    Rrf { k: f32 },
+    /// Weighted sum of the relevance in each ranking, where the distances of each
+    /// ranking are min-max normalized to a relevance in `[0, 1]`.
+    ///
+    /// The dense vector search is weighted by `vector_weight`, and the sparse vector
+    /// search by `1 - vector_weight`.
+    WeightedSum { vector_weight: f32 },
+}
+
+impl Default for FusionMethod {
+    fn default() -> Self {
+        Self::Rrf { k: 60.0 }
+    }
+}
+
+impl FusionMethod {
+    fn validate(&self) -> Result<()> {
+        let valid = match self {
+            Self::Rrf { k } => *k >= 0.0,
+            Self::WeightedSum { vector_weight } => (0.0..=1.0).contains(vector_weight),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::Index {
+                message: format!("Invalid fusion method: {:?}", self),
+                location: location!(),
+            })
+        }
+    }
+}
+
+/// The schema of the fused results: the score and the row id.
+pub fn fusion_schema() -> Schema {
+    Schema::new(vec![
+        Field::new(SCORE_COL, DataType::Float32, false),
+        ROW_ID_FIELD.clone(),
+    ])
+}
+
+/// The row ids of `batch`, ordered by their distance, the closest first.
+fn ranking(batch: &RecordBatch) -> Result<Vec<(u64, f32)>> {
+    let (Some(row_ids), Some(distances)) =
+        (batch.column_by_name(ROW_ID), batch.column_by_name(DIST_COL))
+    else {
+        return Err(Error::Index {
+            message: format!(
+                "Fusion input must have the {} and {} columns, got {:?}",
+                ROW_ID,
+                DIST_COL,
+                batch.schema()
+            ),
+            location: location!(),
+        });
+    };
+    let row_ids = row_ids
+        .as_any()
+        .downcast_ref::<UInt64Array>()
+        .ok_or_else(|| Error::Index {
+            message: format!("{} column must be UInt64", ROW_ID),
+            location: location!(),
+        })?;
+    let distances = distances
+        .as_primitive_opt::<Float32Type>()
+        .ok_or_else(|| Error::Index {
+            message: format!("{} column must be Float32", DIST_COL),
+            location: location!(),
+        })?;
+    let mut ranking = row_ids
+        .iter()
+        .zip(distances.iter())
+        .filter_map(|(row_id, dist)| Some((row_id?, dist?)))
+        .collect::<Vec<_>>();
+    ranking.sort_by(|a, b| a.1.total_cmp(&b.1));
+    Ok(ranking)
+}
+
+/// Fuse the results of the dense vector search `vector` and of the sparse vector
+/// search `sparse`, both with the row id and the distance columns, into the `k`
+/// best scored results, as batch of [fusion_schema].
+pub fn fuse(
+    vector: &RecordBatch,
+    sparse: &RecordBatch,
+    method: FusionMethod,
+    k: usize,
+) -> Result<RecordBatch> {
+    method.validate()?;
+
+    let mut scores = HashMap::<u64, f32>::new();
+    let rankings = [ranking(vector)?, ranking(sparse)?];
+    for (i, ranking) in rankings.iter().enumerate() {
+        match method {
+            FusionMethod::Rrf { k } => {
+                for (rank, (row_id, _)) in ranking.iter().enumerate() {
+                    *scores.entry(*row_id).or_default() += 1.0 / (k + rank as f32 + 1.0);
+                }
+            }
+            FusionMethod::WeightedSum { vector_weight } => {
+                let weight = if i == 0 {
+                    vector_weight
+                } else {
+                    1.0 - vector_weight
+                };
+                let (Some((_, min)), Some((_, max))) = (ranking.first(), ranking.last()) else {
+                    continue;
+                };
+                for (row_id, dist) in ranking {
+                    let relevance = if max > min {
+                        (max - dist) / (max - min)
+                    } else {
+                        1.0
+                    };
+                    *scores.entry(*row_id).or_default() += weight * relevance;
+                }
+            }
+        }
+    }
+
+    let mut scores = scores.into_iter().collect::<Vec<_>>();
+    // Break the ties by row id, so that the results are deterministic.
+    scores.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    scores.truncate(k);
+
+    Ok(RecordBatch::try_new(
+        Arc::new(fusion_schema()),
+        vec![
+            Arc::new(Float32Array::from_iter_values(scores.iter().map(|s| s.1))),
+            Arc::new(UInt64Array::from_iter_values(scores.iter().map(|s| s.0))),
+        ],
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn results(row_ids: Vec<u64>, distances: Vec<f32>) -> RecordBatch {
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new(DIST_COL, DataType::Float32, true),
+                ROW_ID_FIELD.clone(),
+            ])),
+            vec![
+                Arc::new(Float32Array::from(distances)),
+                Arc::new(UInt64Array::from(row_ids)),
+            ],
+        )
+        .unwrap()
+    }
+
+    fn row_ids(batch: &RecordBatch) -> Vec<u64> {
+        batch[ROW_ID]
+            .as_primitive::<arrow_array::types::UInt64Type>()
+            .values()
+            .to_vec()
+    }
+
+    #[test]
+    fn test_fuse() {
+        // The inputs are not sorted by distance.
+        let vector = results(vec![3, 1, 2], vec![0.3, 0.1, 0.2]);
+        let sparse = results(vec![4, 2, 3], vec![-5.0, -10.0, -1.0]);
+
+        // 2 is ranked 2nd and 1st, 1 is ranked 1st only.
+        let fused = fuse(&vector, &sparse, FusionMethod::default(), 3).unwrap();
+        assert_eq!(row_ids(&fused), vec![2, 3, 1]);
+        let scores = fused[SCORE_COL].as_primitive::<Float32Type>();
+        assert_eq!(scores.value(0), 1.0 / 62.0 + 1.0 / 61.0);
+
+        let fused = fuse(
+            &vector,
+            &sparse,
+            FusionMethod::WeightedSum { vector_weight: 1.0 },
+            2,
+        )
+        .unwrap();
+        assert_eq!(row_ids(&fused), vec![1, 2]);
+
+        let fused = fuse(
+            &vector,
+            &sparse,
+            FusionMethod::WeightedSum { vector_weight: 0.0 },
+            2,
+        )
+        .unwrap();
+        assert_eq!(row_ids(&fused), vec![2, 4]);
+
+        assert!(fuse(
+            &vector,
+            &sparse,
+            FusionMethod::WeightedSum { vector_weight: 2.0 },
+            2,
+        )
+        .is_err());
+    }
+}
@@ -31,7 +31,8 @@ use lance_arrow::*;
 use lance_core::{Error, Result};
 use lance_linalg::{
     distance::{
-        cosine_distance_batch, dot_distance_batch, l2_distance_batch, Cosine, Dot, MetricType, L2,
+        cosine_distance_batch, custom::custom_distance_batch, dot_distance_batch,
+        l2_distance_batch, Cosine, Dot, MetricType, L2,
     },
     MatrixView,
 };
@@ -114,6 +115,7 @@ fn new_ivf_with_pq_impl<T: ArrowFloatType + Dot + Cosine + L2 + 'static>(
     metric_type: MetricType,
     vector_column: &str,
     pq: Arc<dyn ProductQuantizer>,
+    use_residual: bool,
     range: Option<Range<u32>>,
 ) -> Arc<dyn Ivf> {
     let mat = MatrixView::<T>::new(Arc::new(centroids.clone()), dimension);
@@ -122,6 +124,7 @@ fn new_ivf_with_pq_impl<T: ArrowFloatType + Dot + Cosine + L2 + 'static>(
         metric_type,
         vector_column,
         pq,
+        use_residual,
         range,
     ))
 }
@@ -132,6 +135,7 @@ pub fn new_ivf_with_pq(
     metric_type: MetricType,
     vector_column: &str,
     pq: Arc<dyn ProductQuantizer>,
+    use_residual: bool,
     range: Option<Range<u32>>,
 ) -> Result<Arc<dyn Ivf>> {
     match centroids.data_type() {
@@ -141,6 +145,7 @@ pub fn new_ivf_with_pq(
             metric_type,
             vector_column,
             pq,
+            use_residual,
             range,
         )),
         DataType::Float32 => Ok(new_ivf_with_pq_impl::<Float32Type>(
@@ -149,6 +154,7 @@ pub fn new_ivf_with_pq(
             metric_type,
             vector_column,
             pq,
+            use_residual,
             range,
         )),
         DataType::Float64 => Ok(new_ivf_with_pq_impl::<Float64Type>(
@@ -157,6 +163,7 @@ pub fn new_ivf_with_pq(
             metric_type,
             vector_column,
             pq,
+            use_residual,
             range,
         )),
         _ => Err(Error::Index {
@@ -256,14 +263,13 @@ impl<T: ArrowFloatType + Dot + L2 + Cosine + 'static> IvfImpl<T> {
         metric_type: MetricType,
         vector_column: &str,
         pq: Arc<dyn ProductQuantizer>,
+        use_residual: bool,
         range: Option<Range<u32>>,
     ) -> Self {
-        Self {
-            centroids: centroids.clone(),
-            metric_type,
-            transforms: vec![
+        let transforms: Vec<Arc<dyn Transformer>> = if use_residual {
+            vec![
                 Arc::new(ResidualTransform::new(
-                    centroids,
+                    centroids.clone(),
                     PART_ID_COLUMN,
                     vector_column,
                 )),
@@ -272,7 +278,18 @@ impl<T: ArrowFloatType + Dot + L2 + Cosine + 'static> IvfImpl<T> {
                     RESIDUAL_COLUMN,
                     PQ_CODE_COLUMN,
                 )),
-            ],
+            ]
+        } else {
+            vec![Arc::new(PQTransformer::new(
+                pq.clone(),
+                vector_column,
+                PQ_CODE_COLUMN,
+            ))]
+        };
+        Self {
+            centroids,
+            metric_type,
+            transforms,
             partition_range: range,
         }
     }
@@ -427,6 +444,9 @@ impl<T: ArrowFloatType + Dot + L2 + Cosine + 'static> Ivf for IvfImpl<T> {
             lance_linalg::distance::DistanceType::Dot => {
                 dot_distance_batch(query.as_slice(), centroids, dim)
             }
+            lance_linalg::distance::DistanceType::Custom(name) => {
+                Box::new(custom_distance_batch(name, query.as_slice(), centroids, dim).into_iter())
+            }
         });
 
         let top_k_partitions = sort_to_indices(&distances, None, Some(nprobes))?;
@@ -20,6 +20,9 @@ use arrow_array::{Array, FixedSizeListArray};
 use snafu::{location, Location};
 
 use lance_core::error::{Error, Result};
+use lance_linalg::kmeans::KMeanInit;
+
+use crate::progress::{IndexBuildProgress, NoopIndexBuildProgress};
 
 /// Parameters to build IVF partitions
 #[derive(Debug, Clone)]
@@ -31,10 +34,59 @@ pub struct IvfBuildParams {
     /// Max number of iterations to train kmeans.
     pub max_iters: usize,
 
-    /// Use provided IVF centroids.
+    /// Use provided IVF centroids, i.e., trained externally, instead of training kmeans.
     pub centroids: Option<Arc<FixedSizeListArray>>,
 
+    /// How to initialize the kmeans centroids. Not used if `centroids` is provided.
+    pub init: KMeanInit,
+
+    /// Number of vectors sampled per centroid to train kmeans, see Faiss.
     pub sample_rate: usize,
+
+    /// Max number of vectors sampled to train kmeans, regardless of `sample_rate`,
+    /// so that training on a huge dataset does not read too many vectors.
+    /// If None, `sample_rate` vectors are sampled per centroid.
+    pub max_training_rows: Option<usize>,
+
+    /// Seed of the random generator to sample the training data and train kmeans.
+    /// If None, the index is built with a random seed.
+    pub seed: Option<u64>,
+
+    /// Train kmeans with mini-batches of this many vectors streamed from the dataset,
+    /// instead of loading the training sample in memory.
+    ///
+    /// Each of the `max_iters` iterations is a pass over the whole dataset.
+    pub mini_batch_size: Option<usize>,
+
+    /// After training, split the partitions which are larger than this many times
+    /// the average partition size, and drop the smallest ones to keep `num_partitions`.
+    ///
+    /// It is the same measure as `partition_skew` in the index statistics, and must be
+    /// at least `1.0`. If None, the partitions are kept as trained.
+    pub max_partition_skew: Option<f64>,
+
+    /// Quantize the residual of each vector to the centroid of its partition, i.e.,
+    /// `vector - centroid`, instead of the vector itself.
+    ///
+    /// Residuals are much smaller than the vectors of clustered data, so they are
+    /// quantized with a lower error. Only used by IVF_PQ and IVF_SQ. Defaults to true.
+    pub use_residual: bool,
+
+    /// Reports the progress of shuffling and writing the partitions.
+    pub progress: Arc<dyn IndexBuildProgress>,
+
+    // ---- resource limits, so an index can be built next to a serving workload
+    /// Max number of threads to train kmeans and to assign the vectors to partitions.
+    /// If None, the number of CPUs is used.
+    pub num_threads: Option<usize>,
+
+    /// Max bytes of the shuffled vectors buffered in memory before they are spilled
+    /// to disk. If None, up to 512 MiB are buffered.
+    pub shuffle_memory_budget: Option<usize>,
+
+    /// Max number of partitions written concurrently by the shuffle.
+    /// If None, the number of CPUs is used.
+    pub num_parallel_partitions: Option<usize>,
 }
 
 impl Default for IvfBuildParams {
@@ -43,7 +95,17 @@ impl Default for IvfBuildParams {
             num_partitions: 32,
             max_iters: 50,
             centroids: None,
+            init: KMeanInit::Random,
             sample_rate: 256, // See faiss
+            max_training_rows: None,
+            seed: None,
+            mini_batch_size: None,
+            max_partition_skew: None,
+            use_residual: true,
+            progress: Arc::new(NoopIndexBuildProgress::new()),
+            num_threads: None,
+            shuffle_memory_budget: None,
+            num_parallel_partitions: None,
         }
     }
 }
@@ -57,6 +119,14 @@ impl IvfBuildParams {
         }
     }
 
+    /// The number of vectors sampled to train `num_centroids` centroids, i.e.,
+    /// `sample_rate` vectors per centroid, up to `max_training_rows`.
+    pub fn sample_size(&self, num_centroids: usize) -> usize {
+        let sample_size = num_centroids * self.sample_rate;
+        self.max_training_rows
+            .map_or(sample_size, |max_rows| std::cmp::min(sample_size, max_rows))
+    }
+
     /// Create a new instance of [`IvfBuildParams`] with centroids.
     pub fn try_with_centroids(
         num_partitions: usize,
@@ -22,10 +22,13 @@ use std::sync::Arc;
 use lance_core::{Error, Result};
 use lance_linalg::{
     distance::{Cosine, Dot, MetricType, L2},
-    kmeans::{KMeans, KMeansParams},
+    kmeans::{KMeanInit, KMeans, KMeansParams},
 };
 
 /// Train KMeans model and returns the centroids of each cluster.
+///
+/// The memberships are computed with at most `num_threads` threads, or with the
+/// number of CPUs if `None`.
 #[allow(clippy::too_many_arguments)]
 pub async fn train_kmeans<T: ArrowFloatType + Dot + L2 + Cosine>(
     array: &T::ArrayType,
@@ -37,6 +40,8 @@ pub async fn train_kmeans<T: ArrowFloatType + Dot + L2 + Cosine>(
     mut rng: impl Rng,
     metric_type: MetricType,
     sample_rate: usize,
+    init: KMeanInit,
+    num_threads: Option<usize>,
 ) -> Result<T::ArrayType> {
     let num_rows = array.len() / dimension;
     if num_rows < k {
@@ -71,6 +76,9 @@ pub async fn train_kmeans<T: ArrowFloatType + Dot + L2 + Cosine>(
         metric_type,
         centroids,
         redos,
+        init,
+        seed: Some(rng.gen()),
+        num_threads,
         ..Default::default()
     };
     let data = FixedSizeListArray::try_new_from_values(data, dimension as i32)?;
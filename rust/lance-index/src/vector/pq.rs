@@ -25,7 +25,8 @@ use lance_arrow::floats::FloatArray;
 use lance_arrow::*;
 use lance_core::{Error, Result};
 use lance_linalg::distance::{
-    cosine_distance_batch, dot_distance_batch, l2_distance_batch, norm_l2, Cosine, Dot, L2,
+    cosine_distance_batch, custom::custom_distance_batch, dot_distance_batch, l2_distance_batch,
+    norm_l2, Cosine, Dot, L2,
 };
 use lance_linalg::kernels::{argmin, argmin_value_float};
 use lance_linalg::{distance::MetricType, MatrixView};
@@ -37,7 +38,7 @@ pub(crate) mod utils;
 pub use self::utils::num_centroids;
 use super::pb;
 pub use builder::PQBuildParams;
-use lance_linalg::simd::{f32::f32x8, SIMD};
+use lance_linalg::simd::{avx512, f32::f32x8, SIMD};
 
 /// Product Quantization
 
@@ -162,8 +163,7 @@ impl<T: ArrowFloatType + Cosine + Dot + L2> ProductQuantizerImpl<T> {
     /// Reconstruct a vector from its PQ code.
     ///
     /// It only supports U8 PQ code for now.
-    #[allow(dead_code)]
-    pub(crate) fn reconstruct(&self, code: &[u8]) -> Arc<T::ArrayType> {
+    pub fn reconstruct(&self, code: &[u8]) -> Arc<T::ArrayType> {
         assert_eq!(code.len(), self.num_sub_vectors);
         let mut builder = Vec::with_capacity(self.dimension);
         let sub_vector_dim = self.dimension / self.num_sub_vectors;
@@ -183,12 +183,7 @@ impl<T: ArrowFloatType + Cosine + Dot + L2> ProductQuantizerImpl<T> {
     /// from the PQ code to the actual vector.
     ///
     /// This method is just for debugging purpose.
-    #[allow(dead_code)]
-    pub(crate) async fn distortion(
-        &self,
-        data: &MatrixView<T>,
-        metric_type: MetricType,
-    ) -> Result<f64> {
+    pub async fn distortion(&self, data: &MatrixView<T>, metric_type: MetricType) -> Result<f64> {
         let sub_vector_width = self.dimension / self.num_sub_vectors;
         let total_distortion = data
             .iter()
@@ -208,6 +203,10 @@ impl<T: ArrowFloatType + Cosine + Dot + L2> ProductQuantizerImpl<T> {
                             lance_linalg::distance::DistanceType::Dot => {
                                 dot_distance_batch(sub_vec, centroids, sub_vector_width)
                             }
+                            lance_linalg::distance::DistanceType::Custom(name) => Box::new(
+                                custom_distance_batch(name, sub_vec, centroids, sub_vector_width)
+                                    .into_iter(),
+                            ),
                         };
                         argmin_value_float(distances).1
                     })
@@ -241,63 +240,12 @@ impl<T: ArrowFloatType + Cosine + Dot + L2> ProductQuantizerImpl<T> {
                 distance_table.extend(distances);
             });
 
-        #[cfg(target_feature = "avx512f")]
-        {
-            if self.num_sub_vectors % 16 == 0 {
-                use std::arch::x86_64::*;
-                return Ok(Arc::new(Float32Array::from_iter_values(
-                    code.values()
-                        .chunks_exact(self.num_sub_vectors)
-                        .map(|c| unsafe {
-                            let mut s = _mm512_setzero_ps();
-                            c.chunks_exact(16)
-                                .enumerate()
-                                .for_each(|(idx, lane_chunk)| {
-                                    let mut offsets: [i32; 16] = [0; 16];
-                                    lane_chunk.iter().enumerate().for_each(|(j, &code)| {
-                                        offsets[j] = ((idx * 8 + j) * 256 + code as usize) as i32
-                                    });
-                                    let simd_offsets = _mm512_loadu_epi32(offsets.as_ptr());
-                                    let v = _mm512_i32gather_ps(
-                                        simd_offsets,
-                                        distance_table.as_ptr() as *const u8,
-                                        4,
-                                    );
-                                    s = _mm512_add_ps(s, v);
-                                });
-                            _mm512_reduce_add_ps(s)
-                        }),
-                )));
-            }
-        }
-
-        if cfg!(target_feature = "avx2") && self.num_sub_vectors % 8 == 0 {
-            Ok(Arc::new(Float32Array::from_iter_values(
-                code.values().chunks_exact(self.num_sub_vectors).map(|c| {
-                    let mut s = f32x8::zeros();
-                    c.chunks_exact(8).enumerate().for_each(|(idx, lane_chunk)| {
-                        let mut offsets: [i32; 8] = [0; 8];
-                        lane_chunk.iter().enumerate().for_each(|(j, &code)| {
-                            offsets[j] = ((idx * 8 + j) * 256 + code as usize) as i32
-                        });
-                        let v = f32x8::gather(&distance_table, &offsets);
-                        s += v;
-                    });
-                    s.reduce_sum()
-                }),
-            )))
-        } else {
-            Ok(Arc::new(Float32Array::from_iter_values(
-                code.values().chunks_exact(self.num_sub_vectors).map(|c| {
-                    c.iter()
-                        .enumerate()
-                        .map(|(sub_vec_idx, centroid)| {
-                            distance_table[sub_vec_idx * 256 + *centroid as usize]
-                        })
-                        .sum()
-                }),
-            )))
-        }
+        Ok(Arc::new(Float32Array::from(sum_distance_table(
+            &distance_table,
+            num_centroids(self.num_bits),
+            code.values(),
+            self.num_sub_vectors,
+        ))))
     }
 
     /// Pre-compute dot product to each sub-centroids.
@@ -329,16 +277,12 @@ impl<T: ArrowFloatType + Cosine + Dot + L2> ProductQuantizerImpl<T> {
             });
 
         // Compute distance from the pre-compute table.
-        Ok(Arc::new(Float32Array::from_iter_values(
-            code.values().chunks_exact(self.num_sub_vectors).map(|c| {
-                c.iter()
-                    .enumerate()
-                    .map(|(sub_vec_idx, centroid)| {
-                        distance_table[sub_vec_idx * 256 + *centroid as usize]
-                    })
-                    .sum::<f32>()
-            }),
-        )))
+        Ok(Arc::new(Float32Array::from(sum_distance_table(
+            &distance_table,
+            num_centroids(self.num_bits),
+            code.values(),
+            self.num_sub_vectors,
+        ))))
     }
 
     /// Pre-compute cosine distance to each sub-centroids.
@@ -384,28 +328,68 @@ impl<T: ArrowFloatType + Cosine + Dot + L2> ProductQuantizerImpl<T> {
                 );
             });
 
-        // Compute distance from the pre-compute table.
+        // Compute distance from the pre-compute tables.
+        let xy = sum_distance_table(
+            &xy_table,
+            num_centroids,
+            code.values(),
+            self.num_sub_vectors,
+        );
+        let y2 = sum_distance_table(
+            &y2_table,
+            num_centroids,
+            code.values(),
+            self.num_sub_vectors,
+        );
         Ok(Arc::new(Float32Array::from_iter_values(
-            code.values().chunks_exact(self.num_sub_vectors).map(|c| {
-                let xy = c
-                    .iter()
-                    .enumerate()
-                    .map(|(sub_vec_idx, centroid)| {
-                        let idx = sub_vec_idx * num_centroids + *centroid as usize;
-                        xy_table[idx]
-                    })
-                    .sum::<f32>();
-                let y2 = c
-                    .iter()
+            xy.into_iter()
+                .zip(y2)
+                .map(|(xy, y2)| 1.0 - xy / (x_norm * y2.sqrt())),
+        )))
+    }
+}
+
+/// Sum the distance table entries selected by each PQ code.
+///
+/// `table` is `[num_sub_vectors * num_centroids]`, and `codes` holds
+/// `num_sub_vectors` centroid ids per vector.
+fn sum_distance_table(
+    table: &[f32],
+    num_centroids: usize,
+    codes: &[u8],
+    num_sub_vectors: usize,
+) -> Vec<f32> {
+    if let Some(sums) = avx512::table_lookup_sum(table, num_centroids, codes, num_sub_vectors) {
+        return sums;
+    }
+
+    if cfg!(target_feature = "avx2") && num_sub_vectors % 8 == 0 {
+        codes
+            .chunks_exact(num_sub_vectors)
+            .map(|c| {
+                let mut s = f32x8::zeros();
+                c.chunks_exact(8).enumerate().for_each(|(idx, lane_chunk)| {
+                    let mut offsets: [i32; 8] = [0; 8];
+                    lane_chunk.iter().enumerate().for_each(|(j, &code)| {
+                        offsets[j] = ((idx * 8 + j) * num_centroids + code as usize) as i32
+                    });
+                    s += f32x8::gather(table, &offsets);
+                });
+                s.reduce_sum()
+            })
+            .collect()
+    } else {
+        codes
+            .chunks_exact(num_sub_vectors)
+            .map(|c| {
+                c.iter()
                     .enumerate()
-                    .map(|(sub_vec_idx, centroid)| {
-                        let idx = sub_vec_idx * num_centroids + *centroid as usize;
-                        y2_table[idx]
+                    .map(|(sub_vec_idx, &centroid)| {
+                        table[sub_vec_idx * num_centroids + centroid as usize]
                     })
-                    .sum::<f32>();
-                1.0 - xy / (x_norm * y2.sqrt())
-            }),
-        )))
+                    .sum()
+            })
+            .collect()
     }
 }
 
@@ -471,16 +455,17 @@ impl<T: ArrowFloatType + Cosine + Dot + L2 + 'static> ProductQuantizer for Produ
                     let sub_vector = &flatten_values[offset..offset + sub_dim];
                     let centroids = all_centroids[sub_idx];
 
+                    // Dot product is not a metric, the vector is encoded to the
+                    // closest centroid to minimize the reconstruction error.
                     let dist_iter = match metric_type {
-                        lance_linalg::distance::DistanceType::L2 => {
+                        lance_linalg::distance::DistanceType::L2
+                        | lance_linalg::distance::DistanceType::Dot
+                        | lance_linalg::distance::DistanceType::Custom(_) => {
                             l2_distance_batch(sub_vector, centroids, sub_dim)
                         }
                         lance_linalg::distance::DistanceType::Cosine => {
                             cosine_distance_batch(sub_vector, centroids, sub_dim)
                         }
-                        lance_linalg::distance::DistanceType::Dot => {
-                            dot_distance_batch(sub_vector, centroids, sub_dim)
-                        }
                     };
                     let code = argmin(dist_iter).unwrap();
                     builder[i * num_sub_vectors + sub_idx] = code as u8;
@@ -501,6 +486,10 @@ impl<T: ArrowFloatType + Cosine + Dot + L2 + 'static> ProductQuantizer for Produ
             MetricType::Cosine => self.cosine_distances(query, code),
             MetricType::Dot => self.dot_distance_table(query, code),
             MetricType::L2 => self.l2_distance_table(query, code),
+            MetricType::Custom(name) => Err(Error::Index {
+                message: format!("PQ does not support custom distance {}", name.as_str()),
+                location: location!(),
+            }),
         }
     }
 
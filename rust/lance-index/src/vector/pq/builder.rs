@@ -28,7 +28,7 @@ use futures::{stream, StreamExt, TryStreamExt};
 use lance_arrow::{ArrowFloatType, FloatArray};
 use lance_core::{Error, Result};
 use lance_linalg::distance::{Cosine, Dot, L2};
-use lance_linalg::{distance::MetricType, MatrixView};
+use lance_linalg::{distance::MetricType, kmeans::KMeanInit, MatrixView};
 use rand::{self, SeedableRng};
 use snafu::{location, Location};
 
@@ -59,8 +59,21 @@ pub struct PQBuildParams {
     /// User provided codebook.
     pub codebook: Option<ArrayRef>,
 
-    /// Sample rate to train PQ codebook.
+    /// Number of vectors sampled per centroid to train PQ codebook.
     pub sample_rate: usize,
+
+    /// Max number of vectors sampled to train PQ codebook, regardless of
+    /// `sample_rate`. If None, `sample_rate` vectors are sampled per centroid.
+    pub max_training_rows: Option<usize>,
+
+    /// Seed of the random generator to sample the training data and train kmeans.
+    /// If None, the codebook is trained with a random seed.
+    pub seed: Option<u64>,
+
+    /// Max number of threads to train the codebook, i.e., the number of sub-vectors
+    /// trained concurrently, each with a single thread. If None, the sub-vectors are
+    /// trained with all the CPUs.
+    pub num_threads: Option<usize>,
 }
 
 impl Default for PQBuildParams {
@@ -73,6 +86,9 @@ impl Default for PQBuildParams {
             max_opq_iters: 50,
             codebook: None,
             sample_rate: 256,
+            max_training_rows: None,
+            seed: None,
+            num_threads: None,
         }
     }
 }
@@ -86,6 +102,14 @@ impl PQBuildParams {
         }
     }
 
+    /// The number of vectors sampled to train the codebook, i.e., `sample_rate`
+    /// vectors per centroid, up to `max_training_rows`.
+    pub fn sample_size(&self) -> usize {
+        let sample_size = super::num_centroids(self.num_bits as u32) * self.sample_rate;
+        self.max_training_rows
+            .map_or(sample_size, |max_rows| std::cmp::min(sample_size, max_rows))
+    }
+
     pub fn with_codebook(num_sub_vectors: usize, num_bits: usize, codebook: ArrayRef) -> Self {
         Self {
             num_sub_vectors,
@@ -105,11 +129,21 @@ impl PQBuildParams {
         let dimension = data.num_columns();
         let sub_vector_dimension = dimension / self.num_sub_vectors;
         const REDOS: usize = 1;
+        // The codebook of dot product is trained to minimize the reconstruction error,
+        // since kmeans over dot product collapses to the centroids of the largest norms.
+        let kmeans_metric_type = match metric_type {
+            MetricType::Dot => MetricType::L2,
+            _ => metric_type,
+        };
 
         // TODO: parallel training.
-        let d = stream::iter(sub_vectors.into_iter())
-            .map(|sub_vec| async move {
-                let rng = rand::rngs::SmallRng::from_entropy();
+        let d = stream::iter(sub_vectors.into_iter().enumerate())
+            .map(|(i, sub_vec)| async move {
+                // Each sub-vector has its own stream of random numbers.
+                let rng = match self.seed {
+                    Some(seed) => rand::rngs::SmallRng::seed_from_u64(seed.wrapping_add(i as u64)),
+                    None => rand::rngs::SmallRng::from_entropy(),
+                };
                 train_kmeans::<T>(
                     sub_vec.as_ref(),
                     None,
@@ -118,12 +152,14 @@ impl PQBuildParams {
                     self.max_iters as u32,
                     REDOS,
                     rng.clone(),
-                    metric_type,
+                    kmeans_metric_type,
                     self.sample_rate,
+                    KMeanInit::Random,
+                    self.num_threads.map(|_| 1),
                 )
                 .await
             })
-            .buffered(num_cpus::get())
+            .buffered(self.num_threads.unwrap_or_else(num_cpus::get))
             .try_collect::<Vec<_>>()
             .await?;
         let mut codebook_builder = Vec::with_capacity(num_centroids * dimension);
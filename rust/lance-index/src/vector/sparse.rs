@@ -0,0 +1,212 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Sparse vectors, i.e., SPLADE or BM25-weighted embeddings.
+//!
+//! A sparse vector column is a struct of the non-zero `indices` and their `values`:
+//! `Struct<indices: List<UInt32>, values: List<Float32>>`.
+//! Sparse vectors are always ranked by dot product.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{Float32Builder, ListBuilder, UInt32Builder},
+    cast::AsArray,
+    types::{Float32Type, UInt32Type},
+    Array, ArrayRef, Float32Array, ListArray, StructArray,
+};
+use arrow_schema::{DataType, Field, Fields};
+use lance_core::{Error, Result};
+use snafu::{location, Location};
+
+/// Field name of the non-zero indices of a sparse vector.
+pub const INDICES_FIELD: &str = "indices";
+
+/// Field name of the values of a sparse vector.
+pub const VALUES_FIELD: &str = "values";
+
+fn sparse_vector_fields() -> Fields {
+    Fields::from(vec![
+        Field::new(
+            INDICES_FIELD,
+            DataType::List(Arc::new(Field::new("item", DataType::UInt32, true))),
+            true,
+        ),
+        Field::new(
+            VALUES_FIELD,
+            DataType::List(Arc::new(Field::new("item", DataType::Float32, true))),
+            true,
+        ),
+    ])
+}
+
+/// The data type of a sparse vector column.
+pub fn sparse_vector_type() -> DataType {
+    DataType::Struct(sparse_vector_fields())
+}
+
+/// Whether `data_type` is a sparse vector column.
+pub fn is_sparse_vector_type(data_type: &DataType) -> bool {
+    let DataType::Struct(fields) = data_type else {
+        return false;
+    };
+    let is_list_of = |name: &str, item_type: &DataType| {
+        fields.find(name).is_some_and(
+            |(_, f)| matches!(f.data_type(), DataType::List(item) if item.data_type() == item_type),
+        )
+    };
+    fields.len() == 2
+        && is_list_of(INDICES_FIELD, &DataType::UInt32)
+        && is_list_of(VALUES_FIELD, &DataType::Float32)
+}
+
+/// Build a sparse vector array from `(indices, values)` pairs.
+pub fn sparse_vector_array(
+    vectors: impl IntoIterator<Item = (Vec<u32>, Vec<f32>)>,
+) -> Result<StructArray> {
+    let mut indices_builder = ListBuilder::new(UInt32Builder::new());
+    let mut values_builder = ListBuilder::new(Float32Builder::new());
+    for (indices, values) in vectors {
+        if indices.len() != values.len() {
+            return Err(Error::Index {
+                message: format!(
+                    "Sparse vector must have as many indices as values, got {} and {}",
+                    indices.len(),
+                    values.len()
+                ),
+                location: location!(),
+            });
+        }
+        indices_builder.values().append_slice(&indices);
+        indices_builder.append(true);
+        values_builder.values().append_slice(&values);
+        values_builder.append(true);
+    }
+    Ok(StructArray::new(
+        sparse_vector_fields(),
+        vec![
+            Arc::new(indices_builder.finish()) as ArrayRef,
+            Arc::new(values_builder.finish()) as ArrayRef,
+        ],
+        None,
+    ))
+}
+
+/// The indices and the values of a sparse vector array.
+pub fn as_sparse_vectors(array: &dyn Array) -> Result<(&ListArray, &ListArray)> {
+    if !is_sparse_vector_type(array.data_type()) {
+        return Err(Error::Index {
+            message: format!(
+                "Expect a sparse vector array, i.e., struct of indices and values, got {}",
+                array.data_type()
+            ),
+            location: location!(),
+        });
+    }
+    let array = array.as_struct();
+    let indices = array.column_by_name(INDICES_FIELD).unwrap().as_list();
+    let values = array.column_by_name(VALUES_FIELD).unwrap().as_list();
+    Ok((indices, values))
+}
+
+/// The `i`-th sparse vector of the array, as `(indices, values)`.
+///
+/// Returns `None` if the vector is null.
+pub fn sparse_vector_at(
+    indices: &ListArray,
+    values: &ListArray,
+    i: usize,
+) -> Result<Option<(Vec<u32>, Vec<f32>)>> {
+    if indices.is_null(i) || values.is_null(i) {
+        return Ok(None);
+    }
+    let idx = indices.value(i);
+    let vals = values.value(i);
+    if idx.len() != vals.len() {
+        return Err(Error::Index {
+            message: format!(
+                "Sparse vector must have as many indices as values, got {} and {}",
+                idx.len(),
+                vals.len()
+            ),
+            location: location!(),
+        });
+    }
+    Ok(Some((
+        idx.as_primitive::<UInt32Type>().values().to_vec(),
+        vals.as_primitive::<Float32Type>().values().to_vec(),
+    )))
+}
+
+/// Compute the dot distances, i.e., the negative dot products, from the sparse `query`
+/// to each sparse vector of `vectors`.
+///
+/// Null vectors have null distances.
+pub fn sparse_dot_distance(query: &dyn Array, vectors: &dyn Array) -> Result<ArrayRef> {
+    let (query_indices, query_values) = as_sparse_vectors(query)?;
+    let Some((query_indices, query_values)) = sparse_vector_at(query_indices, query_values, 0)?
+    else {
+        return Err(Error::Index {
+            message: "Sparse query vector must not be null".to_string(),
+            location: location!(),
+        });
+    };
+    let query = query_indices
+        .into_iter()
+        .zip(query_values)
+        .collect::<HashMap<_, _>>();
+
+    let (indices, values) = as_sparse_vectors(vectors)?;
+    let distances = (0..vectors.len())
+        .map(|i| {
+            if vectors.is_null(i) {
+                return Ok(None);
+            }
+            Ok(sparse_vector_at(indices, values, i)?.map(|(idx, vals)| {
+                -idx.iter()
+                    .zip(vals.iter())
+                    .filter_map(|(i, v)| query.get(i).map(|q| q * v))
+                    .sum::<f32>()
+            }))
+        })
+        .collect::<Result<Float32Array>>()?;
+    Ok(Arc::new(distances))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sparse_dot_distance() {
+        let vectors = sparse_vector_array(vec![
+            (vec![1, 5, 9], vec![1.0, 2.0, 3.0]),
+            (vec![], vec![]),
+            (vec![5, 7], vec![0.5, 4.0]),
+        ])
+        .unwrap();
+        assert!(is_sparse_vector_type(vectors.data_type()));
+
+        let query = sparse_vector_array(vec![(vec![5, 9], vec![2.0, 1.0])]).unwrap();
+        let distances = sparse_dot_distance(&query, &vectors).unwrap();
+        assert_eq!(
+            distances.as_primitive::<Float32Type>().values(),
+            &[-7.0, 0.0, -1.0]
+        );
+
+        assert!(sparse_vector_array(vec![(vec![1], vec![])]).is_err());
+        assert!(!is_sparse_vector_type(&DataType::Float32));
+    }
+}
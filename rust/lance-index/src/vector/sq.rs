@@ -0,0 +1,365 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Scalar Quantization (SQ)
+//!
+//! Each dimension is linearly mapped from its `[min, max]` range, learned from the
+//! training data, to an 8-bit code.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray, types::Float32Type, Array, FixedSizeListArray, Float32Array, UInt8Array,
+};
+use lance_arrow::FixedSizeListArrayExt;
+use lance_core::{Error, Result};
+use lance_linalg::distance::{
+    cosine_distance_batch, custom::custom_distance_batch, dot_distance_batch, l2_distance_batch,
+    MetricType,
+};
+use snafu::{location, Location};
+
+use crate::pb;
+
+pub mod transform;
+
+/// Number of vectors to decode at once when computing distances.
+///
+/// Small enough for the decoded block to stay in the CPU cache.
+const DECODE_BLOCK_SIZE: usize = 256;
+
+/// Parameters for building scalar quantizer.
+#[derive(Debug, Clone)]
+pub struct SQBuildParams {
+    /// The number of bits to present one dimension. Only `8` is supported for now.
+    pub num_bits: u16,
+
+    /// Sample rate to train the range of each dimension.
+    pub sample_rate: usize,
+}
+
+impl Default for SQBuildParams {
+    fn default() -> Self {
+        Self {
+            num_bits: 8,
+            sample_rate: 256,
+        }
+    }
+}
+
+impl SQBuildParams {
+    /// Train a [ScalarQuantizer] from the training data.
+    pub fn build(&self, data: &FixedSizeListArray) -> Result<ScalarQuantizer> {
+        ScalarQuantizer::train(self.num_bits, data)
+    }
+}
+
+/// Scalar Quantizer.
+#[derive(Debug, Clone)]
+pub struct ScalarQuantizer {
+    num_bits: u16,
+
+    dimension: usize,
+
+    /// Lower bound of each dimension.
+    min: Vec<f32>,
+
+    /// Upper bound of each dimension.
+    max: Vec<f32>,
+
+    /// The step between two adjacent codes of each dimension, `(max - min) / 255`.
+    scale: Vec<f32>,
+}
+
+impl ScalarQuantizer {
+    /// Create a scalar quantizer from the per-dimension bounds.
+    pub fn try_new(num_bits: u16, min: Vec<f32>, max: Vec<f32>) -> Result<Self> {
+        if num_bits != 8 {
+            return Err(Error::Index {
+                message: format!("SQ: only 8 bits is supported, got {}", num_bits),
+                location: location!(),
+            });
+        }
+        if min.len() != max.len() {
+            return Err(Error::Index {
+                message: format!(
+                    "SQ: bounds length mismatch: min={}, max={}",
+                    min.len(),
+                    max.len()
+                ),
+                location: location!(),
+            });
+        }
+        let levels = ((1 << num_bits) - 1) as f32;
+        let scale = min
+            .iter()
+            .zip(max.iter())
+            .map(|(&lo, &hi)| (hi - lo) / levels)
+            .collect();
+        Ok(Self {
+            num_bits,
+            dimension: min.len(),
+            min,
+            max,
+            scale,
+        })
+    }
+
+    /// Train the range of each dimension from the training data.
+    pub fn train(num_bits: u16, data: &FixedSizeListArray) -> Result<Self> {
+        let values = data
+            .values()
+            .as_primitive_opt::<Float32Type>()
+            .ok_or_else(|| Error::Index {
+                message: format!(
+                    "SQ: only supports float32 vectors, got {}",
+                    data.value_type()
+                ),
+                location: location!(),
+            })?;
+        let dimension = data.value_length() as usize;
+        let mut min = vec![f32::MAX; dimension];
+        let mut max = vec![f32::MIN; dimension];
+        for vector in values.values().chunks_exact(dimension) {
+            for (d, &v) in vector.iter().enumerate() {
+                min[d] = min[d].min(v);
+                max[d] = max[d].max(v);
+            }
+        }
+        if data.is_empty() {
+            min.fill(0.0);
+            max.fill(0.0);
+        }
+        Self::try_new(num_bits, min, max)
+    }
+
+    pub fn num_bits(&self) -> u16 {
+        self.num_bits
+    }
+
+    pub fn dimension(&self) -> usize {
+        self.dimension
+    }
+
+    /// Encode the vectors into a fixed size list of `dimension` codes.
+    ///
+    /// Values out of the trained range are clamped.
+    pub fn transform(&self, data: &FixedSizeListArray) -> Result<FixedSizeListArray> {
+        if data.value_length() as usize != self.dimension {
+            return Err(Error::Index {
+                message: format!(
+                    "SQ: dimension mismatch: expected {}, got {}",
+                    self.dimension,
+                    data.value_length()
+                ),
+                location: location!(),
+            });
+        }
+        let values = data
+            .values()
+            .as_primitive_opt::<Float32Type>()
+            .ok_or_else(|| Error::Index {
+                message: format!(
+                    "SQ: only supports float32 vectors, got {}",
+                    data.value_type()
+                ),
+                location: location!(),
+            })?;
+        let max_code = ((1 << self.num_bits) - 1) as f32;
+        let codes = values
+            .values()
+            .chunks_exact(self.dimension)
+            .flat_map(|vector| {
+                vector
+                    .iter()
+                    .zip(self.min.iter().zip(self.scale.iter()))
+                    .map(move |(&v, (&lo, &scale))| {
+                        if scale > 0.0 {
+                            ((v - lo) / scale).round().clamp(0.0, max_code) as u8
+                        } else {
+                            0
+                        }
+                    })
+            })
+            .collect::<Vec<_>>();
+        Ok(FixedSizeListArray::try_new_from_values(
+            UInt8Array::from(codes),
+            self.dimension as i32,
+        )?)
+    }
+
+    /// Decode the codes of a list of vectors into `output`.
+    ///
+    /// The loop over the dimensions has no branches, so it is auto-vectorized.
+    pub fn decode(&self, codes: &[u8], output: &mut Vec<f32>) {
+        output.reserve(codes.len());
+        for code in codes.chunks_exact(self.dimension) {
+            output.extend(
+                code.iter()
+                    .zip(self.min.iter().zip(self.scale.iter()))
+                    .map(|(&c, (&lo, &scale))| lo + c as f32 * scale),
+            );
+        }
+    }
+
+    /// Compute the distances from the query to each encoded vector.
+    ///
+    /// The codes are decoded block by block, and each block is fed to the SIMD
+    /// distance kernels.
+    pub fn compute_distances(
+        &self,
+        query: &[f32],
+        codes: &[u8],
+        metric_type: MetricType,
+    ) -> Result<Float32Array> {
+        if query.len() != self.dimension {
+            return Err(Error::Index {
+                message: format!(
+                    "SQ: query dimension mismatch: expected {}, got {}",
+                    self.dimension,
+                    query.len()
+                ),
+                location: location!(),
+            });
+        }
+        let mut distances = Vec::with_capacity(codes.len() / self.dimension);
+        let mut block = Vec::with_capacity(DECODE_BLOCK_SIZE * self.dimension);
+        for block_codes in codes.chunks(DECODE_BLOCK_SIZE * self.dimension) {
+            block.clear();
+            self.decode(block_codes, &mut block);
+            match metric_type {
+                MetricType::L2 => {
+                    distances.extend(l2_distance_batch(query, &block, self.dimension))
+                }
+                MetricType::Cosine => {
+                    distances.extend(cosine_distance_batch(query, &block, self.dimension))
+                }
+                MetricType::Dot => {
+                    distances.extend(dot_distance_batch(query, &block, self.dimension))
+                }
+                MetricType::Custom(name) => {
+                    distances.extend(custom_distance_batch(name, query, &block, self.dimension))
+                }
+            }
+        }
+        Ok(Float32Array::from(distances))
+    }
+}
+
+impl From<&ScalarQuantizer> for pb::Sq {
+    fn from(sq: &ScalarQuantizer) -> Self {
+        Self {
+            num_bits: sq.num_bits as u32,
+            dimension: sq.dimension as u32,
+            min: sq.min.clone(),
+            max: sq.max.clone(),
+        }
+    }
+}
+
+/// Load a [ScalarQuantizer] from the protobuf.
+pub fn from_proto(proto: &pb::Sq) -> Result<Arc<ScalarQuantizer>> {
+    if proto.min.len() != proto.dimension as usize {
+        return Err(Error::Index {
+            message: format!(
+                "SQ: expected {} bounds, got {}",
+                proto.dimension,
+                proto.min.len()
+            ),
+            location: location!(),
+        });
+    }
+    Ok(Arc::new(ScalarQuantizer::try_new(
+        proto.num_bits as u16,
+        proto.min.clone(),
+        proto.max.clone(),
+    )?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::UInt8Array;
+
+    #[test]
+    fn test_train_and_encode() {
+        let values = Float32Array::from_iter((0..1000).map(|v| v as f32));
+        let data = FixedSizeListArray::try_new_from_values(values, 4).unwrap();
+        let sq = SQBuildParams::default().build(&data).unwrap();
+        assert_eq!(sq.dimension(), 4);
+        assert_eq!(sq.min, vec![0.0, 1.0, 2.0, 3.0]);
+        assert_eq!(sq.max, vec![996.0, 997.0, 998.0, 999.0]);
+
+        let codes = sq.transform(&data).unwrap();
+        let codes: &UInt8Array = codes.values().as_primitive();
+        assert_eq!(&codes.values()[..4], &[0, 0, 0, 0]);
+        assert_eq!(&codes.values()[996..], &[255, 255, 255, 255]);
+
+        // The decoding error is bounded by half of a step.
+        let mut decoded = vec![];
+        sq.decode(codes.values(), &mut decoded);
+        let step = 996.0 / 255.0;
+        for (orig, dec) in data
+            .values()
+            .as_primitive::<Float32Type>()
+            .values()
+            .iter()
+            .zip(decoded.iter())
+        {
+            assert!((orig - dec).abs() <= step / 2.0 + 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_values_are_clamped() {
+        let sq = ScalarQuantizer::try_new(8, vec![0.0, 0.0], vec![1.0, 1.0]).unwrap();
+        let data = FixedSizeListArray::try_new_from_values(Float32Array::from(vec![-1.0, 2.0]), 2)
+            .unwrap();
+        let codes = sq.transform(&data).unwrap();
+        let codes: &UInt8Array = codes.values().as_primitive();
+        assert_eq!(codes.values(), &[0, 255]);
+    }
+
+    #[test]
+    fn test_compute_distances() {
+        let values = Float32Array::from_iter((0..4096).map(|v| (v % 97) as f32));
+        let data = FixedSizeListArray::try_new_from_values(values.clone(), 8).unwrap();
+        let sq = SQBuildParams::default().build(&data).unwrap();
+        let codes = sq.transform(&data).unwrap();
+        let codes: &UInt8Array = codes.values().as_primitive();
+
+        let query = &values.values()[8..16];
+        let distances = sq
+            .compute_distances(query, codes.values(), MetricType::L2)
+            .unwrap();
+        assert_eq!(distances.len(), 512);
+        let expected = l2_distance_batch(query, values.values(), 8).collect::<Vec<_>>();
+        // Each dimension is off by at most half of a step.
+        let max_error = (8.0_f32).sqrt() * (96.0 / 255.0) / 2.0 + 1e-3;
+        for (actual, expected) in distances.values().iter().zip(expected.iter()) {
+            assert!((actual.sqrt() - expected.sqrt()).abs() <= max_error);
+        }
+    }
+
+    #[test]
+    fn test_proto_roundtrip() {
+        let sq = ScalarQuantizer::try_new(8, vec![0.0, -1.0], vec![1.0, 3.0]).unwrap();
+        let proto = pb::Sq::from(&sq);
+        let loaded = from_proto(&proto).unwrap();
+        assert_eq!(loaded.min, sq.min);
+        assert_eq!(loaded.max, sq.max);
+        assert_eq!(loaded.scale, sq.scale);
+    }
+}
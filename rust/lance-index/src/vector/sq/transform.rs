@@ -0,0 +1,83 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, Array, RecordBatch};
+use arrow_schema::Field;
+use async_trait::async_trait;
+use lance_arrow::RecordBatchExt;
+use lance_core::{Error, Result};
+use snafu::{location, Location};
+
+use super::ScalarQuantizer;
+use crate::vector::transform::Transformer;
+
+/// Scalar Quantizer Transformer
+///
+/// It transforms a column of vectors into a column of SQ codes.
+pub struct SQTransformer {
+    quantizer: Arc<ScalarQuantizer>,
+    input_column: String,
+    output_column: String,
+}
+
+impl SQTransformer {
+    pub fn new(quantizer: Arc<ScalarQuantizer>, input_column: &str, output_column: &str) -> Self {
+        Self {
+            quantizer,
+            input_column: input_column.to_owned(),
+            output_column: output_column.to_owned(),
+        }
+    }
+}
+
+impl Debug for SQTransformer {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SQTransformer(input={}, output={})",
+            self.input_column, self.output_column
+        )
+    }
+}
+
+#[async_trait]
+impl Transformer for SQTransformer {
+    async fn transform(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let input_arr = batch
+            .column_by_name(&self.input_column)
+            .ok_or(Error::Index {
+                message: format!(
+                    "SQ Transform: column {} not found in batch",
+                    self.input_column
+                ),
+                location: location!(),
+            })?;
+        let data = input_arr.as_fixed_size_list_opt().ok_or(Error::Index {
+            message: format!(
+                "SQ Transform: column {} is not a fixed size list, got {}",
+                self.input_column,
+                input_arr.data_type(),
+            ),
+            location: location!(),
+        })?;
+        let sq_code = self.quantizer.transform(data)?;
+        let sq_field = Field::new(&self.output_column, sq_code.data_type().clone(), false);
+        let batch = batch.try_with_column(sq_field, Arc::new(sq_code))?;
+        let batch = batch.drop_column(&self.input_column)?;
+        Ok(batch)
+    }
+}
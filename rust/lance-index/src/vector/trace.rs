@@ -0,0 +1,166 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Traces of vector searches, to explain how the results of a query are found.
+//!
+//! A [SearchTrace] is shared by all the stages of one search. Each stage records how
+//! many candidates it examined, how many results it produced and how long it took.
+
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// A stage of a vector search.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SearchStage {
+    /// Search one delta of the vector index.
+    Index,
+    /// Flat search over the fragments that are not indexed, or over all the
+    /// fragments if the column has no index.
+    Flat,
+    /// Re-rank the candidates of the index by their original vectors.
+    Refine,
+    /// Merge the results of the index and of the flat search over the new fragments.
+    Merge,
+}
+
+impl fmt::Display for SearchStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Self::Index => "index",
+            Self::Flat => "flat",
+            Self::Refine => "refine",
+            Self::Merge => "merge",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The trace of one stage of a vector search.
+#[derive(Debug, Clone)]
+pub struct StageTrace {
+    pub stage: SearchStage,
+
+    /// The number of candidates examined, i.e., the vectors of the probed partitions of
+    /// an IVF index, or the input rows of a flat search.
+    ///
+    /// Indices that do not report it, e.g., graph indices, count their results.
+    pub candidates: usize,
+
+    /// The number of results of the stage.
+    pub results: usize,
+
+    /// The time spent in the stage, including reading its input.
+    pub elapsed: Duration,
+}
+
+/// A partition probed by an IVF index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionTrace {
+    pub partition_id: u32,
+
+    /// The number of vectors in the partition.
+    pub candidates: usize,
+}
+
+/// The trace of a vector search.
+#[derive(Debug, Default)]
+pub struct SearchTrace {
+    partitions: Mutex<Vec<PartitionTrace>>,
+    stages: Mutex<Vec<StageTrace>>,
+}
+
+impl SearchTrace {
+    /// Record the partitions probed by an IVF index, nearest first.
+    pub fn record_partitions(&self, partitions: impl IntoIterator<Item = PartitionTrace>) {
+        self.partitions.lock().unwrap().extend(partitions);
+    }
+
+    /// Record a finished stage.
+    pub fn record_stage(&self, stage: StageTrace) {
+        self.stages.lock().unwrap().push(stage);
+    }
+
+    /// The partitions probed by the IVF indices.
+    pub fn partitions(&self) -> Vec<PartitionTrace> {
+        self.partitions.lock().unwrap().clone()
+    }
+
+    /// The stages of the search, in the order they finished.
+    pub fn stages(&self) -> Vec<StageTrace> {
+        self.stages.lock().unwrap().clone()
+    }
+
+    /// The traces of `stage`.
+    pub fn stage(&self, stage: SearchStage) -> Vec<StageTrace> {
+        self.stages()
+            .into_iter()
+            .filter(|s| s.stage == stage)
+            .collect()
+    }
+}
+
+impl fmt::Display for SearchTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let partitions = self.partitions();
+        if !partitions.is_empty() {
+            let probed = partitions
+                .iter()
+                .map(|p| format!("{} ({} vectors)", p.partition_id, p.candidates))
+                .collect::<Vec<_>>();
+            writeln!(f, "probed partitions: {}", probed.join(", "))?;
+        }
+        for stage in self.stages() {
+            writeln!(
+                f,
+                "{}: candidates={} results={} elapsed={:?}",
+                stage.stage, stage.candidates, stage.results, stage.elapsed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_trace() {
+        let trace = SearchTrace::default();
+        trace.record_partitions([
+            PartitionTrace {
+                partition_id: 3,
+                candidates: 120,
+            },
+            PartitionTrace {
+                partition_id: 7,
+                candidates: 98,
+            },
+        ]);
+        trace.record_stage(StageTrace {
+            stage: SearchStage::Index,
+            candidates: 218,
+            results: 10,
+            elapsed: Duration::from_millis(2),
+        });
+        assert_eq!(trace.stage(SearchStage::Index).len(), 1);
+        assert!(trace.stage(SearchStage::Refine).is_empty());
+        assert_eq!(
+            trace.to_string(),
+            "probed partitions: 3 (120 vectors), 7 (98 vectors)\n\
+             index: candidates=218 results=10 elapsed=2ms\n"
+        );
+    }
+}
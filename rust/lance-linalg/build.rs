@@ -1,5 +1,19 @@
 fn main() {
     println!("cargo:rerun-if-changed=src/simd/f16.c");
+    println!("cargo:rerun-if-changed=src/simd/avx512.c");
+
+    let target_arch = std::env::var("CARGO_CFG_TARGET_ARCH").unwrap_or_default();
+    let target_env = std::env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
+    if target_arch == "x86_64" && target_env != "msvc" {
+        // The kernels enable AVX-512 per function and are dispatched at runtime.
+        cc::Build::new()
+            .file("src/simd/avx512.c")
+            .flag("-O3")
+            .flag("-Wall")
+            .flag("-Werror")
+            .flag("-Wextra")
+            .compile("avx512");
+    }
 
     if cfg!(all(target_os = "macos", target_feature = "neon")) {
         cc::Build::new()
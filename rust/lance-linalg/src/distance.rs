@@ -17,7 +17,9 @@
 //! This module provides distance metrics for vectors.
 //!
 //! - `bf16, f16, f32, f64` types are supported.
+//! - `i8` vectors, i.e., quantized embeddings, are supported by the arrow batch functions.
 //! - SIMD is used when available, on `x86_64` and `aarch64` architectures.
+//! - User defined distances can be registered, see [custom].
 
 use std::sync::Arc;
 
@@ -25,12 +27,16 @@ use arrow_array::{Array, FixedSizeListArray, Float32Array};
 use arrow_schema::ArrowError;
 
 pub mod cosine;
+pub mod custom;
 pub mod dot;
+pub mod int8;
 pub mod l2;
 pub mod norm_l2;
 
 pub use cosine::*;
+pub use custom::{register_distance, CustomDistance, CustomDistanceName};
 pub use dot::*;
+pub use int8::*;
 pub use l2::*;
 pub use norm_l2::*;
 
@@ -42,6 +48,8 @@ pub enum DistanceType {
     L2,
     Cosine,
     Dot, // Dot product
+    /// A registered [CustomDistance].
+    Custom(CustomDistanceName),
 }
 
 /// For backwards compatibility.
@@ -49,26 +57,48 @@ pub type MetricType = DistanceType;
 
 pub type DistanceFunc = fn(&[f32], &[f32]) -> f32;
 pub type BatchDistanceFunc = fn(&[f32], &[f32], usize) -> Arc<Float32Array>;
-pub type ArrowBatchDistanceFunc = fn(&dyn Array, &FixedSizeListArray) -> Result<Arc<Float32Array>>;
+pub type ArrowBatchDistanceFunc =
+    Arc<dyn Fn(&dyn Array, &FixedSizeListArray) -> Result<Arc<Float32Array>> + Send + Sync>;
 
 impl DistanceType {
     /// Compute the distance from one vector to a batch of vectors.
     ///
     /// This propagates nulls to the output.
     pub fn arrow_batch_func(&self) -> ArrowBatchDistanceFunc {
-        match self {
-            Self::L2 => l2_distance_arrow_batch,
-            Self::Cosine => cosine_distance_arrow_batch,
-            Self::Dot => dot_distance_arrow_batch,
+        match *self {
+            Self::L2 => Arc::new(l2_distance_arrow_batch),
+            Self::Cosine => Arc::new(cosine_distance_arrow_batch),
+            Self::Dot => Arc::new(dot_distance_arrow_batch),
+            Self::Custom(name) => {
+                Arc::new(move |from, to| custom::custom_distance_arrow_batch(name, from, to))
+            }
         }
     }
 
     /// Returns the distance function between two vectors.
+    ///
+    /// # Panics
+    ///
+    /// Panics for [Self::Custom], which is only supported by the batch functions.
     pub fn func(&self) -> DistanceFunc {
         match self {
             Self::L2 => l2::<f32>,
             Self::Cosine => cosine_distance,
             Self::Dot => dot_distance,
+            Self::Custom(name) => panic!(
+                "custom distance {} is not supported by graph indices",
+                name.as_str()
+            ),
+        }
+    }
+
+    /// The built-in distance type of `name`, if any.
+    fn try_builtin(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "l2" | "euclidean" => Some(Self::L2),
+            "cosine" => Some(Self::Cosine),
+            "dot" => Some(Self::Dot),
+            _ => None,
         }
     }
 }
@@ -82,6 +112,7 @@ impl std::fmt::Display for DistanceType {
                 Self::L2 => "l2",
                 Self::Cosine => "cosine",
                 Self::Dot => "dot",
+                Self::Custom(name) => name.as_str(),
             }
         )
     }
@@ -91,13 +122,10 @@ impl TryFrom<&str> for DistanceType {
     type Error = ArrowError;
 
     fn try_from(s: &str) -> std::result::Result<Self, Self::Error> {
-        match s.to_lowercase().as_str() {
-            "l2" | "euclidean" => Ok(Self::L2),
-            "cosine" => Ok(Self::Cosine),
-            "dot" => Ok(Self::Dot),
-            _ => Err(ArrowError::InvalidArgumentError(format!(
-                "Metric type '{s}' is not supported"
-            ))),
-        }
+        Self::try_builtin(s)
+            .or_else(|| custom::lookup_distance(s).map(Self::Custom))
+            .ok_or_else(|| {
+                ArrowError::InvalidArgumentError(format!("Metric type '{s}' is not supported"))
+            })
     }
 }
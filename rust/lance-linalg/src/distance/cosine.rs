@@ -31,8 +31,10 @@ use lance_arrow::{ArrowFloatType, FloatArray, FloatToArrayType};
 use num_traits::{AsPrimitive, FromPrimitive};
 
 use super::dot::dot;
+use super::int8::{cosine_distance_i8, int8_distance_arrow_batch};
 use super::norm_l2::norm_l2;
 use crate::simd::{
+    avx512,
     f32::{f32x16, f32x8},
     FloatSimd, SIMD,
 };
@@ -113,6 +115,10 @@ mod f32 {
 impl Cosine for Float32Type {
     #[inline]
     fn cosine_fast(x: &[f32], x_norm: f32, other: &[f32]) -> f32 {
+        if let Some(d) = avx512::cosine_f32(x, x_norm, other) {
+            return d;
+        }
+
         let dim = x.len();
         let unrolled_len = dim / 16 * 16;
         let mut y_norm16 = f32x16::zeros();
@@ -145,6 +151,10 @@ impl Cosine for Float32Type {
 
     #[inline]
     fn cosine_with_norms(x: &[f32], x_norm: f32, y_norm: f32, y: &[f32]) -> Self::Native {
+        if let Some(xy) = avx512::dot_f32(x, y) {
+            return 1.0 - xy / x_norm / y_norm;
+        }
+
         let dim = x.len();
         let unrolled_len = dim / 16 * 16;
         let mut xy16 = f32x16::zeros();
@@ -211,7 +221,7 @@ where
     let y_sq = dot(y, y);
     let xy = dot(x, y);
     // 1 - xy / (sqrt(x_sq) * sqrt(y_sq))
-    1.0 - xy / x_norm * y_sq.sqrt()
+    1.0 - xy / (x_norm * y_sq.sqrt())
 }
 
 #[inline]
@@ -227,7 +237,7 @@ where
     let xy = dot(x, y);
     // 1 - xy / (sqrt(x_sq) * sqrt(y_sq))
     // use f64 for overflow protection.
-    1.0 - (xy / x_norm * y_norm)
+    1.0 - xy / (x_norm * y_norm)
 }
 
 /// Cosine distance function between two vectors.
@@ -308,6 +318,7 @@ pub fn cosine_distance_arrow_batch(
         DataType::Float16 => do_cosine_distance_arrow_batch::<Float16Type>(from.as_primitive(), to),
         DataType::Float32 => do_cosine_distance_arrow_batch::<Float32Type>(from.as_primitive(), to),
         DataType::Float64 => do_cosine_distance_arrow_batch::<Float64Type>(from.as_primitive(), to),
+        DataType::Int8 => int8_distance_arrow_batch(from, to, cosine_distance_i8),
         _ => Err(Error::InvalidArgumentError(format!(
             "Unsupported data type {:?}",
             from.data_type()
@@ -321,6 +332,7 @@ mod tests {
 
     use approx::assert_relative_eq;
     use arrow_array::Float32Array;
+    use half::f16;
 
     fn cosine_dist_brute_force(x: &[f32], y: &[f32]) -> f32 {
         let xy = x
@@ -357,6 +369,30 @@ mod tests {
         assert_relative_eq!(d[0], cosine_dist_brute_force(&x, &y));
     }
 
+    #[test]
+    fn test_cosine_f16_f64() {
+        let x = (1..9).map(|v| v as f32).collect::<Vec<_>>();
+        let y = (100..108).map(|v| v as f32).collect::<Vec<_>>();
+        let expected = cosine_dist_brute_force(&x, &y);
+
+        let x16 = x.iter().map(|&v| f16::from_f32(v)).collect::<Vec<_>>();
+        let y16 = y.iter().map(|&v| f16::from_f32(v)).collect::<Vec<_>>();
+        let d = cosine_distance_batch(&x16, &y16, 8).collect::<Vec<_>>();
+        assert_relative_eq!(d[0], expected, epsilon = 1e-3);
+        let x_norm = norm_l2(&x16);
+        let y_norm = norm_l2(&y16);
+        assert_relative_eq!(
+            Float16Type::cosine_with_norms(&x16, x_norm, y_norm, &y16),
+            expected,
+            epsilon = 1e-3
+        );
+
+        let x64 = x.iter().map(|&v| v as f64).collect::<Vec<_>>();
+        let y64 = y.iter().map(|&v| v as f64).collect::<Vec<_>>();
+        let d = cosine_distance_batch(&x64, &y64, 8).collect::<Vec<_>>();
+        assert_relative_eq!(d[0], expected, epsilon = 1e-6);
+    }
+
     #[test]
     fn test_cosine_not_aligned() {
         let x: Float32Array = vec![16_f32, 32_f32].into();
@@ -0,0 +1,223 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! User defined distances.
+//!
+//! A [CustomDistance] is registered under a name with [register_distance], which
+//! returns the [DistanceType] to search and to train IVF with, i.e., flat search,
+//! kmeans and IVF partition assignment. The distances are computed over `f32`s,
+//! vectors of other float types are converted first.
+
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float16Type, Float32Type, Float64Type},
+    Array, FixedSizeListArray, Float32Array,
+};
+use arrow_schema::DataType;
+use num_traits::AsPrimitive;
+
+use super::DistanceType;
+use crate::{Error, Result};
+
+/// A distance between two vectors, the smaller the closer, e.g., a weighted L2 or
+/// a Mahalanobis distance with a fixed matrix.
+pub trait CustomDistance: Send + Sync {
+    /// Distance between two vectors of the same dimension.
+    fn distance(&self, x: &[f32], y: &[f32]) -> f32;
+
+    /// Distances from `from` to each vector of `to`, a flattened batch of vectors
+    /// of `dimension`.
+    fn distance_batch(&self, from: &[f32], to: &[f32], dimension: usize) -> Vec<f32> {
+        to.chunks_exact(dimension)
+            .map(|y| self.distance(from, y))
+            .collect()
+    }
+}
+
+/// Name of a registered [CustomDistance].
+///
+/// It can only be obtained by registering the distance, and a distance is never
+/// unregistered, so the distance of a name can always be found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CustomDistanceName(&'static str);
+
+impl CustomDistanceName {
+    pub fn as_str(&self) -> &'static str {
+        self.0
+    }
+
+    /// The distance registered under this name.
+    pub fn distance(&self) -> Arc<dyn CustomDistance> {
+        registry()
+            .read()
+            .unwrap()
+            .get(self.0)
+            .expect("custom distances are never unregistered")
+            .clone()
+    }
+}
+
+fn registry() -> &'static RwLock<HashMap<&'static str, Arc<dyn CustomDistance>>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<&'static str, Arc<dyn CustomDistance>>>> =
+        OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+/// Register `distance` under the case-insensitive `name`, replacing the distance
+/// previously registered under the same name.
+///
+/// Returns the [DistanceType] of the distance, which can also be parsed from `name`.
+pub fn register_distance(name: &str, distance: Arc<dyn CustomDistance>) -> Result<DistanceType> {
+    let name = name.to_lowercase();
+    if name.is_empty() || DistanceType::try_builtin(&name).is_some() {
+        return Err(Error::InvalidArgumentError(format!(
+            "Can not register a custom distance as '{}'",
+            name
+        )));
+    }
+    let mut registry = registry().write().unwrap();
+    // The names are leaked, there are only a few of them.
+    let key = match registry.get_key_value(name.as_str()) {
+        Some((key, _)) => *key,
+        None => Box::leak(name.into_boxed_str()),
+    };
+    registry.insert(key, distance);
+    Ok(DistanceType::Custom(CustomDistanceName(key)))
+}
+
+/// The registered custom distance of the case-insensitive `name`, if any.
+pub fn lookup_distance(name: &str) -> Option<CustomDistanceName> {
+    registry()
+        .read()
+        .unwrap()
+        .get_key_value(name.to_lowercase().as_str())
+        .map(|(key, _)| CustomDistanceName(key))
+}
+
+/// Compute the custom distance from `from` to each vector of `to`, a flattened batch
+/// of vectors of `dimension`.
+pub fn custom_distance_batch<T: AsPrimitive<f32>>(
+    name: CustomDistanceName,
+    from: &[T],
+    to: &[T],
+    dimension: usize,
+) -> Vec<f32> {
+    let from = from.iter().map(|v| v.as_()).collect::<Vec<_>>();
+    let to = to.iter().map(|v| v.as_()).collect::<Vec<_>>();
+    name.distance().distance_batch(&from, &to, dimension)
+}
+
+fn to_f32s(array: &dyn Array) -> Result<Vec<f32>> {
+    match array.data_type() {
+        DataType::Float16 => Ok(array
+            .as_primitive::<Float16Type>()
+            .values()
+            .iter()
+            .map(|v| v.to_f32())
+            .collect()),
+        DataType::Float32 => Ok(array.as_primitive::<Float32Type>().values().to_vec()),
+        DataType::Float64 => Ok(array
+            .as_primitive::<Float64Type>()
+            .values()
+            .iter()
+            .map(|v| *v as f32)
+            .collect()),
+        _ => Err(Error::ComputeError(format!(
+            "Unsupported data type for custom distance: {}",
+            array.data_type()
+        ))),
+    }
+}
+
+/// Compute the custom distance from one vector to a batch of vectors.
+///
+/// Null buffer of `to` is propagated to the returned array.
+pub fn custom_distance_arrow_batch(
+    name: CustomDistanceName,
+    from: &dyn Array,
+    to: &FixedSizeListArray,
+) -> Result<Arc<Float32Array>> {
+    if from.data_type() != &to.value_type() {
+        return Err(Error::ComputeError(format!(
+            "Cannot downcast to the same type: {} != {}",
+            from.data_type(),
+            to.value_type()
+        )));
+    }
+    let dimension = to.value_length() as usize;
+    let from = to_f32s(from)?;
+    let to_values = to_f32s(to.values().as_ref())?;
+    let dists = name.distance().distance_batch(&from, &to_values, dimension);
+    Ok(Arc::new(Float32Array::new(
+        dists.into(),
+        to.nulls().cloned(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::types::Float32Type;
+
+    /// L2 distance weighted by each dimension.
+    struct WeightedL2(Vec<f32>);
+
+    impl CustomDistance for WeightedL2 {
+        fn distance(&self, x: &[f32], y: &[f32]) -> f32 {
+            x.iter()
+                .zip(y)
+                .zip(&self.0)
+                .map(|((x, y), w)| w * (x - y) * (x - y))
+                .sum()
+        }
+    }
+
+    #[test]
+    fn test_custom_distance() {
+        let distance_type =
+            register_distance("Weighted_L2", Arc::new(WeightedL2(vec![1.0, 0.0]))).unwrap();
+        assert_eq!(distance_type.to_string(), "weighted_l2");
+        assert_eq!(
+            DistanceType::try_from("WEIGHTED_L2").unwrap(),
+            distance_type
+        );
+        assert!(register_distance("l2", Arc::new(WeightedL2(vec![]))).is_err());
+        assert!(DistanceType::try_from("unregistered").is_err());
+
+        let to = FixedSizeListArray::from_iter_primitive::<Float32Type, _, _>(
+            vec![
+                Some(vec![Some(1.0), Some(5.0)]),
+                None,
+                Some(vec![Some(3.0), Some(0.0)]),
+            ],
+            2,
+        );
+        let from = Float32Array::from(vec![0.0, 0.0]);
+        let dists = distance_type.arrow_batch_func()(&from, &to).unwrap();
+        assert_eq!(dists.value(0), 1.0);
+        assert!(dists.is_null(1));
+        assert_eq!(dists.value(2), 9.0);
+
+        // Registering again replaces the distance.
+        let replaced =
+            register_distance("weighted_l2", Arc::new(WeightedL2(vec![0.0, 1.0]))).unwrap();
+        assert_eq!(replaced, distance_type);
+        let dists = distance_type.arrow_batch_func()(&from, &to).unwrap();
+        assert_eq!(dists.value(0), 25.0);
+    }
+}
@@ -28,7 +28,9 @@ use lance_arrow::{ArrowFloatType, FloatArray, FloatToArrayType};
 use num_traits::real::Real;
 use num_traits::AsPrimitive;
 
+use super::int8::{dot_distance_i8, int8_distance_arrow_batch};
 use crate::simd::{
+    avx512,
     f32::{f32x16, f32x8},
     SIMD,
 };
@@ -46,24 +48,25 @@ fn dot_scalar<T: Real + Sum + AddAssign + AsPrimitive<f32>, const LANES: usize>(
 ) -> f32 {
     let x_chunks = to.chunks_exact(LANES);
     let y_chunks = from.chunks_exact(LANES);
+    // Accumulate in f32, so that the sums of f16 vectors do not overflow.
     let sum = if x_chunks.remainder().is_empty() {
-        T::zero()
+        0.0
     } else {
         x_chunks
             .remainder()
             .iter()
             .zip(y_chunks.remainder().iter())
-            .map(|(&x, &y)| x * y)
-            .sum::<T>()
+            .map(|(&x, &y)| x.as_() * y.as_())
+            .sum::<f32>()
     };
     // Use known size to allow LLVM to kick in auto-vectorization.
-    let mut sums = [T::zero(); LANES];
+    let mut sums = [0_f32; LANES];
     for (x, y) in x_chunks.zip(y_chunks) {
         for i in 0..LANES {
-            sums[i] += x[i] * y[i];
+            sums[i] += x[i].as_() * y[i].as_();
         }
     }
-    (sum + sums.iter().copied().sum::<T>()).as_()
+    sum + sums.iter().copied().sum::<f32>()
 }
 
 /// Dot product.
@@ -132,8 +135,11 @@ impl Dot for Float16Type {
 impl Dot for Float32Type {
     #[inline]
     fn dot(x: &[f32], y: &[f32]) -> f32 {
+        if let Some(d) = avx512::dot_f32(x, y) {
+            return d;
+        }
+
         // Manually unrolled 8 times to get enough registers.
-        // TODO: avx512 can unroll more
         let x_unrolled_chunks = x.chunks_exact(64);
         let y_unrolled_chunks = y.chunks_exact(64);
 
@@ -258,6 +264,7 @@ pub fn dot_distance_arrow_batch(
         DataType::Float16 => do_dot_distance_arrow_batch::<Float16Type>(from.as_primitive(), to),
         DataType::Float32 => do_dot_distance_arrow_batch::<Float32Type>(from.as_primitive(), to),
         DataType::Float64 => do_dot_distance_arrow_batch::<Float64Type>(from.as_primitive(), to),
+        DataType::Int8 => int8_distance_arrow_batch(from, to, dot_distance_i8),
         _ => Err(Error::InvalidArgumentError(format!(
             "Unsupported data type: {:?}",
             from.data_type()
@@ -0,0 +1,139 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Distances between int8 vectors, i.e., quantized embeddings.
+//!
+//! The products are accumulated in integers, so the distances are exact
+//! before the final conversion to `f32`.
+
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::Int8Type, Array, FixedSizeListArray, Float32Array};
+use arrow_schema::DataType;
+
+use crate::{Error, Result};
+
+/// Dot product of two int8 vectors.
+#[inline]
+fn dot_i8(x: &[i8], y: &[i8]) -> i64 {
+    x.iter()
+        .zip(y.iter())
+        .map(|(&a, &b)| a as i32 * b as i32)
+        .fold(0_i64, |sum, v| sum + v as i64)
+}
+
+/// Squared L2 distance between two int8 vectors.
+#[inline]
+pub fn l2_distance_i8(x: &[i8], y: &[i8]) -> f32 {
+    x.iter()
+        .zip(y.iter())
+        .map(|(&a, &b)| {
+            let diff = a as i32 - b as i32;
+            diff * diff
+        })
+        .fold(0_i64, |sum, v| sum + v as i64) as f32
+}
+
+/// Cosine distance between two int8 vectors.
+#[inline]
+pub fn cosine_distance_i8(x: &[i8], y: &[i8]) -> f32 {
+    let xy = dot_i8(x, y) as f64;
+    let xx = dot_i8(x, x) as f64;
+    let yy = dot_i8(y, y) as f64;
+    (1.0 - xy / (xx * yy).sqrt()) as f32
+}
+
+/// Negative dot product of two int8 vectors, to present the relative order of dot distance.
+#[inline]
+pub fn dot_distance_i8(x: &[i8], y: &[i8]) -> f32 {
+    -(dot_i8(x, y) as f32)
+}
+
+/// Compute the distances from an int8 vector to a batch of int8 vectors.
+///
+/// Null buffer of `to` is propagated to the returned array.
+pub(crate) fn int8_distance_arrow_batch(
+    from: &dyn Array,
+    to: &FixedSizeListArray,
+    distance: fn(&[i8], &[i8]) -> f32,
+) -> Result<Arc<Float32Array>> {
+    if to.value_type() != DataType::Int8 {
+        return Err(Error::ComputeError(format!(
+            "Cannot compute distances from int8 vector to {} vectors",
+            to.value_type()
+        )));
+    }
+    let dimension = to.value_length() as usize;
+    debug_assert_eq!(from.len(), dimension);
+
+    let from = from.as_primitive::<Int8Type>().values();
+    let dists = to
+        .values()
+        .as_primitive::<Int8Type>()
+        .values()
+        .chunks_exact(dimension)
+        .map(|y| distance(from, y))
+        .collect::<Vec<_>>();
+    Ok(Arc::new(Float32Array::new(
+        dists.into(),
+        to.nulls().cloned(),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::assert_relative_eq;
+    use arrow_array::Int8Array;
+    use lance_arrow::FixedSizeListArrayExt;
+
+    use crate::distance::{cosine_distance, dot_distance, l2_distance, MetricType};
+
+    #[test]
+    fn test_int8_distances() {
+        let x = (-64..64).map(|v| v as i8).collect::<Vec<_>>();
+        let y = (0..128).map(|v| (127 - v) as i8).collect::<Vec<_>>();
+        let x_f32 = x.iter().map(|&v| v as f32).collect::<Vec<_>>();
+        let y_f32 = y.iter().map(|&v| v as f32).collect::<Vec<_>>();
+
+        assert_eq!(l2_distance_i8(&x, &y), l2_distance(&x_f32, &y_f32));
+        assert_eq!(dot_distance_i8(&x, &y), dot_distance(&x_f32, &y_f32));
+        assert_relative_eq!(
+            cosine_distance_i8(&x, &y),
+            cosine_distance(&x_f32, &y_f32),
+            epsilon = 1e-6
+        );
+
+        // The extremes do not overflow.
+        let x = vec![i8::MIN; 1024];
+        let y = vec![i8::MAX; 1024];
+        assert_eq!(l2_distance_i8(&x, &y), 255.0 * 255.0 * 1024.0);
+        assert_eq!(dot_distance_i8(&x, &x), -128.0 * 128.0 * 1024.0);
+    }
+
+    #[test]
+    fn test_int8_arrow_batch() {
+        let from = Int8Array::from(vec![1, 2]);
+        let to = FixedSizeListArray::try_new_from_values(Int8Array::from(vec![1, 2, 3, 4]), 2);
+        let to = to.unwrap();
+        let dists = MetricType::L2.arrow_batch_func()(&from, &to).unwrap();
+        assert_eq!(dists.values(), &[0.0, 8.0]);
+        let dists = MetricType::Dot.arrow_batch_func()(&from, &to).unwrap();
+        assert_eq!(dists.values(), &[-5.0, -11.0]);
+
+        let floats = FixedSizeListArray::try_new_from_values(Float32Array::from(vec![1.0; 4]), 2);
+        assert!(MetricType::L2.arrow_batch_func()(&from, &floats.unwrap()).is_err());
+    }
+}
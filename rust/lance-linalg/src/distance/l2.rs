@@ -29,9 +29,11 @@ use half::{bf16, f16};
 use lance_arrow::{bfloat16::BFloat16Type, ArrowFloatType, FloatArray, FloatToArrayType};
 use num_traits::{AsPrimitive, Float};
 
+use super::int8::{int8_distance_arrow_batch, l2_distance_i8};
 use crate::simd::{
+    avx512,
     f32::{f32x16, f32x8},
-    SIMD,
+    FloatSimd, SIMD,
 };
 use crate::{Error, Result};
 
@@ -71,29 +73,30 @@ pub fn l2_scalar<T: Float + Sum + AddAssign + AsPrimitive<f32>, const LANES: usi
     let x_chunks = from.chunks_exact(LANES);
     let y_chunks = to.chunks_exact(LANES);
 
+    // Accumulate in f32, so that the sums of f16 vectors do not overflow.
     let s = if !x_chunks.remainder().is_empty() {
         x_chunks
             .remainder()
             .iter()
             .zip(y_chunks.remainder())
             .map(|(&x, &y)| {
-                let diff = x - y;
+                let diff: f32 = (x - y).as_();
                 diff * diff
             })
             .sum()
     } else {
-        T::zero()
+        0.0
     };
 
-    let mut sums = [T::zero(); LANES];
+    let mut sums = [0_f32; LANES];
     for (x, y) in x_chunks.zip(y_chunks) {
         for i in 0..LANES {
-            let diff = x[i] - y[i];
+            let diff: f32 = (x[i] - y[i]).as_();
             sums[i] += diff * diff;
         }
     }
 
-    (s + sums.iter().copied().sum()).as_()
+    s + sums.iter().copied().sum::<f32>()
 }
 
 impl L2 for BFloat16Type {
@@ -139,7 +142,10 @@ impl L2 for Float16Type {
 impl L2 for Float32Type {
     #[inline]
     fn l2(x: &[f32], y: &[f32]) -> f32 {
-        l2_scalar::<f32, 32>(x, y)
+        if let Some(d) = avx512::l2_f32(x, y) {
+            return d;
+        }
+        self::f32::l2(x, y)
     }
 
     fn l2_batch<'a>(
@@ -190,6 +196,27 @@ mod f32 {
         let s = x - y;
         (s * s).reduce_sum()
     }
+
+    /// L2 distance with `f32x16`, which maps to AVX2 or NEON registers.
+    #[inline]
+    pub fn l2(x: &[f32], y: &[f32]) -> f32 {
+        debug_assert_eq!(x.len(), y.len());
+        let x_chunks = x.chunks_exact(16);
+        let y_chunks = y.chunks_exact(16);
+        let tail = x_chunks
+            .remainder()
+            .iter()
+            .zip(y_chunks.remainder())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f32>();
+
+        let mut sum16 = f32x16::zeros();
+        x_chunks.zip(y_chunks).for_each(|(x, y)| unsafe {
+            let diff = f32x16::load_unaligned(x.as_ptr()) - f32x16::load_unaligned(y.as_ptr());
+            sum16.multiply_add(diff, diff);
+        });
+        sum16.reduce_sum() + tail
+    }
 }
 
 /// Compute L2 distance between a vector and a batch of vectors.
@@ -262,6 +289,7 @@ pub fn l2_distance_arrow_batch(
         DataType::Float16 => do_l2_distance_arrow_batch::<Float16Type>(from.as_primitive(), to),
         DataType::Float32 => do_l2_distance_arrow_batch::<Float32Type>(from.as_primitive(), to),
         DataType::Float64 => do_l2_distance_arrow_batch::<Float64Type>(from.as_primitive(), to),
+        DataType::Int8 => int8_distance_arrow_batch(from, to, l2_distance_i8),
         _ => Err(Error::ComputeError(format!(
             "Unsupported data type: {}",
             from.data_type()
@@ -313,6 +341,17 @@ mod tests {
         assert_eq!(distances, vec![32.0, 8.0, 0.0, 8.0]);
     }
 
+    #[test]
+    fn test_f32_simd_l2_matches_scalar() {
+        for dim in (1..100).chain([768, 1536]) {
+            let x: Vec<f32> = (0..dim).map(|v| (v % 7) as f32 * 0.25).collect();
+            let y: Vec<f32> = (0..dim).map(|v| (v % 5) as f32 * 0.5 + 1.0).collect();
+            let expected = l2_scalar::<f32, 1>(&x, &y);
+            assert_relative_eq!(self::f32::l2(&x, &y), expected, max_relative = 1e-5);
+            assert_relative_eq!(Float32Type::l2(&x, &y), expected, max_relative = 1e-5);
+        }
+    }
+
     #[test]
     fn test_odd_length_vector() {
         let mat = Float32Array::from_iter((0..5).map(|v| Some(v as f32)));
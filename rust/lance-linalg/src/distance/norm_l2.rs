@@ -130,18 +130,23 @@ impl Normalize<f64> for &[f64] {
 #[inline]
 fn norm_l2_impl<T: Float + Sum + AsPrimitive<f32>, const LANES: usize>(vector: &[T]) -> f32 {
     let chunks = vector.chunks_exact(LANES);
+    // Accumulate in f32, so that the sums of f16 vectors do not overflow.
     let sum = if chunks.remainder().is_empty() {
-        T::zero()
+        0.0
     } else {
-        chunks.remainder().iter().map(|&v| v.powi(2)).sum::<T>()
+        chunks
+            .remainder()
+            .iter()
+            .map(|&v| v.as_().powi(2))
+            .sum::<f32>()
     };
-    let mut sums = [T::zero(); LANES];
+    let mut sums = [0_f32; LANES];
     for chunk in chunks {
         for i in 0..LANES {
-            sums[i] = sums[i].add(chunk[i].powi(2));
+            sums[i] += chunk[i].as_().powi(2);
         }
     }
-    (sum + sums.iter().copied().sum::<T>()).sqrt().as_()
+    (sum + sums.iter().copied().sum::<f32>()).sqrt()
 }
 
 /// Normalize a vector.
@@ -22,6 +22,7 @@ use futures::stream::{self, repeat_with, StreamExt, TryStreamExt};
 use lance_arrow::{ArrowFloatType, FloatArray, FloatToArrayType};
 use log::{info, warn};
 use num_traits::{AsPrimitive, Float, FromPrimitive, Zero};
+use rand::distributions::WeightedIndex;
 use rand::prelude::*;
 use rand::Rng;
 use tracing::instrument;
@@ -29,6 +30,7 @@ use tracing::instrument;
 use crate::kernels::argmin_value_float;
 use crate::{
     distance::{
+        custom::custom_distance_batch,
         dot_distance,
         l2::{l2, l2_distance_batch, L2},
         norm_l2, Cosine, Dot, MetricType,
@@ -39,9 +41,12 @@ use crate::{
 use crate::{Error, Result};
 
 /// KMean initialization method.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum KMeanInit {
+    /// Pick `k` random vectors as the initial centroids.
     Random,
+
+    /// Pick the initial centroids with kmeans++, which spreads them out.
     KMeanPlusPlus,
 }
 
@@ -67,6 +72,14 @@ pub struct KMeansParams<T: ArrowFloatType> {
     /// Centroids to continuous training. If present, it will continuously train
     /// from the given centroids. If None, it will initialize centroids via init method.
     pub centroids: Option<Arc<T::ArrayType>>,
+
+    /// Seed of the random generator, to make the training reproducible.
+    /// If None, the random generator is seeded from the OS.
+    pub seed: Option<u64>,
+
+    /// Max number of threads to compute the memberships.
+    /// If None, the number of CPUs is used.
+    pub num_threads: Option<usize>,
 }
 
 impl<T: ArrowFloatType> Default for KMeansParams<T> {
@@ -78,6 +91,8 @@ impl<T: ArrowFloatType> Default for KMeansParams<T> {
             init: KMeanInit::Random,
             metric_type: MetricType::L2,
             centroids: None,
+            seed: None,
+            num_threads: None,
         }
     }
 }
@@ -100,6 +115,9 @@ where
     pub k: usize,
 
     pub metric_type: MetricType,
+
+    /// Max number of threads to compute the memberships, the number of CPUs by default.
+    pub num_threads: usize,
 }
 
 /// Randomly initialize kmeans centroids.
@@ -128,6 +146,63 @@ where
     Ok(kmeans)
 }
 
+/// Initialize kmeans centroids with kmeans++.
+///
+/// [k-means++: The Advantages of Careful Seeding (SODA' 07)](https://dl.acm.org/doi/10.5555/1283383.1283494)
+///
+/// The first centroid is chosen uniformly at random. Each next centroid is chosen with
+/// a probability proportional to the distance from the vector to its nearest chosen
+/// centroid, which is the squared L2 distance for L2 and dot product.
+fn kmeans_plus_plus_init<T: ArrowFloatType + Dot + Cosine + L2>(
+    data: &T::ArrayType,
+    dimension: usize,
+    k: usize,
+    mut rng: impl Rng,
+    metric_type: MetricType,
+) -> Result<KMeans<T>>
+where
+    T::Native: AsPrimitive<f32>,
+{
+    assert!(data.len() >= k * dimension);
+    let vectors = data.as_slice();
+    let n = data.len() / dimension;
+    let distance = |x: &[T::Native], y: &[T::Native]| match metric_type {
+        MetricType::Cosine => T::cosine(x, y),
+        MetricType::Custom(name) => custom_distance_batch(name, x, y, dimension)[0],
+        _ => T::l2(x, y),
+    };
+
+    let mut chosen = Vec::with_capacity(k);
+    chosen.push(rng.gen_range(0..n));
+    let mut min_distances = vec![f32::MAX; n];
+    while chosen.len() < k {
+        let last = chosen[chosen.len() - 1];
+        let centroid = &vectors[last * dimension..(last + 1) * dimension];
+        for (min_distance, vector) in min_distances
+            .iter_mut()
+            .zip(vectors.chunks_exact(dimension))
+        {
+            *min_distance = min_distance.min(distance(vector, centroid));
+        }
+        // The chosen vectors have zero weights. NaN weights, i.e., of zero vectors
+        // under cosine, are also zero.
+        let next = match WeightedIndex::new(min_distances.iter().map(|d| d.max(0.0) as f64)) {
+            Ok(weights) => weights.sample(&mut rng),
+            // All the vectors are duplicates of the chosen ones.
+            Err(_) => rng.gen_range(0..n),
+        };
+        chosen.push(next);
+    }
+
+    let mut builder: Vec<T::Native> = Vec::with_capacity(k * dimension);
+    for i in chosen {
+        builder.extend(vectors[i * dimension..(i + 1) * dimension].iter());
+    }
+    let mut kmeans = KMeans::empty(k, dimension, metric_type);
+    kmeans.centroids = Arc::new(builder.into());
+    Ok(kmeans)
+}
+
 pub struct KMeanMembership<T: ArrowFloatType + Dot + Cosine + L2>
 where
     T::Native: Float + Zero,
@@ -144,6 +219,8 @@ where
     k: usize,
 
     metric_type: MetricType,
+
+    num_threads: usize,
 }
 
 impl<T: ArrowFloatType + Dot + Cosine + L2> KMeanMembership<T> {
@@ -184,6 +261,7 @@ impl<T: ArrowFloatType + Dot + Cosine + L2> KMeanMembership<T> {
             dimension,
             k: self.k,
             metric_type: self.metric_type,
+            num_threads: self.num_threads,
         })
     }
 
@@ -232,12 +310,13 @@ where
             dimension,
             k,
             metric_type,
+            num_threads: num_cpus::get(),
         }
     }
 
     /// Create a [`KMeans`] with existing centroids.
     /// It is useful for continuing training.
-    fn with_centroids(
+    pub fn with_centroids(
         centroids: Arc<T::ArrayType>,
         k: usize,
         dimension: usize,
@@ -248,6 +327,7 @@ where
             dimension,
             k,
             metric_type,
+            num_threads: num_cpus::get(),
         }
     }
 
@@ -322,21 +402,20 @@ where
         let mut best_kmeans = Self::empty(k, dimension, params.metric_type);
         let mut best_stddev = f32::MAX;
 
-        let rng = rand::rngs::SmallRng::from_entropy();
+        let rng = match params.seed {
+            Some(seed) => rand::rngs::SmallRng::seed_from_u64(seed),
+            None => rand::rngs::SmallRng::from_entropy(),
+        };
         for redo in 1..=params.redos {
             let mut kmeans = if let Some(centroids) = params.centroids.as_ref() {
                 // Use existing centroids.
                 Self::with_centroids(centroids.clone(), k, dimension, params.metric_type)
             } else {
-                match params.init {
-                    KMeanInit::Random => {
-                        Self::init_random(&mat, k, params.metric_type, rng.clone()).await?
-                    }
-                    KMeanInit::KMeanPlusPlus => {
-                        unimplemented!()
-                    }
-                }
+                Self::init(&mat, k, params.metric_type, params.init, rng.clone()).await?
             };
+            if let Some(num_threads) = params.num_threads {
+                kmeans.num_threads = num_threads;
+            }
 
             let mut dist_sum = f64::MAX;
             let mut stddev = f32::MAX;
@@ -351,7 +430,8 @@ where
                 let last_dist_sum = last_membership.distance_sum();
                 stddev = last_membership.hist_stddev();
                 kmeans = last_membership.to_kmeans().await.unwrap();
-                if (dist_sum - last_dist_sum).abs() / last_dist_sum < params.tolerance {
+                // Dot distances are negative, so compare the relative change by magnitude.
+                if (dist_sum - last_dist_sum).abs() / last_dist_sum.abs() < params.tolerance {
                     info!(
                         "KMeans training: converged at iteration {} / {}, redo={}",
                         i, params.max_iters, redo
@@ -370,6 +450,115 @@ where
         Ok(best_kmeans)
     }
 
+    /// Initialize a [`KMeans`] with kmeans++.
+    ///
+    /// Parameters
+    /// - *data*: training data. provided to do samplings.
+    /// - *k*: the number of clusters.
+    /// - *metric_type*: the metric type to calculate distance.
+    /// - *rng*: random generator.
+    pub fn init_kmeans_plus_plus(
+        data: &MatrixView<T>,
+        k: usize,
+        metric_type: MetricType,
+        rng: impl Rng,
+    ) -> Result<Self> {
+        kmeans_plus_plus_init(
+            data.data().as_ref(),
+            data.num_columns(),
+            k,
+            rng,
+            metric_type,
+        )
+    }
+
+    /// Initialize a [`KMeans`] with the given initialization method.
+    pub async fn init(
+        data: &MatrixView<T>,
+        k: usize,
+        metric_type: MetricType,
+        init: KMeanInit,
+        rng: impl Rng,
+    ) -> Result<Self> {
+        match init {
+            KMeanInit::Random => Self::init_random(data, k, metric_type, rng).await,
+            KMeanInit::KMeanPlusPlus => Self::init_kmeans_plus_plus(data, k, metric_type, rng),
+        }
+    }
+
+    /// Split the oversized clusters, until no cluster on `data` is larger than
+    /// `max_skew` times the average cluster size.
+    ///
+    /// The largest cluster is split in two by running 2-means over its vectors, and the
+    /// smallest cluster is dropped to keep `k` clusters, so its vectors move to their
+    /// next nearest centroids. It stops after `k` splits, or when the largest cluster
+    /// can not be split, i.e., all of its vectors are the same.
+    pub async fn split_oversized(
+        &self,
+        data: &MatrixView<T>,
+        max_skew: f64,
+        mut rng: impl Rng,
+    ) -> Result<Self> {
+        const SPLIT_ITERS: usize = 10;
+
+        if max_skew < 1.0 {
+            return Err(ArrowError::InvalidArgumentError(format!(
+                "KMeans: max_skew must be at least 1.0, got {}",
+                max_skew
+            )));
+        }
+        let dimension = self.dimension;
+        let mut kmeans =
+            Self::with_centroids(self.centroids.clone(), self.k, dimension, self.metric_type);
+        for _ in 0..self.k {
+            let membership = kmeans.train_once(data).await;
+            let hist = membership.histogram();
+            let mean = membership.len() as f64 / self.k as f64;
+            let Some((largest, &max_size)) = hist.iter().enumerate().max_by_key(|(_, &c)| c) else {
+                break;
+            };
+            if self.k < 2 || max_size < 2 || max_size as f64 <= max_skew * mean {
+                break;
+            }
+            let (smallest, _) = hist
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| *i != largest)
+                .min_by_key(|(_, &c)| c)
+                .unwrap();
+
+            let members = data
+                .data()
+                .as_slice()
+                .chunks_exact(dimension)
+                .zip(membership.cluster_id_and_distances.iter())
+                .filter(|(_, (cluster_id, _))| *cluster_id as usize == largest)
+                .flat_map(|(vector, _)| vector.iter().copied())
+                .collect::<Vec<_>>();
+            let members = MatrixView::<T>::new(Arc::new(members.into()), dimension);
+            let mut split = Self::init_random(&members, 2, self.metric_type, &mut rng).await?;
+            for _ in 0..SPLIT_ITERS {
+                split = split.train_once(&members).await.to_kmeans().await?;
+            }
+            if split.train_once(&members).await.histogram().contains(&0) {
+                warn!(
+                    "KMeans: can not split cluster {} of {} vectors",
+                    largest, max_size
+                );
+                break;
+            }
+
+            let mut centroids = kmeans.centroids.as_slice().to_vec();
+            let split_centroids = split.centroids.as_slice();
+            centroids[largest * dimension..(largest + 1) * dimension]
+                .copy_from_slice(&split_centroids[..dimension]);
+            centroids[smallest * dimension..(smallest + 1) * dimension]
+                .copy_from_slice(&split_centroids[dimension..]);
+            kmeans.centroids = Arc::new(centroids.into());
+        }
+        Ok(kmeans)
+    }
+
     /// Train for one iteration.
     ///
     /// Parameters
@@ -488,6 +677,15 @@ where
                                     crate::distance::DistanceType::Dot => argmin_value(
                                         centroid_stream.map(|cent| dot_distance(vector, cent)),
                                     ),
+                                    MetricType::Custom(name) => argmin_value(
+                                        custom_distance_batch(
+                                            name,
+                                            vector,
+                                            centroids_array,
+                                            dimension,
+                                        )
+                                        .into_iter(),
+                                    ),
                                 }
                                 .unwrap()
                             })
@@ -503,7 +701,7 @@ where
                     Ok::<Vec<_>, Error>(data)
                 },
             )
-            .buffered(num_cpus::get())
+            .buffered(self.num_threads)
             .try_collect::<Vec<_>>()
             .await
             .unwrap();
@@ -513,10 +711,81 @@ where
             cluster_id_and_distances: cluster_with_distances.iter().flatten().copied().collect(),
             k: self.k,
             metric_type: self.metric_type,
+            num_threads: self.num_threads,
         }
     }
 }
 
+/// Mini-batch KMeans, which updates the centroids with one batch of vectors at a time.
+///
+/// [Web-Scale K-Means Clustering (WWW' 10)](https://dl.acm.org/doi/10.1145/1772690.1772862)
+///
+/// Each vector moves its nearest centroid towards it, with a learning rate of
+/// `1 / n`, where `n` is the number of vectors assigned to that centroid so far.
+/// So the training data does not need to fit in memory, the batches can be streamed.
+#[derive(Debug, Clone)]
+pub struct MiniBatchKMeans<T: ArrowFloatType>
+where
+    T: L2 + Dot + Cosine,
+{
+    kmeans: KMeans<T>,
+
+    /// The number of vectors assigned to each centroid so far.
+    counts: Vec<u64>,
+}
+
+impl<T: ArrowFloatType> MiniBatchKMeans<T>
+where
+    T: L2 + Dot + Cosine,
+    T::Native: AsPrimitive<f32>,
+{
+    /// Start the training from initial centroids, i.e., from [`KMeans::init_random`].
+    pub fn new(kmeans: KMeans<T>) -> Self {
+        let counts = vec![0; kmeans.k];
+        Self { kmeans, counts }
+    }
+
+    /// Update the centroids with one batch of vectors.
+    ///
+    /// Returns the sum of the distances from the vectors to their nearest centroids
+    /// before the update.
+    pub async fn update(&mut self, batch: &MatrixView<T>) -> f64 {
+        let dimension = self.kmeans.dimension;
+        let membership = self.kmeans.train_once(batch).await;
+        let mut centroids = self.kmeans.centroids.as_slice().to_vec();
+        for (vector, (cluster_id, _)) in batch
+            .data()
+            .as_slice()
+            .chunks_exact(dimension)
+            .zip(membership.cluster_id_and_distances.iter())
+        {
+            let cluster_id = *cluster_id as usize;
+            self.counts[cluster_id] += 1;
+            let learning_rate = 1.0 / self.counts[cluster_id] as f32;
+            for (c, &v) in centroids[cluster_id * dimension..(cluster_id + 1) * dimension]
+                .iter_mut()
+                .zip(vector)
+            {
+                let c_f32: f32 = c.as_();
+                let v_f32: f32 = v.as_();
+                *c = T::Native::from_f32(c_f32 + learning_rate * (v_f32 - c_f32)).unwrap();
+            }
+        }
+        self.kmeans.centroids = Arc::new(centroids.into());
+        membership.distance_sum()
+    }
+
+    /// The number of vectors assigned to each centroid so far.
+    pub fn counts(&self) -> &[u64] {
+        &self.counts
+    }
+
+    /// The trained [`KMeans`] model.
+    pub fn into_kmeans(self) -> KMeans<T> {
+        self.kmeans
+    }
+}
+
 /// Return a slice of `data[x,y..y+strip]`.
 #[inline]
 fn get_slice<T: Float>(data: &[T], x: usize, y: usize, dim: usize, strip: usize) -> &[T] {
@@ -661,6 +930,12 @@ where
             .collect(),
         MetricType::Cosine => compute_partitions_cosine(centroids, data, dimension),
         MetricType::Dot => compute_partitions_dot(centroids, data, dimension),
+        MetricType::Custom(name) => data
+            .chunks(dimension)
+            .map(|row| {
+                argmin(custom_distance_batch(name, row, centroids, dimension).into_iter()).unwrap()
+            })
+            .collect(),
     }
 }
 
@@ -685,6 +960,110 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_kmeans_plus_plus_init() {
+        const DIM: usize = 2;
+        // 4 clusters of identical vectors, far from each other.
+        let values = (0..100)
+            .flat_map(|i| {
+                let c = (i % 4) as f32 * 100.0;
+                [c, -c]
+            })
+            .collect::<Vec<_>>();
+        let data = MatrixView::<Float32Type>::new(Arc::new(values.into()), DIM);
+
+        // Once a cluster has a centroid, its vectors have zero probability to be chosen.
+        for seed in 0..10 {
+            let rng = rand::rngs::SmallRng::seed_from_u64(seed);
+            let kmeans = KMeans::init_kmeans_plus_plus(&data, 4, MetricType::L2, rng).unwrap();
+            let mut centroids = kmeans
+                .centroids
+                .values()
+                .chunks(DIM)
+                .map(|c| c[0] as i32)
+                .collect::<Vec<_>>();
+            centroids.sort();
+            assert_eq!(centroids, vec![0, 100, 200, 300]);
+        }
+
+        // More centroids than distinct vectors.
+        let rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let kmeans = KMeans::init_kmeans_plus_plus(&data, 6, MetricType::L2, rng).unwrap();
+        assert_eq!(kmeans.centroids.len(), 6 * DIM);
+    }
+
+    #[tokio::test]
+    async fn test_split_oversized() {
+        const DIM: usize = 2;
+        // 90% of the vectors are in one blob around (0, 0), and the rest are
+        // spread in 3 small blobs.
+        let mut rng = rand::rngs::SmallRng::seed_from_u64(42);
+        let mut values = vec![];
+        for i in 0..1000 {
+            let (x, y) = match i % 10 {
+                7 => (100.0, 0.0),
+                8 => (0.0, 100.0),
+                9 => (100.0, 100.0),
+                _ => (0.0, 0.0),
+            };
+            values.push(x + rng.gen_range(-10.0..10.0_f32));
+            values.push(y + rng.gen_range(-10.0..10.0_f32));
+        }
+        let data = MatrixView::<Float32Type>::new(Arc::new(values.into()), DIM);
+        // Centroids of the 4 blobs.
+        let kmeans = KMeans::<Float32Type>::with_centroids(
+            Arc::new(vec![0.0, 0.0, 100.0, 0.0, 0.0, 100.0, 100.0, 100.0].into()),
+            4,
+            DIM,
+            MetricType::L2,
+        );
+
+        let balanced = kmeans.split_oversized(&data, 2.0, &mut rng).await.unwrap();
+        assert_eq!(balanced.k, 4);
+        let hist = balanced.train_once(&data).await.histogram();
+        assert_eq!(hist.iter().sum::<usize>(), 1000);
+        assert!(hist.iter().all(|&c| c <= 500), "histogram: {:?}", hist);
+
+        assert!(kmeans.split_oversized(&data, 0.5, &mut rng).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_mini_batch_kmeans() {
+        const DIM: usize = 2;
+        // Two well separated clusters around (0, 0) and (100, 100).
+        let values = (0..1000)
+            .flat_map(|i| {
+                let offset = if i % 2 == 0 { 0.0 } else { 100.0 };
+                let noise = (i % 7) as f32 / 7.0;
+                [offset + noise, offset - noise]
+            })
+            .collect::<Vec<_>>();
+        let data = MatrixView::<Float32Type>::new(Arc::new(values.into()), DIM);
+
+        let init = Float32Array::from(vec![1.0, 1.0, 99.0, 99.0]);
+        let kmeans = KMeans::with_centroids(Arc::new(init), 2, DIM, MetricType::L2);
+        let mut mini_batch = MiniBatchKMeans::new(kmeans);
+        for start in (0..data.num_rows()).step_by(100) {
+            let batch = data.data().slice(start * DIM, 100 * DIM);
+            let batch = MatrixView::<Float32Type>::new(Arc::new(batch), DIM);
+            mini_batch.update(&batch).await;
+        }
+        assert_eq!(mini_batch.counts(), &[500, 500]);
+
+        // Each centroid converges to the mean of its cluster.
+        let kmeans = mini_batch.into_kmeans();
+        let expected_noise = (0..7).map(|n| n as f32 / 7.0).sum::<f32>() / 7.0;
+        let expected = [
+            expected_noise,
+            -expected_noise,
+            100.0 + expected_noise,
+            100.0 - expected_noise,
+        ];
+        for (actual, expected) in kmeans.centroids.values().iter().zip(expected) {
+            assert!((actual - expected).abs() < 0.1, "{actual} != {expected}");
+        }
+    }
+
     #[test]
     fn test_compute_partitions() {
         const DIM: usize = 256;
@@ -712,4 +1091,42 @@ mod tests {
         );
         assert_eq!(expected, actual);
     }
+
+    #[tokio::test]
+    async fn test_custom_distance_partitions() {
+        /// Squared distance of the first dimension only.
+        struct FirstDimension;
+
+        impl crate::distance::CustomDistance for FirstDimension {
+            fn distance(&self, x: &[f32], y: &[f32]) -> f32 {
+                (x[0] - y[0]) * (x[0] - y[0])
+            }
+        }
+
+        const DIM: usize = 2;
+        let metric_type = crate::distance::register_distance(
+            "test_kmeans_first_dimension",
+            Arc::new(FirstDimension),
+        )
+        .unwrap();
+        let centroids = Float32Array::from(vec![0.0, 100.0, 100.0, 0.0]);
+        // Closer to the other centroid by L2.
+        let data = Float32Array::from(vec![10.0, 0.0, 90.0, 100.0, 20.0, 10.0]);
+
+        let partitions =
+            compute_partitions::<Float32Type>(centroids.values(), data.values(), DIM, metric_type);
+        assert_eq!(partitions, vec![0, 1, 0]);
+        let l2_partitions = compute_partitions::<Float32Type>(
+            centroids.values(),
+            data.values(),
+            DIM,
+            MetricType::L2,
+        );
+        assert_eq!(l2_partitions, vec![1, 0, 1]);
+
+        let kmeans = KMeans::with_centroids(Arc::new(centroids), 2, DIM, metric_type);
+        let data = MatrixView::<Float32Type>::new(Arc::new(data), DIM);
+        let membership = kmeans.train_once(&data).await;
+        assert_eq!(membership.histogram(), vec![2, 1]);
+    }
 }
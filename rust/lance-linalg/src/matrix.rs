@@ -38,6 +38,18 @@ fn transpose<T: Float>(input: &[T], dimension: usize) -> Vec<T> {
     mat
 }
 
+/// Apply a Jacobi rotation on the columns `p` and `q` of a column-major matrix.
+fn rotate_columns(mat: &mut [f64], num_rows: usize, p: usize, q: usize, c: f64, s: f64) {
+    let (left, right) = mat.split_at_mut(q * num_rows);
+    let col_p = &mut left[p * num_rows..(p + 1) * num_rows];
+    let col_q = &mut right[..num_rows];
+    col_p.iter_mut().zip(col_q.iter_mut()).for_each(|(x, y)| {
+        let (xp, xq) = (*x, *y);
+        *x = c * xp - s * xq;
+        *y = s * xp + c * xq;
+    });
+}
+
 /// A 2-D dense matrix on top of Arrow Arrays.
 ///
 #[derive(Debug)]
@@ -74,7 +86,12 @@ impl<T: ArrowFloatType> MatrixView<T> {
 
     /// Randomly initialize a matrix of shape `(num_rows, num_columns)`.
     pub fn random(num_rows: usize, num_columns: usize) -> Self {
-        let mut rng = SmallRng::from_entropy();
+        let rng = SmallRng::from_entropy();
+        Self::random_with(num_rows, num_columns, rng)
+    }
+
+    /// Randomly initialize a matrix of shape `(num_rows, num_columns)` with a random generator.
+    pub fn random_with(num_rows: usize, num_columns: usize, mut rng: impl Rng) -> Self {
         let data = Arc::new(T::ArrayType::from(
             (&mut rng)
                 .sample_iter(Standard)
@@ -199,62 +216,170 @@ impl<T: ArrowFloatType> MatrixView<T> {
     }
 
     /// Dot multiply
-    #[cfg(feature = "opq")]
     pub fn dot(&self, rhs: &Self) -> Result<Self> {
-        use cblas::{sgemm, Layout, Transpose};
-
-        let m = self.num_rows() as i32;
-        let k = self.num_columns() as i32;
-        let n = rhs.num_columns() as i32;
-        if self.num_columns() != rhs.num_rows() {
-            return Err(Error::Arrow {
-                message: format!(
-                    "MatMul dimension mismatch: A({m}x{k}) * B({}x{n}",
-                    rhs.num_rows()
-                ),
-            });
+        let m = self.num_rows();
+        let k = self.num_columns();
+        let n = rhs.num_columns();
+        if k != rhs.num_rows() {
+            return Err(Error::ComputeError(format!(
+                "MatMul dimension mismatch: A({m}x{k}) * B({}x{n})",
+                rhs.num_rows()
+            )));
         }
 
-        let mut c_builder = Float32Builder::with_capacity((m * n) as usize);
-        unsafe { c_builder.append_trusted_len_iter((0..n * m).map(|_| 0.0)) }
-
-        let (trans_a, lda) = if self.transpose {
-            (Transpose::Ordinary, m)
-        } else {
-            (Transpose::None, k)
-        };
-        let (trans_b, ldb) = if rhs.transpose {
-            (Transpose::Ordinary, k)
-        } else {
-            (Transpose::None, n)
-        };
-        unsafe {
-            sgemm(
-                Layout::RowMajor,
-                trans_a,
-                trans_b,
-                m,
-                n,
-                k,
-                1.0,
-                self.data.values(),
-                lda,
-                rhs.data.values(),
-                ldb,
-                0.0,
-                c_builder.values_slice_mut(),
-                n,
-            )
+        let a = self.data();
+        let b = rhs.data();
+        let a = a.as_slice();
+        let b = b.as_slice();
+        let mut c = vec![T::Native::from_f32(0.0).unwrap(); m * n];
+        // i-k-j order so that the inner loop runs over contiguous rows of B and C,
+        // which can be auto-vectorized.
+        for (a_row, c_row) in a.chunks_exact(k).zip(c.chunks_exact_mut(n)) {
+            for (&a_ik, b_row) in a_row.iter().zip(b.chunks_exact(n)) {
+                c_row
+                    .iter_mut()
+                    .zip(b_row.iter())
+                    .for_each(|(c_ij, &b_kj)| *c_ij += a_ik * b_kj);
+            }
         }
 
-        let data = Arc::new(c_builder.finish());
         Ok(Self {
-            data,
-            num_columns: n as usize,
+            data: Arc::new(c.into()),
+            num_columns: n,
             transpose: false,
         })
     }
 
+    /// Singular Value Decomposition, `A = U * diag(S) * V^T`.
+    ///
+    /// It uses one-sided Jacobi rotations, which is accurate and simple enough for
+    /// the small square matrices, i.e., OPQ rotations, that we need to decompose.
+    ///
+    /// For a `(m, n)` matrix with `m >= n`, it returns `U` of shape `(m, n)`,
+    /// `S` of length `n` and `V^T` of shape `(n, n)`. If the matrix is rank-deficient,
+    /// the columns of `U` are completed to an orthonormal basis.
+    pub fn svd(&self) -> Result<(Self, T::ArrayType, Self)> {
+        const MAX_SWEEPS: usize = 60;
+        const EPSILON: f64 = 1e-12;
+
+        let m = self.num_rows();
+        let n = self.num_columns();
+        if m < n {
+            return Err(Error::ComputeError(format!(
+                "SVD requires num_rows >= num_columns, got ({m}x{n})"
+            )));
+        }
+
+        // Work on the columns of A and V, each of which is contiguous.
+        let mut a = transpose(
+            &self
+                .data()
+                .as_slice()
+                .iter()
+                .map(|v| v.to_f64().unwrap())
+                .collect::<Vec<_>>(),
+            n,
+        );
+        let mut v = vec![0_f64; n * n];
+        for i in 0..n {
+            v[i * n + i] = 1.0;
+        }
+
+        for _ in 0..MAX_SWEEPS {
+            let mut converged = true;
+            for p in 0..n {
+                for q in p + 1..n {
+                    let (alpha, beta, gamma) = a[p * m..(p + 1) * m]
+                        .iter()
+                        .zip(a[q * m..(q + 1) * m].iter())
+                        .fold((0.0, 0.0, 0.0), |(al, be, ga), (&x, &y)| {
+                            (al + x * x, be + y * y, ga + x * y)
+                        });
+                    if gamma.abs() <= EPSILON * (alpha * beta).sqrt() || gamma == 0.0 {
+                        continue;
+                    }
+                    converged = false;
+
+                    let zeta = (beta - alpha) / (2.0 * gamma);
+                    let t = zeta.signum() / (zeta.abs() + (1.0 + zeta * zeta).sqrt());
+                    let c = 1.0 / (1.0 + t * t).sqrt();
+                    let s = c * t;
+                    rotate_columns(&mut a, m, p, q, c, s);
+                    rotate_columns(&mut v, n, p, q, c, s);
+                }
+            }
+            if converged {
+                break;
+            }
+        }
+
+        // Singular values are the norms of the rotated columns.
+        let sigma = (0..n)
+            .map(|j| {
+                a[j * m..(j + 1) * m]
+                    .iter()
+                    .map(|x| x * x)
+                    .sum::<f64>()
+                    .sqrt()
+            })
+            .collect::<Vec<_>>();
+        let max_sigma = sigma.iter().cloned().fold(0.0, f64::max);
+        let mut u = vec![0_f64; n * m];
+        let mut missing = vec![];
+        for j in 0..n {
+            if sigma[j] > EPSILON * max_sigma.max(1.0) {
+                u[j * m..(j + 1) * m]
+                    .iter_mut()
+                    .zip(a[j * m..(j + 1) * m].iter())
+                    .for_each(|(u, x)| *u = x / sigma[j]);
+            } else {
+                missing.push(j);
+            }
+        }
+        // Complete U with Gram-Schmidt over the standard basis.
+        let mut basis = 0;
+        for j in missing {
+            while basis < m {
+                let mut col = vec![0_f64; m];
+                col[basis] = 1.0;
+                basis += 1;
+                for k in 0..n {
+                    if k == j {
+                        continue;
+                    }
+                    let other = &u[k * m..(k + 1) * m];
+                    let proj = col.iter().zip(other).map(|(x, y)| x * y).sum::<f64>();
+                    col.iter_mut().zip(other).for_each(|(x, y)| *x -= proj * y);
+                }
+                let norm = col.iter().map(|x| x * x).sum::<f64>().sqrt();
+                if norm > 1e-6 {
+                    u[j * m..(j + 1) * m]
+                        .iter_mut()
+                        .zip(col.iter())
+                        .for_each(|(u, x)| *u = x / norm);
+                    break;
+                }
+            }
+        }
+
+        let to_native = |values: &[f64]| {
+            values
+                .iter()
+                .map(|&v| T::Native::from_f64(v).unwrap())
+                .collect::<Vec<_>>()
+        };
+        // `u` holds the columns of U, i.e., it is the row-major U^T.
+        let u = Self {
+            data: Arc::new(to_native(&u).into()),
+            num_columns: m,
+            transpose: true,
+        };
+        let u = Self::new(u.data(), n);
+        // Likewise, `v` is the row-major V^T.
+        let vt = Self::new(Arc::new(to_native(&v).into()), n);
+        Ok((u, to_native(&sigma).into(), vt))
+    }
+
     /// Sample `n` rows from the matrix.
     pub fn sample(&self, n: usize) -> Self {
         let rng = SmallRng::from_entropy();
@@ -355,7 +480,6 @@ mod tests {
 
     use arrow_array::Float32Array;
 
-    #[cfg(feature = "opq")]
     use approx::assert_relative_eq;
     use arrow_array::types::{Float32Type, Float64Type};
     use lance_arrow::FixedSizeListArrayExt;
@@ -363,7 +487,6 @@ mod tests {
     use super::*;
 
     #[test]
-    #[cfg(feature = "opq")]
     fn test_matrix_dot() {
         // A[2,3]
         let a_data = Arc::new(Float32Array::from_iter((1..=6).map(|v| v as f32)));
@@ -375,7 +498,7 @@ mod tests {
         ]));
         let b = MatrixView::new(b_data, 2);
 
-        let c = a.dot(&b).unwrap();
+        let c: MatrixView<Float32Type> = a.dot(&b).unwrap();
         let expected = vec![44.0, 50.0, 98.0, 113.0];
         c.data.values().iter().zip(expected).for_each(|(&a, b)| {
             assert_relative_eq!(a, b, epsilon = 0.0001);
@@ -383,17 +506,16 @@ mod tests {
     }
 
     #[test]
-    #[cfg(feature = "opq")]
     fn test_dot_on_transposed_mat() {
         // A[2,3]
         let a_data = Arc::new(Float32Array::from_iter((1..=6).map(|v| v as f32)));
-        let a = MatrixView::<Float32Array>::new(a_data, 3);
+        let a = MatrixView::<Float32Type>::new(a_data, 3);
 
         // B[3,2]
         let b_data = Arc::new(Float32Array::from_iter_values([
             2.0, 3.0, 6.0, 7.0, 10.0, 11.0,
         ]));
-        let b = MatrixView::<Float32Array>::new(b_data, 2);
+        let b = MatrixView::<Float32Type>::new(b_data, 2);
 
         let c_t = b.transpose().dot(&a.transpose()).unwrap();
         let expected = vec![44.0, 98.0, 50.0, 113.0];
@@ -402,6 +524,64 @@ mod tests {
         });
     }
 
+    #[test]
+    fn test_svd() {
+        let data = Arc::new(Float32Array::from_iter_values([
+            4.0, 0.0, 1.0, 3.0, -2.0, 5.0, 0.5, 1.0, 7.0, 2.0, 2.0, -1.0,
+        ]));
+        let a = MatrixView::<Float32Type>::new(data, 3);
+        let (u, s, vt) = a.svd().unwrap();
+        assert_eq!((u.num_rows(), u.num_columns()), (4, 3));
+        assert_eq!(s.len(), 3);
+        assert_eq!((vt.num_rows(), vt.num_columns()), (3, 3));
+
+        // U^T * U = I and V^T * V = I
+        let identity = MatrixView::<Float32Type>::identity(3);
+        for orth in [
+            u.transpose().dot(&u).unwrap(),
+            vt.dot(&vt.transpose()).unwrap(),
+        ] {
+            orth.data()
+                .values()
+                .iter()
+                .zip(identity.data().values())
+                .for_each(|(&a, &b)| assert_relative_eq!(a, b, epsilon = 0.0001));
+        }
+
+        // A = U * diag(S) * V^T
+        let us = u
+            .iter()
+            .flat_map(|row| row.iter().zip(s.values()).map(|(u, s)| u * s))
+            .collect::<Vec<_>>();
+        let us = MatrixView::<Float32Type>::new(Arc::new(us.into()), 3);
+        let reconstructed = us.dot(&vt).unwrap();
+        reconstructed
+            .data()
+            .values()
+            .iter()
+            .zip(a.data().values())
+            .for_each(|(&a, &b)| assert_relative_eq!(a, b, epsilon = 0.001));
+    }
+
+    #[test]
+    fn test_svd_rank_deficient() {
+        // Rank 1 matrix.
+        let data = Arc::new(Float32Array::from_iter_values([
+            1.0, 2.0, 3.0, 2.0, 4.0, 6.0, 3.0, 6.0, 9.0,
+        ]));
+        let a = MatrixView::<Float32Type>::new(data, 3);
+        let (u, _, _) = a.svd().unwrap();
+        let identity = MatrixView::<Float32Type>::identity(3);
+        u.transpose()
+            .dot(&u)
+            .unwrap()
+            .data()
+            .values()
+            .iter()
+            .zip(identity.data().values())
+            .for_each(|(&a, &b)| assert_relative_eq!(a, b, epsilon = 0.0001));
+    }
+
     #[test]
     fn test_sample_matrix() {
         let a_data = Arc::new(Float32Array::from_iter((1..=20).map(|v| v as f32)));
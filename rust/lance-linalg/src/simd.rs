@@ -25,6 +25,7 @@
 
 use std::ops::{Add, AddAssign, Mul, Sub, SubAssign};
 
+pub mod avx512;
 pub mod f32;
 pub mod i32;
 
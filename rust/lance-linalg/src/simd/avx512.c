@@ -0,0 +1,129 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// AVX-512 kernels for f32 vectors.
+//
+// The file is compiled for any x86_64 CPU: each function enables AVX-512 by itself,
+// and must only be called after checking that the CPU supports `avx512f`.
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include <immintrin.h>
+
+#define AVX512 __attribute__((target("avx512f")))
+
+// Mask of the first `n` lanes, `n < 16`.
+AVX512 static inline __mmask16 tail_mask(uint32_t n) {
+  return (__mmask16)((1u << n) - 1);
+}
+
+AVX512 float l2_f32_avx512(const float *x, const float *y, uint32_t len) {
+  __m512 sum1 = _mm512_setzero_ps();
+  __m512 sum2 = _mm512_setzero_ps();
+  uint32_t i = 0;
+  for (; i + 32 <= len; i += 32) {
+    __m512 d1 = _mm512_sub_ps(_mm512_loadu_ps(x + i), _mm512_loadu_ps(y + i));
+    __m512 d2 =
+        _mm512_sub_ps(_mm512_loadu_ps(x + i + 16), _mm512_loadu_ps(y + i + 16));
+    sum1 = _mm512_fmadd_ps(d1, d1, sum1);
+    sum2 = _mm512_fmadd_ps(d2, d2, sum2);
+  }
+  for (; i + 16 <= len; i += 16) {
+    __m512 d = _mm512_sub_ps(_mm512_loadu_ps(x + i), _mm512_loadu_ps(y + i));
+    sum1 = _mm512_fmadd_ps(d, d, sum1);
+  }
+  if (i < len) {
+    __mmask16 mask = tail_mask(len - i);
+    __m512 d = _mm512_sub_ps(_mm512_maskz_loadu_ps(mask, x + i),
+                             _mm512_maskz_loadu_ps(mask, y + i));
+    sum1 = _mm512_fmadd_ps(d, d, sum1);
+  }
+  return _mm512_reduce_add_ps(_mm512_add_ps(sum1, sum2));
+}
+
+AVX512 float dot_f32_avx512(const float *x, const float *y, uint32_t len) {
+  __m512 sum1 = _mm512_setzero_ps();
+  __m512 sum2 = _mm512_setzero_ps();
+  uint32_t i = 0;
+  for (; i + 32 <= len; i += 32) {
+    sum1 = _mm512_fmadd_ps(_mm512_loadu_ps(x + i), _mm512_loadu_ps(y + i), sum1);
+    sum2 = _mm512_fmadd_ps(_mm512_loadu_ps(x + i + 16),
+                           _mm512_loadu_ps(y + i + 16), sum2);
+  }
+  for (; i + 16 <= len; i += 16) {
+    sum1 = _mm512_fmadd_ps(_mm512_loadu_ps(x + i), _mm512_loadu_ps(y + i), sum1);
+  }
+  if (i < len) {
+    __mmask16 mask = tail_mask(len - i);
+    sum1 = _mm512_fmadd_ps(_mm512_maskz_loadu_ps(mask, x + i),
+                           _mm512_maskz_loadu_ps(mask, y + i), sum1);
+  }
+  return _mm512_reduce_add_ps(_mm512_add_ps(sum1, sum2));
+}
+
+// Cosine distance, with the L2 norm of `x` already known.
+AVX512 float cosine_f32_avx512(const float *x, float x_norm, const float *y,
+                               uint32_t len) {
+  __m512 xy = _mm512_setzero_ps();
+  __m512 yy = _mm512_setzero_ps();
+  uint32_t i = 0;
+  for (; i + 16 <= len; i += 16) {
+    __m512 xv = _mm512_loadu_ps(x + i);
+    __m512 yv = _mm512_loadu_ps(y + i);
+    xy = _mm512_fmadd_ps(xv, yv, xy);
+    yy = _mm512_fmadd_ps(yv, yv, yy);
+  }
+  if (i < len) {
+    __mmask16 mask = tail_mask(len - i);
+    __m512 xv = _mm512_maskz_loadu_ps(mask, x + i);
+    __m512 yv = _mm512_maskz_loadu_ps(mask, y + i);
+    xy = _mm512_fmadd_ps(xv, yv, xy);
+    yy = _mm512_fmadd_ps(yv, yv, yy);
+  }
+  float y_norm = __builtin_sqrtf(_mm512_reduce_add_ps(yy));
+  return 1.0f - _mm512_reduce_add_ps(xy) / x_norm / y_norm;
+}
+
+// Sum the entries of a PQ distance table selected by each code.
+//
+// `table` is `num_sub_vectors * num_centroids` floats, and `codes` holds
+// `num_sub_vectors` centroid ids per vector. Writes one sum per vector to `out`.
+AVX512 void table_lookup_sum_avx512(const float *table, uint32_t num_centroids,
+                                    const uint8_t *codes,
+                                    uint32_t num_sub_vectors,
+                                    uint32_t num_vectors, float *out) {
+  const __m512i lanes = _mm512_set_epi32(15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5,
+                                         4, 3, 2, 1, 0);
+  const __m512i stride = _mm512_set1_epi32((int)num_centroids);
+  const __m512i lane_offsets = _mm512_mullo_epi32(lanes, stride);
+  for (uint32_t v = 0; v < num_vectors; v++) {
+    const uint8_t *code = codes + (size_t)v * num_sub_vectors;
+    __m512 sum = _mm512_setzero_ps();
+    uint32_t i = 0;
+    for (; i + 16 <= num_sub_vectors; i += 16) {
+      __m128i c8 = _mm_loadu_si128((const __m128i *)(code + i));
+      __m512i idx = _mm512_add_epi32(
+          _mm512_cvtepu8_epi32(c8),
+          _mm512_add_epi32(lane_offsets,
+                           _mm512_set1_epi32((int)(i * num_centroids))));
+      sum = _mm512_add_ps(sum, _mm512_i32gather_ps(idx, table, 4));
+    }
+    float total = _mm512_reduce_add_ps(sum);
+    for (; i < num_sub_vectors; i++) {
+      total += table[(size_t)i * num_centroids + code[i]];
+    }
+    out[v] = total;
+  }
+}
@@ -0,0 +1,197 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! AVX-512 kernels, dispatched at runtime.
+//!
+//! The kernels are written in C (`avx512.c`) and built for every x86_64 target,
+//! so a single binary can use AVX-512 on the CPUs that have it. Each function
+//! returns `None` when the running CPU does not support `avx512f`, and the caller
+//! falls back to its portable SIMD path.
+
+#[cfg(all(target_arch = "x86_64", not(target_env = "msvc")))]
+mod kernel {
+    extern "C" {
+        pub fn l2_f32_avx512(x: *const f32, y: *const f32, len: u32) -> f32;
+        pub fn dot_f32_avx512(x: *const f32, y: *const f32, len: u32) -> f32;
+        pub fn cosine_f32_avx512(x: *const f32, x_norm: f32, y: *const f32, len: u32) -> f32;
+        pub fn table_lookup_sum_avx512(
+            table: *const f32,
+            num_centroids: u32,
+            codes: *const u8,
+            num_sub_vectors: u32,
+            num_vectors: u32,
+            out: *mut f32,
+        );
+    }
+}
+
+/// Whether the running CPU can execute the AVX-512 kernels.
+#[inline]
+pub fn is_supported() -> bool {
+    #[cfg(all(target_arch = "x86_64", not(target_env = "msvc")))]
+    {
+        is_x86_feature_detected!("avx512f")
+    }
+    #[cfg(not(all(target_arch = "x86_64", not(target_env = "msvc"))))]
+    {
+        false
+    }
+}
+
+/// L2 distance between `x` and `y`.
+#[inline]
+pub fn l2_f32(x: &[f32], y: &[f32]) -> Option<f32> {
+    debug_assert_eq!(x.len(), y.len());
+    #[cfg(all(target_arch = "x86_64", not(target_env = "msvc")))]
+    if is_supported() {
+        return Some(unsafe { kernel::l2_f32_avx512(x.as_ptr(), y.as_ptr(), x.len() as u32) });
+    }
+    None
+}
+
+/// Dot product of `x` and `y`.
+#[inline]
+pub fn dot_f32(x: &[f32], y: &[f32]) -> Option<f32> {
+    debug_assert_eq!(x.len(), y.len());
+    #[cfg(all(target_arch = "x86_64", not(target_env = "msvc")))]
+    if is_supported() {
+        return Some(unsafe { kernel::dot_f32_avx512(x.as_ptr(), y.as_ptr(), x.len() as u32) });
+    }
+    None
+}
+
+/// Cosine distance between `x` and `y`, with the L2 norm of `x` precomputed.
+#[inline]
+pub fn cosine_f32(x: &[f32], x_norm: f32, y: &[f32]) -> Option<f32> {
+    debug_assert_eq!(x.len(), y.len());
+    #[cfg(all(target_arch = "x86_64", not(target_env = "msvc")))]
+    if is_supported() {
+        return Some(unsafe {
+            kernel::cosine_f32_avx512(x.as_ptr(), x_norm, y.as_ptr(), x.len() as u32)
+        });
+    }
+    None
+}
+
+/// For each code in `codes`, sum the table entries it selects.
+///
+/// `table` is laid out as `[num_sub_vectors * num_centroids]`, and `codes` holds
+/// `num_sub_vectors` centroid ids per vector.
+pub fn table_lookup_sum(
+    table: &[f32],
+    num_centroids: usize,
+    codes: &[u8],
+    num_sub_vectors: usize,
+) -> Option<Vec<f32>> {
+    debug_assert!(num_centroids <= 256);
+    debug_assert!(table.len() >= num_sub_vectors * num_centroids);
+    debug_assert_eq!(codes.len() % num_sub_vectors, 0);
+    #[cfg(all(target_arch = "x86_64", not(target_env = "msvc")))]
+    if is_supported() {
+        let num_vectors = codes.len() / num_sub_vectors;
+        let mut out = vec![0_f32; num_vectors];
+        unsafe {
+            kernel::table_lookup_sum_avx512(
+                table.as_ptr(),
+                num_centroids as u32,
+                codes.as_ptr(),
+                num_sub_vectors as u32,
+                num_vectors as u32,
+                out.as_mut_ptr(),
+            );
+        }
+        return Some(out);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::assert_relative_eq;
+
+    fn scalar_l2(x: &[f32], y: &[f32]) -> f32 {
+        x.iter().zip(y).map(|(a, b)| (a - b) * (a - b)).sum()
+    }
+
+    fn scalar_dot(x: &[f32], y: &[f32]) -> f32 {
+        x.iter().zip(y).map(|(a, b)| a * b).sum()
+    }
+
+    #[test]
+    fn test_avx512_kernels_match_scalar() {
+        if !is_supported() {
+            return;
+        }
+        for dim in (1..100).chain([768, 1536]) {
+            let x: Vec<f32> = (0..dim).map(|v| (v % 7) as f32 * 0.25).collect();
+            let y: Vec<f32> = (0..dim).map(|v| (v % 5) as f32 * 0.5 + 1.0).collect();
+            assert_relative_eq!(
+                l2_f32(&x, &y).unwrap(),
+                scalar_l2(&x, &y),
+                max_relative = 1e-5
+            );
+            assert_relative_eq!(
+                dot_f32(&x, &y).unwrap(),
+                scalar_dot(&x, &y),
+                max_relative = 1e-5
+            );
+
+            let x_norm = scalar_dot(&x, &x).sqrt();
+            let y_norm = scalar_dot(&y, &y).sqrt();
+            let expected = if x_norm == 0.0 {
+                f32::NAN
+            } else {
+                1.0 - scalar_dot(&x, &y) / x_norm / y_norm
+            };
+            let actual = cosine_f32(&x, x_norm, &y).unwrap();
+            if expected.is_nan() {
+                assert!(actual.is_nan());
+            } else {
+                assert_relative_eq!(actual, expected, epsilon = 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn test_avx512_table_lookup_sum() {
+        if !is_supported() {
+            return;
+        }
+        for (num_sub_vectors, num_centroids) in [(1, 16), (8, 256), (16, 256), (20, 256), (35, 16)]
+        {
+            let table: Vec<f32> = (0..num_sub_vectors * num_centroids)
+                .map(|v| v as f32 * 0.5)
+                .collect();
+            let codes: Vec<u8> = (0..num_sub_vectors * 10)
+                .map(|v| ((v * 31) % num_centroids) as u8)
+                .collect();
+            let expected: Vec<f32> = codes
+                .chunks_exact(num_sub_vectors)
+                .map(|c| {
+                    c.iter()
+                        .enumerate()
+                        .map(|(i, &code)| table[i * num_centroids + code as usize])
+                        .sum()
+                })
+                .collect();
+            let actual = table_lookup_sum(&table, num_centroids, &codes, num_sub_vectors).unwrap();
+            assert_eq!(actual.len(), expected.len());
+            for (a, e) in actual.iter().zip(expected.iter()) {
+                assert_relative_eq!(a, e, max_relative = 1e-5);
+            }
+        }
+    }
+}
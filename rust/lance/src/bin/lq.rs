@@ -189,16 +189,6 @@ async fn create_index(
             });
         }
     };
-    #[cfg(not(feature = "opq"))]
-    match use_opq {
-        false => (),
-        true => {
-            return Err(Error::Index {
-                message: "Feature 'opq' not installed.".to_string(),
-                location: location!(),
-            });
-        }
-    };
     dataset
         .create_index(
             &[col],
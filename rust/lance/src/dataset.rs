@@ -19,24 +19,35 @@ use arrow_array::cast::AsArray;
 use arrow_array::types::UInt64Type;
 use arrow_array::Array;
 use arrow_array::{
-    cast::as_struct_array, RecordBatch, RecordBatchReader, StructArray, UInt64Array,
+    cast::as_struct_array, FixedSizeListArray, RecordBatch, RecordBatchReader, StructArray,
+    UInt64Array,
 };
-use arrow_schema::{Field as ArrowField, Schema as ArrowSchema};
+use arrow_ord::sort::sort_to_indices;
+use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema, SchemaRef, SortOptions};
 use arrow_select::interleave::interleave;
-use arrow_select::{concat::concat_batches, take::take};
+use arrow_select::{
+    concat::{concat, concat_batches},
+    take::take,
+};
 use chrono::{prelude::*, Duration};
 use datafusion::error::DataFusionError;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::SendableRecordBatchStream;
 use futures::future::BoxFuture;
 use futures::stream::{self, StreamExt, TryStreamExt};
 use futures::{Future, FutureExt, Stream};
 use lance_core::io::{
-    commit::CommitError,
+    commit::{CommitConfig, CommitError},
+    deletion::deletion_file_path,
     object_store::{ObjectStore, ObjectStoreParams},
     read_metadata_offset, read_struct,
     reader::{read_manifest, read_manifest_indexes},
     write_manifest, ObjectWriter, WriteExt,
 };
+use lance_core::ROW_ID_FIELD;
+use lance_index::vector::{Query, DIST_COL};
+use lance_index::IndexType;
+use lance_linalg::distance::MetricType;
 use log::warn;
 use object_store::path::Path;
 use snafu::{location, Location};
@@ -47,39 +58,77 @@ use std::pin::Pin;
 use std::sync::Arc;
 use tracing::instrument;
 
+pub mod blob;
+pub mod branch;
 pub mod builder;
+pub mod changefeed;
 pub mod cleanup;
+mod constraints;
+pub mod diff;
+pub mod export;
 mod feature_flags;
 pub mod fragment;
 mod hash_joiner;
+pub mod history;
+pub mod import;
 pub mod index;
+pub mod merge_insert;
+pub mod migration;
 pub mod optimize;
+mod partition;
 pub mod progress;
+pub mod refs;
+mod rowids;
 pub mod scanner;
+pub mod schema_evolution;
+pub mod stats;
 pub mod transaction;
+pub mod union;
+mod update;
 pub mod updater;
 mod write;
+pub mod writer;
+mod zone_map;
 
+use self::blob::BlobFile;
+use self::branch::BranchCommitHandler;
 use self::builder::DatasetBuilder;
+use self::changefeed::ChangeStream;
 use self::cleanup::RemovalStats;
-use self::feature_flags::{apply_feature_flags, can_read_dataset, can_write_dataset};
+use self::constraints::UniqueKeyValues;
+use self::diff::DatasetDiff;
+use self::feature_flags::{
+    apply_feature_flags, can_read_dataset, can_write_dataset, has_stable_row_ids,
+};
 use self::fragment::FileFragment;
+use self::history::TransactionRecord;
+use self::merge_insert::{MergeInsertParams, MergeInsertStats};
+use self::migration::MigrationReport;
+use self::optimize::{CompactionMetrics, CompactionOptions};
+use self::partition::append_partition_columns;
+use self::refs::Tags;
+use self::rowids::RowIdIndex;
 use self::scanner::{DatasetRecordBatchStream, Scanner};
+use self::schema_evolution::{ColumnAlteration, NewColumnTransform};
+use self::stats::DatasetStatistics;
 use self::transaction::{Operation, Transaction};
 use self::write::{reader_to_stream, write_fragments_internal};
 use crate::dataset::index::unindexed_fragments;
 use crate::datatypes::Schema;
 use crate::error::box_error;
-use crate::format::{Fragment, Index, Manifest};
-use crate::index::DatasetIndexInternalExt;
+use crate::format::{DataFile, Fragment, Index, Manifest};
+use crate::index::{prefilter::PreFilter, DatasetIndexInternalExt, IndexDescription};
 use crate::io::commit::{commit_new_dataset, commit_transaction};
-use crate::session::Session;
+use crate::session::{IndexCacheConfig, IndexCacheStats, Session};
 
 use crate::utils::temporal::{timestamp_to_nanos, utc_now, SystemTime};
 use crate::{Error, Result};
 use hash_joiner::HashJoiner;
 pub use lance_core::ROW_ID;
 pub use write::{write_fragments, WriteMode, WriteParams};
+pub use writer::DatasetWriter;
+
+pub(crate) use self::rowids::IndexRowIds;
 
 const INDICES_DIR: &str = "_indices";
 
@@ -94,6 +143,10 @@ pub struct Dataset {
     pub(crate) base: Path,
     pub(crate) manifest: Arc<Manifest>,
     pub(crate) session: Arc<Session>,
+    /// The branch this dataset is checked out on, if any.
+    pub(crate) branch: Option<Arc<BranchCommitHandler>>,
+    /// How the commits of this dataset are retried after a concurrent commit.
+    pub(crate) commit_config: CommitConfig,
 }
 
 /// Dataset Version
@@ -129,12 +182,23 @@ pub struct ReadParams {
     /// cache is disabled.
     pub metadata_cache_size: usize,
 
+    /// Bound the index cache by the memory held by the cached indices, in bytes,
+    /// instead of by the number of entries.
+    pub index_cache_max_bytes: Option<usize>,
+
+    /// Evict a cached index this long after it is loaded.
+    pub index_cache_ttl: Option<std::time::Duration>,
+
     /// If present, dataset will use this shared [`Session`] instead creating a new one.
     ///
     /// This is useful for sharing the same session across multiple datasets.
     pub session: Option<Arc<Session>>,
 
     pub store_options: Option<ObjectStoreParams>,
+
+    /// The number of times to retry the commits of the dataset, e.g., of a delete,
+    /// after a concurrent commit. See [`WriteParams::commit_retries`].
+    pub commit_retries: u32,
 }
 
 impl ReadParams {
@@ -150,11 +214,38 @@ impl ReadParams {
         self
     }
 
+    /// Bound the index cache by the memory held by the cached indices, in bytes.
+    pub fn index_cache_max_bytes(&mut self, max_bytes: usize) -> &mut Self {
+        self.index_cache_max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Evict a cached index `ttl` after it is loaded.
+    pub fn index_cache_ttl(&mut self, ttl: std::time::Duration) -> &mut Self {
+        self.index_cache_ttl = Some(ttl);
+        self
+    }
+
     /// Set a shared session for the datasets.
     pub fn session(&mut self, session: Arc<Session>) -> &mut Self {
         self.session = Some(session);
         self
     }
+
+    /// Set the number of times to retry a commit after a concurrent commit.
+    pub fn commit_retries(&mut self, num_retries: u32) -> &mut Self {
+        self.commit_retries = num_retries;
+        self
+    }
+
+    /// The configuration of the index cache of a new session.
+    pub fn index_cache_config(&self) -> IndexCacheConfig {
+        IndexCacheConfig {
+            max_entries: self.index_cache_size,
+            max_bytes: self.index_cache_max_bytes,
+            time_to_live: self.index_cache_ttl,
+        }
+    }
 }
 
 impl Default for ReadParams {
@@ -162,8 +253,11 @@ impl Default for ReadParams {
         Self {
             index_cache_size: DEFAULT_INDEX_CACHE_SIZE,
             metadata_cache_size: DEFAULT_METADATA_CACHE_SIZE,
+            index_cache_max_bytes: None,
+            index_cache_ttl: None,
             session: None,
             store_options: None,
+            commit_retries: CommitConfig::default().num_retries,
         }
     }
 }
@@ -207,19 +301,23 @@ impl Dataset {
         let session = if let Some(session) = params.session.as_ref() {
             session.clone()
         } else {
-            Arc::new(Session::new(
-                params.index_cache_size,
+            Arc::new(Session::with_index_cache_config(
+                &params.index_cache_config(),
                 params.metadata_cache_size,
             ))
         };
 
-        Self::checkout_manifest(
+        let mut dataset = Self::checkout_manifest(
             Arc::new(object_store),
             base_path.clone(),
             &latest_manifest,
             session,
         )
-        .await
+        .await?;
+        dataset.commit_config = CommitConfig {
+            num_retries: params.commit_retries,
+        };
+        Ok(dataset)
     }
 
     /// Check out a version of the dataset.
@@ -251,29 +349,281 @@ impl Dataset {
         let session = if let Some(session) = params.session.as_ref() {
             session.clone()
         } else {
-            Arc::new(Session::new(
-                params.index_cache_size,
+            Arc::new(Session::with_index_cache_config(
+                &params.index_cache_config(),
                 params.metadata_cache_size,
             ))
         };
-        Self::checkout_manifest(Arc::new(object_store), base_path, &manifest_file, session).await
+        let mut dataset =
+            Self::checkout_manifest(Arc::new(object_store), base_path, &manifest_file, session)
+                .await?;
+        dataset.commit_config = CommitConfig {
+            num_retries: params.commit_retries,
+        };
+        Ok(dataset)
     }
 
     /// Check out the specified version of this dataset
     pub async fn checkout_version(&self, version: u64) -> Result<Self> {
-        let base_path = self.base.clone();
-        let manifest_file = self
-            .object_store
+        self.checkout_lineage_version(self.branch.clone(), version)
+            .await
+    }
+
+    /// Check out the version of the main lineage of this dataset pointed to by a tag.
+    pub async fn checkout_tag(&self, tag: &str) -> Result<Self> {
+        let version = self.tags().get(tag).await?.version;
+        self.checkout_lineage_version(None, version).await
+    }
+
+    /// The name of the branch this dataset is checked out on, or `None` for the
+    /// main lineage.
+    pub fn branch(&self) -> Option<&str> {
+        self.branch.as_ref().map(|branch| branch.name())
+    }
+
+    /// List the branches of this dataset.
+    pub async fn list_branches(&self) -> Result<Vec<String>> {
+        branch::list_branches(&self.object_store, &self.base).await
+    }
+
+    /// Create a branch starting at `version` of the lineage this dataset is
+    /// checked out on, and check it out.
+    ///
+    /// The branch shares the data files of the dataset, and the versions committed
+    /// to it do not affect the other branches or the main lineage.
+    pub async fn create_branch(&self, name: &str, version: u64) -> Result<Self> {
+        refs::check_valid_name("branch", name)?;
+        if self
+            .list_branches()
+            .await?
+            .iter()
+            .any(|branch| branch == name)
+        {
+            return Err(Error::invalid_input(
+                format!("Branch {} already exists", name),
+                location!(),
+            ));
+        }
+        let source = self.checkout_version(version).await?;
+        let indices = source.load_indices().await?;
+        let mut manifest = source.manifest.as_ref().clone();
+        let (object_store, _) = self.lineage_object_store(Some(name));
+        write_manifest_file(
+            &object_store,
+            &self.base,
+            &mut manifest,
+            Some(indices),
+            &ManifestWriteConfig::default(),
+        )
+        .await?;
+        self.checkout_branch(name).await
+    }
+
+    /// Create a shallow clone of this version of the dataset at `target_uri`, which must be
+    /// in the same object store.
+    ///
+    /// The clone references the data files of this dataset by their absolute path instead of
+    /// copying them, so it is cheap to create. Only the deletion files, the index files and
+    /// the transaction file, which are small, are copied. The clone can be modified without
+    /// affecting this dataset, but the versions of this dataset it references must not be
+    /// removed with [`Self::cleanup_old_versions`] while the clone is used.
+    pub async fn shallow_clone(&self, target_uri: &str) -> Result<Self> {
+        let (target_store, target_base) = ObjectStore::from_uri(target_uri).await?;
+        if target_store.to_string() != self.object_store.to_string()
+            || target_store.inner.to_string() != self.object_store.inner.to_string()
+        {
+            return Err(Error::invalid_input(
+                format!(
+                    "Cannot shallow clone the dataset to {}: it is not in the same object store",
+                    target_uri
+                ),
+                location!(),
+            ));
+        }
+        match target_store
             .commit_handler
-            .resolve_version(&base_path, version, &self.object_store.inner)
+            .resolve_latest_version(&target_base, &target_store.inner)
+            .await
+        {
+            Ok(_) => {
+                return Err(Error::DatasetAlreadyExists {
+                    uri: target_uri.to_string(),
+                    location: location!(),
+                })
+            }
+            Err(Error::NotFound { .. }) => {}
+            Err(e) => return Err(e),
+        }
+
+        let mut manifest = self.manifest.as_ref().clone();
+        let mut copies = Vec::new();
+        let mut fragments = manifest.fragments.as_ref().clone();
+        for fragment in fragments.iter_mut() {
+            for data_file in fragment.files.iter_mut() {
+                data_file.path = format!("/{}", self.data_file_path(data_file));
+            }
+            if let Some(deletion_file) = &fragment.deletion_file {
+                copies.push((
+                    deletion_file_path(&self.base, fragment.id, deletion_file),
+                    deletion_file_path(&target_base, fragment.id, deletion_file),
+                ));
+            }
+        }
+        manifest.fragments = Arc::new(fragments);
+
+        let indices = self.load_indices().await?;
+        for index in indices.iter() {
+            let index_dir = self.indices_dir().child(index.uuid.to_string());
+            let target_dir = target_base.child(INDICES_DIR).child(index.uuid.to_string());
+            let mut files = self.object_store.read_dir_all(&index_dir, None).await?;
+            while let Some(file) = files.try_next().await? {
+                if let Some(parts) = file.location.prefix_match(&index_dir) {
+                    copies.push((
+                        file.location.clone(),
+                        Path::from_iter(target_dir.parts().chain(parts)),
+                    ));
+                }
+            }
+        }
+        if let Some(transaction_file) = &manifest.transaction_file {
+            copies.push((
+                self.base
+                    .child("_transactions")
+                    .child(transaction_file.as_str()),
+                target_base
+                    .child("_transactions")
+                    .child(transaction_file.as_str()),
+            ));
+        }
+        stream::iter(copies)
+            .map(|(from, to)| async move { self.object_store.copy(&from, &to).await })
+            .buffer_unordered(num_cpus::get() * 4)
+            .try_collect::<Vec<_>>()
             .await?;
-        Self::checkout_manifest(
-            self.object_store.clone(),
-            base_path,
+
+        write_manifest_file(
+            &target_store,
+            &target_base,
+            &mut manifest,
+            Some(indices),
+            &ManifestWriteConfig::default(),
+        )
+        .await?;
+        DatasetBuilder::from_uri(target_uri).load().await
+    }
+
+    /// Check out the latest version of a branch.
+    pub async fn checkout_branch(&self, name: &str) -> Result<Self> {
+        refs::check_valid_name("branch", name)?;
+        if !self
+            .list_branches()
+            .await?
+            .iter()
+            .any(|branch| branch == name)
+        {
+            return Err(Error::NotFound {
+                uri: branch::branch_base(&self.base, name).to_string(),
+                location: location!(),
+            });
+        }
+        let (object_store, branch) = self.lineage_object_store(Some(name));
+        let manifest_file = object_store
+            .commit_handler
+            .resolve_latest_version(&self.base, &object_store.inner)
+            .await?;
+        let mut dataset = Self::checkout_manifest(
+            object_store,
+            self.base.clone(),
             &manifest_file,
             self.session.clone(),
         )
-        .await
+        .await?;
+        dataset.branch = branch;
+        dataset.commit_config = self.commit_config.clone();
+        Ok(dataset)
+    }
+
+    /// Delete a branch and its manifests. The files it shares with other branches
+    /// are left untouched, and the files only it references are removed by the
+    /// next [`Self::cleanup_old_versions`].
+    pub async fn delete_branch(&self, name: &str) -> Result<()> {
+        refs::check_valid_name("branch", name)?;
+        if !self
+            .list_branches()
+            .await?
+            .iter()
+            .any(|branch| branch == name)
+        {
+            return Err(Error::NotFound {
+                uri: branch::branch_base(&self.base, name).to_string(),
+                location: location!(),
+            });
+        }
+        self.object_store
+            .remove_dir_all(branch::branch_base(&self.base, name))
+            .await
+    }
+
+    /// The object store resolving the manifests of a branch, or of the main
+    /// lineage if `branch` is `None`.
+    fn lineage_object_store(
+        &self,
+        branch: Option<&str>,
+    ) -> (Arc<ObjectStore>, Option<Arc<BranchCommitHandler>>) {
+        let root = match &self.branch {
+            Some(current) => current.root().clone(),
+            None => self.object_store.commit_handler.clone(),
+        };
+        let mut object_store = self.object_store.as_ref().clone();
+        let branch = branch.map(|name| Arc::new(BranchCommitHandler::new(root.clone(), name)));
+        object_store.commit_handler = match &branch {
+            Some(branch) => branch.clone(),
+            None => root,
+        };
+        (Arc::new(object_store), branch)
+    }
+
+    async fn checkout_lineage_version(
+        &self,
+        branch: Option<Arc<BranchCommitHandler>>,
+        version: u64,
+    ) -> Result<Self> {
+        let (object_store, branch) =
+            self.lineage_object_store(branch.as_ref().map(|branch| branch.name()));
+        let manifest_file = object_store
+            .commit_handler
+            .resolve_version(&self.base, version, &object_store.inner)
+            .await?;
+        let mut dataset = Self::checkout_manifest(
+            object_store,
+            self.base.clone(),
+            &manifest_file,
+            self.session.clone(),
+        )
+        .await?;
+        dataset.branch = branch;
+        dataset.commit_config = self.commit_config.clone();
+        Ok(dataset)
+    }
+
+    /// Check out the version of this dataset as of the given timestamp: the latest
+    /// version committed at or before `timestamp`.
+    pub async fn checkout_as_of(&self, timestamp: DateTime<Utc>) -> Result<Self> {
+        let versions = self.versions().await?;
+        let Some(version) = versions
+            .iter()
+            .rev()
+            .find(|version| version.timestamp <= timestamp)
+        else {
+            return Err(Error::invalid_input(
+                format!(
+                    "No version of dataset {} was committed at or before {}",
+                    self.base, timestamp
+                ),
+                location!(),
+            ));
+        };
+        self.checkout_version(version.version).await
     }
 
     async fn checkout_manifest(
@@ -331,6 +681,8 @@ impl Dataset {
             base: base_path,
             manifest: Arc::new(manifest),
             session,
+            branch: None,
+            commit_config: CommitConfig::default(),
         })
     }
 
@@ -339,6 +691,17 @@ impl Dataset {
         batches: Box<dyn RecordBatchReader + Send>,
         uri: &str,
         params: Option<WriteParams>,
+    ) -> Result<Self> {
+        let (stream, schema) = reader_to_stream(batches)?;
+        Self::write_stream(stream, schema, uri, params).await
+    }
+
+    /// Write a stream of batches with the given schema, see [`Self::write`].
+    pub(crate) async fn write_stream(
+        mut stream: SendableRecordBatchStream,
+        mut schema: Schema,
+        uri: &str,
+        params: Option<WriteParams>,
     ) -> Result<Self> {
         let mut params = params.unwrap_or_default();
 
@@ -357,8 +720,6 @@ impl Dataset {
             Err(e) => return Err(e),
         };
 
-        let (stream, schema) = reader_to_stream(batches)?;
-
         // Running checks for the different write modes
         // create + dataset already exists = error
         if dataset_exists && matches!(params.mode, WriteMode::Create) {
@@ -397,15 +758,14 @@ impl Dataset {
         };
 
         // append + input schema different from existing schema = error
+        let mut unique_key_values = None;
+        let mut partition_columns = params.partition_columns.clone();
         if matches!(params.mode, WriteMode::Append) {
             if let Some(d) = dataset.as_ref() {
                 let m = d.manifest.as_ref();
-                if schema != m.schema {
-                    return Err(Error::SchemaMismatch {
-                        // original: m.schema.clone(),
-                        // new: schema,
-                    });
-                }
+                schema = append_schema(&schema, &m.schema)?;
+                (stream, unique_key_values) = UniqueKeyValues::track(&m.schema, stream);
+                partition_columns = append_partition_columns(m, &params.partition_columns)?;
             }
         }
 
@@ -424,32 +784,53 @@ impl Dataset {
         }
 
         let object_store = Arc::new(object_store);
-        let fragments =
-            write_fragments_internal(object_store.clone(), &base, &schema, stream, params.clone())
-                .await?;
+        let fragments = write_fragments_internal(
+            object_store.clone(),
+            &base,
+            &schema,
+            stream,
+            WriteParams {
+                partition_columns: partition_columns.clone(),
+                ..params.clone()
+            },
+        )
+        .await?;
+        if let (Some(values), Some(d)) = (&unique_key_values, dataset.as_ref()) {
+            values.check(d).await?;
+        }
 
         let operation = match params.mode {
             WriteMode::Create | WriteMode::Overwrite => Operation::Overwrite { schema, fragments },
             WriteMode::Append => Operation::Append { fragments },
         };
 
-        let transaction = Transaction::new(
+        let mut transaction = Transaction::new(
             dataset.as_ref().map(|ds| ds.manifest.version).unwrap_or(0),
             operation,
             None,
         );
+        transaction.properties = params.commit_properties.clone();
+        transaction.config_upserts = params.config_upserts.clone();
 
+        let write_config = ManifestWriteConfig {
+            use_stable_row_ids: params.enable_stable_row_ids,
+            partition_columns,
+            ..Default::default()
+        };
+        let commit_config = CommitConfig {
+            num_retries: params.commit_retries,
+        };
         let manifest = if let Some(dataset) = &dataset {
             commit_transaction(
                 dataset,
                 &object_store,
                 &transaction,
-                &Default::default(),
-                &Default::default(),
+                &write_config,
+                &commit_config,
             )
             .await?
         } else {
-            commit_new_dataset(&object_store, &base, &transaction, &Default::default()).await?
+            commit_new_dataset(&object_store, &base, &transaction, &write_config).await?
         };
 
         Ok(Self {
@@ -457,6 +838,8 @@ impl Dataset {
             base,
             manifest: Arc::new(manifest.clone()),
             session: Arc::new(Session::default()),
+            branch: None,
+            commit_config,
         })
     }
 
@@ -481,10 +864,29 @@ impl Dataset {
         batches: Box<dyn RecordBatchReader + Send>,
         params: Option<WriteParams>,
     ) -> Result<()> {
-        // Force append mode
+        let (stream, schema) = reader_to_stream(batches)?;
+        self.append_stream(stream, schema, params).await
+    }
+
+    /// Append a stream of batches with the given schema, see [`Self::append`].
+    pub(crate) async fn append_stream(
+        &mut self,
+        stream: SendableRecordBatchStream,
+        schema: Schema,
+        params: Option<WriteParams>,
+    ) -> Result<()> {
+        // Force append mode, and partition the data like the dataset
+        let commit_config = match &params {
+            Some(params) => CommitConfig {
+                num_retries: params.commit_retries,
+            },
+            None => self.commit_config.clone(),
+        };
+        let params = params.unwrap_or_default();
         let params = WriteParams {
             mode: WriteMode::Append,
-            ..params.unwrap_or_default()
+            partition_columns: append_partition_columns(&self.manifest, &params.partition_columns)?,
+            ..params
         };
 
         // Need to include params here because it might include a commit mechanism.
@@ -493,15 +895,9 @@ impl Dataset {
                 .with_params(&params.store_params.clone().unwrap_or_default()),
         );
 
-        let (stream, schema) = reader_to_stream(batches)?;
-
         // Return Error if append and input schema differ
-        if self.manifest.schema != schema {
-            return Err(Error::SchemaMismatch {
-                // original: self.manifest.schema.clone(),
-                // new: schema,
-            });
-        }
+        let schema = append_schema(&schema, &self.manifest.schema)?;
+        let (stream, unique_key_values) = UniqueKeyValues::track(&self.manifest.schema, stream);
 
         let fragments = write_fragments_internal(
             object_store.clone(),
@@ -511,16 +907,21 @@ impl Dataset {
             params.clone(),
         )
         .await?;
+        if let Some(values) = &unique_key_values {
+            values.check(self).await?;
+        }
 
-        let transaction =
+        let mut transaction =
             Transaction::new(self.manifest.version, Operation::Append { fragments }, None);
+        transaction.properties = params.commit_properties.clone();
+        transaction.config_upserts = params.config_upserts.clone();
 
         let new_manifest = commit_transaction(
             self,
             &object_store,
             &transaction,
             &Default::default(),
-            &Default::default(),
+            &commit_config,
         )
         .await?;
 
@@ -557,8 +958,8 @@ impl Dataset {
 
     /// Restore the currently checked out version of the dataset as the latest version.
     ///
-    /// Currently, `write_params` is just used to get additional store params.
-    /// Other options are ignored.
+    /// Currently, `write_params` is just used to get additional store params and the
+    /// number of commit retries. Other options are ignored.
     pub async fn restore(&mut self, write_params: Option<WriteParams>) -> Result<()> {
         let latest_manifest = self.latest_manifest().await?;
         let latest_version = latest_manifest.version;
@@ -571,6 +972,12 @@ impl Dataset {
             None,
         );
 
+        let commit_config = match &write_params {
+            Some(params) => CommitConfig {
+                num_retries: params.commit_retries,
+            },
+            None => self.commit_config.clone(),
+        };
         let object_store =
             if let Some(store_params) = write_params.and_then(|params| params.store_params) {
                 Arc::new(self.object_store.with_params(&store_params))
@@ -584,7 +991,7 @@ impl Dataset {
                 &object_store,
                 &transaction,
                 &Default::default(),
-                &Default::default(),
+                &commit_config,
             )
             .await?,
         );
@@ -592,6 +999,60 @@ impl Dataset {
         Ok(())
     }
 
+    /// The key-value configuration of the dataset in this version.
+    pub fn config(&self) -> &HashMap<String, String> {
+        &self.manifest.config
+    }
+
+    /// Set the entries `upsert_values` of the dataset config, replacing the existing
+    /// values, as a new version of the dataset.
+    ///
+    /// The update fails with [`Error::CommitConflict`](crate::Error::CommitConflict) if a
+    /// concurrent transaction changed any of the same keys since this version was read.
+    pub async fn update_config(&mut self, upsert_values: HashMap<String, String>) -> Result<()> {
+        self.commit_config_operation(Operation::UpdateConfig {
+            upsert_values,
+            delete_keys: vec![],
+        })
+        .await
+    }
+
+    /// Remove the entries with the keys `delete_keys` from the dataset config, as a
+    /// new version of the dataset. The keys without entries are ignored.
+    pub async fn delete_config_keys(&mut self, delete_keys: &[&str]) -> Result<()> {
+        self.commit_config_operation(Operation::UpdateConfig {
+            upsert_values: HashMap::new(),
+            delete_keys: delete_keys.iter().map(|key| key.to_string()).collect(),
+        })
+        .await
+    }
+
+    async fn commit_config_operation(&mut self, operation: Operation) -> Result<()> {
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+        self.manifest = Arc::new(manifest);
+        Ok(())
+    }
+
+    /// Restore `version` as the latest version of the dataset, by committing a new
+    /// version with the same content.
+    ///
+    /// No version is deleted, so the restore itself can be undone by restoring the
+    /// version before it.
+    pub async fn restore_version(&mut self, version: u64) -> Result<()> {
+        let mut dataset = self.checkout_version(version).await?;
+        dataset.restore(None).await?;
+        *self = dataset;
+        Ok(())
+    }
+
     /// Removes old versions of the dataset from disk
     ///
     /// This function will remove all versions of the dataset that are older than the provided
@@ -608,6 +1069,9 @@ impl Dataset {
     ///                        be kept since they cannot be distinguished from an in-progress
     ///                        transaction.  Set to true to delete these files if you are sure
     ///                        there are no other in-progress dataset operations.
+    /// * `keep_tagged` - If true (the default) then the versions pointed to by tags are kept
+    ///   even if they are old.  Otherwise they are removed like any other version, and so
+    ///   are their tags.
     ///
     /// # Returns
     ///
@@ -616,9 +1080,19 @@ impl Dataset {
         &self,
         older_than: Duration,
         delete_unverified: Option<bool>,
+        keep_tagged: Option<bool>,
     ) -> BoxFuture<Result<RemovalStats>> {
         let before = utc_now() - older_than;
-        cleanup::cleanup_old_versions(self, before, delete_unverified).boxed()
+        cleanup::cleanup_old_versions(self, before, delete_unverified, keep_tagged).boxed()
+    }
+
+    /// Compact the small fragments of the dataset, such as the ones left by streaming
+    /// appends, into fragments of up to `target_rows_per_fragment` rows, and materialize
+    /// the deletions. The indices are remapped to the new fragments.
+    ///
+    /// See [`optimize::compact_files`].
+    pub async fn compact_files(&mut self, options: CompactionOptions) -> Result<CompactionMetrics> {
+        optimize::compact_files(self, options, None).await
     }
 
     /// Commit changes to the dataset
@@ -641,6 +1115,10 @@ impl Dataset {
     ///
     /// All operations except Overwrite will fail if the dataset does not already exist.
     ///
+    /// The `read_version` must not be newer than the latest version, and the fragments
+    /// modified by the operation, e.g., the fragments of a `Delete`, must exist in it.
+    /// The operation is then checked against the concurrent commits made after it.
+    ///
     /// # Arguments
     ///
     /// * `base_uri` - The base URI of the dataset
@@ -701,6 +1179,10 @@ impl Dataset {
             None
         };
 
+        if let Some(dataset) = &dataset {
+            dataset.check_read_version(&operation, read_version).await?;
+        }
+
         let transaction = Transaction::new(read_version, operation, None);
 
         let manifest = if let Some(dataset) = &dataset {
@@ -709,7 +1191,7 @@ impl Dataset {
                 &object_store,
                 &transaction,
                 &Default::default(),
-                &Default::default(),
+                &dataset.commit_config,
             )
             .await?
         } else {
@@ -721,20 +1203,46 @@ impl Dataset {
             base,
             manifest: Arc::new(manifest.clone()),
             session: Arc::new(Session::default()),
+            branch: None,
+            commit_config: CommitConfig::default(),
         })
     }
 
-    /// Merge this dataset with another arrow Table / Dataset, and returns a new version of dataset.
-    ///
-    /// Parameters:
-    ///
-    /// - `stream`: the stream of [`RecordBatch`] to merge.
-    /// - `left_on`: the column name to join on the left side (self).
-    /// - `right_on`: the column name to join on the right side (stream).
-    ///
-    /// Returns: a new version of dataset.
-    ///
-    /// It performs a left-join on the two datasets.
+    /// Check that `operation` can be based on `read_version`: the version is not newer
+    /// than this version, and it has the fragments modified by the operation.
+    async fn check_read_version(&self, operation: &Operation, read_version: u64) -> Result<()> {
+        let latest_version = self.version().version;
+        if read_version > latest_version {
+            return Err(Error::invalid_input(
+                format!(
+                    "The read version {} is newer than the latest version {} of the dataset",
+                    read_version, latest_version
+                ),
+                location!(),
+            ));
+        }
+
+        let modified_ids = operation.modified_fragment_ids().collect::<Vec<_>>();
+        if modified_ids.is_empty() {
+            return Ok(());
+        }
+        let read_dataset = self.checkout_version(read_version).await?;
+        let missing_ids = modified_ids
+            .into_iter()
+            .filter(|id| !read_dataset.fragments().iter().any(|f| f.id == *id))
+            .collect::<Vec<_>>();
+        if !missing_ids.is_empty() {
+            return Err(Error::invalid_input(
+                format!(
+                    "The fragments {:?} modified by the operation do not exist in the read version {}",
+                    missing_ids, read_version
+                ),
+                location!(),
+            ));
+        }
+        Ok(())
+    }
+
     async fn merge_impl(
         &mut self,
         stream: Box<dyn RecordBatchReader + Send>,
@@ -742,14 +1250,14 @@ impl Dataset {
         right_on: &str,
     ) -> Result<()> {
         // Sanity check.
-        if self.schema().field(left_on).is_none() {
+        let Some(left_field) = self.schema().field(left_on) else {
             return Err(Error::invalid_input(
                 format!("Column {} does not exist in the left side dataset", left_on),
                 location!(),
             ));
         };
         let right_schema = stream.schema();
-        if right_schema.field_with_name(right_on).is_err() {
+        let Ok(right_field) = right_schema.field_with_name(right_on) else {
             return Err(Error::invalid_input(
                 format!(
                     "Column {} does not exist in the right side dataset",
@@ -758,6 +1266,18 @@ impl Dataset {
                 location!(),
             ));
         };
+        if &left_field.data_type() != right_field.data_type() {
+            return Err(Error::invalid_input(
+                format!(
+                    "Column {} has type {} but column {} has type {}",
+                    left_on,
+                    left_field.data_type(),
+                    right_on,
+                    right_field.data_type()
+                ),
+                location!(),
+            ));
+        }
         for field in right_schema.fields() {
             if field.name() == right_on {
                 // right_on is allowed to exist in the dataset, since it may be
@@ -805,7 +1325,7 @@ impl Dataset {
             &self.object_store,
             &transaction,
             &Default::default(),
-            &Default::default(),
+            &self.commit_config,
         )
         .await?;
 
@@ -814,6 +1334,19 @@ impl Dataset {
         Ok(())
     }
 
+    /// Merge this dataset with another arrow Table / Dataset, and returns a new version of dataset.
+    ///
+    /// Parameters:
+    ///
+    /// - `stream`: the stream of [`RecordBatch`] to merge.
+    /// - `left_on`: the column name to join on the left side (self).
+    /// - `right_on`: the column name to join on the right side (stream).
+    ///
+    /// Returns: a new version of dataset.
+    ///
+    /// It performs a left-join on the two datasets: the other columns of the stream are
+    /// written as new column files of each fragment, and are null for the rows without
+    /// a match. The keys of the stream must be unique, and null keys never match.
     pub async fn merge(
         &mut self,
         stream: impl RecordBatchReader + Send + 'static,
@@ -823,6 +1356,116 @@ impl Dataset {
         let stream = Box::new(stream);
         self.merge_impl(stream, left_on, right_on).await
     }
+    /// Add new columns, computed from the existing columns, and returns a new version
+    /// of the dataset.
+    ///
+    /// Parameters:
+    ///
+    /// - `transform`: the SQL expressions or the batch UDF computing the new columns.
+    /// - `read_columns`: the existing columns passed to the batch UDF, all of them by
+    ///   default. The columns read for SQL expressions are those they reference.
+    ///
+    /// Only the files of the new columns are written, so the existing data is not
+    /// rewritten.
+    pub async fn add_columns(
+        &mut self,
+        transform: NewColumnTransform,
+        read_columns: Option<Vec<String>>,
+    ) -> Result<()> {
+        let operation = schema_evolution::add_columns(self, transform, read_columns).await?;
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(())
+    }
+
+    /// Set the unique key of the dataset, or remove it if `column` is None, and returns a
+    /// new version of the dataset.
+    ///
+    /// The key must be an integer or string column, whose values are unique. Its values
+    /// are then checked by [`Self::append`] and [`Self::merge_insert`], using a scalar
+    /// index on the column, which is created if it has none. Null values are allowed
+    /// and not compared, like in SQL.
+    pub async fn set_unique_key(&mut self, column: Option<&str>) -> Result<()> {
+        let operation = constraints::set_unique_key(self, column).await?;
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(())
+    }
+
+    /// The unique key of the dataset, see [`Self::set_unique_key`].
+    pub fn unique_key(&self) -> Option<&str> {
+        constraints::unique_key(self.schema()).map(|field| field.name.as_str())
+    }
+
+    /// Alter columns, or fields of struct columns, e.g., to rename them or change their
+    /// type, and returns a new version of the dataset.
+    ///
+    /// Renaming only changes the metadata: the field ids are kept, so the data files
+    /// and the indices remain valid. Changing the type of a column rewrites the files
+    /// of this column only.
+    pub async fn alter_columns(&mut self, alterations: &[ColumnAlteration]) -> Result<()> {
+        let operation = schema_evolution::alter_columns(self, alterations).await?;
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(())
+    }
+
+    /// Remove columns, or fields of struct columns, e.g., `["a", "b.c"]`, from the
+    /// dataset, and returns a new version of the dataset.
+    ///
+    /// This only changes the metadata: the data files are left untouched, and are
+    /// deleted by [`Self::cleanup_old_versions`] once no longer referenced.
+    pub async fn drop_columns(&mut self, columns: &[&str]) -> Result<()> {
+        let operation = schema_evolution::drop_columns(self, columns)?;
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(())
+    }
+
     /// Create a Scanner to scan the dataset.
     pub fn scan(&self) -> Scanner {
         Scanner::new(Arc::new(self.clone()))
@@ -1096,6 +1739,129 @@ impl Dataset {
         }
     }
 
+    /// The columns the data files of the dataset are partitioned by, or an empty slice
+    /// if it is not partitioned.
+    ///
+    /// See [`WriteParams::partition_columns`].
+    pub fn partition_columns(&self) -> &[String] {
+        &self.manifest.partition_columns
+    }
+
+    /// Whether the rows of the dataset have stable row ids, which they keep when their
+    /// fragment is rewritten by compaction or when they are updated.
+    ///
+    /// See [`WriteParams::enable_stable_row_ids`].
+    pub fn has_stable_row_ids(&self) -> bool {
+        has_stable_row_ids(&self.manifest)
+    }
+
+    /// The stable row ids of the rows with the given row ids, as returned by a scan
+    /// [with row ids](Scanner::with_row_id).
+    ///
+    /// The stable row ids can be kept to reference the rows, and the rows taken later by
+    /// [`Self::take_by_stable_row_ids`], in any later version of the dataset.
+    pub fn stable_row_ids(&self, row_ids: &[u64]) -> Result<Vec<u64>> {
+        self.check_stable_row_ids()?;
+        rowids::stable_row_ids(&self.manifest.fragments, row_ids)
+    }
+
+    /// Take rows by their stable row ids, in the order of `stable_row_ids`.
+    ///
+    /// Returns an error if a row does not exist in this version of the dataset, e.g.,
+    /// because it was deleted.
+    pub async fn take_by_stable_row_ids(
+        &self,
+        stable_row_ids: &[u64],
+        projection: &Schema,
+    ) -> Result<RecordBatch> {
+        self.check_stable_row_ids()?;
+        let index = self.row_id_index().await?;
+        let row_ids = stable_row_ids
+            .iter()
+            .map(|stable_row_id| {
+                index.get(*stable_row_id).ok_or_else(|| {
+                    Error::invalid_input(
+                        format!(
+                            "Row with stable row id {} does not exist in version {}",
+                            stable_row_id,
+                            self.version().version
+                        ),
+                        location!(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.take_rows(&row_ids, projection).await
+    }
+
+    fn check_stable_row_ids(&self) -> Result<()> {
+        if self.has_stable_row_ids() {
+            Ok(())
+        } else {
+            Err(Error::invalid_input(
+                "The dataset does not have stable row ids",
+                location!(),
+            ))
+        }
+    }
+
+    /// Key the rows in the `_rowid` column of `stream`, which are the addresses of the
+    /// rows, as the indices are keyed: by their stable row ids if the dataset has them.
+    pub(crate) fn with_index_row_ids(
+        &self,
+        stream: SendableRecordBatchStream,
+    ) -> SendableRecordBatchStream {
+        if !self.has_stable_row_ids() {
+            return stream;
+        }
+        let fragments = self.manifest.fragments.clone();
+        let schema = stream.schema();
+        let stream = stream.map(move |batch| Ok(rowids::with_stable_row_ids(&fragments, batch?)?));
+        Box::pin(RecordBatchStreamAdapter::new(schema, stream))
+    }
+
+    /// The resolver of the row ids stored in `index` to the addresses of the rows, if
+    /// the dataset has stable row ids, or None if the index is keyed by the addresses.
+    pub(crate) async fn index_row_ids(&self, index: &Index) -> Result<Option<IndexRowIds>> {
+        if !self.has_stable_row_ids() {
+            return Ok(None);
+        }
+        let fragments = match &index.fragment_bitmap {
+            Some(fragments) => fragments.clone(),
+            None => self
+                .manifest
+                .fragments
+                .iter()
+                .map(|fragment| fragment.id as u32)
+                .collect(),
+        };
+        Ok(Some(IndexRowIds::new(
+            self.row_id_index().await?,
+            fragments,
+        )))
+    }
+
+    /// The address of each stable row id in this version, cached in the session.
+    async fn row_id_index(&self) -> Result<Arc<RowIdIndex>> {
+        let cache = &self.session.file_metadata_cache;
+        let path = self.manifest_file(self.version().version).await?;
+        if let Some(index) = cache.get::<RowIdIndex>(&path) {
+            return Ok(index);
+        }
+        let index = Arc::new(RowIdIndex::try_new(self).await?);
+        cache.insert(path, index.clone());
+        Ok(index)
+    }
+
+    /// Open the values of the blob column `column` of the rows at the addresses
+    /// `row_ids`, in their order, or None for the null values.
+    ///
+    /// Unlike [`Self::take_rows`], the values are not loaded in memory, and can be read
+    /// by ranges, e.g., to stream large images or audio.
+    pub async fn take_blobs(&self, row_ids: &[u64], column: &str) -> Result<Vec<Option<BlobFile>>> {
+        blob::take_blobs(self, row_ids, column).await
+    }
+
     /// Get a stream of batches based on iterator of ranges of row numbers.
     ///
     /// This is an experimental API. It may change at any time.
@@ -1130,10 +1896,28 @@ impl Dataset {
     }
 
     /// Sample `n` rows from the dataset.
-    pub(crate) async fn sample(&self, n: usize, projection: &Schema) -> Result<RecordBatch> {
-        use rand::seq::IteratorRandom;
+    ///
+    /// The rows are chosen with a random generator seeded with `seed`, or from the OS
+    /// if it is None. Only the sampled rows are read, and choosing them takes time
+    /// proportional to `n` rather than to the number of rows.
+    pub(crate) async fn sample(
+        &self,
+        n: usize,
+        projection: &Schema,
+        seed: Option<u64>,
+    ) -> Result<RecordBatch> {
+        use rand::{rngs::SmallRng, seq::index, SeedableRng};
         let num_rows = self.count_rows().await?;
-        let ids = (0..num_rows as u64).choose_multiple(&mut rand::thread_rng(), n);
+        let mut rng = match seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        };
+        let mut ids = index::sample(&mut rng, num_rows, std::cmp::min(n, num_rows))
+            .into_iter()
+            .map(|id| id as u64)
+            .collect::<Vec<_>>();
+        // Read the rows in order.
+        ids.sort_unstable();
         self.take(&ids, projection).await
     }
 
@@ -1176,7 +1960,176 @@ impl Dataset {
             &self.object_store,
             &transaction,
             &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(())
+    }
+
+    /// Upsert the rows of `source` by the key columns `on`, in a single transaction.
+    ///
+    /// By default, the rows of the dataset matching a source row are replaced by it,
+    /// and the other source rows are inserted. The source must have the schema of the
+    /// dataset, and at most one row per key.
+    pub async fn merge_insert(
+        &mut self,
+        source: impl RecordBatchReader + Send + 'static,
+        on: &[&str],
+        params: Option<MergeInsertParams>,
+    ) -> Result<MergeInsertStats> {
+        let params = params.unwrap_or_default();
+        let store_params = params
+            .write_params
+            .as_ref()
+            .and_then(|params| params.store_params.clone())
+            .unwrap_or_default();
+        let (operation, stats) =
+            merge_insert::merge_insert(self, Box::new(source), on, params).await?;
+        let Some(operation) = operation else {
+            return Ok(stats);
+        };
+
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+        let object_store = Arc::new(self.object_store().with_params(&store_params));
+        let manifest = commit_transaction(
+            self,
+            &object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(stats)
+    }
+
+    /// Append the rows of `source` whose values of the key columns `on` match no row
+    /// of the dataset, returning the number of appended rows.
+    ///
+    /// Of the source rows with the same key, only the first one is appended, so
+    /// re-running an ingestion job doesn't duplicate the rows it already wrote. The
+    /// keys are looked up with the scalar index of the key column if there is a single
+    /// indexed key column, or else joined with the key columns of the dataset.
+    pub async fn append_deduplicated(
+        &mut self,
+        source: impl RecordBatchReader + Send + 'static,
+        on: &[&str],
+        params: Option<WriteParams>,
+    ) -> Result<usize> {
+        let params = params.unwrap_or_default();
+        let store_params = params.store_params.clone().unwrap_or_default();
+        let commit_properties = params.commit_properties.clone();
+        let config_upserts = params.config_upserts.clone();
+        let (operation, num_rows) =
+            merge_insert::append_deduplicated(self, Box::new(source), on, params).await?;
+        // The config is still updated when all the rows were already appended, e.g.,
+        // to move the checkpoint of a re-run job forward.
+        let operation = match operation {
+            Some(operation) => operation,
+            None if !config_upserts.is_empty() => Operation::UpdateConfig {
+                upsert_values: HashMap::new(),
+                delete_keys: vec![],
+            },
+            None => return Ok(0),
+        };
+
+        let mut transaction = Transaction::new(self.manifest.version, operation, None);
+        transaction.properties = commit_properties;
+        transaction.config_upserts = config_upserts;
+        let object_store = Arc::new(self.object_store().with_params(&store_params));
+        let manifest = commit_transaction(
+            self,
+            &object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(num_rows)
+    }
+
+    /// Delete the rows whose values of the key columns `on` match a row of `keys`.
+    ///
+    /// Unlike a predicate with a large `IN` list, the keys are joined with the
+    /// dataset, so this is efficient for many keys.
+    pub async fn delete_by_keys(
+        &mut self,
+        keys: impl RecordBatchReader + Send + 'static,
+        on: &[&str],
+    ) -> Result<()> {
+        let Some(operation) = merge_insert::delete_by_keys(self, Box::new(keys), on).await? else {
+            return Ok(());
+        };
+
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(())
+    }
+
+    /// Delete the rows at the addresses `row_ids`, as returned by a scan
+    /// [with row ids](Scanner::with_row_id) or used by [`Self::take_rows`].
+    ///
+    /// No predicate is evaluated, so this is efficient when the rows to remove are
+    /// already known. Returns an error if an address is not in this version.
+    pub async fn delete_rows(&mut self, row_ids: &[u64]) -> Result<()> {
+        let Some(operation) = update::delete_rows(self, row_ids).await? else {
+            return Ok(());
+        };
+
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
+            &Default::default(),
+            &self.commit_config,
+        )
+        .await?;
+
+        self.manifest = Arc::new(manifest);
+
+        Ok(())
+    }
+
+    /// Update the rows matching `predicate`, or all rows if it is `None`.
+    ///
+    /// Each of `updates` sets a column to the value of a SQL expression, e.g.,
+    /// `("price", "price * 1.1")`. The updated rows are rewritten into new fragments,
+    /// and the change is committed as one new version.
+    pub async fn update(
+        &mut self,
+        predicate: Option<&str>,
+        updates: &[(&str, &str)],
+    ) -> Result<()> {
+        let Some(operation) = update::update(self, predicate, updates).await? else {
+            return Ok(());
+        };
+
+        let transaction = Transaction::new(self.manifest.version, operation, None);
+        let manifest = commit_transaction(
+            self,
+            &self.object_store,
+            &transaction,
             &Default::default(),
+            &self.commit_config,
         )
         .await?;
 
@@ -1208,6 +2161,19 @@ impl Dataset {
         self.base.child(DATA_DIR)
     }
 
+    /// The path of a data file. It is relative to the data directory, possibly in the
+    /// directory of its partition, or absolute for the files of the dataset a shallow
+    /// clone was made from.
+    pub(crate) fn data_file_path(&self, data_file: &DataFile) -> Path {
+        match data_file.path.strip_prefix('/') {
+            Some(path) => Path::from(path),
+            None => data_file
+                .path
+                .split('/')
+                .fold(self.data_dir(), |path, part| path.child(part)),
+        }
+    }
+
     pub(crate) fn indices_dir(&self) -> Path {
         self.base.child(INDICES_DIR)
     }
@@ -1226,6 +2192,16 @@ impl Dataset {
         self.session.index_cache.hit_rate()
     }
 
+    /// Get the statistics of the index cache.
+    pub fn index_cache_stats(&self) -> IndexCacheStats {
+        self.session.index_cache.stats()
+    }
+
+    /// Get the tags of this dataset, which give names to versions of its main lineage.
+    pub fn tags(&self) -> Tags {
+        Tags::new(self.lineage_object_store(None).0, self.base.clone())
+    }
+
     /// Get all versions.
     pub async fn versions(&self) -> Result<Vec<Version>> {
         let mut versions: Vec<Version> = self
@@ -1248,6 +2224,45 @@ impl Dataset {
         Ok(versions)
     }
 
+    /// Get the transaction log: all the versions, from the oldest, with the operation
+    /// and properties of the transaction that created each of them and the fragments
+    /// it added, updated and removed.
+    pub async fn transactions(&self) -> Result<Vec<TransactionRecord>> {
+        history::transactions(self).await
+    }
+
+    /// Get the rows inserted, updated and deleted between two versions, e.g., to
+    /// synchronize a search index or a cache incrementally. See [`DatasetDiff`].
+    pub async fn diff(&self, from_version: u64, to_version: u64) -> Result<DatasetDiff> {
+        let from = self.checkout_version(from_version).await?;
+        let to = self.checkout_version(to_version).await?;
+        DatasetDiff::try_new(from, to).await
+    }
+
+    /// Get the changes of the rows made by each version after `version`, up to this
+    /// version of the dataset, e.g., to feed a streaming system.
+    ///
+    /// The changes are ordered by version, and the changes of a version are ordered by
+    /// [`changefeed::ChangeKind`]: deletions, updates and then insertions. Once the
+    /// changes of a version are processed, it is the cursor to resume from on a later
+    /// version of the dataset. The version of the cursor must not have been cleaned up.
+    pub async fn changes_since(&self, version: u64) -> Result<ChangeStream> {
+        changefeed::changes_since(self, version).await
+    }
+
+    /// Upgrade the latest version of a dataset written by an older version of Lance to
+    /// the current format, by committing a new version.
+    ///
+    /// The fragment statistics that are missing or were written incorrectly are
+    /// recomputed, and so are the fragment bitmaps of the indices that cannot be
+    /// trusted. The data files are not rewritten. Nothing is committed if the dataset
+    /// is already up to date.
+    ///
+    /// If `dry_run` is true, only report what would be migrated.
+    pub async fn migrate(&mut self, dry_run: bool) -> Result<MigrationReport> {
+        migration::migrate(self, dry_run).await
+    }
+
     /// Get the latest version of the dataset
     /// This is meant to be a fast path for checking if a dataset has changed. This is why
     /// we don't return the full version struct.
@@ -1258,6 +2273,12 @@ impl Dataset {
             .await
     }
 
+    /// Get the statistics of this version: the number of rows, fragments and files, the
+    /// size of each column, and how much of the dataset each index covers.
+    pub async fn stats(&self) -> Result<DatasetStatistics> {
+        stats::dataset_statistics(self).await
+    }
+
     pub fn count_fragments(&self) -> usize {
         self.manifest.fragments.len()
     }
@@ -1298,6 +2319,29 @@ impl Dataset {
         read_manifest_indexes(&self.object_store, &manifest_file, &self.manifest).await
     }
 
+    /// Describe all the indices of the dataset, one entry per index delta.
+    pub async fn list_indices(&self) -> Result<Vec<IndexDescription>> {
+        let indices = self.load_indices().await?;
+        let mut descriptions = Vec::with_capacity(indices.len());
+        for idx in indices {
+            // The path of a field of a struct column, e.g., `metadata.source`.
+            let columns = idx
+                .fields
+                .iter()
+                .filter_map(|field_id| self.schema().field_path(*field_id))
+                .collect();
+            descriptions.push(IndexDescription {
+                index_type: self.index_type(&idx.uuid.to_string()).await?,
+                name: idx.name,
+                uuid: idx.uuid,
+                columns,
+                dataset_version: idx.dataset_version,
+                fragment_bitmap: idx.fragment_bitmap,
+            });
+        }
+        Ok(descriptions)
+    }
+
     /// Loads a specific index with the given id
     pub async fn load_index(&self, uuid: &str) -> Option<Index> {
         self.load_indices()
@@ -1316,45 +2360,110 @@ impl Dataset {
     }
 
     pub(crate) async fn load_scalar_index_for_column(&self, col: &str) -> Result<Option<Index>> {
+        self.load_index_for_column(col, IndexType::Scalar).await
+    }
+
+    /// All the deltas of the scalar index on the column `col`, in the order they were
+    /// committed.
+    pub(crate) async fn load_scalar_index_deltas_for_column(
+        &self,
+        col: &str,
+    ) -> Result<Vec<Index>> {
+        let Some(index) = self.load_scalar_index_for_column(col).await? else {
+            return Ok(vec![]);
+        };
         Ok(self
             .load_indices()
             .await?
             .into_iter()
-            .filter(|idx| idx.fields.len() == 1)
-            .find(|idx| {
-                let field = self.schema().field_by_id(idx.fields[0]);
-                if let Some(field) = field {
-                    field.name == col
-                } else {
-                    false
-                }
-            }))
+            .filter(|idx| idx.name == index.name)
+            .collect())
+    }
+
+    /// The first index of `index_type` on the column `col`, e.g., to tell a scalar index
+    /// from an inverted index on the same string column.
+    pub(crate) async fn load_index_for_column(
+        &self,
+        col: &str,
+        index_type: IndexType,
+    ) -> Result<Option<Index>> {
+        // The column can be a field of a struct column, e.g., `metadata.source`.
+        let Some(field) = self.schema().field(col) else {
+            return Ok(None);
+        };
+        for idx in self.load_indices().await? {
+            if idx.fields != [field.id] {
+                continue;
+            }
+            // Only the strings can have an inverted index.
+            let is_inverted = matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8)
+                && self.index_type(&idx.uuid.to_string()).await? == IndexType::Inverted;
+            if is_inverted == (index_type == IndexType::Inverted) {
+                return Ok(Some(idx));
+            }
+        }
+        Ok(None)
     }
 
     /// Find index with a given index_name and return its serialized statistics.
+    ///
+    /// Besides the statistics of the latest delta of the index, e.g., the partitions of
+    /// IVF, it reports the number of deltas, and the number of indexed and unindexed rows.
     pub async fn index_statistics(&self, index_name: &str) -> Result<Option<String>> {
-        let index_uuid = self
-            .load_index_by_name(index_name)
-            .await
-            .map(|idx| idx.uuid.to_string());
+        let deltas = self
+            .load_indices()
+            .await?
+            .iter()
+            .filter(|idx| idx.name == index_name)
+            .cloned()
+            .collect::<Vec<_>>();
+        let Some(latest) = deltas.last() else {
+            return Ok(None);
+        };
+        let field_id = latest.fields.first().ok_or_else(|| Error::Index {
+            message: format!("Index {} does not have any field", index_name),
+            location: location!(),
+        })?;
+        let column = self
+            .schema()
+            .field_by_id(*field_id)
+            .ok_or_else(|| Error::Index {
+                message: format!("Field {} of index {} does not exist", field_id, index_name),
+                location: location!(),
+            })?
+            .name
+            .clone();
 
-        if let Some(index_uuid) = index_uuid {
-            let index_statistics = self
-                .open_generic_index("vector", &index_uuid)
-                .await?
-                .statistics()
-                .unwrap();
-            Ok(Some(index_statistics))
-        } else {
-            Ok(None)
+        let uuid = latest.uuid.to_string();
+        let index = self.open_generic_index(&column, &uuid).await?;
+        let mut statistics: serde_json::Value = serde_json::from_str(&index.statistics()?)?;
+        let num_rows = self.count_rows().await?;
+        let num_unindexed_rows = self.count_unindexed_rows(&uuid).await?;
+        if let Some(statistics) = statistics.as_object_mut() {
+            statistics.insert("num_indices".to_string(), deltas.len().into());
+            if let Some(num_unindexed_rows) = num_unindexed_rows {
+                statistics.insert(
+                    "num_indexed_rows".to_string(),
+                    (num_rows - num_unindexed_rows).into(),
+                );
+                statistics.insert("num_unindexed_rows".to_string(), num_unindexed_rows.into());
+            }
         }
+        Ok(Some(statistics.to_string()))
     }
 
     pub async fn count_unindexed_rows(&self, index_uuid: &str) -> Result<Option<usize>> {
         let index = self.load_index(index_uuid).await;
 
         if let Some(index) = index {
-            let unindexed_frags = unindexed_fragments(&index, self).await?;
+            // Count the rows that are not covered by any delta of the index.
+            let deltas = self
+                .load_indices()
+                .await?
+                .into_iter()
+                .filter(|idx| idx.name == index.name)
+                .collect::<Vec<_>>();
+            let unindexed_frags = unindexed_fragments(&deltas, self).await?;
             let unindexed_rows = unindexed_frags
                 .iter()
                 .map(Fragment::num_rows)
@@ -1380,6 +2489,153 @@ impl Dataset {
         }
     }
 
+    /// Search the `k` nearest neighbors of each vector in `queries` in one call.
+    ///
+    /// If the column has a vector index, all the queries are searched together, so the
+    /// index partitions probed by several queries are only read once. The fragments that
+    /// are not indexed yet are scanned once for all the queries.
+    ///
+    /// It returns one [RecordBatch] of `_distance` and `_rowid` per query, in the same
+    /// order as `queries`, sorted by distance. The distances are defined by the metric
+    /// of the index, or L2 if the column is not indexed.
+    pub async fn nearest_batch(
+        &self,
+        column: &str,
+        queries: &FixedSizeListArray,
+        k: usize,
+        nprobes: usize,
+    ) -> Result<Vec<RecordBatch>> {
+        let field = self.schema().field(column).ok_or_else(|| Error::IO {
+            message: format!("Column {} not found", column),
+            location: location!(),
+        })?;
+        match field.data_type() {
+            DataType::FixedSizeList(_, dim) if dim == queries.value_length() => {}
+            _ => {
+                return Err(Error::IO {
+                    message: format!(
+                        "Query dimension {} does not match column {} of type {}",
+                        queries.value_length(),
+                        column,
+                        field.data_type()
+                    ),
+                    location: location!(),
+                })
+            }
+        }
+        if k == 0 {
+            return Err(Error::IO {
+                message: "k must be positive".to_string(),
+                location: location!(),
+            });
+        }
+
+        let mut query = Query {
+            column: column.to_string(),
+            key: queries.values().slice(0, 0),
+            k,
+            nprobes,
+            refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: None,
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
+            metric_type: MetricType::L2,
+            use_index: true,
+        };
+        let mut results = vec![vec![]; queries.len()];
+
+        let indices = self.load_indices().await?;
+        let unindexed = if let Some(index) = indices.iter().find(|i| i.fields.contains(&field.id)) {
+            let deltas = indices
+                .iter()
+                .filter(|i| i.name == index.name)
+                .cloned()
+                .collect::<Vec<_>>();
+            let dataset = Arc::new(self.clone());
+            for delta in deltas.iter() {
+                let vector_index = self
+                    .open_vector_index(column, &delta.uuid.to_string())
+                    .await?;
+                query.metric_type = vector_index.metric_type();
+                let pre_filter = Arc::new(PreFilter::new(dataset.clone(), delta.clone(), None));
+                let batches = vector_index
+                    .search_batch(queries, &query, pre_filter.clone())
+                    .await?;
+                // The index may be keyed by the stable row ids of the rows.
+                pre_filter.wait_for_ready().await?;
+                for (result, batch) in results.iter_mut().zip(batches) {
+                    result.push(pre_filter.resolve_row_ids(batch)?);
+                }
+            }
+            unindexed_fragments(&deltas, self).await?
+        } else {
+            self.manifest.fragments.as_ref().clone()
+        };
+
+        if !unindexed.is_empty() {
+            let mut scanner = self.scan();
+            scanner
+                .with_fragments(unindexed)
+                .project(&[column])?
+                .with_row_id();
+            let mut stream = scanner.try_into_stream().await?;
+            let distance_func = query.metric_type.arrow_batch_func();
+            let sort_options = SortOptions {
+                nulls_first: false,
+                ..Default::default()
+            };
+            while let Some(batch) = stream.try_next().await? {
+                let vectors = batch[column].as_fixed_size_list();
+                let row_ids = &batch[ROW_ID];
+                for (query_id, result) in results.iter_mut().enumerate() {
+                    let distances = distance_func(queries.value(query_id).as_ref(), vectors)?;
+                    let indices = sort_to_indices(distances.as_ref(), Some(sort_options), Some(k))?;
+                    result.push(RecordBatch::try_new(
+                        Self::nearest_batch_schema(),
+                        vec![
+                            take(distances.as_ref(), &indices, None)?,
+                            take(row_ids.as_ref(), &indices, None)?,
+                        ],
+                    )?);
+                }
+            }
+        }
+
+        results
+            .into_iter()
+            .map(|batches| {
+                let distances = batches
+                    .iter()
+                    .map(|b| b[DIST_COL].as_ref())
+                    .collect::<Vec<_>>();
+                let row_ids = batches
+                    .iter()
+                    .map(|b| b[ROW_ID].as_ref())
+                    .collect::<Vec<_>>();
+                let distances = concat(&distances)?;
+                let indices = sort_to_indices(&distances, None, Some(k))?;
+                Ok(RecordBatch::try_new(
+                    Self::nearest_batch_schema(),
+                    vec![
+                        take(&distances, &indices, None)?,
+                        take(&concat(&row_ids)?, &indices, None)?,
+                    ],
+                )?)
+            })
+            .collect()
+    }
+
+    fn nearest_batch_schema() -> SchemaRef {
+        Arc::new(ArrowSchema::new(vec![
+            ArrowField::new(DIST_COL, DataType::Float32, true),
+            ROW_ID_FIELD.clone(),
+        ]))
+    }
+
     /// Gets the number of files that are so small they don't even have a full
     /// group. These are considered too small because reading many of them is
     /// much less efficient than reading a single file because the separate files
@@ -1430,8 +2686,10 @@ impl Dataset {
 
 #[derive(Debug)]
 pub(crate) struct ManifestWriteConfig {
-    auto_set_feature_flags: bool,  // default true
-    timestamp: Option<SystemTime>, // default None
+    auto_set_feature_flags: bool,   // default true
+    timestamp: Option<SystemTime>,  // default None
+    use_stable_row_ids: bool,       // default false
+    partition_columns: Vec<String>, // default empty
 }
 
 impl Default for ManifestWriteConfig {
@@ -1439,8 +2697,42 @@ impl Default for ManifestWriteConfig {
         Self {
             auto_set_feature_flags: true,
             timestamp: None,
+            use_stable_row_ids: false,
+            partition_columns: Vec::new(),
+        }
+    }
+}
+
+/// The schema to write the data of `schema` appended to a dataset, or
+/// [`Error::SchemaMismatch`] if the data can't be appended.
+///
+/// The top-level columns of the data may be nullable where those of the dataset are
+/// not, as long as they have no null values, which is checked when writing them. The
+/// schema of the data doesn't need to specify the default values of the columns, nor
+/// the unique key.
+fn append_schema(schema: &Schema, dataset_schema: &Schema) -> Result<Schema> {
+    let mut schema = schema.clone();
+    for field in schema.fields.iter_mut() {
+        if let Some(dataset_field) = dataset_schema.field(&field.name) {
+            field.nullable = dataset_field.nullable;
+        }
+    }
+    let mut expected = schema.clone();
+    for field in expected.fields.iter_mut() {
+        if let Some(dataset_field) = dataset_schema.field(&field.name) {
+            if field.default_value().is_none() {
+                field.set_default_value(dataset_field.default_value().map(String::from));
+            }
+            field.set_unique(dataset_field.is_unique());
         }
     }
+    if expected != *dataset_schema {
+        return Err(Error::SchemaMismatch {
+            // original: dataset_schema.clone(),
+            // new: schema,
+        });
+    }
+    Ok(schema)
 }
 
 /// Commit a manifest file and create a copy at the latest manifest path.
@@ -1452,7 +2744,8 @@ pub(crate) async fn write_manifest_file(
     config: &ManifestWriteConfig,
 ) -> std::result::Result<(), CommitError> {
     if config.auto_set_feature_flags {
-        apply_feature_flags(manifest);
+        let stable_row_ids = has_stable_row_ids(manifest);
+        apply_feature_flags(manifest, stable_row_ids);
     }
     manifest.set_timestamp(timestamp_to_nanos(config.timestamp));
 
@@ -1522,7 +2815,7 @@ mod tests {
 
     use super::*;
     use crate::arrow::FixedSizeListArrayExt;
-    use crate::dataset::optimize::{compact_files, CompactionOptions};
+    use crate::dataset::optimize::compact_files;
     use crate::dataset::WriteMode::Overwrite;
     use crate::datatypes::Schema;
     use crate::index::scalar::ScalarIndexParams;
@@ -1532,7 +2825,7 @@ mod tests {
     use arrow_array::{
         builder::StringDictionaryBuilder,
         cast::{as_string_array, as_struct_array},
-        types::Int32Type,
+        types::{Float32Type, Int32Type},
         ArrayRef, DictionaryArray, Float32Array, Int32Array, Int64Array, Int8Array,
         Int8DictionaryArray, RecordBatch, RecordBatchIterator, StringArray, UInt16Array,
         UInt32Array,
@@ -1542,11 +2835,13 @@ mod tests {
     use arrow_select::take::take;
     use futures::stream::TryStreamExt;
     use lance_core::format::WriterVersion;
-    use lance_datagen::{array, gen, BatchCount, RowCount};
+    use lance_core::utils::testing::MockClock;
+    use lance_datagen::{array, gen, BatchCount, Dimension, RowCount};
     use lance_index::vector::DIST_COL;
     use lance_index::IndexType;
     use lance_linalg::distance::MetricType;
     use lance_testing::datagen::generate_random_array;
+    use roaring::RoaringBitmap;
     use tempfile::{tempdir, TempDir};
 
     // Used to validate that futures returned are Send.
@@ -1831,8 +3126,8 @@ mod tests {
         );
 
         // Write with custom manifest
-        manifest.writer_feature_flags = 5; // Set another flag
-        manifest.reader_feature_flags = 5;
+        manifest.writer_feature_flags = 1 << 40; // Set an unknown flag
+        manifest.reader_feature_flags = 1 << 40;
         manifest.version += 1;
         write_manifest_file(
             dataset.object_store(),
@@ -1842,6 +3137,8 @@ mod tests {
             &ManifestWriteConfig {
                 auto_set_feature_flags: false,
                 timestamp: None,
+                use_stable_row_ids: false,
+                partition_columns: Vec::new(),
             },
         )
         .await
@@ -2486,6 +3783,23 @@ mod tests {
         assert_eq!(actual_statistics["index_type"].as_str().unwrap(), "IVF");
         assert_eq!(actual_statistics["metric_type"].as_str().unwrap(), "l2");
         assert_eq!(actual_statistics["num_partitions"].as_i64().unwrap(), 10);
+        assert_eq!(actual_statistics["num_indices"].as_i64().unwrap(), 1);
+        assert_eq!(actual_statistics["num_indexed_rows"].as_i64().unwrap(), 512);
+        assert_eq!(
+            actual_statistics["num_unindexed_rows"].as_i64().unwrap(),
+            512
+        );
+        assert_eq!(actual_statistics["sub_index"]["index_type"], "PQ");
+        let partition_sizes = actual_statistics["partitions"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|p| p["length"].as_f64().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(partition_sizes.iter().sum::<f64>(), 512.0);
+        let max_size = partition_sizes.iter().copied().fold(0.0, f64::max);
+        let skew = actual_statistics["partition_skew"].as_f64().unwrap();
+        assert!((skew - max_size / (512.0 / 10.0)).abs() < 1e-9);
 
         assert_eq!(
             dataset.index_statistics("non-existent_idx").await.unwrap(),
@@ -2512,6 +3826,72 @@ mod tests {
         assert!(fragment_bitmap.contains(0));
     }
 
+    #[tokio::test]
+    async fn test_nearest_batch() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+        let vectors =
+            <arrow_array::FixedSizeListArray as FixedSizeListArrayExt>::try_new_from_values(
+                generate_random_array(600 * dimension as usize),
+                dimension,
+            )
+            .unwrap();
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors.clone())]).unwrap();
+
+        let reader = RecordBatchIterator::new(vec![Ok(batch.slice(0, 500))], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        let params = VectorIndexParams::ivf_flat(4, MetricType::L2);
+        dataset
+            .create_index(&["embeddings"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        // The appended rows are not indexed, and are searched with a flat scan.
+        let reader = RecordBatchIterator::new(vec![Ok(batch.slice(500, 100))], schema.clone());
+        let write_params = WriteParams {
+            mode: WriteMode::Append,
+            ..Default::default()
+        };
+        dataset.append(reader, Some(write_params)).await.unwrap();
+
+        let queries = vectors.slice(495, 10);
+        let results = dataset
+            .nearest_batch("embeddings", &queries, 5, 2)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 10);
+        for (i, result) in results.iter().enumerate() {
+            let query = queries.value(i);
+            let expected = dataset
+                .scan()
+                .nearest("embeddings", query.as_primitive::<Float32Type>(), 5)
+                .unwrap()
+                .nprobs(2)
+                .with_row_id()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(result.num_rows(), 5);
+            assert_eq!(result[ROW_ID].as_ref(), expected[0][ROW_ID].as_ref());
+            assert_eq!(result[DIST_COL].as_ref(), expected[0][DIST_COL].as_ref());
+            // Each query is one of the vectors in the dataset.
+            assert_eq!(result[DIST_COL].as_primitive::<Float32Type>().value(0), 0.0);
+        }
+    }
+
     #[tokio::test]
     async fn test_create_scalar_index() {
         let test_dir = tempdir().unwrap();
@@ -2549,6 +3929,55 @@ mod tests {
         dataset.index_statistics(&index_name).await.unwrap();
     }
 
+    #[tokio::test]
+    async fn test_list_indices() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let data = gen()
+            .col(Some("int".to_string()), array::step::<Int32Type>())
+            .col(
+                Some("vec".to_string()),
+                array::rand_vec::<Float32Type>(Dimension::from(8)),
+            );
+        let mut dataset = Dataset::write(
+            data.into_reader_rows(RowCount::from(256), BatchCount::from(2)),
+            test_uri,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(dataset.list_indices().await.unwrap().is_empty());
+
+        dataset
+            .create_index(
+                &["int"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        let params = VectorIndexParams::ivf_flat(2, MetricType::L2);
+        dataset
+            .create_index(&["vec"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let indices = dataset.list_indices().await.unwrap();
+        assert_eq!(indices.len(), 2);
+        let scalar = indices.iter().find(|i| i.name == "int_idx").unwrap();
+        assert_eq!(scalar.columns, vec!["int"]);
+        assert_eq!(scalar.index_type, IndexType::Scalar);
+        assert_eq!(scalar.dataset_version, 1);
+        let vector = indices.iter().find(|i| i.name == "vec_idx").unwrap();
+        assert_eq!(vector.columns, vec!["vec"]);
+        assert_eq!(vector.index_type, IndexType::Vector);
+        assert_eq!(vector.dataset_version, 2);
+        assert_eq!(vector.fragment_bitmap, Some(RoaringBitmap::from_iter([0])));
+    }
+
     async fn create_bad_file() -> Result<Dataset> {
         let test_dir = tempdir().unwrap();
 
@@ -2699,6 +4128,102 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[tokio::test]
+    async fn test_checkout_as_of() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = || {
+            gen()
+                .col(Some("i".to_string()), array::step::<Int32Type>())
+                .into_reader_rows(RowCount::from(10), BatchCount::from(1))
+        };
+        let clock = MockClock::new();
+        clock.set_system_time(chrono::Duration::days(1));
+        let mut dataset = Dataset::write(data(), test_uri, None).await.unwrap();
+        for day in 2..4 {
+            clock.set_system_time(chrono::Duration::days(day));
+            dataset.append(data(), None).await.unwrap();
+        }
+
+        let versions = dataset.versions().await.unwrap();
+        assert_eq!(versions.len(), 3);
+        for version in &versions {
+            let checked_out = dataset.checkout_as_of(version.timestamp).await.unwrap();
+            assert_eq!(checked_out.version().version, version.version);
+        }
+
+        let checked_out = dataset
+            .checkout_as_of(versions[1].timestamp + chrono::Duration::hours(12))
+            .await
+            .unwrap();
+        assert_eq!(checked_out.version().version, versions[1].version);
+        assert_eq!(checked_out.count_rows().await.unwrap(), 20);
+
+        let checked_out = dataset
+            .checkout_as_of(versions[2].timestamp + chrono::Duration::days(100))
+            .await
+            .unwrap();
+        assert_eq!(checked_out.version().version, versions[2].version);
+
+        let before = versions[0].timestamp - chrono::Duration::seconds(1);
+        assert!(dataset.checkout_as_of(before).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_merge_invalid_keys() {
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "i",
+            DataType::Int32,
+            true,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(vec![Some(1), None, Some(3)]))],
+        )
+        .unwrap();
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let batches = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+
+        let right = |keys: ArrayRef| {
+            let right_schema = Arc::new(ArrowSchema::new(vec![
+                Field::new("i2", keys.data_type().clone(), true),
+                Field::new("y", DataType::Utf8, true),
+            ]));
+            let values = Arc::new(StringArray::from_iter_values(
+                (0..keys.len()).map(|i| format!("y{i}")),
+            ));
+            let batch = RecordBatch::try_new(right_schema.clone(), vec![keys, values]).unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], right_schema)
+        };
+
+        // The key types must match.
+        let keys = Arc::new(Int64Array::from(vec![1, 3]));
+        assert!(dataset.merge(right(keys), "i", "i2").await.is_err());
+        // The keys on the right side must be unique.
+        let keys = Arc::new(Int32Array::from(vec![1, 3, 1]));
+        assert!(dataset.merge(right(keys), "i", "i2").await.is_err());
+        assert_eq!(dataset.version().version, 1);
+
+        // Null keys never match.
+        let keys = Arc::new(Int32Array::from(vec![None, Some(3), None]));
+        dataset.merge(right(keys), "i", "i2").await.unwrap();
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(
+            as_string_array(&batch["y"]),
+            &StringArray::from(vec![None, None, Some("y1")])
+        );
+    }
+
     #[tokio::test]
     async fn test_delete() {
         fn sequence_data(range: Range<u32>) -> RecordBatch {
@@ -2890,6 +4415,149 @@ mod tests {
         assert!(fragments[0].metadata.deletion_file.is_some());
     }
 
+    #[tokio::test]
+    async fn test_restore_version() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = || {
+            gen()
+                .col(Some("i".to_string()), array::step::<Int32Type>())
+                .into_reader_rows(RowCount::from(10), BatchCount::from(1))
+        };
+        let mut dataset = Dataset::write(data(), test_uri, None).await.unwrap();
+        dataset.append(data(), None).await.unwrap();
+        dataset.delete("i < 5").await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 10);
+
+        // Roll back the append and the delete.
+        dataset.restore_version(1).await.unwrap();
+        assert_eq!(dataset.version().version, 4);
+        assert_eq!(dataset.count_rows().await.unwrap(), 10);
+        let dataset = Dataset::open(test_uri).await.unwrap();
+        assert_eq!(dataset.version().version, 4);
+        assert_eq!(dataset.versions().await.unwrap().len(), 4);
+
+        // The ids of the fragments of the rolled back versions are not reused.
+        let mut dataset = dataset;
+        dataset.append(data(), None).await.unwrap();
+        let ids = dataset
+            .get_fragments()
+            .iter()
+            .map(|f| f.id())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, vec![0, 2]);
+
+        // The restore can itself be rolled back.
+        dataset.restore_version(3).await.unwrap();
+        assert_eq!(dataset.version().version, 6);
+        assert_eq!(dataset.count_rows().await.unwrap(), 10);
+        assert_eq!(dataset.count_fragments(), 2);
+
+        assert!(dataset.restore_version(10).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_update_config() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = || {
+            gen()
+                .col(Some("i".to_string()), array::step::<Int32Type>())
+                .into_reader_rows(RowCount::from(10), BatchCount::from(1))
+        };
+        let config = |entries: &[(&str, &str)]| {
+            entries
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect::<HashMap<_, _>>()
+        };
+        let mut dataset = Dataset::write(data(), test_uri, None).await.unwrap();
+        assert!(dataset.config().is_empty());
+
+        dataset
+            .update_config(config(&[("a", "1"), ("b", "2")]))
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, 2);
+        assert_eq!(dataset.config(), &config(&[("a", "1"), ("b", "2")]));
+        dataset.delete_config_keys(&["a", "c"]).await.unwrap();
+        assert_eq!(dataset.version().version, 3);
+        assert_eq!(dataset.config(), &config(&[("b", "2")]));
+
+        // A checkpoint is set in the same version as the appended data.
+        let write_params = WriteParams {
+            mode: WriteMode::Append,
+            config_upserts: config(&[("offset", "10")]),
+            ..Default::default()
+        };
+        let dataset = Dataset::write(data(), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, 4);
+        assert_eq!(dataset.count_rows().await.unwrap(), 20);
+        assert_eq!(dataset.config(), &config(&[("b", "2"), ("offset", "10")]));
+
+        // The config is versioned with the data.
+        let mut dataset = Dataset::open(test_uri).await.unwrap();
+        assert_eq!(dataset.config(), &config(&[("b", "2"), ("offset", "10")]));
+        let previous = dataset.checkout_version(2).await.unwrap();
+        assert_eq!(previous.config(), &config(&[("a", "1"), ("b", "2")]));
+
+        // Concurrent changes of the same keys conflict, unlike those of other keys.
+        let mut concurrent = dataset.clone();
+        dataset
+            .update_config(config(&[("offset", "20")]))
+            .await
+            .unwrap();
+        assert!(matches!(
+            concurrent.update_config(config(&[("offset", "30")])).await,
+            Err(Error::CommitConflict { .. })
+        ));
+        concurrent
+            .update_config(config(&[("c", "3")]))
+            .await
+            .unwrap();
+        assert_eq!(
+            concurrent.config(),
+            &config(&[("b", "2"), ("c", "3"), ("offset", "20")])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_commit_checks_read_version() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = || {
+            gen()
+                .col(Some("i".to_string()), array::step::<Int32Type>())
+                .into_reader_rows(RowCount::from(10), BatchCount::from(1))
+        };
+        let mut dataset = Dataset::write(data(), test_uri, None).await.unwrap();
+        dataset.append(data(), None).await.unwrap();
+
+        let delete = |ids: Vec<u64>| Operation::Delete {
+            updated_fragments: vec![],
+            deleted_fragment_ids: ids,
+            predicate: "true".to_string(),
+        };
+        // The fragment 1 was appended after the read version.
+        assert!(matches!(
+            Dataset::commit(test_uri, delete(vec![1]), Some(1), None).await,
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            Dataset::commit(test_uri, delete(vec![0]), Some(3), None).await,
+            Err(Error::InvalidInput { .. })
+        ));
+
+        let dataset = Dataset::commit(test_uri, delete(vec![0]), Some(2), None)
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, 3);
+        assert_eq!(dataset.count_rows().await.unwrap(), 10);
+        assert_eq!(dataset.get_fragments()[0].id(), 1);
+    }
+
     #[tokio::test]
     async fn test_search_empty() {
         // Create a table
@@ -3154,6 +4822,88 @@ mod tests {
         assert!(dataset.num_small_files(512).await == 0);
     }
 
+    #[tokio::test]
+    async fn test_shallow_clone() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().join("source");
+        let test_uri = test_uri.to_str().unwrap();
+        let clone_uri = test_dir.path().join("clone");
+        let clone_uri = clone_uri.to_str().unwrap();
+
+        let data = gen()
+            .col(Some("i".to_string()), array::step::<Int32Type>())
+            .into_reader_rows(RowCount::from(100), BatchCount::from(2));
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["i"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        dataset.delete("i < 10").await.unwrap();
+
+        let mut clone = dataset.shallow_clone(clone_uri).await.unwrap();
+        clone.validate().await.unwrap();
+        assert_eq!(clone.version().version, dataset.version().version);
+        assert_eq!(clone.count_rows().await.unwrap(), 190);
+        assert_eq!(clone.load_indices().await.unwrap().len(), 1);
+        assert_eq!(
+            clone
+                .scan()
+                .filter("i = 150")
+                .unwrap()
+                .count_rows()
+                .await
+                .unwrap(),
+            1
+        );
+        // No data file is copied.
+        assert!(!test_dir.path().join("clone").join("data").exists());
+
+        // The clone and the dataset are independent.
+        let data = gen()
+            .col(Some("i".to_string()), array::step::<Int32Type>())
+            .into_reader_rows(RowCount::from(10), BatchCount::from(1));
+        clone.append(data, None).await.unwrap();
+        clone.delete("i = 150").await.unwrap();
+        assert_eq!(clone.count_rows().await.unwrap(), 199);
+        let dataset = Dataset::open(test_uri).await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 190);
+        assert_eq!(
+            dataset
+                .scan()
+                .filter("i = 150")
+                .unwrap()
+                .count_rows()
+                .await
+                .unwrap(),
+            1
+        );
+
+        // Cleaning up the clone leaves the files of the dataset.
+        clone
+            .cleanup_old_versions(Duration::seconds(-1), Some(true), None)
+            .await
+            .unwrap();
+        dataset.validate().await.unwrap();
+        assert_eq!(clone.count_rows().await.unwrap(), 199);
+
+        assert!(matches!(
+            dataset.shallow_clone(clone_uri).await,
+            Err(Error::DatasetAlreadyExists { .. })
+        ));
+    }
+
     #[tokio::test]
     async fn test_read_struct_of_dictionary_arrays() {
         let test_dir = tempdir().unwrap();
@@ -3293,7 +5043,7 @@ mod tests {
     ///
     /// The `table_path` should be relative to `test_data/` at the root of the
     /// repo.
-    fn copy_test_data_to_tmp(table_path: &str) -> std::io::Result<TempDir> {
+    pub(super) fn copy_test_data_to_tmp(table_path: &str) -> std::io::Result<TempDir> {
         use std::path::PathBuf;
 
         let mut src = PathBuf::new();
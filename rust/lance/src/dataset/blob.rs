@@ -0,0 +1,577 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Out-of-line storage of the values of large binary columns.
+//!
+//! The values of the blob columns, marked with [`BLOB_KEY`], are written to a blob
+//! file next to the data file of the column, and the column in the data file only holds
+//! a reference to each value, its position and size in the blob file. Scanning the
+//! other columns then never reads through the large values.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+
+use arrow_array::builder::GenericBinaryBuilder;
+use arrow_array::{
+    cast::AsArray, Array, ArrayRef, GenericBinaryArray, OffsetSizeTrait, RecordBatch,
+};
+use arrow_schema::DataType;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use lance_core::datatypes::{Field, Schema, BLOB_KEY};
+use lance_core::io::{object_store::ObjectStore, ObjectWriter, Reader, Writer};
+use lance_core::{Error, Result};
+use object_store::path::Path;
+use snafu::{location, Location};
+use tokio::io::AsyncWriteExt;
+
+use super::Dataset;
+use crate::format::RowAddress;
+
+/// The extension of the blob files, which are named after their data file.
+pub const BLOB_FILE_EXTENSION: &str = "blob";
+
+/// The path of the blob file of the data file at `data_file_path`.
+pub(crate) fn blob_file_path(data_file_path: &Path) -> Path {
+    let path = data_file_path.as_ref();
+    let stem = path.strip_suffix(".lance").unwrap_or(path);
+    Path::from(format!("{}.{}", stem, BLOB_FILE_EXTENSION))
+}
+
+/// The path of the data file of the blob file at `blob_file_path`.
+pub(crate) fn blob_data_file_path(blob_file_path: &Path) -> Path {
+    let path = blob_file_path.as_ref();
+    let stem = path
+        .strip_suffix(BLOB_FILE_EXTENSION)
+        .and_then(|stem| stem.strip_suffix('.'))
+        .unwrap_or(path);
+    Path::from(format!("{}.lance", stem))
+}
+
+/// The reference to a value in a blob file, stored in the data file in place of the
+/// value, as its position and size in little endian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BlobReference {
+    position: u64,
+    size: u64,
+}
+
+impl BlobReference {
+    const ENCODED_SIZE: usize = 16;
+
+    fn to_bytes(self) -> [u8; Self::ENCODED_SIZE] {
+        let mut bytes = [0; Self::ENCODED_SIZE];
+        bytes[..8].copy_from_slice(&self.position.to_le_bytes());
+        bytes[8..].copy_from_slice(&self.size.to_le_bytes());
+        bytes
+    }
+
+    fn try_from_bytes(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != Self::ENCODED_SIZE {
+            return Err(Error::Internal {
+                message: format!("Invalid blob reference of {} bytes", bytes.len()),
+                location: location!(),
+            });
+        }
+        Ok(Self {
+            position: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            size: u64::from_le_bytes(bytes[8..].try_into().unwrap()),
+        })
+    }
+
+    fn range(&self) -> Range<usize> {
+        self.position as usize..(self.position + self.size) as usize
+    }
+}
+
+/// Check that the blob columns of `schema` are top-level binary columns.
+pub(crate) fn check_blob_fields(schema: &Schema) -> Result<()> {
+    for field in schema.fields.iter() {
+        if field.is_blob() && !matches!(field.data_type(), DataType::Binary | DataType::LargeBinary)
+        {
+            return Err(Error::invalid_input(
+                format!(
+                    "Blob column {} must be binary or large binary, but is {}",
+                    field.name,
+                    field.data_type()
+                ),
+                location!(),
+            ));
+        }
+        if let Some(child) = nested_blob_field(field) {
+            return Err(Error::invalid_input(
+                format!(
+                    "Blob column {} must be a top-level column, it is a child of {}",
+                    child.name, field.name
+                ),
+                location!(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn nested_blob_field(field: &Field) -> Option<&Field> {
+    field.children.iter().find_map(|child| {
+        if child.is_blob() {
+            Some(child)
+        } else {
+            nested_blob_field(child)
+        }
+    })
+}
+
+/// Writes the values of the blob columns of the batches written to a data file to
+/// its blob file.
+pub(crate) struct BlobWriter {
+    writer: ObjectWriter,
+    fields: Vec<String>,
+}
+
+impl BlobWriter {
+    /// Create the writer of the blob file of the data file at `data_file_path`, or
+    /// None if `schema` has no blob columns.
+    pub(crate) async fn try_new(
+        object_store: &ObjectStore,
+        data_file_path: &Path,
+        schema: &Schema,
+    ) -> Result<Option<Self>> {
+        check_blob_fields(schema)?;
+        let fields = schema
+            .fields
+            .iter()
+            .filter(|field| field.is_blob())
+            .map(|field| field.name.clone())
+            .collect::<Vec<_>>();
+        if fields.is_empty() {
+            return Ok(None);
+        }
+        let writer = object_store.create(&blob_file_path(data_file_path)).await?;
+        Ok(Some(Self { writer, fields }))
+    }
+
+    /// Write the values of the blob columns of `batch` to the blob file, and replace
+    /// them with their references.
+    pub(crate) async fn write(&mut self, batch: &RecordBatch) -> Result<RecordBatch> {
+        let mut columns = batch.columns().to_vec();
+        for name in self.fields.iter() {
+            let Some(index) = batch.schema().index_of(name).ok() else {
+                continue;
+            };
+            let array = batch.column(index);
+            columns[index] = match array.data_type() {
+                DataType::Binary => {
+                    write_values(&mut self.writer, array.as_binary::<i32>()).await?
+                }
+                DataType::LargeBinary => {
+                    write_values(&mut self.writer, array.as_binary::<i64>()).await?
+                }
+                data_type => {
+                    return Err(Error::invalid_input(
+                        format!("Blob column {} must be binary, got {}", name, data_type),
+                        location!(),
+                    ))
+                }
+            };
+        }
+        Ok(RecordBatch::try_new(batch.schema(), columns)?)
+    }
+
+    /// Write the batches, see [`Self::write`].
+    pub(crate) async fn write_batches(
+        &mut self,
+        batches: &[RecordBatch],
+    ) -> Result<Vec<RecordBatch>> {
+        let mut written = Vec::with_capacity(batches.len());
+        for batch in batches {
+            written.push(self.write(batch).await?);
+        }
+        Ok(written)
+    }
+
+    /// The number of bytes written to the blob file.
+    pub(crate) async fn tell(&mut self) -> Result<usize> {
+        self.writer.tell().await
+    }
+
+    pub(crate) async fn finish(&mut self) -> Result<()> {
+        self.writer.shutdown().await
+    }
+}
+
+async fn write_values<O: OffsetSizeTrait>(
+    writer: &mut ObjectWriter,
+    array: &GenericBinaryArray<O>,
+) -> Result<ArrayRef> {
+    // The values are contiguous in the array, so they are written at once.
+    let offsets = array.value_offsets();
+    let first_offset = offsets[0].as_usize();
+    let last_offset = offsets[array.len()].as_usize();
+    let base_position = writer.tell().await? as u64;
+    writer
+        .write_all(&array.value_data()[first_offset..last_offset])
+        .await?;
+
+    let mut builder = GenericBinaryBuilder::<O>::with_capacity(
+        array.len(),
+        array.len() * BlobReference::ENCODED_SIZE,
+    );
+    for (idx, window) in offsets.windows(2).enumerate() {
+        if array.is_null(idx) {
+            builder.append_null();
+            continue;
+        }
+        let reference = BlobReference {
+            position: base_position + (window[0].as_usize() - first_offset) as u64,
+            size: (window[1].as_usize() - window[0].as_usize()) as u64,
+        };
+        builder.append_value(reference.to_bytes());
+    }
+    Ok(Arc::new(builder.finish()))
+}
+
+/// Load the values of the blob column `field` from the blob file opened by `reader`,
+/// given the `references` read from the data file.
+pub(crate) async fn load_blobs(
+    reader: &dyn Reader,
+    field: &Field,
+    references: &dyn Array,
+) -> Result<ArrayRef> {
+    match references.data_type() {
+        DataType::Binary => load_values(reader, references.as_binary::<i32>()).await,
+        DataType::LargeBinary => load_values(reader, references.as_binary::<i64>()).await,
+        data_type => Err(Error::Internal {
+            message: format!("Blob column {} is stored as {}", field.name, data_type),
+            location: location!(),
+        }),
+    }
+}
+
+async fn load_values<O: OffsetSizeTrait>(
+    reader: &dyn Reader,
+    references: &GenericBinaryArray<O>,
+) -> Result<ArrayRef> {
+    let references = references
+        .iter()
+        .map(|reference| reference.map(BlobReference::try_from_bytes).transpose())
+        .collect::<Result<Vec<_>>>()?;
+    let values = futures::stream::iter(references)
+        .map(|reference| async move {
+            match reference {
+                Some(reference) => {
+                    if reference.size == 0 {
+                        return Ok(Some(Bytes::new()));
+                    }
+                    reader.get_range(reference.range()).await.map(Some)
+                }
+                None => Ok(None),
+            }
+        })
+        .buffered(num_cpus::get())
+        .try_collect::<Vec<_>>()
+        .await?;
+    Ok(Arc::new(GenericBinaryArray::<O>::from_iter(values)))
+}
+
+/// A value of a blob column, whose bytes can be read in ranges, without loading the
+/// whole value in memory.
+#[derive(Clone)]
+pub struct BlobFile {
+    reader: Arc<dyn Reader>,
+    position: u64,
+    size: u64,
+}
+
+impl std::fmt::Debug for BlobFile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BlobFile")
+            .field("path", self.reader.path())
+            .field("position", &self.position)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
+impl BlobFile {
+    /// Open the value referenced by `reference`, read from a blob column.
+    pub(crate) fn try_new(reader: Arc<dyn Reader>, reference: &[u8]) -> Result<Self> {
+        let reference = BlobReference::try_from_bytes(reference)?;
+        Ok(Self {
+            reader,
+            position: reference.position,
+            size: reference.size,
+        })
+    }
+
+    /// The size of the value, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Read the whole value.
+    pub async fn read(&self) -> Result<Bytes> {
+        self.read_range(0..self.size).await
+    }
+
+    /// Read the bytes of the value in `range`.
+    pub async fn read_range(&self, range: Range<u64>) -> Result<Bytes> {
+        if range.start > range.end || range.end > self.size {
+            return Err(Error::invalid_input(
+                format!(
+                    "Range {:?} is out of the bounds of the blob of {} bytes",
+                    range, self.size
+                ),
+                location!(),
+            ));
+        }
+        if range.is_empty() {
+            return Ok(Bytes::new());
+        }
+        let start = (self.position + range.start) as usize;
+        let end = (self.position + range.end) as usize;
+        self.reader.get_range(start..end).await
+    }
+}
+
+/// Open the values of the blob column `column` of the rows at the addresses `row_ids`.
+pub(super) async fn take_blobs(
+    dataset: &Dataset,
+    row_ids: &[u64],
+    column: &str,
+) -> Result<Vec<Option<BlobFile>>> {
+    let projection = dataset.schema().project(&[column])?;
+    if !projection.fields[0].is_blob() {
+        return Err(Error::invalid_input(
+            format!("Column {} is not a blob column", column),
+            location!(),
+        ));
+    }
+
+    // The references are taken from each fragment at once.
+    let mut offsets_by_fragment: BTreeMap<u32, Vec<u32>> = BTreeMap::new();
+    for row_id in row_ids {
+        let address = RowAddress::new_from_id(*row_id);
+        offsets_by_fragment
+            .entry(address.fragment_id())
+            .or_default()
+            .push(address.row_id());
+    }
+    let mut blobs = HashMap::with_capacity(row_ids.len());
+    for (fragment_id, mut offsets) in offsets_by_fragment {
+        let fragment = dataset.get_fragment(fragment_id as usize).ok_or_else(|| {
+            Error::invalid_input(
+                format!("Fragment {} does not exist in the dataset", fragment_id),
+                location!(),
+            )
+        })?;
+        offsets.sort_unstable();
+        offsets.dedup();
+        let mut reader = fragment.open(&projection).await?;
+        reader.with_blob_references();
+        let batch = reader.take(&offsets).await?;
+        let references = batch.column(0);
+        let references = match references.data_type() {
+            DataType::Binary => {
+                open_blobs(reader.blob_reader(column), references.as_binary::<i32>())
+            }
+            DataType::LargeBinary => {
+                open_blobs(reader.blob_reader(column), references.as_binary::<i64>())
+            }
+            data_type => Err(Error::Internal {
+                message: format!("Blob column {} is stored as {}", column, data_type),
+                location: location!(),
+            }),
+        }?;
+        for (offset, blob) in offsets.into_iter().zip(references) {
+            let address = RowAddress::new_from_parts(fragment_id, offset);
+            blobs.insert(u64::from(address), blob);
+        }
+    }
+    Ok(row_ids.iter().map(|row_id| blobs[row_id].clone()).collect())
+}
+
+fn open_blobs<O: OffsetSizeTrait>(
+    reader: Option<Arc<dyn Reader>>,
+    references: &GenericBinaryArray<O>,
+) -> Result<Vec<Option<BlobFile>>> {
+    references
+        .iter()
+        .map(|reference| match (reference, reader.as_ref()) {
+            (Some(reference), Some(reader)) => {
+                BlobFile::try_new(reader.clone(), reference).map(Some)
+            }
+            (Some(_), None) => Err(Error::Internal {
+                message: "Blob column has no blob file".to_string(),
+                location: location!(),
+            }),
+            (None, _) => Ok(None),
+        })
+        .collect()
+}
+
+/// The arrow field of a blob column, see [`BLOB_KEY`].
+pub fn blob_field(name: &str, nullable: bool) -> arrow_schema::Field {
+    arrow_schema::Field::new(name, DataType::LargeBinary, nullable).with_metadata(
+        [(BLOB_KEY.to_string(), "true".to_string())]
+            .into_iter()
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{types::UInt64Type, Int32Array, LargeBinaryArray, RecordBatchIterator};
+    use arrow_schema::{Field as ArrowField, Schema as ArrowSchema};
+    use arrow_select::concat::concat_batches;
+    use chrono::Duration;
+    use tempfile::tempdir;
+
+    use crate::dataset::optimize::{compact_files, CompactionOptions};
+    use crate::dataset::{WriteParams, ROW_ID};
+    use lance_core::utils::testing::MockClock;
+
+    fn blob_value(i: usize) -> Option<Vec<u8>> {
+        (i % 4 != 3).then(|| vec![i as u8; i * 100])
+    }
+
+    async fn count_files(dataset: &Dataset, extension: &str) -> usize {
+        let files = dataset
+            .object_store
+            .read_dir_all(&dataset.data_dir(), None)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        files
+            .iter()
+            .filter(|meta| meta.location.extension() == Some(extension))
+            .count()
+    }
+
+    async fn scan(dataset: &Dataset, columns: &[&str], with_row_id: bool) -> RecordBatch {
+        let mut scanner = dataset.scan();
+        scanner.project(columns).unwrap();
+        if with_row_id {
+            scanner.with_row_id();
+        }
+        let batches = scanner
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        concat_batches(&batches[0].schema(), &batches).unwrap()
+    }
+
+    #[test]
+    fn test_blob_file_path() {
+        let data_file_path = Path::from("base/data/abc.lance");
+        let path = blob_file_path(&data_file_path);
+        assert_eq!(path, Path::from("base/data/abc.blob"));
+        assert_eq!(blob_data_file_path(&path), data_file_path);
+    }
+
+    #[tokio::test]
+    async fn test_blob_columns() {
+        let clock = MockClock::new();
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, false),
+            blob_field("blob", true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..20)),
+                Arc::new(LargeBinaryArray::from_iter((0..20).map(blob_value))),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone());
+        let write_params = WriteParams {
+            max_rows_per_file: 5,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert!(dataset.schema().field("blob").unwrap().is_blob());
+        assert_eq!(count_files(&dataset, BLOB_FILE_EXTENSION).await, 4);
+
+        // Scans read the values from the blob files.
+        let scanned = scan(&dataset, &["i", "blob"], false).await;
+        assert_eq!(scanned.columns(), batch.columns());
+
+        let scanned = scan(&dataset, &["i"], true).await;
+        let row_ids = scanned[ROW_ID].as_primitive::<UInt64Type>().values();
+        let row_ids = [row_ids[12], row_ids[3], row_ids[1]];
+
+        let blobs = dataset.take_blobs(&row_ids, "blob").await.unwrap();
+        assert_eq!(blobs.len(), 3);
+        assert!(blobs[1].is_none());
+        let blob = blobs[0].as_ref().unwrap();
+        assert_eq!(blob.size(), 1200);
+        assert_eq!(blob.read().await.unwrap().as_ref(), blob_value(12).unwrap());
+        let blob = blobs[2].as_ref().unwrap();
+        assert_eq!(blob.read_range(10..30).await.unwrap().as_ref(), [1; 20]);
+        assert!(blob.read_range(50..101).await.is_err());
+        assert!(dataset.take_blobs(&row_ids, "i").await.is_err());
+
+        // Compaction rewrites the values to new blob files, and the cleanup removes the
+        // blob files of the old data files.
+        compact_files(&mut dataset, CompactionOptions::default(), None)
+            .await
+            .unwrap();
+        assert_eq!(dataset.get_fragments().len(), 1);
+        let scanned = scan(&dataset, &["i", "blob"], false).await;
+        assert_eq!(scanned.columns(), batch.columns());
+        // The files have real modification times, so the clock is moved past them.
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap();
+        clock.set_system_time(Duration::from_std(now).unwrap() + Duration::days(10));
+        dataset
+            .cleanup_old_versions(Duration::days(1), Some(true), None)
+            .await
+            .unwrap();
+        assert_eq!(count_files(&dataset, BLOB_FILE_EXTENSION).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_blob_column() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int32,
+            false,
+        )
+        .with_metadata([(BLOB_KEY.to_string(), "true".to_string())].into())]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        assert!(Dataset::write(reader, test_uri, None).await.is_err());
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Lightweight branches of a dataset.
+//!
+//! A branch has its own lineage of manifests, stored under `_branches/{name}`,
+//! but shares the data, deletion and index directories of the dataset. Creating
+//! a branch only writes one manifest, and the files written on a branch never
+//! collide with the files of other branches because their names are unique.
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use futures::stream::BoxStream;
+use lance_core::io::commit::{CommitError, CommitHandler, ManifestWriter};
+use object_store::path::Path;
+use object_store::ObjectStore as OSObjectStore;
+
+use crate::format::{Index, Manifest};
+use crate::io::ObjectStore;
+use crate::Result;
+
+const BRANCHES_DIR: &str = "_branches";
+
+/// Resolves the manifests of a branch, delegating the commits to the commit
+/// handler of the dataset.
+#[derive(Debug)]
+pub struct BranchCommitHandler {
+    root: Arc<dyn CommitHandler>,
+    name: String,
+}
+
+impl BranchCommitHandler {
+    pub(crate) fn new(root: Arc<dyn CommitHandler>, name: &str) -> Self {
+        Self {
+            root,
+            name: name.to_string(),
+        }
+    }
+
+    /// The name of the branch.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The commit handler of the dataset the branch belongs to.
+    pub(crate) fn root(&self) -> &Arc<dyn CommitHandler> {
+        &self.root
+    }
+
+    fn branch_base(&self, base_path: &Path) -> Path {
+        branch_base(base_path, &self.name)
+    }
+}
+
+#[async_trait]
+impl CommitHandler for BranchCommitHandler {
+    async fn resolve_latest_version(
+        &self,
+        base_path: &Path,
+        object_store: &dyn OSObjectStore,
+    ) -> Result<Path> {
+        self.root
+            .resolve_latest_version(&self.branch_base(base_path), object_store)
+            .await
+    }
+
+    async fn resolve_latest_version_id(
+        &self,
+        base_path: &Path,
+        object_store: &dyn OSObjectStore,
+    ) -> Result<u64> {
+        self.root
+            .resolve_latest_version_id(&self.branch_base(base_path), object_store)
+            .await
+    }
+
+    async fn resolve_version(
+        &self,
+        base_path: &Path,
+        version: u64,
+        object_store: &dyn OSObjectStore,
+    ) -> Result<Path> {
+        self.root
+            .resolve_version(&self.branch_base(base_path), version, object_store)
+            .await
+    }
+
+    async fn list_manifests<'a>(
+        &self,
+        base_path: &Path,
+        object_store: &'a dyn OSObjectStore,
+    ) -> Result<BoxStream<'a, Result<Path>>> {
+        self.root
+            .list_manifests(&self.branch_base(base_path), object_store)
+            .await
+    }
+
+    async fn commit(
+        &self,
+        manifest: &mut Manifest,
+        indices: Option<Vec<Index>>,
+        base_path: &Path,
+        object_store: &dyn OSObjectStore,
+        manifest_writer: ManifestWriter,
+    ) -> std::result::Result<(), CommitError> {
+        self.root
+            .commit(
+                manifest,
+                indices,
+                &self.branch_base(base_path),
+                object_store,
+                manifest_writer,
+            )
+            .await
+    }
+}
+
+/// The directory holding the manifests of a branch.
+pub(crate) fn branch_base(base: &Path, name: &str) -> Path {
+    base.child(BRANCHES_DIR).child(name)
+}
+
+/// List the names of the branches of the dataset at `base`.
+pub(crate) async fn list_branches(object_store: &ObjectStore, base: &Path) -> Result<Vec<String>> {
+    let mut branches = object_store.read_dir(base.child(BRANCHES_DIR)).await?;
+    branches.sort();
+    Ok(branches)
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow_array::types::Int32Type;
+    use lance_datagen::{array, gen, BatchCount, RowCount};
+    use tempfile::tempdir;
+
+    use crate::dataset::Dataset;
+
+    fn data() -> impl arrow_array::RecordBatchReader + Send + 'static {
+        gen()
+            .col(Some("i".to_string()), array::step::<Int32Type>())
+            .into_reader_rows(RowCount::from(10), BatchCount::from(1))
+    }
+
+    #[tokio::test]
+    async fn test_branches() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let mut dataset = Dataset::write(data(), test_uri, None).await.unwrap();
+        dataset.append(data(), None).await.unwrap();
+        assert_eq!(dataset.branch(), None);
+        assert!(dataset.list_branches().await.unwrap().is_empty());
+
+        // The branch starts at the requested version, and gets its own lineage.
+        let mut experiment = dataset.create_branch("experiment", 1).await.unwrap();
+        assert_eq!(experiment.branch(), Some("experiment"));
+        assert_eq!(experiment.version().version, 1);
+        assert_eq!(experiment.count_rows().await.unwrap(), 10);
+        experiment.append(data(), None).await.unwrap();
+        experiment.append(data(), None).await.unwrap();
+        experiment.delete("i < 5").await.unwrap();
+        assert_eq!(experiment.version().version, 4);
+        assert_eq!(experiment.count_rows().await.unwrap(), 15);
+        assert_eq!(experiment.versions().await.unwrap().len(), 4);
+
+        // The main lineage is not affected.
+        let dataset = Dataset::open(test_uri).await.unwrap();
+        assert_eq!(dataset.version().version, 2);
+        assert_eq!(dataset.count_rows().await.unwrap(), 20);
+        assert_eq!(dataset.versions().await.unwrap().len(), 2);
+
+        // Versions are resolved on the lineage of the dataset.
+        let experiment = dataset.checkout_branch("experiment").await.unwrap();
+        assert_eq!(experiment.version().version, 4);
+        let previous = experiment.checkout_version(2).await.unwrap();
+        assert_eq!(previous.branch(), Some("experiment"));
+        assert_eq!(previous.count_rows().await.unwrap(), 20);
+
+        // Branches can be created from branches, and tags always refer to the main lineage.
+        let nested = experiment.create_branch("nested", 4).await.unwrap();
+        assert_eq!(nested.count_rows().await.unwrap(), 15);
+        nested.tags().create("main-v1", 1).await.unwrap();
+        let tagged = nested.checkout_tag("main-v1").await.unwrap();
+        assert_eq!(tagged.branch(), None);
+        assert_eq!(tagged.count_rows().await.unwrap(), 10);
+        assert_eq!(
+            dataset.list_branches().await.unwrap(),
+            vec!["experiment".to_string(), "nested".to_string()]
+        );
+
+        assert!(dataset.create_branch("experiment", 1).await.is_err());
+        assert!(dataset.create_branch("missing-version", 3).await.is_err());
+        assert!(dataset.create_branch("../escape", 1).await.is_err());
+        assert!(dataset.checkout_branch("missing").await.is_err());
+
+        dataset.delete_branch("nested").await.unwrap();
+        assert!(dataset.checkout_branch("nested").await.is_err());
+        assert!(dataset.delete_branch("nested").await.is_err());
+        assert_eq!(
+            dataset.list_branches().await.unwrap(),
+            vec!["experiment".to_string()]
+        );
+    }
+}
@@ -1,24 +1,24 @@
 use std::{collections::HashMap, sync::Arc, time::Duration};
 
 use lance_core::io::{
-    commit::CommitHandler,
+    commit::{CommitConfig, CommitHandler},
     object_store::{ObjectStore, ObjectStoreParams},
 };
 use object_store::{aws::AwsCredentialProvider, DynObjectStore};
 use snafu::{location, Location};
 use url::Url;
 
-use super::{ReadParams, DEFAULT_INDEX_CACHE_SIZE, DEFAULT_METADATA_CACHE_SIZE};
+use super::{ReadParams, DEFAULT_METADATA_CACHE_SIZE};
 use crate::{
     error::{Error, Result},
-    session::Session,
+    session::{IndexCacheConfig, Session},
     Dataset,
 };
 /// builder for loading a [`Dataset`].
 #[derive(Debug, Clone)]
 pub struct DatasetBuilder {
-    /// Cache size for index cache. If it is zero, index cache is disabled.
-    index_cache_size: usize,
+    /// Configuration of the index cache. If its size is zero, index cache is disabled.
+    index_cache_config: IndexCacheConfig,
     /// Metadata cache size for the fragment metadata. If it is zero, metadata
     /// cache is disabled.
     metadata_cache_size: usize,
@@ -26,17 +26,19 @@ pub struct DatasetBuilder {
     options: ObjectStoreParams,
     version: Option<u64>,
     table_uri: String,
+    commit_config: CommitConfig,
 }
 
 impl DatasetBuilder {
     pub fn from_uri<T: AsRef<str>>(table_uri: T) -> Self {
         Self {
-            index_cache_size: DEFAULT_INDEX_CACHE_SIZE,
+            index_cache_config: IndexCacheConfig::default(),
             metadata_cache_size: DEFAULT_METADATA_CACHE_SIZE,
             table_uri: table_uri.as_ref().to_string(),
             options: ObjectStoreParams::default(),
             session: None,
             version: None,
+            commit_config: CommitConfig::default(),
         }
     }
 }
@@ -46,7 +48,20 @@ impl DatasetBuilder {
 impl DatasetBuilder {
     /// Set the cache size for indices. Set to zero, to disable the cache.
     pub fn with_index_cache_size(mut self, cache_size: usize) -> Self {
-        self.index_cache_size = cache_size;
+        self.index_cache_config.max_entries = cache_size;
+        self
+    }
+
+    /// Bound the index cache by the memory held by the cached indices, in bytes,
+    /// instead of by the number of entries.
+    pub fn with_index_cache_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.index_cache_config.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Evict a cached index `ttl` after it is loaded.
+    pub fn with_index_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.index_cache_config.time_to_live = Some(ttl);
         self
     }
 
@@ -76,6 +91,13 @@ impl DatasetBuilder {
         self
     }
 
+    /// Set the number of times to retry the commits of the dataset after a
+    /// concurrent commit.
+    pub fn with_commit_retries(mut self, num_retries: u32) -> Self {
+        self.commit_config.num_retries = num_retries;
+        self
+    }
+
     /// Sets the s3 credentials refresh.
     /// This only applies to s3 storage.
     pub fn with_s3_credentials_refresh_offset(mut self, offset: Duration) -> Self {
@@ -125,9 +147,9 @@ impl DatasetBuilder {
 
     /// Set options based on [ReadParams].
     pub fn with_read_params(mut self, read_params: ReadParams) -> Self {
-        self = self
-            .with_index_cache_size(read_params.index_cache_size)
-            .with_metadata_cache_size(read_params.metadata_cache_size);
+        self.index_cache_config = read_params.index_cache_config();
+        self = self.with_metadata_cache_size(read_params.metadata_cache_size);
+        self = self.with_commit_retries(read_params.commit_retries);
 
         if let Some(options) = read_params.store_options {
             self.options = options;
@@ -144,7 +166,7 @@ impl DatasetBuilder {
     ///
     /// The session holds caches for index and metadata.
     ///
-    /// If this is set, then `with_index_cache_size` and `with_metadata_cache_size` are ignored.
+    /// If this is set, then the index cache options and `with_metadata_cache_size` are ignored.
     pub fn with_session(mut self, session: Arc<Session>) -> Self {
         self.session = Some(session);
         self
@@ -171,13 +193,14 @@ impl DatasetBuilder {
     pub async fn load(mut self) -> Result<Dataset> {
         let session = match self.session.take() {
             Some(session) => session,
-            None => Arc::new(Session::new(
-                self.index_cache_size,
+            None => Arc::new(Session::with_index_cache_config(
+                &self.index_cache_config,
                 self.metadata_cache_size,
             )),
         };
 
         let version = self.version;
+        let commit_config = self.commit_config.clone();
 
         let object_store = self.build_object_store().await?;
         let base_path = object_store.base_path();
@@ -199,12 +222,14 @@ impl DatasetBuilder {
                 })?,
         };
 
-        Dataset::checkout_manifest(
+        let mut dataset = Dataset::checkout_manifest(
             Arc::new(object_store.clone()),
             base_path.clone(),
             &manifest,
             session,
         )
-        .await
+        .await?;
+        dataset.commit_config = commit_config;
+        Ok(dataset)
     }
 }
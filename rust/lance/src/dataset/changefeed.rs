@@ -0,0 +1,245 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A changefeed of the rows changed by each version of a dataset, for incremental
+//! consumers such as streaming systems.
+//!
+//! The consumer keeps the version up to which it has processed the changes as its
+//! cursor, and resumes with [`Dataset::changes_since`] from it.
+
+use arrow_array::RecordBatch;
+use futures::stream::{self, BoxStream};
+use futures::{Stream, StreamExt, TryStreamExt};
+use snafu::{location, Location};
+
+use super::diff::DatasetDiff;
+use super::Dataset;
+use crate::{Error, Result};
+
+/// The kind of change of the rows of a [`ChangeBatch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    /// The batch has the `_rowid` of the deleted rows.
+    Delete,
+    /// The batch has the new values of the updated rows, with their `_rowid`.
+    Update,
+    /// The batch has the inserted rows, with their `_rowid`.
+    Insert,
+}
+
+/// The rows changed in the same way by a version.
+#[derive(Debug, Clone)]
+pub struct ChangeBatch {
+    /// The version which made the change.
+    pub version: u64,
+    pub kind: ChangeKind,
+    pub batch: RecordBatch,
+}
+
+/// The ordered changes of a changefeed, see [`Dataset::changes_since`].
+pub type ChangeStream = BoxStream<'static, Result<ChangeBatch>>;
+
+pub(super) async fn changes_since(dataset: &Dataset, version: u64) -> Result<ChangeStream> {
+    let latest = dataset.version().version;
+    if version > latest {
+        return Err(Error::invalid_input(
+            format!(
+                "Version {} is later than the version {} of the dataset",
+                version, latest
+            ),
+            location!(),
+        ));
+    }
+    // The versions in between may have been cleaned up, but the changes are computed
+    // from the rows of the versions, so only the one of the cursor is needed.
+    let versions = dataset
+        .versions()
+        .await?
+        .into_iter()
+        .map(|v| v.version)
+        .filter(|v| (version..=latest).contains(v))
+        .collect::<Vec<_>>();
+    if versions.first() != Some(&version) {
+        return Err(Error::invalid_input(
+            format!(
+                "Version {} of the dataset does not exist anymore, so the changes since it \
+                can not be computed",
+                version
+            ),
+            location!(),
+        ));
+    }
+
+    // Each version is checked out once, and diffed with the previous one, which only
+    // reads the fragments changed by the version.
+    let from = dataset.checkout_version(version).await?;
+    let dataset = dataset.clone();
+    let diffs = stream::try_unfold(
+        (from, versions.into_iter().skip(1)),
+        move |(from, mut versions)| {
+            let dataset = dataset.clone();
+            async move {
+                let Some(to) = versions.next() else {
+                    return Ok::<_, Error>(None);
+                };
+                let to = dataset.checkout_version(to).await?;
+                let diff = DatasetDiff::try_new(from, to.clone()).await?;
+                Ok(Some((version_changes(diff), (to, versions))))
+            }
+        },
+    );
+    Ok(diffs.try_flatten().boxed())
+}
+
+/// The changes of one version, with the deletions first, since without stable row ids
+/// the inserted rows can reuse the row ids of the deleted ones.
+fn version_changes(diff: DatasetDiff) -> impl Stream<Item = Result<ChangeBatch>> {
+    let version = diff.to_version();
+    let changes = [
+        (ChangeKind::Delete, diff.deleted()),
+        (ChangeKind::Update, diff.updated()),
+        (ChangeKind::Insert, diff.inserted()),
+    ];
+    stream::iter(changes).flat_map(move |(kind, batches)| {
+        batches.map_ok(move |batch| ChangeBatch {
+            version,
+            kind,
+            batch,
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ops::Range;
+    use std::sync::Arc;
+
+    use arrow_array::{
+        cast::AsArray, types::UInt64Type, Int32Array, RecordBatchIterator, RecordBatchReader,
+    };
+    use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
+    use tempfile::tempdir;
+
+    use crate::dataset::{WriteMode, WriteParams, ROW_ID};
+
+    fn data(values: Range<i32>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, false),
+            ArrowField::new("j", DataType::Int32, false),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(values.clone())),
+                Arc::new(Int32Array::from_iter_values(values)),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    fn summary(changes: &[ChangeBatch]) -> Vec<(u64, ChangeKind, usize)> {
+        changes
+            .iter()
+            .map(|change| (change.version, change.kind, change.batch.num_rows()))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_changes_since() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let mut dataset = Dataset::write(
+            data(0..100),
+            test_uri,
+            Some(WriteParams {
+                enable_stable_row_ids: true,
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        let append = WriteParams {
+            mode: WriteMode::Append,
+            ..Default::default()
+        };
+        dataset.append(data(100..110), Some(append)).await.unwrap();
+        dataset.delete("i < 5").await.unwrap();
+        dataset
+            .update(Some("i >= 50 AND i < 53"), &[("j", "0")])
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, 4);
+
+        let changes = dataset
+            .changes_since(1)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            summary(&changes),
+            vec![
+                (2, ChangeKind::Insert, 10),
+                (3, ChangeKind::Delete, 5),
+                (4, ChangeKind::Update, 3),
+            ]
+        );
+        assert_eq!(
+            changes[1].batch[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            vec![0, 1, 2, 3, 4]
+        );
+        assert_eq!(
+            changes[2].batch[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            vec![50, 51, 52]
+        );
+
+        // Resume from a cursor.
+        let changes = dataset
+            .changes_since(3)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(summary(&changes), vec![(4, ChangeKind::Update, 3)]);
+
+        let changes = dataset
+            .changes_since(4)
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert!(changes.is_empty());
+        assert!(matches!(
+            dataset.changes_since(5).await,
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            dataset.changes_since(0).await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
@@ -62,6 +62,8 @@ use std::{
     sync::{Mutex, MutexGuard},
 };
 
+use super::blob::{blob_data_file_path, BLOB_FILE_EXTENSION};
+use super::branch;
 use crate::{utils::temporal::utc_now, Dataset};
 
 #[derive(Clone, Debug, Default)]
@@ -93,12 +95,16 @@ struct CleanupTask<'a> {
     before: DateTime<Utc>,
     /// If true, delete unverified data files even if they are recent
     delete_unverified: bool,
+    /// If true, keep the versions pointed to by tags even if they are old
+    keep_tagged: bool,
 }
 
 /// Information about the dataset that we learn by inspecting all of the manifests
 #[derive(Clone, Debug, Default)]
 struct CleanupInspection {
     old_manifests: Vec<Path>,
+    /// The versions of the old manifests
+    old_versions: HashSet<u64>,
     /// Referenced files are part of our working set
     referenced_files: ReferencedFiles,
     /// Verified files may or may not be part of the working set but they are
@@ -112,22 +118,52 @@ struct CleanupInspection {
 const UNVERIFIED_THRESHOLD_DAYS: i64 = 7;
 
 impl<'a> CleanupTask<'a> {
-    fn new(dataset: &'a Dataset, before: DateTime<Utc>, delete_unverified: bool) -> Self {
+    fn new(
+        dataset: &'a Dataset,
+        before: DateTime<Utc>,
+        delete_unverified: bool,
+        keep_tagged: bool,
+    ) -> Self {
         Self {
             dataset,
             before,
             delete_unverified,
+            keep_tagged,
         }
     }
 
     async fn run(self) -> Result<RemovalStats> {
+        // Tags refer to the versions of the main lineage.
+        let tags = if self.dataset.branch().is_none() {
+            self.dataset.tags().list().await?
+        } else {
+            Default::default()
+        };
+        let tagged_versions = if self.keep_tagged {
+            tags.values().map(|tag| tag.version).collect()
+        } else {
+            HashSet::new()
+        };
+
         // First we process all manifest files in parallel to figure
         // out which files are referenced by valid manifests
-        let inspection = self.process_manifests().await?;
-        self.delete_unreferenced_files(inspection).await
+        let inspection = self.process_manifests(&tagged_versions).await?;
+        let old_versions = inspection.old_versions.clone();
+        let removal_stats = self.delete_unreferenced_files(inspection).await?;
+
+        // Don't leave tags pointing to removed versions.
+        for (name, tag) in tags {
+            if old_versions.contains(&tag.version) {
+                self.dataset.tags().delete(&name).await?;
+            }
+        }
+        Ok(removal_stats)
     }
 
-    async fn process_manifests(&'a self) -> Result<CleanupInspection> {
+    async fn process_manifests(
+        &'a self,
+        tagged_versions: &HashSet<u64>,
+    ) -> Result<CleanupInspection> {
         let inspection = Mutex::new(CleanupInspection::default());
         self.dataset
             .object_store
@@ -135,15 +171,41 @@ impl<'a> CleanupTask<'a> {
             .list_manifests(&self.dataset.base, &self.dataset.object_store.inner)
             .await?
             .try_for_each_concurrent(num_cpus::get(), |path| {
-                self.process_manifest_file(path, &inspection)
+                self.process_manifest_file(path, false, tagged_versions, &inspection)
             })
             .await?;
+
+        // The other lineages (the main one and the branches) share the data files
+        // of the dataset, so all the files they reference are kept.
+        let mut other_lineages =
+            branch::list_branches(&self.dataset.object_store, &self.dataset.base)
+                .await?
+                .into_iter()
+                .filter(|name| self.dataset.branch() != Some(name.as_str()))
+                .map(Some)
+                .collect::<Vec<_>>();
+        if self.dataset.branch().is_some() {
+            other_lineages.push(None);
+        }
+        for lineage in other_lineages {
+            let (object_store, _) = self.dataset.lineage_object_store(lineage.as_deref());
+            object_store
+                .commit_handler
+                .list_manifests(&self.dataset.base, &object_store.inner)
+                .await?
+                .try_for_each_concurrent(num_cpus::get(), |path| {
+                    self.process_manifest_file(path, true, tagged_versions, &inspection)
+                })
+                .await?;
+        }
         Ok(inspection.into_inner().unwrap())
     }
 
     async fn process_manifest_file(
         &self,
         path: Path,
+        other_lineage: bool,
+        tagged_versions: &HashSet<u64>,
         inspection: &Mutex<CleanupInspection>,
     ) -> Result<()> {
         // TODO: We can't cleanup invalid manifests.  There is no way to distinguish
@@ -158,7 +220,10 @@ impl<'a> CleanupTask<'a> {
         // if their version is newer than the dataset version.  These are either in-progress
         // or newly added since we started.
         let is_latest = dataset_version <= manifest.version;
-        let in_working_set = is_latest || manifest.timestamp() >= self.before;
+        let in_working_set = other_lineage
+            || is_latest
+            || tagged_versions.contains(&manifest.version)
+            || manifest.timestamp() >= self.before;
         let indexes = read_manifest_indexes(&self.dataset.object_store, &path, &manifest).await?;
 
         let mut inspection = inspection.lock().unwrap();
@@ -166,6 +231,7 @@ impl<'a> CleanupTask<'a> {
         self.process_manifest(&manifest, &indexes, in_working_set, &mut inspection)?;
         if !in_working_set {
             inspection.old_manifests.push(path.clone());
+            inspection.old_versions.insert(manifest.version);
         }
         Ok(())
     }
@@ -187,7 +253,7 @@ impl<'a> CleanupTask<'a> {
 
         for fragment in manifest.fragments.iter() {
             for file in fragment.files.iter() {
-                let full_data_path = self.dataset.data_dir().child(file.path.as_str());
+                let full_data_path = self.dataset.data_file_path(file);
                 let relative_data_path = remove_prefix(&full_data_path, &self.dataset.base);
                 referenced_files.data_paths.insert(relative_data_path);
             }
@@ -311,8 +377,14 @@ impl<'a> CleanupTask<'a> {
             }
         }
         match path.extension() {
-            Some("lance") => {
+            Some("lance") | Some(BLOB_FILE_EXTENSION) => {
                 if relative_path.as_ref().starts_with("data") {
+                    // Blob files are kept or removed with the data file they are named after.
+                    let relative_path = if path.extension() == Some(BLOB_FILE_EXTENSION) {
+                        blob_data_file_path(&relative_path)
+                    } else {
+                        relative_path
+                    };
                     if inspection
                         .referenced_files
                         .data_paths
@@ -396,12 +468,21 @@ impl<'a> CleanupTask<'a> {
 /// even if it is older than the `before` parameter.
 ///
 /// The `before` parameter must be at least 7 days before the current date.
+///
+/// The versions pointed to by tags are also considered valid, unless `keep_tagged`
+/// is false, in which case the tags of the removed versions are deleted with them.
 pub async fn cleanup_old_versions(
     dataset: &Dataset,
     before: DateTime<Utc>,
     delete_unverified: Option<bool>,
+    keep_tagged: Option<bool>,
 ) -> Result<RemovalStats> {
-    let cleanup = CleanupTask::new(dataset, before, delete_unverified.unwrap_or(false));
+    let cleanup = CleanupTask::new(
+        dataset,
+        before,
+        delete_unverified.unwrap_or(false),
+        keep_tagged.unwrap_or(true),
+    );
     cleanup.run().await
 }
 
@@ -469,10 +550,7 @@ mod tests {
 
     use crate::{
         dataset::{builder::DatasetBuilder, ReadParams, WriteMode, WriteParams},
-        index::{
-            vector::{StageParams, VectorIndexParams},
-            DatasetIndexExt,
-        },
+        index::{vector::VectorIndexParams, DatasetIndexExt},
         io::{
             object_store::{ObjectStoreParams, WrappingObjectStore},
             ObjectStore,
@@ -631,12 +709,17 @@ mod tests {
             Ok(())
         }
 
+        async fn drop_some_index(&self) -> Result<()> {
+            let mut db = self.open().await?;
+            db.drop_index("some_index").await
+        }
+
         async fn create_some_index(&self) -> Result<()> {
             let mut db = self.open().await?;
-            let index_params = Box::new(VectorIndexParams {
-                stages: vec![StageParams::DiskANN(Default::default())],
-                metric_type: MetricType::L2,
-            });
+            let index_params = Box::new(VectorIndexParams::with_diskann_params(
+                MetricType::L2,
+                Default::default(),
+            ));
             db.create_index(
                 &["indexable"],
                 IndexType::Vector,
@@ -688,7 +771,7 @@ mod tests {
 
         async fn run_cleanup(&self, before: DateTime<Utc>) -> Result<RemovalStats> {
             let db = self.open().await?;
-            cleanup_old_versions(&db, before, None).await
+            cleanup_old_versions(&db, before, None, None).await
         }
 
         async fn run_cleanup_with_override(
@@ -697,7 +780,7 @@ mod tests {
             delete_unverified: Option<bool>,
         ) -> Result<RemovalStats> {
             let db = self.open().await?;
-            cleanup_old_versions(&db, before, delete_unverified).await
+            cleanup_old_versions(&db, before, delete_unverified, None).await
         }
 
         async fn open(&self) -> Result<Box<Dataset>> {
@@ -786,6 +869,86 @@ mod tests {
         assert_gt!(after_count.num_tx_files, 0);
     }
 
+    #[tokio::test]
+    async fn cleanup_tagged_versions() {
+        let fixture = MockDatasetFixture::try_new().unwrap();
+        fixture.create_some_data().await.unwrap();
+        fixture.overwrite_some_data().await.unwrap();
+        fixture.overwrite_some_data().await.unwrap();
+        let db = fixture.open().await.unwrap();
+        db.tags().create("first", 1).await.unwrap();
+
+        fixture.clock.set_system_time(Duration::days(10));
+        let before = utc_now() - Duration::days(8);
+
+        // Tagged versions are kept by default.
+        let removed = fixture.run_cleanup(before).await.unwrap();
+        assert_eq!(removed.old_versions, 1);
+        let first = db.checkout_tag("first").await.unwrap();
+        assert_eq!(first.version().version, 1);
+        first.validate().await.unwrap();
+
+        // Otherwise they are removed with their tags.
+        let removed = cleanup_old_versions(&db, before, None, Some(false))
+            .await
+            .unwrap();
+        assert_eq!(removed.old_versions, 1);
+        assert!(db.tags().list().await.unwrap().is_empty());
+        assert!(db.checkout_version(1).await.is_err());
+        assert_eq!(fixture.count_files().await.unwrap().num_manifest_files, 2);
+    }
+
+    #[tokio::test]
+    async fn cleanup_keeps_files_of_branches() {
+        // Branches share the data files of the dataset, so the files of an old
+        // version must be kept while a branch still references them.
+        let fixture = MockDatasetFixture::try_new().unwrap();
+        fixture.create_some_data().await.unwrap();
+        let num_rows = fixture.count_rows().await.unwrap();
+        let db = fixture.open().await.unwrap();
+        db.create_branch("experiment", 1).await.unwrap();
+        fixture.overwrite_some_data().await.unwrap();
+
+        fixture.clock.set_system_time(Duration::days(10));
+
+        let before_count = fixture.count_files().await.unwrap();
+        let removed = fixture
+            .run_cleanup(utc_now() - Duration::days(8))
+            .await
+            .unwrap();
+        let after_count = fixture.count_files().await.unwrap();
+        assert_eq!(removed.old_versions, 1);
+        assert_eq!(after_count.num_data_files, before_count.num_data_files);
+
+        let branch = fixture
+            .open()
+            .await
+            .unwrap()
+            .checkout_branch("experiment")
+            .await
+            .unwrap();
+        assert_eq!(branch.count_rows().await.unwrap(), num_rows);
+        branch.validate().await.unwrap();
+
+        // Cleaning up the branch keeps the files of the main lineage.
+        cleanup_old_versions(&branch, utc_now() - Duration::days(8), None, None)
+            .await
+            .unwrap();
+        assert_eq!(fixture.count_files().await.unwrap(), after_count);
+        assert_eq!(fixture.count_rows().await.unwrap(), num_rows);
+
+        // Once the branch is deleted, its files are removed.
+        branch.delete_branch("experiment").await.unwrap();
+        fixture
+            .run_cleanup(utc_now() - Duration::days(8))
+            .await
+            .unwrap();
+        assert_lt!(
+            fixture.count_files().await.unwrap().num_data_files,
+            after_count.num_data_files
+        );
+    }
+
     #[tokio::test]
     async fn do_not_cleanup_newer_data() {
         // Even though an old manifest is removed the data files should
@@ -930,6 +1093,29 @@ mod tests {
         assert_eq!(after_count.num_tx_files, 1);
     }
 
+    #[tokio::test]
+    async fn cleanup_dropped_index() {
+        let fixture = MockDatasetFixture::try_new().unwrap();
+        fixture.create_some_data().await.unwrap();
+        fixture.create_some_index().await.unwrap();
+        fixture.drop_some_index().await.unwrap();
+        fixture.clock.set_system_time(Duration::days(10));
+
+        let before_count = fixture.count_files().await.unwrap();
+        assert_eq!(before_count.num_index_files, 1);
+        assert_eq!(before_count.num_manifest_files, 4);
+
+        let before = utc_now() - Duration::days(8);
+        let removed = fixture.run_cleanup(before).await.unwrap();
+
+        let after_count = fixture.count_files().await.unwrap();
+        assert_eq!(removed.old_versions, 2);
+        assert_eq!(after_count.num_index_files, 0);
+        // The data file is still referenced by the latest version.
+        assert_eq!(after_count.num_data_files, 1);
+        assert_eq!(after_count.num_manifest_files, 2);
+    }
+
     #[tokio::test]
     async fn clean_old_delete_files() {
         let fixture = MockDatasetFixture::try_new().unwrap();
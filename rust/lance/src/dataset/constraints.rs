@@ -0,0 +1,379 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The unique key constraint of a dataset, checked by the appends and upserts.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+use arrow_array::{cast::AsArray, types::UInt64Type, Array, ArrayRef};
+use arrow_cast::display::array_value_to_string;
+use arrow_row::{RowConverter, SortField};
+use arrow_schema::DataType;
+use datafusion::physical_plan::{stream::RecordBatchStreamAdapter, SendableRecordBatchStream};
+use futures::TryStreamExt;
+use lance_core::ROW_ID;
+use lance_index::IndexType;
+use snafu::{location, Location};
+
+use super::transaction::Operation;
+use super::Dataset;
+use crate::datatypes::{Field, Schema};
+use crate::index::{scalar::ScalarIndexParams, DatasetIndexExt};
+use crate::{Error, Result};
+
+/// The number of key values looked up in the dataset by each scan.
+pub(super) const LOOKUP_BATCH_SIZE: usize = 1024;
+
+/// The unique key column of the dataset of `schema`, if any.
+pub(super) fn unique_key(schema: &Schema) -> Option<&Field> {
+    schema.fields.iter().find(|field| field.is_unique())
+}
+
+pub(super) fn is_key_type(data_type: &DataType) -> bool {
+    data_type.is_integer() || matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+}
+
+fn duplicate_error(column: &str, value: &str) -> Error {
+    Error::invalid_input(
+        format!("Duplicate value {} of the unique key {}", value, column),
+        location!(),
+    )
+}
+
+/// Set the unique key of the dataset, or remove it if `column` is None, returning the
+/// operation to commit.
+///
+/// The values of the column must be unique already. A scalar index is created on the
+/// column if it has none, to look up the keys of the rows written afterwards.
+pub(super) async fn set_unique_key(
+    dataset: &mut Dataset,
+    column: Option<&str>,
+) -> Result<Operation> {
+    let mut schema = dataset.schema().clone();
+    for field in schema.fields.iter_mut() {
+        field.set_unique(false);
+    }
+    if let Some(column) = column {
+        let field = schema
+            .fields
+            .iter_mut()
+            .find(|field| field.name == column)
+            .ok_or_else(|| {
+                Error::invalid_input(
+                    format!("Column {} does not exist in the dataset", column),
+                    location!(),
+                )
+            })?;
+        if !is_key_type(&field.data_type()) {
+            return Err(Error::invalid_input(
+                format!(
+                    "The unique key must be an integer or string column, but {} is {}",
+                    column,
+                    field.data_type()
+                ),
+                location!(),
+            ));
+        }
+        field.set_unique(true);
+        let field_id = field.id;
+
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?;
+        let values = scanner
+            .try_into_stream()
+            .await?
+            .map_ok(|batch| batch.column(0).clone())
+            .try_collect::<Vec<_>>()
+            .await?;
+        if let Some(value) = find_duplicate(&values)? {
+            return Err(duplicate_error(column, &value));
+        }
+
+        let indices = dataset.load_indices().await?;
+        if !indices.iter().any(|index| index.fields == [field_id]) {
+            dataset
+                .create_index(
+                    &[column],
+                    IndexType::Scalar,
+                    None,
+                    &ScalarIndexParams::default(),
+                    false,
+                )
+                .await?;
+        }
+    }
+    Ok(Operation::Merge {
+        fragments: dataset.manifest.fragments.as_ref().clone(),
+        schema,
+    })
+}
+
+/// A value appearing twice in `values`. Null values are not compared, like in SQL.
+fn find_duplicate(values: &[ArrayRef]) -> Result<Option<String>> {
+    let Some(first) = values.first() else {
+        return Ok(None);
+    };
+    let converter = RowConverter::new(vec![SortField::new(first.data_type().clone())])?;
+    let mut seen = HashSet::new();
+    for array in values {
+        let rows = converter.convert_columns(std::slice::from_ref(array))?;
+        for (idx, row) in rows.iter().enumerate() {
+            if array.is_valid(idx) && !seen.insert(row.owned()) {
+                return Ok(Some(array_value_to_string(array, idx)?));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The SQL literal of a value of the unique key.
+pub(super) fn to_literal(array: &dyn Array, idx: usize) -> Result<String> {
+    let value = array_value_to_string(array, idx)?;
+    Ok(match array.data_type() {
+        DataType::Utf8 | DataType::LargeUtf8 => format!("'{}'", value.replace('\'', "''")),
+        _ => value,
+    })
+}
+
+/// The SQL identifier of a column, quoted so that it can have any name.
+pub(super) fn to_identifier(column: &str) -> String {
+    format!("`{}`", column.replace('`', "``"))
+}
+
+/// Check that the `values` of the unique key `column` written to the dataset are
+/// unique, and that no row of the dataset has one of them, except the rows for which
+/// `is_replaced` is true, i.e., deleted by the same transaction.
+///
+/// The rows are looked up with the scalar index of the column.
+pub(super) async fn check_unique_key(
+    dataset: &Dataset,
+    column: &str,
+    values: &[ArrayRef],
+    is_replaced: impl Fn(u64) -> bool,
+) -> Result<()> {
+    if let Some(value) = find_duplicate(values)? {
+        return Err(duplicate_error(column, &value));
+    }
+
+    let mut literals = Vec::new();
+    for array in values {
+        for idx in 0..array.len() {
+            if array.is_valid(idx) {
+                literals.push(to_literal(array.as_ref(), idx)?);
+            }
+        }
+    }
+    for chunk in literals.chunks(LOOKUP_BATCH_SIZE) {
+        let mut scanner = dataset.scan();
+        scanner.with_row_id().project(&[column])?.filter(&format!(
+            "{} IN ({})",
+            to_identifier(column),
+            chunk.join(", ")
+        ))?;
+        let mut stream = scanner.try_into_stream().await?;
+        while let Some(batch) = stream.try_next().await? {
+            let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+            for (idx, row_id) in row_ids.values().iter().enumerate() {
+                if !is_replaced(*row_id) {
+                    let value = array_value_to_string(&batch[column], idx)?;
+                    return Err(duplicate_error(column, &value));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The values of the unique key column of the batches of a stream written to a
+/// dataset, checked before committing them.
+pub(super) struct UniqueKeyValues {
+    column: String,
+    values: Arc<Mutex<Vec<ArrayRef>>>,
+}
+
+impl UniqueKeyValues {
+    /// Collect the values of the unique key of `schema`, if it has one, from the
+    /// batches of `stream`.
+    pub(super) fn track(
+        schema: &Schema,
+        stream: SendableRecordBatchStream,
+    ) -> (SendableRecordBatchStream, Option<Self>) {
+        let Some(field) = unique_key(schema) else {
+            return (stream, None);
+        };
+        let column = field.name.clone();
+        let values = Arc::new(Mutex::new(Vec::new()));
+
+        let arrow_schema = stream.schema();
+        let tracked_column = column.clone();
+        let tracked_values = values.clone();
+        let stream = stream.inspect_ok(move |batch| {
+            if let Some(array) = batch.column_by_name(&tracked_column) {
+                tracked_values.lock().unwrap().push(array.clone());
+            }
+        });
+        let stream = Box::pin(RecordBatchStreamAdapter::new(arrow_schema, stream));
+        (stream, Some(Self { column, values }))
+    }
+
+    /// Check the values collected, see [`check_unique_key`].
+    pub(super) async fn check(&self, dataset: &Dataset) -> Result<()> {
+        let values = std::mem::take(&mut *self.values.lock().unwrap());
+        check_unique_key(dataset, &self.column, &values, |_| false).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Int64Array, RecordBatch, RecordBatchIterator, StringArray};
+    use arrow_schema::{Field as ArrowField, Schema as ArrowSchema};
+    use tempfile::tempdir;
+
+    #[tokio::test]
+    async fn test_unique_key() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int64, false),
+            ArrowField::new("name", DataType::Utf8, true),
+        ]));
+        let data = |ids: Vec<i64>| {
+            let names = ids
+                .iter()
+                .map(|id| format!("name-{}", id))
+                .collect::<Vec<_>>();
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from(ids)),
+                    Arc::new(StringArray::from(names)),
+                ],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let mut dataset = Dataset::write(data(vec![1, 2, 3, 3]), test_uri, None)
+            .await
+            .unwrap();
+
+        // The existing values must be unique.
+        let result = dataset.set_unique_key(Some("id")).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+        dataset.delete("id = 3").await.unwrap();
+        dataset.set_unique_key(Some("id")).await.unwrap();
+        assert_eq!(dataset.unique_key(), Some("id"));
+        // The key is backed by a scalar index.
+        let id = dataset.schema().field("id").unwrap().id;
+        assert!(dataset
+            .load_indices()
+            .await
+            .unwrap()
+            .iter()
+            .any(|index| index.fields == [id]));
+
+        dataset.append(data(vec![3, 4]), None).await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 4);
+
+        // Duplicates within the data, and with the existing rows, are rejected.
+        for ids in [vec![5, 5], vec![6, 2], vec![4]] {
+            match dataset.append(data(ids), None).await {
+                Err(Error::InvalidInput { source, .. }) => {
+                    assert!(source.to_string().starts_with("Duplicate value"))
+                }
+                result => panic!("Expected an InvalidInput error, got {:?}", result),
+            }
+        }
+        assert_eq!(dataset.count_rows().await.unwrap(), 4);
+
+        // An upsert by the key replaces the rows.
+        dataset
+            .merge_insert(data(vec![4, 5]), &["id"], None)
+            .await
+            .unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 5);
+        // An upsert by another column can replace a row, but not insert an existing key.
+        dataset
+            .merge_insert(data(vec![1]), &["name"], None)
+            .await
+            .unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(vec![1])),
+                Arc::new(StringArray::from(vec!["other"])),
+            ],
+        )
+        .unwrap();
+        let result = dataset
+            .merge_insert(
+                RecordBatchIterator::new(vec![Ok(batch)], schema.clone()),
+                &["name"],
+                None,
+            )
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        // Without the constraint, duplicates can be appended.
+        dataset.set_unique_key(None).await.unwrap();
+        assert_eq!(dataset.unique_key(), None);
+        dataset.append(data(vec![1]), None).await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 6);
+
+        let result = dataset.set_unique_key(Some("missing")).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_unique_key_concurrent_appends() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        // The column name needs to be quoted in the lookups.
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "Key Id",
+            DataType::Int64,
+            false,
+        )]));
+        let data = |ids: Vec<i64>| {
+            let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(Int64Array::from(ids))])
+                .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let mut dataset = Dataset::write(data(vec![1, 2]), test_uri, None)
+            .await
+            .unwrap();
+        dataset.set_unique_key(Some("Key Id")).await.unwrap();
+        let result = dataset.append(data(vec![2]), None).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        // Both appends are checked against the same version, so the second one can
+        // not commit.
+        let mut other = dataset.clone();
+        dataset.append(data(vec![3]), None).await.unwrap();
+        let result = other.append(data(vec![3]), None).await;
+        assert!(matches!(result, Err(Error::CommitConflict { .. })));
+        assert_eq!(dataset.count_rows().await.unwrap(), 3);
+
+        // Without a unique key, concurrent appends still commit.
+        dataset.set_unique_key(None).await.unwrap();
+        let mut other = dataset.clone();
+        dataset.append(data(vec![4]), None).await.unwrap();
+        other.append(data(vec![4]), None).await.unwrap();
+        assert_eq!(other.count_rows().await.unwrap(), 5);
+    }
+}
@@ -0,0 +1,610 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The changes of the rows of a dataset between two versions, to synchronize
+//! downstream systems incrementally.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{RecordBatch, UInt64Array};
+use arrow_schema::Schema as ArrowSchema;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use futures::{stream, StreamExt, TryStreamExt};
+use lance_arrow::RecordBatchExt;
+use lance_core::ROW_ID_FIELD;
+use roaring::RoaringBitmap;
+use snafu::{location, Location};
+
+use super::fragment::FileFragment;
+use super::rowids::row_id_sequence;
+use super::scanner::{DatasetRecordBatchStream, DEFAULT_BATCH_SIZE};
+use super::Dataset;
+use crate::format::{Fragment, RowAddress};
+use crate::{Error, Result};
+
+/// The rows inserted, updated and deleted between two versions of a dataset.
+///
+/// The rows are identified by their `_rowid`, which is their stable row id if both
+/// versions have stable row ids, or else their row address. Without stable row ids,
+/// a row rewritten by an update or a compaction gets a new address, so it is
+/// reported as deleted and inserted again, and the deletions must be applied before
+/// the insertions since an overwrite can reuse addresses.
+///
+/// Only the changes of the rows are reported, not the changes of the schema, e.g.,
+/// the values of a column added to the existing rows.
+///
+/// Only the fragments changed between the versions are read, and the rows deleted
+/// from a fragment are read from its deletion files.
+#[derive(Debug, Clone)]
+pub struct DatasetDiff {
+    from: Dataset,
+    to: Dataset,
+    /// The row ids and addresses, in `to`, of the inserted rows.
+    inserted: Vec<(u64, u64)>,
+    /// The row ids and addresses, in `to`, of the updated rows.
+    updated: Vec<(u64, u64)>,
+    deleted: Vec<u64>,
+    batch_size: usize,
+}
+
+impl DatasetDiff {
+    pub(super) async fn try_new(from: Dataset, to: Dataset) -> Result<Self> {
+        let stable_row_ids = from.has_stable_row_ids() && to.has_stable_row_ids();
+
+        // Only the fragments which changed between the versions are read, so that the
+        // cost of a diff is proportional to the changes rather than to the dataset.
+        let mut from_fragments = from
+            .get_fragments()
+            .into_iter()
+            .map(|fragment| (fragment.id() as u64, fragment))
+            .collect::<HashMap<_, _>>();
+        let mut changes = Vec::new();
+        for fragment in to.get_fragments() {
+            match from_fragments.remove(&(fragment.id() as u64)) {
+                Some(from_fragment)
+                    if same_fragment(from_fragment.metadata(), fragment.metadata()) =>
+                {
+                    if from_fragment.metadata().deletion_file != fragment.metadata().deletion_file {
+                        changes.push(FragmentChange::Deleted {
+                            from: from_fragment,
+                            to: fragment,
+                        });
+                    }
+                }
+                // The id was reused by another fragment, e.g., of an overwrite, so all
+                // the rows of the fragment of `from` were removed.
+                Some(from_fragment) => {
+                    changes.push(FragmentChange::Removed(from_fragment));
+                    changes.push(FragmentChange::Added(fragment));
+                }
+                None => changes.push(FragmentChange::Added(fragment)),
+            }
+        }
+        changes.extend(from_fragments.into_values().map(FragmentChange::Removed));
+
+        // The ids and addresses, in `from`, of the rows which are not at the same
+        // address in `to`, and the ids and addresses, in `to`, of the rows which are
+        // not at the same address in `from`.
+        let mut removed = Vec::new();
+        let mut added = Vec::new();
+        let mut rows = stream::iter(changes)
+            .map(|change| change.rows(stable_row_ids))
+            .buffered(num_cpus::get());
+        while let Some((change_removed, change_added)) = rows.try_next().await? {
+            removed.extend(change_removed);
+            added.extend(change_added);
+        }
+
+        let mut diff = Self {
+            from,
+            to,
+            inserted: Vec::new(),
+            updated: Vec::new(),
+            deleted: Vec::new(),
+            batch_size: DEFAULT_BATCH_SIZE,
+        };
+        if !stable_row_ids {
+            diff.inserted = added;
+            diff.deleted = removed.into_iter().map(|(id, _)| id).collect();
+            diff.deleted.sort();
+            return Ok(diff);
+        }
+
+        // A row with the same stable row id in both versions was moved, either by an
+        // update or by a compaction which left its values unchanged.
+        let mut removed = removed.into_iter().collect::<HashMap<_, _>>();
+        let mut moved = Vec::new();
+        for (id, address) in added {
+            match removed.remove(&id) {
+                Some(from_address) => moved.push((id, from_address, address)),
+                None => diff.inserted.push((id, address)),
+            }
+        }
+        diff.deleted = removed.into_keys().collect();
+        diff.deleted.sort();
+        diff.updated = diff.changed_rows(moved).await?;
+        Ok(diff)
+    }
+
+    /// Set the number of rows per batch of the streams.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn from_version(&self) -> u64 {
+        self.from.version().version
+    }
+
+    pub fn to_version(&self) -> u64 {
+        self.to.version().version
+    }
+
+    pub fn num_inserted(&self) -> usize {
+        self.inserted.len()
+    }
+
+    pub fn num_updated(&self) -> usize {
+        self.updated.len()
+    }
+
+    pub fn num_deleted(&self) -> usize {
+        self.deleted.len()
+    }
+
+    /// The inserted rows, as of [`Self::to_version`], with their `_rowid`.
+    pub fn inserted(&self) -> DatasetRecordBatchStream {
+        self.take_stream(&self.inserted)
+    }
+
+    /// The new values of the updated rows, with their `_rowid`.
+    pub fn updated(&self) -> DatasetRecordBatchStream {
+        self.take_stream(&self.updated)
+    }
+
+    /// The `_rowid` of the deleted rows, in batches of a single column.
+    pub fn deleted(&self) -> DatasetRecordBatchStream {
+        let schema = Arc::new(ArrowSchema::new(vec![ROW_ID_FIELD.clone()]));
+        let batches = self
+            .deleted
+            .chunks(self.batch_size)
+            .map(|ids| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(UInt64Array::from(ids.to_vec()))],
+                )
+                .map_err(DataFusionError::from)
+            })
+            .collect::<Vec<_>>();
+        DatasetRecordBatchStream::new(Box::pin(RecordBatchStreamAdapter::new(
+            schema,
+            stream::iter(batches),
+        )))
+    }
+
+    /// The rows of `to` with their row ids, taken by address.
+    fn take_stream(&self, rows: &[(u64, u64)]) -> DatasetRecordBatchStream {
+        let dataset = Arc::new(self.to.clone());
+        let projection = Arc::new(dataset.schema().clone());
+        let mut fields = ArrowSchema::from(projection.as_ref()).fields().to_vec();
+        fields.push(Arc::new(ROW_ID_FIELD.clone()));
+        let schema = Arc::new(ArrowSchema::new(fields));
+        let chunks = rows
+            .chunks(self.batch_size)
+            .map(|chunk| chunk.to_vec())
+            .collect::<Vec<_>>();
+        let batches = stream::iter(chunks)
+            .then(move |chunk| {
+                let dataset = dataset.clone();
+                let projection = projection.clone();
+                async move {
+                    let (ids, addresses): (Vec<_>, Vec<_>) = chunk.into_iter().unzip();
+                    let batch = dataset.take_rows(&addresses, &projection).await?;
+                    let row_ids = Arc::new(UInt64Array::from(ids));
+                    Ok::<_, Error>(batch.try_with_column(ROW_ID_FIELD.clone(), row_ids)?)
+                }
+            })
+            .map_err(DataFusionError::from);
+        DatasetRecordBatchStream::new(Box::pin(RecordBatchStreamAdapter::new(schema, batches)))
+    }
+
+    /// The rows moved from `from` to `to` whose values changed, in the columns of both.
+    ///
+    /// The rows are given by their id and their addresses in `from` and `to`.
+    async fn changed_rows(&self, moved: Vec<(u64, u64, u64)>) -> Result<Vec<(u64, u64)>> {
+        let columns = self
+            .to
+            .schema()
+            .fields
+            .iter()
+            .filter(|f| self.from.schema().field(&f.name).is_some())
+            .map(|f| f.name.as_str())
+            .collect::<Vec<_>>();
+        if columns.is_empty() || moved.is_empty() {
+            return Ok(Vec::new());
+        }
+        let from_projection = self.from.schema().project(&columns)?;
+        let to_projection = self.to.schema().project(&columns)?;
+
+        let mut changed = Vec::new();
+        for chunk in moved.chunks(self.batch_size) {
+            let from_addresses = chunk.iter().map(|row| row.1).collect::<Vec<_>>();
+            let to_addresses = chunk.iter().map(|row| row.2).collect::<Vec<_>>();
+            let old = self
+                .from
+                .take_rows(&from_addresses, &from_projection)
+                .await?;
+            let new = self.to.take_rows(&to_addresses, &to_projection).await?;
+            for (idx, (id, _, address)) in chunk.iter().enumerate() {
+                let differs = old
+                    .columns()
+                    .iter()
+                    .zip(new.columns())
+                    .any(|(old, new)| old.slice(idx, 1).as_ref() != new.slice(idx, 1).as_ref());
+                if differs {
+                    changed.push((*id, *address));
+                }
+            }
+        }
+        Ok(changed)
+    }
+}
+
+/// The rows of a fragment which changed between two versions.
+enum FragmentChange {
+    /// All the rows of the fragment of the new version were added.
+    Added(FileFragment),
+    /// All the rows of the fragment of the old version were removed.
+    Removed(FileFragment),
+    /// Rows of the fragment were deleted, and no row was added to it.
+    Deleted {
+        from: FileFragment,
+        to: FileFragment,
+    },
+}
+
+impl FragmentChange {
+    /// The ids and addresses of the rows removed from the old version, and of the rows
+    /// added to the new version.
+    async fn rows(self, stable_row_ids: bool) -> Result<(Vec<(u64, u64)>, Vec<(u64, u64)>)> {
+        let rows = |fragment: &Fragment, offsets: &mut dyn Iterator<Item = u32>| {
+            offsets
+                .map(|offset| {
+                    let address = RowAddress::new_from_parts(fragment.id as u32, offset);
+                    Ok((
+                        row_id(fragment, offset, stable_row_ids)?,
+                        u64::from(address),
+                    ))
+                })
+                .collect::<Result<Vec<_>>>()
+        };
+        match self {
+            Self::Added(fragment) => {
+                let offsets = live_rows(&fragment).await?;
+                Ok((
+                    Vec::new(),
+                    rows(fragment.metadata(), &mut offsets.into_iter())?,
+                ))
+            }
+            Self::Removed(fragment) => {
+                let offsets = live_rows(&fragment).await?;
+                Ok((
+                    rows(fragment.metadata(), &mut offsets.into_iter())?,
+                    Vec::new(),
+                ))
+            }
+            Self::Deleted { from, to } => {
+                let (old, new) =
+                    futures::try_join!(from.get_deletion_vector(), to.get_deletion_vector())?;
+                let old = old.as_deref().map(RoaringBitmap::from).unwrap_or_default();
+                let new = new.as_deref().map(RoaringBitmap::from).unwrap_or_default();
+                let deleted = new - old;
+                Ok((rows(from.metadata(), &mut deleted.into_iter())?, Vec::new()))
+            }
+        }
+    }
+}
+
+/// The id of the row at `offset` in `fragment`.
+fn row_id(fragment: &Fragment, offset: u32, stable_row_ids: bool) -> Result<u64> {
+    let address = u64::from(RowAddress::new_from_parts(fragment.id as u32, offset));
+    if !stable_row_ids {
+        return Ok(address);
+    }
+    row_id_sequence(fragment)?
+        .get(offset as usize)
+        .ok_or_else(|| Error::Internal {
+            message: format!(
+                "Row {} has no stable row id",
+                RowAddress::new_from_id(address)
+            ),
+            location: location!(),
+        })
+}
+
+/// Whether the fragments with the same id in two versions are the same fragment, with
+/// the data files of the earlier version, and not a fragment of an overwrite.
+fn same_fragment(from: &Fragment, to: &Fragment) -> bool {
+    from.files
+        .iter()
+        .all(|file| to.files.iter().any(|f| f.path == file.path))
+}
+
+/// The offsets of the rows of the fragment which are not deleted.
+async fn live_rows(fragment: &FileFragment) -> Result<Vec<u32>> {
+    let physical_rows = fragment.physical_rows().await? as u32;
+    let deletion_vector = fragment.get_deletion_vector().await?;
+    Ok((0..physical_rows)
+        .filter(|offset| {
+            deletion_vector
+                .as_ref()
+                .map_or(true, |deleted| !deleted.contains(*offset))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::ops::Range;
+
+    use arrow_array::{
+        cast::AsArray, types::Int32Type, types::UInt64Type, Int32Array, RecordBatchIterator,
+        RecordBatchReader, StringArray,
+    };
+    use arrow_schema::{DataType, Field as ArrowField};
+    use arrow_select::concat::concat_batches;
+    use lance_core::io::{deletion::deletion_file_path, RecordBatchStream};
+    use tempfile::tempdir;
+
+    use crate::dataset::optimize::{compact_files, CompactionOptions};
+    use crate::dataset::{WriteMode, WriteParams, ROW_ID};
+
+    fn data(values: Range<i32>) -> impl RecordBatchReader + Send + 'static {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, false),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(values.clone())),
+                Arc::new(StringArray::from_iter_values(
+                    values.map(|i| format!("s-{}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+        RecordBatchIterator::new(vec![Ok(batch)], schema)
+    }
+
+    async fn collect(stream: DatasetRecordBatchStream) -> RecordBatch {
+        let schema = stream.schema();
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        concat_batches(&schema, &batches).unwrap()
+    }
+
+    fn append() -> Option<WriteParams> {
+        Some(WriteParams {
+            mode: WriteMode::Append,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn test_diff_stable_row_ids() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            enable_stable_row_ids: true,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data(0..300), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset.delete("i < 50").await.unwrap();
+        dataset
+            .update(Some("i >= 100 AND i < 120"), &[("s", "'updated'")])
+            .await
+            .unwrap();
+        dataset.append(data(300..310), append()).await.unwrap();
+        // The rows moved by the compaction are not reported as updated.
+        compact_files(
+            &mut dataset,
+            CompactionOptions {
+                target_rows_per_fragment: 1000,
+                materialize_deletions_threshold: 0.0,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        // The compaction also commits a version to reserve the ids of its fragments.
+        let latest = dataset.version().version;
+        assert_eq!(latest, 6);
+
+        let diff = dataset.diff(1, latest).await.unwrap().with_batch_size(7);
+        assert_eq!(diff.num_inserted(), 10);
+        assert_eq!(diff.num_updated(), 20);
+        assert_eq!(diff.num_deleted(), 50);
+
+        let inserted = collect(diff.inserted()).await;
+        assert_eq!(
+            inserted["i"].as_primitive::<Int32Type>().values().to_vec(),
+            (300..310).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            inserted[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            (300..310).collect::<Vec<_>>()
+        );
+        let updated = collect(diff.updated()).await;
+        assert_eq!(
+            updated[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            (100..120).collect::<Vec<_>>()
+        );
+        assert!(updated["s"]
+            .as_string::<i32>()
+            .iter()
+            .all(|s| s == Some("updated")));
+        let deleted = collect(diff.deleted()).await;
+        assert_eq!(
+            deleted[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            (0..50).collect::<Vec<_>>()
+        );
+
+        // Only the compaction.
+        let diff = dataset.diff(4, latest).await.unwrap();
+        assert_eq!(
+            (diff.num_inserted(), diff.num_updated(), diff.num_deleted()),
+            (0, 0, 0)
+        );
+        let diff = dataset.diff(2, 3).await.unwrap();
+        assert_eq!(
+            (diff.num_inserted(), diff.num_updated(), diff.num_deleted()),
+            (0, 20, 0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diff_row_addresses() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data(0..300), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset.delete("i < 50").await.unwrap();
+        dataset.append(data(300..310), append()).await.unwrap();
+
+        let diff = dataset.diff(1, 3).await.unwrap();
+        assert_eq!(
+            (diff.num_inserted(), diff.num_updated(), diff.num_deleted()),
+            (10, 0, 50)
+        );
+        let deleted = collect(diff.deleted()).await;
+        assert_eq!(
+            deleted[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            (0..50).collect::<Vec<_>>()
+        );
+        let inserted = collect(diff.inserted()).await;
+        assert_eq!(
+            inserted[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            (0..10)
+                .map(|offset| u64::from(RowAddress::new_from_parts(3, offset)))
+                .collect::<Vec<_>>()
+        );
+
+        // Without stable row ids, the updated rows are deleted and inserted again.
+        dataset
+            .update(Some("i >= 100 AND i < 120"), &[("s", "'updated'")])
+            .await
+            .unwrap();
+        let diff = dataset.diff(3, 4).await.unwrap();
+        assert_eq!(
+            (diff.num_inserted(), diff.num_updated(), diff.num_deleted()),
+            (20, 0, 20)
+        );
+
+        // The fragments of an overwrite reuse the ids of the fragments overwritten.
+        let dataset = Dataset::write(
+            data(0..10),
+            test_uri,
+            Some(WriteParams {
+                mode: WriteMode::Overwrite,
+                ..Default::default()
+            }),
+        )
+        .await
+        .unwrap();
+        let diff = dataset.diff(1, 5).await.unwrap();
+        assert_eq!(
+            (diff.num_inserted(), diff.num_updated(), diff.num_deleted()),
+            (10, 0, 300)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_diff_skips_unchanged_fragments() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data(0..300), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset.delete("i < 10").await.unwrap();
+        dataset.delete("i >= 100 AND i < 105").await.unwrap();
+        dataset.delete("i >= 100 AND i < 110").await.unwrap();
+
+        // The files of the fragments unchanged between the versions are not read.
+        for fragment in dataset.get_fragments() {
+            let metadata = fragment.metadata();
+            if metadata.id == 1 {
+                continue;
+            }
+            for file in &metadata.files {
+                std::fs::remove_file(test_dir.path().join("data").join(&file.path)).unwrap();
+            }
+            if let Some(deletion_file) = &metadata.deletion_file {
+                let path = deletion_file_path(&dataset.base, metadata.id, deletion_file);
+                std::fs::remove_file(format!("/{}", path)).unwrap();
+            }
+        }
+
+        // Only the rows deleted since the previous deletion file of the fragment.
+        let diff = dataset.diff(3, 4).await.unwrap();
+        assert_eq!(
+            (diff.num_inserted(), diff.num_updated(), diff.num_deleted()),
+            (0, 0, 5)
+        );
+        let deleted = collect(diff.deleted()).await;
+        assert_eq!(
+            deleted[ROW_ID]
+                .as_primitive::<UInt64Type>()
+                .values()
+                .to_vec(),
+            (5..10)
+                .map(|offset| u64::from(RowAddress::new_from_parts(1, offset)))
+                .collect::<Vec<_>>()
+        );
+    }
+}
@@ -0,0 +1,314 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Export of datasets to other file formats, for the engines that don't read Lance.
+
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use futures::StreamExt;
+use lance_core::io::object_store::{ObjectStore, ObjectStoreParams};
+use lance_core::io::{ObjectWriter, RecordBatchStream};
+use object_store::path::Path;
+use parquet::arrow::AsyncArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+use snafu::{location, Location};
+
+use crate::{Error, Result};
+
+/// The size of the buffer of encoded row groups flushed to the object store.
+const PARQUET_BUFFER_SIZE: usize = 8 * 1024 * 1024;
+
+/// Parameters of [`write_parquet`].
+#[derive(Debug, Clone)]
+pub struct ParquetWriteParams {
+    /// The maximum number of rows per Parquet file.
+    pub max_rows_per_file: usize,
+    /// The maximum number of rows per row group.
+    pub max_rows_per_group: usize,
+    /// The compression codec of the pages.
+    pub compression: Compression,
+    pub store_params: Option<ObjectStoreParams>,
+}
+
+impl Default for ParquetWriteParams {
+    fn default() -> Self {
+        Self {
+            max_rows_per_file: 1024 * 1024,
+            max_rows_per_group: 64 * 1024,
+            compression: Compression::SNAPPY,
+            store_params: None,
+        }
+    }
+}
+
+fn parquet_error(err: ParquetError) -> Error {
+    Error::IO {
+        message: format!("Failed to write Parquet file: {}", err),
+        location: location!(),
+    }
+}
+
+struct ParquetFileWriter {
+    writer: AsyncArrowWriter<ObjectWriter>,
+    num_rows: usize,
+}
+
+impl ParquetFileWriter {
+    async fn try_new(
+        object_store: &ObjectStore,
+        path: &Path,
+        schema: SchemaRef,
+        params: &ParquetWriteParams,
+    ) -> Result<Self> {
+        let object_writer = object_store.create(path).await?;
+        let properties = WriterProperties::builder()
+            .set_max_row_group_size(params.max_rows_per_group)
+            .set_compression(params.compression)
+            .build();
+        let writer =
+            AsyncArrowWriter::try_new(object_writer, schema, PARQUET_BUFFER_SIZE, Some(properties))
+                .map_err(parquet_error)?;
+        Ok(Self {
+            writer,
+            num_rows: 0,
+        })
+    }
+
+    async fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        self.writer.write(batch).await.map_err(parquet_error)?;
+        self.num_rows += batch.num_rows();
+        Ok(())
+    }
+
+    async fn finish(self) -> Result<()> {
+        self.writer.close().await.map_err(parquet_error)?;
+        Ok(())
+    }
+}
+
+/// Write a stream, e.g., the stream of a [`Scanner`](super::scanner::Scanner) over a
+/// dataset or a filtered scan of it, to a new directory of Parquet files at `uri`.
+///
+/// The files are named `part-00000.parquet`, `part-00001.parquet`, etc. Nested types,
+/// e.g., structs and lists, are written as nested Parquet columns. At least one file is
+/// written, so that the schema is kept even if the stream is empty.
+///
+/// Returns the names of the files written.
+pub async fn write_parquet(
+    mut stream: impl RecordBatchStream + Unpin,
+    uri: &str,
+    params: Option<ParquetWriteParams>,
+) -> Result<Vec<String>> {
+    let params = params.unwrap_or_default();
+    if params.max_rows_per_file == 0 || params.max_rows_per_group == 0 {
+        return Err(Error::invalid_input(
+            "The maximum numbers of rows per file and per row group must be positive",
+            location!(),
+        ));
+    }
+    let (object_store, base) =
+        ObjectStore::from_uri_and_params(uri, &params.store_params.clone().unwrap_or_default())
+            .await?;
+    if !object_store.read_dir(base.clone()).await?.is_empty() {
+        return Err(Error::invalid_input(
+            format!("Cannot write Parquet files to {}: it is not empty", uri),
+            location!(),
+        ));
+    }
+
+    let schema = stream.schema();
+    let mut file_names = vec!["part-00000.parquet".to_string()];
+    let mut writer = ParquetFileWriter::try_new(
+        &object_store,
+        &base.child(file_names[0].as_str()),
+        schema.clone(),
+        &params,
+    )
+    .await?;
+    while let Some(batch) = stream.next().await {
+        let batch = batch?;
+        let mut offset = 0;
+        while offset < batch.num_rows() {
+            if writer.num_rows == params.max_rows_per_file {
+                writer.finish().await?;
+                let file_name = format!("part-{:05}.parquet", file_names.len());
+                writer = ParquetFileWriter::try_new(
+                    &object_store,
+                    &base.child(file_name.as_str()),
+                    schema.clone(),
+                    &params,
+                )
+                .await?;
+                file_names.push(file_name);
+            }
+            let length =
+                (params.max_rows_per_file - writer.num_rows).min(batch.num_rows() - offset);
+            writer.write(&batch.slice(offset, length)).await?;
+            offset += length;
+        }
+    }
+    writer.finish().await?;
+
+    Ok(file_names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use arrow_array::{
+        builder::{Int32Builder, ListBuilder},
+        FixedSizeListArray, Float32Array, Int32Array, RecordBatchIterator, StringArray,
+        StructArray,
+    };
+    use arrow_schema::{DataType, Field, Fields, Schema as ArrowSchema};
+    use arrow_select::concat::concat_batches;
+    use lance_arrow::FixedSizeListArrayExt;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use tempfile::tempdir;
+
+    use crate::dataset::{Dataset, WriteParams};
+
+    #[tokio::test]
+    async fn test_write_parquet() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().join("dataset");
+        let test_uri = test_uri.to_str().unwrap();
+        let parquet_dir = test_dir.path().join("parquet");
+        let parquet_uri = parquet_dir.to_str().unwrap();
+
+        let struct_fields = Fields::from(vec![
+            Field::new("name", DataType::Utf8, true),
+            Field::new("score", DataType::Float32, true),
+        ]);
+        let schema = Arc::new(ArrowSchema::new(vec![
+            Field::new("i", DataType::Int32, false),
+            Field::new("meta", DataType::Struct(struct_fields.clone()), true),
+            Field::new(
+                "tags",
+                DataType::List(Arc::new(Field::new("item", DataType::Int32, true))),
+                true,
+            ),
+            Field::new(
+                "vec",
+                DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 2),
+                true,
+            ),
+        ]));
+        let num_rows = 250;
+        let mut tags = ListBuilder::new(Int32Builder::new());
+        for i in 0..num_rows {
+            tags.values().append_slice(&vec![i; i as usize % 3]);
+            tags.append(true);
+        }
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..num_rows)),
+                Arc::new(StructArray::new(
+                    struct_fields,
+                    vec![
+                        Arc::new(StringArray::from_iter_values(
+                            (0..num_rows).map(|i| format!("row-{}", i)),
+                        )),
+                        Arc::new(Float32Array::from_iter_values(
+                            (0..num_rows).map(|i| i as f32 / 2.0),
+                        )),
+                    ],
+                    None,
+                )),
+                Arc::new(tags.finish()),
+                Arc::new(
+                    FixedSizeListArray::try_new_from_values(
+                        Float32Array::from_iter_values((0..num_rows * 2).map(|i| i as f32)),
+                        2,
+                    )
+                    .unwrap(),
+                ),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch.clone())], schema.clone());
+        let write_params = WriteParams {
+            max_rows_per_group: 64,
+            ..Default::default()
+        };
+        let dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+
+        let params = ParquetWriteParams {
+            max_rows_per_file: 100,
+            max_rows_per_group: 30,
+            ..Default::default()
+        };
+        let stream = dataset.scan().try_into_stream().await.unwrap();
+        let files = write_parquet(stream, parquet_uri, Some(params.clone()))
+            .await
+            .unwrap();
+        assert_eq!(
+            files,
+            vec![
+                "part-00000.parquet",
+                "part-00001.parquet",
+                "part-00002.parquet"
+            ]
+        );
+
+        let mut batches = Vec::new();
+        for (file, expected_rows) in files.iter().zip([100, 100, 50]) {
+            let file = std::fs::File::open(parquet_dir.join(file)).unwrap();
+            let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+            let metadata = reader.metadata().clone();
+            assert_eq!(metadata.file_metadata().num_rows(), expected_rows);
+            assert!(metadata
+                .row_groups()
+                .iter()
+                .all(|row_group| row_group.num_rows() <= 30));
+            batches.extend(reader.build().unwrap().map(|batch| batch.unwrap()));
+        }
+        let actual = concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(actual.schema().fields(), schema.fields());
+        assert_eq!(actual.columns(), batch.columns());
+
+        // The directory must be empty.
+        let stream = dataset.scan().try_into_stream().await.unwrap();
+        assert!(matches!(
+            write_parquet(stream, parquet_uri, Some(params)).await,
+            Err(Error::InvalidInput { .. })
+        ));
+
+        // A filtered scan with no rows still writes the schema.
+        let empty_dir = test_dir.path().join("empty");
+        let stream = dataset
+            .scan()
+            .filter("i < 0")
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap();
+        let files = write_parquet(stream, empty_dir.to_str().unwrap(), None)
+            .await
+            .unwrap();
+        assert_eq!(files, vec!["part-00000.parquet"]);
+        let file = std::fs::File::open(empty_dir.join(&files[0])).unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file).unwrap();
+        assert_eq!(reader.schema().fields(), schema.fields());
+        assert_eq!(reader.metadata().file_metadata().num_rows(), 0);
+    }
+}
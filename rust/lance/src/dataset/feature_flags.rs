@@ -16,9 +16,15 @@
 use crate::format::Manifest;
 
 pub const FLAG_DELETION_FILES: u64 = 1;
+pub const FLAG_STABLE_ROW_IDS: u64 = 2;
+pub const FLAG_BLOB_FILES: u64 = 4;
+pub const FLAG_PARTITIONED: u64 = 8;
 
 /// Set the reader and writer feature flags in the manifest based on the contents of the manifest.
-pub fn apply_feature_flags(manifest: &mut Manifest) {
+///
+/// Whether the dataset uses stable row ids can't be told from the contents of an empty
+/// dataset, so it is given by `stable_row_ids`.
+pub fn apply_feature_flags(manifest: &mut Manifest, stable_row_ids: bool) {
     // Reset flags
     manifest.reader_feature_flags = 0;
     manifest.writer_feature_flags = 0;
@@ -32,14 +38,40 @@ pub fn apply_feature_flags(manifest: &mut Manifest) {
         manifest.reader_feature_flags |= FLAG_DELETION_FILES;
         manifest.writer_feature_flags |= FLAG_DELETION_FILES;
     }
+
+    if stable_row_ids {
+        // Readers need the row id sequences to look up the rows by their stable row
+        // id, and writers need to assign and carry them over.
+        manifest.reader_feature_flags |= FLAG_STABLE_ROW_IDS;
+        manifest.writer_feature_flags |= FLAG_STABLE_ROW_IDS;
+    }
+
+    let has_blob_columns = manifest.schema.fields.iter().any(|field| field.is_blob());
+    if has_blob_columns {
+        // The data files of the blob columns only hold references to the blob files.
+        manifest.reader_feature_flags |= FLAG_BLOB_FILES;
+        manifest.writer_feature_flags |= FLAG_BLOB_FILES;
+    }
+
+    if !manifest.partition_columns.is_empty() {
+        // The data files are in the directories of their partitions, and writers
+        // need to write the new rows to the directories of their partitions.
+        manifest.reader_feature_flags |= FLAG_PARTITIONED;
+        manifest.writer_feature_flags |= FLAG_PARTITIONED;
+    }
+}
+
+/// Whether the dataset of the manifest uses stable row ids.
+pub fn has_stable_row_ids(manifest: &Manifest) -> bool {
+    manifest.reader_feature_flags & FLAG_STABLE_ROW_IDS != 0
 }
 
 pub fn can_read_dataset(reader_flags: u64) -> bool {
-    reader_flags <= 1
+    reader_flags <= 15
 }
 
 pub fn can_write_dataset(writer_flags: u64) -> bool {
-    writer_flags <= 1
+    writer_flags <= 15
 }
 
 #[cfg(test)]
@@ -50,13 +82,25 @@ mod tests {
     fn test_read_check() {
         assert!(can_read_dataset(0));
         assert!(can_read_dataset(super::FLAG_DELETION_FILES));
-        assert!(!can_read_dataset(super::FLAG_DELETION_FILES + 1));
+        assert!(can_read_dataset(
+            super::FLAG_DELETION_FILES
+                | super::FLAG_STABLE_ROW_IDS
+                | super::FLAG_BLOB_FILES
+                | super::FLAG_PARTITIONED
+        ));
+        assert!(!can_read_dataset(super::FLAG_PARTITIONED << 1));
     }
 
     #[test]
     fn test_write_check() {
         assert!(can_write_dataset(0));
         assert!(can_write_dataset(super::FLAG_DELETION_FILES));
-        assert!(!can_write_dataset(super::FLAG_DELETION_FILES + 1));
+        assert!(can_write_dataset(
+            super::FLAG_DELETION_FILES
+                | super::FLAG_STABLE_ROW_IDS
+                | super::FLAG_BLOB_FILES
+                | super::FLAG_PARTITIONED
+        ));
+        assert!(!can_write_dataset(super::FLAG_PARTITIONED << 1));
     }
 }
@@ -15,21 +15,27 @@
 //! Wraps a Fragment of the dataset.
 
 use std::borrow::Cow;
+use std::collections::HashMap;
 use std::ops::Range;
 use std::sync::Arc;
 
-use arrow_array::cast::as_primitive_array;
-use arrow_array::{RecordBatch, RecordBatchReader, UInt64Array};
+use arrow_array::cast::{as_primitive_array, AsArray};
+use arrow_array::types::Int64Type;
+use arrow_array::{
+    new_null_array, Array, RecordBatch, RecordBatchReader, StructArray, UInt64Array,
+};
+use arrow_schema::Field as ArrowField;
+use datafusion::scalar::ScalarValue;
 use futures::future::try_join_all;
 use futures::stream::BoxStream;
 use futures::{join, StreamExt, TryFutureExt, TryStreamExt};
 use lance_core::format::DeletionFile;
 use lance_core::{
-    datatypes::Schema,
+    datatypes::{Field, Schema},
     io::{
         deletion::{deletion_file_path, read_deletion_file, write_deletion_file, DeletionVector},
         object_store::ObjectStore,
-        FileReader, FileWriter, ReadBatchParams,
+        FileReader, FileWriter, ReadBatchParams, Reader,
     },
     Error, Result, ROW_ID,
 };
@@ -38,14 +44,81 @@ use object_store::path::Path;
 use snafu::{location, Location};
 use uuid::Uuid;
 
+use super::blob::{blob_file_path, load_blobs, BlobWriter};
 use super::hash_joiner::HashJoiner;
 use super::scanner::Scanner;
+use super::schema_evolution::parse_default_value;
 use super::updater::Updater;
-use super::write::reader_to_stream;
+use super::write::{file_writer_options, reader_to_stream};
 use super::WriteParams;
 use crate::arrow::*;
 use crate::dataset::{Dataset, DATA_DIR};
-use crate::format::Fragment;
+use crate::format::{DataFile, Fragment};
+
+/// The statistics of a column of a [`FileFragment`], aggregated from the page-level
+/// statistics of its data file. The deleted rows are included.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStatistics {
+    pub null_count: u64,
+    /// The minimum value, or None if it is unknown or all the values are null.
+    pub min_value: Option<ScalarValue>,
+    /// The maximum value, or None if it is unknown or all the values are null.
+    pub max_value: Option<ScalarValue>,
+}
+
+impl ColumnStatistics {
+    /// Aggregate the page-level statistics `stats`, whose children are `null_count`,
+    /// `min_value` and `max_value`, of the pages with `page_rows` rows.
+    fn try_from_page_stats(stats: &StructArray, page_rows: &[usize]) -> Result<Self> {
+        let (Some(null_counts), Some(min_values), Some(max_values)) = (
+            stats.column_by_name("null_count"),
+            stats.column_by_name("min_value"),
+            stats.column_by_name("max_value"),
+        ) else {
+            return Err(Error::Internal {
+                message: format!("Invalid page statistics: {:?}", stats.data_type()),
+                location: location!(),
+            });
+        };
+        let null_counts = null_counts.as_primitive::<Int64Type>();
+
+        let mut null_count = 0;
+        let mut bounds: Option<(ScalarValue, ScalarValue)> = None;
+        let mut known = true;
+        for page in 0..stats.len() {
+            let page_null_count = null_counts.value(page) as usize;
+            null_count += page_null_count as u64;
+            if page_rows.get(page) == Some(&page_null_count) {
+                // All the values of the page are null.
+                continue;
+            }
+            // The bounds can be missing even if the page has values, e.g., when a
+            // string is too long to be truncated into a valid maximum.
+            if min_values.is_null(page) || max_values.is_null(page) {
+                known = false;
+                continue;
+            }
+            let page_min = ScalarValue::try_from_array(min_values, page)?;
+            let page_max = ScalarValue::try_from_array(max_values, page)?;
+            bounds = Some(match bounds {
+                None => (page_min, page_max),
+                Some((min, max)) => (
+                    if page_min < min { page_min } else { min },
+                    if page_max > max { page_max } else { max },
+                ),
+            });
+        }
+        let (min_value, max_value) = match bounds {
+            Some((min, max)) if known => (Some(min), Some(max)),
+            _ => (None, None),
+        };
+        Ok(Self {
+            null_count,
+            min_value,
+            max_value,
+        })
+    }
+}
 
 /// A Fragment of a Lance [`Dataset`].
 ///
@@ -69,6 +142,16 @@ impl FileFragment {
     /// Fragments can be created distributed first, before a central machine to
     /// commit the dataset with these fragments.
     ///
+    /// Each worker writes the data files of its fragments, and sends the returned
+    /// [`Fragment`], which is serializable, to the driver. The driver then commits all
+    /// of them at once, e.g., with [`Operation::Append`] or [`Operation::Overwrite`] in
+    /// [`Dataset::commit`]. The fragments are given new ids when they are committed, so
+    /// `id` does not need to be unique across the workers.
+    ///
+    /// The data files are written with the object store parameters of `params`.
+    ///
+    /// [`Operation::Append`]: super::transaction::Operation::Append
+    /// [`Operation::Overwrite`]: super::transaction::Operation::Overwrite
     pub async fn create(
         dataset_uri: &str,
         id: usize,
@@ -88,7 +171,11 @@ impl FileFragment {
             ));
         }
 
-        let (object_store, base_path) = ObjectStore::from_uri(dataset_uri).await?;
+        let (object_store, base_path) = ObjectStore::from_uri_and_params(
+            dataset_uri,
+            &params.store_params.clone().unwrap_or_default(),
+        )
+        .await?;
         let filename = format!("{}.lance", Uuid::new_v4());
         let mut fragment = Fragment::with_file(id as u64, &filename, &schema, None);
         let full_path = base_path.child(DATA_DIR).child(filename.clone());
@@ -96,18 +183,25 @@ impl FileFragment {
             &object_store,
             &full_path,
             schema.clone(),
-            &Default::default(),
+            &file_writer_options(&schema),
         )
         .await?;
+        let mut blob_writer = BlobWriter::try_new(&object_store, &full_path, &schema).await?;
 
         progress.begin(&fragment, writer.multipart_id()).await?;
 
         let mut buffered_reader = chunk_stream(stream, params.max_rows_per_group);
         while let Some(batched_chunk) = buffered_reader.next().await {
-            let batch = batched_chunk?;
+            let mut batch = batched_chunk?;
+            if let Some(blob_writer) = blob_writer.as_mut() {
+                batch = blob_writer.write_batches(&batch).await?;
+            }
             writer.write(&batch).await?;
         }
 
+        if let Some(blob_writer) = blob_writer.as_mut() {
+            blob_writer.finish().await?;
+        }
         fragment.physical_rows = Some(writer.finish().await?);
 
         progress.complete(&fragment).await?;
@@ -151,21 +245,59 @@ impl FileFragment {
         let full_schema = self.dataset.schema();
 
         let mut opened_files = vec![];
+        let mut blob_columns = vec![];
         for data_file in self.metadata.files.iter() {
             let data_file_schema = data_file.schema(full_schema);
             let schema_per_file = data_file_schema.intersection(projection)?;
             if !schema_per_file.fields.is_empty() {
-                let path = self.dataset.data_dir().child(data_file.path.as_str());
-                let reader = FileReader::try_new_with_fragment(
-                    &self.dataset.object_store,
-                    &path,
-                    self.id() as u64,
-                    Some(self.dataset.manifest.as_ref()),
-                    Some(&self.dataset.session.file_metadata_cache),
-                )
-                .await?;
-                let initialized_schema = reader.schema().project_by_schema(&schema_per_file)?;
-                opened_files.push((reader, initialized_schema));
+                opened_files.push(self.open_file(data_file, &schema_per_file).await?);
+            }
+            // The values of the blob columns are read from the blob file of their
+            // data file.
+            let blob_fields = schema_per_file
+                .fields
+                .iter()
+                .filter(|field| field.is_blob())
+                .collect::<Vec<_>>();
+            if !blob_fields.is_empty() {
+                let path = blob_file_path(&self.dataset.data_file_path(data_file));
+                let reader: Arc<dyn Reader> =
+                    Arc::from(self.dataset.object_store.open(&path).await?);
+                for field in blob_fields {
+                    blob_columns.push((field.clone(), reader.clone()));
+                }
+            }
+        }
+
+        // The columns added without data files after the fragment was written read
+        // their default value, or null.
+        let mut default_columns = vec![];
+        for field in projection.fields.iter() {
+            if field.id >= 0
+                && !self
+                    .metadata
+                    .files
+                    .iter()
+                    .any(|data_file| data_file.fields.contains(&field.id))
+            {
+                let value = field
+                    .default_value()
+                    .map(|value| parse_default_value(value, &field.data_type()))
+                    .transpose()?;
+                default_columns.push((ArrowField::from(field), value));
+            }
+        }
+
+        // A column must be read to know the number of rows, it is dropped afterwards.
+        let mut placeholder_column = None;
+        if opened_files.is_empty() && !default_columns.is_empty() {
+            if let Some(data_file) = self.metadata.files.first() {
+                let data_file_schema = data_file.schema(full_schema);
+                if let Some(field) = data_file_schema.fields.first() {
+                    let schema = data_file_schema.project(&[&field.name])?;
+                    opened_files.push(self.open_file(data_file, &schema).await?);
+                    placeholder_column = Some(field.name.clone());
+                }
             }
         }
 
@@ -180,7 +312,97 @@ impl FileFragment {
             });
         }
 
-        FragmentReader::try_new(self.id(), opened_files)
+        let mut reader = FragmentReader::try_new(self.id(), opened_files)?;
+        reader.default_columns = default_columns;
+        reader.placeholder_column = placeholder_column;
+        reader.blob_columns = blob_columns;
+        Ok(reader)
+    }
+
+    /// Open a data file, to read the fields of `schema`.
+    async fn open_file(
+        &self,
+        data_file: &DataFile,
+        schema: &Schema,
+    ) -> Result<(FileReader, Schema)> {
+        let path = self.dataset.data_file_path(data_file);
+        let reader = FileReader::try_new_with_fragment(
+            &self.dataset.object_store,
+            &path,
+            self.id() as u64,
+            Some(self.dataset.manifest.as_ref()),
+            Some(&self.dataset.session.file_metadata_cache),
+        )
+        .await?;
+        // The fields may have been renamed since the file was written, so match
+        // them by their ids.
+        let mut file_schema = reader.schema().clone();
+        for id in schema.field_ids() {
+            if let (Some(field), Some(file_field)) =
+                (schema.field_by_id(id), file_schema.mut_field_by_id(id))
+            {
+                file_field.name = field.name.clone();
+            }
+        }
+        let initialized_schema = file_schema.project_by_schema(schema)?;
+        Ok((reader, initialized_schema))
+    }
+
+    /// The statistics of the top-level `columns`, from the page-level statistics of the
+    /// data files.
+    ///
+    /// The columns without statistics, e.g., written by an older version of Lance, are
+    /// left out.
+    pub async fn column_statistics(
+        &self,
+        columns: &[&str],
+    ) -> Result<HashMap<String, ColumnStatistics>> {
+        let full_schema = self.dataset.schema();
+        let field_ids = columns
+            .iter()
+            .filter_map(|name| full_schema.field(name))
+            .map(|field| field.id)
+            .collect::<Vec<_>>();
+
+        let mut statistics = HashMap::new();
+        for data_file in self.metadata.files.iter() {
+            if !data_file.fields.iter().any(|id| field_ids.contains(id)) {
+                continue;
+            }
+            let path = self.dataset.data_file_path(data_file);
+            let reader = FileReader::try_new_with_fragment(
+                &self.dataset.object_store,
+                &path,
+                self.id() as u64,
+                Some(self.dataset.manifest.as_ref()),
+                Some(&self.dataset.session.file_metadata_cache),
+            )
+            .await?;
+            let Some(stats_schema) = reader.page_stats_schema(&field_ids) else {
+                continue;
+            };
+            let Some(page_stats) = reader.read_page_stats(&stats_schema).await? else {
+                continue;
+            };
+            let page_rows = (0..reader.num_batches())
+                .map(|batch_id| reader.num_rows_in_batch(batch_id as i32))
+                .collect::<Vec<_>>();
+            for (stats_field, stats) in stats_schema.fields.iter().zip(page_stats.columns()) {
+                let Some(field) = stats_field
+                    .name
+                    .parse::<i32>()
+                    .ok()
+                    .and_then(|id| full_schema.field_by_id(id))
+                else {
+                    continue;
+                };
+                statistics.insert(
+                    field.name.clone(),
+                    ColumnStatistics::try_from_page_stats(stats.as_struct(), &page_rows)?,
+                );
+            }
+        }
+        Ok(statistics)
     }
 
     /// Count the rows in this fragment.
@@ -236,10 +458,7 @@ impl FileFragment {
         }
 
         // Just open any file. All of them should have same size.
-        let path = self
-            .dataset
-            .data_dir()
-            .child(self.metadata.files[0].path.as_str());
+        let path = self.dataset.data_file_path(&self.metadata.files[0]);
         let reader = FileReader::try_new_with_fragment(
             &self.dataset.object_store,
             &path,
@@ -264,7 +483,7 @@ impl FileFragment {
             .metadata
             .files
             .iter()
-            .map(|data_file| self.dataset.data_dir().child(data_file.path.as_str()))
+            .map(|data_file| self.dataset.data_file_path(data_file))
             .collect::<Vec<_>>();
         let get_lengths = data_file_paths.iter().map(|path| {
             let reader = FileReader::try_new_with_fragment(
@@ -303,9 +522,7 @@ impl FileFragment {
         if let Some(physical_rows) = self.metadata.physical_rows {
             if physical_rows != *expected_length {
                 return Err(Error::corrupt_file(
-                    self.dataset
-                        .data_dir()
-                        .child(self.metadata.files[0].path.as_str()),
+                    self.dataset.data_file_path(&self.metadata.files[0]),
                     format!(
                         "Fragment metadata has incorrect physical_rows. Actual: {} Metadata: {}",
                         expected_length, physical_rows
@@ -533,18 +750,7 @@ impl FileFragment {
     /// If all rows are deleted, returns `Ok(None)`. Otherwise, returns a new
     /// fragment with the updated deletion vector. This must be persisted to
     /// the manifest.
-    pub async fn delete(mut self, predicate: &str) -> Result<Option<Self>> {
-        // Load existing deletion vector
-        let mut deletion_vector = read_deletion_file(
-            &self.dataset.base,
-            &self.metadata,
-            self.dataset.object_store(),
-        )
-        .await?
-        .unwrap_or_default();
-
-        let starting_length = deletion_vector.len();
-
+    pub async fn delete(self, predicate: &str) -> Result<Option<Self>> {
         // scan with predicate and row ids
         let mut scanner = self.scan();
 
@@ -562,7 +768,8 @@ impl FileFragment {
             .filter(predicate)?
             .project::<&str>(&[])?;
 
-        // As we get row ids, add them into our deletion vector
+        // As we get row ids, collect the rows to delete
+        let mut local_row_ids = Vec::new();
         scanner
             .try_into_stream()
             .await?
@@ -573,13 +780,35 @@ impl FileFragment {
                 // _row_id is global, not within fragment level. The high bits
                 // are the fragment_id, the low bits are the row_id within the
                 // fragment.
-                let local_row_ids = int_array.iter().map(|v| v.unwrap() as u32);
-
-                deletion_vector.extend(local_row_ids);
+                local_row_ids.extend(int_array.iter().map(|v| v.unwrap() as u32));
                 futures::future::ready(Ok(()))
             })
             .await?;
 
+        self.extend_deletions(local_row_ids).await
+    }
+
+    /// Mark the rows at the given offsets within the fragment as deleted.
+    ///
+    /// If all rows are deleted, returns `Ok(None)`. Otherwise, returns a new
+    /// fragment with the updated deletion vector. This must be persisted to
+    /// the manifest.
+    pub(crate) async fn extend_deletions(
+        mut self,
+        local_row_ids: impl IntoIterator<Item = u32>,
+    ) -> Result<Option<Self>> {
+        // Load existing deletion vector
+        let mut deletion_vector = read_deletion_file(
+            &self.dataset.base,
+            &self.metadata,
+            self.dataset.object_store(),
+        )
+        .await?
+        .unwrap_or_default();
+
+        let starting_length = deletion_vector.len();
+        deletion_vector.extend(local_row_ids);
+
         // If we haven't deleted any additional rows, we can return the fragment as-is.
         if deletion_vector.len() == starting_length {
             return Ok(Some(self));
@@ -637,6 +866,21 @@ pub struct FragmentReader {
     /// Readers and schema of each opened data file.
     readers: Vec<(FileReader, Schema)>,
 
+    /// The columns without data files in the fragment, with their default value,
+    /// or None if they are null.
+    default_columns: Vec<(ArrowField, Option<ScalarValue>)>,
+
+    /// The column read only to know the number of rows, when all the columns of
+    /// the projection are default columns.
+    placeholder_column: Option<String>,
+
+    /// The blob columns of the projection, with the reader of their blob file.
+    blob_columns: Vec<(Field, Arc<dyn Reader>)>,
+
+    /// Whether the blob columns are read as the references to their values, instead
+    /// of the values.
+    read_blob_references: bool,
+
     /// ID of the fragment
     fragment_id: usize,
 }
@@ -682,10 +926,60 @@ impl FragmentReader {
         }
         Ok(Self {
             readers,
+            default_columns: vec![],
+            placeholder_column: None,
+            blob_columns: vec![],
+            read_blob_references: false,
             fragment_id,
         })
     }
 
+    /// Merge the batches read from each data file, and add the default columns.
+    fn merge_batches(&self, batches: &[RecordBatch]) -> Result<RecordBatch> {
+        let mut batch = merge_batches(batches)?;
+        for (field, value) in &self.default_columns {
+            let array = match value {
+                Some(value) => value.to_array_of_size(batch.num_rows()),
+                None => new_null_array(field.data_type(), batch.num_rows()),
+            };
+            batch = batch.try_with_column(field.clone(), array)?;
+        }
+        if let Some(name) = &self.placeholder_column {
+            batch = batch.drop_column(name)?;
+        }
+        Ok(batch)
+    }
+
+    /// Replace the references read from the blob columns with their values.
+    async fn load_blobs(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        if self.read_blob_references || self.blob_columns.is_empty() {
+            return Ok(batch);
+        }
+        let mut columns = batch.columns().to_vec();
+        for (field, reader) in &self.blob_columns {
+            if let Ok(index) = batch.schema().index_of(&field.name) {
+                columns[index] =
+                    load_blobs(reader.as_ref(), field, batch.column(index).as_ref()).await?;
+            }
+        }
+        Ok(RecordBatch::try_new(batch.schema(), columns)?)
+    }
+
+    /// Read the blob columns as the references to their values, see
+    /// [`Dataset::take_blobs`].
+    pub(crate) fn with_blob_references(&mut self) -> &mut Self {
+        self.read_blob_references = true;
+        self
+    }
+
+    /// The reader of the blob file of the blob column `name`.
+    pub(crate) fn blob_reader(&self, name: &str) -> Option<Arc<dyn Reader>> {
+        self.blob_columns
+            .iter()
+            .find(|(field, _)| field.name == name)
+            .map(|(_, reader)| reader.clone())
+    }
+
     pub(crate) fn with_row_id(&mut self) -> &mut Self {
         self.readers[0].0.with_row_id(true);
         self
@@ -719,7 +1013,7 @@ impl FragmentReader {
                 .await?;
             batches.push(batch);
         }
-        merge_batches(&batches)
+        self.load_blobs(self.merge_batches(&batches)?).await
     }
 
     pub async fn read_range(&self, range: Range<usize>) -> Result<RecordBatch> {
@@ -731,7 +1025,7 @@ impl FragmentReader {
             batches.push(batch);
         }
 
-        merge_batches(&batches)
+        self.load_blobs(self.merge_batches(&batches)?).await
     }
 
     /// Take rows from this fragment.
@@ -743,7 +1037,7 @@ impl FragmentReader {
             .boxed();
         let batches: Vec<RecordBatch> = stream.try_collect::<Vec<_>>().await?;
 
-        merge_batches(&batches)
+        self.load_blobs(self.merge_batches(&batches)?).await
     }
 }
 
@@ -751,7 +1045,7 @@ impl FragmentReader {
 mod tests {
 
     use arrow_arith::numeric::mul;
-    use arrow_array::{ArrayRef, Int32Array, RecordBatchIterator, StringArray};
+    use arrow_array::{types::Int32Type, ArrayRef, Int32Array, RecordBatchIterator, StringArray};
     use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
     use arrow_select::concat::concat_batches;
     use futures::TryStreamExt;
@@ -1248,4 +1542,71 @@ mod tests {
             in_memory_batch * 10 % 100
         );
     }
+
+    #[tokio::test]
+    async fn test_create_fragments_distributed() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int32,
+            false,
+        )]));
+        let data = |range: Range<i32>| {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(range))],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+
+        // Each worker writes its fragment, and sends the serialized metadata to the
+        // driver.
+        let mut messages = Vec::new();
+        for worker in 0..3 {
+            let fragment =
+                FileFragment::create(test_uri, 0, data(worker * 10..(worker + 1) * 10), None)
+                    .await
+                    .unwrap();
+            messages.push(serde_json::to_string(&fragment).unwrap());
+        }
+
+        let fragments = messages
+            .iter()
+            .map(|message| serde_json::from_str::<Fragment>(message).unwrap())
+            .collect::<Vec<_>>();
+        let dataset = Dataset::commit(
+            test_uri,
+            Operation::Overwrite {
+                fragments,
+                schema: Schema::try_from(schema.as_ref()).unwrap(),
+            },
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let fragment_ids = dataset
+            .get_fragments()
+            .iter()
+            .map(|fragment| fragment.id())
+            .collect::<Vec<_>>();
+        assert_eq!(fragment_ids, vec![0, 1, 2]);
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        assert_eq!(
+            batch["i"].as_primitive::<Int32Type>().values().to_vec(),
+            (0..30).collect::<Vec<_>>()
+        );
+    }
 }
@@ -93,9 +93,21 @@ impl HashJoiner {
                 async move {
                     let column = batch[on].clone();
                     let task_result = task::spawn_blocking(move || {
-                        let rows = column_to_rows(column)?;
+                        let rows = column_to_rows(column.clone())?;
                         for (row_i, row) in rows.iter().enumerate() {
-                            map.insert(row.owned(), (batch_i, row_i));
+                            // Like in SQL, a null key never matches.
+                            if column.is_null(row_i) {
+                                continue;
+                            }
+                            if map.insert(row.owned(), (batch_i, row_i)).is_some() {
+                                return Err(Error::invalid_input(
+                                    format!(
+                                        "HashJoiner: multiple rows on RHS have the same key as row {} of batch {}",
+                                        row_i, batch_i
+                                    ),
+                                    location!(),
+                                ));
+                            }
                         }
                         Ok(())
                     })
@@ -156,9 +168,13 @@ impl HashJoiner {
         // Indices are a pair of (batch_i, row_i). We'll add a null batch at the
         // end with one null element, and that's what we resolve when no match is
         // found.
-        let indices = column_to_rows(index_column)?
+        let indices = column_to_rows(index_column.clone())?
             .into_iter()
-            .map(|row| {
+            .enumerate()
+            .map(|(i, row)| {
+                if index_column.is_null(i) {
+                    return (null_index, 0);
+                }
                 self.index_map
                     .get(&row.owned())
                     .map(|(batch_i, row_i)| (*batch_i, *row_i))
@@ -0,0 +1,205 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The transaction log of a dataset: its versions, and the transactions that
+//! created them.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use lance_core::io::reader::read_manifest;
+
+use super::transaction::Transaction;
+use super::Dataset;
+use crate::io::commit::read_transaction_file;
+use crate::Result;
+
+/// A version of a dataset and the transaction that created it, returned by
+/// [`Dataset::transactions`].
+///
+/// The fragments are compared to the previous version that still exists: the changes
+/// of the versions removed by [`Dataset::cleanup_old_versions`] are attributed to the
+/// next version.
+#[derive(Debug, Clone)]
+pub struct TransactionRecord {
+    pub version: u64,
+    /// When the version was committed, in UTC.
+    pub timestamp: DateTime<Utc>,
+    /// The tag given to the version when it was committed.
+    pub tag: Option<String>,
+    /// The transaction that created the version, with its operation and properties.
+    /// It is None for the versions written before transactions were recorded.
+    pub transaction: Option<Transaction>,
+    /// The ids of the fragments that are new in this version.
+    pub added_fragment_ids: Vec<u64>,
+    /// The ids of the fragments whose data files or deletion file changed.
+    pub updated_fragment_ids: Vec<u64>,
+    /// The ids of the fragments that no longer exist in this version.
+    pub removed_fragment_ids: Vec<u64>,
+}
+
+impl TransactionRecord {
+    /// The name of the operation that created the version, e.g., "Append" or "Delete".
+    pub fn operation_name(&self) -> Option<&str> {
+        self.transaction
+            .as_ref()
+            .map(|transaction| transaction.operation.name())
+    }
+}
+
+pub(super) async fn transactions(dataset: &Dataset) -> Result<Vec<TransactionRecord>> {
+    let object_store = &dataset.object_store;
+    let mut manifests = object_store
+        .commit_handler
+        .list_manifests(&dataset.base, &object_store.inner)
+        .await?
+        .map_ok(|path| async move { read_manifest(object_store, &path).await })
+        .try_buffer_unordered(num_cpus::get() * 4)
+        .try_collect::<Vec<_>>()
+        .await?;
+    manifests.sort_by_key(|manifest| manifest.version);
+
+    let mut records = Vec::with_capacity(manifests.len());
+    let mut previous = HashMap::new();
+    for manifest in manifests {
+        let transaction = match &manifest.transaction_file {
+            Some(path) => Some(read_transaction_file(object_store, &dataset.base, path).await?),
+            None => None,
+        };
+        let fragments = manifest
+            .fragments
+            .iter()
+            .map(|fragment| (fragment.id, fragment))
+            .collect::<HashMap<_, _>>();
+
+        let mut added_fragment_ids = Vec::new();
+        let mut updated_fragment_ids = Vec::new();
+        for fragment in manifest.fragments.iter() {
+            match previous.get(&fragment.id) {
+                None => added_fragment_ids.push(fragment.id),
+                Some(old) if old != fragment => updated_fragment_ids.push(fragment.id),
+                Some(_) => {}
+            }
+        }
+        let mut removed_fragment_ids = previous
+            .keys()
+            .filter(|id| !fragments.contains_key(id))
+            .copied()
+            .collect::<Vec<_>>();
+        removed_fragment_ids.sort();
+
+        records.push(TransactionRecord {
+            version: manifest.version,
+            timestamp: manifest.timestamp(),
+            tag: manifest.tag.clone(),
+            transaction,
+            added_fragment_ids,
+            updated_fragment_ids,
+            removed_fragment_ids,
+        });
+        previous = manifest
+            .fragments
+            .iter()
+            .map(|fragment| (fragment.id, fragment.clone()))
+            .collect();
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+    use tempfile::tempdir;
+
+    use crate::dataset::{WriteMode, WriteParams};
+
+    #[tokio::test]
+    async fn test_transactions() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "i",
+            DataType::Int32,
+            false,
+        )]));
+        let data = |range: std::ops::Range<i32>| {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(range))],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let write_params = WriteParams {
+            max_rows_per_file: 10,
+            commit_properties: HashMap::from([("author".to_string(), "ingest".to_string())]),
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data(0..20), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        let write_params = WriteParams {
+            mode: WriteMode::Append,
+            max_rows_per_file: 10,
+            ..Default::default()
+        };
+        dataset
+            .append(data(20..30), Some(write_params))
+            .await
+            .unwrap();
+        // Removes the first fragment, and deletes a row of the second one.
+        dataset.delete("i < 10 or i = 15").await.unwrap();
+
+        let records = dataset.transactions().await.unwrap();
+        assert_eq!(
+            records.iter().map(|r| r.version).collect::<Vec<_>>(),
+            vec![1, 2, 3]
+        );
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| r.operation_name().unwrap())
+                .collect::<Vec<_>>(),
+            vec!["Overwrite", "Append", "Delete"]
+        );
+        assert!(records.windows(2).all(|w| w[0].timestamp <= w[1].timestamp));
+
+        assert_eq!(records[0].added_fragment_ids, vec![0, 1]);
+        assert_eq!(
+            records[0].transaction.as_ref().unwrap().properties,
+            HashMap::from([("author".to_string(), "ingest".to_string())])
+        );
+
+        assert_eq!(records[1].added_fragment_ids, vec![2]);
+        assert!(records[1].updated_fragment_ids.is_empty());
+        assert!(records[1].removed_fragment_ids.is_empty());
+        assert!(records[1]
+            .transaction
+            .as_ref()
+            .unwrap()
+            .properties
+            .is_empty());
+
+        assert!(records[2].added_fragment_ids.is_empty());
+        assert_eq!(records[2].updated_fragment_ids, vec![1]);
+        assert_eq!(records[2].removed_fragment_ids, vec![0]);
+    }
+}
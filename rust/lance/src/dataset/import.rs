@@ -0,0 +1,451 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Import of files of other formats into datasets.
+
+use std::pin::Pin;
+use std::sync::Arc;
+
+use arrow_array::{new_null_array, RecordBatch};
+use arrow_cast::{can_cast_types, cast};
+use arrow_schema::{Field as ArrowField, Schema as ArrowSchema, SchemaRef};
+use datafusion::error::DataFusionError;
+use datafusion::logical_expr::type_coercion::binary::comparison_coercion;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::{stream, StreamExt, TryStreamExt};
+use lance_core::io::object_store::ObjectStore;
+use object_store::{path::Path, ObjectMeta};
+use parquet::arrow::async_reader::{ParquetObjectReader, ParquetRecordBatchStreamBuilder};
+use parquet::errors::ParquetError;
+use snafu::{location, Location};
+
+use super::{Dataset, WriteMode, WriteParams};
+use crate::datatypes::Schema;
+use crate::{Error, Result};
+
+/// Parameters of [`import_parquet`].
+#[derive(Debug, Clone)]
+pub struct ParquetImportParams {
+    /// The schema to import the files with. By default, it is the schema of the dataset
+    /// appended to, or else the schemas of the files unified.
+    pub schema: Option<SchemaRef>,
+    /// Fill the columns missing from some of the files with nulls, instead of failing.
+    pub allow_missing_columns: bool,
+    /// Cast the columns whose type differs between the files, or from the schema, to a
+    /// common type, e.g., Int32 and Int64 to Int64, instead of failing.
+    pub coerce_types: bool,
+    /// The number of files read concurrently. If it is more than one, the rows of
+    /// different files may be interleaved.
+    pub io_parallelism: usize,
+    /// The number of rows per batch read from the files.
+    pub batch_size: usize,
+    /// The parameters of the write, e.g., [`WriteMode::Append`] to append the files to an
+    /// existing dataset. Its store parameters are also used to read the files.
+    pub write_params: WriteParams,
+}
+
+impl Default for ParquetImportParams {
+    fn default() -> Self {
+        Self {
+            schema: None,
+            allow_missing_columns: false,
+            coerce_types: false,
+            io_parallelism: num_cpus::get(),
+            batch_size: 8 * 1024,
+            write_params: WriteParams::default(),
+        }
+    }
+}
+
+fn parquet_error(err: ParquetError) -> Error {
+    Error::IO {
+        message: format!("Failed to read Parquet file: {}", err),
+        location: location!(),
+    }
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any sequence of characters and
+/// `?` any single character.
+fn matches_pattern(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            matches_pattern(&pattern[1..], name)
+                || (!name.is_empty() && matches_pattern(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => matches_pattern(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => matches_pattern(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// List the Parquet files of `uri`, which is a file, a directory whose `.parquet` files
+/// are imported recursively, or a pattern of file names in a directory, e.g.,
+/// `s3://bucket/path/part-*.parquet`.
+async fn list_files(
+    uri: &str,
+    params: &ParquetImportParams,
+) -> Result<(ObjectStore, Vec<ObjectMeta>)> {
+    let store_params = params.write_params.store_params.clone().unwrap_or_default();
+    let (dir_uri, pattern) = match uri.rsplit_once('/') {
+        Some((dir_uri, name)) if name.contains(['*', '?']) => (dir_uri, Some(name)),
+        _ => (uri, None),
+    };
+    let (object_store, path) = ObjectStore::from_uri_and_params(dir_uri, &store_params).await?;
+
+    let mut files = if let Some(pattern) = pattern {
+        let pattern = pattern.chars().collect::<Vec<_>>();
+        object_store
+            .inner
+            .list_with_delimiter(Some(&path))
+            .await?
+            .objects
+            .into_iter()
+            .filter(|meta| {
+                let name = meta.location.filename().unwrap_or_default();
+                matches_pattern(&pattern, &name.chars().collect::<Vec<_>>())
+            })
+            .collect::<Vec<_>>()
+    } else if let Ok(meta) = object_store.inner.head(&path).await {
+        vec![meta]
+    } else {
+        object_store
+            .read_dir_all(&path, None)
+            .await?
+            .try_filter(|meta| futures::future::ready(meta.location.extension() == Some("parquet")))
+            .try_collect::<Vec<_>>()
+            .await?
+    };
+    if files.is_empty() {
+        return Err(Error::invalid_input(
+            format!("No Parquet file found at {}", uri),
+            location!(),
+        ));
+    }
+    files.sort_by(|a, b| a.location.cmp(&b.location));
+    Ok((object_store, files))
+}
+
+/// Check that a file with `file_schema` can be imported with `schema`.
+fn check_file_schema(
+    path: &Path,
+    file_schema: &ArrowSchema,
+    schema: &ArrowSchema,
+    params: &ParquetImportParams,
+) -> Result<()> {
+    for field in schema.fields() {
+        match file_schema.field_with_name(field.name()) {
+            Ok(file_field) => {
+                if file_field.data_type() != field.data_type()
+                    && !(params.coerce_types
+                        && can_cast_types(file_field.data_type(), field.data_type()))
+                {
+                    return Err(Error::invalid_input(
+                        format!(
+                            "Column {} of {} has type {} instead of {}",
+                            field.name(),
+                            path,
+                            file_field.data_type(),
+                            field.data_type()
+                        ),
+                        location!(),
+                    ));
+                }
+            }
+            Err(_) if params.allow_missing_columns && field.is_nullable() => {}
+            Err(_) => {
+                return Err(Error::invalid_input(
+                    format!("Column {} is missing from {}", field.name(), path),
+                    location!(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Unify the schemas of the files: the columns are in the order they are first seen,
+/// and their types are coerced to a common type if `coerce_types` is set.
+fn unify_schemas(schemas: &[SchemaRef], params: &ParquetImportParams) -> ArrowSchema {
+    let mut fields: Vec<ArrowField> = Vec::new();
+    for schema in schemas {
+        for file_field in schema.fields() {
+            match fields.iter_mut().find(|f| f.name() == file_field.name()) {
+                Some(field) => {
+                    if field.data_type() != file_field.data_type() && params.coerce_types {
+                        if let Some(data_type) =
+                            comparison_coercion(field.data_type(), file_field.data_type())
+                        {
+                            *field = field.clone().with_data_type(data_type);
+                        }
+                    }
+                    if file_field.is_nullable() {
+                        *field = field.clone().with_nullable(true);
+                    }
+                }
+                None => fields.push(file_field.as_ref().clone()),
+            }
+        }
+    }
+    if params.allow_missing_columns {
+        for field in fields.iter_mut() {
+            if schemas
+                .iter()
+                .any(|schema| schema.field_with_name(field.name()).is_err())
+            {
+                *field = field.clone().with_nullable(true);
+            }
+        }
+    }
+    ArrowSchema::new(fields)
+}
+
+/// Conform a batch of a file to the schema of the import.
+fn conform_batch(batch: RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) if column.data_type() == field.data_type() => Ok(column.clone()),
+            Some(column) => cast(column, field.data_type()),
+            None => Ok(new_null_array(field.data_type(), batch.num_rows())),
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Import a file, a directory, or a pattern of file names, e.g.,
+/// `s3://bucket/path/part-*.parquet`, of Parquet files into a dataset at `uri`.
+///
+/// The files are read in parallel, and their schemas are unified. The dataset is
+/// created, appended to or overwritten depending on the mode of the write parameters.
+pub async fn import_parquet(
+    source_uri: &str,
+    uri: &str,
+    params: Option<ParquetImportParams>,
+) -> Result<Dataset> {
+    let params = params.unwrap_or_default();
+    if params.io_parallelism == 0 || params.batch_size == 0 {
+        return Err(Error::invalid_input(
+            "The I/O parallelism and the batch size must be positive",
+            location!(),
+        ));
+    }
+    let (object_store, files) = list_files(source_uri, &params).await?;
+
+    let builders = stream::iter(files)
+        .map(|meta| {
+            let reader = ParquetObjectReader::new(object_store.inner.clone(), meta.clone());
+            async move {
+                let builder = ParquetRecordBatchStreamBuilder::new(reader)
+                    .await
+                    .map_err(parquet_error)?;
+                Result::Ok((meta.location, builder))
+            }
+        })
+        .buffered(params.io_parallelism)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let mut existing = None;
+    if matches!(params.write_params.mode, WriteMode::Append) {
+        match Dataset::open(uri).await {
+            Ok(dataset) => existing = Some(dataset),
+            Err(Error::DatasetNotFound { .. }) | Err(Error::NotFound { .. }) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    let schema = match (&params.schema, &existing) {
+        (Some(schema), _) => schema.clone(),
+        (None, Some(dataset)) => Arc::new(ArrowSchema::from(dataset.schema())),
+        (None, None) => {
+            let schemas = builders
+                .iter()
+                .map(|(_, builder)| builder.schema().clone())
+                .collect::<Vec<_>>();
+            Arc::new(unify_schemas(&schemas, &params))
+        }
+    };
+    for (path, builder) in builders.iter() {
+        check_file_schema(path, builder.schema(), &schema, &params)?;
+    }
+
+    let batch_size = params.batch_size;
+    let file_schema = schema.clone();
+    let batches = stream::iter(builders)
+        .map(move |(_, builder)| {
+            let schema = file_schema.clone();
+            match builder.with_batch_size(batch_size).build() {
+                Ok(file_stream) => file_stream
+                    .map_err(parquet_error)
+                    .and_then(move |batch| futures::future::ready(conform_batch(batch, &schema)))
+                    .boxed(),
+                Err(err) => stream::once(futures::future::ready(Err(parquet_error(err)))).boxed(),
+            }
+        })
+        .flatten_unordered(params.io_parallelism);
+    let mut batches = batches.peekable();
+
+    // The dictionaries of the schema are set from the first batch.
+    let mut lance_schema = Schema::try_from(schema.as_ref())?;
+    if let Some(batch) = Pin::new(&mut batches).peek().await {
+        match batch {
+            Ok(batch) => lance_schema.set_dictionary(batch)?,
+            Err(_) => return Err(batches.next().await.unwrap().unwrap_err()),
+        }
+    }
+    lance_schema.validate()?;
+    let stream = Box::pin(RecordBatchStreamAdapter::new(
+        schema.clone(),
+        batches.map_err(|err| DataFusionError::External(Box::new(err))),
+    )) as SendableRecordBatchStream;
+
+    match existing {
+        Some(mut dataset) => {
+            dataset
+                .append_stream(stream, lance_schema, Some(params.write_params))
+                .await?;
+            Ok(dataset)
+        }
+        None => Dataset::write_stream(stream, lance_schema, uri, Some(params.write_params)).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{cast::AsArray, types::Int64Type, Int32Array, Int64Array, StringArray};
+    use arrow_schema::DataType;
+    use arrow_select::concat::concat_batches;
+    use parquet::arrow::ArrowWriter;
+    use tempfile::tempdir;
+
+    fn write_file(path: std::path::PathBuf, batch: RecordBatch) {
+        let file = std::fs::File::create(path).unwrap();
+        let mut writer = ArrowWriter::try_new(file, batch.schema(), None).unwrap();
+        writer.write(&batch).unwrap();
+        writer.close().unwrap();
+    }
+
+    #[test]
+    fn test_matches_pattern() {
+        let matches = |pattern: &str, name: &str| {
+            matches_pattern(
+                &pattern.chars().collect::<Vec<_>>(),
+                &name.chars().collect::<Vec<_>>(),
+            )
+        };
+        assert!(matches("part-*.parquet", "part-0001.parquet"));
+        assert!(matches("*", "a.parquet"));
+        assert!(matches("part-?.parquet", "part-1.parquet"));
+        assert!(!matches("part-?.parquet", "part-10.parquet"));
+        assert!(!matches("part-*.parquet", "other.parquet"));
+    }
+
+    #[tokio::test]
+    async fn test_import_parquet() {
+        let test_dir = tempdir().unwrap();
+        let source_dir = test_dir.path().join("source");
+        std::fs::create_dir_all(source_dir.join("nested")).unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, false),
+            ArrowField::new("name", DataType::Utf8, false),
+        ]));
+        for i in 0..3 {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(i * 100..(i + 1) * 100)),
+                    Arc::new(StringArray::from_iter_values(
+                        (i * 100..(i + 1) * 100).map(|id| format!("name-{}", id)),
+                    )),
+                ],
+            )
+            .unwrap();
+            write_file(source_dir.join(format!("part-{}.parquet", i)), batch);
+        }
+        // A file with a wider type and a missing column, in a subdirectory.
+        let other_schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "id",
+            DataType::Int64,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            other_schema,
+            vec![Arc::new(Int64Array::from_iter_values(300..350))],
+        )
+        .unwrap();
+        write_file(source_dir.join("nested").join("other.parquet"), batch);
+        std::fs::write(source_dir.join("_SUCCESS"), b"").unwrap();
+
+        let source_uri = source_dir.to_str().unwrap();
+        let test_uri = test_dir.path().join("dataset");
+        let test_uri = test_uri.to_str().unwrap();
+
+        // The schemas differ.
+        assert!(matches!(
+            import_parquet(source_uri, test_uri, None).await,
+            Err(Error::InvalidInput { .. })
+        ));
+
+        let params = ParquetImportParams {
+            allow_missing_columns: true,
+            coerce_types: true,
+            io_parallelism: 2,
+            batch_size: 64,
+            ..Default::default()
+        };
+        let dataset = import_parquet(source_uri, test_uri, Some(params))
+            .await
+            .unwrap();
+        let expected_schema = ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int64, false),
+            ArrowField::new("name", DataType::Utf8, true),
+        ]);
+        assert_eq!(ArrowSchema::from(dataset.schema()), expected_schema);
+        assert_eq!(dataset.count_rows().await.unwrap(), 350);
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        let mut ids = batch["id"].as_primitive::<Int64Type>().values().to_vec();
+        ids.sort();
+        assert_eq!(ids, (0..350).collect::<Vec<_>>());
+        assert_eq!(batch["name"].null_count(), 50);
+
+        // Append the files matching a pattern, with the schema of the dataset.
+        let params = ParquetImportParams {
+            coerce_types: true,
+            write_params: WriteParams {
+                mode: WriteMode::Append,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let pattern = format!("{}/part-?.parquet", source_uri);
+        let dataset = import_parquet(&pattern, test_uri, Some(params))
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, 2);
+        assert_eq!(dataset.count_rows().await.unwrap(), 650);
+    }
+}
@@ -21,6 +21,7 @@ use lance_core::{
     Error, Result,
 };
 use nohash_hasher::IntMap;
+use roaring::RoaringBitmap;
 use serde::{Deserialize, Serialize};
 use snafu::{location, Location};
 
@@ -83,35 +84,32 @@ impl IndexRemapper for DatasetIndexRemapper {
     }
 }
 
-/// Returns the fragment ids that are not indexed by this index.
-pub async fn unindexed_fragments(index: &Index, dataset: &Dataset) -> Result<Vec<Fragment>> {
-    if index.dataset_version == dataset.version().version {
-        return Ok(vec![]);
-    }
-    if let Some(bitmap) = index.fragment_bitmap.as_ref() {
-        Ok(dataset
-            .fragments()
-            .iter()
-            .filter(|f| !bitmap.contains(f.id as u32))
-            .cloned()
-            .collect::<Vec<_>>())
-    } else {
-        let ds = dataset.checkout_version(index.dataset_version).await?;
-        let max_fragment_id_idx = ds.manifest.max_fragment_id().ok_or_else(|| Error::IO {
-            message: "No fragments in index version".to_string(),
-            location: location!(),
-        })?;
-        let max_fragment_id_ds = dataset
-            .manifest
-            .max_fragment_id()
-            .ok_or_else(|| Error::IO {
-                message: "No fragments in dataset version".to_string(),
+/// Returns the fragments that are not indexed by any delta of an index.
+///
+/// `indices` are the deltas of the same index.
+pub async fn unindexed_fragments(indices: &[Index], dataset: &Dataset) -> Result<Vec<Fragment>> {
+    let mut indexed = RoaringBitmap::new();
+    for index in indices {
+        if index.dataset_version == dataset.version().version {
+            return Ok(vec![]);
+        }
+        if let Some(bitmap) = index.fragment_bitmap.as_ref() {
+            indexed |= bitmap;
+        } else {
+            // Indices written before the fragment bitmap was introduced cover all
+            // the fragments of the version they were built on.
+            let ds = dataset.checkout_version(index.dataset_version).await?;
+            let max_fragment_id_idx = ds.manifest.max_fragment_id().ok_or_else(|| Error::IO {
+                message: "No fragments in index version".to_string(),
                 location: location!(),
             })?;
-        if max_fragment_id_idx < max_fragment_id_ds {
-            dataset.manifest.fragments_since(&ds.manifest)
-        } else {
-            Ok(vec![])
+            indexed.insert_range(0..=max_fragment_id_idx as u32);
         }
     }
+    Ok(dataset
+        .fragments()
+        .iter()
+        .filter(|f| !indexed.contains(f.id as u32))
+        .cloned()
+        .collect::<Vec<_>>())
 }
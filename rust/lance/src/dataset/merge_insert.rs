@@ -0,0 +1,843 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Joining a dataset with a batch of rows by key, to upsert (merge insert) the rows
+//! into the dataset or to delete the matching rows of the dataset.
+
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow_array::{
+    Array, ArrayRef, RecordBatch, RecordBatchIterator, RecordBatchReader, UInt32Array, UInt64Array,
+};
+use arrow_row::{OwnedRow, RowConverter, SortField};
+use arrow_select::{concat::concat_batches, take::take};
+use futures::TryStreamExt;
+use lance_core::ROW_ID;
+use snafu::{location, Location};
+
+use super::constraints::{
+    check_unique_key, is_key_type, to_identifier, to_literal, unique_key, LOOKUP_BATCH_SIZE,
+};
+use super::partition::{append_partition_columns, partition_order};
+use super::rowids::{assign_row_ids, stable_row_ids};
+use super::transaction::Operation;
+use super::update::{apply_deletions, replace_rows};
+use super::write::{reader_to_stream, write_fragments_internal};
+use super::{append_schema, Dataset, WriteParams};
+use crate::datatypes::Schema;
+use crate::format::Fragment;
+use crate::{Error, Result};
+
+/// What to do with the rows of the dataset whose key matches a source row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhenMatched {
+    /// Replace the matched rows with the source row.
+    #[default]
+    UpdateAll,
+    /// Keep the matched rows as they are.
+    DoNothing,
+}
+
+/// What to do with the source rows whose key matches no row of the dataset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WhenNotMatched {
+    /// Insert the source rows.
+    #[default]
+    InsertAll,
+    /// Drop the source rows.
+    DoNothing,
+}
+
+/// Parameters of [`Dataset::merge_insert`].
+#[derive(Debug, Clone, Default)]
+pub struct MergeInsertParams {
+    pub when_matched: WhenMatched,
+
+    pub when_not_matched: WhenNotMatched,
+
+    /// Parameters used to write the updated and inserted rows.
+    pub write_params: Option<WriteParams>,
+}
+
+/// The outcome of a [`Dataset::merge_insert`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MergeInsertStats {
+    /// The number of source rows inserted as new rows.
+    pub num_inserted_rows: usize,
+    /// The number of source rows which replaced the matched rows of the dataset.
+    pub num_updated_rows: usize,
+}
+
+/// The source rows, keyed by the values of the `on` columns.
+struct SourceRows {
+    batch: RecordBatch,
+    converter: RowConverter,
+    keys: HashMap<OwnedRow, usize>,
+}
+
+impl SourceRows {
+    /// If `unique`, two source rows with the same key are an error. Otherwise, the
+    /// key points to the first of them.
+    fn try_new(batch: RecordBatch, on: &[&str], unique: bool) -> Result<Self> {
+        let columns = key_columns(&batch, on)?;
+        let converter = RowConverter::new(
+            columns
+                .iter()
+                .map(|column| SortField::new(column.data_type().clone()))
+                .collect(),
+        )?;
+        let rows = converter.convert_columns(&columns)?;
+        let mut keys = HashMap::with_capacity(batch.num_rows());
+        for (idx, row) in rows.iter().enumerate() {
+            if has_null_key(&columns, idx) {
+                continue;
+            }
+            match keys.entry(row.owned()) {
+                Entry::Occupied(_) if unique => {
+                    return Err(Error::invalid_input(
+                        format!(
+                            "Merge insert source has multiple rows with the same key (row {})",
+                            idx
+                        ),
+                        location!(),
+                    ));
+                }
+                Entry::Occupied(_) => {}
+                Entry::Vacant(entry) => {
+                    entry.insert(idx);
+                }
+            }
+        }
+        Ok(Self {
+            batch,
+            converter,
+            keys,
+        })
+    }
+}
+
+fn key_columns(batch: &RecordBatch, on: &[&str]) -> Result<Vec<ArrayRef>> {
+    on.iter()
+        .map(|name| {
+            batch.column_by_name(name).cloned().ok_or_else(|| {
+                Error::invalid_input(format!("Column {} does not exist", name), location!())
+            })
+        })
+        .collect()
+}
+
+/// Like in SQL, a null key never matches.
+fn has_null_key(columns: &[ArrayRef], idx: usize) -> bool {
+    columns.iter().any(|column| column.is_null(idx))
+}
+
+fn validate_keys(dataset: &Dataset, on: &[&str]) -> Result<()> {
+    if on.is_empty() {
+        return Err(Error::invalid_input(
+            "Joining by key requires at least one key column",
+            location!(),
+        ));
+    }
+    for name in on {
+        if dataset.schema().field(name).is_none() {
+            return Err(Error::invalid_input(
+                format!("Column {} does not exist in the dataset", name),
+                location!(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Scan the key columns of the dataset, calling `on_match` with the row id of each
+/// row matching a source row, and the index of the source row.
+async fn join_keys(
+    dataset: &Dataset,
+    on: &[&str],
+    source: &SourceRows,
+    mut on_match: impl FnMut(u64, usize),
+) -> Result<()> {
+    let mut scanner = dataset.scan();
+    scanner.with_row_id().project(on)?;
+    let mut stream = scanner.try_into_stream().await?;
+    while let Some(batch) = stream.try_next().await? {
+        let columns = key_columns(&batch, on)?;
+        let rows = source.converter.convert_columns(&columns)?;
+        let row_ids = batch[ROW_ID]
+            .as_any()
+            .downcast_ref::<UInt64Array>()
+            .ok_or_else(|| Error::Internal {
+                message: "Row ids must be a UInt64 array".to_string(),
+                location: location!(),
+            })?;
+        for (idx, row) in rows.iter().enumerate() {
+            if has_null_key(&columns, idx) {
+                continue;
+            }
+            if let Some(&source_idx) = source.keys.get(&row.owned()) {
+                on_match(row_ids.value(idx), source_idx);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Upsert the rows of `source` into `dataset`, returning the operation to commit.
+///
+/// The source is held in memory. The matched rows of the dataset are deleted and,
+/// with the source rows to insert, the source rows replacing them are written to
+/// new fragments.
+pub(super) async fn merge_insert(
+    dataset: &Dataset,
+    source: Box<dyn RecordBatchReader + Send>,
+    on: &[&str],
+    params: MergeInsertParams,
+) -> Result<(Option<Operation>, MergeInsertStats)> {
+    validate_keys(dataset, on)?;
+    let arrow_schema = source.schema();
+    append_schema(&Schema::try_from(arrow_schema.as_ref())?, dataset.schema())?;
+
+    let batches = source.collect::<std::result::Result<Vec<_>, _>>()?;
+    let source = SourceRows::try_new(concat_batches(&arrow_schema, &batches)?, on, true)?;
+
+    // Find the rows of the dataset matching a source row, keeping the first match.
+    let mut matched = vec![None; source.batch.num_rows()];
+    let mut deletions: BTreeMap<u64, Vec<u32>> = BTreeMap::new();
+    join_keys(dataset, on, &source, |row_id, source_idx| {
+        matched[source_idx].get_or_insert(row_id);
+        if params.when_matched == WhenMatched::UpdateAll {
+            deletions
+                .entry(row_id >> 32)
+                .or_default()
+                .push(row_id as u32);
+        }
+    })
+    .await?;
+
+    let mut stats = MergeInsertStats::default();
+    let rows_to_write = matched
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, matched)| {
+            let write = if matched.is_some() {
+                params.when_matched == WhenMatched::UpdateAll
+            } else {
+                params.when_not_matched == WhenNotMatched::InsertAll
+            };
+            if write {
+                if matched.is_some() {
+                    stats.num_updated_rows += 1;
+                } else {
+                    stats.num_inserted_rows += 1;
+                }
+            }
+            write.then_some(idx as u32)
+        })
+        .collect::<UInt32Array>();
+    if rows_to_write.is_empty() {
+        return Ok((None, stats));
+    }
+
+    let batch = take_rows(&source.batch, &rows_to_write)?;
+    if let Some(field) = unique_key(dataset.schema()) {
+        let values = batch
+            .column_by_name(&field.name)
+            .cloned()
+            .into_iter()
+            .collect::<Vec<_>>();
+        // The rows replaced by the source rows are deleted.
+        check_unique_key(dataset, &field.name, &values, |row_id| {
+            deletions
+                .get(&(row_id >> 32))
+                .map(|rows| rows.contains(&(row_id as u32)))
+                .unwrap_or(false)
+        })
+        .await?;
+    }
+    let write_params = params.write_params.unwrap_or_default();
+    let new_fragments = if dataset.has_stable_row_ids() {
+        // The updated rows keep the stable row ids of the rows they replace, so they
+        // are written apart from the inserted rows, which get new ones on commit.
+        let (updated, inserted): (Vec<u32>, Vec<u32>) = (0..rows_to_write.len() as u32)
+            .partition(|&idx| matched[rows_to_write.value(idx as usize) as usize].is_some());
+        let updated_row_ids = updated
+            .iter()
+            .filter_map(|&idx| matched[rows_to_write.value(idx as usize) as usize])
+            .collect::<Vec<_>>();
+        let mut new_fragments = Vec::new();
+        if !updated.is_empty() {
+            let updated = take_rows(&batch, &UInt32Array::from(updated))?;
+            // Grouped by partition, the rows are written in the order of their row ids.
+            let order = partition_order(&updated, dataset.partition_columns())?;
+            let updated = take_rows(&updated, &order)?;
+            let updated_row_ids = order
+                .values()
+                .iter()
+                .map(|&idx| updated_row_ids[idx as usize])
+                .collect::<Vec<_>>();
+            new_fragments = write_rows(dataset, updated, &write_params).await?;
+            let stable_row_ids = stable_row_ids(&dataset.manifest.fragments, &updated_row_ids)?;
+            assign_row_ids(&mut new_fragments, &stable_row_ids)?;
+        }
+        if !inserted.is_empty() {
+            let inserted = take_rows(&batch, &UInt32Array::from(inserted))?;
+            new_fragments.extend(write_rows(dataset, inserted, &write_params).await?);
+        }
+        new_fragments
+    } else {
+        write_rows(dataset, batch, &write_params).await?
+    };
+
+    // The updated rows take all their values from the source, and the new fragments
+    // also hold the inserted rows.
+    let operation = replace_rows(dataset, deletions, new_fragments, Vec::new()).await?;
+    Ok((Some(operation), stats))
+}
+
+/// Append the rows of `source` whose key matches no row of `dataset`, returning the
+/// operation to commit and the number of appended rows.
+///
+/// Of the source rows with the same key, only the first one is appended. The rows with
+/// a null key never match, so they are always appended.
+pub(super) async fn append_deduplicated(
+    dataset: &Dataset,
+    source: Box<dyn RecordBatchReader + Send>,
+    on: &[&str],
+    write_params: WriteParams,
+) -> Result<(Option<Operation>, usize)> {
+    validate_keys(dataset, on)?;
+    let arrow_schema = source.schema();
+    append_schema(&Schema::try_from(arrow_schema.as_ref())?, dataset.schema())?;
+
+    let batches = source.collect::<std::result::Result<Vec<_>, _>>()?;
+    let source = SourceRows::try_new(concat_batches(&arrow_schema, &batches)?, on, false)?;
+
+    let mut matched = vec![false; source.batch.num_rows()];
+    match indexed_key(dataset, on).await? {
+        Some(column) => {
+            lookup_keys(dataset, column, &source, |source_idx| {
+                matched[source_idx] = true
+            })
+            .await?
+        }
+        None => {
+            join_keys(dataset, on, &source, |_, source_idx| {
+                matched[source_idx] = true
+            })
+            .await?
+        }
+    }
+
+    let columns = key_columns(&source.batch, on)?;
+    let first_rows = source.keys.values().copied().collect::<HashSet<_>>();
+    let rows_to_write = (0..source.batch.num_rows())
+        .filter(|&idx| has_null_key(&columns, idx) || (first_rows.contains(&idx) && !matched[idx]))
+        .map(|idx| idx as u32)
+        .collect::<UInt32Array>();
+    if rows_to_write.is_empty() {
+        return Ok((None, 0));
+    }
+
+    let batch = take_rows(&source.batch, &rows_to_write)?;
+    if let Some(field) = unique_key(dataset.schema()) {
+        let values = batch
+            .column_by_name(&field.name)
+            .cloned()
+            .into_iter()
+            .collect::<Vec<_>>();
+        check_unique_key(dataset, &field.name, &values, |_| false).await?;
+    }
+    let fragments = write_rows(dataset, batch, &write_params).await?;
+    Ok((Some(Operation::Append { fragments }), rows_to_write.len()))
+}
+
+/// The key column of `on`, if it is a single column with a scalar index, to look up
+/// the keys with the index rather than scanning all of them.
+async fn indexed_key<'a>(dataset: &Dataset, on: &[&'a str]) -> Result<Option<&'a str>> {
+    let [column] = on else {
+        return Ok(None);
+    };
+    let Some(field) = dataset.schema().field(column) else {
+        return Ok(None);
+    };
+    if !is_key_type(&field.data_type()) {
+        return Ok(None);
+    }
+    let indices = dataset.load_indices().await?;
+    Ok(indices
+        .iter()
+        .any(|index| index.fields == [field.id])
+        .then_some(*column))
+}
+
+/// Look up the keys of `source` in the indexed key `column` of the dataset, calling
+/// `on_match` with the index of each source row matching a row of the dataset.
+async fn lookup_keys(
+    dataset: &Dataset,
+    column: &str,
+    source: &SourceRows,
+    mut on_match: impl FnMut(usize),
+) -> Result<()> {
+    let keys = source.batch[column].as_ref();
+    let literals = source
+        .keys
+        .values()
+        .map(|idx| to_literal(keys, *idx))
+        .collect::<Result<Vec<_>>>()?;
+    for chunk in literals.chunks(LOOKUP_BATCH_SIZE) {
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?.filter(&format!(
+            "{} IN ({})",
+            to_identifier(column),
+            chunk.join(", ")
+        ))?;
+        let mut stream = scanner.try_into_stream().await?;
+        while let Some(batch) = stream.try_next().await? {
+            let rows = source
+                .converter
+                .convert_columns(&key_columns(&batch, &[column])?)?;
+            for row in rows.iter() {
+                if let Some(&source_idx) = source.keys.get(&row.owned()) {
+                    on_match(source_idx);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn take_rows(batch: &RecordBatch, indices: &UInt32Array) -> Result<RecordBatch> {
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| Ok(take(column.as_ref(), indices, None)?))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(batch.schema(), columns)?)
+}
+
+/// Write the rows of `batch` to new fragments of `dataset`, partitioned like the
+/// dataset.
+async fn write_rows(
+    dataset: &Dataset,
+    batch: RecordBatch,
+    write_params: &WriteParams,
+) -> Result<Vec<Fragment>> {
+    let arrow_schema = batch.schema();
+    let reader = RecordBatchIterator::new(vec![Ok(batch)], arrow_schema);
+    let (stream, schema) = reader_to_stream(Box::new(reader))?;
+    let schema = append_schema(&schema, dataset.schema())?;
+    let object_store = Arc::new(
+        dataset
+            .object_store()
+            .with_params(&write_params.store_params.clone().unwrap_or_default()),
+    );
+    let write_params = WriteParams {
+        partition_columns: append_partition_columns(
+            &dataset.manifest,
+            &write_params.partition_columns,
+        )?,
+        ..write_params.clone()
+    };
+    write_fragments_internal(object_store, &dataset.base, &schema, stream, write_params).await
+}
+
+/// Delete the rows of `dataset` whose key matches a row of `keys`, returning the
+/// operation to commit.
+///
+/// Only the key columns of `keys` are held in memory, in a hash table which the key
+/// columns of the dataset are probed against.
+pub(super) async fn delete_by_keys(
+    dataset: &Dataset,
+    keys: Box<dyn RecordBatchReader + Send>,
+    on: &[&str],
+) -> Result<Option<Operation>> {
+    validate_keys(dataset, on)?;
+    let keys_schema = keys.schema();
+    let projection = on
+        .iter()
+        .map(|name| {
+            let (idx, field) = keys_schema.column_with_name(name).ok_or_else(|| {
+                Error::invalid_input(
+                    format!("Column {} does not exist in the keys", name),
+                    location!(),
+                )
+            })?;
+            let data_type = dataset.schema().field(name).unwrap().data_type();
+            if field.data_type() != &data_type {
+                return Err(Error::invalid_input(
+                    format!(
+                        "Column {} has type {} in the keys but {} in the dataset",
+                        name,
+                        field.data_type(),
+                        data_type
+                    ),
+                    location!(),
+                ));
+            }
+            Ok(idx)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let key_schema = Arc::new(keys_schema.project(&projection)?);
+    let batches = keys
+        .map(|batch| batch.and_then(|batch| batch.project(&projection)))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let keys = SourceRows::try_new(concat_batches(&key_schema, &batches)?, on, false)?;
+    if keys.keys.is_empty() {
+        return Ok(None);
+    }
+
+    let mut deletions: BTreeMap<u64, Vec<u32>> = BTreeMap::new();
+    join_keys(dataset, on, &keys, |row_id, _| {
+        deletions
+            .entry(row_id >> 32)
+            .or_default()
+            .push(row_id as u32);
+    })
+    .await?;
+    if deletions.is_empty() {
+        return Ok(None);
+    }
+
+    let (updated_fragments, deleted_fragment_ids) = apply_deletions(dataset, deletions).await?;
+    Ok(Some(Operation::Delete {
+        updated_fragments,
+        deleted_fragment_ids,
+        predicate: format!("({}) IN <{} keys>", on.join(", "), keys.keys.len()),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Int32Array, StringArray};
+    use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema, SchemaRef};
+    use lance_index::IndexType;
+    use tempfile::tempdir;
+
+    use crate::index::{scalar::ScalarIndexParams, DatasetIndexExt};
+
+    fn batch(schema: &SchemaRef, keys: std::ops::Range<i32>, value: &str) -> RecordBatch {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(keys.clone())),
+                Arc::new(StringArray::from_iter_values(
+                    keys.map(|i| format!("{value}-{i}")),
+                )),
+            ],
+        )
+        .unwrap()
+    }
+
+    async fn values(dataset: &Dataset) -> Vec<(i32, String)> {
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut values = batches
+            .iter()
+            .flat_map(|batch| {
+                let keys = batch["i"].as_any().downcast_ref::<Int32Array>().unwrap();
+                let values = batch["s"].as_any().downcast_ref::<StringArray>().unwrap();
+                keys.values()
+                    .iter()
+                    .zip(values.iter())
+                    .map(|(key, value)| (*key, value.unwrap().to_string()))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        values.sort();
+        values
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 50,
+            ..Default::default()
+        };
+        let reader =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 0..100, "old"))], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        let version = dataset.version().version;
+
+        // Upsert: the keys 50..100 are updated, and the keys 100..150 are inserted.
+        let source =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 50..150, "new"))], schema.clone());
+        let stats = dataset.merge_insert(source, &["i"], None).await.unwrap();
+        assert_eq!(
+            stats,
+            MergeInsertStats {
+                num_inserted_rows: 50,
+                num_updated_rows: 50,
+            }
+        );
+        // All of it is committed as a single version.
+        assert_eq!(dataset.version().version, version + 1);
+        let expected = (0..150)
+            .map(|i| (i, format!("{}-{i}", if i < 50 { "old" } else { "new" })))
+            .collect::<Vec<_>>();
+        assert_eq!(values(&dataset).await, expected);
+        // The second fragment was entirely replaced.
+        assert!(dataset.get_fragment(1).is_none());
+        assert!(dataset
+            .get_fragment(0)
+            .unwrap()
+            .metadata
+            .deletion_file
+            .is_none());
+
+        // Insert only: the existing rows are kept.
+        let source =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 140..160, "newer"))], schema.clone());
+        let params = MergeInsertParams {
+            when_matched: WhenMatched::DoNothing,
+            ..Default::default()
+        };
+        let stats = dataset
+            .merge_insert(source, &["i"], Some(params))
+            .await
+            .unwrap();
+        assert_eq!(stats.num_inserted_rows, 10);
+        assert_eq!(stats.num_updated_rows, 0);
+        let values = values(&dataset).await;
+        assert_eq!(values.len(), 160);
+        assert_eq!(values[145], (145, "new-145".to_string()));
+        assert_eq!(values[155], (155, "newer-155".to_string()));
+
+        // Nothing to write, so nothing is committed.
+        let version = dataset.version().version;
+        let source = RecordBatchIterator::new(vec![Ok(batch(&schema, 0..10, "x"))], schema.clone());
+        let params = MergeInsertParams {
+            when_matched: WhenMatched::DoNothing,
+            when_not_matched: WhenNotMatched::DoNothing,
+            ..Default::default()
+        };
+        dataset
+            .merge_insert(source, &["i"], Some(params))
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, version);
+    }
+
+    #[tokio::test]
+    async fn test_merge_insert_invalid_source() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 0..10, "old"))], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        // Duplicate keys are ambiguous.
+        let source = RecordBatchIterator::new(
+            vec![Ok(batch(&schema, 0..5, "a")), Ok(batch(&schema, 4..6, "b"))],
+            schema.clone(),
+        );
+        let result = dataset.merge_insert(source, &["i"], None).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        let source = RecordBatchIterator::new(vec![Ok(batch(&schema, 0..5, "a"))], schema.clone());
+        let result = dataset.merge_insert(source, &["x"], None).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+
+        let other_schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int32,
+            true,
+        )]));
+        let source = RecordBatchIterator::new(
+            vec![Ok(RecordBatch::try_new(
+                other_schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(0..5))],
+            )
+            .unwrap())],
+            other_schema,
+        );
+        let result = dataset.merge_insert(source, &["i"], None).await;
+        assert!(matches!(result, Err(Error::SchemaMismatch { .. })));
+        assert_eq!(dataset.count_rows().await.unwrap(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_delete_by_keys() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let reader =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 0..300, "a"))], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        let version = dataset.version().version;
+
+        // The keys may have other columns, duplicates, nulls and keys not in the dataset.
+        let keys_schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("other", DataType::Utf8, true),
+            ArrowField::new("i", DataType::Int32, true),
+        ]));
+        let keys = [5, 7, 7, 1000]
+            .into_iter()
+            .map(Some)
+            .chain((100..200).map(Some))
+            .chain([None])
+            .collect::<Int32Array>();
+        let keys = RecordBatch::try_new(
+            keys_schema.clone(),
+            vec![
+                Arc::new(StringArray::from_iter_values(
+                    (0..keys.len()).map(|i| i.to_string()),
+                )),
+                Arc::new(keys),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(keys)], keys_schema.clone());
+        dataset.delete_by_keys(reader, &["i"]).await.unwrap();
+
+        assert_eq!(dataset.version().version, version + 1);
+        assert_eq!(dataset.count_rows().await.unwrap(), 198);
+        // The fragment whose rows all matched is removed.
+        assert!(dataset.get_fragment(1).is_none());
+        let remaining = values(&dataset).await;
+        assert_eq!(remaining[4], (4, "a-4".to_string()));
+        assert_eq!(remaining[5], (6, "a-6".to_string()));
+        assert_eq!(remaining[98], (200, "a-200".to_string()));
+
+        // Nothing matches, so nothing is committed.
+        let version = dataset.version().version;
+        let reader =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 500..510, "a"))], schema.clone());
+        dataset.delete_by_keys(reader, &["i"]).await.unwrap();
+        assert_eq!(dataset.version().version, version);
+
+        // Multiple key columns.
+        let reader = RecordBatchIterator::new(vec![Ok(batch(&schema, 0..10, "a"))], schema.clone());
+        dataset.delete_by_keys(reader, &["s", "i"]).await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 190);
+
+        // The keys must have the type of the key columns.
+        let bad_schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int64,
+            true,
+        )]));
+        let bad_keys = RecordBatch::try_new(
+            bad_schema.clone(),
+            vec![Arc::new(arrow_array::Int64Array::from_iter_values(0..5))],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(bad_keys)], bad_schema);
+        let result = dataset.delete_by_keys(reader, &["i"]).await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_append_deduplicated() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 50,
+            ..Default::default()
+        };
+        let reader =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 0..100, "old"))], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        let version = dataset.version().version;
+
+        // The existing keys and the later duplicates of the source are dropped.
+        let source = || {
+            RecordBatchIterator::new(
+                vec![
+                    Ok(batch(&schema, 50..150, "new")),
+                    Ok(batch(&schema, 120..130, "duplicate")),
+                ],
+                schema.clone(),
+            )
+        };
+        let num_rows = dataset
+            .append_deduplicated(source(), &["i"], None)
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 50);
+        assert_eq!(dataset.version().version, version + 1);
+        let expected = (0..150)
+            .map(|i| (i, format!("{}-{i}", if i < 100 { "old" } else { "new" })))
+            .collect::<Vec<_>>();
+        assert_eq!(values(&dataset).await, expected);
+
+        // Re-running the append is a no-op.
+        let num_rows = dataset
+            .append_deduplicated(source(), &["i"], None)
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 0);
+        assert_eq!(dataset.version().version, version + 1);
+
+        // The keys are looked up with the scalar index of the key column.
+        dataset
+            .create_index(
+                &["i"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(indexed_key(&dataset, &["i"]).await.unwrap(), Some("i"));
+        let source =
+            RecordBatchIterator::new(vec![Ok(batch(&schema, 140..160, "newer"))], schema.clone());
+        let num_rows = dataset
+            .append_deduplicated(source, &["i"], None)
+            .await
+            .unwrap();
+        assert_eq!(num_rows, 10);
+        let values = values(&dataset).await;
+        assert_eq!(values.len(), 160);
+        assert_eq!(values[149], (149, "new-149".to_string()));
+        assert_eq!(values[150], (150, "newer-150".to_string()));
+    }
+}
@@ -0,0 +1,179 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Upgrade of the datasets written by older versions of Lance to the current format.
+
+use std::sync::Arc;
+
+use lance_core::format::WriterVersion;
+
+use super::transaction::{Operation, Transaction};
+use super::Dataset;
+use crate::io::commit::{commit_transaction, index_needs_migration, migrate_fragments};
+use crate::Result;
+
+/// What [`Dataset::migrate`] changed, or would change in a dry run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MigrationReport {
+    /// The version of Lance that wrote the dataset. It is None for the versions
+    /// older than 0.8.
+    pub writer_version: Option<WriterVersion>,
+    /// The ids of the fragments whose physical row count or number of deleted rows
+    /// was missing or incorrect.
+    pub migrated_fragment_ids: Vec<u64>,
+    /// The names of the indices whose fragment bitmap was missing or could not be
+    /// trusted.
+    pub migrated_indices: Vec<String>,
+    /// The version committed by the migration. It is None for a dry run, or if the
+    /// dataset was already up to date.
+    pub committed_version: Option<u64>,
+}
+
+impl MigrationReport {
+    /// Whether the dataset must be migrated to the current format.
+    pub fn needs_migration(&self) -> bool {
+        let current_version = WriterVersion::default();
+        let (major, minor, patch, _) = current_version.semver_or_panic();
+        let outdated = match &self.writer_version {
+            Some(writer_version) => writer_version.older_than(major, minor, patch),
+            None => true,
+        };
+        outdated || !self.migrated_fragment_ids.is_empty() || !self.migrated_indices.is_empty()
+    }
+}
+
+pub(super) async fn migrate(dataset: &mut Dataset, dry_run: bool) -> Result<MigrationReport> {
+    let latest_version = dataset.latest_version_id().await?;
+    if dataset.manifest.version != latest_version {
+        *dataset = dataset.checkout_version(latest_version).await?;
+    }
+
+    // The versions of Lance prior to when we started writing the writer version
+    // sometimes wrote incorrect statistics, so they are recomputed, like on commit.
+    let writer_version = dataset.manifest.writer_version.clone();
+    let recompute_stats = writer_version.is_none();
+    let migrated_fragment_ids =
+        migrate_fragments(dataset, &dataset.manifest.fragments, recompute_stats)
+            .await?
+            .into_iter()
+            .zip(dataset.manifest.fragments.iter())
+            .filter(|(migrated, fragment)| migrated != *fragment)
+            .map(|(migrated, _)| migrated.id)
+            .collect();
+    let mut migrated_indices = dataset
+        .load_indices()
+        .await?
+        .into_iter()
+        .filter(|index| index_needs_migration(dataset, index))
+        .map(|index| index.name)
+        .collect::<Vec<_>>();
+    migrated_indices.dedup();
+
+    let mut report = MigrationReport {
+        writer_version,
+        migrated_fragment_ids,
+        migrated_indices,
+        committed_version: None,
+    };
+    if dry_run || !report.needs_migration() {
+        return Ok(report);
+    }
+
+    // The migrations themselves are applied by the commit of any transaction.
+    let transaction = Transaction::new(dataset.manifest.version, Operation::Migrate, None);
+    let manifest = commit_transaction(
+        dataset,
+        &dataset.object_store,
+        &transaction,
+        &Default::default(),
+        &dataset.commit_config,
+    )
+    .await?;
+    report.committed_version = Some(manifest.version);
+    dataset.manifest = Arc::new(manifest);
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::dataset::tests::copy_test_data_to_tmp;
+
+    #[tokio::test]
+    async fn test_migrate() {
+        let test_dir = copy_test_data_to_tmp("v0.8.0/migrated_from_v0.7.5").unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let mut dataset = Dataset::open(test_uri).await.unwrap();
+        let version = dataset.version().version;
+        let count_rows = dataset.count_rows().await.unwrap();
+
+        let report = dataset.migrate(true).await.unwrap();
+        assert!(report.needs_migration());
+        assert!(report.writer_version.is_none());
+        assert!(!report.migrated_fragment_ids.is_empty());
+        assert!(report.committed_version.is_none());
+        // A dry run doesn't commit anything.
+        assert_eq!(dataset.latest_version_id().await.unwrap(), version);
+
+        let migrated = dataset.migrate(false).await.unwrap();
+        assert_eq!(
+            migrated,
+            MigrationReport {
+                committed_version: Some(version + 1),
+                ..report
+            }
+        );
+        assert_eq!(dataset.version().version, version + 1);
+        assert_eq!(
+            dataset.manifest.writer_version,
+            Some(WriterVersion::default())
+        );
+        assert!(dataset
+            .manifest
+            .fragments
+            .iter()
+            .all(|fragment| fragment.physical_rows.is_some()));
+        assert_eq!(dataset.count_rows().await.unwrap(), count_rows);
+        dataset.validate().await.unwrap();
+        let transactions = dataset.transactions().await.unwrap();
+        assert_eq!(
+            transactions.last().unwrap().operation_name(),
+            Some("Migrate")
+        );
+
+        // The dataset is up to date now.
+        let report = dataset.migrate(false).await.unwrap();
+        assert!(!report.needs_migration());
+        assert!(report.committed_version.is_none());
+        assert_eq!(dataset.version().version, version + 1);
+    }
+
+    #[tokio::test]
+    async fn test_migrate_indices() {
+        let test_dir = copy_test_data_to_tmp("v0.8.14/corrupt_index").unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let mut dataset = Dataset::open(test_uri).await.unwrap();
+
+        let report = dataset.migrate(false).await.unwrap();
+        assert!(!report.migrated_indices.is_empty());
+        assert!(report.committed_version.is_some());
+
+        let indices = dataset.load_indices().await.unwrap();
+        assert!(indices
+            .iter()
+            .all(|index| !index_needs_migration(&dataset, index)));
+    }
+}
@@ -34,6 +34,13 @@
 //! that fragment will be remapped.  However, we cannot combine indexed fragments
 //! with unindexed fragments.
 //!
+//! Compaction can also cluster the rows, so that filtered scans touch fewer
+//! pages, by setting [CompactionOptions::order_by]. The rows are then sorted by
+//! some columns, or ordered along a Z-order curve over several columns (see
+//! [RowOrder]). In that case every fragment is rewritten, and the rows of all
+//! the fragments that can be combined are reordered together, so they must fit
+//! in memory.
+//!
 //! ```rust
 //! # use std::sync::Arc;
 //! # use tokio::runtime::Runtime;
@@ -96,7 +103,11 @@ use std::collections::HashMap;
 use std::ops::{AddAssign, Range};
 use std::sync::{Arc, RwLock};
 
-use arrow_array::{RecordBatch, UInt64Array};
+use arrow_array::{
+    cast::AsArray, types::UInt64Type, ArrayRef, RecordBatch, UInt32Array, UInt64Array,
+};
+use arrow_ord::sort::{lexsort_to_indices, sort_to_indices, SortColumn};
+use arrow_select::{concat::concat_batches, take::take};
 use async_trait::async_trait;
 use datafusion::error::Result as DFResult;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
@@ -105,16 +116,18 @@ use futures::{StreamExt, TryStreamExt};
 use nohash_hasher::{BuildNoHashHasher, IntMap};
 use roaring::{RoaringBitmap, RoaringTreemap};
 use serde::{Deserialize, Serialize};
+use snafu::{location, Location};
 use uuid::Uuid;
 
 use crate::dataset::ROW_ID;
 use crate::format::RowAddress;
 use crate::io::commit::{commit_transaction, migrate_fragments};
-use crate::Result;
 use crate::{format::Fragment, Dataset};
+use crate::{Error, Result};
 
 use super::fragment::FileFragment;
 use super::index::DatasetIndexRemapperOptions;
+use super::rowids::{assign_row_ids, stable_row_ids};
 use super::transaction::{Operation, RewriteGroup, RewrittenIndex, Transaction};
 use super::{write_fragments_internal, WriteMode, WriteParams};
 
@@ -175,6 +188,99 @@ pub struct CompactionOptions {
     pub materialize_deletions_threshold: f32,
     /// The number of threads to use. Defaults to the number of cores.
     pub num_threads: usize,
+    /// The order to rewrite the rows in. Defaults to `None`, which keeps the
+    /// insertion order and only rewrites the fragments that need compaction.
+    pub order_by: Option<RowOrder>,
+}
+
+/// An order to cluster the rows of a dataset in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RowOrder {
+    /// Sort the rows by the columns, in ascending order with nulls first.
+    Sort(Vec<String>),
+    /// Order the rows along a Z-order curve over the columns, which keeps
+    /// the rows with close values in every column close together.
+    ZOrder(Vec<String>),
+}
+
+impl RowOrder {
+    fn columns(&self) -> &[String] {
+        match self {
+            Self::Sort(columns) | Self::ZOrder(columns) => columns,
+        }
+    }
+
+    fn validate(&self, dataset: &Dataset) -> Result<()> {
+        if self.columns().is_empty() {
+            return Err(Error::invalid_input(
+                "The order of the rows needs at least one column",
+                location!(),
+            ));
+        }
+        for column in self.columns() {
+            if dataset.schema().field(column).is_none() {
+                return Err(Error::invalid_input(
+                    format!("Column {} does not exist in the dataset", column),
+                    location!(),
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// The indices of the rows of `batch` in this order.
+    fn sort_to_indices(&self, batch: &RecordBatch) -> Result<UInt32Array> {
+        let columns = self
+            .columns()
+            .iter()
+            .map(|column| {
+                batch.column_by_name(column).ok_or_else(|| {
+                    Error::invalid_input(
+                        format!("Column {} does not exist in the dataset", column),
+                        location!(),
+                    )
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        match self {
+            Self::Sort(_) => {
+                let sort_columns = columns
+                    .into_iter()
+                    .map(|values| SortColumn {
+                        values: values.clone(),
+                        options: None,
+                    })
+                    .collect::<Vec<_>>();
+                Ok(lexsort_to_indices(&sort_columns, None)?)
+            }
+            Self::ZOrder(_) => {
+                let keys = z_order_keys(&columns)?;
+                Ok(sort_to_indices(&keys, None, None)?)
+            }
+        }
+    }
+}
+
+/// Compute the position of each row on a Z-order curve over the columns.
+///
+/// The values of each column are replaced by their rank, scaled to the same
+/// number of bits for every column, and the bits of the columns are interleaved.
+fn z_order_keys(columns: &[&ArrayRef]) -> Result<UInt64Array> {
+    let num_rows = columns.first().map(|column| column.len()).unwrap_or(0);
+    let bits = (u64::BITS as usize / columns.len()).min(32);
+    let mut keys = vec![0_u64; num_rows];
+    for (column_idx, column) in columns.iter().enumerate() {
+        let ranks = sort_to_indices(column, None, None)?;
+        for (rank, row) in ranks.values().iter().enumerate() {
+            let scaled = ((rank as u128) << bits) / num_rows as u128;
+            let mut key = 0;
+            for bit in 0..bits {
+                key |= ((scaled >> bit) as u64 & 1) << (bit * columns.len() + column_idx);
+            }
+            keys[*row as usize] |= key;
+        }
+    }
+    Ok(UInt64Array::from(keys))
 }
 
 impl Default for CompactionOptions {
@@ -186,6 +292,7 @@ impl Default for CompactionOptions {
             materialize_deletions: true,
             materialize_deletions_threshold: 0.1,
             num_threads: num_cpus::get(),
+            order_by: None,
         }
     }
 }
@@ -526,10 +633,17 @@ pub async fn plan_compaction(
     let mut current_bin: Option<CandidateBin> = None;
     let mut i = 0;
 
+    if let Some(order_by) = &options.order_by {
+        order_by.validate(dataset)?;
+    }
+
     while let Some(res) = fragment_metrics.next().await {
         let (fragment, metrics) = res?;
 
-        let candidacy = if options.materialize_deletions
+        let candidacy = if options.order_by.is_some() {
+            // All the rows are reordered.
+            Some(CompactionCandidacy::CompactItself)
+        } else if options.materialize_deletions
             && metrics.deletion_percentage() > options.materialize_deletions_threshold
         {
             Some(CompactionCandidacy::CompactItself)
@@ -558,8 +672,11 @@ pub async fn plan_compaction(
             }
             (Some(candidacy), Some(bin)) => {
                 // We cannot mix "indexed" and "non-indexed" fragments and so we only consider
-                // the existing bin if it contains the same indices
-                if bin.indices == indices {
+                // the existing bin if it contains the same indices. The fragments of
+                // different partitions are not mixed either.
+                if bin.indices == indices
+                    && bin.fragments[0].partition_values == fragment.partition_values
+                {
                     // Add to current bin
                     bin.fragments.push(fragment);
                     bin.pos_range.end += 1;
@@ -594,7 +711,15 @@ pub async fn plan_compaction(
     let final_bins = candidate_bins
         .into_iter()
         .filter(|bin| !bin.is_noop())
-        .flat_map(|bin| bin.split_for_size(options.target_rows_per_fragment))
+        .flat_map(|bin| {
+            // The rows of a bin are reordered together, and split into fragments
+            // when they are written.
+            if options.order_by.is_some() {
+                vec![bin]
+            } else {
+                bin.split_for_size(options.target_rows_per_fragment)
+            }
+        })
         .map(|bin| TaskData {
             fragments: bin.fragments,
         });
@@ -678,6 +803,42 @@ fn make_rowid_capture_stream(
     Ok(Box::pin(stream))
 }
 
+/// Collect the rows of `data` and reorder them, returning them without the row
+/// ids in batches of `batch_size` rows, along with the row ids in the new order.
+async fn reorder_rows(
+    data: SendableRecordBatchStream,
+    order_by: &RowOrder,
+    batch_size: usize,
+) -> Result<(SendableRecordBatchStream, Vec<u64>)> {
+    let schema = data.schema();
+    let batches = data.try_collect::<Vec<_>>().await?;
+    let batch = concat_batches(&schema, &batches)?;
+    let indices = order_by.sort_to_indices(&batch)?;
+    let columns = batch
+        .columns()
+        .iter()
+        .map(|column| take(column, &indices, None))
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    let batch = RecordBatch::try_new(schema.clone(), columns)?;
+
+    let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>().values().to_vec();
+    let (row_id_idx, _) = schema
+        .column_with_name(ROW_ID)
+        .expect("Received a batch without row ids");
+    let non_row_ids_cols = (0..schema.fields.len())
+        .filter(|col| *col != row_id_idx)
+        .collect::<Vec<_>>();
+    let batch = batch.project(&non_row_ids_cols)?;
+
+    let batch_size = batch_size.max(1);
+    let batches = (0..batch.num_rows())
+        .step_by(batch_size)
+        .map(|offset| Ok(batch.slice(offset, batch_size.min(batch.num_rows() - offset))))
+        .collect::<Vec<DFResult<RecordBatch>>>();
+    let stream = RecordBatchStreamAdapter::new(batch.schema(), futures::stream::iter(batches));
+    Ok((Box::pin(stream), row_ids))
+}
+
 /// Iterator that yields row_ids that are in the given fragments but not in
 /// the given row_ids iterator.
 struct MissingIds<'a, I: Iterator<Item = u64>> {
@@ -752,6 +913,7 @@ impl<'a, I: Iterator<Item = u64>> Iterator for MissingIds<'a, I> {
 
 fn transpose_row_ids(
     row_ids: RoaringTreemap,
+    reordered_row_ids: Option<Vec<u64>>,
     old_fragments: &Vec<Fragment>,
     new_fragments: &[Fragment],
 ) -> IntMap<u64, Option<u64>> {
@@ -780,7 +942,10 @@ fn transpose_row_ids(
     // more than we need for this use case.
     let mut mapping: IntMap<u64, Option<u64>> =
         HashMap::with_capacity_and_hasher(expected_size, BuildNoHashHasher::default());
-    mapping.extend(row_ids.iter().zip(new_ids));
+    match reordered_row_ids {
+        Some(reordered_row_ids) => mapping.extend(reordered_row_ids.into_iter().zip(new_ids)),
+        None => mapping.extend(row_ids.iter().zip(new_ids)),
+    }
     MissingIds::new(row_ids.into_iter(), old_fragments).for_each(|id| {
         mapping.insert(id, None);
     });
@@ -801,7 +966,7 @@ async fn reserve_fragment_ids(dataset: &Dataset, fragments: &mut Vec<Fragment>)
         dataset.object_store(),
         &transaction,
         &Default::default(),
-        &Default::default(),
+        &dataset.commit_config,
     )
     .await?;
 
@@ -852,12 +1017,33 @@ async fn rewrite_files(
 
     let data = SendableRecordBatchStream::from(scanner.try_into_stream().await?);
     let row_ids = Arc::new(RwLock::new(RoaringTreemap::new()));
-    let data_no_row_ids = make_rowid_capture_stream(row_ids.clone(), data)?;
+    // The row ids in the order the rows are written, if it's not the order they are read in.
+    let mut reordered_row_ids = None;
+    let data_no_row_ids = if let Some(order_by) = &options.order_by {
+        let (data, ordered_row_ids) =
+            reorder_rows(data, order_by, options.max_rows_per_group).await?;
+        row_ids
+            .write()
+            .unwrap()
+            .extend(ordered_row_ids.iter().copied());
+        reordered_row_ids = Some(ordered_row_ids);
+        data
+    } else {
+        make_rowid_capture_stream(row_ids.clone(), data)?
+    };
 
+    // The fragments of a task are in the same partition, so the rewritten rows are
+    // all written to it, in order.
+    let partition_columns = if task.fragments[0].partition_values.is_empty() {
+        Vec::new()
+    } else {
+        dataset.partition_columns().to_vec()
+    };
     let params = WriteParams {
         max_rows_per_file: options.target_rows_per_fragment,
         max_rows_per_group: options.max_rows_per_group,
         mode: WriteMode::Append,
+        partition_columns,
         ..Default::default()
     };
     let mut new_fragments = write_fragments_internal(
@@ -874,10 +1060,20 @@ async fn rewrite_files(
         .into_inner()
         .expect("Row ids mutex still locked");
 
+    // The rewritten rows keep their stable row ids.
+    if dataset.has_stable_row_ids() {
+        let row_addrs = match &reordered_row_ids {
+            Some(reordered_row_ids) => reordered_row_ids.clone(),
+            None => row_ids.iter().collect(),
+        };
+        let stable_row_ids = stable_row_ids(&fragments, &row_addrs)?;
+        assign_row_ids(&mut new_fragments, &stable_row_ids)?;
+    }
+
     reserve_fragment_ids(&dataset, &mut new_fragments).await?;
 
     let row_id_map: IntMap<u64, Option<u64>> =
-        transpose_row_ids(row_ids, &fragments, &new_fragments);
+        transpose_row_ids(row_ids, reordered_row_ids, &fragments, &new_fragments);
 
     metrics.files_removed = task
         .fragments
@@ -930,22 +1126,41 @@ pub async fn commit_compaction(
         rewrite_groups.push(rewrite_group);
     }
 
-    let index_remapper = options.create_remapper(dataset)?;
     let affected_ids = rewrite_groups
         .iter()
         .flat_map(|group| group.old_fragments.iter().map(|frag| frag.id))
         .collect::<Vec<_>>();
 
-    let remapped_indices = index_remapper
-        .remap_indices(row_id_map, &affected_ids)
-        .await?;
-    let rewritten_indices = remapped_indices
-        .iter()
-        .map(|rewritten| RewrittenIndex {
-            old_id: rewritten.original,
-            new_id: rewritten.new,
-        })
-        .collect();
+    let rewritten_indices = if dataset.has_stable_row_ids() {
+        // The indices are keyed by the stable row ids, which the rewritten rows keep,
+        // so only the fragments they cover change.
+        dataset
+            .load_indices()
+            .await?
+            .iter()
+            .filter(|index| {
+                index.fragment_bitmap.as_ref().map_or(true, |bitmap| {
+                    affected_ids.iter().any(|id| bitmap.contains(*id as u32))
+                })
+            })
+            .map(|index| RewrittenIndex {
+                old_id: index.uuid,
+                new_id: index.uuid,
+            })
+            .collect()
+    } else {
+        let index_remapper = options.create_remapper(dataset)?;
+        let remapped_indices = index_remapper
+            .remap_indices(row_id_map, &affected_ids)
+            .await?;
+        remapped_indices
+            .iter()
+            .map(|rewritten| RewrittenIndex {
+                old_id: rewritten.original,
+                new_id: rewritten.new,
+            })
+            .collect()
+    };
 
     let transaction = Transaction::new(
         dataset.manifest.version,
@@ -961,7 +1176,7 @@ pub async fn commit_compaction(
         dataset.object_store(),
         &transaction,
         &Default::default(),
-        &Default::default(),
+        &dataset.commit_config,
     )
     .await?;
 
@@ -973,7 +1188,9 @@ pub async fn commit_compaction(
 #[cfg(test)]
 mod tests {
 
-    use arrow_array::{Float32Array, Int64Array, RecordBatch, RecordBatchIterator};
+    use arrow_array::{
+        cast::AsArray, types::Int64Type, Float32Array, Int64Array, RecordBatch, RecordBatchIterator,
+    };
     use arrow_schema::{DataType, Field, Schema};
     use arrow_select::concat::concat_batches;
     use futures::TryStreamExt;
@@ -991,12 +1208,16 @@ mod tests {
                 files: Vec::new(),
                 deletion_file: None,
                 physical_rows: Some(5),
+                row_id_sequence: None,
+                partition_values: Vec::new(),
             },
             Fragment {
                 id: 3,
                 files: Vec::new(),
                 deletion_file: None,
                 physical_rows: Some(3),
+                row_id_sequence: None,
+                partition_values: Vec::new(),
             },
         ];
         let rows = [(0, 1), (0, 3), (0, 4), (3, 0), (3, 2)]
@@ -1122,6 +1343,8 @@ mod tests {
             files: vec![],
             deletion_file: None,
             physical_rows: Some(0),
+            row_id_sequence: None,
+            partition_values: Vec::new(),
         };
         let single_bin = CandidateBin {
             fragments: vec![fragment.clone()],
@@ -1553,6 +1776,202 @@ mod tests {
         assert!(fragments[0].metadata.deletion_file.is_none());
     }
 
+    #[tokio::test]
+    async fn test_compact_streaming_appends() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let data = sample_data();
+        let reader = RecordBatchIterator::new(vec![Ok(data.slice(0, 10))], data.schema());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        for offset in (10..250).step_by(10) {
+            let reader = RecordBatchIterator::new(vec![Ok(data.slice(offset, 10))], data.schema());
+            dataset.append(reader, None).await.unwrap();
+        }
+        dataset.delete("a % 50 = 0").await.unwrap();
+        assert_eq!(dataset.get_fragments().len(), 25);
+
+        let metrics = dataset
+            .compact_files(CompactionOptions {
+                target_rows_per_fragment: 100,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(metrics.fragments_removed, 25);
+        assert_eq!(metrics.fragments_added, 4);
+
+        let fragments = dataset.get_fragments();
+        assert_eq!(fragments.len(), 4);
+        for fragment in fragments {
+            assert!(fragment.metadata.deletion_file.is_none());
+            assert!(fragment.count_rows().await.unwrap() <= 100);
+        }
+
+        // The rows keep their order.
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let values = batches
+            .iter()
+            .flat_map(|batch| batch["a"].as_primitive::<Int64Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        let expected = (0..250).filter(|a| a % 50 != 0).collect::<Vec<_>>();
+        assert_eq!(values, expected);
+    }
+
+    #[tokio::test]
+    async fn test_compact_sorted() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let values = (0..1000_i64).map(|i| (i * 7919) % 1000).collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("a", DataType::Int64, false),
+            Field::new("b", DataType::Int64, false),
+        ]));
+        let data = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from(values.clone())),
+                Arc::new(Int64Array::from_iter_values(values.iter().map(|a| a * 2))),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(data)], schema);
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset.delete("a % 10 = 0").await.unwrap();
+
+        let options = CompactionOptions {
+            target_rows_per_fragment: 300,
+            order_by: Some(RowOrder::Sort(vec!["a".to_string()])),
+            ..Default::default()
+        };
+        let plan = plan_compaction(&dataset, &options).await.unwrap();
+        assert_eq!(plan.tasks().len(), 1);
+        let mut results = Vec::new();
+        for task in plan.compaction_tasks() {
+            results.push(task.execute(&dataset).await.unwrap());
+        }
+
+        // The rows are mapped to their new position.
+        let projection = dataset.schema().project(&["a"]).unwrap();
+        let old_dataset = dataset.clone();
+        commit_compaction(
+            &mut dataset,
+            results.clone(),
+            Arc::new(IgnoreRemap::default()),
+        )
+        .await
+        .unwrap();
+        let (old_ids, new_ids): (Vec<u64>, Vec<u64>) = results[0]
+            .row_id_map
+            .iter()
+            .filter_map(|(old_id, new_id)| new_id.map(|new_id| (*old_id, new_id)))
+            .unzip();
+        assert_eq!(old_ids.len(), 900);
+        assert_eq!(
+            old_dataset.take_rows(&old_ids, &projection).await.unwrap(),
+            dataset.take_rows(&new_ids, &projection).await.unwrap()
+        );
+
+        let fragments = dataset.get_fragments();
+        assert_eq!(fragments.len(), 3);
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        let expected = (0..1000).filter(|a| a % 10 != 0).collect::<Vec<i64>>();
+        assert_eq!(
+            batch["a"].as_primitive::<Int64Type>().values().to_vec(),
+            expected
+        );
+        assert_eq!(
+            batch["b"].as_primitive::<Int64Type>().values().to_vec(),
+            expected.iter().map(|a| a * 2).collect::<Vec<_>>()
+        );
+
+        let options = CompactionOptions {
+            order_by: Some(RowOrder::Sort(vec!["missing".to_string()])),
+            ..Default::default()
+        };
+        assert!(plan_compaction(&dataset, &options).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_compact_z_order() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        // A 32x32 grid, in a scrambled order.
+        let cells = (0..1024_i64).map(|i| (i * 397) % 1024).collect::<Vec<_>>();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("x", DataType::Int64, false),
+            Field::new("y", DataType::Int64, false),
+        ]));
+        let data = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int64Array::from_iter_values(cells.iter().map(|c| c % 32))),
+                Arc::new(Int64Array::from_iter_values(cells.iter().map(|c| c / 32))),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(data)], schema);
+        let write_params = WriteParams {
+            max_rows_per_file: 128,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+
+        let options = CompactionOptions {
+            target_rows_per_fragment: 256,
+            order_by: Some(RowOrder::ZOrder(vec!["x".to_string(), "y".to_string()])),
+            ..Default::default()
+        };
+        dataset.compact_files(options).await.unwrap();
+
+        // Each fragment covers one quadrant of the grid.
+        let fragments = dataset.get_fragments();
+        assert_eq!(fragments.len(), 4);
+        for fragment in fragments {
+            let batches = fragment
+                .scan()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+            assert_eq!(batch.num_rows(), 256);
+            for column in ["x", "y"] {
+                let values = batch[column].as_primitive::<Int64Type>();
+                let min = values.values().iter().min().unwrap();
+                let max = values.values().iter().max().unwrap();
+                assert_eq!(max - min, 15);
+            }
+        }
+    }
+
     #[tokio::test]
     async fn test_compact_distributed() {
         // Can run the tasks independently
@@ -1633,18 +2052,24 @@ mod tests {
                 files: Vec::new(),
                 deletion_file: None,
                 physical_rows: Some(5),
+                row_id_sequence: None,
+                partition_values: Vec::new(),
             },
             Fragment {
                 id: 3,
                 files: Vec::new(),
                 deletion_file: None,
                 physical_rows: Some(3),
+                row_id_sequence: None,
+                partition_values: Vec::new(),
             },
             Fragment {
                 id: 1,
                 files: Vec::new(),
                 deletion_file: None,
                 physical_rows: Some(3),
+                row_id_sequence: None,
+                partition_values: Vec::new(),
             },
         ];
 
@@ -0,0 +1,513 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hive-style partitioning of the data files by the values of some columns, and the
+//! pruning of the partitions which can not match a filter.
+//!
+//! The rows with the same values of the partition columns are written to fragments
+//! whose data files are in the directory `data/{column}={value}/...` of their
+//! partition, and which record the values, formatted as strings, in the manifest.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use arrow_array::{Array, ArrayRef, RecordBatch, UInt32Array, UInt64Array};
+use arrow_cast::display::array_value_to_string;
+use arrow_schema::{DataType, Schema as ArrowSchema};
+use datafusion::common::Column;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_optimizer::pruning::{PruningPredicate, PruningStatistics};
+use datafusion::scalar::ScalarValue;
+use lance_arrow::RecordBatchExt;
+use snafu::{location, Location};
+
+use super::Dataset;
+use crate::datatypes::Schema;
+use crate::format::{Fragment, Manifest};
+use crate::io::exec::Planner;
+use crate::{Error, Result};
+
+/// The name of the directory of the null values of a partition column, like Hive.
+const NULL_PARTITION: &str = "__HIVE_DEFAULT_PARTITION__";
+
+/// The values of the partition columns of some rows, formatted as strings.
+pub(super) type PartitionValues = Vec<Option<String>>;
+
+fn is_partition_type(data_type: &DataType) -> bool {
+    data_type.is_integer()
+        || matches!(
+            data_type,
+            DataType::Boolean | DataType::Utf8 | DataType::LargeUtf8 | DataType::Date32
+        )
+}
+
+/// Check that `columns` are distinct top-level columns of `schema` that can be
+/// partitioned by, i.e., of boolean, integer, string or date type.
+pub(super) fn check_partition_columns(schema: &Schema, columns: &[String]) -> Result<()> {
+    let mut seen = HashSet::new();
+    for column in columns {
+        let Some(field) = schema.fields.iter().find(|field| &field.name == column) else {
+            return Err(Error::invalid_input(
+                format!("Partition column {} is not a top-level column", column),
+                location!(),
+            ));
+        };
+        if !is_partition_type(&field.data_type()) {
+            return Err(Error::invalid_input(
+                format!(
+                    "Cannot partition by column {} of type {}",
+                    column,
+                    field.data_type()
+                ),
+                location!(),
+            ));
+        }
+        if !seen.insert(column) {
+            return Err(Error::invalid_input(
+                format!("Partition column {} is given twice", column),
+                location!(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// The partition columns of the data appended to the dataset of `manifest`, which
+/// must be those of the dataset if `partition_columns` is not empty.
+pub(super) fn append_partition_columns(
+    manifest: &Manifest,
+    partition_columns: &[String],
+) -> Result<Vec<String>> {
+    if !partition_columns.is_empty() && partition_columns != manifest.partition_columns {
+        return Err(Error::invalid_input(
+            format!(
+                "Cannot append data partitioned by {:?} to a dataset partitioned by {:?}: \
+                 the partitioning can only be changed by an overwrite",
+                partition_columns, manifest.partition_columns
+            ),
+            location!(),
+        ));
+    }
+    Ok(manifest.partition_columns.clone())
+}
+
+/// The positions of the rows of `batch` of each partition of the partition `columns`,
+/// in the order of the first row of each partition.
+fn partition_rows(
+    batch: &RecordBatch,
+    columns: &[String],
+) -> Result<Vec<(PartitionValues, Vec<u32>)>> {
+    let arrays = columns
+        .iter()
+        .map(|column| {
+            batch.column_by_name(column).ok_or_else(|| {
+                Error::invalid_input(
+                    format!("Partition column {} is missing from the data", column),
+                    location!(),
+                )
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut partitions: Vec<(PartitionValues, Vec<u32>)> = Vec::new();
+    let mut positions: HashMap<PartitionValues, usize> = HashMap::new();
+    for row in 0..batch.num_rows() {
+        let values = arrays
+            .iter()
+            .map(|array| {
+                array
+                    .is_valid(row)
+                    .then(|| array_value_to_string(array, row))
+                    .transpose()
+            })
+            .collect::<std::result::Result<PartitionValues, _>>()?;
+        let position = *positions.entry(values.clone()).or_insert_with(|| {
+            partitions.push((values, Vec::new()));
+            partitions.len() - 1
+        });
+        partitions[position].1.push(row as u32);
+    }
+    Ok(partitions)
+}
+
+/// Split the rows of `batch` by the values of the partition `columns`, in the order
+/// of the first row of each partition.
+pub(super) fn split_by_partition(
+    batch: &RecordBatch,
+    columns: &[String],
+) -> Result<Vec<(PartitionValues, RecordBatch)>> {
+    let mut partitions = partition_rows(batch, columns)?;
+    if partitions.len() == 1 {
+        let (values, _) = partitions.pop().unwrap();
+        return Ok(vec![(values, batch.clone())]);
+    }
+    partitions
+        .into_iter()
+        .map(|(values, rows)| Ok((values, batch.take(&UInt32Array::from(rows))?)))
+        .collect()
+}
+
+/// The order of the rows of `batch` grouped by the values of the partition `columns`.
+///
+/// Once written in this order, the rows are in the same order in the written
+/// fragments, which is needed to assign them their stable row ids.
+pub(super) fn partition_order(batch: &RecordBatch, columns: &[String]) -> Result<UInt32Array> {
+    if columns.is_empty() {
+        return Ok(UInt32Array::from_iter_values(0..batch.num_rows() as u32));
+    }
+    Ok(partition_rows(batch, columns)?
+        .into_iter()
+        .flat_map(|(_, rows)| rows)
+        .collect())
+}
+
+/// The directory of the data files of the partition with the `values` of the
+/// partition `columns`, relative to the data directory.
+///
+/// The characters which are not safe in paths are replaced, so the directory names
+/// are only informative: the values are read from the manifest.
+pub(super) fn partition_dir(columns: &[String], values: &[Option<String>]) -> String {
+    let path_safe = |name: &str| -> String {
+        name.chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect()
+    };
+    columns
+        .iter()
+        .zip(values)
+        .map(|(column, value)| {
+            let value = value
+                .as_deref()
+                .map_or(NULL_PARTITION.to_string(), path_safe);
+            format!("{}={}", path_safe(column), value)
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// The values of the partition columns of the fragments, each fragment being a
+/// container whose minimum and maximum are its value.
+struct FragmentPartitions {
+    num_fragments: usize,
+    columns: HashMap<String, PartitionColumn>,
+}
+
+/// The values of a partition column in the fragments.
+struct PartitionColumn {
+    data_type: DataType,
+    /// The value and the null count, if known, of each fragment, or None if the fragment
+    /// has no partition values.
+    values: Vec<Option<(ScalarValue, Option<u64>)>>,
+}
+
+impl FragmentPartitions {
+    fn values(&self, column: &Column) -> Option<ArrayRef> {
+        let PartitionColumn { data_type, values } = self.columns.get(&column.name)?;
+        let null = ScalarValue::try_from(data_type).ok()?;
+        ScalarValue::iter_to_array(values.iter().map(|value| {
+            value
+                .as_ref()
+                .map(|(value, _)| value.clone())
+                .unwrap_or_else(|| null.clone())
+        }))
+        .ok()
+    }
+}
+
+impl PruningStatistics for FragmentPartitions {
+    fn min_values(&self, column: &Column) -> Option<ArrayRef> {
+        self.values(column)
+    }
+
+    fn max_values(&self, column: &Column) -> Option<ArrayRef> {
+        self.values(column)
+    }
+
+    fn num_containers(&self) -> usize {
+        self.num_fragments
+    }
+
+    fn null_counts(&self, column: &Column) -> Option<ArrayRef> {
+        let values = &self.columns.get(&column.name)?.values;
+        Some(Arc::new(UInt64Array::from_iter(values.iter().map(
+            |value| value.as_ref().and_then(|(_, null_count)| *null_count),
+        ))))
+    }
+}
+
+/// The `fragments` which may have rows matching `filter`, according to the values
+/// of the partition columns of the dataset recorded in the fragments.
+///
+/// Unlike [`super::zone_map::prune_fragments`], no file is read. The fragments
+/// without partition values, e.g., written before the dataset was partitioned, are
+/// kept.
+pub(super) fn prune_partitions(
+    dataset: &Dataset,
+    fragments: &[Fragment],
+    filter: &Expr,
+) -> Result<Vec<Fragment>> {
+    let partition_columns = &dataset.manifest.partition_columns;
+    let columns = Planner::column_names_in_expr(filter);
+    let schema = dataset.schema();
+    let filtered_partitions = partition_columns
+        .iter()
+        .enumerate()
+        .filter(|(_, name)| columns.contains(name))
+        .filter_map(|(idx, name)| {
+            let field = schema.fields.iter().find(|field| &field.name == name)?;
+            Some((idx, name.as_str(), field.data_type()))
+        })
+        .collect::<Vec<_>>();
+    if filtered_partitions.is_empty() || fragments.is_empty() {
+        return Ok(fragments.to_vec());
+    }
+
+    let arrow_schema = Arc::new(ArrowSchema::from(&schema.project(&columns)?));
+    let planner = Planner::new(arrow_schema.clone());
+    let predicate = PruningPredicate::try_new(planner.create_physical_expr(filter)?, arrow_schema)?;
+    if predicate.allways_true() {
+        return Ok(fragments.to_vec());
+    }
+
+    let partitions = FragmentPartitions {
+        num_fragments: fragments.len(),
+        columns: filtered_partitions
+            .into_iter()
+            .map(|(idx, name, data_type)| {
+                let values = fragments
+                    .iter()
+                    .map(|fragment| {
+                        if fragment.partition_values.len() != partition_columns.len() {
+                            return None;
+                        }
+                        match &fragment.partition_values[idx] {
+                            Some(value) => ScalarValue::try_from_string(value.clone(), &data_type)
+                                .ok()
+                                .map(|value| (value, Some(0))),
+                            None => Some((
+                                ScalarValue::try_from(&data_type).ok()?,
+                                fragment.physical_rows.map(|rows| rows as u64),
+                            )),
+                        }
+                    })
+                    .collect();
+                (name.to_string(), PartitionColumn { data_type, values })
+            })
+            .collect(),
+    };
+    let keep = predicate.prune(&partitions)?;
+    Ok(fragments
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(fragment, _)| fragment.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Date32Array, Int32Array, RecordBatchIterator, StringArray};
+    use arrow_schema::Field as ArrowField;
+    use futures::TryStreamExt;
+    use tempfile::tempdir;
+
+    use crate::dataset::{WriteMode, WriteParams};
+
+    fn batch(schema: &Arc<ArrowSchema>, days: std::ops::Range<i32>) -> RecordBatch {
+        // Ten rows per day, with a null region every fifth day.
+        let num_rows = (days.end - days.start) * 10;
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Date32Array::from_iter_values(
+                    (0..num_rows).map(|i| days.start + i / 10),
+                )),
+                Arc::new(StringArray::from_iter((0..num_rows).map(|i| {
+                    let day = days.start + i / 10;
+                    (day % 5 != 0).then(|| format!("region/{}", i % 2))
+                }))),
+                Arc::new(Int32Array::from_iter_values(0..num_rows)),
+            ],
+        )
+        .unwrap()
+    }
+
+    async fn count(dataset: &Dataset, filter: &str) -> usize {
+        dataset
+            .scan()
+            .filter(filter)
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum()
+    }
+
+    #[test]
+    fn test_partition_dir() {
+        let columns = vec!["day".to_string(), "region".to_string()];
+        assert_eq!(
+            partition_dir(
+                &columns,
+                &[
+                    Some("2024-01-01".to_string()),
+                    Some("us/east 1".to_string())
+                ]
+            ),
+            "day=2024-01-01/region=us_east_1"
+        );
+        assert_eq!(
+            partition_dir(&columns, &[Some("2024-01-01".to_string()), None]),
+            "day=2024-01-01/region=__HIVE_DEFAULT_PARTITION__"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partitioned_dataset() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("day", DataType::Date32, false),
+            ArrowField::new("region", DataType::Utf8, true),
+            ArrowField::new("i", DataType::Int32, false),
+        ]));
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            partition_columns: vec!["day".to_string(), "region".to_string()],
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch(&schema, 0..4))], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert_eq!(dataset.partition_columns(), ["day", "region"]);
+
+        // One fragment per partition, in the directory of the partition.
+        let fragments = dataset.fragments().as_ref().clone();
+        assert_eq!(fragments.len(), 7);
+        assert_eq!(
+            fragments[0].partition_values,
+            vec![Some("1970-01-01".to_string()), None]
+        );
+        assert_eq!(fragments[0].physical_rows, Some(10));
+        assert!(fragments[0].files[0]
+            .path
+            .starts_with("day=1970-01-01/region=__HIVE_DEFAULT_PARTITION__/"));
+        assert_eq!(
+            fragments[1].partition_values,
+            vec![Some("1970-01-02".to_string()), Some("region/0".to_string())]
+        );
+        assert!(fragments[1].files[0]
+            .path
+            .starts_with("day=1970-01-02/region=region_0/"));
+
+        // Appends are partitioned like the dataset.
+        let reader = RecordBatchIterator::new(vec![Ok(batch(&schema, 4..6))], schema.clone());
+        dataset.append(reader, None).await.unwrap();
+        assert_eq!(dataset.fragments().len(), 10);
+        assert_eq!(dataset.count_rows().await.unwrap(), 60);
+
+        let planner = Planner::new(schema.clone());
+        let cases = [
+            ("day = date '1970-01-02'", 2),
+            ("day >= date '1970-01-05'", 3),
+            ("region IS NULL", 2),
+            ("region = 'region/1' AND day = date '1970-01-02'", 1),
+            ("i < 3", 10),
+        ];
+        for (filter, expected) in cases {
+            let expr = planner.parse_filter(filter).unwrap();
+            let expr = planner.optimize_expr(expr).unwrap();
+            let pruned = prune_partitions(&dataset, dataset.fragments(), &expr).unwrap();
+            assert_eq!(pruned.len(), expected, "filter: {filter}");
+        }
+
+        assert_eq!(count(&dataset, "day = date '1970-01-02'").await, 10);
+        assert_eq!(count(&dataset, "region IS NULL").await, 20);
+        assert_eq!(
+            count(&dataset, "region = 'region/1' AND day >= date '1970-01-04'").await,
+            10
+        );
+
+        // The fragments rewritten by compaction stay in their partitions.
+        dataset.delete("i < 4").await.unwrap();
+        dataset.compact_files(Default::default()).await.unwrap();
+        let fragments = dataset.fragments().as_ref().clone();
+        assert_eq!(fragments.len(), 10);
+        for fragment in fragments.iter() {
+            assert!(fragment.deletion_file.is_none());
+            let dir = partition_dir(dataset.partition_columns(), &fragment.partition_values);
+            assert!(fragment.files[0].path.starts_with(&dir));
+        }
+        assert_eq!(dataset.count_rows().await.unwrap(), 52);
+        assert_eq!(count(&dataset, "region IS NULL").await, 16);
+
+        assert!(dataset.drop_columns(&["day"]).await.is_err());
+
+        // The partitioning can only be changed by an overwrite.
+        let params = WriteParams {
+            mode: WriteMode::Append,
+            partition_columns: vec!["region".to_string()],
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch(&schema, 6..7))], schema.clone());
+        assert!(Dataset::write(reader, test_uri, Some(params))
+            .await
+            .is_err());
+        let params = WriteParams {
+            mode: WriteMode::Overwrite,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch(&schema, 6..7))], schema.clone());
+        let dataset = Dataset::write(reader, test_uri, Some(params))
+            .await
+            .unwrap();
+        assert!(dataset.partition_columns().is_empty());
+        assert_eq!(dataset.fragments().len(), 1);
+        assert!(dataset.fragments()[0].partition_values.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_partition_columns() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("day", DataType::Date32, false),
+            ArrowField::new("region", DataType::Utf8, true),
+            ArrowField::new("i", DataType::Int32, false),
+        ]));
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        for columns in [vec!["x"], vec!["day", "day"]] {
+            let write_params = WriteParams {
+                partition_columns: columns.iter().map(|c| c.to_string()).collect(),
+                ..Default::default()
+            };
+            let reader = RecordBatchIterator::new(vec![Ok(batch(&schema, 0..1))], schema.clone());
+            assert!(Dataset::write(reader, test_uri, Some(write_params))
+                .await
+                .is_err());
+        }
+    }
+}
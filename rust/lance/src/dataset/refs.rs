@@ -0,0 +1,224 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Named references to versions of a dataset.
+//!
+//! Each tag is stored as a small JSON file at `_refs/tags/{name}.json`, next to
+//! the manifests under `_versions`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use futures::TryStreamExt;
+use object_store::path::Path;
+use serde::{Deserialize, Serialize};
+use snafu::{location, Location};
+
+use crate::io::ObjectStore;
+use crate::{Error, Result};
+
+const REFS_DIR: &str = "_refs";
+const TAGS_DIR: &str = "tags";
+
+/// The contents of a tag.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagContents {
+    /// The version of the dataset the tag points to.
+    pub version: u64,
+}
+
+/// The tags of a dataset, mapping names to versions.
+///
+/// Obtained from [`super::Dataset::tags`].
+#[derive(Debug, Clone)]
+pub struct Tags {
+    object_store: Arc<ObjectStore>,
+    base: Path,
+}
+
+impl Tags {
+    pub(crate) fn new(object_store: Arc<ObjectStore>, base: Path) -> Self {
+        Self { object_store, base }
+    }
+
+    fn tags_dir(&self) -> Path {
+        self.base.child(REFS_DIR).child(TAGS_DIR)
+    }
+
+    fn tag_path(&self, tag: &str) -> Path {
+        self.tags_dir().child(format!("{}.json", tag))
+    }
+
+    /// List all tags of the dataset.
+    pub async fn list(&self) -> Result<HashMap<String, TagContents>> {
+        let tags_dir = self.tags_dir();
+        self.object_store
+            .inner
+            .list(Some(&tags_dir))
+            .await?
+            .map_err(Error::from)
+            .try_filter_map(|meta| async move {
+                let Some(tag) = meta
+                    .location
+                    .filename()
+                    .and_then(|name| name.strip_suffix(".json"))
+                    .map(String::from)
+                else {
+                    return Ok(None);
+                };
+                let contents = self.read(&meta.location).await?;
+                Ok(Some((tag, contents)))
+            })
+            .try_collect()
+            .await
+    }
+
+    /// Get the contents of a tag.
+    pub async fn get(&self, tag: &str) -> Result<TagContents> {
+        check_valid_name("tag", tag)?;
+        let path = self.tag_path(tag);
+        if !self.object_store.exists(&path).await? {
+            return Err(Error::NotFound {
+                uri: path.to_string(),
+                location: location!(),
+            });
+        }
+        self.read(&path).await
+    }
+
+    /// Create a tag pointing to `version`.
+    ///
+    /// Returns an error if the tag already exists or the version does not exist.
+    pub async fn create(&self, tag: &str, version: u64) -> Result<()> {
+        check_valid_name("tag", tag)?;
+        let path = self.tag_path(tag);
+        if self.object_store.exists(&path).await? {
+            return Err(Error::invalid_input(
+                format!("Tag {} already exists", tag),
+                location!(),
+            ));
+        }
+        let manifest_path = self
+            .object_store
+            .commit_handler
+            .resolve_version(&self.base, version, &self.object_store.inner)
+            .await?;
+        if !self.object_store.exists(&manifest_path).await? {
+            return Err(Error::invalid_input(
+                format!("Version {} does not exist", version),
+                location!(),
+            ));
+        }
+        let contents = serde_json::to_vec(&TagContents { version })?;
+        self.object_store.put(&path, &contents).await
+    }
+
+    /// Delete a tag. The version it points to is left untouched.
+    pub async fn delete(&self, tag: &str) -> Result<()> {
+        check_valid_name("tag", tag)?;
+        let path = self.tag_path(tag);
+        if !self.object_store.exists(&path).await? {
+            return Err(Error::NotFound {
+                uri: path.to_string(),
+                location: location!(),
+            });
+        }
+        self.object_store.delete(&path).await
+    }
+
+    async fn read(&self, path: &Path) -> Result<TagContents> {
+        let bytes = self.object_store.inner.get(path).await?.bytes().await?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+}
+
+/// Tag and branch names may contain ASCII letters, digits, `-`, `_` and `.`, and
+/// may not start with `.`, so they can be used as file names on every object store.
+pub(super) fn check_valid_name(kind: &str, name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && !name.starts_with('.')
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if valid {
+        Ok(())
+    } else {
+        Err(Error::invalid_input(
+            format!(
+                "Invalid {} name {:?}: it may only contain ASCII letters, digits, '-', '_' and '.', and may not start with '.'",
+                kind, name
+            ),
+            location!(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::types::Int32Type;
+    use lance_datagen::{array, gen, BatchCount, RowCount};
+    use tempfile::tempdir;
+
+    use crate::dataset::Dataset;
+
+    #[tokio::test]
+    async fn test_tags() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = || {
+            gen()
+                .col(Some("i".to_string()), array::step::<Int32Type>())
+                .into_reader_rows(RowCount::from(10), BatchCount::from(1))
+        };
+        let mut dataset = Dataset::write(data(), test_uri, None).await.unwrap();
+        dataset.append(data(), None).await.unwrap();
+
+        let tags = dataset.tags();
+        assert!(tags.list().await.unwrap().is_empty());
+        tags.create("prod-2024-05", 1).await.unwrap();
+        tags.create("latest", 2).await.unwrap();
+        assert_eq!(
+            tags.list().await.unwrap(),
+            HashMap::from([
+                ("prod-2024-05".to_string(), TagContents { version: 1 }),
+                ("latest".to_string(), TagContents { version: 2 }),
+            ])
+        );
+
+        let checked_out = dataset.checkout_tag("prod-2024-05").await.unwrap();
+        assert_eq!(checked_out.version().version, 1);
+        assert_eq!(checked_out.count_rows().await.unwrap(), 10);
+
+        // Tags are durable.
+        let dataset = Dataset::open(test_uri).await.unwrap();
+        assert_eq!(
+            dataset.tags().get("latest").await.unwrap(),
+            TagContents { version: 2 }
+        );
+
+        let tags = dataset.tags();
+        assert!(tags.create("latest", 1).await.is_err());
+        assert!(tags.create("missing-version", 3).await.is_err());
+        assert!(tags.create("../escape", 1).await.is_err());
+        assert!(tags.create("", 1).await.is_err());
+
+        tags.delete("latest").await.unwrap();
+        assert!(tags.get("latest").await.is_err());
+        assert!(tags.delete("latest").await.is_err());
+        assert!(dataset.checkout_tag("latest").await.is_err());
+        assert_eq!(tags.list().await.unwrap().len(), 1);
+    }
+}
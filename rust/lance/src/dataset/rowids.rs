@@ -0,0 +1,587 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Stable row ids, which are kept by the rows when their fragment is rewritten by
+//! compaction or when they are updated, unlike their address.
+//!
+//! Scans still return row addresses, but the indices are keyed by the stable row ids,
+//! so compaction does not remap them, and the updated rows stay indexed if the update
+//! did not change the indexed column. See [`IndexRowIds`].
+
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, types::UInt64Type, RecordBatch, UInt32Array, UInt64Array};
+use futures::{StreamExt, TryStreamExt};
+use lance_arrow::RecordBatchExt;
+use roaring::RoaringBitmap;
+use snafu::{location, Location};
+
+use super::{Dataset, ROW_ID};
+use crate::format::{Fragment, RowAddress, RowIdSegment, RowIdSequence};
+use crate::{Error, Result};
+
+/// The address of the row with each stable row id in a version of the dataset.
+#[derive(Debug, Default)]
+pub(super) struct RowIdIndex {
+    /// The runs of row ids of contiguous rows, by their first row id, with their end,
+    /// the id of their fragment and the offset of their first row in the fragment.
+    runs: BTreeMap<u64, (u64, u32, u32)>,
+}
+
+impl RowIdIndex {
+    pub(super) async fn try_new(dataset: &Dataset) -> Result<Self> {
+        let fragments = futures::stream::iter(dataset.get_fragments())
+            .map(|fragment| async move {
+                let deletion_vector = fragment.get_deletion_vector().await?;
+                Ok::<_, Error>((fragment, deletion_vector))
+            })
+            .buffered(num_cpus::get())
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut index = Self::default();
+        for (fragment, deletion_vector) in fragments {
+            let fragment_id = fragment.id() as u32;
+            let sequence = row_id_sequence(&fragment.metadata)?;
+            let Some(deletion_vector) = deletion_vector else {
+                let mut offset = 0;
+                for segment in &sequence.0 {
+                    match segment {
+                        RowIdSegment::Range(range) => {
+                            index.insert(range.clone(), fragment_id, offset);
+                        }
+                        RowIdSegment::Array(values) => {
+                            for (idx, id) in values.iter().enumerate() {
+                                index.insert(*id..*id + 1, fragment_id, offset + idx as u32);
+                            }
+                        }
+                    }
+                    offset += segment.len() as u32;
+                }
+                continue;
+            };
+            // Only the rows left are indexed, since an updated row has the same stable
+            // row id as the deleted row it replaces.
+            let mut run: Option<(Range<u64>, u32)> = None;
+            for (offset, id) in sequence.iter().enumerate() {
+                let offset = offset as u32;
+                if deletion_vector.contains(offset) {
+                    if let Some((ids, start)) = run.take() {
+                        index.insert(ids, fragment_id, start);
+                    }
+                    continue;
+                }
+                match &mut run {
+                    Some((ids, _)) if ids.end == id => ids.end += 1,
+                    _ => {
+                        if let Some((ids, start)) = run.replace((id..id + 1, offset)) {
+                            index.insert(ids, fragment_id, start);
+                        }
+                    }
+                }
+            }
+            if let Some((ids, start)) = run {
+                index.insert(ids, fragment_id, start);
+            }
+        }
+        Ok(index)
+    }
+
+    fn insert(&mut self, ids: Range<u64>, fragment_id: u32, offset: u32) {
+        self.runs.insert(ids.start, (ids.end, fragment_id, offset));
+    }
+
+    /// The address of the row with the stable row id `row_id`, or None if there is no
+    /// such row in this version, e.g., because it was deleted.
+    pub(super) fn get(&self, row_id: u64) -> Option<u64> {
+        let (start, (end, fragment_id, offset)) = self.runs.range(..=row_id).next_back()?;
+        (row_id < *end).then(|| {
+            let offset = offset + (row_id - start) as u32;
+            u64::from(RowAddress::new_from_parts(*fragment_id, offset))
+        })
+    }
+}
+
+pub(super) fn row_id_sequence(fragment: &Fragment) -> Result<&RowIdSequence> {
+    fragment
+        .row_id_sequence
+        .as_ref()
+        .ok_or_else(|| Error::Internal {
+            message: format!("Fragment {} has no stable row ids", fragment.id),
+            location: location!(),
+        })
+}
+
+/// The stable row ids of the rows at the addresses `row_addrs` in `fragments`.
+pub(super) fn stable_row_ids(fragments: &[Fragment], row_addrs: &[u64]) -> Result<Vec<u64>> {
+    let sequences = fragments
+        .iter()
+        .map(|fragment| Ok((fragment.id as u32, row_id_sequence(fragment)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+    row_addrs
+        .iter()
+        .map(|address| {
+            let address = RowAddress::new_from_id(*address);
+            sequences
+                .get(&address.fragment_id())
+                .and_then(|sequence| sequence.get(address.row_id() as usize))
+                .ok_or_else(|| {
+                    Error::invalid_input(
+                        format!("Row {} does not exist in the dataset", address),
+                        location!(),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Replace the row addresses in the `_rowid` column of `batch` by the stable row ids
+/// of the rows in `fragments`.
+pub(super) fn with_stable_row_ids(
+    fragments: &[Fragment],
+    batch: RecordBatch,
+) -> Result<RecordBatch> {
+    let row_addrs = batch[ROW_ID].as_primitive::<UInt64Type>();
+    let row_ids = stable_row_ids(fragments, row_addrs.values())?;
+    replace_row_ids(batch, row_ids)
+}
+
+fn replace_row_ids(batch: RecordBatch, row_ids: Vec<u64>) -> Result<RecordBatch> {
+    let schema = batch.schema();
+    let idx = schema.index_of(ROW_ID)?;
+    let mut columns = batch.columns().to_vec();
+    columns[idx] = Arc::new(UInt64Array::from(row_ids));
+    Ok(RecordBatch::try_new(schema, columns)?)
+}
+
+/// Resolves the row ids stored in an index of a dataset with stable row ids, which are
+/// the stable row ids of the rows, to the addresses of the rows.
+///
+/// A row id is only resolved if its row still exists and is in a fragment covered by
+/// the index. An updated row is moved to a new fragment, which the index only covers if
+/// the update did not change the indexed column: otherwise the row is found by scanning
+/// the fragment, and its entry in the index is stale.
+#[derive(Debug, Clone)]
+pub struct IndexRowIds {
+    index: Arc<RowIdIndex>,
+    fragments: RoaringBitmap,
+}
+
+impl IndexRowIds {
+    pub(super) fn new(index: Arc<RowIdIndex>, fragments: RoaringBitmap) -> Self {
+        Self { index, fragments }
+    }
+
+    /// The address of the row with the stable row id `row_id`, or None if the index
+    /// does not cover the row anymore.
+    pub fn address(&self, row_id: u64) -> Option<u64> {
+        let address = self.index.get(row_id)?;
+        self.fragments
+            .contains(RowAddress::new_from_id(address).fragment_id())
+            .then_some(address)
+    }
+
+    /// Replace the stable row ids in the `_rowid` column of `batch` by the addresses of
+    /// the rows, dropping the rows the index does not cover anymore.
+    pub fn resolve_batch(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+        let mut indices = Vec::with_capacity(row_ids.len());
+        let mut addresses = Vec::with_capacity(row_ids.len());
+        for (idx, row_id) in row_ids.values().iter().enumerate() {
+            if let Some(address) = self.address(*row_id) {
+                indices.push(idx as u32);
+                addresses.push(address);
+            }
+        }
+        let batch = if indices.len() == batch.num_rows() {
+            batch
+        } else {
+            batch.take(&UInt32Array::from(indices))?
+        };
+        replace_row_ids(batch, addresses)
+    }
+}
+
+/// Give the rows of the `fragments`, in the order they were written, the stable row
+/// ids `row_ids`, so they keep the ids of the rows they were rewritten from.
+pub(super) fn assign_row_ids(fragments: &mut [Fragment], row_ids: &[u64]) -> Result<()> {
+    let mut row_ids = row_ids.iter().copied();
+    for fragment in fragments.iter_mut() {
+        let physical_rows = fragment.physical_rows.ok_or_else(|| Error::Internal {
+            message: format!("Fragment {} has no physical row count", fragment.id),
+            location: location!(),
+        })?;
+        let sequence = row_ids.by_ref().take(physical_rows).collect();
+        fragment.row_id_sequence = Some(sequence);
+    }
+    if row_ids.next().is_some()
+        || fragments.iter().any(|fragment| {
+            fragment.row_id_sequence.as_ref().map(|s| s.len()) != fragment.physical_rows
+        })
+    {
+        return Err(Error::Internal {
+            message: "The number of rewritten rows does not match their row ids".to_string(),
+            location: location!(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use arrow_array::{
+        cast::AsArray, types::Int32Type, types::UInt64Type, FixedSizeListArray, Float32Array,
+        Int32Array, RecordBatch, RecordBatchIterator, StringArray,
+    };
+    use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
+    use arrow_select::concat::concat_batches;
+    use lance_arrow::FixedSizeListArrayExt;
+    use lance_index::IndexType;
+    use lance_linalg::distance::MetricType;
+    use tempfile::tempdir;
+
+    use crate::dataset::optimize::{compact_files, CompactionOptions};
+    use crate::dataset::scanner::Scanner;
+    use crate::dataset::{WriteMode, WriteParams};
+    use crate::index::{
+        inverted::InvertedIndexParams, scalar::ScalarIndexParams, vector::VectorIndexParams,
+        DatasetIndexExt,
+    };
+
+    #[tokio::test]
+    async fn test_stable_row_ids() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, false),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let data = |values: Range<i32>| {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(values.clone())),
+                    Arc::new(StringArray::from_iter_values(
+                        values.map(|i| format!("s-{}", i)),
+                    )),
+                ],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            enable_stable_row_ids: true,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data(0..300), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert!(dataset.has_stable_row_ids());
+        assert_eq!(dataset.manifest.next_row_id, 300);
+
+        // Reference the rows with even values by their stable row id.
+        let mut scanner = dataset.scan();
+        scanner.with_row_id().filter("i % 2 = 0").unwrap();
+        let batches = scanner
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>().values();
+        let stable_row_ids = dataset.stable_row_ids(row_ids).unwrap();
+        let values = batch["i"].as_primitive::<Int32Type>().values().to_vec();
+
+        dataset.delete("i < 50").await.unwrap();
+        dataset
+            .update(Some("i >= 100 AND i < 120"), &[("s", "'updated'")])
+            .await
+            .unwrap();
+        dataset
+            .merge_insert(data(280..320), &["i"], None)
+            .await
+            .unwrap();
+        compact_files(
+            &mut dataset,
+            CompactionOptions {
+                target_rows_per_fragment: 1000,
+                materialize_deletions_threshold: 0.0,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(dataset.get_fragments().len(), 1);
+        // The inserted rows got new stable row ids, the updated ones kept theirs.
+        assert_eq!(dataset.manifest.next_row_id, 320);
+
+        let live = values.iter().position(|value| *value >= 50).unwrap();
+        let taken = dataset
+            .take_by_stable_row_ids(&stable_row_ids[live..], dataset.schema())
+            .await
+            .unwrap();
+        assert_eq!(
+            taken["i"].as_primitive::<Int32Type>().values().to_vec(),
+            values[live..].to_vec()
+        );
+        let strings = taken["s"].as_string::<i32>();
+        assert_eq!(strings.value(0), "s-50");
+        assert_eq!(strings.value(25), "updated");
+
+        // The deleted rows can't be taken anymore.
+        assert!(dataset
+            .take_by_stable_row_ids(&stable_row_ids[..1], dataset.schema())
+            .await
+            .is_err());
+
+        // Appends continue the sequence of stable row ids.
+        let write_params = WriteParams {
+            mode: WriteMode::Append,
+            ..Default::default()
+        };
+        let dataset = Dataset::write(data(320..330), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert!(dataset.has_stable_row_ids());
+        let fragments = dataset.get_fragments();
+        let last = fragments.last().unwrap();
+        assert_eq!(
+            last.metadata.row_id_sequence,
+            Some(RowIdSequence::from(320..330))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_indices_keyed_by_stable_row_ids() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, false),
+            ArrowField::new("s", DataType::Utf8, true),
+            ArrowField::new(
+                "vec",
+                DataType::FixedSizeList(
+                    Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                    8,
+                ),
+                true,
+            ),
+        ]));
+        let vector = |i: i32| Float32Array::from(vec![i as f32; 8]);
+        let vectors = (0..300).flat_map(|i| vec![i as f32; 8]).collect::<Vec<_>>();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..300)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..300).map(|i| format!("text {}", i)),
+                )),
+                Arc::new(
+                    FixedSizeListArray::try_new_from_values(Float32Array::from(vectors), 8)
+                        .unwrap(),
+                ),
+            ],
+        )
+        .unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            enable_stable_row_ids: true,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["i"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["s"],
+                IndexType::Inverted,
+                None,
+                &InvertedIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["vec"],
+                IndexType::Vector,
+                None,
+                &VectorIndexParams::ivf_flat(1, MetricType::L2),
+                false,
+            )
+            .await
+            .unwrap();
+
+        // The values of `i` of the rows found by a filter, a full text search or a
+        // vector search, all answered by the indices.
+        let filtered = |dataset: &Dataset, filter: &str| {
+            let mut scan = dataset.scan();
+            scan.project(&["i"]).unwrap().filter(filter).unwrap();
+            values(scan)
+        };
+        let text_search = |dataset: &Dataset, query: &str| {
+            let mut scan = dataset.scan();
+            scan.project(&["i"])
+                .unwrap()
+                .full_text_search("s", query)
+                .unwrap();
+            values(scan)
+        };
+        let nearest = |dataset: &Dataset, i: i32| {
+            let mut scan = dataset.scan();
+            scan.project(&["i"])
+                .unwrap()
+                .nearest("vec", &vector(i), 1)
+                .unwrap();
+            values(scan)
+        };
+
+        // Compaction moves the rows left, but the indices are not remapped.
+        let indices = dataset.load_indices().await.unwrap();
+        dataset.delete("i < 50").await.unwrap();
+        compact_files(
+            &mut dataset,
+            CompactionOptions {
+                target_rows_per_fragment: 1000,
+                materialize_deletions_threshold: 0.0,
+                ..Default::default()
+            },
+            None,
+        )
+        .await
+        .unwrap();
+        let fragment_ids = dataset
+            .get_fragments()
+            .iter()
+            .map(|fragment| fragment.id() as u32)
+            .collect::<RoaringBitmap>();
+        let compacted = dataset.load_indices().await.unwrap();
+        for (index, compacted) in indices.iter().zip(&compacted) {
+            assert_eq!(index.uuid, compacted.uuid);
+            assert_eq!(compacted.fragment_bitmap.as_ref(), Some(&fragment_ids));
+        }
+        assert_eq!(
+            filtered(&dataset, "i >= 40 AND i < 55").await,
+            (50..55).collect::<Vec<_>>()
+        );
+        assert_eq!(text_search(&dataset, "150").await, vec![150]);
+        assert!(text_search(&dataset, "10").await.is_empty());
+        assert_eq!(nearest(&dataset, 150).await, vec![150]);
+        assert_eq!(nearest(&dataset, 10).await, vec![50]);
+
+        // The rows updated without changing the indexed column stay indexed.
+        dataset
+            .update(Some("i >= 100 AND i < 120"), &[("s", "'updated'")])
+            .await
+            .unwrap();
+        let new_fragment = dataset.get_fragments().last().unwrap().id() as u32;
+        let indices = dataset.load_indices().await.unwrap();
+        let covered = |name: &str| {
+            let index = indices.iter().find(|index| index.name == name).unwrap();
+            let bitmap = index.fragment_bitmap.as_ref().unwrap();
+            bitmap.contains(new_fragment)
+        };
+        assert!(covered("i_idx"));
+        assert!(covered("vec_idx"));
+        assert!(!covered("s_idx"));
+        assert_eq!(filtered(&dataset, "i = 110").await, vec![110]);
+        assert_eq!(nearest(&dataset, 110).await, vec![110]);
+        // The stale entries of the text index are not returned.
+        assert!(text_search(&dataset, "110").await.is_empty());
+        assert_eq!(text_search(&dataset, "updated").await.len(), 20);
+
+        // The rows updated with new indexed values are found by their new values.
+        dataset
+            .update(Some("i >= 200 AND i < 210"), &[("i", "i + 1000")])
+            .await
+            .unwrap();
+        assert!(filtered(&dataset, "i = 205").await.is_empty());
+        assert_eq!(filtered(&dataset, "i = 1205").await, vec![1205]);
+        assert_eq!(
+            filtered(&dataset, "i >= 195 AND i < 205").await,
+            (195..200).collect::<Vec<_>>()
+        );
+        assert_eq!(nearest(&dataset, 205).await, vec![1205]);
+        assert_eq!(text_search(&dataset, "205").await, vec![1205]);
+    }
+
+    /// The sorted values of `i` in the results of `scan`.
+    async fn values(scan: Scanner) -> Vec<i32> {
+        let batches = scan
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let mut values = batches
+            .iter()
+            .flat_map(|batch| batch["i"].as_primitive::<Int32Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        values.sort();
+        values
+    }
+
+    #[tokio::test]
+    async fn test_no_stable_row_ids() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int32,
+            false,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..10))],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        assert!(!dataset.has_stable_row_ids());
+        assert!(dataset.get_fragments()[0]
+            .metadata
+            .row_id_sequence
+            .is_none());
+        assert!(dataset.stable_row_ids(&[0]).is_err());
+        assert!(dataset
+            .take_by_stable_row_ids(&[0], dataset.schema())
+            .await
+            .is_err());
+    }
+}
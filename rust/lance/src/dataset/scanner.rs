@@ -16,7 +16,10 @@ use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
 
-use arrow_array::{Array, Float32Array, Int64Array, RecordBatch};
+use arrow_array::{
+    cast::AsArray, Array, FixedSizeListArray, Float32Array, Int64Array, Int8Array, RecordBatch,
+    UInt64Array,
+};
 use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema, SchemaRef, SortOptions};
 use async_recursion::async_recursion;
 use datafusion::logical_expr::AggregateFunction;
@@ -29,30 +32,45 @@ use datafusion::physical_plan::{
     expressions::{create_aggregate_expr, Literal},
     filter::FilterExec,
     limit::GlobalLimitExec,
+    memory::MemoryExec,
     repartition::RepartitionExec,
     union::UnionExec,
     ExecutionPlan, SendableRecordBatchStream,
 };
 use datafusion::scalar::ScalarValue;
-use futures::stream::{Stream, StreamExt};
+use futures::stream::{Stream, StreamExt, TryStreamExt};
 use lance_arrow::floats::{coerce_float_vector, FloatType};
 use lance_core::ROW_ID_FIELD;
 use lance_datafusion::exec::execute_plan;
 use lance_index::scalar::expression::ScalarIndexExpr;
-use lance_index::vector::{Query, DIST_COL};
+use lance_index::vector::{
+    fusion::{FusionMethod, SCORE_COL},
+    sparse::{is_sparse_vector_type, sparse_vector_array},
+    trace::{SearchStage, SearchTrace},
+    Query, DIST_COL,
+};
+use lance_index::IndexType;
 use lance_linalg::distance::MetricType;
 use log::debug;
+use rand::{rngs::SmallRng, seq::index, SeedableRng};
 use roaring::RoaringBitmap;
 use tracing::{info_span, instrument, Span};
 
+use super::fragment::FileFragment;
+use super::partition::prune_partitions;
+use super::zone_map::prune_fragments;
 use super::Dataset;
 use crate::dataset::index::unindexed_fragments;
 use crate::datatypes::Schema;
-use crate::format::{Fragment, Index};
-use crate::index::DatasetIndexInternalExt;
-use crate::io::exec::{FilterPlan, MaterializeIndexExec, PreFilterSource, ScalarIndexExec};
+use crate::format::{Fragment, Index, RowAddress};
+use crate::index::{DatasetIndexInternalExt, ScalarIndexInfo};
+use crate::io::exec::{
+    FilterPlan, FtsExec, MaterializeIndexExec, PreFilterSource, SampleExec, ScalarIndexExec,
+};
 use crate::io::{
-    exec::{KNNFlatExec, KNNIndexExec, LanceScanExec, Planner, ProjectionExec, TakeExec},
+    exec::{
+        FusionExec, KNNFlatExec, KNNIndexExec, LanceScanExec, Planner, ProjectionExec, TakeExec,
+    },
     RecordBatchStream,
 };
 use crate::utils::sql::parse_sql_filter;
@@ -147,6 +165,9 @@ pub struct Scanner {
     limit: Option<i64>,
     offset: Option<i64>,
 
+    /// The number of rows to sample, and the seed of the random generator.
+    sample: Option<(usize, Option<u64>)>,
+
     /// If Some then results will be ordered by the provided ordering
     ///
     /// If there are multiple columns the the results will first be ordered
@@ -159,9 +180,20 @@ pub struct Scanner {
 
     nearest: Option<Query>,
 
+    /// The sparse vector search whose results are fused with those of `nearest`,
+    /// for a hybrid search.
+    hybrid: Option<(Query, FusionMethod)>,
+
+    /// The column and the query of a full text search.
+    full_text_query: Option<(String, String)>,
+
     /// Scan the dataset with a meta column: "_rowid"
     with_row_id: bool,
 
+    /// Whether the "_rowid" column has the row ids the indices are keyed by, instead
+    /// of the addresses of the rows.
+    index_row_id: bool,
+
     /// Whether to scan in deterministic order (default: true)
     ///
     /// This field is ignored if `ordering` is defined
@@ -191,9 +223,13 @@ impl Scanner {
             fragment_readahead: DEFAULT_FRAGMENT_READAHEAD,
             limit: None,
             offset: None,
+            sample: None,
             ordering: None,
             nearest: None,
+            hybrid: None,
+            full_text_query: None,
             with_row_id: false,
+            index_row_id: false,
             ordered: true,
             fragments: None,
         }
@@ -212,9 +248,13 @@ impl Scanner {
             fragment_readahead: DEFAULT_FRAGMENT_READAHEAD,
             limit: None,
             offset: None,
+            sample: None,
             ordering: None,
             nearest: None,
+            hybrid: None,
+            full_text_query: None,
             with_row_id: false,
+            index_row_id: false,
             ordered: true,
             fragments: Some(vec![fragment]),
         }
@@ -228,19 +268,35 @@ impl Scanner {
         self
     }
 
-    fn ensure_not_fragment_scan(&self) -> Result<()> {
-        if self.is_fragment_scan() {
-            Err(Error::IO {
-                message: "This operation is not supported for fragment scan".to_string(),
-                location: location!(),
+    /// Only scan, or search, the fragments with the given ids.
+    ///
+    /// Vector searches only return the rows of these fragments, whether the index is
+    /// used or not.
+    pub fn with_fragment_ids(&mut self, fragment_ids: &[u64]) -> Result<&mut Self> {
+        let fragments = fragment_ids
+            .iter()
+            .map(|id| {
+                self.dataset
+                    .fragments()
+                    .iter()
+                    .find(|f| f.id == *id)
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::invalid_input(
+                            format!("fragment {} does not exist in the dataset", id),
+                            location!(),
+                        )
+                    })
             })
-        } else {
-            Ok(())
-        }
+            .collect::<Result<Vec<_>>>()?;
+        Ok(self.with_fragments(fragments))
     }
 
-    fn is_fragment_scan(&self) -> bool {
-        self.fragments.is_some()
+    /// The ids of the fragments this scanner is restricted to, if any.
+    fn fragment_bitmap(&self) -> Option<RoaringBitmap> {
+        self.fragments
+            .as_ref()
+            .map(|fragments| RoaringBitmap::from_iter(fragments.iter().map(|f| f.id as u32)))
     }
 
     /// Projection.
@@ -349,10 +405,20 @@ impl Scanner {
         Ok(self)
     }
 
+    /// Only return a uniform random sample of `n` rows, chosen with a random generator
+    /// seeded with `seed`, or from the OS if it is None.
+    ///
+    /// The rows are sampled after the filter, and before the ordering and the limit.
+    /// They are returned in the order of the scan. Without a filter or a search, if the
+    /// row counts of the fragments are known, the rows are chosen upfront and only the
+    /// sampled rows are read. Otherwise, they are sampled while the rows are scanned.
+    pub fn sample(&mut self, n: usize, seed: Option<u64>) -> &mut Self {
+        self.sample = Some((n, seed));
+        self
+    }
+
     /// Find k-nearest neighbor within the vector column.
     pub fn nearest(&mut self, column: &str, q: &Float32Array, k: usize) -> Result<&mut Self> {
-        self.ensure_not_fragment_scan()?;
-
         if k == 0 {
             return Err(Error::IO {
                 message: "k must be positive".to_string(),
@@ -371,6 +437,9 @@ impl Scanner {
             location: location!(),
         })?;
         let key = match field.data_type() {
+            DataType::FixedSizeList(dt, _) if dt.data_type() == &DataType::Int8 => {
+                Box::new(coerce_int8_vector(q)?)
+            }
             DataType::FixedSizeList(dt, _) => {
                 if dt.data_type().is_floating() {
                     coerce_float_vector(q, FloatType::try_from(dt.data_type())?)?
@@ -403,12 +472,210 @@ impl Scanner {
             k,
             nprobes: 1,
             refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: None,
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
+            metric_type: MetricType::L2,
+            use_index: true,
+        });
+        Ok(self)
+    }
+
+    /// Find k-nearest neighbors of a multivector query, i.e., late-interaction
+    /// retrieval like ColBERT.
+    ///
+    /// The column must be a list of float32 vectors. Each query vector is matched to
+    /// its closest vector in the row, and the rows are ranked by the sum of these
+    /// distances. With the dot metric, this is the MaxSim score.
+    ///
+    /// If the column has an IVF_PQ index, the index is searched for each query vector
+    /// and the candidates are always re-ranked with the exact distances.
+    pub fn nearest_multivector(
+        &mut self,
+        column: &str,
+        queries: &FixedSizeListArray,
+        k: usize,
+    ) -> Result<&mut Self> {
+        if k == 0 {
+            return Err(Error::IO {
+                message: "k must be positive".to_string(),
+                location: location!(),
+            });
+        }
+        if queries.is_empty() || queries.value_length() == 0 {
+            return Err(Error::IO {
+                message: "Query vectors must be non-empty".to_string(),
+                location: location!(),
+            });
+        }
+        if queries.value_type() != DataType::Float32 {
+            return Err(Error::IO {
+                message: format!(
+                    "Query vectors must be float32, got {}",
+                    queries.value_type()
+                ),
+                location: location!(),
+            });
+        }
+        let field = self.dataset.schema().field(column).ok_or(Error::IO {
+            message: format!("Column {} not found", column),
+            location: location!(),
+        })?;
+        match field.data_type() {
+            DataType::List(f)
+                if matches!(
+                    f.data_type(),
+                    DataType::FixedSizeList(dt, dim) if dt.data_type() == &DataType::Float32 && *dim == queries.value_length()
+                ) => {}
+            _ => {
+                return Err(Error::IO {
+                    message: format!(
+                        "Column {} is not a multivector column of dimension {} (type: {})",
+                        column,
+                        queries.value_length(),
+                        field.data_type()
+                    ),
+                    location: location!(),
+                })
+            }
+        }
+
+        self.nearest = Some(Query {
+            column: column.to_string(),
+            key: Arc::new(queries.clone()),
+            k,
+            nprobes: 1,
+            refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: None,
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
             metric_type: MetricType::L2,
             use_index: true,
         });
         Ok(self)
     }
 
+    /// Find k-nearest neighbors of a sparse vector, i.e., SPLADE or BM25-weighted
+    /// embeddings, given as its non-zero `indices` and their `values`.
+    ///
+    /// The column must be a sparse vector column, see
+    /// [`lance_index::vector::sparse::sparse_vector_type`].
+    /// Sparse vectors are ranked by dot product.
+    pub fn nearest_sparse(
+        &mut self,
+        column: &str,
+        indices: &[u32],
+        values: &[f32],
+        k: usize,
+    ) -> Result<&mut Self> {
+        self.nearest = Some(self.sparse_query(column, indices, values, k)?);
+        Ok(self)
+    }
+
+    /// Run a hybrid search: fuse the results of [Self::nearest] with the nearest
+    /// neighbors of a sparse vector, e.g., the BM25-weighted terms of a keyword query,
+    /// into one ranking by `fusion`.
+    ///
+    /// Both searches retrieve the `k` of [Self::nearest]. The output has a `_score`
+    /// column instead of `_distance`, the best scored result first.
+    pub fn hybrid_sparse(
+        &mut self,
+        column: &str,
+        indices: &[u32],
+        values: &[f32],
+        fusion: FusionMethod,
+    ) -> Result<&mut Self> {
+        let Some(k) = self.nearest.as_ref().map(|q| q.k) else {
+            return Err(Error::IO {
+                message: "Hybrid search requires a vector search, call nearest first".to_string(),
+                location: location!(),
+            });
+        };
+        self.hybrid = Some((self.sparse_query(column, indices, values, k)?, fusion));
+        Ok(self)
+    }
+
+    fn sparse_query(
+        &self,
+        column: &str,
+        indices: &[u32],
+        values: &[f32],
+        k: usize,
+    ) -> Result<Query> {
+        if k == 0 {
+            return Err(Error::IO {
+                message: "k must be positive".to_string(),
+                location: location!(),
+            });
+        }
+        let field = self.dataset.schema().field(column).ok_or(Error::IO {
+            message: format!("Column {} not found", column),
+            location: location!(),
+        })?;
+        if !is_sparse_vector_type(&field.data_type()) {
+            return Err(Error::IO {
+                message: format!(
+                    "Column {} is not a sparse vector column (type: {})",
+                    column,
+                    field.data_type()
+                ),
+                location: location!(),
+            });
+        }
+        let key = sparse_vector_array(vec![(indices.to_vec(), values.to_vec())])?;
+
+        Ok(Query {
+            column: column.to_string(),
+            key: Arc::new(key),
+            k,
+            nprobes: 1,
+            refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: None,
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
+            metric_type: MetricType::Dot,
+            use_index: true,
+        })
+    }
+
+    /// Search the rows of the string `column` which contain any word of `query`, ranked
+    /// by BM25.
+    ///
+    /// The column must have an inverted index, see
+    /// [`crate::index::inverted::InvertedIndexParams`]. The output has a `_score`
+    /// column, the best scored row first. The filter is applied before the search
+    /// if [Self::prefilter] is set, otherwise to the results of the search.
+    pub fn full_text_search(&mut self, column: &str, query: &str) -> Result<&mut Self> {
+        let field = self.dataset.schema().field(column).ok_or(Error::IO {
+            message: format!("Column {} not found", column),
+            location: location!(),
+        })?;
+        if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+            return Err(Error::IO {
+                message: format!(
+                    "Column {} is not a string column (type: {})",
+                    column,
+                    field.data_type()
+                ),
+                location: location!(),
+            });
+        }
+        self.full_text_query = Some((column.to_string(), query.to_string()));
+        Ok(self)
+    }
+
     pub fn nprobs(&mut self, n: usize) -> &mut Self {
         if let Some(q) = self.nearest.as_mut() {
             q.nprobes = n;
@@ -416,6 +683,36 @@ impl Scanner {
         self
     }
 
+    /// Pick the number of partitions to probe for each query to reach the `recall`, in `(0, 1]`.
+    ///
+    /// The recall is estimated from the statistics of the partitions gathered when the IVF
+    /// index was built, so `nprobes` no longer needs to be tuned for each dataset. Indices
+    /// built without these statistics still probe `nprobes` partitions.
+    pub fn target_recall(&mut self, recall: f32) -> Result<&mut Self> {
+        if !(recall > 0.0 && recall <= 1.0) {
+            return Err(Error::IO {
+                message: format!("Target recall must be in (0, 1], got {}", recall),
+                location: location!(),
+            });
+        }
+        if let Some(q) = self.nearest.as_mut() {
+            q.target_recall = Some(recall);
+        }
+        Ok(self)
+    }
+
+    /// Bound the latency of the vector search by the number of vectors to scan.
+    ///
+    /// IVF indices probe the nearest partitions of each query as long as their vectors
+    /// are within `max_rows`. Together with [`Self::target_recall`], the recall is only
+    /// targeted within this budget.
+    pub fn probe_budget(&mut self, max_rows: usize) -> &mut Self {
+        if let Some(q) = self.nearest.as_mut() {
+            q.max_probe_rows = Some(max_rows);
+        }
+        self
+    }
+
     /// Apply a refine step to the vector search.
     ///
     /// A refine improves query accuracy but also makes search slower, by reading extra elements
@@ -432,7 +729,44 @@ impl Scanner {
         self
     }
 
+    /// Set the size of the dynamic candidate list for graph-based indices, i.e., `ef` in
+    /// HNSW or the search list size `L` in DiskANN.
+    ///
+    /// A larger `ef` improves recall at the cost of latency. It is ignored by IVF indices.
+    pub fn ef_search(&mut self, ef: usize) -> &mut Self {
+        if let Some(q) = self.nearest.as_mut() {
+            q.ef_search = Some(ef);
+        }
+        self
+    }
+
+    /// Set the beam width for searching the on-disk graph index, i.e., DiskANN.
+    ///
+    /// A wider beam fetches more vertices from the storage concurrently in each round.
+    pub fn beam_width(&mut self, beam_width: usize) -> &mut Self {
+        if let Some(q) = self.nearest.as_mut() {
+            q.beam_width = Some(beam_width);
+        }
+        self
+    }
+
+    /// Only return the vectors with a distance within `[lower, upper)` to the query.
+    ///
+    /// At most `k` rows are still returned, so a large `k` returns all the rows in the
+    /// range. With an upper bound, IVF indices skip the partitions that can not have any
+    /// vector within the range. `nprobes` is still the maximum number of partitions to
+    /// search.
+    pub fn distance_range(&mut self, lower: Option<f32>, upper: Option<f32>) -> &mut Self {
+        if let Some(q) = self.nearest.as_mut() {
+            q.lower_bound = lower;
+            q.upper_bound = upper;
+        }
+        self
+    }
+
     /// Change the distance [MetricType], i.e, L2 or Cosine distance.
+    ///
+    /// If the vector column has an index, the metric type of the index is used instead.
     pub fn distance_metric(&mut self, metric_type: MetricType) -> &mut Self {
         if let Some(q) = self.nearest.as_mut() {
             q.metric_type = metric_type
@@ -471,6 +805,9 @@ impl Scanner {
         if let Some(q) = self.nearest.as_mut() {
             q.use_index = use_index
         }
+        if let Some((q, _)) = self.hybrid.as_mut() {
+            q.use_index = use_index
+        }
         self
     }
 
@@ -480,6 +817,15 @@ impl Scanner {
         self
     }
 
+    /// Instruct the scanner to return the `_rowid` meta column with the row ids the
+    /// indices are keyed by, i.e., the stable row ids of the rows if the dataset has
+    /// them, to build an index.
+    pub(crate) fn with_index_row_id(&mut self) -> &mut Self {
+        self.with_row_id = true;
+        self.index_row_id = true;
+        self
+    }
+
     /// The Arrow schema of the output, including projections and vector / _distance
     pub fn schema(&self) -> Result<SchemaRef> {
         let schema = self
@@ -502,7 +848,13 @@ impl Scanner {
                 location: location!(),
             })?;
             extra_columns.push(vector_field);
-            extra_columns.push(ArrowField::new(DIST_COL, DataType::Float32, true));
+            if self.hybrid.is_some() {
+                extra_columns.push(ArrowField::new(SCORE_COL, DataType::Float32, false));
+            } else {
+                extra_columns.push(ArrowField::new(DIST_COL, DataType::Float32, true));
+            }
+        } else if self.full_text_query.is_some() {
+            extra_columns.push(ArrowField::new(SCORE_COL, DataType::Float32, false));
         };
         if self.with_row_id {
             extra_columns.push(ROW_ID_FIELD.clone());
@@ -519,13 +871,19 @@ impl Scanner {
     /// Create a stream from the Scanner.
     #[instrument(skip_all)]
     pub async fn try_into_stream(&self) -> Result<DatasetRecordBatchStream> {
-        let plan = self.create_plan().await?;
-        Ok(DatasetRecordBatchStream::new(execute_plan(plan)?))
+        Ok(DatasetRecordBatchStream::new(
+            self.try_into_dfstream().await?,
+        ))
     }
 
     pub(crate) async fn try_into_dfstream(&self) -> Result<SendableRecordBatchStream> {
         let plan = self.create_plan().await?;
-        execute_plan(plan)
+        let stream = execute_plan(plan)?;
+        if self.index_row_id {
+            Ok(self.dataset.with_index_row_ids(stream))
+        } else {
+            Ok(stream)
+        }
     }
 
     /// Scan and return the number of matching rows
@@ -615,6 +973,14 @@ impl Scanner {
     ///     -> Take(remaining_cols) -> Projection()
     ///  ```
     ///
+    ///  - **Full text search (with filter and/or limits)**
+    ///
+    /// ```ignore
+    /// FullTextSearch() -> Take(filtered_cols) -> Filter(expr)
+    ///     -> (*LimitExec(limit, offset))
+    ///     -> Take(remaining_cols) -> Projection()
+    /// ```
+    ///
     ///  - **Use KNN Index (with filter and/or limits)**
     ///
     /// ```ignore
@@ -650,12 +1016,16 @@ impl Scanner {
         // produce multiple need to be repartitioned to 1.
         let mut filter_plan = if let Some(filter) = self.filter.as_ref() {
             let planner = Planner::new(Arc::new(self.dataset.schema().into()));
-            let index_info = self.dataset.scalar_index_info().await?;
+            let mut index_info = self.dataset.scalar_index_info().await?;
+            // The statistics of the indices tell whether searching them is worth it
+            if use_scalar_index {
+                let columns = Planner::column_names_in_expr(&planner.parse_filter(filter)?);
+                index_info.load_statistics(&self.dataset, &columns).await?;
+            }
             let filter_plan = planner.create_filter_plan(filter, &index_info, use_scalar_index)?;
 
-            // TODO: Remove this check once we handle indexed scans with new data
-            // This check is testing to see if we have an indexed query and new data
-            // and, if we do, falling back to a filter plan that does not use scalar indices
+            // The new data, which is not indexed yet, is scanned with the filter, see
+            // `scalar_indexed_scan`.
             if let Some(index_query) = filter_plan.index_query.as_ref() {
                 let covered_frags = self.fragments_covered_by_index_query(index_query).await?;
                 let fragments = if let Some(fragments) = self.fragments.as_ref() {
@@ -663,19 +1033,10 @@ impl Scanner {
                 } else {
                     self.dataset.fragments()
                 };
-                let mut has_new_data = false;
-                let mut has_missing_row_count = false;
-                for frag in fragments {
-                    if !covered_frags.contains(frag.id as u32) {
-                        has_new_data = true;
-                        break;
-                    }
-                    if frag.physical_rows.is_none() {
-                        has_missing_row_count = true;
-                        break;
-                    }
-                }
-                if has_new_data || has_missing_row_count {
+                let has_missing_row_count = fragments.iter().any(|frag| {
+                    covered_frags.contains(frag.id as u32) && frag.physical_rows.is_none()
+                });
+                if has_missing_row_count {
                     // We need row counts to use scalar indices.  If we don't have them then
                     // fallback to a non-indexed filter
                     planner.create_filter_plan(filter, &index_info, false)?
@@ -689,16 +1050,48 @@ impl Scanner {
             FilterPlan::default()
         };
 
-        // Stage 1: source (either an (K|A)NN search or a (full|indexed) scan)
+        if self.nearest.is_some() && self.full_text_query.is_some() {
+            return Err(Error::IO {
+                message: "Can not run a vector search and a full text search together".to_string(),
+                location: location!(),
+            });
+        }
+
+        // Stage 1: source (either an (K|A)NN search, a full text search, a sample or a
+        // (full|indexed) scan)
+        let mut sampled_at_source = false;
         let mut plan: Arc<dyn ExecutionPlan> = if self.nearest.is_some() {
             // The source is an nearest neighbor search
             if self.prefilter {
                 // If we are prefiltering then the knn node will take care of the filter
-                let source = self.knn(&filter_plan).await?;
+                let source = self.vector_search(&filter_plan).await?;
                 filter_plan = FilterPlan::default();
                 source
             } else {
-                self.knn(&FilterPlan::default()).await?
+                self.vector_search(&FilterPlan::default()).await?
+            }
+        } else if let Some((column, query)) = self.full_text_query.as_ref() {
+            if self.prefilter {
+                // The search will take care of the filter, so the best scored rows
+                // are the results.
+                let source = self
+                    .fts(column, query, &filter_plan, self.fts_fetch())
+                    .await?;
+                filter_plan = FilterPlan::default();
+                source
+            } else {
+                // The results are filtered afterwards, which can only be done
+                // without the scalar indices.
+                if filter_plan.index_query.is_some() {
+                    filter_plan = self.unindexed_filter_plan()?;
+                }
+                let fetch = if filter_plan.has_refine() {
+                    None
+                } else {
+                    self.fts_fetch()
+                };
+                self.fts(column, query, &FilterPlan::default(), fetch)
+                    .await?
             }
         } else {
             // The source is a scan
@@ -712,9 +1105,32 @@ impl Scanner {
                 // If there is no filter then load the user's desired columns
                 (self.with_row_id, self.projections.clone().into())
             };
-            if let Some(index_query) = &filter_plan.index_query {
+            if let Some(row_ids) = self.sample_row_ids(&filter_plan).await? {
+                // The source is the row ids of the sample, whose columns are taken
+                // afterwards
+                sampled_at_source = true;
+                let batch = RecordBatch::try_new(
+                    Arc::new(ArrowSchema::new(vec![ROW_ID_FIELD.clone()])),
+                    vec![Arc::new(row_ids)],
+                )?;
+                let schema = batch.schema();
+                Arc::new(MemoryExec::try_new(&[vec![batch]], schema, None)?)
+            } else if let Some(index_query) = &filter_plan.index_query {
                 // The source is an indexed scan
                 self.scalar_indexed_scan(&schema, index_query).await?
+            } else if let Some(refine_expr) = &filter_plan.refine_expr {
+                // The source is a scan of the fragments whose partition values and
+                // zone maps may match the filter
+                let fragments =
+                    prune_partitions(&self.dataset, &self.scanned_fragments(), refine_expr)?;
+                let fragments = prune_fragments(&self.dataset, &fragments, refine_expr).await?;
+                self.scan_fragments(
+                    with_row_id,
+                    false,
+                    schema,
+                    Arc::new(fragments),
+                    self.scan_ordered(),
+                )
             } else {
                 // The source is a full scan of the table
                 self.scan(with_row_id, false, schema)
@@ -754,6 +1170,13 @@ impl Scanner {
             plan = Arc::new(FilterExec::try_new(physical_refine_expr, plan)?);
         }
 
+        // Stage 2.5: sample the rows, if they were not sampled by the source
+        if let Some((n, seed)) = self.sample {
+            if !sampled_at_source {
+                plan = Arc::new(SampleExec::new(plan, n, seed));
+            }
+        }
+
         // Stage 3: sort
         if let Some(ordering) = &self.ordering {
             let order_by_schema = Arc::new(
@@ -769,11 +1192,14 @@ impl Scanner {
                 // We haven't loaded the sort column yet so take it now
                 plan = self.take(plan, &remaining_schema, self.batch_readahead)?;
             }
+            // A nested field, e.g., `metadata.source`, is sorted by the field of its struct.
+            let planner = Planner::new(plan.schema());
             let col_exprs = ordering
                 .iter()
                 .map(|col| {
                     Ok(PhysicalSortExpr {
-                        expr: expressions::col(&col.column_name, plan.schema().as_ref())?,
+                        expr: planner
+                            .create_physical_expr(&Planner::column_path_expr(&col.column_name))?,
                         options: SortOptions {
                             descending: !col.ascending,
                             nulls_first: col.nulls_first,
@@ -802,20 +1228,47 @@ impl Scanner {
         Ok(plan)
     }
 
-    // ANN/KNN search execution node with optional prefilter
-    async fn knn(&self, filter_plan: &FilterPlan) -> Result<Arc<dyn ExecutionPlan>> {
+    // The nearest neighbors, or the fused results of a hybrid search
+    async fn vector_search(&self, filter_plan: &FilterPlan) -> Result<Arc<dyn ExecutionPlan>> {
         let Some(q) = self.nearest.as_ref() else {
             return Err(Error::IO {
                 message: "No nearest query".to_string(),
                 location: location!(),
             });
         };
+        let knn_node = self.knn(q, filter_plan).await?;
+        let Some((sparse_q, fusion)) = self.hybrid.as_ref() else {
+            return Ok(knn_node);
+        };
+        if is_sparse_vector_type(&self.dataset.schema().field(&q.column).unwrap().data_type()) {
+            return Err(Error::IO {
+                message: "Hybrid search requires a dense vector search and a sparse one"
+                    .to_string(),
+                location: location!(),
+            });
+        }
+        let sparse_node = self.knn(sparse_q, filter_plan).await?;
+        Ok(Arc::new(FusionExec::new(
+            knn_node,
+            sparse_node,
+            *fusion,
+            q.k,
+        )))
+    }
 
+    // ANN/KNN search execution node with optional prefilter
+    async fn knn(&self, q: &Query, filter_plan: &FilterPlan) -> Result<Arc<dyn ExecutionPlan>> {
         // Santity check
         let schema = self.dataset.schema();
         if let Some(field) = schema.field(&q.column) {
             match field.data_type() {
-                DataType::FixedSizeList(subfield, _) if subfield.data_type().is_floating() => {}
+                DataType::FixedSizeList(subfield, _)
+                    if subfield.data_type().is_floating()
+                        || subfield.data_type() == &DataType::Int8 => {}
+                // Multivector column.
+                DataType::List(subfield)
+                    if matches!(subfield.data_type(), DataType::FixedSizeList(..)) => {}
+                ref dt if is_sparse_vector_type(dt) => {}
                 _ => {
                     return Err(Error::IO {
                         message: format!(
@@ -834,7 +1287,7 @@ impl Scanner {
         }
 
         let column_id = self.dataset.schema().field_id(q.column.as_str())?;
-        let use_index = self.nearest.as_ref().map(|q| q.use_index).unwrap_or(false);
+        let use_index = q.use_index;
         let indices = if use_index {
             self.dataset.load_indices().await?
         } else {
@@ -842,6 +1295,12 @@ impl Scanner {
         };
         let knn_idx = indices.iter().find(|i| i.fields.contains(&column_id));
         if let Some(index) = knn_idx {
+            // All the deltas of the index are searched.
+            let deltas = indices
+                .iter()
+                .filter(|i| i.name == index.name)
+                .cloned()
+                .collect::<Vec<_>>();
             // There is an index built for the column.
             // We will use the index.
             if let Some(rf) = q.refine_factor {
@@ -853,17 +1312,30 @@ impl Scanner {
                 }
             }
 
-            let ann_node = self.ann(q, index, filter_plan).await?; // _distance, _rowid
+            // The distances of the index are defined by the metric it was built with,
+            // so the refine and the flat search over new data must use the same metric.
+            let vector_index = self
+                .dataset
+                .open_vector_index(&q.column, &index.uuid.to_string())
+                .await?;
+            let mut q = q.clone();
+            q.metric_type = vector_index.metric_type();
+            let q = &q;
+
+            let ann_node = self.ann(q, &deltas, filter_plan).await?; // _distance, _rowid
 
             let with_vector = self.dataset.schema().project(&[&q.column])?;
             let knn_node_with_vector = self.take(ann_node, &with_vector, self.batch_readahead)?;
-            let mut knn_node = if q.refine_factor.is_some() {
-                self.flat_knn(knn_node_with_vector, q)?
+            // The index distances of a multivector query are the distances of the
+            // single vectors, so the candidates are always re-ranked.
+            let is_multivector = matches!(q.key.data_type(), DataType::FixedSizeList(..));
+            let mut knn_node = if q.refine_factor.is_some() || is_multivector {
+                self.flat_knn(knn_node_with_vector, q, SearchStage::Refine)?
             } else {
                 knn_node_with_vector
             }; // vector, _distance, _rowid
 
-            knn_node = self.knn_combined(&q, index, knn_node).await?;
+            knn_node = self.knn_combined(&q, &deltas, knn_node).await?;
 
             Ok(knn_node)
         } else {
@@ -877,7 +1349,13 @@ impl Scanner {
                 self.scalar_indexed_scan(&vector_scan_projection, index_query)
                     .await?
             } else {
-                self.scan(true, true, vector_scan_projection)
+                let fragments = match self.fragments.as_ref() {
+                    Some(fragments) => Arc::new(fragments.clone()),
+                    None => self.dataset.fragments().clone(),
+                };
+                // The results are ranked by distance, so the fragments are read
+                // concurrently, in whichever order their batches arrive.
+                self.scan_fragments(true, true, vector_scan_projection, fragments, false)
             };
             if let Some(refine_expr) = &filter_plan.refine_expr {
                 let planner = Planner::new(plan.schema());
@@ -885,19 +1363,111 @@ impl Scanner {
 
                 plan = Arc::new(FilterExec::try_new(physical_refine_expr, plan)?);
             }
-            Ok(self.flat_knn(plan, q)?)
+            Ok(self.flat_knn(plan, q, SearchStage::Flat)?)
+        }
+    }
+
+    // The number of results of a full text search, which is only known if they are
+    // not sorted nor filtered afterwards.
+    fn fts_fetch(&self) -> Option<usize> {
+        if self.ordering.is_some() {
+            return None;
+        }
+        self.limit
+            .filter(|limit| *limit > 0)
+            .map(|limit| (limit + self.offset.unwrap_or(0)) as usize)
+    }
+
+    // The filter plan of the filter without the scalar indices
+    fn unindexed_filter_plan(&self) -> Result<FilterPlan> {
+        let Some(filter) = self.filter.as_ref() else {
+            return Ok(FilterPlan::default());
+        };
+        let planner = Planner::new(Arc::new(self.dataset.schema().into()));
+        let index_info = ScalarIndexInfo::default();
+        planner.create_filter_plan(filter, &index_info, false)
+    }
+
+    // Full text search execution node with optional prefilter
+    async fn fts(
+        &self,
+        column: &str,
+        query: &str,
+        filter_plan: &FilterPlan,
+        fetch: Option<usize>,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        let Some(index) = self
+            .dataset
+            .load_index_for_column(column, IndexType::Inverted)
+            .await?
+        else {
+            return Err(Error::IO {
+                message: format!(
+                    "Full text search error: column {} does not have an inverted index",
+                    column
+                ),
+                location: location!(),
+            });
+        };
+        let prefilter_source = self.prefilter_source(filter_plan).await?;
+        let mut fts_node = FtsExec::new(
+            self.dataset.clone(),
+            index.clone(),
+            column.to_string(),
+            query.to_string(),
+            prefilter_source,
+        )
+        .with_fetch(fetch);
+        if let Some(fragments) = self.fragment_bitmap() {
+            fts_node = fts_node.with_fragments(fragments);
+        }
+
+        // The rows appended after the index was built are read and scored too.
+        let mut unindexed_fragments = unindexed_fragments(&[index], self.dataset.as_ref()).await?;
+        if let Some(fragments) = self.fragment_bitmap() {
+            unindexed_fragments.retain(|f| fragments.contains(f.id as u32));
+        }
+        if !unindexed_fragments.is_empty() {
+            // The scalar indices do not cover the new rows either.
+            let filter_plan = if filter_plan.has_refine() || filter_plan.index_query.is_some() {
+                self.unindexed_filter_plan()?
+            } else {
+                FilterPlan::default()
+            };
+            let mut columns = vec![column.to_string()];
+            if let Some(refine_expr) = filter_plan.refine_expr.as_ref() {
+                columns.extend(Planner::column_names_in_expr(refine_expr));
+            }
+            let projection = Arc::new(self.dataset.schema().project(&columns)?);
+            let mut plan = self.scan_fragments(
+                true,
+                false,
+                projection,
+                Arc::new(unindexed_fragments),
+                false,
+            );
+            if let Some(refine_expr) = &filter_plan.refine_expr {
+                let planner = Planner::new(plan.schema());
+                let physical_refine_expr = planner.create_physical_expr(refine_expr)?;
+                plan = Arc::new(FilterExec::try_new(physical_refine_expr, plan)?);
+            }
+            fts_node = fts_node.with_unindexed(plan);
         }
+        Ok(Arc::new(fts_node))
     }
 
     /// Combine ANN results with KNN results for data appended after index creation
     async fn knn_combined(
         &self,
         q: &&Query,
-        index: &Index,
+        deltas: &[Index],
         knn_node: Arc<dyn ExecutionPlan>,
     ) -> Result<Arc<dyn ExecutionPlan>> {
         // Check if we've created new versions since the index
-        let unindexed_fragments = unindexed_fragments(index, self.dataset.as_ref()).await?;
+        let mut unindexed_fragments = unindexed_fragments(deltas, self.dataset.as_ref()).await?;
+        if let Some(fragments) = self.fragment_bitmap() {
+            unindexed_fragments.retain(|f| fragments.contains(f.id as u32));
+        }
         if !unindexed_fragments.is_empty() {
             let vector_scan_projection =
                 Arc::new(self.dataset.schema().project(&[&q.column]).unwrap());
@@ -911,7 +1481,7 @@ impl Scanner {
                 false,
             );
             // first we do flat search on just the new data
-            let topk_appended = self.flat_knn(scan_node, q)?;
+            let topk_appended = self.flat_knn(scan_node, q, SearchStage::Flat)?;
 
             // To do a union, we need to make the schemas match. Right now
             // knn_node: _distance, _rowid, vector
@@ -932,7 +1502,7 @@ impl Scanner {
                 datafusion::physical_plan::Partitioning::RoundRobinBatch(1),
             )?;
             // then we do a flat search on KNN(new data) + ANN(indexed data)
-            return self.flat_knn(Arc::new(unioned), q);
+            return self.flat_knn(Arc::new(unioned), q, SearchStage::Merge);
         }
 
         Ok(knn_node)
@@ -952,20 +1522,39 @@ impl Scanner {
                 & self.fragments_covered_by_index_query(rhs).await?),
             ScalarIndexExpr::Not(expr) => self.fragments_covered_by_index_query(expr).await,
             ScalarIndexExpr::Query(column, _) => {
-                let idx = self
+                // The deltas of the index cover different fragments.
+                let deltas = self
                     .dataset
-                    .load_scalar_index_for_column(column)
-                    .await?
-                    .expect("Index not found even though it must have been found earlier");
-                Ok(idx
-                    .fragment_bitmap
-                    .expect("scalar indices should always have a fragment bitmap"))
+                    .load_scalar_index_deltas_for_column(column)
+                    .await?;
+                assert!(
+                    !deltas.is_empty(),
+                    "Index not found even though it must have been found earlier"
+                );
+                Ok(deltas
+                    .iter()
+                    .map(|idx| {
+                        idx.fragment_bitmap
+                            .as_ref()
+                            .expect("scalar indices should always have a fragment bitmap")
+                    })
+                    .fold(RoaringBitmap::new(), |acc, bitmap| acc | bitmap))
             }
         }
     }
 
+    // Whether some of the scanned fragments are not covered by the indices of the query
+    async fn has_unindexed_data(&self, index_expr: &ScalarIndexExpr) -> Result<bool> {
+        let covered_frags = self.fragments_covered_by_index_query(index_expr).await?;
+        Ok(self
+            .scanned_fragments()
+            .iter()
+            .any(|frag| !covered_frags.contains(frag.id as u32)))
+    }
+
     // First perform a lookup in a scalar index for ids and then perform a take on the
-    // target fragments with those ids
+    // target fragments with those ids.  The fragments not covered by the indices yet
+    // are scanned with the filter instead.
     async fn scalar_indexed_scan(
         &self,
         projection: &Schema,
@@ -992,29 +1581,61 @@ impl Scanner {
             }
         }
 
-        if !missing_frags.is_empty() {
-            // TODO: If there is new data then we need this:
-            //
-            // MaterializeIndexExec(old_frags) -> Take -> Union
-            // Scan(new_frags) -> Filter -> Project    -|
-            //
-            // The project is to drop any columns we had to include
-            // in the full scan merely for the sake of fulfilling the
-            // filter (there may not be any and project can be skipped).
-            //
-            // This is TODO because we need to go from:
-            //   ScalarIndexExpr -> Expr -> PhysicalExpr
-            // which is doable but complex enough to defer to a future PR.
-            panic!("Indexed scans including new data not yet supported (and we should not be able to reach this point)");
-        }
-
         let plan = Arc::new(MaterializeIndexExec::new(
             self.dataset.clone(),
             index_expr.clone(),
             Arc::new(relevant_frags),
         ));
+        let indexed = self.take(plan, projection, self.batch_readahead)?;
+        if missing_frags.is_empty() {
+            return Ok(indexed);
+        }
+
+        // If there is new data then we need this:
+        //
+        // MaterializeIndexExec(old_frags) -> Take -> Union
+        // Scan(new_frags) -> Filter -> Project    -|
+        //
+        // The whole filter is applied to the new data, which is a superset of the
+        // index query.  The project is to drop any columns we had to include in the
+        // scan merely for the sake of fulfilling the filter.
+        let filter_plan = self.unindexed_filter_plan()?;
+        let Some(filter_expr) = filter_plan.refine_expr.as_ref() else {
+            return Err(Error::Internal {
+                message: "Scalar indexed scan without a filter".to_string(),
+                location: location!(),
+            });
+        };
+        let mut columns = filter_plan.refine_columns();
+        for field in projection.fields.iter() {
+            if !columns.contains(&field.name) {
+                columns.push(field.name.clone());
+            }
+        }
+        let scan_schema = Arc::new(self.dataset.schema().project(&columns)?);
+        let new_data = self.scan_fragments(
+            true,
+            false,
+            scan_schema,
+            Arc::new(missing_frags),
+            self.scan_ordered(),
+        );
+        let planner = Planner::new(new_data.schema());
+        let new_data = Arc::new(FilterExec::try_new(
+            planner.create_physical_expr(filter_expr)?,
+            new_data,
+        )?);
+        let new_data = Arc::new(ProjectionExec::try_new(
+            new_data,
+            Arc::new(Schema::try_from(indexed.schema().as_ref())?),
+        )?);
 
-        self.take(plan, projection, self.batch_readahead)
+        let unioned = UnionExec::new(vec![indexed, new_data]);
+        // Enforce only 1 partition.
+        Ok(Arc::new(RepartitionExec::try_new(
+            Arc::new(unioned),
+            datafusion::physical_plan::Partitioning::RoundRobinBatch(1),
+        )?))
     }
 
     /// Create an Execution plan with a scan node
@@ -1027,24 +1648,31 @@ impl Scanner {
         with_make_deletions_null: bool,
         projection: Arc<Schema>,
     ) -> Arc<dyn ExecutionPlan> {
-        let fragments = if let Some(fragment) = self.fragments.as_ref() {
+        self.scan_fragments(
+            with_row_id,
+            with_make_deletions_null,
+            projection,
+            self.scanned_fragments(),
+            self.scan_ordered(),
+        )
+    }
+
+    /// The fragments to scan, all the fragments of the dataset by default.
+    fn scanned_fragments(&self) -> Arc<Vec<Fragment>> {
+        if let Some(fragment) = self.fragments.as_ref() {
             Arc::new(fragment.clone())
         } else {
             self.dataset.fragments().clone()
-        };
-        let ordered = if self.ordering.is_some() {
+        }
+    }
+
+    fn scan_ordered(&self) -> bool {
+        if self.ordering.is_some() {
             // If we are sorting the results there is no need to scan in order
             false
         } else {
             self.ordered
-        };
-        self.scan_fragments(
-            with_row_id,
-            with_make_deletions_null,
-            projection,
-            fragments,
-            ordered,
-        )
+        }
     }
 
     fn scan_fragments(
@@ -1069,17 +1697,72 @@ impl Scanner {
     }
 
     /// Add a knn search node to the input plan
-    fn flat_knn(&self, input: Arc<dyn ExecutionPlan>, q: &Query) -> Result<Arc<dyn ExecutionPlan>> {
-        Ok(Arc::new(KNNFlatExec::try_new(input, q.clone())?))
+    fn flat_knn(
+        &self,
+        input: Arc<dyn ExecutionPlan>,
+        q: &Query,
+        stage: SearchStage,
+    ) -> Result<Arc<dyn ExecutionPlan>> {
+        Ok(Arc::new(
+            KNNFlatExec::try_new(input, q.clone())?.with_stage(stage),
+        ))
     }
 
     /// Create an Execution plan to do indexed ANN search
+    ///
+    /// Each delta of the index is searched, and the results are merged by distance.
     async fn ann(
         &self,
         q: &Query,
-        index: &Index,
+        deltas: &[Index],
         filter_plan: &FilterPlan,
     ) -> Result<Arc<dyn ExecutionPlan>> {
+        let prefilter_source = self.prefilter_source(filter_plan).await?;
+
+        let fragments = self.fragment_bitmap();
+        let mut knn_nodes = deltas
+            .iter()
+            .map(|index| -> Result<Arc<dyn ExecutionPlan>> {
+                let mut knn_node = KNNIndexExec::try_new(
+                    self.dataset.clone(),
+                    index.clone(),
+                    q,
+                    prefilter_source.clone(),
+                )?;
+                if let Some(fragments) = &fragments {
+                    knn_node = knn_node.with_fragments(fragments.clone());
+                }
+                Ok(Arc::new(knn_node))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if knn_nodes.len() == 1 {
+            return Ok(knn_nodes.pop().unwrap());
+        }
+
+        let unioned = UnionExec::new(knn_nodes);
+        // Enforce only 1 partition.
+        let unioned = RepartitionExec::try_new(
+            Arc::new(unioned),
+            datafusion::physical_plan::Partitioning::RoundRobinBatch(1),
+        )?;
+        let sort_expr = PhysicalSortExpr {
+            expr: expressions::col(DIST_COL, unioned.schema().as_ref())?,
+            options: SortOptions {
+                descending: false,
+                nulls_first: false,
+            },
+        };
+        // Each vector of a multivector query has its own candidates.
+        let num_keys = q.key.as_fixed_size_list_opt().map_or(1, |keys| keys.len());
+        Ok(Arc::new(
+            SortExec::new(vec![sort_expr], Arc::new(unioned))
+                .with_fetch(Some(q.k * q.refine_factor.unwrap_or(1) as usize * num_keys)),
+        ))
+    }
+
+    /// The prefilter of an index search, i.e., the rows which satisfy the filter,
+    /// if the filter is applied before the search.
+    async fn prefilter_source(&self, filter_plan: &FilterPlan) -> Result<PreFilterSource> {
         let prefilter_source = match (
             &filter_plan.index_query,
             &filter_plan.refine_expr,
@@ -1100,6 +1783,17 @@ impl Scanner {
                     Arc::new(FilterExec::try_new(physical_refine_expr, filter_input)?);
                 PreFilterSource::FilteredRowIds(filtered_row_ids)
             } // Should be index_scan -> filter
+            (Some(index_query), None, true) if self.has_unindexed_data(index_query).await? => {
+                // The filter is completely satisfied by the index, but not all
+                // the fragments are indexed yet.  The indexed scan filters the
+                // new data to determine the row ids.
+                let columns_in_filter = self.unindexed_filter_plan()?.refine_columns();
+                let filter_schema = Arc::new(self.dataset.schema().project(&columns_in_filter)?);
+                let filtered_row_ids = self
+                    .scalar_indexed_scan(&filter_schema, index_query)
+                    .await?;
+                PreFilterSource::FilteredRowIds(filtered_row_ids)
+            }
             (Some(index_query), None, true) => {
                 // The filter is completely satisfied by the index.  We
                 // only need to search the index to determine the valid row
@@ -1126,13 +1820,7 @@ impl Scanner {
             (None, None, true) => PreFilterSource::None,
             (_, _, false) => PreFilterSource::None,
         };
-
-        Ok(Arc::new(KNNIndexExec::try_new(
-            self.dataset.clone(),
-            index.clone(),
-            q,
-            prefilter_source,
-        )?))
+        Ok(prefilter_source)
     }
 
     /// Take row indices produced by input plan from the dataset (with projection)
@@ -1150,6 +1838,69 @@ impl Scanner {
         )?))
     }
 
+    /// The row ids of the sample, if it can be chosen from the row counts of the
+    /// fragments, i.e., without a filter, and if the counts are known.
+    async fn sample_row_ids(&self, filter_plan: &FilterPlan) -> Result<Option<UInt64Array>> {
+        let Some((n, seed)) = self.sample else {
+            return Ok(None);
+        };
+        if filter_plan.index_query.is_some() || filter_plan.has_refine() {
+            return Ok(None);
+        }
+        let fragments = self.scanned_fragments();
+        let Some(num_rows) = fragments
+            .iter()
+            .map(Fragment::num_rows)
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(None);
+        };
+
+        let mut rng = match seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        };
+        let total_rows = num_rows.iter().sum();
+        let mut offsets = index::sample(&mut rng, total_rows, n.min(total_rows)).into_vec();
+        offsets.sort_unstable();
+
+        let mut offsets = offsets.into_iter().peekable();
+        let mut row_ids: Vec<u64> = Vec::with_capacity(n.min(total_rows));
+        let mut fragment_start = 0;
+        for (fragment, num_rows) in fragments.iter().zip(num_rows) {
+            let fragment_end = fragment_start + num_rows;
+            let local_offsets =
+                std::iter::from_fn(|| offsets.next_if(|offset| *offset < fragment_end))
+                    .map(|offset| offset - fragment_start)
+                    .collect::<Vec<_>>();
+            fragment_start = fragment_end;
+            if local_offsets.is_empty() {
+                continue;
+            }
+
+            // Skip the deleted rows to find the offsets in the fragment.
+            let mut deleted = match FileFragment::new(self.dataset.clone(), fragment.clone())
+                .get_deletion_vector()
+                .await?
+            {
+                Some(deletion_vector) => deletion_vector.as_ref().clone().into_iter().collect(),
+                None => Vec::new(),
+            };
+            deleted.sort_unstable();
+            let mut deleted = deleted.into_iter().peekable();
+            let mut num_skipped = 0;
+            for offset in local_offsets {
+                let mut row = offset + num_skipped;
+                while deleted.next_if(|id| *id as usize <= row).is_some() {
+                    num_skipped += 1;
+                    row += 1;
+                }
+                row_ids.push(RowAddress::new_from_parts(fragment.id as u32, row as u32).into());
+            }
+        }
+        Ok(Some(UInt64Array::from(row_ids)))
+    }
+
     /// Global offset-limit of the result of the input plan
     fn limit_node(&self, plan: Arc<dyn ExecutionPlan>) -> Arc<dyn ExecutionPlan> {
         Arc::new(GlobalLimitExec::new(
@@ -1165,6 +1916,31 @@ impl Scanner {
 
         Ok(format!("{}", display.indent(verbose)))
     }
+
+    /// Run the vector search and explain how its results are found.
+    ///
+    /// The trace reports the partitions probed by IVF indices, and the candidates, the
+    /// results and the time of each stage of the search, i.e., the index search, the
+    /// flat search over the unindexed fragments, the refine and the merge.
+    pub async fn explain_nearest(&mut self) -> Result<Arc<SearchTrace>> {
+        let Some(q) = self.nearest.as_mut() else {
+            return Err(Error::IO {
+                message: "Explain nearest: the scanner has no vector search".to_string(),
+                location: location!(),
+            });
+        };
+        let trace = Arc::new(SearchTrace::default());
+        q.trace = Some(trace.clone());
+        let result = match self.try_into_stream().await {
+            Ok(stream) => stream.try_collect::<Vec<_>>().await.map(|_| ()),
+            Err(e) => Err(e),
+        };
+        if let Some(q) = self.nearest.as_mut() {
+            q.trace = None;
+        }
+        result?;
+        Ok(trace)
+    }
 }
 
 /// [`DatasetRecordBatchStream`] wraps the dataset into a [`RecordBatchStream`] for
@@ -1214,6 +1990,30 @@ impl From<DatasetRecordBatchStream> for SendableRecordBatchStream {
     }
 }
 
+/// Convert the query vector of an int8 vector column, i.e., quantized embeddings.
+///
+/// The values must be integers in the range of int8.
+fn coerce_int8_vector(q: &Float32Array) -> Result<Int8Array> {
+    q.values()
+        .iter()
+        .map(|&v| {
+            if v.fract() == 0.0 && v >= i8::MIN as f32 && v <= i8::MAX as f32 {
+                Ok(v as i8)
+            } else {
+                Err(Error::IO {
+                    message: format!(
+                        "Query vector of an int8 vector column must have integer values in [{}, {}], got {}",
+                        i8::MIN,
+                        i8::MAX,
+                        v
+                    ),
+                    location: location!(),
+                })
+            }
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod test {
 
@@ -1225,11 +2025,12 @@ mod test {
     use arrow::compute::concat_batches;
     use arrow::datatypes::Int32Type;
     use arrow_array::cast::AsArray;
-    use arrow_array::types::{Float32Type, UInt64Type};
+    use arrow_array::types::{Float32Type, Int8Type, UInt64Type};
     use arrow_array::{
-        ArrayRef, FixedSizeListArray, Int32Array, Int64Array, LargeStringArray, PrimitiveArray,
-        RecordBatchIterator, StringArray, StructArray,
+        ArrayRef, FixedSizeListArray, Int32Array, Int64Array, LargeStringArray, ListArray,
+        PrimitiveArray, RecordBatchIterator, StringArray, StructArray,
     };
+    use arrow_buffer::OffsetBuffer;
     use arrow_ord::sort::sort_to_indices;
     use arrow_schema::{ArrowError, DataType};
     use arrow_select::take;
@@ -1238,7 +2039,10 @@ mod test {
     use lance_datagen::{array, gen, BatchCount, Dimension, RowCount};
     use lance_index::vector::DIST_COL;
     use lance_index::IndexType;
-    use lance_testing::datagen::{BatchGenerator, IncrementingInt32};
+    use lance_linalg::distance::{l2_distance, l2_distance_i8, register_distance, CustomDistance};
+    use lance_testing::datagen::{
+        generate_random_array_with_seed, BatchGenerator, IncrementingInt32,
+    };
     use tempfile::{tempdir, TempDir};
 
     use super::*;
@@ -1246,8 +2050,9 @@ mod test {
     use crate::dataset::optimize::{compact_files, CompactionOptions};
     use crate::dataset::WriteMode;
     use crate::dataset::WriteParams;
+    use crate::index::inverted::InvertedIndexParams;
     use crate::index::scalar::ScalarIndexParams;
-    use crate::index::{vector::VectorIndexParams, DatasetIndexExt};
+    use crate::index::{vector::VectorIndexParams, DatasetIndexExt, OptimizeOptions};
 
     #[tokio::test]
     async fn test_batch_size() {
@@ -1380,6 +2185,68 @@ mod test {
         assert_eq!(actual_batches.len(), 2);
     }
 
+    #[tokio::test]
+    async fn test_sample() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = gen()
+            .col(Some("i".to_string()), array::step::<Int32Type>())
+            .col(Some("s".to_string()), array::rand_utf8(4.into()))
+            .into_reader_rows(RowCount::from(100), BatchCount::from(10));
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset.delete("i % 3 = 0").await.unwrap();
+
+        let sample = |n: usize, seed: u64, filter: Option<&str>| {
+            let mut scanner = dataset.scan();
+            scanner.project(&["i"]).unwrap().sample(n, Some(seed));
+            if let Some(filter) = filter {
+                scanner.filter(filter).unwrap();
+            }
+            async move {
+                let batches = scanner
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                let values = batches
+                    .iter()
+                    .flat_map(|batch| batch["i"].as_primitive::<Int32Type>().values().to_vec())
+                    .collect::<Vec<_>>();
+                // The sampled rows are distinct rows left, in the order of the scan.
+                assert!(values.windows(2).all(|w| w[0] < w[1]));
+                assert!(values.iter().all(|i| i % 3 != 0));
+                (values, scanner.explain_plan(false).await.unwrap())
+            }
+        };
+
+        // Only the sampled rows are read.
+        let (values, plan) = sample(100, 42, None).await;
+        assert_eq!(values.len(), 100);
+        assert!(values.iter().any(|i| *i < 500) && values.iter().any(|i| *i >= 500));
+        assert!(!plan.contains("Sample") && !plan.contains("LanceScan"));
+        assert_eq!(sample(100, 42, None).await.0, values);
+        assert_ne!(sample(100, 7, None).await.0, values);
+        assert_eq!(sample(1000, 42, None).await.0.len(), 666);
+
+        // The rows matching the filter are sampled while they are scanned.
+        let (values, plan) = sample(50, 42, Some("i < 500")).await;
+        assert_eq!(values.len(), 50);
+        assert!(values.iter().all(|i| *i < 500));
+        assert!(plan.contains("Sample: n=50"));
+
+        let mut scanner = dataset.scan();
+        scanner.sample(100, None);
+        assert_eq!(scanner.count_rows().await.unwrap(), 100);
+    }
+
     async fn write_data(path: &str) -> Vec<RecordBatch> {
         let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
             "i",
@@ -1786,6 +2653,206 @@ mod test {
         assert_eq!(expected_i, actual_i);
     }
 
+    #[tokio::test]
+    async fn test_explain_nearest() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let dataset = create_vector_dataset(test_uri, true).await;
+        assert!(dataset.scan().explain_nearest().await.is_err());
+
+        // Append 10 rows that are not indexed.
+        let vector_values: Float32Array =
+            (0..10).flat_map(|i| [i as f32; 32].into_iter()).collect();
+        let new_vectors = FixedSizeListArray::try_new_from_values(vector_values, 32).unwrap();
+        let schema: Arc<ArrowSchema> = Arc::new(dataset.schema().into());
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(400..410)),
+                Arc::new(StringArray::from_iter_values(
+                    (400..410).map(|v| format!("s-{}", v)),
+                )),
+                Arc::new(new_vectors),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let params = WriteParams {
+            mode: WriteMode::Append,
+            ..Default::default()
+        };
+        let dataset = Dataset::write(reader, test_uri, Some(params))
+            .await
+            .unwrap();
+
+        let key: Float32Array = (32..64).map(|v| v as f32).collect();
+        let mut scan = dataset.scan();
+        scan.nearest("vec", &key, 5).unwrap().nprobs(1).refine(2);
+        let trace = scan.explain_nearest().await.unwrap();
+
+        let partitions = trace.partitions();
+        assert_eq!(partitions.len(), 1);
+        let index = trace.stage(SearchStage::Index);
+        assert_eq!(index.len(), 1);
+        assert_eq!(index[0].candidates, partitions[0].candidates);
+        assert_eq!(index[0].results, 10);
+        let refine = trace.stage(SearchStage::Refine);
+        assert_eq!((refine[0].candidates, refine[0].results), (10, 5));
+        let flat = trace.stage(SearchStage::Flat);
+        assert_eq!((flat[0].candidates, flat[0].results), (10, 5));
+        let merge = trace.stage(SearchStage::Merge);
+        assert_eq!((merge[0].candidates, merge[0].results), (10, 5));
+        assert!(trace.to_string().starts_with("probed partitions: "));
+    }
+
+    #[tokio::test]
+    async fn test_refine_factor_recomputes_exact_distances() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let vectors = lance_testing::datagen::generate_random_array_with_seed::<Float32Type>(
+            1000 * 32,
+            [7; 32],
+        );
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new(
+                "vec",
+                DataType::FixedSizeList(
+                    Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                    32,
+                ),
+                true,
+            ),
+        ]));
+        let vectors = FixedSizeListArray::try_new_from_values(vectors, 32).unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..1000)),
+                Arc::new(vectors.clone()),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        // Two sub-vectors for 32 dimensions make the PQ distances very coarse.
+        let params = VectorIndexParams::ivf_pq(1, 8, 2, false, MetricType::L2, 2);
+        dataset
+            .create_index(&["vec"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        let key = vectors.value(7);
+        let key = key.as_primitive::<Float32Type>();
+        let exact = lance_linalg::distance::l2_distance_batch(
+            key.values(),
+            vectors.values().as_primitive::<Float32Type>().values(),
+            32,
+        )
+        .collect::<Vec<_>>();
+        let mut expected = (0..1000).collect::<Vec<_>>();
+        expected.sort_by(|&a, &b| exact[a].total_cmp(&exact[b]));
+
+        let mut scan = dataset.scan();
+        scan.nearest("vec", key, 10).unwrap();
+        scan.refine(50);
+        let results = scan
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+
+        let ids = batch["i"].as_primitive::<Int32Type>();
+        let distances = batch[DIST_COL].as_primitive::<Float32Type>();
+        assert_eq!(ids.len(), 10);
+        for (rank, (id, distance)) in ids.values().iter().zip(distances.values()).enumerate() {
+            assert_eq!(*id as usize, expected[rank]);
+            assert!((distance - exact[*id as usize]).abs() < 1e-4);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_distance_range() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let vectors = lance_testing::datagen::generate_random_array_with_seed::<Float32Type>(
+            1000 * 32,
+            [3; 32],
+        );
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new(
+                "vec",
+                DataType::FixedSizeList(
+                    Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                    32,
+                ),
+                true,
+            ),
+        ]));
+        let vectors = FixedSizeListArray::try_new_from_values(vectors, 32).unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..1000)),
+                Arc::new(vectors.clone()),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        let key = vectors.value(42);
+        let key = key.as_primitive::<Float32Type>();
+        let mut exact = lance_linalg::distance::l2_distance_batch(
+            key.values(),
+            vectors.values().as_primitive::<Float32Type>().values(),
+            32,
+        )
+        .collect::<Vec<_>>();
+        exact.sort_by(|a, b| a.total_cmp(b));
+        let (lower, upper) = (exact[20], exact[120]);
+
+        let search = |dataset: Dataset| async move {
+            let mut scan = dataset.scan();
+            scan.nearest("vec", key, 1000)
+                .unwrap()
+                .nprobs(8)
+                .distance_range(Some(lower), Some(upper));
+            let results = scan
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            concat_batches(&results[0].schema(), &results).unwrap()
+        };
+
+        // Flat search.
+        let batch = search(dataset.clone()).await;
+        assert_eq!(batch.num_rows(), 100);
+        let distances = batch[DIST_COL].as_primitive::<Float32Type>();
+        assert!(distances.values().iter().all(|&d| d >= lower && d < upper));
+
+        // IVF_FLAT computes the exact distances, and the pruned partitions do not
+        // have any vector in the range.
+        let params = VectorIndexParams::ivf_flat(8, MetricType::L2);
+        dataset
+            .create_index(&["vec"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+        let indexed = search(dataset.clone()).await;
+        assert_eq!(indexed["i"].as_ref(), batch["i"].as_ref());
+        assert_eq!(indexed[DIST_COL].as_ref(), batch[DIST_COL].as_ref());
+    }
+
     #[tokio::test]
     async fn test_simple_scan_plan() {
         let test_dir = tempdir().unwrap();
@@ -2729,6 +3796,401 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn test_knn_with_fragment_ids() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new(
+                "vec",
+                DataType::FixedSizeList(
+                    Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                    32,
+                ),
+                true,
+            ),
+        ]));
+        // vectors are [i, i, i, ...]
+        let make_batch = |range: std::ops::Range<i32>| {
+            let vector_values: Float32Array = range
+                .clone()
+                .flat_map(|i| std::iter::repeat(i as f32).take(32))
+                .collect();
+            let vectors = FixedSizeListArray::try_new_from_values(vector_values, 32).unwrap();
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(range)),
+                    Arc::new(vectors),
+                ],
+            )
+            .unwrap()
+        };
+
+        // Fragments 0..4 are indexed, and fragment 4 is appended afterwards.
+        let mut write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(make_batch(0..400))], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params.clone()))
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["vec"],
+                IndexType::Vector,
+                None,
+                &VectorIndexParams::ivf_pq(2, 8, 2, false, MetricType::L2, 2),
+                true,
+            )
+            .await
+            .unwrap();
+        write_params.mode = WriteMode::Append;
+        let reader = RecordBatchIterator::new(vec![Ok(make_batch(400..500))], schema.clone());
+        let dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert_eq!(dataset.get_fragments().len(), 5);
+
+        let key: Float32Array = (0..32).map(|_| 0.0_f32).collect();
+        for use_index in [true, false] {
+            let mut scan = dataset.scan();
+            scan.with_fragment_ids(&[1, 4])
+                .unwrap()
+                .nearest("vec", &key, 10)
+                .unwrap()
+                .nprobs(2)
+                .refine(100)
+                .use_index(use_index);
+            let batches = scan
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+            let actual_i: BTreeSet<i32> = batch["i"]
+                .as_primitive::<Int32Type>()
+                .values()
+                .iter()
+                .copied()
+                .collect();
+            assert_eq!(actual_i, BTreeSet::from_iter(100..110));
+
+            // The fragment of the appended data is searched too.
+            let mut scan = dataset.scan();
+            scan.with_fragment_ids(&[4])
+                .unwrap()
+                .nearest("vec", &key, 10)
+                .unwrap()
+                .nprobs(2)
+                .refine(100)
+                .use_index(use_index);
+            let batches = scan
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+            let actual_i: BTreeSet<i32> = batch["i"]
+                .as_primitive::<Int32Type>()
+                .values()
+                .iter()
+                .copied()
+                .collect();
+            assert_eq!(actual_i, BTreeSet::from_iter(400..410));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_knn_with_custom_distance() {
+        /// Squared distance of the first dimension only.
+        struct FirstDimension;
+
+        impl CustomDistance for FirstDimension {
+            fn distance(&self, x: &[f32], y: &[f32]) -> f32 {
+                (x[0] - y[0]) * (x[0] - y[0])
+            }
+        }
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new(
+                "vec",
+                DataType::FixedSizeList(
+                    Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                    2,
+                ),
+                true,
+            ),
+        ]));
+        // vectors are [i, i * 37 % 100]
+        let vector_values: Float32Array = (0..100)
+            .flat_map(|i| [i as f32, (i * 37 % 100) as f32])
+            .collect();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(FixedSizeListArray::try_new_from_values(vector_values, 2).unwrap()),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        let metric_type =
+            register_distance("test_first_dimension", Arc::new(FirstDimension)).unwrap();
+        let key = Float32Array::from(vec![10.0, 90.0]);
+        let batches = dataset
+            .scan()
+            .nearest("vec", &key, 3)
+            .unwrap()
+            .distance_metric(metric_type)
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+        let actual_i = batch["i"].as_primitive::<Int32Type>().values().to_vec();
+        assert_eq!(actual_i[0], 10);
+        assert_eq!(
+            actual_i.iter().copied().collect::<BTreeSet<_>>(),
+            BTreeSet::from([9, 10, 11])
+        );
+        let distances = batch[DIST_COL]
+            .as_primitive::<Float32Type>()
+            .values()
+            .to_vec();
+        assert_eq!(distances, vec![0.0, 1.0, 1.0]);
+
+        // The index can not be persisted with a custom distance.
+        assert!(dataset
+            .create_index(
+                &["vec"],
+                IndexType::Vector,
+                None,
+                &VectorIndexParams::ivf_flat(2, metric_type),
+                true,
+            )
+            .await
+            .is_err());
+
+        assert!(dataset.scan().with_fragment_ids(&[7]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_hybrid_search() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, false),
+            ArrowField::new(
+                "vec",
+                DataType::FixedSizeList(
+                    Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                    4,
+                ),
+                true,
+            ),
+            ArrowField::new(
+                "sparse",
+                lance_index::vector::sparse::sparse_vector_type(),
+                true,
+            ),
+        ]));
+        // The vectors are [i, i, i, i], and the sparse vectors have the term
+        // `i % 10`, weighted by `1 / (i + 1)`.
+        let vector_values: Float32Array = (0..100)
+            .flat_map(|i| std::iter::repeat(i as f32).take(4))
+            .collect();
+        let sparse =
+            sparse_vector_array((0..100).map(|i| (vec![i % 10], vec![1.0 / (i + 1) as f32])))
+                .unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(FixedSizeListArray::try_new_from_values(vector_values, 4).unwrap()),
+                Arc::new(sparse),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        // The vector search ranks 5, 6, 4, 7, 3, and the sparse search 5, 15, 25, 35, 45.
+        let key: Float32Array = (0..4).map(|_| 5.2_f32).collect();
+        let search = |fusion: FusionMethod, filter: Option<&str>| {
+            let mut scan = dataset.scan();
+            scan.project(&["i"])
+                .unwrap()
+                .nearest("vec", &key, 5)
+                .unwrap()
+                .hybrid_sparse("sparse", &[5], &[1.0], fusion)
+                .unwrap();
+            if let Some(filter) = filter {
+                scan.filter(filter).unwrap().prefilter(true);
+            }
+            async move {
+                let batches = scan
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+                assert!(batch.column_by_name(SCORE_COL).is_some());
+                assert!(batch.column_by_name(DIST_COL).is_none());
+                batch["i"].as_primitive::<Int32Type>().values().to_vec()
+            }
+        };
+
+        assert_eq!(
+            search(FusionMethod::default(), None).await,
+            vec![5, 6, 15, 4, 25]
+        );
+        assert_eq!(
+            search(FusionMethod::WeightedSum { vector_weight: 1.0 }, None).await,
+            vec![5, 6, 4, 7, 3]
+        );
+        assert_eq!(
+            search(FusionMethod::default(), Some("i != 5")).await,
+            vec![6, 15, 4, 25, 7]
+        );
+
+        assert!(dataset
+            .scan()
+            .hybrid_sparse("sparse", &[5], &[1.0], FusionMethod::default())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_full_text_search() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, false),
+            ArrowField::new("text", DataType::Utf8, true),
+        ]));
+        let batch = |ids: Vec<i32>, texts: Vec<Option<&str>>| {
+            RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from(ids)),
+                    Arc::new(StringArray::from(texts)),
+                ],
+            )
+            .unwrap()
+        };
+        let reader = RecordBatchIterator::new(
+            vec![Ok(batch(
+                vec![0, 1, 2, 3, 4],
+                vec![
+                    Some("Lance is a columnar format for machine learning"),
+                    Some("the quick brown fox"),
+                    Some("a lance dataset"),
+                    Some("a format for vectors"),
+                    None,
+                ],
+            ))],
+            schema.clone(),
+        );
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        dataset
+            .create_index(
+                &["text"],
+                IndexType::Inverted,
+                None,
+                &InvertedIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+
+        let search = |dataset: &Dataset, filter: Option<&str>, limit: Option<i64>| {
+            let mut scan = dataset.scan();
+            scan.project(&["i"])
+                .unwrap()
+                .full_text_search("text", "LANCE format")
+                .unwrap()
+                .limit(limit, None)
+                .unwrap();
+            if let Some(filter) = filter {
+                scan.filter(filter).unwrap().prefilter(true);
+            }
+            async move {
+                let batches = scan
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                let batch = concat_batches(&batches[0].schema(), &batches).unwrap();
+                let scores = batch[SCORE_COL].as_primitive::<Float32Type>().values();
+                assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+                batch["i"].as_primitive::<Int32Type>().values().to_vec()
+            }
+        };
+
+        // The only row with both words is the best.
+        let results = search(&dataset, None, None).await;
+        assert_eq!(results[0], 0);
+        assert_eq!(
+            results.iter().copied().collect::<BTreeSet<_>>(),
+            BTreeSet::from([0, 2, 3])
+        );
+        assert_eq!(search(&dataset, None, Some(1)).await, vec![0]);
+        let results = search(&dataset, Some("i != 0"), None).await;
+        assert_eq!(
+            results.iter().copied().collect::<BTreeSet<_>>(),
+            BTreeSet::from([2, 3])
+        );
+
+        // The inverted index does not answer the filters.
+        assert_eq!(
+            dataset
+                .scan()
+                .filter("text = 'the quick brown fox'")
+                .unwrap()
+                .count_rows()
+                .await
+                .unwrap(),
+            1
+        );
+
+        // The appended rows are searched before and after they are indexed.
+        let reader = RecordBatchIterator::new(
+            vec![Ok(batch(vec![5], vec![Some("lance format, lance format")]))],
+            schema.clone(),
+        );
+        dataset.append(reader, None).await.unwrap();
+        assert_eq!(search(&dataset, None, Some(1)).await, vec![5]);
+        dataset
+            .optimize_indices(&OptimizeOptions::default())
+            .await
+            .unwrap();
+        assert_eq!(search(&dataset, None, Some(1)).await, vec![5]);
+        assert_eq!(search(&dataset, Some("i < 5"), Some(1)).await, vec![0]);
+
+        assert!(dataset.scan().full_text_search("i", "lance").is_err());
+    }
+
     #[tokio::test]
     async fn test_count_rows_with_filter() {
         let test_dir = tempdir().unwrap();
@@ -2868,7 +4330,10 @@ mod test {
 
             // UPDATE
 
-            dataset.optimize_indices().await.unwrap();
+            dataset
+                .optimize_indices(&OptimizeOptions::default())
+                .await
+                .unwrap();
             let updated_version = dataset.version().version;
 
             // APPEND -> DELETE
@@ -3045,8 +4510,8 @@ mod test {
                     params,
                 )
                 .await;
-            // TODO: Remove below check once we support new data in scalar index scan
-            if !params.use_new_data && !params.use_deleted_data {
+            // TODO: Remove below check once we support deleted data in scalar index scan
+            if !params.use_deleted_data {
                 // Materialization is always required if there is a refine
                 assert!(query_plan.contains("MaterializeIndex"));
             }
@@ -3079,13 +4544,14 @@ mod test {
             let (query_plan, batch) = self
                 .run_query("indexed != 50", Some(self.sample_query()), params)
                 .await;
-            // TODO: Remove below check once we support new data in scalar index scan
-            if !params.use_new_data && !params.use_deleted_data {
-                if params.use_index {
+            // TODO: Remove below check once we support deleted data in scalar index scan
+            if !params.use_deleted_data {
+                if params.use_index && !params.use_new_data {
                     // An ANN search whose prefilter is fully satisfied by the index should be
                     // able to use a ScalarIndexQuery
                     assert!(query_plan.contains("ScalarIndexQuery"));
                 } else {
+                    // The new data is filtered by a scan alongside the indexed scan
                     assert!(query_plan.contains("MaterializeIndex"));
                 }
             }
@@ -3129,8 +4595,8 @@ mod test {
 
         async fn check_simple_indexed_only(&self, params: &ScalarTestParams) {
             let (query_plan, batch) = self.run_query("indexed != 50", None, params).await;
-            // TODO: Remove below check once we support new data in scalar index scan
-            if !params.use_new_data && !params.use_deleted_data {
+            // TODO: Remove below check once we support deleted data in scalar index scan
+            if !params.use_deleted_data {
                 // Materialization is always required for non-vector search
                 assert!(query_plan.contains("MaterializeIndex"));
             }
@@ -3169,8 +4635,8 @@ mod test {
                 None,
                 params
             ).await;
-            // TODO: Remove below check once we support new data in scalar index scan
-            if !params.use_new_data && !params.use_deleted_data {
+            // TODO: Remove below check once we support deleted data in scalar index scan
+            if !params.use_deleted_data {
                 // Materialization is always required for non-vector search
                 assert!(query_plan.contains("MaterializeIndex"));
             }
@@ -3251,4 +4717,149 @@ mod test {
             }
         }
     }
+
+    #[tokio::test]
+    async fn test_knn_int8() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        const DIM: i32 = 8;
+        let values = Int8Array::from_iter_values((0..100 * DIM).map(|v| (v % 251 - 125) as i8));
+        let vectors = FixedSizeListArray::try_new_from_values(values, DIM).unwrap();
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "vec",
+            vectors.data_type().clone(),
+            true,
+        )]));
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors.clone())]).unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        let key = Float32Array::from_iter_values((0..DIM).map(|v| (v * 10) as f32));
+        let results = dataset
+            .scan()
+            .nearest("vec", &key, 5)
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let results = &results[0];
+
+        let key_i8 = (0..DIM).map(|v| (v * 10) as i8).collect::<Vec<_>>();
+        let mut expected = (0..vectors.len())
+            .map(|i| {
+                let v = vectors.value(i);
+                l2_distance_i8(&key_i8, v.as_primitive::<Int8Type>().values())
+            })
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(
+            results[DIST_COL].as_primitive::<Float32Type>().values(),
+            &expected[..5]
+        );
+
+        let key = Float32Array::from_iter_values((0..DIM).map(|v| v as f32 + 0.5));
+        assert!(dataset.scan().nearest("vec", &key, 5).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_knn_multivector() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        const DIM: i32 = 16;
+        let lengths = (0..300).map(|i| i % 4 + 1).collect::<Vec<_>>();
+        let num_vectors = lengths.iter().sum::<usize>();
+        let values =
+            generate_random_array_with_seed::<Float32Type>(num_vectors * DIM as usize, [27; 32]);
+        let vectors = FixedSizeListArray::try_new_from_values(values, DIM).unwrap();
+        let item_field = Arc::new(ArrowField::new("item", vectors.data_type().clone(), true));
+        let multivectors = ListArray::new(
+            item_field,
+            OffsetBuffer::from_lengths(lengths),
+            Arc::new(vectors),
+            None,
+        );
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("vecs", multivectors.data_type().clone(), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..300)),
+                Arc::new(multivectors.clone()),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        // The query is the multivector of row 42, which has distance 0 to itself.
+        let query = multivectors.value(42);
+        let query = query.as_fixed_size_list();
+        let expected = (0..multivectors.len())
+            .map(|i| {
+                let row = multivectors.value(i);
+                let row = row.as_fixed_size_list();
+                (0..query.len())
+                    .map(|j| {
+                        let q = query.value(j);
+                        let q = q.as_primitive::<Float32Type>().values();
+                        (0..row.len())
+                            .map(|r| {
+                                let v = row.value(r);
+                                l2_distance(q, v.as_primitive::<Float32Type>().values())
+                            })
+                            .fold(f32::INFINITY, f32::min)
+                    })
+                    .sum::<f32>()
+            })
+            .collect::<Vec<_>>();
+        let mut expected_order = (0..expected.len()).collect::<Vec<_>>();
+        expected_order.sort_by(|&a, &b| expected[a].partial_cmp(&expected[b]).unwrap());
+
+        let results = dataset
+            .scan()
+            .nearest_multivector("vecs", query, 5)
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let ids = results[0]["i"].as_primitive::<Int32Type>().values();
+        assert_eq!(
+            ids.iter().map(|&i| i as usize).collect::<Vec<_>>(),
+            expected_order[..5]
+        );
+
+        let params = VectorIndexParams::ivf_pq(2, 8, 4, false, MetricType::L2, 10);
+        dataset
+            .create_index(&["vecs"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+        let results = dataset
+            .scan()
+            .nearest_multivector("vecs", query, 5)
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let ids = results[0]["i"].as_primitive::<Int32Type>();
+        let dists = results[0][DIST_COL].as_primitive::<Float32Type>();
+        assert_eq!(ids.value(0), 42);
+        assert_eq!(dists.value(0), 0.0);
+
+        // A multivector column is searched with multivector queries only.
+        let key = Float32Array::from_iter_values((0..DIM).map(|v| v as f32));
+        assert!(dataset.scan().nearest("vecs", &key, 5).is_err());
+    }
 }
@@ -0,0 +1,1111 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evolving the schema of a dataset: adding new columns, computed from the existing
+//! ones, altering and dropping columns.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_cast::{can_cast_types, cast_with_options, CastOptions};
+use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema, SchemaRef};
+use datafusion::logical_expr::{Cast, Expr};
+use datafusion::physical_plan::PhysicalExpr;
+use datafusion::scalar::ScalarValue;
+use futures::{StreamExt, TryStreamExt};
+use snafu::{location, Location};
+use uuid::Uuid;
+
+use super::transaction::Operation;
+use super::Dataset;
+use crate::datatypes::{Schema, DEFAULT_VALUE_KEY};
+use crate::format::Fragment;
+use crate::io::exec::Planner;
+use crate::{Error, Result};
+
+/// The function of a [`BatchUDF`].
+pub type BatchMapper = Box<dyn Fn(&RecordBatch) -> Result<RecordBatch> + Send + Sync>;
+
+/// A function producing the new columns from a batch of existing columns.
+pub struct BatchUDF {
+    pub mapper: BatchMapper,
+    /// The schema of the batches returned by `mapper`.
+    pub output_schema: SchemaRef,
+}
+
+/// How to compute the values of the new columns.
+pub enum NewColumnTransform {
+    /// The names of the new columns, with the SQL expressions over the existing
+    /// columns computing them, e.g., `("double_price", "price * 2")`.
+    SqlExpressions(Vec<(String, String)>),
+    /// A function called on each batch of existing columns.
+    BatchUDF(BatchUDF),
+    /// New columns without data files: the existing rows read their default value, a
+    /// SQL literal such as `0` or `'unknown'`, or null if they have none. The default
+    /// value is recorded in the schema.
+    Defaults(Vec<(ArrowField, Option<String>)>),
+}
+
+/// A change to a column, or to a field of a struct column.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnAlteration {
+    /// The path of the column, e.g., `a.b` for the field `b` of the struct column `a`.
+    pub path: String,
+    /// The new name of the column, or of the field.
+    pub rename: Option<String>,
+    /// The new data type of the column, which its values are cast to.
+    ///
+    /// Only the files of the column are rewritten, and its indices are dropped.
+    pub data_type: Option<DataType>,
+}
+
+impl ColumnAlteration {
+    pub fn new(path: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            rename: None,
+            data_type: None,
+        }
+    }
+
+    pub fn rename(mut self, name: impl Into<String>) -> Self {
+        self.rename = Some(name.into());
+        self
+    }
+
+    pub fn cast_to(mut self, data_type: DataType) -> Self {
+        self.data_type = Some(data_type);
+        self
+    }
+}
+
+/// The new columns of a transform, and how to compute them from a batch of the
+/// columns read.
+struct ColumnTransformer {
+    read_columns: Vec<String>,
+    output_schema: SchemaRef,
+    exprs: Vec<Arc<dyn PhysicalExpr>>,
+    udf: Option<BatchUDF>,
+}
+
+impl ColumnTransformer {
+    fn try_new(
+        dataset: &Dataset,
+        transform: NewColumnTransform,
+        read_columns: Option<Vec<String>>,
+    ) -> Result<Self> {
+        match transform {
+            NewColumnTransform::SqlExpressions(expressions) => {
+                let arrow_schema = Arc::new(ArrowSchema::from(dataset.schema()));
+                let planner = Planner::new(arrow_schema);
+                let mut columns = HashSet::new();
+                for (_, expr) in &expressions {
+                    let expr = planner.parse_expr(expr)?;
+                    columns.extend(
+                        Planner::column_names_in_expr(&expr)
+                            .iter()
+                            .map(|path| path.split('.').next().unwrap_or_default().to_string()),
+                    );
+                }
+                // Keep the order of the schema, so the batches read match it.
+                let read_columns = dataset
+                    .schema()
+                    .fields
+                    .iter()
+                    .filter(|field| columns.contains(&field.name))
+                    .map(|field| field.name.clone())
+                    .collect::<Vec<_>>();
+                let read_columns = Self::non_empty(dataset, read_columns);
+
+                let read_schema =
+                    Arc::new(ArrowSchema::from(&dataset.schema().project(&read_columns)?));
+                let planner = Planner::new(read_schema.clone());
+                let mut fields = Vec::with_capacity(expressions.len());
+                let mut exprs = Vec::with_capacity(expressions.len());
+                for (name, expr) in &expressions {
+                    let expr = planner.optimize_expr(planner.parse_expr(expr)?)?;
+                    let expr = planner.create_physical_expr(&expr)?;
+                    fields.push(ArrowField::new(
+                        name,
+                        expr.data_type(read_schema.as_ref())?,
+                        true,
+                    ));
+                    exprs.push(expr);
+                }
+                Ok(Self {
+                    read_columns,
+                    output_schema: Arc::new(ArrowSchema::new(fields)),
+                    exprs,
+                    udf: None,
+                })
+            }
+            NewColumnTransform::BatchUDF(udf) => {
+                let read_columns = read_columns.unwrap_or_else(|| {
+                    dataset
+                        .schema()
+                        .fields
+                        .iter()
+                        .map(|field| field.name.clone())
+                        .collect()
+                });
+                Ok(Self {
+                    read_columns: Self::non_empty(dataset, read_columns),
+                    output_schema: udf.output_schema.clone(),
+                    exprs: vec![],
+                    udf: Some(udf),
+                })
+            }
+            NewColumnTransform::Defaults(_) => Err(Error::Internal {
+                message: "The columns with default values are not computed".to_string(),
+                location: location!(),
+            }),
+        }
+    }
+
+    /// Some column must be read to know the number of rows of each batch.
+    fn non_empty(dataset: &Dataset, read_columns: Vec<String>) -> Vec<String> {
+        if read_columns.is_empty() {
+            dataset
+                .schema()
+                .fields
+                .iter()
+                .take(1)
+                .map(|field| field.name.clone())
+                .collect()
+        } else {
+            read_columns
+        }
+    }
+
+    fn transform(&self, batch: &RecordBatch) -> Result<RecordBatch> {
+        if let Some(udf) = &self.udf {
+            let output = (udf.mapper)(batch)?;
+            if output.schema() != self.output_schema {
+                return Err(Error::invalid_input(
+                    format!(
+                        "The batch UDF returned a batch with schema {:?}, expected {:?}",
+                        output.schema(),
+                        self.output_schema
+                    ),
+                    location!(),
+                ));
+            }
+            return Ok(output);
+        }
+        let columns = self
+            .exprs
+            .iter()
+            .map(|expr| Ok(expr.evaluate(batch)?.into_array(batch.num_rows())))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(RecordBatch::try_new(self.output_schema.clone(), columns)?)
+    }
+}
+
+/// Compute the new columns of each fragment, returning the operation to commit.
+///
+/// Only the files of the new columns are written, the existing data files are kept.
+pub(super) async fn add_columns(
+    dataset: &Dataset,
+    transform: NewColumnTransform,
+    read_columns: Option<Vec<String>>,
+) -> Result<Operation> {
+    let (fragments, schema) = match transform {
+        NewColumnTransform::Defaults(columns) => add_default_columns(dataset, columns)?,
+        transform => write_new_columns(dataset, transform, read_columns).await?,
+    };
+    Ok(Operation::Merge { fragments, schema })
+}
+
+/// Parse the default value of a column, a SQL literal, into a scalar of its type.
+pub(super) fn parse_default_value(value: &str, data_type: &DataType) -> Result<ScalarValue> {
+    let planner = Planner::new(Arc::new(ArrowSchema::empty()));
+    let expr = Expr::Cast(Cast::new(
+        Box::new(planner.parse_expr(value)?),
+        data_type.clone(),
+    ));
+    match planner.optimize_expr(expr)? {
+        Expr::Literal(value) => Ok(value),
+        _ => Err(Error::invalid_input(
+            format!("The default value {} is not a constant", value),
+            location!(),
+        )),
+    }
+}
+
+fn check_new_columns<'a>(
+    dataset: &Dataset,
+    names: impl IntoIterator<Item = &'a String>,
+) -> Result<()> {
+    let mut new_names = HashSet::new();
+    for name in names {
+        if dataset.schema().field(name).is_some() || !new_names.insert(name) {
+            return Err(Error::invalid_input(
+                format!("Column {} already exists", name),
+                location!(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Add the columns to the schema only, returning the fragments unchanged and the
+/// schema with the new columns and their default values.
+fn add_default_columns(
+    dataset: &Dataset,
+    columns: Vec<(ArrowField, Option<String>)>,
+) -> Result<(Vec<Fragment>, Schema)> {
+    check_new_columns(dataset, columns.iter().map(|(field, _)| field.name()))?;
+    let mut fields = Vec::with_capacity(columns.len());
+    for (field, default_value) in columns {
+        let field = match default_value {
+            Some(value) => {
+                // Fail now rather than when reading the column.
+                parse_default_value(&value, field.data_type())?;
+                let mut metadata = field.metadata().clone();
+                metadata.insert(DEFAULT_VALUE_KEY.to_string(), value);
+                field.with_metadata(metadata)
+            }
+            None if !field.is_nullable() => {
+                return Err(Error::invalid_input(
+                    format!(
+                        "Column {} must be nullable, or have a default value",
+                        field.name()
+                    ),
+                    location!(),
+                ));
+            }
+            None => field,
+        };
+        fields.push(field);
+    }
+    let schema = dataset.schema().merge(&ArrowSchema::new(fields))?;
+    Ok((dataset.manifest.fragments.as_ref().clone(), schema))
+}
+
+/// Write the files of the new columns of each fragment, returning the updated
+/// fragments and the schema with the new columns.
+async fn write_new_columns(
+    dataset: &Dataset,
+    transform: NewColumnTransform,
+    read_columns: Option<Vec<String>>,
+) -> Result<(Vec<Fragment>, Schema)> {
+    let transformer = Arc::new(ColumnTransformer::try_new(
+        dataset,
+        transform,
+        read_columns,
+    )?);
+    check_new_columns(
+        dataset,
+        transformer
+            .output_schema
+            .fields()
+            .iter()
+            .map(|field| field.name()),
+    )?;
+    let schema = dataset.schema().merge(transformer.output_schema.as_ref())?;
+
+    let fragments: Vec<Fragment> = futures::stream::iter(dataset.get_fragments())
+        .map(|fragment| {
+            let transformer = transformer.clone();
+            async move {
+                let mut updater = fragment
+                    .updater(Some(transformer.read_columns.as_slice()))
+                    .await?;
+                while let Some(batch) = updater.next().await? {
+                    let batch = transformer.transform(batch)?;
+                    updater.update(batch).await?;
+                }
+                updater.finish().await
+            }
+        })
+        .buffered(num_cpus::get())
+        .try_collect()
+        .await?;
+
+    Ok((fragments, schema))
+}
+
+/// Cast the top-level columns of `casts` to their new data type, by writing the
+/// files of the cast columns, returning the updated fragments and schema.
+///
+/// The cast columns get new field ids, so the indices on them are dropped.
+async fn cast_columns(
+    dataset: &Dataset,
+    casts: &[(&str, &DataType)],
+) -> Result<(Vec<Fragment>, Schema)> {
+    let mut read_columns = Vec::with_capacity(casts.len());
+    let mut output_fields = Vec::with_capacity(casts.len());
+    for (path, data_type) in casts {
+        let field = match dataset.schema().field(path) {
+            Some(field) if !path.contains('.') => field,
+            _ => {
+                return Err(Error::invalid_input(
+                    format!("Cannot change the type of {}: not a top-level column", path),
+                    location!(),
+                ))
+            }
+        };
+        if !can_cast_types(&field.data_type(), data_type) {
+            return Err(Error::invalid_input(
+                format!(
+                    "Cannot change the type of {} from {} to {}",
+                    path,
+                    field.data_type(),
+                    data_type
+                ),
+                location!(),
+            ));
+        }
+        read_columns.push(path.to_string());
+        // The cast column is written next to the existing one, and replaces it.
+        output_fields.push(ArrowField::new(
+            format!("{}_cast_{}", path, Uuid::new_v4().simple()),
+            (*data_type).clone(),
+            field.nullable,
+        ));
+    }
+    let output_schema = Arc::new(ArrowSchema::new(output_fields));
+    let udf = BatchUDF {
+        mapper: Box::new({
+            let output_schema = output_schema.clone();
+            let read_columns = read_columns.clone();
+            move |batch| {
+                let options = CastOptions {
+                    safe: false,
+                    ..Default::default()
+                };
+                let columns = read_columns
+                    .iter()
+                    .zip(output_schema.fields())
+                    .map(|(name, field)| {
+                        Ok(cast_with_options(
+                            &batch[name.as_str()],
+                            field.data_type(),
+                            &options,
+                        )?)
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(RecordBatch::try_new(output_schema.clone(), columns)?)
+            }
+        }),
+        output_schema: output_schema.clone(),
+    };
+    let (mut fragments, mut schema) = write_new_columns(
+        dataset,
+        NewColumnTransform::BatchUDF(udf),
+        Some(read_columns.clone()),
+    )
+    .await?;
+
+    let mut replaced_ids = HashSet::new();
+    for (path, output_field) in read_columns.iter().zip(output_schema.fields()) {
+        let new_idx = schema
+            .fields
+            .iter()
+            .position(|field| &field.name == output_field.name())
+            .unwrap();
+        let mut new_field = schema.fields.remove(new_idx);
+        new_field.name = path.clone();
+        let old_idx = schema
+            .fields
+            .iter()
+            .position(|field| &field.name == path)
+            .unwrap();
+        let old_field = std::mem::replace(&mut schema.fields[old_idx], new_field);
+        replaced_ids.extend(
+            Schema {
+                fields: vec![old_field],
+                metadata: Default::default(),
+            }
+            .field_ids(),
+        );
+    }
+    for fragment in fragments.iter_mut() {
+        fragment.files.retain_mut(|file| {
+            file.fields.retain(|id| !replaced_ids.contains(id));
+            !file.fields.is_empty()
+        });
+    }
+    Ok((fragments, schema))
+}
+
+/// Apply the `alterations` to the schema, returning the operation to commit.
+///
+/// Renaming keeps the field ids, so the data files and the indices remain valid
+/// without being rewritten. Changing the type of a column rewrites its files only.
+pub(super) async fn alter_columns(
+    dataset: &Dataset,
+    alterations: &[ColumnAlteration],
+) -> Result<Operation> {
+    for alteration in alterations {
+        if dataset.partition_columns().contains(&alteration.path) {
+            return Err(Error::invalid_input(
+                format!("Cannot alter the partition column {}", alteration.path),
+                location!(),
+            ));
+        }
+    }
+    let casts = alterations
+        .iter()
+        .filter_map(|alteration| {
+            alteration
+                .data_type
+                .as_ref()
+                .map(|data_type| (alteration.path.as_str(), data_type))
+        })
+        .collect::<Vec<_>>();
+    let (fragments, mut schema) = if casts.is_empty() {
+        (
+            dataset.fragments().as_ref().clone(),
+            dataset.schema().clone(),
+        )
+    } else {
+        cast_columns(dataset, &casts).await?
+    };
+
+    for alteration in alterations {
+        let Some(field) = schema.field(&alteration.path) else {
+            return Err(Error::invalid_input(
+                format!("Column {} does not exist in the dataset", alteration.path),
+                location!(),
+            ));
+        };
+        let id = field.id;
+        if let Some(name) = &alteration.rename {
+            if name.is_empty() || name.contains('.') {
+                return Err(Error::invalid_input(
+                    format!("Invalid name {} for column {}", name, alteration.path),
+                    location!(),
+                ));
+            }
+            let new_path = match alteration.path.rsplit_once('.') {
+                Some((parent, _)) => format!("{}.{}", parent, name),
+                None => name.clone(),
+            };
+            if new_path != alteration.path && schema.field(&new_path).is_some() {
+                return Err(Error::invalid_input(
+                    format!(
+                        "Cannot rename {} to {}: the column already exists",
+                        alteration.path, new_path
+                    ),
+                    location!(),
+                ));
+            }
+            if let Some(field) = schema.mut_field_by_id(id) {
+                field.name = name.clone();
+            }
+        }
+    }
+
+    Ok(Operation::Merge { fragments, schema })
+}
+
+/// Remove the `columns`, which may be fields of struct columns, from the schema,
+/// returning the operation to commit.
+///
+/// The data files are not rewritten, they just no longer reference the dropped
+/// fields. A data file with no fields left is removed from its fragment, and deleted
+/// by [`Dataset::cleanup_old_versions`] once no version references it.
+pub(super) fn drop_columns(dataset: &Dataset, columns: &[&str]) -> Result<Operation> {
+    for column in columns {
+        if dataset.schema().field(column).is_none() {
+            return Err(Error::invalid_input(
+                format!("Column {} does not exist in the dataset", column),
+                location!(),
+            ));
+        }
+        if dataset
+            .partition_columns()
+            .iter()
+            .any(|name| name == column)
+        {
+            return Err(Error::invalid_input(
+                format!("Cannot drop the partition column {}", column),
+                location!(),
+            ));
+        }
+    }
+    let dropped = dataset.schema().project(columns)?;
+    let schema = dataset.schema().exclude(dropped)?;
+    if schema.fields.is_empty() {
+        return Err(Error::invalid_input(
+            "Cannot drop all the columns of a dataset",
+            location!(),
+        ));
+    }
+
+    let field_ids = schema.field_ids().into_iter().collect::<HashSet<_>>();
+    let fragments = dataset
+        .fragments()
+        .iter()
+        .map(|fragment| {
+            let mut fragment = fragment.clone();
+            fragment.files.retain_mut(|file| {
+                file.fields.retain(|id| field_ids.contains(id));
+                !file.fields.is_empty()
+            });
+            fragment
+        })
+        .collect();
+
+    Ok(Operation::Merge { fragments, schema })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{
+        cast::AsArray,
+        types::{Float32Type, Float64Type, Int32Type, Int64Type},
+        Float64Array, Int32Array, RecordBatchIterator, StringArray, StructArray,
+    };
+    use arrow_schema::{DataType, Fields};
+    use lance_index::IndexType;
+    use tempfile::tempdir;
+
+    use crate::dataset::WriteParams;
+    use crate::index::scalar::ScalarIndexParams;
+    use crate::index::DatasetIndexExt;
+
+    async fn create_dataset(test_uri: &str) -> Dataset {
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int32,
+            true,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..100))],
+        )
+        .unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 50,
+            max_rows_per_group: 10,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset.delete("i % 10 = 0").await.unwrap();
+        dataset
+    }
+
+    async fn collect(dataset: &Dataset) -> RecordBatch {
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        arrow_select::concat::concat_batches(&batches[0].schema(), &batches).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_add_columns_sql() {
+        let test_dir = tempdir().unwrap();
+        let mut dataset = create_dataset(test_dir.path().to_str().unwrap()).await;
+        let version = dataset.version().version;
+
+        dataset
+            .add_columns(
+                NewColumnTransform::SqlExpressions(vec![
+                    ("double_i".to_string(), "i * 2".to_string()),
+                    ("one".to_string(), "1".to_string()),
+                ]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, version + 1);
+        // The existing data file of each fragment is kept.
+        for fragment in dataset.get_fragments() {
+            assert_eq!(fragment.metadata().files.len(), 2);
+        }
+
+        let batch = collect(&dataset).await;
+        assert_eq!(batch.num_rows(), 90);
+        let ids = batch["i"].as_primitive::<Int32Type>();
+        let doubles = batch["double_i"].as_primitive::<Int32Type>();
+        for (id, double) in ids.values().iter().zip(doubles.values()) {
+            assert_eq!(id * 2, *double);
+        }
+        assert!(batch["one"]
+            .as_primitive::<Int64Type>()
+            .values()
+            .iter()
+            .all(|v| *v == 1));
+
+        // The new columns can't replace existing ones.
+        let result = dataset
+            .add_columns(
+                NewColumnTransform::SqlExpressions(vec![("i".to_string(), "i + 1".to_string())]),
+                None,
+            )
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_add_columns_defaults() {
+        let test_dir = tempdir().unwrap();
+        let mut dataset = create_dataset(test_dir.path().to_str().unwrap()).await;
+        let version = dataset.version().version;
+
+        dataset
+            .add_columns(
+                NewColumnTransform::Defaults(vec![
+                    (
+                        ArrowField::new("label", DataType::Utf8, true),
+                        Some("'unknown'".to_string()),
+                    ),
+                    (ArrowField::new("score", DataType::Float64, true), None),
+                ]),
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, version + 1);
+        // No data file is written.
+        for fragment in dataset.get_fragments() {
+            assert_eq!(fragment.metadata().files.len(), 1);
+        }
+        assert_eq!(
+            dataset.schema().field("label").unwrap().default_value(),
+            Some("'unknown'")
+        );
+
+        let batch = collect(&dataset).await;
+        assert_eq!(batch.num_rows(), 90);
+        assert!(batch["label"]
+            .as_string::<i32>()
+            .iter()
+            .all(|v| v == Some("unknown")));
+        assert_eq!(batch["score"].null_count(), 90);
+
+        // Only the default columns are read.
+        let batch = dataset
+            .scan()
+            .project(&["label"])
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batch.iter().map(|b| b.num_rows()).sum::<usize>(), 90);
+
+        // The rows appended afterwards have values.
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("label", DataType::Utf8, true),
+            ArrowField::new("score", DataType::Float64, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(100..110)),
+                Arc::new(StringArray::from_iter_values(
+                    (100..110).map(|i| i.to_string()),
+                )),
+                Arc::new(Float64Array::from_iter_values((100..110).map(f64::from))),
+            ],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        dataset.append(reader, None).await.unwrap();
+        let batch = dataset
+            .scan()
+            .filter("i >= 95")
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow_select::concat::concat_batches(&batch[0].schema(), &batch).unwrap();
+        assert_eq!(batch.num_rows(), 15);
+        assert_eq!(batch["label"].as_string::<i32>().value(0), "unknown");
+        assert_eq!(batch["label"].as_string::<i32>().value(5), "100");
+        assert_eq!(batch["score"].null_count(), 5);
+
+        // The default value must be a constant of the type of the column.
+        let result = dataset
+            .add_columns(
+                NewColumnTransform::Defaults(vec![(
+                    ArrowField::new("n", DataType::Int32, true),
+                    Some("i + 1".to_string()),
+                )]),
+                None,
+            )
+            .await;
+        assert!(result.is_err());
+        let result = dataset
+            .add_columns(
+                NewColumnTransform::Defaults(vec![(
+                    ArrowField::new("n", DataType::Int32, false),
+                    None,
+                )]),
+                None,
+            )
+            .await;
+        assert!(matches!(result, Err(Error::InvalidInput { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_add_columns_udf() {
+        let test_dir = tempdir().unwrap();
+        let mut dataset = create_dataset(test_dir.path().to_str().unwrap()).await;
+
+        let output_schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "half",
+            DataType::Float64,
+            true,
+        )]));
+        let udf = BatchUDF {
+            mapper: Box::new({
+                let output_schema = output_schema.clone();
+                move |batch| {
+                    let ids = batch["i"].as_primitive::<Int32Type>();
+                    Ok(RecordBatch::try_new(
+                        output_schema.clone(),
+                        vec![Arc::new(
+                            ids.values()
+                                .iter()
+                                .map(|id| *id as f64 / 2.0)
+                                .collect::<Float64Array>(),
+                        )],
+                    )?)
+                }
+            }),
+            output_schema,
+        };
+        dataset
+            .add_columns(
+                NewColumnTransform::BatchUDF(udf),
+                Some(vec!["i".to_string()]),
+            )
+            .await
+            .unwrap();
+
+        let batch = collect(&dataset).await;
+        assert_eq!(batch.num_rows(), 90);
+        let ids = batch["i"].as_primitive::<Int32Type>();
+        let halves = batch["half"].as_primitive::<Float64Type>();
+        for (id, half) in ids.values().iter().zip(halves.values()) {
+            assert_eq!(*id as f64 / 2.0, *half);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_drop_columns() {
+        let struct_fields = Fields::from(vec![
+            ArrowField::new("c", DataType::Int32, true),
+            ArrowField::new("d", DataType::Int32, true),
+        ]);
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("s", DataType::Utf8, true),
+            ArrowField::new("b", DataType::Struct(struct_fields.clone()), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| format!("s-{i}")),
+                )),
+                Arc::new(StructArray::new(
+                    struct_fields,
+                    vec![
+                        Arc::new(Int32Array::from_iter_values(100..200)),
+                        Arc::new(Int32Array::from_iter_values(200..300)),
+                    ],
+                    None,
+                )),
+            ],
+        )
+        .unwrap();
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        dataset
+            .create_index(
+                &["s"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        let data_files = dataset.fragments()[0].files.clone();
+
+        dataset.drop_columns(&["s", "b.c"]).await.unwrap();
+        let batch = collect(&dataset).await;
+        assert_eq!(
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().as_str())
+                .collect::<Vec<_>>(),
+            vec!["i", "b"]
+        );
+        let b = batch["b"].as_struct();
+        assert_eq!(b.num_columns(), 1);
+        assert_eq!(
+            b.column_by_name("d")
+                .unwrap()
+                .as_primitive::<Int32Type>()
+                .value(5),
+            205
+        );
+        // The data file is kept, and the index of the dropped column is dropped.
+        assert_eq!(dataset.fragments()[0].files.len(), 1);
+        assert_eq!(dataset.fragments()[0].files[0].path, data_files[0].path);
+        assert!(dataset.load_indices().await.unwrap().is_empty());
+
+        // Dropping the last field of a struct drops the struct.
+        dataset.drop_columns(&["b.d"]).await.unwrap();
+        assert_eq!(dataset.schema().fields.len(), 1);
+
+        // A new column doesn't read the data of the dropped ones.
+        dataset
+            .add_columns(
+                NewColumnTransform::SqlExpressions(vec![("x".to_string(), "i + 1".to_string())]),
+                None,
+            )
+            .await
+            .unwrap();
+        let batch = collect(&dataset).await;
+        assert_eq!(batch["x"].as_primitive::<Int32Type>().value(5), 6);
+
+        // Dropping a column which only has a data file removes the file from the fragment.
+        dataset.drop_columns(&["x"]).await.unwrap();
+        assert_eq!(dataset.fragments()[0].files.len(), 1);
+
+        assert!(matches!(
+            dataset.drop_columns(&["missing"]).await,
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            dataset.drop_columns(&["i"]).await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_rename_columns() {
+        let struct_fields = Fields::from(vec![
+            ArrowField::new("c", DataType::Int32, true),
+            ArrowField::new("d", DataType::Int32, true),
+        ]);
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("s", DataType::Utf8, true),
+            ArrowField::new("b", DataType::Struct(struct_fields.clone()), true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| format!("s-{i}")),
+                )),
+                Arc::new(StructArray::new(
+                    struct_fields,
+                    vec![
+                        Arc::new(Int32Array::from_iter_values(100..200)),
+                        Arc::new(Int32Array::from_iter_values(200..300)),
+                    ],
+                    None,
+                )),
+            ],
+        )
+        .unwrap();
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        dataset
+            .create_index(
+                &["s"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        let field_ids = dataset.schema().field_ids();
+
+        dataset
+            .alter_columns(&[
+                ColumnAlteration::new("s").rename("name"),
+                ColumnAlteration::new("b.c").rename("e"),
+                ColumnAlteration::new("b").rename("nested"),
+            ])
+            .await
+            .unwrap();
+        assert_eq!(dataset.schema().field_ids(), field_ids);
+        assert!(dataset.schema().field("nested.e").is_some());
+        assert!(dataset.schema().field("s").is_none());
+
+        let batch = collect(&dataset).await;
+        assert_eq!(
+            batch
+                .schema()
+                .fields()
+                .iter()
+                .map(|f| f.name().as_str())
+                .collect::<Vec<_>>(),
+            vec!["i", "name", "nested"]
+        );
+        assert_eq!(batch["name"].as_string::<i32>().value(5), "s-5");
+        let nested = batch["nested"].as_struct();
+        assert_eq!(
+            nested
+                .column_by_name("e")
+                .unwrap()
+                .as_primitive::<Int32Type>()
+                .value(5),
+            105
+        );
+
+        // The index is kept, and used with the new name.
+        assert_eq!(dataset.load_indices().await.unwrap().len(), 1);
+        let mut scanner = dataset.scan();
+        scanner.filter("name = 's-5'").unwrap();
+        let plan = scanner.explain_plan(false).await.unwrap();
+        assert!(plan.contains("MaterializeIndex"), "{plan}");
+        let batches = scanner
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 1);
+
+        assert!(matches!(
+            dataset
+                .alter_columns(&[ColumnAlteration::new("name").rename("i")])
+                .await,
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            dataset
+                .alter_columns(&[ColumnAlteration::new("s").rename("t")])
+                .await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_cast_columns() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("f", DataType::Float64, true),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..100)),
+                Arc::new(Float64Array::from_iter_values((0..100).map(|i| i as f64))),
+                Arc::new(StringArray::from_iter_values(
+                    (0..100).map(|i| format!("s-{i}")),
+                )),
+            ],
+        )
+        .unwrap();
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        dataset
+            .create_index(
+                &["i"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        let data_file = dataset.fragments()[0].files[0].clone();
+
+        dataset
+            .alter_columns(&[
+                ColumnAlteration::new("i").cast_to(DataType::Int64),
+                ColumnAlteration::new("f")
+                    .cast_to(DataType::Float32)
+                    .rename("g"),
+            ])
+            .await
+            .unwrap();
+        let batch = collect(&dataset).await;
+        assert_eq!(
+            batch.schema().as_ref(),
+            &ArrowSchema::new(vec![
+                ArrowField::new("i", DataType::Int64, true),
+                ArrowField::new("g", DataType::Float32, true),
+                ArrowField::new("s", DataType::Utf8, true),
+            ])
+        );
+        assert_eq!(batch["i"].as_primitive::<Int64Type>().value(7), 7);
+        assert_eq!(batch["g"].as_primitive::<Float32Type>().value(7), 7.0);
+        assert_eq!(batch["s"].as_string::<i32>().value(7), "s-7");
+        // The index of the cast column is dropped, and the data file is still read.
+        assert!(dataset.load_indices().await.unwrap().is_empty());
+        let files = &dataset.fragments()[0].files;
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].path, data_file.path);
+        assert_eq!(files[0].fields.len(), 1);
+
+        dataset
+            .alter_columns(&[ColumnAlteration::new("s").cast_to(DataType::LargeUtf8)])
+            .await
+            .unwrap();
+        let batch = collect(&dataset).await;
+        assert_eq!(batch["s"].as_string::<i64>().value(7), "s-7");
+        // The original data file is no longer referenced.
+        assert!(dataset.fragments()[0]
+            .files
+            .iter()
+            .all(|file| file.path != data_file.path));
+
+        // The values which can't be cast are errors, not nulls.
+        assert!(dataset
+            .alter_columns(&[ColumnAlteration::new("s").cast_to(DataType::Int32)])
+            .await
+            .is_err());
+        assert!(matches!(
+            dataset
+                .alter_columns(&[ColumnAlteration::new("i").cast_to(DataType::Struct(
+                    Fields::from(vec![ArrowField::new("x", DataType::Int32, true)])
+                ))])
+                .await,
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics of a version of a dataset.
+
+use std::collections::HashMap;
+
+use futures::{StreamExt, TryStreamExt};
+use lance_core::io::FileReader;
+
+use super::fragment::FileFragment;
+use super::index::unindexed_fragments;
+use super::Dataset;
+use crate::datatypes::Schema;
+use crate::Result;
+
+/// The statistics of a version of a dataset, returned by [`Dataset::stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DatasetStatistics {
+    /// The number of rows, excluding the deleted rows.
+    pub num_rows: usize,
+    /// The number of deleted rows, which are still stored in the data files.
+    pub num_deleted_rows: usize,
+    pub num_fragments: usize,
+    pub num_data_files: usize,
+    pub num_deletion_files: usize,
+    /// The size of each top-level column, in the order of the schema.
+    pub columns: Vec<ColumnSize>,
+    /// The coverage of each index, in the order they were created.
+    pub indices: Vec<IndexCoverage>,
+}
+
+/// The size of a column in the data files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColumnSize {
+    pub name: String,
+    /// The number of bytes of the column, including its nested fields.
+    pub num_bytes: usize,
+}
+
+/// The fragments and rows covered by an index, across all its deltas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexCoverage {
+    pub name: String,
+    /// The columns the index is built on.
+    pub columns: Vec<String>,
+    pub num_deltas: usize,
+    pub num_indexed_fragments: usize,
+    pub num_unindexed_fragments: usize,
+    pub num_indexed_rows: usize,
+    pub num_unindexed_rows: usize,
+}
+
+struct FragmentStatistics {
+    id: u64,
+    num_rows: usize,
+    num_deleted_rows: usize,
+    field_sizes: HashMap<i32, usize>,
+}
+
+async fn fragment_statistics(fragment: FileFragment) -> Result<FragmentStatistics> {
+    let (physical_rows, num_deleted_rows) =
+        futures::try_join!(fragment.physical_rows(), fragment.count_deletions())?;
+
+    let dataset = fragment.dataset();
+    let mut field_sizes = HashMap::new();
+    for data_file in fragment.metadata().files.iter() {
+        let path = dataset.data_file_path(data_file);
+        let reader = FileReader::try_new_with_fragment(
+            &dataset.object_store,
+            &path,
+            fragment.id() as u64,
+            Some(dataset.manifest.as_ref()),
+            Some(&dataset.session.file_metadata_cache),
+        )
+        .await?;
+        for (field_id, size) in reader.field_sizes(&data_file.fields) {
+            *field_sizes.entry(field_id).or_default() += size;
+        }
+    }
+
+    Ok(FragmentStatistics {
+        id: fragment.id() as u64,
+        num_rows: physical_rows - num_deleted_rows,
+        num_deleted_rows,
+        field_sizes,
+    })
+}
+
+pub(super) async fn dataset_statistics(dataset: &Dataset) -> Result<DatasetStatistics> {
+    let fragments = futures::stream::iter(dataset.get_fragments())
+        .map(fragment_statistics)
+        .buffered(num_cpus::get() * 4)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let columns = dataset
+        .schema()
+        .fields
+        .iter()
+        .map(|field| {
+            let field_ids = Schema {
+                fields: vec![field.clone()],
+                metadata: HashMap::new(),
+            }
+            .field_ids();
+            let num_bytes = fragments
+                .iter()
+                .flat_map(|fragment| {
+                    field_ids
+                        .iter()
+                        .filter_map(|id| fragment.field_sizes.get(id))
+                })
+                .sum();
+            ColumnSize {
+                name: field.name.clone(),
+                num_bytes,
+            }
+        })
+        .collect();
+
+    // The deltas of an index share its name.
+    let mut deltas_by_name: Vec<(String, Vec<_>)> = Vec::new();
+    for index in dataset.load_indices().await? {
+        match deltas_by_name
+            .iter_mut()
+            .find(|(name, _)| *name == index.name)
+        {
+            Some((_, deltas)) => deltas.push(index),
+            None => deltas_by_name.push((index.name.clone(), vec![index])),
+        }
+    }
+    let mut indices = Vec::with_capacity(deltas_by_name.len());
+    for (name, deltas) in deltas_by_name {
+        let unindexed = unindexed_fragments(&deltas, dataset)
+            .await?
+            .iter()
+            .map(|fragment| fragment.id)
+            .collect::<Vec<_>>();
+        let (unindexed, indexed): (Vec<_>, Vec<_>) = fragments
+            .iter()
+            .partition(|fragment| unindexed.contains(&fragment.id));
+        let columns = deltas[0]
+            .fields
+            .iter()
+            .filter_map(|field_id| dataset.schema().field_path(*field_id))
+            .collect();
+        indices.push(IndexCoverage {
+            name,
+            columns,
+            num_deltas: deltas.len(),
+            num_indexed_fragments: indexed.len(),
+            num_unindexed_fragments: unindexed.len(),
+            num_indexed_rows: indexed.iter().map(|fragment| fragment.num_rows).sum(),
+            num_unindexed_rows: unindexed.iter().map(|fragment| fragment.num_rows).sum(),
+        });
+    }
+
+    let manifest_fragments = dataset.fragments();
+    Ok(DatasetStatistics {
+        num_rows: fragments.iter().map(|fragment| fragment.num_rows).sum(),
+        num_deleted_rows: fragments
+            .iter()
+            .map(|fragment| fragment.num_deleted_rows)
+            .sum(),
+        num_fragments: manifest_fragments.len(),
+        num_data_files: manifest_fragments
+            .iter()
+            .map(|fragment| fragment.files.len())
+            .sum(),
+        num_deletion_files: manifest_fragments
+            .iter()
+            .filter(|fragment| fragment.deletion_file.is_some())
+            .count(),
+        columns,
+        indices,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::sync::Arc;
+
+    use arrow_array::{Int64Array, RecordBatch, RecordBatchIterator, StringArray};
+    use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+    use lance_index::IndexType;
+    use tempfile::tempdir;
+
+    use crate::dataset::WriteParams;
+    use crate::index::{scalar::ScalarIndexParams, DatasetIndexExt};
+
+    #[tokio::test]
+    async fn test_stats() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(ArrowSchema::new(vec![
+            Field::new("i", DataType::Int64, false),
+            Field::new("s", DataType::Utf8, false),
+        ]));
+        let data = |range: std::ops::Range<i64>| {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int64Array::from_iter_values(range.clone())),
+                    Arc::new(StringArray::from_iter_values(
+                        range.map(|i| format!("{:04}", i)),
+                    )),
+                ],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let write_params = WriteParams {
+            max_rows_per_file: 50,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data(0..100), test_uri, Some(write_params))
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["i"],
+                IndexType::Scalar,
+                Some("i_idx".to_string()),
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        dataset.append(data(100..150), None).await.unwrap();
+        dataset.delete("i < 10").await.unwrap();
+
+        let stats = dataset.stats().await.unwrap();
+        assert_eq!(stats.num_rows, 140);
+        assert_eq!(stats.num_deleted_rows, 10);
+        assert_eq!(stats.num_fragments, 3);
+        assert_eq!(stats.num_data_files, 3);
+        assert_eq!(stats.num_deletion_files, 1);
+        assert_eq!(
+            stats.columns,
+            vec![
+                ColumnSize {
+                    name: "i".to_string(),
+                    num_bytes: 150 * 8,
+                },
+                ColumnSize {
+                    name: "s".to_string(),
+                    // The values, and the offsets of each page.
+                    num_bytes: 150 * 4 + (150 + 3) * 8,
+                },
+            ]
+        );
+        assert_eq!(
+            stats.indices,
+            vec![IndexCoverage {
+                name: "i_idx".to_string(),
+                columns: vec!["i".to_string()],
+                num_deltas: 1,
+                num_indexed_fragments: 2,
+                num_unindexed_fragments: 1,
+                num_indexed_rows: 90,
+                num_unindexed_rows: 50,
+            }]
+        );
+    }
+}
@@ -45,13 +45,16 @@
 //! (1) Delete and rewrite are compatible with each other and themselves only if
 //! they affect distinct fragments. Otherwise, they conflict.
 
-use std::{collections::HashSet, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+};
 
 use lance_core::{
     datatypes::Schema,
     format::{
         pb::{self, IndexMetadata},
-        Fragment, Index, Manifest,
+        Fragment, Index, Manifest, RowIdSequence,
     },
     io::{object_store::ObjectStore, reader::read_manifest, reader::read_manifest_indexes},
     Error, Result,
@@ -61,7 +64,8 @@ use roaring::RoaringBitmap;
 use snafu::{location, Location};
 use uuid::Uuid;
 
-use super::{feature_flags::apply_feature_flags, ManifestWriteConfig};
+use super::feature_flags::{apply_feature_flags, has_stable_row_ids};
+use super::ManifestWriteConfig;
 use crate::utils::temporal::timestamp_to_nanos;
 
 /// A change to a dataset that can be retried
@@ -76,6 +80,11 @@ pub struct Transaction {
     pub uuid: String,
     pub operation: Operation,
     pub tag: Option<String>,
+    /// Key-value metadata of the commit, e.g., the author of the transaction.
+    pub properties: HashMap<String, String>,
+    /// Entries of the dataset config set along with the operation, e.g., the
+    /// checkpoint of the data appended by the transaction.
+    pub config_upserts: HashMap<String, String>,
 }
 
 /// An operation on a dataset.
@@ -130,6 +139,28 @@ pub enum Operation {
     /// has been committed.  It is used during a rewrite operation to allow
     /// indices to be remapped to the new row ids as part of the operation.
     ReserveFragments { num_fragments: u32 },
+    /// Upgrades the metadata written by an older version of Lance to the current
+    /// format. The fragment statistics and index fragment bitmaps are migrated
+    /// when the transaction is committed.
+    Migrate,
+    /// Replace rows of existing fragments with the rows of new fragments. The
+    /// updated fragments have new deletion files and the removed fragment IDs
+    /// are those with no rows left.
+    ///
+    /// If the new fragments only hold rows moved from the removed and updated
+    /// fragments, `fields_modified` has the ids of the fields whose values changed,
+    /// otherwise it is empty.
+    Update {
+        removed_fragment_ids: Vec<u64>,
+        updated_fragments: Vec<Fragment>,
+        new_fragments: Vec<Fragment>,
+        fields_modified: Vec<i32>,
+    },
+    /// Set and remove entries of the dataset config, without changing its data.
+    UpdateConfig {
+        upsert_values: HashMap<String, String>,
+        delete_keys: Vec<String>,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -148,13 +179,15 @@ impl Operation {
     /// Returns the IDs of fragments that have been modified by this operation.
     ///
     /// This does not include new fragments.
-    fn modified_fragment_ids(&self) -> Box<dyn Iterator<Item = u64> + '_> {
+    pub(crate) fn modified_fragment_ids(&self) -> Box<dyn Iterator<Item = u64> + '_> {
         match self {
             // These operations add new fragments or don't modify any.
             Self::Append { .. }
             | Self::Overwrite { .. }
             | Self::CreateIndex { .. }
             | Self::ReserveFragments { .. }
+            | Self::Migrate
+            | Self::UpdateConfig { .. }
             | Self::Restore { .. } => Box::new(std::iter::empty()),
             Self::Delete {
                 updated_fragments,
@@ -172,6 +205,26 @@ impl Operation {
                     .flat_map(|f| f.old_fragments.iter().map(|f| f.id)),
             ),
             Self::Merge { fragments, .. } => Box::new(fragments.iter().map(|f| f.id)),
+            Self::Update {
+                removed_fragment_ids,
+                updated_fragments,
+                ..
+            } => Box::new(
+                updated_fragments
+                    .iter()
+                    .map(|f| f.id)
+                    .chain(removed_fragment_ids.iter().copied()),
+            ),
+        }
+    }
+
+    /// Whether the operation writes new rows, checked against the unique key of the
+    /// dataset, if it has one.
+    pub(crate) fn adds_rows(&self) -> bool {
+        match self {
+            Self::Append { .. } => true,
+            Self::Update { new_fragments, .. } => !new_fragments.is_empty(),
+            _ => false,
         }
     }
 
@@ -192,6 +245,9 @@ impl Operation {
             Self::Merge { .. } => "Merge",
             Self::ReserveFragments { .. } => "ReserveFragments",
             Self::Restore { .. } => "Restore",
+            Self::Update { .. } => "Update",
+            Self::Migrate => "Migrate",
+            Self::UpdateConfig { .. } => "UpdateConfig",
         }
     }
 }
@@ -204,9 +260,29 @@ impl Transaction {
             uuid,
             operation,
             tag,
+            properties: HashMap::new(),
+            config_upserts: HashMap::new(),
         }
     }
 
+    /// The keys of the dataset config changed by the transaction.
+    fn config_keys(&self) -> HashSet<&str> {
+        let mut keys = self
+            .config_upserts
+            .keys()
+            .map(String::as_str)
+            .collect::<HashSet<_>>();
+        if let Operation::UpdateConfig {
+            upsert_values,
+            delete_keys,
+        } = &self.operation
+        {
+            keys.extend(upsert_values.keys().map(String::as_str));
+            keys.extend(delete_keys.iter().map(String::as_str));
+        }
+        keys
+    }
+
     /// Returns true if the transaction cannot be committed if the other
     /// transaction is committed first.
     pub fn conflicts_with(&self, other: &Self) -> bool {
@@ -214,6 +290,12 @@ impl Transaction {
         // support Snapshot Isolation, which is more permissive. In particular,
         // it would allow a Delete transaction to succeed after a concurrent
         // Append, even if the Append added rows that would be deleted.
+        //
+        // Transactions changing the same config keys conflict, so that a checkpoint
+        // is only moved forward by the writer that read it.
+        if !self.config_keys().is_disjoint(&other.config_keys()) {
+            return true;
+        }
         match &self.operation {
             Operation::Append { .. } => match &other.operation {
                 // Append is compatible with anything that doesn't change the schema
@@ -221,7 +303,10 @@ impl Transaction {
                 Operation::Rewrite { .. } => false,
                 Operation::CreateIndex { .. } => false,
                 Operation::Delete { .. } => false,
+                Operation::Update { .. } => false,
                 Operation::ReserveFragments { .. } => false,
+                Operation::Migrate => false,
+                Operation::UpdateConfig { .. } => false,
                 _ => true,
             },
             Operation::Rewrite { .. } => match &other.operation {
@@ -231,9 +316,11 @@ impl Transaction {
                 // fragments we don't touch.
                 Operation::Append { .. } => false,
                 Operation::ReserveFragments { .. } => false,
-                Operation::Delete { .. } => {
-                    // If we rewrote any fragments that were modified by delete,
-                    // we conflict.
+                Operation::Migrate => false,
+                Operation::UpdateConfig { .. } => false,
+                Operation::Delete { .. } | Operation::Update { .. } => {
+                    // If we rewrote any fragments that were modified by delete
+                    // or update, we conflict.
                     self.operation.modifies_same_ids(&other.operation)
                 }
                 Operation::Rewrite { .. } => {
@@ -251,6 +338,14 @@ impl Transaction {
                 &other.operation,
                 Operation::Overwrite { .. } | Operation::Restore { .. }
             ),
+            // Migrate doesn't change the data, so it can always be applied on top of
+            // another transaction.
+            Operation::Migrate => false,
+            // The config changes apply on top of any data change, but a restore
+            // replaces the config with the one of the restored version.
+            Operation::UpdateConfig { .. } => {
+                matches!(&other.operation, Operation::Restore { .. })
+            }
             Operation::CreateIndex { .. } => match &other.operation {
                 Operation::Append { .. } => false,
                 // Indices are identified by UUIDs, so they shouldn't conflict.
@@ -258,9 +353,14 @@ impl Transaction {
                 // Although some of the rows we indexed may have been deleted,
                 // row ids are still valid, so we allow this optimistically.
                 Operation::Delete { .. } => false,
+                // Updated rows are deleted and rewritten into new fragments, which
+                // the index doesn't cover, like appended rows.
+                Operation::Update { .. } => false,
                 // Merge & reserve don't change row ids, so this should be fine.
                 Operation::Merge { .. } => false,
                 Operation::ReserveFragments { .. } => false,
+                Operation::Migrate => false,
+                Operation::UpdateConfig { .. } => false,
                 // Rewrite likely changed many of the row ids, so our index is
                 // likely useless. It should be rebuilt.
                 // TODO: we could be smarter here and only invalidate the index
@@ -268,10 +368,14 @@ impl Transaction {
                 Operation::Rewrite { .. } => true,
                 _ => true,
             },
-            Operation::Delete { .. } => match &other.operation {
+            // Update deletes rows like Delete does, and its new rows only go into
+            // new fragments.
+            Operation::Delete { .. } | Operation::Update { .. } => match &other.operation {
                 Operation::CreateIndex { .. } => false,
                 Operation::ReserveFragments { .. } => false,
-                Operation::Delete { .. } => {
+                Operation::Migrate => false,
+                Operation::UpdateConfig { .. } => false,
+                Operation::Delete { .. } | Operation::Update { .. } => {
                     // If we update the same fragments, we conflict.
                     self.operation.modifies_same_ids(&other.operation)
                 }
@@ -282,10 +386,14 @@ impl Transaction {
                 _ => true,
             },
             // Merge changes the schema, but preserves row ids, so the only operations
-            // it's compatible with is CreateIndex and ReserveFragments.
+            // it's compatible with is CreateIndex, ReserveFragments, Migrate and
+            // UpdateConfig.
             Operation::Merge { .. } => !matches!(
                 &other.operation,
-                Operation::CreateIndex { .. } | Operation::ReserveFragments { .. }
+                Operation::CreateIndex { .. }
+                    | Operation::ReserveFragments { .. }
+                    | Operation::Migrate
+                    | Operation::UpdateConfig { .. }
             ),
         }
     }
@@ -422,27 +530,86 @@ impl Transaction {
                 removed_indices,
             } => {
                 final_fragments.extend(maybe_existing_fragments?.clone());
+                // The deltas of an index cover disjoint fragments, so a new index only
+                // replaces the indices with the same name that overlap with it.
                 final_indices.retain(|existing_index| {
-                    !new_indices
+                    !new_indices.iter().any(|new_index| {
+                        new_index.name == existing_index.name
+                            && match (&new_index.fragment_bitmap, &existing_index.fragment_bitmap) {
+                                (Some(new_frags), Some(existing_frags)) => {
+                                    !new_frags.is_disjoint(existing_frags)
+                                }
+                                _ => true,
+                            }
+                    }) && !removed_indices
                         .iter()
-                        .any(|new_index| new_index.name == existing_index.name)
-                        && !removed_indices
-                            .iter()
-                            .any(|old_index| old_index.uuid == existing_index.uuid)
+                        .any(|old_index| old_index.uuid == existing_index.uuid)
                 });
                 final_indices.extend(new_indices.clone());
             }
-            Operation::ReserveFragments { .. } => {
+            Operation::ReserveFragments { .. }
+            | Operation::Migrate
+            | Operation::UpdateConfig { .. } => {
                 final_fragments.extend(maybe_existing_fragments?.clone());
             }
             Operation::Merge { ref fragments, .. } => {
                 final_fragments.extend(fragments.clone());
+                // The indices of dropped columns are dropped with them.
+                final_indices.retain(|index| {
+                    index
+                        .fields
+                        .iter()
+                        .all(|id| schema.field_by_id(*id).is_some())
+                });
+            }
+            Operation::Update {
+                ref removed_fragment_ids,
+                ref updated_fragments,
+                ref new_fragments,
+                ref fields_modified,
+            } => {
+                final_fragments.extend(maybe_existing_fragments?.clone());
+                final_fragments.retain(|f| !removed_fragment_ids.contains(&f.id));
+                final_fragments.iter_mut().for_each(|f| {
+                    for updated in updated_fragments {
+                        if updated.id == f.id {
+                            *f = updated.clone();
+                        }
+                    }
+                });
+                let new_fragments =
+                    Self::fragments_with_ids(new_fragments.clone(), &mut fragment_id)
+                        .collect::<Vec<_>>();
+                if !fields_modified.is_empty() && current_manifest.is_some_and(has_stable_row_ids) {
+                    let source_fragment_ids = updated_fragments
+                        .iter()
+                        .map(|f| f.id)
+                        .chain(removed_fragment_ids.iter().copied())
+                        .collect::<Vec<_>>();
+                    Self::cover_updated_rows(
+                        &mut final_indices,
+                        &source_fragment_ids,
+                        &new_fragments,
+                        fields_modified,
+                    );
+                }
+                final_fragments.extend(new_fragments);
             }
             Operation::Restore { .. } => {
                 unreachable!()
             }
         };
 
+        // An overwrite starts over, so it decides whether the dataset uses stable row ids.
+        let stable_row_ids = match (&self.operation, current_manifest) {
+            (Operation::Overwrite { .. }, _) | (_, None) => config.use_stable_row_ids,
+            (_, Some(current_manifest)) => has_stable_row_ids(current_manifest),
+        };
+        let mut next_row_id = current_manifest.map(|m| m.next_row_id).unwrap_or(0);
+        if stable_row_ids {
+            Self::assign_row_ids(&mut final_fragments, &mut next_row_id)?;
+        }
+
         let mut manifest = if let Some(current_manifest) = current_manifest {
             Manifest::new_from_previous(current_manifest, &schema, Arc::new(final_fragments))
         } else {
@@ -450,9 +617,25 @@ impl Transaction {
         };
 
         manifest.tag = self.tag.clone();
+        manifest.next_row_id = next_row_id;
+        // Like stable row ids, the partitioning is decided by an overwrite.
+        if matches!(self.operation, Operation::Overwrite { .. }) || current_manifest.is_none() {
+            manifest.partition_columns = config.partition_columns.clone();
+        }
+        if let Operation::UpdateConfig {
+            upsert_values,
+            delete_keys,
+        } = &self.operation
+        {
+            for key in delete_keys {
+                manifest.config.remove(key);
+            }
+            manifest.config.extend(upsert_values.clone());
+        }
+        manifest.config.extend(self.config_upserts.clone());
 
         if config.auto_set_feature_flags {
-            apply_feature_flags(&mut manifest);
+            apply_feature_flags(&mut manifest, stable_row_ids);
         }
         manifest.set_timestamp(timestamp_to_nanos(config.timestamp));
 
@@ -467,6 +650,59 @@ impl Transaction {
         Ok((manifest, final_indices))
     }
 
+    /// Assign new stable row ids to the rows of the fragments that have none yet,
+    /// starting at `next_row_id`.
+    ///
+    /// The fragments rewritten by compaction or holding updated rows already carry
+    /// the row ids of their rows, so only the rows that are new to the dataset get one.
+    fn assign_row_ids(fragments: &mut [Fragment], next_row_id: &mut u64) -> Result<()> {
+        for fragment in fragments
+            .iter_mut()
+            .filter(|fragment| fragment.row_id_sequence.is_none())
+        {
+            let physical_rows = fragment.physical_rows.ok_or_else(|| Error::Internal {
+                message: format!(
+                    "Cannot assign row ids to fragment {} without a physical row count",
+                    fragment.id
+                ),
+                location: location!(),
+            })? as u64;
+            fragment.row_id_sequence = Some(RowIdSequence::from(
+                *next_row_id..*next_row_id + physical_rows,
+            ));
+            *next_row_id += physical_rows;
+        }
+        Ok(())
+    }
+
+    /// Extend the indices which cover all the fragments the rows of an update were
+    /// moved from to the `new_fragments` holding them, unless the update modified the
+    /// indexed fields.
+    ///
+    /// The indices of a dataset with stable row ids are keyed by the stable row ids,
+    /// which the updated rows keep, so their entries stay valid.
+    fn cover_updated_rows(
+        indices: &mut [Index],
+        source_fragment_ids: &[u64],
+        new_fragments: &[Fragment],
+        fields_modified: &[i32],
+    ) {
+        for index in indices.iter_mut() {
+            if index.fields.iter().any(|id| fields_modified.contains(id)) {
+                continue;
+            }
+            let Some(bitmap) = index.fragment_bitmap.as_mut() else {
+                continue;
+            };
+            if source_fragment_ids
+                .iter()
+                .all(|id| bitmap.contains(*id as u32))
+            {
+                bitmap.extend(new_fragments.iter().map(|f| f.id as u32));
+            }
+        }
+    }
+
     fn recalculate_fragment_bitmap(
         old: &RoaringBitmap,
         groups: &[RewriteGroup],
@@ -615,6 +851,16 @@ impl TryFrom<&pb::Transaction> for Transaction {
             )) => Operation::ReserveFragments {
                 num_fragments: *num_fragments,
             },
+            Some(pb::transaction::Operation::Migrate(pb::transaction::Migrate {})) => {
+                Operation::Migrate
+            }
+            Some(pb::transaction::Operation::UpdateConfig(pb::transaction::UpdateConfig {
+                upsert_values,
+                delete_keys,
+            })) => Operation::UpdateConfig {
+                upsert_values: upsert_values.clone(),
+                delete_keys: delete_keys.clone(),
+            },
             Some(pb::transaction::Operation::Rewrite(pb::transaction::Rewrite {
                 old_fragments,
                 new_fragments,
@@ -666,6 +912,17 @@ impl TryFrom<&pb::Transaction> for Transaction {
             Some(pb::transaction::Operation::Restore(pb::transaction::Restore { version })) => {
                 Operation::Restore { version: *version }
             }
+            Some(pb::transaction::Operation::Update(pb::transaction::Update {
+                removed_fragment_ids,
+                updated_fragments,
+                new_fragments,
+                fields_modified,
+            })) => Operation::Update {
+                removed_fragment_ids: removed_fragment_ids.clone(),
+                updated_fragments: updated_fragments.iter().map(Fragment::from).collect(),
+                new_fragments: new_fragments.iter().map(Fragment::from).collect(),
+                fields_modified: fields_modified.clone(),
+            },
             None => {
                 return Err(Error::Internal {
                     message: "Transaction message did not contain an operation".to_string(),
@@ -682,6 +939,8 @@ impl TryFrom<&pb::Transaction> for Transaction {
             } else {
                 Some(message.tag.clone())
             },
+            properties: message.properties.clone(),
+            config_upserts: message.config_upserts.clone(),
         })
     }
 }
@@ -754,6 +1013,14 @@ impl From<&Transaction> for pb::Transaction {
                     num_fragments: *num_fragments,
                 })
             }
+            Operation::Migrate => pb::transaction::Operation::Migrate(pb::transaction::Migrate {}),
+            Operation::UpdateConfig {
+                upsert_values,
+                delete_keys,
+            } => pb::transaction::Operation::UpdateConfig(pb::transaction::UpdateConfig {
+                upsert_values: upsert_values.clone(),
+                delete_keys: delete_keys.clone(),
+            }),
             Operation::Rewrite {
                 groups,
                 rewritten_indices,
@@ -785,6 +1052,20 @@ impl From<&Transaction> for pb::Transaction {
             Operation::Restore { version } => {
                 pb::transaction::Operation::Restore(pb::transaction::Restore { version: *version })
             }
+            Operation::Update {
+                removed_fragment_ids,
+                updated_fragments,
+                new_fragments,
+                fields_modified,
+            } => pb::transaction::Operation::Update(pb::transaction::Update {
+                removed_fragment_ids: removed_fragment_ids.clone(),
+                updated_fragments: updated_fragments
+                    .iter()
+                    .map(pb::DataFragment::from)
+                    .collect(),
+                new_fragments: new_fragments.iter().map(pb::DataFragment::from).collect(),
+                fields_modified: fields_modified.clone(),
+            }),
         };
 
         Self {
@@ -792,6 +1073,8 @@ impl From<&Transaction> for pb::Transaction {
             uuid: value.uuid.clone(),
             operation: Some(operation),
             tag: value.tag.clone().unwrap_or("".to_string()),
+            properties: value.properties.clone(),
+            config_upserts: value.config_upserts.clone(),
         }
     }
 }
@@ -868,6 +1151,17 @@ mod tests {
                 rewritten_indices: vec![],
             },
             Operation::ReserveFragments { num_fragments: 3 },
+            Operation::Update {
+                removed_fragment_ids: vec![2],
+                updated_fragments: vec![fragment0.clone()],
+                new_fragments: vec![fragment2.clone()],
+                fields_modified: vec![],
+            },
+            Operation::Migrate,
+            Operation::UpdateConfig {
+                upsert_values: HashMap::from([("a".to_string(), "1".to_string())]),
+                delete_keys: vec![],
+            },
         ];
         let other_transactions = other_operations
             .iter()
@@ -881,7 +1175,9 @@ mod tests {
                 Operation::Append {
                     fragments: vec![fragment0.clone()],
                 },
-                [false, false, false, true, true, false, false],
+                [
+                    false, false, false, true, true, false, false, false, false, false,
+                ],
             ),
             (
                 Operation::Delete {
@@ -890,7 +1186,9 @@ mod tests {
                     deleted_fragment_ids: vec![],
                     predicate: "x > 2".to_string(),
                 },
-                [true, false, false, true, true, false, false],
+                [
+                    true, false, false, true, true, false, false, false, false, false,
+                ],
             ),
             (
                 Operation::Delete {
@@ -899,7 +1197,9 @@ mod tests {
                     deleted_fragment_ids: vec![],
                     predicate: "x > 2".to_string(),
                 },
-                [true, false, true, true, true, true, false],
+                [
+                    true, false, true, true, true, true, false, true, false, false,
+                ],
             ),
             (
                 Operation::Overwrite {
@@ -908,7 +1208,9 @@ mod tests {
                 },
                 // No conflicts: overwrite can always happen since it doesn't
                 // depend on previous state of the table.
-                [false, false, false, false, false, false, false],
+                [
+                    false, false, false, false, false, false, false, false, false, false,
+                ],
             ),
             (
                 Operation::CreateIndex {
@@ -916,7 +1218,9 @@ mod tests {
                     removed_indices: vec![index0.clone()],
                 },
                 // Will only conflict with operations that modify row ids.
-                [false, false, false, false, true, true, false],
+                [
+                    false, false, false, false, true, true, false, false, false, false,
+                ],
             ),
             (
                 // Rewrite that affects different fragments
@@ -927,7 +1231,9 @@ mod tests {
                     }],
                     rewritten_indices: Vec::new(),
                 },
-                [false, true, false, true, true, false, false],
+                [
+                    false, true, false, true, true, false, false, false, false, false,
+                ],
             ),
             (
                 // Rewrite that affects the same fragments
@@ -938,7 +1244,9 @@ mod tests {
                     }],
                     rewritten_indices: Vec::new(),
                 },
-                [false, true, true, true, true, true, false],
+                [
+                    false, true, true, true, true, true, false, true, false, false,
+                ],
             ),
             (
                 Operation::Merge {
@@ -946,12 +1254,54 @@ mod tests {
                     schema: Schema::default(),
                 },
                 // Merge conflicts with everything except CreateIndex and ReserveFragments.
-                [true, false, true, true, true, true, false],
+                [
+                    true, false, true, true, true, true, false, true, false, false,
+                ],
             ),
             (
                 Operation::ReserveFragments { num_fragments: 2 },
                 // ReserveFragments only conflicts with Overwrite and Restore.
-                [false, false, false, false, true, false, false],
+                [
+                    false, false, false, false, true, false, false, false, false, false,
+                ],
+            ),
+            (
+                Operation::Update {
+                    // Update that affects fragments different from other transactions
+                    removed_fragment_ids: vec![],
+                    updated_fragments: vec![fragment1.clone()],
+                    new_fragments: vec![fragment2.clone()],
+                    fields_modified: vec![],
+                },
+                [
+                    true, false, false, true, true, false, false, false, false, false,
+                ],
+            ),
+            (
+                Operation::Migrate,
+                // Migrate doesn't change the data, so it never conflicts.
+                [
+                    false, false, false, false, false, false, false, false, false, false,
+                ],
+            ),
+            (
+                Operation::UpdateConfig {
+                    upsert_values: HashMap::from([("b".to_string(), "2".to_string())]),
+                    delete_keys: vec!["a".to_string()],
+                },
+                // UpdateConfig only conflicts with the changes of the same config keys.
+                [
+                    false, false, false, false, false, false, false, false, false, true,
+                ],
+            ),
+            (
+                Operation::UpdateConfig {
+                    upsert_values: HashMap::from([("b".to_string(), "2".to_string())]),
+                    delete_keys: vec![],
+                },
+                [
+                    false, false, false, false, false, false, false, false, false, false,
+                ],
             ),
         ];
 
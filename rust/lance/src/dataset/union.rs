@@ -0,0 +1,572 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A read-only view which scans several datasets as one logical table, e.g., the
+//! datasets of a per-tenant or per-day layout.
+
+use std::sync::Arc;
+
+use arrow_array::{cast::AsArray, new_null_array, RecordBatch};
+use arrow_schema::{Field as ArrowField, Schema as ArrowSchema, SchemaRef};
+use arrow_select::filter::filter_record_batch;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::PhysicalExpr;
+use futures::{stream, StreamExt, TryStreamExt};
+use snafu::{location, Location};
+
+use super::scanner::{DatasetRecordBatchStream, Scanner};
+use super::Dataset;
+use crate::datatypes::Schema;
+use crate::io::exec::Planner;
+use crate::{Error, Result};
+
+/// A dataset of a [`UnionDataset`], with the filter of its rows to include.
+#[derive(Debug, Clone)]
+pub struct UnionMember {
+    dataset: Arc<Dataset>,
+    filter: Option<String>,
+}
+
+impl UnionMember {
+    pub fn new(dataset: Dataset) -> Self {
+        Self {
+            dataset: Arc::new(dataset),
+            filter: None,
+        }
+    }
+
+    /// Only include the rows of the dataset which match the SQL `filter`.
+    pub fn with_filter(mut self, filter: impl Into<String>) -> Self {
+        self.filter = Some(filter.into());
+        self
+    }
+
+    pub fn dataset(&self) -> &Dataset {
+        &self.dataset
+    }
+
+    pub fn filter(&self) -> Option<&str> {
+        self.filter.as_deref()
+    }
+}
+
+impl From<Dataset> for UnionMember {
+    fn from(dataset: Dataset) -> Self {
+        Self::new(dataset)
+    }
+}
+
+/// Several datasets with compatible schemas, scanned as one logical table.
+///
+/// The schema of the union has the top-level columns of all the datasets, in the
+/// order they first appear. A column must have the same type in every dataset which
+/// has it, and is null for the rows of the datasets which do not.
+///
+/// ```rust,ignore
+/// let union = UnionDataset::try_new([
+///     UnionMember::new(Dataset::open("s3://bucket/2023-11-01.lance").await?),
+///     UnionMember::new(Dataset::open("s3://bucket/2023-11-02.lance").await?)
+///         .with_filter("tenant = 'acme'"),
+/// ])?;
+/// let stream = union.scan().filter("score > 0.5")?.try_into_stream().await?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnionDataset {
+    members: Vec<UnionMember>,
+    schema: SchemaRef,
+}
+
+impl UnionDataset {
+    pub fn try_new(members: impl IntoIterator<Item = impl Into<UnionMember>>) -> Result<Self> {
+        let members = members.into_iter().map(Into::into).collect::<Vec<_>>();
+        if members.is_empty() {
+            return Err(Error::invalid_input(
+                "A union needs at least one dataset",
+                location!(),
+            ));
+        }
+        let schemas = members
+            .iter()
+            .map(|member| ArrowSchema::from(member.dataset.schema()))
+            .collect::<Vec<_>>();
+        for (member, schema) in members.iter().zip(&schemas) {
+            if let Some(filter) = &member.filter {
+                // Fail early rather than at the first scan.
+                check_filter(filter, &Arc::new(schema.clone()))?;
+            }
+        }
+        let schema = Arc::new(unify_schemas(&schemas)?);
+        Ok(Self { members, schema })
+    }
+
+    /// The unified schema of the datasets.
+    pub fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    pub fn members(&self) -> &[UnionMember] {
+        &self.members
+    }
+
+    /// Scan the rows of all the datasets, one dataset after the other.
+    pub fn scan(&self) -> UnionScanner {
+        UnionScanner {
+            members: self.members.clone(),
+            schema: self.schema.clone(),
+            projection: None,
+            filter: None,
+            batch_size: None,
+        }
+    }
+
+    /// Count the rows of all the datasets, after their filters.
+    pub async fn count_rows(&self) -> Result<u64> {
+        self.scan().count_rows().await
+    }
+}
+
+/// Unify the top-level fields of the schemas by name.
+fn unify_schemas(schemas: &[ArrowSchema]) -> Result<ArrowSchema> {
+    let mut fields: Vec<ArrowField> = Vec::new();
+    for schema in schemas {
+        for field in schema.fields() {
+            match fields.iter_mut().find(|f| f.name() == field.name()) {
+                Some(existing) => {
+                    if existing.data_type() != field.data_type() {
+                        return Err(Error::invalid_input(
+                            format!(
+                                "Column {} has type {} in one dataset and {} in another",
+                                field.name(),
+                                existing.data_type(),
+                                field.data_type()
+                            ),
+                            location!(),
+                        ));
+                    }
+                    if field.is_nullable() {
+                        *existing = existing.clone().with_nullable(true);
+                    }
+                }
+                None => fields.push(field.as_ref().clone()),
+            }
+        }
+    }
+    // The rows of the datasets without a column are null in it.
+    for field in fields.iter_mut() {
+        if schemas
+            .iter()
+            .any(|schema| schema.field_with_name(field.name()).is_err())
+        {
+            *field = field.clone().with_nullable(true);
+        }
+    }
+    Ok(ArrowSchema::new(fields))
+}
+
+/// Check that the filter parses and that its columns are in the schema, which the
+/// planner does not check.
+fn check_filter(filter: &str, schema: &SchemaRef) -> Result<()> {
+    let expr = Planner::new(schema.clone()).parse_filter(filter)?;
+    let lance_schema = Schema::try_from(schema.as_ref())?;
+    for column in Planner::column_names_in_expr(&expr) {
+        if lance_schema.field(&column).is_none() {
+            return Err(Error::invalid_input(
+                format!("Column {} in the filter {} does not exist", column, filter),
+                location!(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Fill the columns of the schema which are missing from the batch with nulls.
+fn conform_batch(batch: &RecordBatch, schema: &SchemaRef) -> Result<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| match batch.column_by_name(field.name()) {
+            Some(column) => column.clone(),
+            None => new_null_array(field.data_type(), batch.num_rows()),
+        })
+        .collect();
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+/// Scan of a [`UnionDataset`].
+///
+/// The filter is pushed down to the scan of each dataset which has all of its
+/// columns, and is otherwise applied after the missing columns are filled with nulls.
+#[derive(Debug, Clone)]
+pub struct UnionScanner {
+    members: Vec<UnionMember>,
+    schema: SchemaRef,
+    projection: Option<Vec<String>>,
+    filter: Option<String>,
+    batch_size: Option<usize>,
+}
+
+/// The plan of the scan of one member of the union.
+struct MemberScan {
+    scanner: Scanner,
+    /// The filter which could not be pushed down, over `scan_schema`.
+    post_filter: Option<Arc<dyn PhysicalExpr>>,
+    /// The projected columns and the columns of the post filter.
+    scan_schema: SchemaRef,
+    output_schema: SchemaRef,
+}
+
+impl UnionScanner {
+    /// Only select the specified top-level columns.
+    pub fn project<T: AsRef<str>>(&mut self, columns: &[T]) -> Result<&mut Self> {
+        for column in columns {
+            if self.schema.field_with_name(column.as_ref()).is_err() {
+                return Err(Error::invalid_input(
+                    format!("Column {} is not in the union", column.as_ref()),
+                    location!(),
+                ));
+            }
+        }
+        self.projection = Some(columns.iter().map(|c| c.as_ref().to_string()).collect());
+        Ok(self)
+    }
+
+    /// Only return the rows which match the SQL `filter`, in addition to the filter
+    /// of their dataset.
+    pub fn filter(&mut self, filter: &str) -> Result<&mut Self> {
+        check_filter(filter, &self.schema)?;
+        self.filter = Some(filter.to_string());
+        Ok(self)
+    }
+
+    pub fn batch_size(&mut self, batch_size: usize) -> &mut Self {
+        self.batch_size = Some(batch_size);
+        self
+    }
+
+    /// The schema of the scanned batches.
+    pub fn schema(&self) -> Result<SchemaRef> {
+        match &self.projection {
+            Some(columns) => Ok(Arc::new(
+                self.schema.project(&self.column_indices(columns))?,
+            )),
+            None => Ok(self.schema.clone()),
+        }
+    }
+
+    fn column_indices(&self, columns: &[String]) -> Vec<usize> {
+        columns
+            .iter()
+            .filter_map(|c| self.schema.index_of(c).ok())
+            .collect()
+    }
+
+    fn plan_member(&self, member: &UnionMember) -> Result<MemberScan> {
+        let dataset_schema = member.dataset.schema();
+        let output_schema = self.schema()?;
+
+        let mut filters = member.filter.iter().cloned().collect::<Vec<_>>();
+        let mut scan_columns = output_schema
+            .fields()
+            .iter()
+            .map(|f| f.name().clone())
+            .collect::<Vec<_>>();
+        let mut post_filter = None;
+        if let Some(filter) = &self.filter {
+            let planner = Planner::new(self.schema.clone());
+            let expr = planner.parse_filter(filter)?;
+            let filter_columns = Planner::column_names_in_expr(&expr);
+            if filter_columns
+                .iter()
+                .all(|c| dataset_schema.field(c).is_some())
+            {
+                filters.push(filter.clone());
+            } else {
+                for column in filter_columns {
+                    let name = column.split('.').next().unwrap_or_default().to_string();
+                    if !scan_columns.contains(&name) {
+                        scan_columns.push(name);
+                    }
+                }
+                post_filter = Some(expr);
+            }
+        }
+        let scan_schema = Arc::new(self.schema.project(&self.column_indices(&scan_columns))?);
+        let post_filter = post_filter
+            .map(|expr| {
+                let planner = Planner::new(scan_schema.clone());
+                planner.create_physical_expr(&planner.optimize_expr(expr)?)
+            })
+            .transpose()?;
+
+        let mut member_columns = scan_columns
+            .iter()
+            .filter(|c| dataset_schema.field(c).is_some())
+            .collect::<Vec<_>>();
+        if member_columns.is_empty() {
+            // None of the columns are in this dataset, but its rows still count.
+            member_columns.extend(dataset_schema.fields.first().map(|f| &f.name));
+        }
+        let mut scanner = member.dataset.scan();
+        scanner.project(&member_columns)?;
+        if !filters.is_empty() {
+            let filter = filters
+                .iter()
+                .map(|f| format!("({})", f))
+                .collect::<Vec<_>>()
+                .join(" AND ");
+            scanner.filter(&filter)?;
+        }
+        if let Some(batch_size) = self.batch_size {
+            scanner.batch_size(batch_size);
+        }
+        Ok(MemberScan {
+            scanner,
+            post_filter,
+            scan_schema,
+            output_schema,
+        })
+    }
+
+    /// Create a stream of the rows of all the datasets, in the order of the datasets.
+    pub async fn try_into_stream(&self) -> Result<DatasetRecordBatchStream> {
+        let scans = self
+            .members
+            .iter()
+            .map(|member| self.plan_member(member))
+            .collect::<Result<Vec<_>>>()?;
+        let batches = stream::iter(scans)
+            .then(|scan| async move {
+                let MemberScan {
+                    scanner,
+                    post_filter,
+                    scan_schema,
+                    output_schema,
+                } = scan;
+                let batches = scanner.try_into_stream().await?;
+                Ok::<_, Error>(batches.map(move |batch| {
+                    let batch = conform_batch(&batch?, &scan_schema)?;
+                    let batch = match &post_filter {
+                        Some(expr) => {
+                            let mask = expr.evaluate(&batch)?.into_array(batch.num_rows());
+                            filter_record_batch(&batch, mask.as_boolean())?
+                        }
+                        None => batch,
+                    };
+                    conform_batch(&batch, &output_schema)
+                }))
+            })
+            .try_flatten();
+        Ok(DatasetRecordBatchStream::new(Box::pin(
+            RecordBatchStreamAdapter::new(self.schema()?, batches.map_err(DataFusionError::from)),
+        )))
+    }
+
+    /// Count the rows of all the datasets which match the filters.
+    pub async fn count_rows(&self) -> Result<u64> {
+        let mut count = 0;
+        for member in &self.members {
+            let scan = self.plan_member(member)?;
+            if scan.post_filter.is_none() {
+                count += scan.scanner.count_rows().await?;
+            } else {
+                let mut scanner = self.clone();
+                scanner.members = vec![member.clone()];
+                count += scanner
+                    .try_into_stream()
+                    .await?
+                    .try_fold(0, |n, batch| async move {
+                        Ok::<_, Error>(n + batch.num_rows() as u64)
+                    })
+                    .await?;
+            }
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Int32Array, RecordBatchIterator, StringArray};
+    use arrow_schema::DataType;
+    use tempfile::tempdir;
+
+    async fn create_dataset(uri: &str, batch: RecordBatch) -> Dataset {
+        let schema = batch.schema();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema);
+        Dataset::write(reader, uri, None).await.unwrap()
+    }
+
+    async fn collect(scanner: &UnionScanner) -> Vec<RecordBatch> {
+        scanner
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+    }
+
+    fn num_rows(batches: &[RecordBatch]) -> usize {
+        batches.iter().map(|b| b.num_rows()).sum()
+    }
+
+    #[tokio::test]
+    async fn test_union_scan() {
+        let test_dir = tempdir().unwrap();
+        let uri = test_dir.path().to_str().unwrap();
+
+        let day1 = create_dataset(
+            &format!("{}/day1", uri),
+            RecordBatch::try_new(
+                Arc::new(ArrowSchema::new(vec![
+                    ArrowField::new("id", DataType::Int32, false),
+                    ArrowField::new("tenant", DataType::Utf8, false),
+                ])),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(0..10)),
+                    Arc::new(StringArray::from_iter_values((0..10).map(|i| {
+                        if i % 2 == 0 {
+                            "a"
+                        } else {
+                            "b"
+                        }
+                    }))),
+                ],
+            )
+            .unwrap(),
+        )
+        .await;
+        // The second day has a new column and no tenant.
+        let day2 = create_dataset(
+            &format!("{}/day2", uri),
+            RecordBatch::try_new(
+                Arc::new(ArrowSchema::new(vec![
+                    ArrowField::new("score", DataType::Int32, false),
+                    ArrowField::new("id", DataType::Int32, false),
+                ])),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(100..110)),
+                    Arc::new(Int32Array::from_iter_values(10..20)),
+                ],
+            )
+            .unwrap(),
+        )
+        .await;
+
+        let union = UnionDataset::try_new([
+            UnionMember::new(day1).with_filter("tenant = 'a'"),
+            UnionMember::new(day2),
+        ])
+        .unwrap();
+        assert_eq!(
+            union.schema().as_ref(),
+            &ArrowSchema::new(vec![
+                ArrowField::new("id", DataType::Int32, false),
+                ArrowField::new("tenant", DataType::Utf8, true),
+                ArrowField::new("score", DataType::Int32, true),
+            ])
+        );
+        assert_eq!(union.count_rows().await.unwrap(), 15);
+
+        let batches = collect(&union.scan()).await;
+        assert_eq!(num_rows(&batches), 15);
+        let batch = arrow_select::concat::concat_batches(union.schema(), &batches).unwrap();
+        assert_eq!(
+            batch.column_by_name("id").unwrap().as_ref(),
+            &Int32Array::from_iter_values((0..10).step_by(2).chain(10..20))
+        );
+        assert_eq!(batch.column_by_name("tenant").unwrap().null_count(), 10);
+        assert_eq!(batch.column_by_name("score").unwrap().null_count(), 5);
+
+        // A filter on a column of both datasets is pushed down.
+        let mut scanner = union.scan();
+        scanner
+            .project(&["score"])
+            .unwrap()
+            .filter("id >= 8")
+            .unwrap();
+        assert_eq!(scanner.schema().unwrap().fields().len(), 1);
+        let batches = collect(&scanner).await;
+        assert_eq!(num_rows(&batches), 11);
+        assert_eq!(batches[0].schema(), scanner.schema().unwrap());
+        assert_eq!(scanner.count_rows().await.unwrap(), 11);
+
+        // A filter on a column missing from a dataset sees nulls in it.
+        let mut scanner = union.scan();
+        scanner
+            .project(&["id"])
+            .unwrap()
+            .filter("score > 104")
+            .unwrap();
+        let batches = collect(&scanner).await;
+        assert_eq!(num_rows(&batches), 5);
+        assert_eq!(scanner.count_rows().await.unwrap(), 5);
+
+        let mut scanner = union.scan();
+        scanner.filter("tenant IS NULL OR id = 0").unwrap();
+        assert_eq!(scanner.count_rows().await.unwrap(), 11);
+
+        assert!(union.scan().project(&["missing"]).is_err());
+        assert!(matches!(
+            union.scan().filter("missing > 1"),
+            Err(Error::InvalidInput { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_union_incompatible_schemas() {
+        let test_dir = tempdir().unwrap();
+        let uri = test_dir.path().to_str().unwrap();
+
+        let int_id = create_dataset(
+            &format!("{}/int", uri),
+            RecordBatch::try_new(
+                Arc::new(ArrowSchema::new(vec![ArrowField::new(
+                    "id",
+                    DataType::Int32,
+                    false,
+                )])),
+                vec![Arc::new(Int32Array::from_iter_values(0..10))],
+            )
+            .unwrap(),
+        )
+        .await;
+        let string_id = create_dataset(
+            &format!("{}/string", uri),
+            RecordBatch::try_new(
+                Arc::new(ArrowSchema::new(vec![ArrowField::new(
+                    "id",
+                    DataType::Utf8,
+                    false,
+                )])),
+                vec![Arc::new(StringArray::from_iter_values(["a", "b"]))],
+            )
+            .unwrap(),
+        )
+        .await;
+
+        assert!(matches!(
+            UnionDataset::try_new([int_id.clone(), string_id]),
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            UnionDataset::try_new([UnionMember::new(int_id).with_filter("x = 1")]),
+            Err(Error::InvalidInput { .. })
+        ));
+        assert!(UnionDataset::try_new(Vec::<Dataset>::new()).is_err());
+    }
+}
@@ -0,0 +1,435 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Update the rows matching a predicate with SQL expressions, or delete rows by
+//! their addresses.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use arrow_array::{cast::AsArray, types::UInt64Type, RecordBatch};
+use arrow_cast::cast;
+use arrow_schema::Schema as ArrowSchema;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::{
+    stream::RecordBatchStreamAdapter, PhysicalExpr, SendableRecordBatchStream,
+};
+use futures::{StreamExt, TryStreamExt};
+use lance_core::ROW_ID;
+use snafu::{location, Location};
+
+use super::fragment::FileFragment;
+use super::rowids::{assign_row_ids, stable_row_ids};
+use super::transaction::Operation;
+use super::write::write_fragments_internal;
+use super::{Dataset, WriteParams};
+use crate::format::{Fragment, RowAddress};
+use crate::io::exec::Planner;
+use crate::{Error, Result};
+
+/// Delete the rows at the given offsets of each fragment, returning the updated
+/// fragments and the IDs of the fragments with no rows left.
+pub(super) async fn apply_deletions(
+    dataset: &Dataset,
+    deletions: BTreeMap<u64, Vec<u32>>,
+) -> Result<(Vec<Fragment>, Vec<u64>)> {
+    let mut removed_fragment_ids = Vec::new();
+    let updated_fragments: Vec<Fragment> = futures::stream::iter(deletions)
+        .map(|(fragment_id, local_row_ids)| async move {
+            let fragment =
+                dataset
+                    .get_fragment(fragment_id as usize)
+                    .ok_or_else(|| Error::Internal {
+                        message: format!("Fragment {} does not exist", fragment_id),
+                        location: location!(),
+                    })?;
+            let updated = fragment.extend_deletions(local_row_ids).await?;
+            Ok::<_, Error>((fragment_id, updated.map(FileFragment::into)))
+        })
+        .buffer_unordered(num_cpus::get())
+        .try_filter_map(|(fragment_id, updated)| {
+            if updated.is_none() {
+                removed_fragment_ids.push(fragment_id);
+            }
+            futures::future::ready(Ok(updated))
+        })
+        .try_collect()
+        .await?;
+    Ok((updated_fragments, removed_fragment_ids))
+}
+
+/// Delete the rows at the addresses `row_ids`, as returned by a scan with row ids,
+/// returning the operation to commit, or None if there is no row to delete.
+///
+/// No predicate is evaluated: the addresses are added to the deletion vectors of
+/// their fragments directly. Rows that are already deleted are ignored.
+pub(super) async fn delete_rows(dataset: &Dataset, row_ids: &[u64]) -> Result<Option<Operation>> {
+    let mut deletions = BTreeMap::<u64, Vec<u32>>::new();
+    for row_id in row_ids {
+        let address = RowAddress::new_from_id(*row_id);
+        deletions
+            .entry(address.fragment_id() as u64)
+            .or_default()
+            .push(address.row_id());
+    }
+    if deletions.is_empty() {
+        return Ok(None);
+    }
+    for (fragment_id, offsets) in &deletions {
+        let fragment = dataset.get_fragment(*fragment_id as usize).ok_or_else(|| {
+            Error::invalid_input(
+                format!(
+                    "Fragment {} does not exist in version {}",
+                    fragment_id,
+                    dataset.version().version
+                ),
+                location!(),
+            )
+        })?;
+        let physical_rows = fragment.physical_rows().await?;
+        if let Some(offset) = offsets
+            .iter()
+            .find(|offset| **offset as usize >= physical_rows)
+        {
+            return Err(Error::invalid_input(
+                format!(
+                    "Row {} does not exist in fragment {} with {} rows",
+                    offset, fragment_id, physical_rows
+                ),
+                location!(),
+            ));
+        }
+    }
+
+    let (mut updated_fragments, deleted_fragment_ids) = apply_deletions(dataset, deletions).await?;
+    // The fragments whose rows were all already deleted are unchanged.
+    updated_fragments.retain(|fragment| !dataset.manifest.fragments.contains(fragment));
+    if updated_fragments.is_empty() && deleted_fragment_ids.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(Operation::Delete {
+        updated_fragments,
+        deleted_fragment_ids,
+        predicate: format!("_rowid IN <{} rows>", row_ids.len()),
+    }))
+}
+
+/// Replace the rows at the given offsets of each fragment with the rows of the
+/// `new_fragments`, which have already been written.
+///
+/// `fields_modified` are the ids of the fields whose values changed, if the new
+/// fragments only hold the replaced rows, or empty.
+pub(super) async fn replace_rows(
+    dataset: &Dataset,
+    deletions: BTreeMap<u64, Vec<u32>>,
+    new_fragments: Vec<Fragment>,
+    fields_modified: Vec<i32>,
+) -> Result<Operation> {
+    let (updated_fragments, removed_fragment_ids) = apply_deletions(dataset, deletions).await?;
+    Ok(Operation::Update {
+        removed_fragment_ids,
+        updated_fragments,
+        new_fragments,
+        fields_modified,
+    })
+}
+
+/// Set the columns of the rows matching `predicate`, or of all rows, to the values
+/// of the SQL expressions of `updates`, returning the operation to commit.
+///
+/// The updated rows are deleted from their fragments and written to new ones, so
+/// only the fragments with matching rows are modified.
+pub(super) async fn update(
+    dataset: &Dataset,
+    predicate: Option<&str>,
+    updates: &[(&str, &str)],
+) -> Result<Option<Operation>> {
+    if updates.is_empty() {
+        return Err(Error::invalid_input(
+            "Update requires at least one column to set",
+            location!(),
+        ));
+    }
+    let arrow_schema = Arc::new(ArrowSchema::from(dataset.schema()));
+    let planner = Planner::new(arrow_schema.clone());
+    let mut exprs: HashMap<usize, Arc<dyn PhysicalExpr>> = HashMap::new();
+    for (column, expr) in updates {
+        let Ok(idx) = arrow_schema.index_of(column) else {
+            return Err(Error::invalid_input(
+                format!("Column {} does not exist in the dataset", column),
+                location!(),
+            ));
+        };
+        let expr = planner.optimize_expr(planner.parse_expr(expr)?)?;
+        if exprs
+            .insert(idx, planner.create_physical_expr(&expr)?)
+            .is_some()
+        {
+            return Err(Error::invalid_input(
+                format!("Column {} is set more than once", column),
+                location!(),
+            ));
+        }
+    }
+
+    let mut scanner = dataset.scan();
+    scanner.with_row_id();
+    if let Some(predicate) = predicate {
+        scanner.filter(predicate)?;
+    }
+    // The row ids of the updated rows, in the order they are written.
+    let updated_row_ids = Arc::new(Mutex::new(Vec::new()));
+    let batches = {
+        let arrow_schema = arrow_schema.clone();
+        let updated_row_ids = updated_row_ids.clone();
+        scanner
+            .try_into_stream()
+            .await?
+            .map(move |batch| -> Result<RecordBatch> {
+                let batch = batch?;
+                updated_row_ids
+                    .lock()
+                    .unwrap()
+                    .extend_from_slice(batch[ROW_ID].as_primitive::<UInt64Type>().values());
+
+                let batch = RecordBatch::try_new(
+                    arrow_schema.clone(),
+                    arrow_schema
+                        .fields()
+                        .iter()
+                        .map(|field| batch[field.name()].clone())
+                        .collect(),
+                )?;
+                let columns = arrow_schema
+                    .fields()
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, field)| match exprs.get(&idx) {
+                        Some(expr) => {
+                            let values = expr.evaluate(&batch)?.into_array(batch.num_rows());
+                            Ok(cast(&values, field.data_type())?)
+                        }
+                        None => Ok(batch.column(idx).clone()),
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(RecordBatch::try_new(arrow_schema.clone(), columns)?)
+            })
+    };
+    let stream = Box::pin(RecordBatchStreamAdapter::new(
+        arrow_schema,
+        batches.map_err(DataFusionError::from),
+    )) as SendableRecordBatchStream;
+
+    // The updated rows are written to the partitions of their new values. With stable
+    // row ids, they must be written in the order of their row ids, so they are
+    // written unpartitioned, to fragments which are always scanned.
+    let write_params = WriteParams {
+        partition_columns: if dataset.has_stable_row_ids() {
+            Vec::new()
+        } else {
+            dataset.partition_columns().to_vec()
+        },
+        ..Default::default()
+    };
+    let mut new_fragments = write_fragments_internal(
+        dataset.object_store.clone(),
+        &dataset.base,
+        dataset.schema(),
+        stream,
+        write_params,
+    )
+    .await?;
+
+    let updated_row_ids = std::mem::take(&mut *updated_row_ids.lock().unwrap());
+    if updated_row_ids.is_empty() {
+        return Ok(None);
+    }
+    // The updated rows keep their stable row ids.
+    if dataset.has_stable_row_ids() {
+        let stable_row_ids = stable_row_ids(&dataset.manifest.fragments, &updated_row_ids)?;
+        assign_row_ids(&mut new_fragments, &stable_row_ids)?;
+    }
+    let mut deletions = BTreeMap::<u64, Vec<u32>>::new();
+    for row_id in updated_row_ids {
+        deletions
+            .entry(row_id >> 32)
+            .or_default()
+            .push(row_id as u32);
+    }
+    let columns = updates
+        .iter()
+        .map(|(column, _)| *column)
+        .collect::<Vec<_>>();
+    let fields_modified = dataset.schema().project(&columns)?.field_ids();
+    replace_rows(dataset, deletions, new_fragments, fields_modified)
+        .await
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Float64Array, Int32Array, RecordBatchIterator, StringArray};
+    use arrow_schema::{DataType, Field as ArrowField};
+    use tempfile::tempdir;
+
+    async fn collect(dataset: &Dataset) -> RecordBatch {
+        let batches = dataset
+            .scan()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = arrow_select::concat::concat_batches(&batches[0].schema(), &batches).unwrap();
+        let indices = arrow_ord::sort::sort_to_indices(&batch["i"], None, None).unwrap();
+        let columns = batch
+            .columns()
+            .iter()
+            .map(|column| arrow_select::take::take(column, &indices, None).unwrap())
+            .collect();
+        RecordBatch::try_new(batch.schema(), columns).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_update() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("price", DataType::Float64, true),
+            ArrowField::new("name", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(0..300)),
+                Arc::new(Float64Array::from_iter_values((0..300).map(|i| i as f64))),
+                Arc::new(StringArray::from_iter_values(
+                    (0..300).map(|i| format!("name-{i}")),
+                )),
+            ],
+        )
+        .unwrap();
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        let version = dataset.version().version;
+
+        dataset
+            .update(
+                Some("i >= 150 AND i < 160"),
+                &[("price", "price * 2"), ("name", "'updated'")],
+            )
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, version + 1);
+        // Only the fragment with matching rows is modified.
+        let fragments = dataset.get_fragments();
+        assert_eq!(fragments.len(), 4);
+        assert!(fragments[0].metadata.deletion_file.is_none());
+        assert!(fragments[1].metadata.deletion_file.is_some());
+        assert!(fragments[2].metadata.deletion_file.is_none());
+        assert_eq!(fragments[3].count_rows().await.unwrap(), 10);
+
+        let batch = collect(&dataset).await;
+        assert_eq!(batch.num_rows(), 300);
+        let prices = batch["price"].as_primitive::<arrow_array::types::Float64Type>();
+        let names = batch["name"].as_string::<i32>();
+        assert_eq!(prices.value(149), 149.0);
+        assert_eq!(prices.value(150), 300.0);
+        assert_eq!(names.value(150), "updated");
+        assert_eq!(names.value(160), "name-160");
+
+        // The values are cast to the type of the column.
+        dataset.update(None, &[("i", "i + 1000")]).await.unwrap();
+        let batch = collect(&dataset).await;
+        let ids = batch["i"].as_primitive::<arrow_array::types::Int32Type>();
+        assert_eq!(ids.values().to_vec(), (1000..1300).collect::<Vec<_>>());
+
+        // Nothing matches, so nothing is committed.
+        let version = dataset.version().version;
+        dataset
+            .update(Some("i < 0"), &[("price", "0")])
+            .await
+            .unwrap();
+        assert_eq!(dataset.version().version, version);
+
+        assert!(dataset.update(None, &[("x", "1")]).await.is_err());
+        assert!(dataset.update(None, &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_rows() {
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "i",
+            DataType::Int32,
+            true,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..300))],
+        )
+        .unwrap();
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        let version = dataset.version().version;
+
+        // Duplicates are ignored, and a fragment with no rows left is removed.
+        let mut row_ids = vec![
+            u64::from(RowAddress::new_from_parts(0, 5)),
+            u64::from(RowAddress::new_from_parts(0, 5)),
+            u64::from(RowAddress::new_from_parts(2, 99)),
+        ];
+        row_ids.extend((0..100).map(|offset| u64::from(RowAddress::new_from_parts(1, offset))));
+        dataset.delete_rows(&row_ids).await.unwrap();
+        assert_eq!(dataset.version().version, version + 1);
+        assert_eq!(dataset.count_rows().await.unwrap(), 198);
+        assert!(dataset.get_fragment(1).is_none());
+        let batch = collect(&dataset).await;
+        let ids = batch["i"].as_primitive::<arrow_array::types::Int32Type>();
+        assert_eq!(
+            ids.values().to_vec(),
+            (0..5).chain(6..100).chain(200..299).collect::<Vec<_>>()
+        );
+
+        // Deleting rows that are already deleted, or no rows, commits nothing.
+        let version = dataset.version().version;
+        dataset.delete_rows(&row_ids[..1]).await.unwrap();
+        dataset.delete_rows(&[]).await.unwrap();
+        assert_eq!(dataset.version().version, version);
+
+        // Addresses outside of the dataset are rejected.
+        let missing_fragment = u64::from(RowAddress::new_from_parts(1, 0));
+        assert!(dataset.delete_rows(&[missing_fragment]).await.is_err());
+        let missing_row = u64::from(RowAddress::new_from_parts(0, 100));
+        assert!(dataset.delete_rows(&[missing_row]).await.is_err());
+        assert_eq!(dataset.count_rows().await.unwrap(), 198);
+    }
+}
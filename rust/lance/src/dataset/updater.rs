@@ -23,7 +23,9 @@ use lance_core::{
 use snafu::{location, Location};
 use uuid::Uuid;
 
+use super::blob::BlobWriter;
 use super::fragment::FragmentReader;
+use super::write::file_writer_options;
 use super::Dataset;
 use crate::dataset::FileFragment;
 use crate::format::Fragment;
@@ -42,6 +44,9 @@ pub struct Updater {
 
     writer: Option<FileWriter>,
 
+    /// The writer of the blob file of the new data file, if it has blob columns.
+    blob_writer: Option<BlobWriter>,
+
     output_schema: Option<Schema>,
 
     batch_id: usize,
@@ -63,6 +68,7 @@ impl Updater {
             reader,
             last_input: None,
             writer: None,
+            blob_writer: None,
             output_schema: None,
             batch_id: 0,
             start_row_id: 0,
@@ -120,12 +126,19 @@ impl Updater {
         self.fragment.metadata.add_file(&file_name, &schema);
 
         let full_path = self.fragment.dataset().data_dir().child(file_name.as_str());
+        let options = file_writer_options(&schema);
+        self.blob_writer = BlobWriter::try_new(
+            self.fragment.dataset().object_store.as_ref(),
+            &full_path,
+            &schema,
+        )
+        .await?;
 
         FileWriter::try_new(
             self.fragment.dataset().object_store.as_ref(),
             &full_path,
             schema,
-            &Default::default(),
+            &options,
         )
         .await
     }
@@ -182,6 +195,10 @@ impl Updater {
             });
         }
 
+        let batch = match self.blob_writer.as_mut() {
+            Some(blob_writer) => blob_writer.write(&batch).await?,
+            None => batch,
+        };
         writer.write(&[batch]).await?;
 
         self.start_row_id += row_id_stride;
@@ -191,6 +208,9 @@ impl Updater {
 
     /// Finish updating this fragment, and returns the updated [`Fragment`].
     pub async fn finish(&mut self) -> Result<Fragment> {
+        if let Some(blob_writer) = self.blob_writer.as_mut() {
+            blob_writer.finish().await?;
+        }
         if let Some(writer) = self.writer.as_mut() {
             writer.finish().await?;
         }
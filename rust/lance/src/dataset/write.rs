@@ -15,27 +15,37 @@
 // specific language governing permissions and limitations
 // under the License.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use arrow_array::RecordBatchReader;
+use arrow_array::{cast::AsArray, Array, RecordBatch, RecordBatchReader};
+use arrow_buffer::NullBuffer;
+use arrow_schema::{DataType, Schema as ArrowSchema};
 use datafusion::error::DataFusionError;
 use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::SendableRecordBatchStream;
 use futures::{StreamExt, TryStreamExt};
 use lance_core::{
-    datatypes::Schema,
+    datatypes::{Field, Schema},
     format::Fragment,
     io::{
+        commit::CommitConfig,
         object_store::{ObjectStore, ObjectStoreParams},
+        writer::{can_collect_statistics, FileWriterOptions},
         FileWriter,
     },
     Error, Result,
 };
 use lance_datafusion::chunker::chunk_stream;
 use object_store::path::Path;
+use snafu::{location, Location};
 use tracing::instrument;
 use uuid::Uuid;
 
+use super::blob::BlobWriter;
+use super::partition::{
+    check_partition_columns, partition_dir, split_by_partition, PartitionValues,
+};
 use super::progress::{NoopFragmentWriteProgress, WriteFragmentProgress};
 use super::DATA_DIR;
 
@@ -78,6 +88,42 @@ pub struct WriteParams {
     pub store_params: Option<ObjectStoreParams>,
 
     pub progress: Arc<dyn WriteFragmentProgress>,
+
+    /// The number of times to retry the commit after a concurrent commit.
+    ///
+    /// If the concurrent commit is compatible with this one, e.g., two appends, this
+    /// one is rebased on top of it and retried. Otherwise, the write fails with
+    /// [`Error::CommitConflict`](crate::Error::CommitConflict).
+    pub commit_retries: u32,
+
+    /// Key-value metadata recorded in the transaction of the commit, e.g., the author
+    /// of the write. See [`Dataset::transactions`](crate::Dataset::transactions).
+    pub commit_properties: HashMap<String, String>,
+
+    /// Entries of the dataset config set in the same version as the written data,
+    /// e.g., the checkpoint of a pipeline, so that the checkpoint is moved forward
+    /// atomically with the data. See [`Dataset::config`](crate::Dataset::config).
+    pub config_upserts: HashMap<String, String>,
+
+    /// Whether a new dataset assigns stable row ids to its rows. Only used when
+    /// creating or overwriting a dataset.
+    ///
+    /// Unlike the row id returned by scans, which is the address of the row in its
+    /// fragment, the stable row id of a row does not change when its fragment is
+    /// rewritten by compaction or when the row is updated. See
+    /// [`Dataset::take_by_stable_row_ids`](crate::Dataset::take_by_stable_row_ids).
+    /// The indices are keyed by the stable row ids, so compaction does not remap them,
+    /// and DiskANN indices are not supported.
+    pub enable_stable_row_ids: bool,
+
+    /// The columns to partition the data files by, Hive-style. Only used when
+    /// creating or overwriting a dataset: appends are partitioned like the dataset.
+    ///
+    /// The rows with the same values of these columns are written to fragments in the
+    /// directory `data/{column}={value}/...`, and the fragments record the values, so
+    /// that a filtered scan skips the partitions which can not match its filter. A file
+    /// is open for each partition being written.
+    pub partition_columns: Vec<String>,
 }
 
 impl Default for WriteParams {
@@ -91,6 +137,11 @@ impl Default for WriteParams {
             mode: WriteMode::Create,
             store_params: None,
             progress: Arc::new(NoopFragmentWriteProgress::new()),
+            commit_retries: CommitConfig::default().num_retries,
+            commit_properties: HashMap::new(),
+            config_upserts: HashMap::new(),
+            enable_stable_row_ids: false,
+            partition_columns: Vec::new(),
         }
     }
 }
@@ -162,52 +213,195 @@ pub async fn write_fragments_internal(
     data: SendableRecordBatchStream,
     mut params: WriteParams,
 ) -> Result<Vec<Fragment>> {
+    check_partition_columns(schema, &params.partition_columns)?;
     // Make sure the max rows per group is not larger than the max rows per file
     params.max_rows_per_group = std::cmp::min(params.max_rows_per_group, params.max_rows_per_file);
     let mut buffered_reader = chunk_stream(data, params.max_rows_per_group);
 
     let writer_generator = WriterGenerator::new(object_store, base_dir, schema);
-    let mut writer: Option<FileWriter> = None;
-    let mut num_rows_in_current_file = 0;
+    // The open file of each partition, or of the only partition if the data is not
+    // partitioned, with the position of its fragment.
+    let mut writers: HashMap<PartitionValues, OpenFile> = HashMap::new();
     let mut fragments = Vec::new();
     while let Some(batch_chunk) = buffered_reader.next().await {
-        let batch_chunk = batch_chunk?;
-
-        if writer.is_none() {
-            let (new_writer, new_fragment) = writer_generator.new_writer().await?;
-            // rustc has a hard time analyzing the lifetime of the &str returned
-            // by multipart_id(), so we convert it to an owned value here.
-            let multipart_id = new_writer.multipart_id().to_string();
-            params.progress.begin(&new_fragment, &multipart_id).await?;
-            writer = Some(new_writer);
-            fragments.push(new_fragment);
-        }
+        let batch_chunk = batch_chunk?
+            .into_iter()
+            .map(|batch| check_nullability(batch, schema))
+            .collect::<Result<Vec<_>>>()?;
+        let partitions = if params.partition_columns.is_empty() {
+            vec![(Vec::new(), batch_chunk)]
+        } else {
+            let mut partitions: Vec<(PartitionValues, Vec<RecordBatch>)> = Vec::new();
+            for batch in batch_chunk.iter() {
+                for (values, batch) in split_by_partition(batch, &params.partition_columns)? {
+                    match partitions.iter_mut().find(|(v, _)| v == &values) {
+                        Some((_, batches)) => batches.push(batch),
+                        None => partitions.push((values, vec![batch])),
+                    }
+                }
+            }
+            partitions
+        };
 
-        writer.as_mut().unwrap().write(&batch_chunk).await?;
-        for batch in batch_chunk {
-            num_rows_in_current_file += batch.num_rows();
-        }
+        for (values, batch_chunk) in partitions {
+            if !writers.contains_key(&values) {
+                let dir = partition_dir(&params.partition_columns, &values);
+                let (new_writer, mut new_fragment) = writer_generator.new_writer(&dir).await?;
+                new_fragment.partition_values = values.clone();
+                // rustc has a hard time analyzing the lifetime of the &str returned
+                // by multipart_id(), so we convert it to an owned value here.
+                let multipart_id = new_writer.0.multipart_id().to_string();
+                params.progress.begin(&new_fragment, &multipart_id).await?;
+                writers.insert(
+                    values.clone(),
+                    OpenFile {
+                        writers: new_writer,
+                        fragment_idx: fragments.len(),
+                        num_rows: 0,
+                    },
+                );
+                fragments.push(new_fragment);
+            }
 
-        if num_rows_in_current_file >= params.max_rows_per_file
-            || writer.as_mut().unwrap().tell().await? >= params.max_bytes_per_file
-        {
-            let num_rows = writer.take().unwrap().finish().await?;
-            debug_assert_eq!(num_rows, num_rows_in_current_file);
-            params.progress.complete(fragments.last().unwrap()).await?;
-            fragments.last_mut().unwrap().physical_rows = Some(num_rows);
-            num_rows_in_current_file = 0;
+            let file = writers.get_mut(&values).unwrap();
+            let (file_writer, blob_writer) = &mut file.writers;
+            let batch_chunk = match blob_writer {
+                Some(blob_writer) => blob_writer.write_batches(&batch_chunk).await?,
+                None => batch_chunk,
+            };
+            file_writer.write(&batch_chunk).await?;
+            for batch in batch_chunk {
+                file.num_rows += batch.num_rows();
+            }
+
+            // The values of the blob columns count towards the size of the data file.
+            let mut num_bytes = file_writer.tell().await?;
+            if let Some(blob_writer) = blob_writer {
+                num_bytes += blob_writer.tell().await?;
+            }
+            if file.num_rows >= params.max_rows_per_file || num_bytes >= params.max_bytes_per_file {
+                let file = writers.remove(&values).unwrap();
+                let num_rows = finish_writer(file.writers).await?;
+                debug_assert_eq!(num_rows, file.num_rows);
+                let fragment = &mut fragments[file.fragment_idx];
+                params.progress.complete(fragment).await?;
+                fragment.physical_rows = Some(num_rows);
+            }
         }
     }
 
-    // Complete the final writer
-    if let Some(mut writer) = writer.take() {
-        let num_rows = writer.finish().await?;
-        fragments.last_mut().unwrap().physical_rows = Some(num_rows);
+    // Complete the remaining writers
+    for file in writers.into_values() {
+        let num_rows = finish_writer(file.writers).await?;
+        fragments[file.fragment_idx].physical_rows = Some(num_rows);
     }
 
     Ok(fragments)
 }
 
+/// A data file being written, with its blob file if any.
+struct OpenFile {
+    writers: (FileWriter, Option<BlobWriter>),
+    /// The position of the fragment of the file in the written fragments.
+    fragment_idx: usize,
+    num_rows: usize,
+}
+
+async fn finish_writer(
+    (mut file_writer, blob_writer): (FileWriter, Option<BlobWriter>),
+) -> Result<usize> {
+    if let Some(mut blob_writer) = blob_writer {
+        blob_writer.finish().await?;
+    }
+    file_writer.finish().await
+}
+
+/// Check that the non-nullable fields of `schema`, including the fields of structs, have
+/// no null values in the batch. The top-level columns of the batch are then marked as
+/// non-nullable like the fields, so that nullable data without null values can be
+/// written to non-nullable columns.
+fn check_nullability(batch: RecordBatch, schema: &Schema) -> Result<RecordBatch> {
+    let batch_schema = batch.schema();
+    let mut fields = Vec::with_capacity(batch_schema.fields().len());
+    for (batch_field, array) in batch_schema.fields().iter().zip(batch.columns()) {
+        match schema.field(batch_field.name()) {
+            Some(field) => {
+                check_not_null(field, &field.name, array.as_ref(), None)?;
+                fields.push(batch_field.as_ref().clone().with_nullable(field.nullable));
+            }
+            None => fields.push(batch_field.as_ref().clone()),
+        }
+    }
+    if fields
+        .iter()
+        .zip(batch_schema.fields())
+        .all(|(field, batch_field)| field.is_nullable() == batch_field.is_nullable())
+    {
+        return Ok(batch);
+    }
+    let schema = ArrowSchema::new_with_metadata(fields, batch_schema.metadata().clone());
+    Ok(RecordBatch::try_new(
+        Arc::new(schema),
+        batch.columns().to_vec(),
+    )?)
+}
+
+/// Check that `array` has no null values if `field` is not nullable, except where its
+/// parent struct is null, i.e., not valid in `parent_validity`, and recurse into the
+/// fields of structs.
+fn check_not_null(
+    field: &Field,
+    path: &str,
+    array: &dyn Array,
+    parent_validity: Option<&NullBuffer>,
+) -> Result<()> {
+    if !field.nullable {
+        let null_count = match (array.nulls(), parent_validity) {
+            (Some(nulls), Some(validity)) => (validity.inner() & &!nulls.inner()).count_set_bits(),
+            (Some(nulls), None) => nulls.null_count(),
+            (None, _) => 0,
+        };
+        if null_count > 0 {
+            return Err(Error::invalid_input(
+                format!(
+                    "Column {} is not nullable, but {} of its values are null",
+                    path, null_count
+                ),
+                location!(),
+            ));
+        }
+    }
+    if let DataType::Struct(_) = array.data_type() {
+        let array = array.as_struct();
+        let validity = NullBuffer::union(parent_validity, array.nulls());
+        for child in field.children.iter() {
+            if let Some(child_array) = array.column_by_name(&child.name) {
+                check_not_null(
+                    child,
+                    &format!("{}.{}", path, child.name),
+                    child_array.as_ref(),
+                    validity.as_ref(),
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The options of the writers of the data files, which collect the statistics of the
+/// top level columns, so that a scan can skip the fragments which can not match its filter.
+/// The blob columns have no statistics, since only their references are in the data files.
+pub fn file_writer_options(schema: &Schema) -> FileWriterOptions {
+    FileWriterOptions {
+        collect_stats_for_fields: schema
+            .fields
+            .iter()
+            .filter(|field| !field.is_blob() && can_collect_statistics(&field.data_type()))
+            .map(|field| field.id)
+            .collect(),
+    }
+}
+
 /// Creates new file writers for a given dataset.
 struct WriterGenerator {
     object_store: Arc<ObjectStore>,
@@ -224,23 +418,37 @@ impl WriterGenerator {
         }
     }
 
-    pub async fn new_writer(&self) -> Result<(FileWriter, Fragment)> {
-        let data_file_path = format!("{}.lance", Uuid::new_v4());
+    /// Create the writer of a new data file in the directory `dir`, relative to the
+    /// data directory, if it is not empty.
+    pub async fn new_writer(
+        &self,
+        dir: &str,
+    ) -> Result<((FileWriter, Option<BlobWriter>), Fragment)> {
+        let file_name = format!("{}.lance", Uuid::new_v4());
+        let data_file_path = if dir.is_empty() {
+            file_name
+        } else {
+            format!("{}/{}", dir, file_name)
+        };
 
         // Use temporary ID 0; will assign ID later.
         let mut fragment = Fragment::new(0);
         fragment.add_file(&data_file_path, &self.schema);
 
-        let full_path = self.base_dir.child(DATA_DIR).child(data_file_path);
+        let full_path = data_file_path
+            .split('/')
+            .fold(self.base_dir.child(DATA_DIR), |path, part| path.child(part));
         let writer = FileWriter::try_new(
             self.object_store.as_ref(),
             &full_path,
             self.schema.clone(),
-            &Default::default(),
+            &file_writer_options(&self.schema),
         )
         .await?;
+        let blob_writer =
+            BlobWriter::try_new(self.object_store.as_ref(), &full_path, &self.schema).await?;
 
-        Ok((writer, fragment))
+        Ok(((writer, blob_writer), fragment))
     }
 }
 
@@ -375,4 +583,94 @@ mod tests {
         .unwrap();
         assert_eq!(fragments.len(), 2);
     }
+
+    #[tokio::test]
+    async fn test_write_not_null() {
+        use arrow_array::{RecordBatchIterator, StructArray};
+        use arrow_schema::{Field as ArrowField, Fields};
+
+        use crate::dataset::Dataset;
+
+        let test_dir = tempfile::tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = |nullable: bool, values: Vec<Option<i32>>| {
+            let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+                "i",
+                DataType::Int32,
+                nullable,
+            )]));
+            let batch =
+                RecordBatch::try_new(schema.clone(), vec![Arc::new(Int32Array::from(values))])
+                    .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema)
+        };
+
+        let mut dataset = Dataset::write(data(false, vec![Some(1), Some(2)]), test_uri, None)
+            .await
+            .unwrap();
+        assert!(!dataset.schema().field("i").unwrap().nullable);
+
+        // Nullable data without null values can be appended to a non-nullable column.
+        dataset
+            .append(data(true, vec![Some(3), Some(4)]), None)
+            .await
+            .unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 4);
+        assert!(!dataset.schema().field("i").unwrap().nullable);
+
+        let result = dataset
+            .append(data(true, vec![Some(5), None, None]), None)
+            .await;
+        match result {
+            Err(Error::InvalidInput { source, .. }) => assert_eq!(
+                source.to_string(),
+                "Column i is not nullable, but 2 of its values are null"
+            ),
+            _ => panic!("Expected an InvalidInput error, got {:?}", result),
+        }
+        assert_eq!(dataset.count_rows().await.unwrap(), 4);
+
+        // The nulls of a non-nullable field of a struct are allowed where the struct
+        // is null.
+        let schema = Schema::try_from(&ArrowSchema::new(vec![ArrowField::new(
+            "s",
+            DataType::Struct(Fields::from(vec![ArrowField::new(
+                "x",
+                DataType::Int32,
+                false,
+            )])),
+            true,
+        )]))
+        .unwrap();
+        let fields = Fields::from(vec![ArrowField::new("x", DataType::Int32, true)]);
+        let struct_array = StructArray::new(
+            fields.clone(),
+            vec![Arc::new(Int32Array::from(vec![Some(1), None, None]))],
+            Some(NullBuffer::from(vec![true, false, true])),
+        );
+        let batch_schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "s",
+            DataType::Struct(fields),
+            true,
+        )]));
+        let batch =
+            RecordBatch::try_new(batch_schema, vec![Arc::new(struct_array.clone())]).unwrap();
+        match check_nullability(batch, &schema) {
+            Err(Error::InvalidInput { source, .. }) => assert_eq!(
+                source.to_string(),
+                "Column s.x is not nullable, but 1 of its values are null"
+            ),
+            result => panic!("Expected an InvalidInput error, got {:?}", result),
+        }
+        let batch = RecordBatch::try_new(
+            Arc::new(ArrowSchema::new(vec![ArrowField::new(
+                "s",
+                struct_array.data_type().clone(),
+                true,
+            )])),
+            vec![Arc::new(struct_array.slice(0, 2))],
+        )
+        .unwrap();
+        assert!(check_nullability(batch, &schema).is_ok());
+    }
 }
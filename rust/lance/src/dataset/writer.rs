@@ -0,0 +1,391 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Incremental writes of batches which are not all available up front.
+
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use datafusion::error::DataFusionError;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use futures::{stream, Stream, StreamExt};
+use snafu::{location, Location};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio::task::JoinHandle;
+
+use super::{Dataset, WriteParams};
+use crate::datatypes::Schema;
+use crate::{Error, Result};
+
+/// The default limit of the memory of the batches pushed but not yet taken by the writer.
+pub const DEFAULT_MAX_BUFFERED_BYTES: usize = 64 * 1024 * 1024;
+
+/// Writes the batches pushed to it to a dataset, and commits them at [`Self::close`].
+///
+/// The batches are written in the background as they are pushed, and the fragments are
+/// flushed as the limits of [`WriteParams`], such as `max_rows_per_file` and
+/// `max_bytes_per_file`, are reached. [`Self::push`] waits while the batches which are
+/// not yet written exceed the buffer limit, so a fast producer is held back by the
+/// writer instead of accumulating batches in memory.
+///
+/// Nothing is committed if the writer is dropped without being closed.
+///
+/// ```rust,ignore
+/// let mut writer = DatasetWriter::new(uri, schema, Some(params));
+/// while let Some(batch) = source.next().await {
+///     writer.push(batch?).await?;
+/// }
+/// let dataset = writer.close().await?;
+/// ```
+pub struct DatasetWriter {
+    uri: String,
+    schema: SchemaRef,
+    params: WriteParams,
+    max_buffered_bytes: usize,
+    /// The background write, started by the first batch.
+    write: Option<BackgroundWrite>,
+    failed: bool,
+}
+
+struct BackgroundWrite {
+    sender: mpsc::UnboundedSender<Message>,
+    buffer: Arc<Semaphore>,
+    task: JoinHandle<Result<Dataset>>,
+}
+
+enum Message {
+    /// A batch, with the permits of its memory in the buffer.
+    Batch(RecordBatch, OwnedSemaphorePermit),
+    Close,
+}
+
+impl DatasetWriter {
+    /// Create a writer of batches with the `schema` to the dataset at `uri`, with the
+    /// same parameters as [`Dataset::write`].
+    pub fn new(uri: &str, schema: SchemaRef, params: Option<WriteParams>) -> Self {
+        Self {
+            uri: uri.to_string(),
+            schema,
+            params: params.unwrap_or_default(),
+            max_buffered_bytes: DEFAULT_MAX_BUFFERED_BYTES,
+            write: None,
+            failed: false,
+        }
+    }
+
+    /// Limit the memory of the batches pushed but not yet taken by the writer, by
+    /// default [`DEFAULT_MAX_BUFFERED_BYTES`]. A larger batch is pushed alone.
+    pub fn with_max_buffered_bytes(mut self, max_buffered_bytes: usize) -> Self {
+        self.max_buffered_bytes = max_buffered_bytes.max(1);
+        self
+    }
+
+    pub fn schema(&self) -> &SchemaRef {
+        &self.schema
+    }
+
+    /// Push a batch to write, waiting while the buffer is full.
+    ///
+    /// If the write has failed, its error is returned and the writer can not be used
+    /// anymore.
+    pub async fn push(&mut self, batch: RecordBatch) -> Result<()> {
+        self.check_schema(&batch)?;
+        if self.failed {
+            return Err(Error::invalid_input(
+                "the write of the dataset writer has failed",
+                location!(),
+            ));
+        }
+        let max_buffered_bytes = self.max_buffered_bytes;
+        let write = match self.write.take() {
+            Some(write) => write,
+            None => self.start(Some(&batch))?,
+        };
+
+        let num_bytes = batch
+            .get_array_memory_size()
+            .min(max_buffered_bytes)
+            .min(u32::MAX as usize) as u32;
+        let permit = write
+            .buffer
+            .clone()
+            .acquire_many_owned(num_bytes)
+            .await
+            .map_err(|err| Error::Internal {
+                message: format!("the write buffer was closed: {}", err),
+                location: location!(),
+            })?;
+        if write.sender.send(Message::Batch(batch, permit)).is_err() {
+            // The write has stopped on an error, which is the one to report.
+            self.failed = true;
+            return match write.finish().await {
+                Ok(_) => Err(Error::Internal {
+                    message: "the write completed before the writer was closed".to_string(),
+                    location: location!(),
+                }),
+                Err(err) => Err(err),
+            };
+        }
+        self.write = Some(write);
+        Ok(())
+    }
+
+    /// Push all the batches of a stream, see [`Self::push`].
+    pub async fn push_stream(
+        &mut self,
+        batches: impl Stream<Item = Result<RecordBatch>> + Unpin,
+    ) -> Result<()> {
+        let mut batches = batches;
+        while let Some(batch) = batches.next().await {
+            self.push(batch?).await?;
+        }
+        Ok(())
+    }
+
+    /// Wait for the batches pushed to be written, and commit them.
+    pub async fn close(mut self) -> Result<Dataset> {
+        if self.failed {
+            return Err(Error::invalid_input(
+                "the write of the dataset writer has failed",
+                location!(),
+            ));
+        }
+        let write = match self.write.take() {
+            Some(write) => write,
+            None => self.start(None)?,
+        };
+        // The write has failed if the message can not be sent, and its error is
+        // returned below.
+        let _ = write.sender.send(Message::Close);
+        write.finish().await
+    }
+
+    fn check_schema(&self, batch: &RecordBatch) -> Result<()> {
+        let batch_schema = batch.schema();
+        let matches = batch_schema.fields().len() == self.schema.fields().len()
+            && batch_schema
+                .fields()
+                .iter()
+                .zip(self.schema.fields())
+                .all(|(a, b)| a.name() == b.name() && a.data_type() == b.data_type());
+        if !matches {
+            return Err(Error::invalid_input(
+                format!(
+                    "the schema of the batch does not match the schema of the writer: {:?} vs {:?}",
+                    batch_schema, self.schema
+                ),
+                location!(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn start(&self, first_batch: Option<&RecordBatch>) -> Result<BackgroundWrite> {
+        // The dictionaries of the schema are set from the first batch.
+        let mut schema = Schema::try_from(self.schema.as_ref())?;
+        if let Some(batch) = first_batch {
+            schema.set_dictionary(batch)?;
+        }
+        schema.validate()?;
+
+        let (sender, receiver) = mpsc::unbounded_channel();
+        // The buffered memory is released as the writer takes the batches.
+        let batches = stream::unfold(Some(receiver), |receiver| async move {
+            let mut receiver = receiver?;
+            match receiver.recv().await {
+                Some(Message::Batch(batch, _permit)) => Some((Ok(batch), Some(receiver))),
+                Some(Message::Close) => None,
+                // Fail the write, so that nothing is committed.
+                None => Some((
+                    Err(DataFusionError::Execution(
+                        "the dataset writer was dropped without being closed".to_string(),
+                    )),
+                    None,
+                )),
+            }
+        });
+        let stream = Box::pin(RecordBatchStreamAdapter::new(self.schema.clone(), batches));
+
+        let uri = self.uri.clone();
+        let params = self.params.clone();
+        let task =
+            tokio::spawn(
+                async move { Dataset::write_stream(stream, schema, &uri, Some(params)).await },
+            );
+        Ok(BackgroundWrite {
+            sender,
+            buffer: Arc::new(Semaphore::new(self.max_buffered_bytes)),
+            task,
+        })
+    }
+}
+
+impl BackgroundWrite {
+    async fn finish(self) -> Result<Dataset> {
+        drop(self.sender);
+        self.task.await.map_err(|err| Error::Internal {
+            message: format!("the dataset write task failed: {}", err),
+            location: location!(),
+        })?
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Int32Array, StringArray};
+    use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
+    use tempfile::tempdir;
+
+    use crate::dataset::WriteMode;
+
+    fn batch(schema: &SchemaRef, ids: std::ops::Range<i32>) -> RecordBatch {
+        RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter_values(ids.clone())),
+                Arc::new(StringArray::from_iter_values(
+                    ids.map(|i| format!("value {}", i)),
+                )),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dataset_writer() {
+        let test_dir = tempdir().unwrap();
+        let uri = test_dir.path().to_str().unwrap();
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, false),
+            ArrowField::new("value", DataType::Utf8, true),
+        ]));
+
+        let params = WriteParams {
+            max_rows_per_file: 250,
+            max_rows_per_group: 50,
+            ..Default::default()
+        };
+        // A buffer smaller than a batch still lets the batches through one at a time.
+        let mut writer = DatasetWriter::new(uri, schema.clone(), Some(params.clone()))
+            .with_max_buffered_bytes(1);
+        for start in (0..1000).step_by(100) {
+            writer
+                .push(batch(&schema, start..start + 100))
+                .await
+                .unwrap();
+        }
+        // Nothing is visible before the writer is closed.
+        assert!(Dataset::open(uri).await.is_err());
+        let dataset = writer.close().await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 1000);
+        assert_eq!(dataset.get_fragments().len(), 4);
+        assert_eq!(dataset.version().version, 1);
+
+        // Append a stream of batches.
+        let mut writer = DatasetWriter::new(
+            uri,
+            schema.clone(),
+            Some(WriteParams {
+                mode: WriteMode::Append,
+                ..params.clone()
+            }),
+        );
+        writer
+            .push_stream(stream::iter(
+                (1000..1300)
+                    .step_by(100)
+                    .map(|start| Ok(batch(&schema, start..start + 100))),
+            ))
+            .await
+            .unwrap();
+        let dataset = writer.close().await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 1300);
+        assert_eq!(dataset.version().version, 2);
+
+        // A writer dropped without being closed commits nothing.
+        let mut writer = DatasetWriter::new(
+            uri,
+            schema.clone(),
+            Some(WriteParams {
+                mode: WriteMode::Append,
+                ..params.clone()
+            }),
+        );
+        writer.push(batch(&schema, 0..100)).await.unwrap();
+        drop(writer);
+        let dataset = Dataset::open(uri).await.unwrap();
+        assert_eq!(dataset.version().version, 2);
+
+        // Closing a writer without batches commits an empty append.
+        let writer = DatasetWriter::new(
+            uri,
+            schema.clone(),
+            Some(WriteParams {
+                mode: WriteMode::Append,
+                ..params
+            }),
+        );
+        let dataset = writer.close().await.unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 1300);
+    }
+
+    #[tokio::test]
+    async fn test_dataset_writer_errors() {
+        let test_dir = tempdir().unwrap();
+        let uri = test_dir.path().to_str().unwrap();
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, false),
+            ArrowField::new("value", DataType::Utf8, true),
+        ]));
+
+        let mut writer = DatasetWriter::new(uri, schema.clone(), None);
+        let other_schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "id",
+            DataType::Int64,
+            false,
+        )]));
+        let other_batch = RecordBatch::try_new(
+            other_schema,
+            vec![Arc::new(arrow_array::Int64Array::from(vec![1]))],
+        )
+        .unwrap();
+        assert!(matches!(
+            writer.push(other_batch).await,
+            Err(Error::InvalidInput { .. })
+        ));
+
+        // The error of the write is returned by the writer.
+        let nullable_schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("id", DataType::Int32, true),
+            ArrowField::new("value", DataType::Utf8, true),
+        ]));
+        let null_ids = RecordBatch::try_new(
+            nullable_schema,
+            vec![
+                Arc::new(Int32Array::from(vec![Some(1), None])),
+                Arc::new(StringArray::from(vec!["a", "b"])),
+            ],
+        )
+        .unwrap();
+        let result = match writer.push(null_ids).await {
+            Ok(()) => writer.close().await.map(|_| ()),
+            Err(err) => Err(err),
+        };
+        assert!(result.is_err());
+        assert!(Dataset::open(uri).await.is_err());
+    }
+}
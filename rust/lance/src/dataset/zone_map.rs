@@ -0,0 +1,319 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Zone maps, i.e., the minimum, the maximum and the null count of the columns of each
+//! fragment, to skip the fragments which can not match a filter.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, UInt64Array};
+use arrow_schema::{DataType, Schema as ArrowSchema};
+use datafusion::common::Column;
+use datafusion::logical_expr::Expr;
+use datafusion::physical_optimizer::pruning::{PruningPredicate, PruningStatistics};
+use datafusion::scalar::ScalarValue;
+use futures::{StreamExt, TryStreamExt};
+use lance_core::io::writer::can_collect_statistics;
+
+use super::fragment::{ColumnStatistics, FileFragment};
+use super::Dataset;
+use crate::format::Fragment;
+use crate::io::exec::Planner;
+use crate::Result;
+
+/// The statistics of the columns of the fragments, each fragment being a container.
+struct FragmentZoneMaps {
+    num_fragments: usize,
+    /// The data type and the statistics of each fragment, if any, of each column.
+    columns: HashMap<String, (DataType, Vec<Option<ColumnStatistics>>)>,
+}
+
+impl FragmentZoneMaps {
+    fn bounds(
+        &self,
+        column: &Column,
+        bound: impl Fn(&ColumnStatistics) -> Option<ScalarValue>,
+    ) -> Option<ArrayRef> {
+        let (data_type, statistics) = self.columns.get(&column.name)?;
+        let null = ScalarValue::try_from(data_type).ok()?;
+        ScalarValue::iter_to_array(statistics.iter().map(|stats| {
+            stats
+                .as_ref()
+                .and_then(&bound)
+                .unwrap_or_else(|| null.clone())
+        }))
+        .ok()
+    }
+}
+
+impl PruningStatistics for FragmentZoneMaps {
+    fn min_values(&self, column: &Column) -> Option<ArrayRef> {
+        self.bounds(column, |stats| stats.min_value.clone())
+    }
+
+    fn max_values(&self, column: &Column) -> Option<ArrayRef> {
+        self.bounds(column, |stats| stats.max_value.clone())
+    }
+
+    fn num_containers(&self) -> usize {
+        self.num_fragments
+    }
+
+    fn null_counts(&self, column: &Column) -> Option<ArrayRef> {
+        let (_, statistics) = self.columns.get(&column.name)?;
+        Some(Arc::new(UInt64Array::from_iter(
+            statistics
+                .iter()
+                .map(|stats| stats.as_ref().map(|stats| stats.null_count)),
+        )))
+    }
+}
+
+/// The `fragments` which may have rows matching `filter`, according to the statistics
+/// written with their data files.
+///
+/// The fragments without statistics of the columns of the filter are kept.
+pub async fn prune_fragments(
+    dataset: &Arc<Dataset>,
+    fragments: &[Fragment],
+    filter: &Expr,
+) -> Result<Vec<Fragment>> {
+    let columns = Planner::column_names_in_expr(filter);
+    let schema = dataset.schema();
+    let stats_columns = columns
+        .iter()
+        // Only the top-level fields have statistics.
+        .filter_map(|name| schema.fields.iter().find(|field| &field.name == name))
+        .filter(|field| can_collect_statistics(&field.data_type()))
+        .map(|field| (field.name.as_str(), field.data_type()))
+        .collect::<Vec<_>>();
+    if stats_columns.is_empty() || fragments.is_empty() {
+        return Ok(fragments.to_vec());
+    }
+
+    let arrow_schema = Arc::new(ArrowSchema::from(&schema.project(&columns)?));
+    let planner = Planner::new(arrow_schema.clone());
+    let predicate = PruningPredicate::try_new(planner.create_physical_expr(filter)?, arrow_schema)?;
+    if predicate.allways_true() {
+        return Ok(fragments.to_vec());
+    }
+
+    let names = stats_columns
+        .iter()
+        .map(|(name, _)| name.to_string())
+        .collect::<Vec<_>>();
+    let fragment_statistics = futures::stream::iter(fragments.to_vec())
+        .map(|fragment| {
+            let fragment = FileFragment::new(dataset.clone(), fragment);
+            let names = names.clone();
+            async move {
+                let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+                fragment.column_statistics(&names).await
+            }
+        })
+        .buffered(num_cpus::get())
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    let zone_maps = FragmentZoneMaps {
+        num_fragments: fragments.len(),
+        columns: stats_columns
+            .iter()
+            .map(|(name, data_type)| {
+                let statistics = fragment_statistics
+                    .iter()
+                    .map(|stats| stats.get(*name).cloned())
+                    .collect();
+                (name.to_string(), (data_type.clone(), statistics))
+            })
+            .collect(),
+    };
+    let keep = predicate.prune(&zone_maps)?;
+    Ok(fragments
+        .iter()
+        .zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(fragment, _)| fragment.clone())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{
+        Int32Array, RecordBatch, RecordBatchIterator, StringArray, TimestampMicrosecondArray,
+    };
+    use arrow_schema::{Field as ArrowField, TimeUnit};
+    use tempfile::tempdir;
+
+    use crate::dataset::WriteParams;
+
+    #[tokio::test]
+    async fn test_prune_fragments() {
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("i", DataType::Int32, true),
+            ArrowField::new("s", DataType::Utf8, true),
+        ]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(Int32Array::from_iter((0..400).map(|i| {
+                    // Only the last fragment has nulls.
+                    (i < 300 || i % 10 != 0).then_some(i)
+                }))),
+                Arc::new(StringArray::from_iter_values(
+                    (0..400).map(|i| format!("s-{:03}", i)),
+                )),
+            ],
+        )
+        .unwrap();
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            max_rows_per_group: 20,
+            ..Default::default()
+        };
+        let batches = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let dataset = Arc::new(
+            Dataset::write(batches, test_uri, Some(write_params))
+                .await
+                .unwrap(),
+        );
+        let fragments = dataset.fragments().as_ref().clone();
+        assert_eq!(fragments.len(), 4);
+
+        let planner = Planner::new(schema.clone());
+        let cases = [
+            ("i >= 150 AND i < 250", vec![1, 2]),
+            ("i = 5 OR i = 205", vec![0, 2]),
+            ("i > 1000", vec![]),
+            ("i IS NULL", vec![3]),
+            ("s < 's-100'", vec![0]),
+            ("s = 's-350' AND i IS NULL", vec![3]),
+            ("i % 2 = 0", vec![0, 1, 2, 3]),
+        ];
+        for (filter, expected) in cases {
+            let expr = planner.parse_filter(filter).unwrap();
+            let pruned = prune_fragments(&dataset, &fragments, &expr).await.unwrap();
+            assert_eq!(
+                pruned.iter().map(|f| f.id).collect::<Vec<_>>(),
+                expected,
+                "filter: {filter}"
+            );
+        }
+
+        // The filtered scan skips the pruned fragments.
+        let count = |filter: &'static str| {
+            let dataset = dataset.clone();
+            async move {
+                dataset
+                    .scan()
+                    .filter(filter)
+                    .unwrap()
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|batch| batch.num_rows())
+                    .sum::<usize>()
+            }
+        };
+        assert_eq!(count("i >= 150 AND i < 160").await, 10);
+        assert_eq!(count("i > 1000").await, 0);
+        assert_eq!(count("s >= 's-395'").await, 5);
+    }
+
+    #[tokio::test]
+    async fn test_prune_timestamp_ranges() {
+        // A log-like dataset, appended in time order, one row per second.
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "ts",
+            DataType::Timestamp(TimeUnit::Microsecond, None),
+            true,
+        )]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(TimestampMicrosecondArray::from_iter_values(
+                (0..400).map(|i| i * 1_000_000),
+            ))],
+        )
+        .unwrap();
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 100,
+            max_rows_per_group: 20,
+            ..Default::default()
+        };
+        let batches = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let dataset = Arc::new(
+            Dataset::write(batches, test_uri, Some(write_params))
+                .await
+                .unwrap(),
+        );
+        let fragments = dataset.fragments().as_ref().clone();
+
+        let planner = Planner::new(schema.clone());
+        let cases = [
+            (
+                "ts BETWEEN timestamp '1970-01-01 00:02:30' AND timestamp '1970-01-01 00:04:00'",
+                vec![1, 2],
+            ),
+            (
+                "ts NOT BETWEEN timestamp '1970-01-01 00:00:00' AND timestamp '1970-01-01 00:05:00'",
+                vec![3],
+            ),
+            (
+                "ts >= timestamp '1970-01-01 00:02:30' AND ts < timestamp '1970-01-01 00:03:00'",
+                vec![1],
+            ),
+            ("ts > timestamp '1970-01-02 00:00:00'", vec![]),
+        ];
+        for (filter, expected) in cases {
+            let expr = planner.parse_filter(filter).unwrap();
+            let expr = planner.optimize_expr(expr).unwrap();
+            let pruned = prune_fragments(&dataset, &fragments, &expr).await.unwrap();
+            assert_eq!(
+                pruned.iter().map(|f| f.id).collect::<Vec<_>>(),
+                expected,
+                "filter: {filter}"
+            );
+        }
+
+        let num_rows = dataset
+            .scan()
+            .filter(
+                "ts BETWEEN timestamp '1970-01-01 00:02:30' AND timestamp '1970-01-01 00:02:39'",
+            )
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap()
+            .iter()
+            .map(|batch| batch.num_rows())
+            .sum::<usize>();
+        assert_eq!(num_rows, 10);
+    }
+}
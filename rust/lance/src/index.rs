@@ -19,33 +19,49 @@ use std::any::Any;
 use std::collections::HashMap;
 use std::sync::Arc;
 
+use arrow_array::RecordBatch;
 use arrow_schema::DataType;
 use async_trait::async_trait;
-use lance_core::io::{read_message, read_message_from_buf, read_metadata_offset, Reader};
+use datafusion::physical_plan::SendableRecordBatchStream;
+use futures::{StreamExt, TryStreamExt};
+use lance_core::io::{
+    read_message, read_message_from_buf, read_metadata_offset, Reader, RecordBatchStreamAdapter,
+};
 use lance_index::pb::index::Implementation;
 use lance_index::scalar::expression::IndexInformationProvider;
 use lance_index::scalar::lance_format::LanceIndexStore;
-use lance_index::scalar::ScalarIndex;
+use lance_index::scalar::{
+    inverted::{InvertedIndex, INVERTED_TOKENS_NAME},
+    json::JsonIndex,
+    label_list::supports_label_list,
+    ScalarIndex, ScalarQuery,
+};
 use lance_index::{pb, Index, IndexType, INDEX_FILE_NAME};
 use nohash_hasher::IntMap;
+use roaring::RoaringBitmap;
 use snafu::{location, Location};
 use uuid::Uuid;
 
 pub(crate) mod append;
 pub(crate) mod cache;
+pub mod inverted;
 pub(crate) mod prefilter;
 pub mod scalar;
 pub mod vector;
 
 use crate::dataset::transaction::{Operation, Transaction};
 use crate::format::Index as IndexMetadata;
-use crate::index::append::append_index;
+use crate::index::append::merge_indices;
 use crate::index::vector::remap_vector_index;
 use crate::io::commit::commit_transaction;
 use crate::{dataset::Dataset, Error, Result};
 
-use self::scalar::build_scalar_index;
-use self::vector::{build_vector_index, VectorIndex, VectorIndexParams};
+use self::inverted::{build_inverted_index, InvertedIndexParams};
+use self::scalar::{build_scalar_index, ScalarIndexParams, ScalarIndexType};
+use self::vector::{
+    build_vector_index, build_vector_index_from_stream, build_vector_indices, ivf::IVFIndex,
+    opq::OPQIndex, pq::PQIndex, VectorIndex, VectorIndexParams,
+};
 
 /// Builds index.
 #[async_trait]
@@ -59,6 +75,31 @@ pub trait IndexParams: Send + Sync {
     fn as_any(&self) -> &dyn Any;
 }
 
+/// Options for [`DatasetIndexExt::optimize_indices`].
+///
+/// An index can consist of several deltas, each of them covers a disjoint set of
+/// fragments. They share the same name and are searched together.
+#[derive(Debug, Clone, Default)]
+pub struct OptimizeOptions {
+    /// The number of the latest deltas to merge, together with the un-indexed
+    /// fragments, into a single index file.
+    ///
+    /// - `Some(0)` indexes the un-indexed fragments into a new delta, without
+    ///   rewriting the existing deltas.
+    /// - `None`, the default, merges all the deltas of each index.
+    pub num_indices_to_merge: Option<usize>,
+}
+
+/// Options for [`DatasetIndexExt::prewarm_index`].
+#[derive(Debug, Clone, Default)]
+pub struct PrewarmOptions {
+    /// The number of the largest partitions of an IVF index to load into the cache,
+    /// in addition to the IVF centroids and the PQ codebook.
+    ///
+    /// The largest partitions are the most likely to be probed. Defaults to 0.
+    pub num_partitions: usize,
+}
+
 pub(crate) async fn remap_index(
     dataset: &Dataset,
     index_id: &Uuid,
@@ -103,6 +144,15 @@ pub(crate) async fn remap_index(
                 .await?;
             scalar_index.remap(row_id_map, &new_store).await?;
         }
+        IndexType::Inverted => {
+            let index_dir = dataset.indices_dir().child(new_id.to_string());
+            let new_store = LanceIndexStore::new((*dataset.object_store).clone(), index_dir);
+
+            let inverted_index = dataset
+                .open_inverted_index(&field.name, &index_id.to_string())
+                .await?;
+            inverted_index.remap(row_id_map, &new_store).await?;
+        }
         IndexType::Vector => {
             remap_vector_index(
                 Arc::new(dataset.clone()),
@@ -119,14 +169,92 @@ pub(crate) async fn remap_index(
     Ok(new_id)
 }
 
+#[derive(Default)]
 pub struct ScalarIndexInfo {
     indexed_columns: HashMap<String, DataType>,
+    /// The columns whose scalar index is an n-gram index.
+    ngram_columns: HashMap<String, DataType>,
+    /// The columns whose scalar index is a bloom filter index.
+    bloom_filter_columns: HashMap<String, DataType>,
+    /// The columns whose scalar index is a label list index.
+    label_list_columns: HashMap<String, DataType>,
+    /// The JSON paths of the columns whose scalar index is a JSON index.
+    json_paths: HashMap<String, Vec<String>>,
+    /// The UUIDs of the indices of `indexed_columns`.
+    index_uuids: HashMap<String, String>,
+    /// The indices whose statistics estimate the selectivity of the queries, see
+    /// [`ScalarIndexInfo::load_statistics`].
+    indices: HashMap<String, Arc<dyn ScalarIndex>>,
+}
+
+impl ScalarIndexInfo {
+    /// Load the indices of the `columns`, e.g., the columns of a filter, so that the
+    /// planner can tell whether searching them is cheaper than scanning.
+    pub(crate) async fn load_statistics(
+        &mut self,
+        dataset: &Dataset,
+        columns: &[String],
+    ) -> Result<()> {
+        for column in columns {
+            if let Some(uuid) = self.index_uuids.get(column) {
+                let index = dataset.open_scalar_index(column, uuid).await?;
+                self.indices.insert(column.clone(), index);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl IndexInformationProvider for ScalarIndexInfo {
     fn get_index(&self, col: &str) -> Option<&DataType> {
         self.indexed_columns.get(col)
     }
+
+    fn get_ngram_index(&self, col: &str) -> Option<&DataType> {
+        self.ngram_columns.get(col)
+    }
+
+    fn get_bloom_filter_index(&self, col: &str) -> Option<&DataType> {
+        self.bloom_filter_columns.get(col)
+    }
+
+    fn get_label_list_index(&self, col: &str) -> Option<&DataType> {
+        self.label_list_columns.get(col)
+    }
+
+    fn has_json_index(&self, col: &str, path: &str) -> bool {
+        self.json_paths
+            .get(col)
+            .is_some_and(|paths| paths.iter().any(|p| p == path))
+    }
+
+    fn estimate_selectivity(&self, col: &str, query: &ScalarQuery) -> Option<f64> {
+        self.indices.get(col)?.estimate_selectivity(query)
+    }
+}
+
+/// Description of one index (delta) of a dataset, returned by [`Dataset::list_indices`].
+#[derive(Debug, Clone)]
+pub struct IndexDescription {
+    /// Index name.
+    pub name: String,
+
+    /// Index UUID. The deltas of an index share the name but not the UUID.
+    pub uuid: Uuid,
+
+    /// The columns the index is built on.
+    pub columns: Vec<String>,
+
+    /// Scalar or vector index.
+    pub index_type: IndexType,
+
+    /// The dataset version when the index was created.
+    pub dataset_version: u64,
+
+    /// The fragments covered by the index.
+    ///
+    /// `None` if the index was written by an older version, which did not record it.
+    pub fragment_bitmap: Option<RoaringBitmap>,
 }
 
 /// Extends Dataset with secondary index.
@@ -153,11 +281,224 @@ pub trait DatasetIndexExt {
         replace: bool,
     ) -> Result<()>;
 
+    /// Create a vector index on `column` from a stream of vectors, instead of a scan
+    /// of the column.
+    ///
+    /// Vectors computed outside of the dataset, e.g., embeddings computed on another
+    /// machine, can be indexed without writing them to the dataset first. `stream`
+    /// must have a `_rowid` column with the row ids of the dataset and a `column`
+    /// column with the same type as in the dataset. The index covers all the
+    /// fragments of the current version.
+    ///
+    /// Only IVF_PQ indices are supported. IVF and PQ are trained on the first
+    /// vectors of the stream.
+    async fn create_index_from_stream(
+        &mut self,
+        column: &str,
+        name: Option<String>,
+        params: &VectorIndexParams,
+        stream: SendableRecordBatchStream,
+        replace: bool,
+    ) -> Result<()>;
+
+    /// Create vector indices on several columns with one scan of the dataset.
+    ///
+    /// Each of `indices` is the column, the optional name and the parameters of an
+    /// index. The columns are read together and shuffled concurrently, and all the
+    /// indices are committed in one new version.
+    ///
+    /// Only IVF_PQ indices are supported.
+    async fn create_vector_indices(
+        &mut self,
+        indices: &[(&str, Option<String>, &VectorIndexParams)],
+        replace: bool,
+    ) -> Result<()>;
+
     /// Optimize indices.
-    async fn optimize_indices(&mut self) -> Result<()>;
+    ///
+    /// Index the new data and merge the deltas of each index, as specified by
+    /// `options`. All the new indices are committed in one transaction.
+    async fn optimize_indices(&mut self, options: &OptimizeOptions) -> Result<()>;
+
+    /// Drop the index with the given name.
+    ///
+    /// All the deltas of the index are removed from the manifest in a new version.
+    /// The index files are kept for the previous versions, and are deleted by
+    /// [`Dataset::cleanup_old_versions`] once no version refers to them.
+    async fn drop_index(&mut self, name: &str) -> Result<()>;
+
+    /// Load the index with the given name into the index cache, so that the first
+    /// query does not have to read it from the object store.
+    ///
+    /// All the deltas of the index are loaded, as specified by `options`.
+    async fn prewarm_index(&self, name: &str, options: &PrewarmOptions) -> Result<()>;
+
+    /// Read the PQ codebook of the IVF_PQ index with the given name, to analyze the
+    /// quantization offline. See [`vector::pq::codebook_to_batch`] for the columns.
+    async fn pq_codebook(&self, name: &str) -> Result<RecordBatch>;
+
+    /// Read the PQ codes of all the rows of the IVF_PQ index with the given name.
+    ///
+    /// Returns one batch per partition of each delta of the index, with the columns
+    /// `_rowid`, `__pq_code` and `__ivf_part_id`.
+    async fn pq_codes(&self, name: &str) -> Result<Vec<RecordBatch>>;
+}
+
+/// The deltas of the index `index_name` to replace with a new index on `field_id`.
+pub(crate) async fn indices_to_replace(
+    dataset: &Dataset,
+    index_name: &str,
+    field_id: i32,
+    replace: bool,
+) -> Result<Vec<IndexMetadata>> {
+    // Load indices from the disk.
+    let indices = dataset.load_indices().await?;
+    // All the deltas of the index are replaced.
+    let removed_indices = indices
+        .iter()
+        .filter(|i| i.name == index_name)
+        .cloned()
+        .collect::<Vec<_>>();
+    if let Some(idx) = removed_indices.first() {
+        if idx.fields == [field_id] && !replace {
+            return Err(Error::Index {
+                message: format!(
+                    "Index name '{index_name} already exists, \
+                    please specify a different name or use replace=True"
+                ),
+                location: location!(),
+            });
+        };
+        if idx.fields != [field_id] {
+            return Err(Error::Index {
+                message: format!(
+                    "Index name '{index_name} already exists with different fields, \
+                    please specify a different name"
+                ),
+                location: location!(),
+            });
+        }
+    }
+    Ok(removed_indices)
+}
+
+/// Commit a new index over all the fragments of the dataset, in place of `removed_indices`.
+pub(crate) async fn commit_new_index(
+    dataset: &mut Dataset,
+    index_id: Uuid,
+    index_name: String,
+    field_id: i32,
+    removed_indices: Vec<IndexMetadata>,
+) -> Result<()> {
+    commit_new_indices(
+        dataset,
+        vec![(index_id, index_name, field_id)],
+        removed_indices,
+    )
+    .await
+}
+
+/// Commit new indices over all the fragments of the dataset in one transaction, in
+/// place of `removed_indices`.
+///
+/// Each of `new_indices` is the uuid, the name and the field id of an index.
+pub(crate) async fn commit_new_indices(
+    dataset: &mut Dataset,
+    new_indices: Vec<(Uuid, String, i32)>,
+    removed_indices: Vec<IndexMetadata>,
+) -> Result<()> {
+    let fragment_bitmap: RoaringBitmap = dataset
+        .get_fragments()
+        .iter()
+        .map(|f| f.id() as u32)
+        .collect();
+    let new_indices = new_indices
+        .into_iter()
+        .map(|(index_id, index_name, field_id)| IndexMetadata {
+            uuid: index_id,
+            name: index_name,
+            fields: vec![field_id],
+            dataset_version: dataset.manifest.version,
+            fragment_bitmap: Some(fragment_bitmap.clone()),
+        })
+        .collect();
+    let transaction = Transaction::new(
+        dataset.manifest.version,
+        Operation::CreateIndex {
+            new_indices,
+            removed_indices,
+        },
+        None,
+    );
+
+    let new_manifest = commit_transaction(
+        dataset,
+        dataset.object_store(),
+        &transaction,
+        &Default::default(),
+        &dataset.commit_config,
+    )
+    .await?;
+
+    dataset.manifest = Arc::new(new_manifest);
+
+    Ok(())
+}
+
+/// Open the deltas of the IVF_PQ index with the given name.
+async fn open_ivf_pq_indices(dataset: &Dataset, name: &str) -> Result<Vec<Arc<dyn VectorIndex>>> {
+    let indices = dataset.load_indices().await?;
+    let indices = indices
+        .iter()
+        .filter(|i| i.name == name)
+        .collect::<Vec<_>>();
+    if indices.is_empty() {
+        return Err(Error::Index {
+            message: format!("Index '{name}' does not exist"),
+            location: location!(),
+        });
+    }
+
+    let mut opened = Vec::with_capacity(indices.len());
+    for index in indices {
+        let field = index.fields[0];
+        let Some(field) = dataset.schema().field_by_id(field) else {
+            return Err(Error::Index {
+                message: format!("Index '{name}' refers to a missing column with id {field}"),
+                location: location!(),
+            });
+        };
+        if !vector::is_vector_type(&field.data_type()) {
+            return Err(Error::Index {
+                message: format!("Index '{name}' is not an IVF_PQ index"),
+                location: location!(),
+            });
+        }
+        let index = dataset
+            .open_vector_index(&field.name, &index.uuid.to_string())
+            .await?;
+        if as_ivf_pq(index.as_ref()).is_none() {
+            return Err(Error::Index {
+                message: format!("Index '{name}' is not an IVF_PQ index: {:?}", index),
+                location: location!(),
+            });
+        }
+        opened.push(index);
+    }
+    Ok(opened)
+}
+
+/// The IVF model and the PQ sub-index of an IVF_PQ index, with or without OPQ.
+fn as_ivf_pq(index: &dyn VectorIndex) -> Option<(&IVFIndex, &PQIndex)> {
+    let index = match index.as_any().downcast_ref::<OPQIndex>() {
+        Some(opq) => opq.sub_index().as_ref(),
+        None => index,
+    };
+    let ivf = index.as_any().downcast_ref::<IVFIndex>()?;
+    Some((ivf, ivf.pq_index()?))
 }
 
-async fn open_index_proto(dataset: &Dataset, reader: &dyn Reader) -> Result<pb::Index> {
+pub(crate) async fn open_index_proto(dataset: &Dataset, reader: &dyn Reader) -> Result<pb::Index> {
     let object_store = dataset.object_store();
 
     let file_size = reader.size().await?;
@@ -202,35 +543,35 @@ impl DatasetIndexExt for Dataset {
                 location: location!(),
             });
         };
+        let field_id = field.id;
 
-        // Load indices from the disk.
-        let indices = self.load_indices().await?;
         let index_name = name.unwrap_or(format!("{column}_idx"));
-        if let Some(idx) = indices.iter().find(|i| i.name == index_name) {
-            if idx.fields == [field.id] && !replace {
-                return Err(Error::Index {
-                    message: format!(
-                        "Index name '{index_name} already exists, \
-                        please specify a different name or use replace=True"
-                    ),
-                    location: location!(),
-                });
-            };
-            if idx.fields != [field.id] {
-                return Err(Error::Index {
-                    message: format!(
-                        "Index name '{index_name} already exists with different fields, \
-                        please specify a different name"
-                    ),
-                    location: location!(),
-                });
-            }
-        }
+        let removed_indices = indices_to_replace(self, &index_name, field_id, replace).await?;
 
         let index_id = Uuid::new_v4();
         match index_type {
             IndexType::Scalar => {
-                build_scalar_index(self, column, &index_id.to_string()).await?;
+                let scalar_params = params
+                    .as_any()
+                    .downcast_ref::<ScalarIndexParams>()
+                    .ok_or_else(|| Error::Index {
+                        message: "Scalar index type must take a ScalarIndexParams".to_string(),
+                        location: location!(),
+                    })?;
+
+                build_scalar_index(self, column, &index_id.to_string(), scalar_params, None)
+                    .await?;
+            }
+            IndexType::Inverted => {
+                let inverted_params = params
+                    .as_any()
+                    .downcast_ref::<InvertedIndexParams>()
+                    .ok_or_else(|| Error::Index {
+                        message: "Inverted index type must take a InvertedIndexParams".to_string(),
+                        location: location!(),
+                    })?;
+
+                build_inverted_index(self, column, &index_id.to_string(), inverted_params).await?;
             }
             IndexType::Vector => {
                 // Vector index params.
@@ -247,18 +588,117 @@ impl DatasetIndexExt for Dataset {
             }
         }
 
-        let new_idx = IndexMetadata {
-            uuid: index_id,
-            name: index_name,
-            fields: vec![field.id],
-            dataset_version: self.manifest.version,
-            fragment_bitmap: Some(self.get_fragments().iter().map(|f| f.id() as u32).collect()),
+        commit_new_index(self, index_id, index_name, field_id, removed_indices).await
+    }
+
+    async fn create_index_from_stream(
+        &mut self,
+        column: &str,
+        name: Option<String>,
+        params: &VectorIndexParams,
+        stream: SendableRecordBatchStream,
+        replace: bool,
+    ) -> Result<()> {
+        let Some(field) = self.schema().field(column) else {
+            return Err(Error::Index {
+                message: format!("CreateIndex: column '{column}' does not exist"),
+                location: location!(),
+            });
         };
+        let field_id = field.id;
+
+        let index_name = name.unwrap_or(format!("{column}_idx"));
+        let removed_indices = indices_to_replace(self, &index_name, field_id, replace).await?;
+
+        let index_id = Uuid::new_v4();
+        let stream = self.with_index_row_ids(stream);
+        let stream =
+            RecordBatchStreamAdapter::new(stream.schema(), stream.map_err(Error::from).boxed());
+        build_vector_index_from_stream(
+            self,
+            column,
+            &index_name,
+            &index_id.to_string(),
+            params,
+            stream,
+        )
+        .await?;
+
+        commit_new_index(self, index_id, index_name, field_id, removed_indices).await
+    }
+
+    async fn create_vector_indices(
+        &mut self,
+        indices: &[(&str, Option<String>, &VectorIndexParams)],
+        replace: bool,
+    ) -> Result<()> {
+        if indices.is_empty() {
+            return Err(Error::Index {
+                message: "CreateIndex: no index to create".to_string(),
+                location: location!(),
+            });
+        }
+        let mut new_indices = Vec::with_capacity(indices.len());
+        let mut removed_indices = vec![];
+        for (column, name, _) in indices {
+            let Some(field) = self.schema().field(column) else {
+                return Err(Error::Index {
+                    message: format!("CreateIndex: column '{column}' does not exist"),
+                    location: location!(),
+                });
+            };
+            let field_id = field.id;
+
+            let index_name = name.clone().unwrap_or(format!("{column}_idx"));
+            if new_indices
+                .iter()
+                .any(|(_, name, _): &(Uuid, String, i32)| name == &index_name)
+            {
+                return Err(Error::Index {
+                    message: format!("CreateIndex: index name '{index_name}' is used twice"),
+                    location: location!(),
+                });
+            }
+            removed_indices.extend(indices_to_replace(self, &index_name, field_id, replace).await?);
+            new_indices.push((Uuid::new_v4(), index_name, field_id));
+        }
+
+        let uuids = new_indices
+            .iter()
+            .map(|(index_id, _, _)| index_id.to_string())
+            .collect::<Vec<_>>();
+        let builds = indices
+            .iter()
+            .zip(&new_indices)
+            .zip(&uuids)
+            .map(|(((column, _, params), (_, index_name, _)), uuid)| {
+                (*column, index_name.as_str(), uuid.as_str(), *params)
+            })
+            .collect::<Vec<_>>();
+        build_vector_indices(self, &builds).await?;
+
+        commit_new_indices(self, new_indices, removed_indices).await
+    }
+
+    async fn drop_index(&mut self, name: &str) -> Result<()> {
+        let indices = self.load_indices().await?;
+        let removed_indices = indices
+            .iter()
+            .filter(|i| i.name == name)
+            .cloned()
+            .collect::<Vec<_>>();
+        if removed_indices.is_empty() {
+            return Err(Error::Index {
+                message: format!("DropIndex: index '{name}' does not exist"),
+                location: location!(),
+            });
+        }
+
         let transaction = Transaction::new(
             self.manifest.version,
             Operation::CreateIndex {
-                new_indices: vec![new_idx],
-                removed_indices: vec![],
+                new_indices: vec![],
+                removed_indices,
             },
             None,
         );
@@ -268,7 +708,7 @@ impl DatasetIndexExt for Dataset {
             self.object_store(),
             &transaction,
             &Default::default(),
-            &Default::default(),
+            &self.commit_config,
         )
         .await?;
 
@@ -277,29 +717,39 @@ impl DatasetIndexExt for Dataset {
         Ok(())
     }
 
-    async fn optimize_indices(&mut self) -> Result<()> {
+    async fn optimize_indices(&mut self, options: &OptimizeOptions) -> Result<()> {
         let dataset = Arc::new(self.clone());
-        // Append index
         let indices = self.load_indices().await?;
 
+        // Group the deltas by index name, in the order they were committed.
+        let mut deltas_by_name: Vec<(&str, Vec<&IndexMetadata>)> = vec![];
+        for idx in indices.iter() {
+            match deltas_by_name
+                .iter_mut()
+                .find(|(name, _)| *name == idx.name)
+            {
+                Some((_, deltas)) => deltas.push(idx),
+                None => deltas_by_name.push((idx.name.as_str(), vec![idx])),
+            }
+        }
+
         let mut new_indices = vec![];
         let mut removed_indices = vec![];
-        for idx in indices.as_slice() {
-            if idx.dataset_version == self.manifest.version {
-                continue;
-            }
-            let Some((new_id, new_frag_ids)) = append_index(dataset.clone(), idx).await? else {
+        for (name, deltas) in deltas_by_name {
+            let Some((new_id, merged, new_frag_ids)) =
+                merge_indices(dataset.clone(), &deltas, options).await?
+            else {
                 continue;
             };
 
             let new_idx = IndexMetadata {
                 uuid: new_id,
-                name: idx.name.clone(),
-                fields: idx.fields.clone(),
+                name: name.to_string(),
+                fields: deltas[0].fields.clone(),
                 dataset_version: self.manifest.version,
                 fragment_bitmap: new_frag_ids,
             };
-            removed_indices.push(idx.clone());
+            removed_indices.extend(merged.into_iter().cloned());
             new_indices.push(new_idx);
         }
 
@@ -321,13 +771,65 @@ impl DatasetIndexExt for Dataset {
             self.object_store(),
             &transaction,
             &Default::default(),
-            &Default::default(),
+            &self.commit_config,
         )
         .await?;
 
         self.manifest = Arc::new(new_manifest);
         Ok(())
     }
+
+    async fn prewarm_index(&self, name: &str, options: &PrewarmOptions) -> Result<()> {
+        let indices = self.load_indices().await?;
+        let indices = indices
+            .iter()
+            .filter(|i| i.name == name)
+            .collect::<Vec<_>>();
+        if indices.is_empty() {
+            return Err(Error::Index {
+                message: format!("PrewarmIndex: index '{name}' does not exist"),
+                location: location!(),
+            });
+        }
+
+        for index in indices {
+            let field = index.fields[0];
+            let column = self
+                .schema()
+                .field_by_id(field)
+                .ok_or_else(|| Error::Index {
+                    message: format!("PrewarmIndex: column with id {field} does not exist"),
+                    location: location!(),
+                })?
+                .name
+                .clone();
+            let index = self
+                .open_generic_index(&column, &index.uuid.to_string())
+                .await?;
+            if options.num_partitions > 0 {
+                if let Some(ivf) = index.as_any().downcast_ref::<IVFIndex>() {
+                    ivf.prewarm_partitions(options.num_partitions).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn pq_codebook(&self, name: &str) -> Result<RecordBatch> {
+        // All the deltas share the same PQ codebook.
+        let indices = open_ivf_pq_indices(self, name).await?;
+        let (_, pq_index) = as_ivf_pq(indices[0].as_ref()).unwrap();
+        vector::pq::codebook_to_batch(pq_index.pq.as_ref())
+    }
+
+    async fn pq_codes(&self, name: &str) -> Result<Vec<RecordBatch>> {
+        let mut batches = vec![];
+        for index in open_ivf_pq_indices(self, name).await? {
+            let (ivf, _) = as_ivf_pq(index.as_ref()).unwrap();
+            batches.extend(ivf.pq_codes().await?);
+        }
+        Ok(batches)
+    }
 }
 
 /// A trait for internal dataset utilities
@@ -335,10 +837,14 @@ impl DatasetIndexExt for Dataset {
 pub(crate) trait DatasetIndexInternalExt {
     /// Opens an index (scalar or vector) as a generic index
     async fn open_generic_index(&self, column: &str, uuid: &str) -> Result<Arc<dyn Index>>;
+    /// Detects whether the index with the given UUID is a scalar or vector index
+    async fn index_type(&self, uuid: &str) -> Result<IndexType>;
     /// Opens the requested scalar index
     async fn open_scalar_index(&self, column: &str, uuid: &str) -> Result<Arc<dyn ScalarIndex>>;
     /// Opens the requested vector index
     async fn open_vector_index(&self, column: &str, uuid: &str) -> Result<Arc<dyn VectorIndex>>;
+    /// Opens the requested inverted index
+    async fn open_inverted_index(&self, column: &str, uuid: &str) -> Result<Arc<InvertedIndex>>;
     /// Loads information about all the available scalar indices on the dataset
     async fn scalar_index_info(&self) -> Result<ScalarIndexInfo>;
 }
@@ -353,23 +859,42 @@ impl DatasetIndexInternalExt for Dataset {
         if let Some(index) = self.session.index_cache.get_vector(uuid) {
             return Ok(index.as_index());
         }
+        if let Some(index) = self.session.index_cache.get_inverted(uuid) {
+            return Ok(index.as_index());
+        }
 
         // Sometimes we want to open an index and we don't care if it is a scalar or vector index.
         // For example, we might want to get statistics for an index, regardless of type.
-        //
-        // Currently, we solve this problem by checking for the existence of INDEX_FILE_NAME since
-        // only vector indices have this file.  In the future, once we support multiple kinds of
-        // scalar indices, we may start having this file with scalar indices too.  Once that happens
-        // we can just read this file and look at the `implementation` or `index_type` fields to
-        // determine what kind of index it is.
-        let index_dir = self.indices_dir().child(uuid);
-        let index_file = index_dir.child(INDEX_FILE_NAME);
+        match self.index_type(uuid).await? {
+            IndexType::Vector => {
+                let index = self.open_vector_index(column, uuid).await?;
+                Ok(index.as_index())
+            }
+            IndexType::Scalar => {
+                let index = self.open_scalar_index(column, uuid).await?;
+                Ok(index.as_index())
+            }
+            IndexType::Inverted => {
+                let index = self.open_inverted_index(column, uuid).await?;
+                Ok(index.as_index())
+            }
+        }
+    }
+
+    async fn index_type(&self, uuid: &str) -> Result<IndexType> {
+        // Currently, we check for the existence of INDEX_FILE_NAME since only vector indices
+        // have this file.  In the future, once we support multiple kinds of scalar indices, we
+        // may start having this file with scalar indices too.  Once that happens we can just
+        // read this file and look at the `implementation` or `index_type` fields to determine
+        // what kind of index it is.
+        let index_file = self.indices_dir().child(uuid).child(INDEX_FILE_NAME);
+        let inverted_file = self.indices_dir().child(uuid).child(INVERTED_TOKENS_NAME);
         if self.object_store.exists(&index_file).await? {
-            let index = self.open_vector_index(column, uuid).await?;
-            Ok(index.as_index())
+            Ok(IndexType::Vector)
+        } else if self.object_store.exists(&inverted_file).await? {
+            Ok(IndexType::Inverted)
         } else {
-            let index = self.open_scalar_index(column, uuid).await?;
-            Ok(index.as_index())
+            Ok(IndexType::Scalar)
         }
     }
 
@@ -383,6 +908,18 @@ impl DatasetIndexInternalExt for Dataset {
         Ok(index)
     }
 
+    async fn open_inverted_index(&self, _column: &str, uuid: &str) -> Result<Arc<InvertedIndex>> {
+        if let Some(index) = self.session.index_cache.get_inverted(uuid) {
+            return Ok(index);
+        }
+
+        let index = crate::index::inverted::open_inverted_index(self, uuid).await?;
+        self.session
+            .index_cache
+            .insert_inverted(uuid, index.clone());
+        Ok(index)
+    }
+
     async fn open_vector_index(&self, column: &str, uuid: &str) -> Result<Arc<dyn VectorIndex>> {
         if let Some(index) = self.session.index_cache.get_vector(uuid) {
             return Ok(index);
@@ -416,18 +953,67 @@ impl DatasetIndexInternalExt for Dataset {
     async fn scalar_index_info(&self) -> Result<ScalarIndexInfo> {
         let indices = self.load_indices().await?;
         let schema = self.schema();
-        let indexed_fields = indices
-        .iter()
-        .filter(|idx| idx.fields.len() == 1)
-        .map(|idx| {
+        let mut index_info = ScalarIndexInfo::default();
+        for idx in indices.iter().filter(|idx| idx.fields.len() == 1) {
             let field = idx.fields[0];
-            let field = schema.field_by_id(field).ok_or_else(|| Error::Internal { message: format!("Index referenced a field with id {field} which did not exist in the schema"), location: location!() });
-            field.map(|field| (field.name.clone(), field.data_type()))
-        }).collect::<Result<Vec<_>>>()?;
-        let index_info_map = HashMap::from_iter(indexed_fields);
-        Ok(ScalarIndexInfo {
-            indexed_columns: index_info_map,
-        })
+            let field = schema.field_by_id(field).ok_or_else(|| Error::Internal {
+                message: format!(
+                    "Index referenced a field with id {field} which did not exist in the schema"
+                ),
+                location: location!(),
+            })?;
+            let data_type = field.data_type();
+            let uuid = idx.uuid.to_string();
+            // A field of a struct column is searched by its path, e.g., `metadata.source`.
+            let column = schema
+                .field_path(field.id)
+                .unwrap_or_else(|| field.name.clone());
+            // The vector indices can not answer scalar queries, i.e., `vector IS NULL`, but a
+            // list column can have a label list index.
+            if vector::is_vector_type(&data_type)
+                && (!supports_label_list(&data_type)
+                    || self.index_type(&uuid).await? != IndexType::Scalar)
+            {
+                continue;
+            }
+            // A column is searched with its first scalar index, see
+            // `Dataset::load_scalar_index_for_column`.
+            if index_info.indexed_columns.contains_key(&column)
+                || index_info.ngram_columns.contains_key(&column)
+                || index_info.bloom_filter_columns.contains_key(&column)
+                || index_info.label_list_columns.contains_key(&column)
+                || index_info.json_paths.contains_key(&column)
+            {
+                continue;
+            }
+            // The inverted indices can not answer scalar queries either.
+            if matches!(data_type, DataType::Utf8 | DataType::LargeUtf8)
+                && self.index_type(&uuid).await? == IndexType::Inverted
+            {
+                continue;
+            }
+            let columns = match scalar::detect_scalar_index_type(self, &uuid).await? {
+                ScalarIndexType::NGram => &mut index_info.ngram_columns,
+                ScalarIndexType::BloomFilter => &mut index_info.bloom_filter_columns,
+                ScalarIndexType::LabelList => &mut index_info.label_list_columns,
+                ScalarIndexType::Json => {
+                    let index = self.open_scalar_index(&column, &uuid).await?;
+                    let paths = index
+                        .as_any()
+                        .downcast_ref::<JsonIndex>()
+                        .map(|index| index.paths().map(String::from).collect())
+                        .unwrap_or_default();
+                    index_info.json_paths.insert(column, paths);
+                    continue;
+                }
+                ScalarIndexType::BTree | ScalarIndexType::Bitmap => {
+                    index_info.index_uuids.insert(column.clone(), uuid);
+                    &mut index_info.indexed_columns
+                }
+            };
+            columns.insert(column, data_type);
+        }
+        Ok(index_info)
     }
 }
 
@@ -435,9 +1021,19 @@ impl DatasetIndexInternalExt for Dataset {
 mod tests {
     use super::*;
 
-    use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator};
+    use arrow_array::{
+        cast::AsArray,
+        types::{UInt32Type, UInt64Type},
+    };
+    use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator, UInt64Array};
     use arrow_schema::{DataType, Field, Schema};
+    use datafusion::physical_plan::stream::RecordBatchStreamAdapter as DFRecordBatchStreamAdapter;
+    use datafusion::scalar::ScalarValue;
+    use futures::TryStreamExt;
     use lance_arrow::*;
+    use lance_core::ROW_ID;
+    use lance_index::scalar::ScalarQuery;
+    use lance_index::vector::{PART_ID_COLUMN, PQ_CODE_COLUMN};
     use lance_linalg::distance::MetricType;
     use lance_testing::datagen::generate_random_array;
     use tempfile::tempdir;
@@ -500,4 +1096,904 @@ mod tests {
             .await
             .is_err());
     }
+
+    #[tokio::test]
+    async fn test_drop_index() {
+        const DIM: i32 = 8;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "v",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), DIM),
+            true,
+        )]));
+        let data = generate_random_array(512 * DIM as usize);
+        let batches: Vec<RecordBatch> = vec![RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(
+                FixedSizeListArray::try_new_from_values(data, DIM).unwrap(),
+            )],
+        )
+        .unwrap()];
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        assert!(dataset.drop_index("v_idx").await.is_err());
+
+        let params = VectorIndexParams::ivf_pq(2, 8, 2, false, MetricType::L2, 2);
+        dataset
+            .create_index(&["v"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+        let indexed_version = dataset.version().version;
+
+        dataset.drop_index("v_idx").await.unwrap();
+        assert_eq!(dataset.version().version, indexed_version + 1);
+        assert!(dataset.load_indices().await.unwrap().is_empty());
+
+        // The previous version still has the index.
+        let old = dataset.checkout_version(indexed_version).await.unwrap();
+        assert_eq!(old.load_indices().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_inspect_pq() {
+        const DIM: i32 = 8;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "v",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), DIM),
+            true,
+        )]));
+        let data = generate_random_array(512 * DIM as usize);
+        let batches: Vec<RecordBatch> = vec![RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(
+                FixedSizeListArray::try_new_from_values(data, DIM).unwrap(),
+            )],
+        )
+        .unwrap()];
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        assert!(dataset.pq_codebook("v_idx").await.is_err());
+
+        let params = VectorIndexParams::ivf_pq(2, 8, 2, false, MetricType::L2, 2);
+        dataset
+            .create_index(&["v"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        // 256 centroids of each of the 2 sub-vectors.
+        let codebook = dataset.pq_codebook("v_idx").await.unwrap();
+        assert_eq!(codebook.num_rows(), 512);
+        assert_eq!(
+            codebook["values"].data_type(),
+            &DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), 4)
+        );
+        let sub_vectors = codebook["sub_vector"].as_primitive::<UInt32Type>();
+        assert_eq!(sub_vectors.value(255), 0);
+        assert_eq!(sub_vectors.value(256), 1);
+
+        let codes = dataset.pq_codes("v_idx").await.unwrap();
+        assert_eq!(codes.len(), 2);
+        let num_rows = codes.iter().map(|b| b.num_rows()).sum::<usize>();
+        assert_eq!(num_rows, 512);
+        for (part_id, batch) in codes.iter().enumerate() {
+            assert_eq!(batch[PQ_CODE_COLUMN].as_fixed_size_list().value_length(), 2);
+            let part_ids = batch[PART_ID_COLUMN].as_primitive::<UInt32Type>();
+            assert!(part_ids.values().iter().all(|&p| p == part_id as u32));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prewarm_index() {
+        const DIM: i32 = 8;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "v",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), DIM),
+            true,
+        )]));
+        let data = generate_random_array(512 * DIM as usize);
+        let batches: Vec<RecordBatch> = vec![RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(
+                FixedSizeListArray::try_new_from_values(data, DIM).unwrap(),
+            )],
+        )
+        .unwrap()];
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        let params = VectorIndexParams::ivf_pq(2, 8, 2, false, MetricType::L2, 2);
+        dataset
+            .create_index(&["v"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        let dataset = Dataset::open(test_uri).await.unwrap();
+        assert!(dataset
+            .prewarm_index("missing", &PrewarmOptions::default())
+            .await
+            .is_err());
+        assert_eq!(dataset.index_cache_entry_count(), 0);
+
+        dataset
+            .prewarm_index("v_idx", &PrewarmOptions { num_partitions: 2 })
+            .await
+            .unwrap();
+        // The IVF_PQ model and both of its partitions.
+        assert_eq!(dataset.index_cache_entry_count(), 3);
+
+        let misses = dataset.index_cache_stats().misses;
+        let q = generate_random_array(DIM as usize);
+        dataset
+            .scan()
+            .nearest("v", &q, 10)
+            .unwrap()
+            .nprobs(2)
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(dataset.index_cache_stats().misses, misses);
+    }
+
+    #[tokio::test]
+    async fn test_create_index_from_stream() {
+        const DIM: i32 = 8;
+        let vector_type =
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), DIM);
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "v",
+            vector_type.clone(),
+            true,
+        )]));
+        let data = generate_random_array(512 * DIM as usize);
+        let batches: Vec<RecordBatch> = vec![RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(
+                FixedSizeListArray::try_new_from_values(data, DIM).unwrap(),
+            )],
+        )
+        .unwrap()];
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        // Vectors computed outside of the dataset, for each row of the single fragment.
+        let stream_schema = Arc::new(Schema::new(vec![
+            Field::new(ROW_ID, DataType::UInt64, false),
+            Field::new("v", vector_type, true),
+        ]));
+        let embeddings = generate_random_array(512 * DIM as usize);
+        let stream_batches = (0..4)
+            .map(|i| {
+                RecordBatch::try_new(
+                    stream_schema.clone(),
+                    vec![
+                        Arc::new(UInt64Array::from_iter_values(i * 128..(i + 1) * 128)),
+                        Arc::new(
+                            FixedSizeListArray::try_new_from_values(
+                                embeddings
+                                    .slice(i as usize * 128 * DIM as usize, 128 * DIM as usize),
+                                DIM,
+                            )
+                            .unwrap(),
+                        ),
+                    ],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let to_stream = |batches: Vec<RecordBatch>| -> SendableRecordBatchStream {
+            let schema = batches[0].schema();
+            Box::pin(DFRecordBatchStreamAdapter::new(
+                schema,
+                futures::stream::iter(batches.into_iter().map(Ok)),
+            ))
+        };
+
+        let flat_params = VectorIndexParams::ivf_flat(2, MetricType::L2);
+        assert!(dataset
+            .create_index_from_stream(
+                "v",
+                None,
+                &flat_params,
+                to_stream(stream_batches.clone()),
+                true
+            )
+            .await
+            .is_err());
+        let without_row_ids = stream_batches
+            .iter()
+            .map(|b| b.project(&[1]).unwrap())
+            .collect::<Vec<_>>();
+        let params = VectorIndexParams::ivf_pq(2, 8, 2, false, MetricType::L2, 2);
+        assert!(dataset
+            .create_index_from_stream("v", None, &params, to_stream(without_row_ids), true)
+            .await
+            .is_err());
+
+        dataset
+            .create_index_from_stream("v", None, &params, to_stream(stream_batches), true)
+            .await
+            .unwrap();
+        let indices = dataset.load_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices[0].name, "v_idx");
+
+        let codes = dataset.pq_codes("v_idx").await.unwrap();
+        let mut row_ids = codes
+            .iter()
+            .flat_map(|b| b[ROW_ID].as_primitive::<UInt64Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        row_ids.sort();
+        assert_eq!(row_ids, (0..512).collect::<Vec<_>>());
+
+        // The nearest neighbor of an embedding from the stream is its own row.
+        let q = embeddings.slice(300 * DIM as usize, DIM as usize);
+        let results = dataset
+            .scan()
+            .nearest("v", &q, 1)
+            .unwrap()
+            .nprobs(2)
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(
+            results[0][ROW_ID].as_primitive::<UInt64Type>().value(0),
+            300
+        );
+    }
+
+    #[tokio::test]
+    async fn test_create_vector_indices() {
+        const DIM: i32 = 8;
+        let vector_type =
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), DIM);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("image_emb", vector_type.clone(), true),
+            Field::new("text_emb", vector_type, true),
+        ]));
+        let image_emb = generate_random_array(512 * DIM as usize);
+        let text_emb = generate_random_array(512 * DIM as usize);
+        let batches: Vec<RecordBatch> = vec![RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(FixedSizeListArray::try_new_from_values(image_emb.clone(), DIM).unwrap()),
+                Arc::new(FixedSizeListArray::try_new_from_values(text_emb.clone(), DIM).unwrap()),
+            ],
+        )
+        .unwrap()];
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+        let version = dataset.version().version;
+
+        let params = VectorIndexParams::ivf_pq(2, 8, 2, false, MetricType::L2, 2);
+        let flat_params = VectorIndexParams::ivf_flat(2, MetricType::L2);
+        assert!(dataset
+            .create_vector_indices(
+                &[
+                    ("image_emb", None, &params),
+                    ("text_emb", None, &flat_params)
+                ],
+                false
+            )
+            .await
+            .is_err());
+        assert!(dataset
+            .create_vector_indices(
+                &[
+                    ("image_emb", Some("emb_idx".to_string()), &params),
+                    ("text_emb", Some("emb_idx".to_string()), &params)
+                ],
+                false
+            )
+            .await
+            .is_err());
+
+        dataset
+            .create_vector_indices(
+                &[("image_emb", None, &params), ("text_emb", None, &params)],
+                false,
+            )
+            .await
+            .unwrap();
+        // Both indices are committed in one version.
+        assert_eq!(dataset.version().version, version + 1);
+        let mut names = dataset
+            .load_indices()
+            .await
+            .unwrap()
+            .iter()
+            .map(|i| i.name.clone())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, vec!["image_emb_idx", "text_emb_idx"]);
+
+        for (column, vectors) in [("image_emb", &image_emb), ("text_emb", &text_emb)] {
+            let codes = dataset.pq_codes(&format!("{column}_idx")).await.unwrap();
+            assert_eq!(codes.iter().map(|b| b.num_rows()).sum::<usize>(), 512);
+
+            let q = vectors.slice(300 * DIM as usize, DIM as usize);
+            let results = dataset
+                .scan()
+                .nearest(column, &q, 1)
+                .unwrap()
+                .nprobs(2)
+                .with_row_id()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(
+                results[0][ROW_ID].as_primitive::<UInt64Type>().value(0),
+                300
+            );
+        }
+
+        // The existing indices are replaced together.
+        assert!(dataset
+            .create_vector_indices(&[("text_emb", None, &params)], false)
+            .await
+            .is_err());
+        dataset
+            .create_vector_indices(
+                &[("image_emb", None, &params), ("text_emb", None, &params)],
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(dataset.load_indices().await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_bitmap_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = lance_datagen::gen()
+            .col(
+                Some("country".to_string()),
+                lance_datagen::array::cycle_utf8_literals(&["US", "CN", "FR", "DE"]),
+            )
+            .col(
+                Some("status".to_string()),
+                lance_datagen::array::cycle_utf8_literals(&["active", "inactive", "pending"]),
+            )
+            .into_reader_rows(
+                lance_datagen::RowCount::from(100),
+                lance_datagen::BatchCount::from(12),
+            );
+        let mut dataset = Dataset::write(data, test_uri, None).await.unwrap();
+        let params = ScalarIndexParams::new(scalar::ScalarIndexType::Bitmap);
+        for column in ["country", "status"] {
+            dataset
+                .create_index(&[column], IndexType::Scalar, None, &params, false)
+                .await
+                .unwrap();
+        }
+        let stats = dataset
+            .index_statistics("country_idx")
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(stats.contains(r#""num_bitmaps":4"#));
+
+        // Row i has country i % 4 and status i % 3.
+        for (filter, expected) in [
+            ("country = 'US'", 300),
+            ("country = 'US' AND status = 'active'", 100),
+            ("country = 'US' OR status = 'active'", 600),
+            ("country IN ('CN', 'FR') AND NOT status = 'pending'", 400),
+        ] {
+            let mut scanner = dataset.scan();
+            scanner.filter(filter).unwrap();
+            let plan = scanner.explain_plan(true).await.unwrap();
+            assert!(plan.contains("MaterializeIndex"), "{filter}: {plan}");
+            assert_eq!(scanner.count_rows().await.unwrap(), expected, "{filter}");
+        }
+    }
+    #[tokio::test]
+    async fn test_ngram_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = lance_datagen::gen()
+            .col(
+                Some("title".to_string()),
+                lance_datagen::array::cycle_utf8_literals(&[
+                    "Lance columnar format",
+                    "The quick brown fox",
+                    "A 50% discount",
+                    "lance_datagen",
+                ]),
+            )
+            .into_reader_rows(
+                lance_datagen::RowCount::from(100),
+                lance_datagen::BatchCount::from(4),
+            );
+        let mut dataset = Dataset::write(data, test_uri, None).await.unwrap();
+        let params = ScalarIndexParams::new(scalar::ScalarIndexType::NGram);
+        dataset
+            .create_index(&["title"], IndexType::Scalar, None, &params, false)
+            .await
+            .unwrap();
+
+        // Row i has title i % 4.
+        for (filter, expected, indexed) in [
+            ("title LIKE '%lance%'", 100, true),
+            ("title ILIKE '%lance%'", 200, true),
+            ("title LIKE 'The%fox'", 100, true),
+            ("contains(title, 'brown')", 100, true),
+            ("contains(title, 'nothing')", 0, true),
+            // Matched by a regular expression, which does not use the index.
+            ("contains(title, '0% d')", 100, false),
+            // An n-gram index does not answer other queries.
+            ("title = 'lance_datagen'", 100, false),
+        ] {
+            let mut scanner = dataset.scan();
+            scanner.filter(filter).unwrap();
+            let plan = scanner.explain_plan(true).await.unwrap();
+            assert_eq!(
+                plan.contains("MaterializeIndex"),
+                indexed,
+                "{filter}: {plan}"
+            );
+            assert_eq!(scanner.count_rows().await.unwrap(), expected, "{filter}");
+        }
+    }
+    #[tokio::test]
+    async fn test_bloom_filter_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let data = lance_datagen::gen()
+            .col(
+                Some("user_id".to_string()),
+                lance_datagen::array::step::<arrow_array::types::Int64Type>(),
+            )
+            .into_reader_rows(
+                lance_datagen::RowCount::from(100),
+                lance_datagen::BatchCount::from(10),
+            );
+        let write_params = crate::dataset::WriteParams {
+            max_rows_per_file: 100,
+            ..Default::default()
+        };
+        let mut dataset = Dataset::write(data, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert_eq!(dataset.get_fragments().len(), 10);
+        let params = ScalarIndexParams::new(scalar::ScalarIndexType::BloomFilter);
+        dataset
+            .create_index(&["user_id"], IndexType::Scalar, None, &params, false)
+            .await
+            .unwrap();
+
+        for (filter, expected) in [
+            ("user_id = 123", 1),
+            ("user_id IN (5, 505, 2000)", 2),
+            ("user_id = 2000", 0),
+            ("user_id = 123 AND user_id > 100", 1),
+        ] {
+            let mut scanner = dataset.scan();
+            scanner.filter(filter).unwrap();
+            let plan = scanner.explain_plan(true).await.unwrap();
+            assert!(plan.contains("MaterializeIndex"), "{filter}: {plan}");
+            assert_eq!(scanner.count_rows().await.unwrap(), expected, "{filter}");
+        }
+
+        // Only the fragments which may contain the value are read.
+        let index = dataset
+            .load_scalar_index_for_column("user_id")
+            .await
+            .unwrap()
+            .unwrap();
+        let index = dataset
+            .open_scalar_index("user_id", &index.uuid.to_string())
+            .await
+            .unwrap();
+        let candidates = index
+            .search(&ScalarQuery::Equals(ScalarValue::Int64(Some(123))))
+            .await
+            .unwrap();
+        assert!(candidates.values().contains(&(1 << 32 | 23)));
+        assert!(candidates.len() < 1000, "{}", candidates.len());
+
+        // The range queries are answered by a scan.
+        let mut scanner = dataset.scan();
+        scanner.filter("user_id < 10").unwrap();
+        let plan = scanner.explain_plan(true).await.unwrap();
+        assert!(!plan.contains("MaterializeIndex"), "{plan}");
+        assert_eq!(scanner.count_rows().await.unwrap(), 10);
+    }
+
+    #[tokio::test]
+    async fn test_label_list_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let tags_batch = |lists: &[&[&str]]| {
+            let mut builder =
+                arrow_array::builder::ListBuilder::new(arrow_array::builder::StringBuilder::new());
+            for list in lists {
+                for tag in *list {
+                    builder.values().append_value(tag);
+                }
+                builder.append(true);
+            }
+            let tags = Arc::new(builder.finish()) as arrow_array::ArrayRef;
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "tags",
+                tags.data_type().clone(),
+                true,
+            )]));
+            RecordBatchIterator::new(
+                vec![RecordBatch::try_new(schema.clone(), vec![tags])],
+                schema,
+            )
+        };
+        let lists: Vec<&[&str]> = vec![&["sale", "new"], &["new"], &[], &["sale", "clearance"]];
+        let mut dataset = Dataset::write(tags_batch(&lists.repeat(25)), test_uri, None)
+            .await
+            .unwrap();
+        let params = ScalarIndexParams::new(scalar::ScalarIndexType::LabelList);
+        dataset
+            .create_index(&["tags"], IndexType::Scalar, None, &params, false)
+            .await
+            .unwrap();
+
+        let check = |dataset: Dataset, filter: &'static str, expected: u64| async move {
+            let mut scanner = dataset.scan();
+            scanner.filter(filter).unwrap();
+            let plan = scanner.explain_plan(true).await.unwrap();
+            assert!(plan.contains("MaterializeIndex"), "{filter}: {plan}");
+            assert_eq!(scanner.count_rows().await.unwrap(), expected, "{filter}");
+        };
+        check(
+            dataset.clone(),
+            "array_has_any(tags, ['new', 'clearance'])",
+            75,
+        )
+        .await;
+        check(dataset.clone(), "array_has_all(tags, ['sale', 'new'])", 25).await;
+        check(dataset.clone(), "array_has(tags, 'sale')", 50).await;
+        check(dataset.clone(), "array_has_any(tags, ['unknown'])", 0).await;
+
+        // The appended rows are found by a scan until the index is optimized.
+        dataset
+            .append(tags_batch(&[&["new", "sale"]]), None)
+            .await
+            .unwrap();
+        let mut scanner = dataset.scan();
+        scanner
+            .filter("array_has_all(tags, ['sale', 'new'])")
+            .unwrap();
+        assert_eq!(scanner.count_rows().await.unwrap(), 26);
+        dataset
+            .optimize_indices(&OptimizeOptions::default())
+            .await
+            .unwrap();
+        check(dataset.clone(), "array_has_all(tags, ['sale', 'new'])", 26).await;
+
+        let err = dataset
+            .create_index(
+                &["tags"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                true,
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("non-nested"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_json_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let json_batch = |documents: Vec<String>| {
+            let schema = Arc::new(Schema::new(vec![Field::new(
+                "metadata",
+                DataType::Utf8,
+                true,
+            )]));
+            RecordBatchIterator::new(
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(arrow_array::StringArray::from(documents))],
+                )],
+                schema,
+            )
+        };
+        let documents = (0..100)
+            .map(|i| format!(r#"{{"type": "t{}", "user": {{"id": {}}}}}"#, i % 4, i))
+            .collect();
+        let mut dataset = Dataset::write(json_batch(documents), test_uri, None)
+            .await
+            .unwrap();
+        let params = ScalarIndexParams::json(vec!["$.type".to_string(), "$.user.id".to_string()]);
+        dataset
+            .create_index(&["metadata"], IndexType::Scalar, None, &params, false)
+            .await
+            .unwrap();
+
+        let check = |dataset: Dataset, filter: &'static str, indexed: bool, expected: usize| async move {
+            let mut scanner = dataset.scan();
+            scanner.filter(filter).unwrap();
+            let plan = scanner.explain_plan(true).await.unwrap();
+            assert_eq!(
+                plan.contains("MaterializeIndex"),
+                indexed,
+                "{filter}: {plan}"
+            );
+            assert_eq!(
+                scanner.count_rows().await.unwrap() as usize,
+                expected,
+                "{filter}"
+            );
+        };
+        check(
+            dataset.clone(),
+            "json_extract(metadata, '$.type') = 't1'",
+            true,
+            25,
+        )
+        .await;
+        check(
+            dataset.clone(),
+            "json_extract(metadata, '$.type') IN ('t1', 't2')",
+            true,
+            50,
+        )
+        .await;
+        check(
+            dataset.clone(),
+            "json_extract(metadata, '$.user.id') = '42'",
+            true,
+            1,
+        )
+        .await;
+        // `$.user` is not indexed
+        check(
+            dataset.clone(),
+            "json_extract(metadata, '$.user') = '{\"id\":42}'",
+            false,
+            1,
+        )
+        .await;
+
+        // The appended rows are found by a scan until the index is optimized.
+        dataset
+            .append(json_batch(vec![r#"{"type": "t1"}"#.to_string()]), None)
+            .await
+            .unwrap();
+        let mut scanner = dataset.scan();
+        scanner
+            .filter("json_extract(metadata, '$.type') = 't1'")
+            .unwrap();
+        assert_eq!(scanner.count_rows().await.unwrap(), 26);
+        dataset
+            .optimize_indices(&OptimizeOptions::default())
+            .await
+            .unwrap();
+        check(
+            dataset.clone(),
+            "json_extract(metadata, '$.type') = 't1'",
+            true,
+            26,
+        )
+        .await;
+
+        let err = dataset
+            .create_index(
+                &["metadata"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::json(vec!["type".to_string()]),
+                true,
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("Invalid JSON path"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_nested_field_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let origin_fields = arrow_schema::Fields::from(vec![
+            Field::new("source", DataType::Utf8, true),
+            Field::new("version", DataType::UInt32, true),
+        ]);
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::UInt32, false),
+            Field::new("origin", DataType::Struct(origin_fields.clone()), true),
+        ]));
+        let origin_batch = |ids: std::ops::Range<u32>| {
+            let sources = ids.clone().map(|i| ["web", "app", "api"][i as usize % 3]);
+            let origin = arrow_array::StructArray::new(
+                origin_fields.clone(),
+                vec![
+                    Arc::new(arrow_array::StringArray::from_iter_values(sources)),
+                    Arc::new(arrow_array::UInt32Array::from_iter_values(
+                        ids.clone().map(|i| i / 10),
+                    )),
+                ],
+                None,
+            );
+            RecordBatchIterator::new(
+                vec![RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(arrow_array::UInt32Array::from_iter_values(ids)),
+                        Arc::new(origin),
+                    ],
+                )],
+                schema.clone(),
+            )
+        };
+        let mut dataset = Dataset::write(origin_batch(0..90), test_uri, None)
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["origin.version"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["origin.source"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::new(scalar::ScalarIndexType::Bitmap),
+                false,
+            )
+            .await
+            .unwrap();
+        let columns = dataset
+            .list_indices()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|index| index.columns)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            columns,
+            vec![
+                vec!["origin.version".to_string()],
+                vec!["origin.source".to_string()]
+            ]
+        );
+
+        let check = |dataset: Dataset, filter: &'static str, expected: u64| async move {
+            let mut scanner = dataset.scan();
+            scanner.filter(filter).unwrap();
+            let plan = scanner.explain_plan(true).await.unwrap();
+            assert!(plan.contains("MaterializeIndex"), "{filter}: {plan}");
+            assert_eq!(scanner.count_rows().await.unwrap(), expected, "{filter}");
+        };
+        check(dataset.clone(), "origin.version >= 7", 20).await;
+        check(dataset.clone(), "origin.source = 'api'", 30).await;
+        check(
+            dataset.clone(),
+            "origin.source = 'web' AND origin.version < 2",
+            7,
+        )
+        .await;
+
+        // The appended rows are indexed once the index is optimized.
+        dataset.append(origin_batch(90..100), None).await.unwrap();
+        dataset
+            .optimize_indices(&OptimizeOptions::default())
+            .await
+            .unwrap();
+        check(dataset.clone(), "origin.version = 9", 10).await;
+        check(dataset.clone(), "origin.source = 'api'", 33).await;
+
+        let err = dataset
+            .create_index(
+                &["origin"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                true,
+            )
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("non-nested"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn test_index_selectivity() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let schema = Arc::new(Schema::new(vec![
+            Field::new("id", DataType::UInt32, false),
+            Field::new("status", DataType::Utf8, false),
+        ]));
+        let statuses = (0..10000).map(|i| if i % 10 == 0 { "rare" } else { "common" });
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(arrow_array::UInt32Array::from_iter_values(0..10000)),
+                Arc::new(arrow_array::StringArray::from_iter_values(statuses)),
+            ],
+        )
+        .unwrap();
+        let batches = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+        // The btree of the ids has 3 pages.
+        dataset
+            .create_index(
+                &["id"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                false,
+            )
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["status"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::new(scalar::ScalarIndexType::Bitmap),
+                false,
+            )
+            .await
+            .unwrap();
+
+        let check = |filter: &'static str, indexed: bool, expected: u64| {
+            let dataset = dataset.clone();
+            async move {
+                let mut scanner = dataset.scan();
+                scanner.filter(filter).unwrap();
+                let plan = scanner.explain_plan(true).await.unwrap();
+                assert_eq!(
+                    plan.contains("MaterializeIndex"),
+                    indexed,
+                    "{filter}: {plan}"
+                );
+                assert_eq!(scanner.count_rows().await.unwrap(), expected, "{filter}");
+                plan
+            }
+        };
+        // The indices are only searched for the selective queries.
+        check("status = 'rare'", true, 1000).await;
+        check("status = 'common'", false, 9000).await;
+        check("status != 'rare'", false, 9000).await;
+        check("id < 100", true, 100).await;
+        check("id >= 100", false, 9900).await;
+        check("id >= 100 OR status = 'rare'", false, 9910).await;
+
+        // The unselective query refines the results of the selective one.
+        let plan = check("id >= 100 AND status = 'rare'", true, 990).await;
+        assert!(
+            plan.contains("MaterializeIndex: query=status = rare"),
+            "{plan}"
+        );
+    }
 }
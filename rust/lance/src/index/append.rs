@@ -23,58 +23,137 @@ use snafu::{location, Location};
 use uuid::Uuid;
 
 use crate::dataset::index::unindexed_fragments;
-use crate::dataset::scanner::ColumnOrdering;
 use crate::dataset::Dataset;
 use crate::index::vector::ivf::IVFIndex;
 
-use super::DatasetIndexInternalExt;
+use super::scalar::{build_scalar_index, scalar_index_params, scan_index_column};
+use super::{DatasetIndexInternalExt, OptimizeOptions};
 
-/// Append new data to the index, without re-train.
+/// Merge the latest deltas of an index, together with the new data, into a new index,
+/// without re-train.
 ///
-/// Returns the UUID of the new index along with a vector of newly indexed fragment ids
-pub async fn append_index(
+/// `old_indices` are the deltas of one index, in the order they were committed.
+///
+/// Returns the UUID of the new index, the deltas it replaces, and the fragment ids it
+/// covers.
+pub async fn merge_indices<'a>(
     dataset: Arc<Dataset>,
-    old_index: &IndexMetadata,
-) -> Result<Option<(Uuid, Option<RoaringBitmap>)>> {
-    let unindexed = unindexed_fragments(old_index, dataset.as_ref()).await?;
-    if unindexed.is_empty() {
+    old_indices: &[&'a IndexMetadata],
+    options: &OptimizeOptions,
+) -> Result<Option<(Uuid, Vec<&'a IndexMetadata>, Option<RoaringBitmap>)>> {
+    let Some(last_index) = old_indices.last() else {
         return Ok(None);
     };
-
-    let frag_bitmap = old_index.fragment_bitmap.as_ref().map(|bitmap| {
-        let mut bitmap = bitmap.clone();
-        bitmap.extend(unindexed.iter().map(|frag| frag.id as u32));
-        bitmap
-    });
+    let deltas = old_indices
+        .iter()
+        .map(|&idx| idx.clone())
+        .collect::<Vec<_>>();
+    let unindexed = unindexed_fragments(&deltas, dataset.as_ref()).await?;
 
     let column = dataset
         .schema()
-        .field_by_id(old_index.fields[0])
+        .field_by_id(last_index.fields[0])
         .ok_or(Error::Index {
             message: format!(
                 "Append index: column {} does not exist",
-                old_index.fields[0]
+                last_index.fields[0]
             ),
             location: location!(),
         })?;
 
     let index = dataset
-        .open_generic_index(&column.name, &old_index.uuid.to_string())
+        .open_generic_index(&column.name, &last_index.uuid.to_string())
         .await?;
 
     match index.index_type() {
         IndexType::Scalar => {
+            let num_to_merge = options
+                .num_indices_to_merge
+                .unwrap_or(old_indices.len())
+                .min(old_indices.len());
+            if unindexed.is_empty() && num_to_merge <= 1 {
+                return Ok(None);
+            }
+
+            // The path of a field of a struct column, e.g., `metadata.source`.
+            let column_path = dataset
+                .schema()
+                .field_path(last_index.fields[0])
+                .unwrap_or_else(|| column.name.clone());
+            let new_uuid = Uuid::new_v4();
+
+            if num_to_merge == 0 {
+                // Only the new data is indexed, into a new delta of the same kind.
+                let last_uuid = last_index.uuid.to_string();
+                let params = scalar_index_params(dataset.as_ref(), &last_uuid).await?;
+                let frag_bitmap = RoaringBitmap::from_iter(unindexed.iter().map(|f| f.id as u32));
+                build_scalar_index(
+                    dataset.as_ref(),
+                    &column_path,
+                    &new_uuid.to_string(),
+                    &params,
+                    Some(unindexed),
+                )
+                .await?;
+                return Ok(Some((new_uuid, vec![], Some(frag_bitmap))));
+            }
+
+            // The oldest of the deltas to merge is updated with the data of the other
+            // deltas and the new data, as the deltas cover different fragments.
+            let to_merge = &old_indices[old_indices.len() - num_to_merge..];
+            let frag_bitmap = if to_merge.iter().all(|idx| idx.fragment_bitmap.is_some()) {
+                let mut bitmap = RoaringBitmap::from_iter(unindexed.iter().map(|f| f.id as u32));
+                for idx in to_merge.iter() {
+                    bitmap |= idx.fragment_bitmap.as_ref().unwrap();
+                }
+                Some(bitmap)
+            } else {
+                None
+            };
+            let merged_frags = to_merge[1..]
+                .iter()
+                .filter_map(|idx| idx.fragment_bitmap.as_ref())
+                .fold(RoaringBitmap::new(), |acc, bitmap| acc | bitmap);
+            let mut new_frags = dataset
+                .fragments()
+                .iter()
+                .filter(|frag| merged_frags.contains(frag.id as u32))
+                .cloned()
+                .collect::<Vec<_>>();
+            new_frags.extend(unindexed);
+
+            let index = dataset
+                .open_scalar_index(&column.name, &to_merge[0].uuid.to_string())
+                .await?;
+            let new_data_stream =
+                scan_index_column(&dataset, &column_path, Some(new_frags), true).await?;
+
+            let index_dir = dataset.indices_dir().child(new_uuid.to_string());
+            let new_store = LanceIndexStore::new((*dataset.object_store).clone(), index_dir);
+
+            index.update(new_data_stream, &new_store).await?;
+
+            Ok(Some((new_uuid, to_merge.to_vec(), frag_bitmap)))
+        }
+        IndexType::Inverted => {
+            if unindexed.is_empty() {
+                return Ok(None);
+            }
+            let frag_bitmap = last_index.fragment_bitmap.as_ref().map(|bitmap| {
+                let mut bitmap = bitmap.clone();
+                bitmap.extend(unindexed.iter().map(|frag| frag.id as u32));
+                bitmap
+            });
+
             let index = dataset
-                .open_scalar_index(&column.name, &old_index.uuid.to_string())
+                .open_inverted_index(&column.name, &last_index.uuid.to_string())
                 .await?;
 
+            // The documents do not need to be in order.
             let mut scanner = dataset.scan();
             scanner
                 .with_fragments(unindexed)
-                .with_row_id()
-                .order_by(Some(vec![ColumnOrdering::asc_nulls_first(
-                    column.name.clone(),
-                )]))?
+                .with_index_row_id()
                 .project(&[&column.name])?;
             let new_data_stream = scanner.try_into_stream().await?;
 
@@ -85,29 +164,71 @@ pub async fn append_index(
 
             index.update(new_data_stream.into(), &new_store).await?;
 
-            Ok(Some((new_uuid, frag_bitmap)))
+            Ok(Some((new_uuid, vec![*last_index], frag_bitmap)))
         }
         IndexType::Vector => {
-            let mut scanner = dataset.scan();
-            scanner.with_fragments(unindexed);
-            scanner.with_row_id();
-            scanner.project(&[&column.name])?;
-            let new_data_stream = scanner.try_into_stream().await?;
-
+            let num_to_merge = options
+                .num_indices_to_merge
+                .unwrap_or(old_indices.len())
+                .min(old_indices.len());
+            if unindexed.is_empty() && num_to_merge <= 1 {
+                return Ok(None);
+            }
+            let to_merge = &old_indices[old_indices.len() - num_to_merge..];
+
+            let mut ivf_indices = Vec::with_capacity(to_merge.len());
+            for idx in to_merge.iter() {
+                let index = dataset
+                    .open_vector_index(&column.name, idx.uuid.to_string().as_str())
+                    .await?;
+                if !index.as_any().is::<IVFIndex>() {
+                    info!("Index type: {:?} does not support append", index);
+                    return Ok(None);
+                }
+                ivf_indices.push(index);
+            }
+            let ivf_indices = ivf_indices
+                .iter()
+                .map(|idx| idx.as_any().downcast_ref::<IVFIndex>().unwrap())
+                .collect::<Vec<_>>();
+
+            // The new data is partitioned with the model of the latest delta.
             let index = dataset
-                .open_vector_index(&column.name, old_index.uuid.to_string().as_str())
+                .open_vector_index(&column.name, last_index.uuid.to_string().as_str())
                 .await?;
-
             let Some(ivf_idx) = index.as_any().downcast_ref::<IVFIndex>() else {
                 info!("Index type: {:?} does not support append", index);
                 return Ok(None);
             };
 
+            let mut scanner = dataset.scan();
+            scanner.with_fragments(unindexed.clone());
+            scanner.with_index_row_id();
+            scanner.project(&[&column.name])?;
+            let new_data_stream = scanner.try_into_stream().await?;
+
             let new_index = ivf_idx
-                .append(dataset.as_ref(), new_data_stream, old_index, &column.name)
+                .append(
+                    dataset.as_ref(),
+                    new_data_stream,
+                    &ivf_indices,
+                    last_index,
+                    &column.name,
+                )
                 .await?;
 
-            Ok(Some((new_index, frag_bitmap)))
+            // The new index covers the merged deltas and the new data.
+            let frag_bitmap = if to_merge.iter().all(|idx| idx.fragment_bitmap.is_some()) {
+                let mut bitmap = RoaringBitmap::from_iter(unindexed.iter().map(|f| f.id as u32));
+                for idx in to_merge.iter() {
+                    bitmap |= idx.fragment_bitmap.as_ref().unwrap();
+                }
+                Some(bitmap)
+            } else {
+                None
+            };
+
+            Ok(Some((new_index, to_merge.to_vec(), frag_bitmap)))
         }
     }
 }
@@ -117,7 +238,9 @@ mod tests {
     use super::*;
 
     use arrow_array::cast::AsArray;
-    use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator};
+    use arrow_array::{
+        types::UInt64Type, FixedSizeListArray, Int32Array, RecordBatch, RecordBatchIterator,
+    };
     use arrow_schema::{DataType, Field, Schema};
     use futures::{stream, StreamExt, TryStreamExt};
     use lance_arrow::FixedSizeListArrayExt;
@@ -129,6 +252,8 @@ mod tests {
     use lance_testing::datagen::generate_random_array;
     use tempfile::tempdir;
 
+    use crate::format::RowAddress;
+    use crate::index::scalar::ScalarIndexParams;
     use crate::index::vector::{pq::PQIndex, VectorIndexParams};
     use crate::index::DatasetIndexExt;
 
@@ -175,8 +300,8 @@ mod tests {
         let batches = RecordBatchIterator::new(vec![batch].into_iter().map(Ok), schema.clone());
         dataset.append(batches, None).await.unwrap();
 
-        let index = &dataset.load_indices().await.unwrap()[0];
-        assert!(!unindexed_fragments(index, &dataset)
+        let indices = dataset.load_indices().await.unwrap();
+        assert!(!unindexed_fragments(&indices, &dataset)
             .await
             .unwrap()
             .is_empty());
@@ -193,9 +318,13 @@ mod tests {
             .unwrap();
         assert_eq!(results[0].num_rows(), 10); // Flat search.
 
-        dataset.optimize_indices().await.unwrap();
-        let index = &dataset.load_indices().await.unwrap()[0];
-        assert!(unindexed_fragments(index, &dataset)
+        dataset
+            .optimize_indices(&OptimizeOptions::default())
+            .await
+            .unwrap();
+        let indices = dataset.load_indices().await.unwrap();
+        let index = &indices[0];
+        assert!(unindexed_fragments(&indices, &dataset)
             .await
             .unwrap()
             .is_empty());
@@ -248,4 +377,210 @@ mod tests {
         assert_eq!(row_in_index, 2000);
         assert_eq!(dataset.index_cache_entry_count(), 6)
     }
+
+    #[tokio::test]
+    async fn test_optimize_with_deltas() {
+        const DIM: usize = 16;
+        const IVF_PARTITIONS: usize = 2;
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                DIM as i32,
+            ),
+            true,
+        )]));
+        let make_batches = || {
+            let vectors = generate_random_array(1000 * DIM);
+            let array =
+                Arc::new(FixedSizeListArray::try_new_from_values(vectors, DIM as i32).unwrap());
+            let batch = RecordBatch::try_new(schema.clone(), vec![array.clone()]).unwrap();
+            (
+                RecordBatchIterator::new(vec![batch].into_iter().map(Ok), schema.clone()),
+                array,
+            )
+        };
+
+        let (batches, _) = make_batches();
+        let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+        let params = VectorIndexParams::with_ivf_pq_params(
+            MetricType::L2,
+            IvfBuildParams::new(IVF_PARTITIONS),
+            PQBuildParams::new(2, 8),
+        );
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        // Index each append into a new delta.
+        let delta_options = OptimizeOptions {
+            num_indices_to_merge: Some(0),
+        };
+        let mut appended = vec![];
+        for _ in 0..2 {
+            let (batches, array) = make_batches();
+            dataset.append(batches, None).await.unwrap();
+            dataset.optimize_indices(&delta_options).await.unwrap();
+            appended.push(array);
+        }
+        let indices = dataset.load_indices().await.unwrap();
+        assert_eq!(indices.len(), 3);
+        assert!(indices.iter().all(|idx| idx.name == indices[0].name));
+        assert!(unindexed_fragments(&indices, &dataset)
+            .await
+            .unwrap()
+            .is_empty());
+
+        // The vectors in the deltas are found by the index search.
+        let search = |dataset: &Dataset, q: Arc<dyn arrow_array::Array>| {
+            let mut scanner = dataset.scan();
+            scanner
+                .nearest("vector", q.as_primitive(), 5)
+                .unwrap()
+                .nprobs(IVF_PARTITIONS)
+                .refine(10)
+                .with_row_id();
+            async move {
+                let results = scanner
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert!(!scanner
+                    .explain_plan(false)
+                    .await
+                    .unwrap()
+                    .contains("LanceScan"));
+                results[0]["_rowid"].as_primitive::<UInt64Type>().value(0)
+            }
+        };
+        let q = appended[1].value(7);
+        let row_id = search(&dataset, q.clone()).await;
+        assert_eq!(RowAddress::new_from_id(row_id).fragment_id(), 2);
+
+        // Merge all the deltas into one index.
+        dataset
+            .optimize_indices(&OptimizeOptions::default())
+            .await
+            .unwrap();
+        let indices = dataset.load_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(
+            indices[0].fragment_bitmap.as_ref().unwrap().len(),
+            dataset.get_fragments().len() as u64
+        );
+        let row_id = search(&dataset, q).await;
+        assert_eq!(RowAddress::new_from_id(row_id).fragment_id(), 2);
+
+        let binding = dataset
+            .open_vector_index("vector", indices[0].uuid.to_string().as_str())
+            .await
+            .unwrap();
+        let ivf_index = binding.as_any().downcast_ref::<IVFIndex>().unwrap();
+        let mut row_in_index = 0;
+        for part_id in 0..IVF_PARTITIONS {
+            let part = ivf_index.load_partition(part_id, true).await.unwrap();
+            let pq_idx = part.as_any().downcast_ref::<PQIndex>().unwrap();
+            row_in_index += pq_idx.row_ids.as_ref().unwrap().len();
+        }
+        assert_eq!(row_in_index, 3000);
+    }
+
+    #[tokio::test]
+    async fn test_scalar_index_deltas() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let make_batches = |start: i32| {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from_iter_values(start..start + 100))],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![batch].into_iter().map(Ok), schema.clone())
+        };
+
+        let mut dataset = Dataset::write(make_batches(0), test_uri, None)
+            .await
+            .unwrap();
+        dataset
+            .create_index(
+                &["i"],
+                IndexType::Scalar,
+                None,
+                &ScalarIndexParams::default(),
+                true,
+            )
+            .await
+            .unwrap();
+
+        // The filter finds the rows of both the indexed and the appended fragments.
+        let count = |dataset: &Dataset, filter: &str| {
+            let mut scanner = dataset.scan();
+            scanner.filter(filter).unwrap();
+            async move {
+                assert!(scanner
+                    .explain_plan(false)
+                    .await
+                    .unwrap()
+                    .contains("MaterializeIndex"));
+                scanner
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap()
+                    .iter()
+                    .map(|batch| batch.num_rows())
+                    .sum::<usize>()
+            }
+        };
+        dataset.append(make_batches(100), None).await.unwrap();
+        assert_eq!(count(&dataset, "i >= 95 AND i < 105").await, 10);
+
+        // Index each append into a new delta.
+        let delta_options = OptimizeOptions {
+            num_indices_to_merge: Some(0),
+        };
+        dataset.optimize_indices(&delta_options).await.unwrap();
+        dataset.append(make_batches(200), None).await.unwrap();
+        dataset.optimize_indices(&delta_options).await.unwrap();
+        let indices = dataset.load_indices().await.unwrap();
+        assert_eq!(indices.len(), 3);
+        assert!(unindexed_fragments(&indices, &dataset)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(count(&dataset, "i >= 95 AND i < 205").await, 110);
+        assert_eq!(count(&dataset, "i = 250").await, 1);
+
+        // Merge the latest two deltas, then all of them.
+        dataset
+            .optimize_indices(&OptimizeOptions {
+                num_indices_to_merge: Some(2),
+            })
+            .await
+            .unwrap();
+        assert_eq!(dataset.load_indices().await.unwrap().len(), 2);
+        assert_eq!(count(&dataset, "i >= 95 AND i < 205").await, 110);
+
+        dataset
+            .optimize_indices(&OptimizeOptions::default())
+            .await
+            .unwrap();
+        let indices = dataset.load_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices[0].fragment_bitmap.as_ref().unwrap().len(), 3);
+        assert_eq!(count(&dataset, "i >= 95 AND i < 205").await, 110);
+        assert_eq!(count(&dataset, "i != 250").await, 299);
+    }
 }
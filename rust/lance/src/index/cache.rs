@@ -14,10 +14,14 @@
 
 use std::sync::Arc;
 
-use lance_index::scalar::ScalarIndex;
+use lance_index::{
+    scalar::{inverted::InvertedIndex, ScalarIndex},
+    Index,
+};
 use moka::sync::{Cache, ConcurrentCacheExt};
 
 use super::vector::VectorIndex;
+use crate::session::IndexCacheConfig;
 
 use std::sync::atomic::{AtomicU64, Ordering};
 
@@ -37,18 +41,50 @@ impl CacheStats {
     }
 }
 
+/// A snapshot of the statistics of the index cache.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndexCacheStats {
+    /// Number of lookups that found the index in the cache.
+    pub hits: u64,
+
+    /// Number of lookups that had to load the index.
+    pub misses: u64,
+
+    /// Number of cached indices.
+    pub entry_count: usize,
+
+    /// Approximate number of bytes held by the cached indices.
+    pub size_bytes: usize,
+}
+
+fn build_cache<V: Index + ?Sized + 'static>(config: &IndexCacheConfig) -> Cache<String, Arc<V>> {
+    let mut builder = Cache::builder();
+    builder = match config.max_bytes {
+        Some(max_bytes) => builder
+            .max_capacity(max_bytes as u64)
+            .weigher(|_, index: &Arc<V>| index.memory_size().min(u32::MAX as usize) as u32),
+        None => builder.max_capacity(config.max_entries as u64),
+    };
+    if let Some(ttl) = config.time_to_live {
+        builder = builder.time_to_live(ttl);
+    }
+    builder.build()
+}
+
 #[derive(Clone)]
 pub struct IndexCache {
     scalar_cache: Arc<Cache<String, Arc<dyn ScalarIndex>>>,
     vector_cache: Arc<Cache<String, Arc<dyn VectorIndex>>>,
+    inverted_cache: Arc<Cache<String, Arc<InvertedIndex>>>,
     cache_stats: Arc<CacheStats>,
 }
 
 impl IndexCache {
-    pub(crate) fn new(capacity: usize) -> Self {
+    pub(crate) fn new(config: &IndexCacheConfig) -> Self {
         Self {
-            scalar_cache: Arc::new(Cache::new(capacity as u64)),
-            vector_cache: Arc::new(Cache::new(capacity as u64)),
+            scalar_cache: Arc::new(build_cache(config)),
+            vector_cache: Arc::new(build_cache(config)),
+            inverted_cache: Arc::new(build_cache(config)),
             cache_stats: Arc::new(CacheStats::default()),
         }
     }
@@ -62,21 +98,41 @@ impl IndexCache {
     pub(crate) fn get_size(&self) -> usize {
         self.scalar_cache.sync();
         self.vector_cache.sync();
-        self.scalar_cache.entry_count() as usize + self.vector_cache.entry_count() as usize
+        self.inverted_cache.sync();
+        self.scalar_cache.entry_count() as usize
+            + self.vector_cache.entry_count() as usize
+            + self.inverted_cache.entry_count() as usize
     }
 
     /// Get an Index if present. Otherwise returns [None].
     pub(crate) fn get_scalar(&self, key: &str) -> Option<Arc<dyn ScalarIndex>> {
-        self.scalar_cache.get(key)
+        let index = self.scalar_cache.get(key);
+        if index.is_some() {
+            self.cache_stats.record_hit();
+        } else {
+            self.cache_stats.record_miss();
+        }
+        index
     }
 
     pub(crate) fn get_vector(&self, key: &str) -> Option<Arc<dyn VectorIndex>> {
-        if self.vector_cache.contains_key(key) || self.scalar_cache.contains_key(key) {
+        let index = self.vector_cache.get(key);
+        if index.is_some() {
             self.cache_stats.record_hit();
         } else {
             self.cache_stats.record_miss();
         }
-        self.vector_cache.get(key)
+        index
+    }
+
+    pub(crate) fn get_inverted(&self, key: &str) -> Option<Arc<InvertedIndex>> {
+        let index = self.inverted_cache.get(key);
+        if index.is_some() {
+            self.cache_stats.record_hit();
+        } else {
+            self.cache_stats.record_miss();
+        }
+        index
     }
 
     /// Insert a new entry into the cache.
@@ -88,6 +144,10 @@ impl IndexCache {
         self.vector_cache.insert(key.to_string(), index);
     }
 
+    pub(crate) fn insert_inverted(&self, key: &str, index: Arc<InvertedIndex>) {
+        self.inverted_cache.insert(key.to_string(), index);
+    }
+
     /// Get cache hit ratio.
     #[allow(dead_code)]
     pub(crate) fn hit_rate(&self) -> f32 {
@@ -99,4 +159,32 @@ impl IndexCache {
         }
         hits / (hits + misses)
     }
+
+    /// Get the statistics of the cache.
+    pub(crate) fn stats(&self) -> IndexCacheStats {
+        self.scalar_cache.sync();
+        self.vector_cache.sync();
+        self.inverted_cache.sync();
+        let size_bytes = self
+            .scalar_cache
+            .iter()
+            .map(|(_, index)| index.memory_size())
+            .chain(
+                self.vector_cache
+                    .iter()
+                    .map(|(_, index)| index.memory_size()),
+            )
+            .chain(
+                self.inverted_cache
+                    .iter()
+                    .map(|(_, index)| index.memory_size()),
+            )
+            .sum();
+        IndexCacheStats {
+            hits: self.cache_stats.hits.load(Ordering::Relaxed),
+            misses: self.cache_stats.misses.load(Ordering::Relaxed),
+            entry_count: self.get_size(),
+            size_bytes,
+        }
+    }
 }
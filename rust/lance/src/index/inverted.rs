@@ -0,0 +1,95 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Inverted indices for full text search, see [crate::dataset::scanner::Scanner::full_text_search].
+
+use std::sync::Arc;
+
+use arrow_schema::DataType;
+use lance_index::scalar::{
+    inverted::{train_inverted_index, InvertedIndex},
+    lance_format::LanceIndexStore,
+};
+use snafu::{location, Location};
+use tracing::instrument;
+
+use lance_core::{Error, Result};
+
+use crate::Dataset;
+
+use super::IndexParams;
+
+pub use lance_index::scalar::inverted::TokenizerConfig;
+
+/// Parameters of an inverted index.
+#[derive(Debug, Clone, Default)]
+pub struct InvertedIndexParams {
+    /// How the text is split into tokens. The queries are split the same way.
+    pub tokenizer: TokenizerConfig,
+}
+
+impl InvertedIndexParams {
+    pub fn new(tokenizer: TokenizerConfig) -> Self {
+        Self { tokenizer }
+    }
+}
+
+impl IndexParams for InvertedIndexParams {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
+/// Build an inverted index over the string column `column`.
+#[instrument(level = "debug", skip(dataset, params))]
+pub async fn build_inverted_index(
+    dataset: &Dataset,
+    column: &str,
+    uuid: &str,
+    params: &InvertedIndexParams,
+) -> Result<()> {
+    let field = dataset.schema().field(column).ok_or(Error::InvalidInput {
+        source: format!("No column with name {}", column).into(),
+        location: location!(),
+    })?;
+    if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+        return Err(Error::InvalidInput {
+            source: format!(
+                "An inverted index can only be created on a string column, column {} is {}",
+                column,
+                field.data_type()
+            )
+            .into(),
+            location: location!(),
+        });
+    }
+    let index_dir = dataset.indices_dir().child(uuid);
+    let index_store = LanceIndexStore::new((*dataset.object_store).clone(), index_dir);
+    let mut scan = dataset.scan();
+    let data = scan
+        .with_index_row_id()
+        .project(&[column])?
+        .try_into_dfstream()
+        .await?;
+    train_inverted_index(data, params.tokenizer.clone(), &index_store).await
+}
+
+pub async fn open_inverted_index(dataset: &Dataset, uuid: &str) -> Result<Arc<InvertedIndex>> {
+    let index_dir = dataset.indices_dir().child(uuid);
+    let index_store = Arc::new(LanceIndexStore::new(
+        (*dataset.object_store).clone(),
+        index_dir,
+    ));
+    InvertedIndex::load(index_store).await
+}
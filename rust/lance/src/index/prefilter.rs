@@ -16,12 +16,16 @@
 //!
 //! Based on the query, we might have information about which fragment ids and
 //! row ids can be excluded from the search.
+//!
+//! The indices of a dataset with stable row ids are keyed by the stable row ids, which
+//! the prefilter resolves to the addresses of the rows before checking them.
 
 use std::cell::OnceCell;
 use std::collections::HashSet;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+use arrow_array::RecordBatch;
 use async_trait::async_trait;
 use futures::future;
 use futures::stream;
@@ -32,6 +36,7 @@ use roaring::RoaringBitmap;
 use tracing::instrument;
 use tracing::Instrument;
 
+use crate::dataset::IndexRowIds;
 use crate::error::Result;
 use crate::format::Index;
 use crate::utils::future::SharedPrerequisite;
@@ -53,12 +58,28 @@ pub struct PreFilter {
     // these tasks only when we've done as much work as we can without them.
     deleted_ids: Option<Arc<SharedPrerequisite<Arc<RowIdTreeMap>>>>,
     filtered_ids: Option<Arc<SharedPrerequisite<RowIdMask>>>,
+    // The addresses of the rows, if the index is keyed by stable row ids.  The rows
+    // it cannot resolve are deleted, so the deleted ids need not be loaded.
+    row_ids: Option<Arc<SharedPrerequisite<Arc<IndexRowIds>>>>,
     // When the tasks are finished this is the combined filter
     final_mask: Mutex<OnceCell<RowIdMask>>,
 }
 
 impl PreFilter {
     pub fn new(dataset: Arc<Dataset>, index: Index, filter: Option<Box<dyn FilterLoader>>) -> Self {
+        let filtered_ids = filter
+            .map(|filtered_ids| SharedPrerequisite::spawn(filtered_ids.load().in_current_span()));
+        if dataset.has_stable_row_ids() {
+            let row_ids =
+                SharedPrerequisite::spawn(Self::load_row_ids(dataset, index).in_current_span());
+            return Self {
+                deleted_ids: None,
+                filtered_ids,
+                row_ids: Some(row_ids),
+                final_mask: Mutex::new(OnceCell::new()),
+            };
+        }
+
         let dataset_ref = dataset.as_ref();
         let mut has_fragment = Vec::new();
         let mut has_deletion_vectors = false;
@@ -74,7 +95,13 @@ impl PreFilter {
             has_fragment[frag.id as usize] = true;
             has_deletion_vectors |= frag.deletion_file.is_some();
         }
-        let has_missing_fragments = has_fragment.iter().any(|&x| !x);
+        let has_missing_fragments = match &index.fragment_bitmap {
+            // Only the fragments covered by the index can leak deleted rows into results.
+            Some(bitmap) => bitmap
+                .iter()
+                .any(|id| !has_fragment.get(id as usize).copied().unwrap_or(false)),
+            None => has_fragment.iter().any(|&x| !x),
+        };
         let dataset_clone = dataset.clone();
         let deleted_ids = if has_missing_fragments || has_deletion_vectors {
             Some(SharedPrerequisite::spawn(
@@ -83,21 +110,27 @@ impl PreFilter {
         } else {
             None
         };
-        let filtered_ids = filter
-            .map(|filtered_ids| SharedPrerequisite::spawn(filtered_ids.load().in_current_span()));
         Self {
             deleted_ids,
             filtered_ids,
+            row_ids: None,
             final_mask: Mutex::new(OnceCell::new()),
         }
     }
 
     pub fn is_empty(&self) -> bool {
-        self.deleted_ids.is_none() && self.filtered_ids.is_none()
+        self.deleted_ids.is_none() && self.filtered_ids.is_none() && self.row_ids.is_none()
     }
 
     /// Check whether a single row id should be included in the query.
     pub fn check_one(&self, row_id: u64) -> bool {
+        let row_id = match &self.row_ids {
+            Some(row_ids) => match row_ids.get_ready().address(row_id) {
+                Some(address) => address,
+                None => return false,
+            },
+            None => row_id,
+        };
         let final_mask = self.final_mask.lock().unwrap();
         final_mask
             .get()
@@ -105,6 +138,12 @@ impl PreFilter {
             .selected(row_id)
     }
 
+    #[instrument(level = "debug", skip_all)]
+    async fn load_row_ids(dataset: Arc<Dataset>, index: Index) -> Result<Arc<IndexRowIds>> {
+        let row_ids = dataset.index_row_ids(&index).await?;
+        Ok(Arc::new(row_ids.expect("the dataset has stable row ids")))
+    }
+
     #[instrument(level = "debug", skip_all)]
     async fn load_deleted_ids(dataset: Arc<Dataset>, index: Index) -> Result<Arc<RowIdTreeMap>> {
         let fragments = dataset.get_fragments();
@@ -137,12 +176,19 @@ impl PreFilter {
 
         let frag_ids_in_dataset: HashSet<u32> =
             HashSet::from_iter(fragments.iter().map(|frag| frag.id() as u32));
-        if let Some(fragment_bitmap) = index.fragment_bitmap {
-            for frag_id in fragment_bitmap.into_iter() {
-                if !frag_ids_in_dataset.contains(&frag_id) {
-                    // Entire fragment has been deleted
-                    deleted_ids.insert_fragment(frag_id);
-                }
+        let indexed_frag_ids = match index.fragment_bitmap {
+            Some(fragment_bitmap) => fragment_bitmap,
+            // Older indices do not record the fragments they cover, so assume they
+            // may cover any fragment id ever assigned in the dataset.
+            None => match dataset.manifest.max_fragment_id() {
+                Some(max_fragment_id) => RoaringBitmap::from_iter(0..=max_fragment_id as u32),
+                None => RoaringBitmap::new(),
+            },
+        };
+        for frag_id in indexed_frag_ids.into_iter() {
+            if !frag_ids_in_dataset.contains(&frag_id) {
+                // Entire fragment has been deleted
+                deleted_ids.insert_fragment(frag_id);
             }
         }
         Ok(Arc::new(deleted_ids))
@@ -161,6 +207,9 @@ impl PreFilter {
         if let Some(deleted_ids) = &self.deleted_ids {
             deleted_ids.wait_ready().await?;
         }
+        if let Some(row_ids) = &self.row_ids {
+            row_ids.wait_ready().await?;
+        }
         let final_mask = self.final_mask.lock().unwrap();
         final_mask.get_or_init(|| {
             let mut combined = RowIdMask::default();
@@ -184,9 +233,96 @@ impl PreFilter {
     #[instrument(level = "debug", skip_all)]
     pub fn filter_row_ids(&self, row_ids: &[u64]) -> Vec<u64> {
         let final_mask = self.final_mask.lock().unwrap();
-        final_mask
+        let final_mask = final_mask
             .get()
-            .expect("filter_row_ids called without call to wait_for_ready")
-            .selected_indices(row_ids)
+            .expect("filter_row_ids called without call to wait_for_ready");
+        let Some(index_row_ids) = &self.row_ids else {
+            return final_mask.selected_indices(row_ids);
+        };
+        let index_row_ids = index_row_ids.get_ready();
+        let (positions, addresses): (Vec<_>, Vec<_>) = row_ids
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, row_id)| Some((pos as u64, index_row_ids.address(*row_id)?)))
+            .unzip();
+        if final_mask.allow_list.is_none() && final_mask.block_list.is_none() {
+            return positions;
+        }
+        final_mask
+            .selected_indices(&addresses)
+            .into_iter()
+            .map(|idx| positions[idx as usize])
+            .collect()
+    }
+
+    /// Replace the row ids the index is keyed by in the `_rowid` column of the results
+    /// of a search by the addresses of the rows.
+    ///
+    /// The results must have been checked by this prefilter.  This method must be called
+    /// after `wait_for_ready`
+    pub fn resolve_row_ids(&self, batch: RecordBatch) -> Result<RecordBatch> {
+        match &self.row_ids {
+            Some(row_ids) => row_ids.get_ready().resolve_batch(batch),
+            None => Ok(batch),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{Int32Array, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema};
+    use tempfile::tempdir;
+    use uuid::Uuid;
+
+    use crate::dataset::WriteParams;
+
+    #[tokio::test]
+    async fn test_prefilter_blocks_deleted_fragments() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from_iter_values(0..30))],
+        )
+        .unwrap();
+        let write_params = WriteParams {
+            max_rows_per_file: 10,
+            max_rows_per_group: 10,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+        assert_eq!(dataset.get_fragments().len(), 3);
+
+        // Drop the last fragment entirely and one row of the first fragment.
+        dataset.delete("i >= 20 or i = 3").await.unwrap();
+        let dataset = Arc::new(dataset);
+
+        let row_ids = [0_u64, 3, (1 << 32) + 5, (2 << 32) + 1];
+        // Indices written by older versions do not record their fragments.
+        for fragment_bitmap in [Some(RoaringBitmap::from_iter(0..3)), None] {
+            let index = Index {
+                uuid: Uuid::new_v4(),
+                fields: vec![0],
+                name: "idx".to_string(),
+                dataset_version: 1,
+                fragment_bitmap,
+            };
+            let pre_filter = PreFilter::new(dataset.clone(), index, None);
+            assert!(!pre_filter.is_empty());
+            pre_filter.wait_for_ready().await.unwrap();
+            assert_eq!(
+                pre_filter.filter_row_ids(&row_ids),
+                vec![0, 2],
+                "deleted rows must be filtered out"
+            );
+        }
     }
 }
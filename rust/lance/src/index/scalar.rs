@@ -17,26 +17,86 @@
 
 use std::sync::Arc;
 
+use arrow_array::{cast::AsArray, make_array, Array, RecordBatch};
+use arrow_buffer::NullBuffer;
+use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema, SchemaRef};
 use async_trait::async_trait;
-use datafusion::physical_plan::SendableRecordBatchStream;
+use datafusion::error::{DataFusionError, Result as DFResult};
+use datafusion::physical_plan::{stream::RecordBatchStreamAdapter, SendableRecordBatchStream};
+use futures::StreamExt;
 use lance_datafusion::chunker::chunk_concat_stream;
 use lance_index::scalar::{
+    bitmap::{train_bitmap_index, BitmapIndex, BITMAP_LOOKUP_NAME},
+    bloomfilter::{
+        supports_bloom_filter, train_bloom_filter_index, BloomFilterIndex, BLOOM_FILTER_NAME,
+    },
     btree::{train_btree_index, BTreeIndex, BtreeTrainingSource},
     flat::FlatIndexMetadata,
+    json::{train_json_index, JsonIndex, JSON_PATHS_NAME},
+    label_list::{
+        supports_label_list, train_label_list_index, LabelListIndex, LABEL_LIST_LOOKUP_NAME,
+    },
     lance_format::LanceIndexStore,
+    ngram::{train_ngram_index, NGramIndex, NGRAM_POSTINGS_NAME},
     ScalarIndex,
 };
 use snafu::{location, Location};
 use tracing::instrument;
 
-use lance_core::{Error, Result};
+use lance_core::{Error, Result, ROW_ID_FIELD};
 
-use crate::{dataset::scanner::ColumnOrdering, Dataset};
+use crate::{dataset::scanner::ColumnOrdering, format::Fragment, Dataset};
 
 use super::IndexParams;
 
+/// The kind of a scalar index.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScalarIndexType {
+    /// A btree of the sorted values, which suits any column.
+    #[default]
+    BTree,
+    /// A bitmap of the rows of each distinct value, which suits columns with few
+    /// distinct values, e.g., a category or a status.
+    Bitmap,
+    /// A bitmap of the rows which contain each 3 characters of a string column, which
+    /// speeds up `LIKE '%substring%'` and `contains()` filters.
+    NGram,
+    /// A bloom filter of the values of each fragment, which suits the point lookups on
+    /// columns with many distinct values, e.g., a uuid or a user id.
+    BloomFilter,
+    /// A bitmap of the rows whose list contains each label, of a list column, which
+    /// speeds up the `array_has_any`, `array_has_all` and `array_has` filters.
+    LabelList,
+    /// A bitmap of the rows of each value at some JSON paths, of a string column of JSON
+    /// documents, which speeds up the `json_extract(column, path)` filters.
+    ///
+    /// The paths are given by [ScalarIndexParams::json_paths].
+    Json,
+}
+
 #[derive(Default)]
-pub struct ScalarIndexParams {}
+pub struct ScalarIndexParams {
+    pub index_type: ScalarIndexType,
+    /// The JSON paths, e.g., `$.type`, to index with a [ScalarIndexType::Json] index.
+    pub json_paths: Vec<String>,
+}
+
+impl ScalarIndexParams {
+    pub fn new(index_type: ScalarIndexType) -> Self {
+        Self {
+            index_type,
+            ..Default::default()
+        }
+    }
+
+    /// Parameters of a JSON index of the values at `paths`.
+    pub fn json(paths: Vec<String>) -> Self {
+        Self {
+            index_type: ScalarIndexType::Json,
+            json_paths: paths,
+        }
+    }
+}
 
 impl IndexParams for ScalarIndexParams {
     fn as_any(&self) -> &dyn std::any::Any {
@@ -47,6 +107,7 @@ impl IndexParams for ScalarIndexParams {
 struct TrainingRequest {
     dataset: Arc<Dataset>,
     column: String,
+    fragments: Option<Vec<Fragment>>,
 }
 
 #[async_trait]
@@ -55,42 +116,237 @@ impl BtreeTrainingSource for TrainingRequest {
         self: Box<Self>,
         chunk_size: u32,
     ) -> Result<SendableRecordBatchStream> {
-        let mut scan = self.dataset.scan();
-        let scan = scan
-            .with_row_id()
-            .order_by(Some(vec![ColumnOrdering::asc_nulls_first(
-                self.column.clone(),
-            )]))?
-            .project(&[&self.column])?;
-
-        let ordered_batches = scan.try_into_dfstream().await?;
+        let ordered_batches =
+            scan_index_column(&self.dataset, &self.column, self.fragments, true).await?;
         Ok(chunk_concat_stream(ordered_batches, chunk_size as usize))
     }
 }
 
-/// Build a Vector Index
-#[instrument(level = "debug", skip(dataset))]
-pub async fn build_scalar_index(dataset: &Dataset, column: &str, uuid: &str) -> Result<()> {
-    let training_request = Box::new(TrainingRequest {
-        dataset: Arc::new(dataset.clone()),
-        column: column.to_string(),
+/// The values of a field of a struct column, for the rows of `batch` with the struct
+/// column and the row ids.
+fn nested_field_batch(
+    batch: &RecordBatch,
+    names: &[&str],
+    schema: SchemaRef,
+) -> DFResult<RecordBatch> {
+    let mut values = batch.column(0).clone();
+    for name in names {
+        let parent = values.as_struct();
+        let Some(child) = parent.column_by_name(name) else {
+            return Err(DataFusionError::Execution(format!(
+                "Struct field {name} not found"
+            )));
+        };
+        // The fields of a null struct are null.
+        let nulls = NullBuffer::union(parent.nulls(), child.nulls());
+        values = make_array(child.to_data().into_builder().nulls(nulls).build()?);
+    }
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![values, batch.column(1).clone()],
+    )?)
+}
+
+/// Scan the `column` and the row ids of `dataset` to train or update a scalar index, only
+/// of the `fragments` if any, and ordered by the column if `ordered`.
+///
+/// A field of a struct column, e.g., `metadata.source`, is returned as a column of its own.
+pub(crate) async fn scan_index_column(
+    dataset: &Dataset,
+    column: &str,
+    fragments: Option<Vec<Fragment>>,
+    ordered: bool,
+) -> Result<SendableRecordBatchStream> {
+    let mut scan = dataset.scan();
+    if let Some(fragments) = fragments {
+        scan.with_fragments(fragments);
+    }
+    if ordered {
+        scan.order_by(Some(vec![ColumnOrdering::asc_nulls_first(
+            column.to_string(),
+        )]))?;
+    }
+    let data = scan
+        .with_index_row_id()
+        .project(&[column])?
+        .try_into_dfstream()
+        .await?;
+
+    let names = column.split('.').collect::<Vec<_>>();
+    if names.len() == 1 {
+        return Ok(data);
+    }
+    let field = dataset.schema().field(column).ok_or(Error::InvalidInput {
+        source: format!("No column with name {}", column).into(),
+        location: location!(),
+    })?;
+    let schema = Arc::new(ArrowSchema::new(vec![
+        ArrowField::new(column, field.data_type(), true),
+        ROW_ID_FIELD.clone(),
+    ]));
+    let names = names[1..]
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    let stream_schema = schema.clone();
+    let batches = data.map(move |batch| {
+        let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+        nested_field_batch(&batch?, &names, schema.clone())
     });
+    Ok(Box::pin(RecordBatchStreamAdapter::new(
+        stream_schema,
+        batches,
+    )))
+}
+
+/// Build a Scalar Index, only of the `fragments` if any, e.g., a new delta of the
+/// fragments appended since the index was built.
+#[instrument(level = "debug", skip(dataset, params, fragments))]
+pub async fn build_scalar_index(
+    dataset: &Dataset,
+    column: &str,
+    uuid: &str,
+    params: &ScalarIndexParams,
+    fragments: Option<Vec<Fragment>>,
+) -> Result<()> {
     let field = dataset.schema().field(column).ok_or(Error::InvalidInput {
         source: format!("No column with name {}", column).into(),
         location: location!(),
     })?;
     // In theory it should be possible to create a scalar index (e.g. btree) on a nested field but
     // performance would be poor and I'm not sure we want to allow that unless there is a need.
-    if field.data_type().is_nested() {
+    if params.index_type == ScalarIndexType::LabelList {
+        if !supports_label_list(&field.data_type()) {
+            return Err(Error::InvalidInput {
+                source: format!(
+                    "A label list index can only be created on a list column, column {} is {}",
+                    column,
+                    field.data_type()
+                )
+                .into(),
+                location: location!(),
+            });
+        }
+    } else if field.data_type().is_nested() {
         return Err(Error::InvalidInput {
             source: "A scalar index can only be created on a non-nested field.".into(),
             location: location!(),
         });
     }
-    let flat_index_trainer = FlatIndexMetadata::new(field.data_type());
     let index_dir = dataset.indices_dir().child(uuid);
     let index_store = LanceIndexStore::new((*dataset.object_store).clone(), index_dir);
-    train_btree_index(training_request, &flat_index_trainer, &index_store).await
+    match params.index_type {
+        ScalarIndexType::BTree => {
+            let training_request = Box::new(TrainingRequest {
+                dataset: Arc::new(dataset.clone()),
+                column: column.to_string(),
+                fragments,
+            });
+            let flat_index_trainer = FlatIndexMetadata::new(field.data_type());
+            train_btree_index(training_request, &flat_index_trainer, &index_store).await
+        }
+        ScalarIndexType::Bitmap => {
+            // The bitmaps do not need the values in order.
+            let data = scan_index_column(dataset, column, fragments, false).await?;
+            train_bitmap_index(data, &index_store).await
+        }
+        ScalarIndexType::NGram => {
+            if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+                return Err(Error::InvalidInput {
+                    source: format!(
+                        "An n-gram index can only be created on a string column, column {} is {}",
+                        column,
+                        field.data_type()
+                    )
+                    .into(),
+                    location: location!(),
+                });
+            }
+            let data = scan_index_column(dataset, column, fragments, false).await?;
+            train_ngram_index(data, &index_store).await
+        }
+        ScalarIndexType::BloomFilter => {
+            if !supports_bloom_filter(&field.data_type()) {
+                return Err(Error::InvalidInput {
+                    source: format!(
+                        "A bloom filter index can not be created on column {} of type {}",
+                        column,
+                        field.data_type()
+                    )
+                    .into(),
+                    location: location!(),
+                });
+            }
+            let data = scan_index_column(dataset, column, fragments, false).await?;
+            train_bloom_filter_index(data, &index_store).await
+        }
+        ScalarIndexType::LabelList => {
+            let data = scan_index_column(dataset, column, fragments, false).await?;
+            train_label_list_index(data, &index_store).await
+        }
+        ScalarIndexType::Json => {
+            if !matches!(field.data_type(), DataType::Utf8 | DataType::LargeUtf8) {
+                return Err(Error::InvalidInput {
+                    source: format!(
+                        "A JSON index can only be created on a string column, column {} is {}",
+                        column,
+                        field.data_type()
+                    )
+                    .into(),
+                    location: location!(),
+                });
+            }
+            let data = scan_index_column(dataset, column, fragments, false).await?;
+            train_json_index(data, &params.json_paths, &index_store).await
+        }
+    }
+}
+
+/// The kind of the scalar index `uuid`.
+///
+/// The bitmap, n-gram, bloom filter, label list and JSON indices are told from a btree index
+/// by their files.
+/// If there are more kinds of scalar indices, we may need to store a metadata file in the
+/// index directory instead.
+pub(crate) async fn detect_scalar_index_type(
+    dataset: &Dataset,
+    uuid: &str,
+) -> Result<ScalarIndexType> {
+    let index_dir = dataset.indices_dir().child(uuid);
+    for (file_name, index_type) in [
+        (BITMAP_LOOKUP_NAME, ScalarIndexType::Bitmap),
+        (NGRAM_POSTINGS_NAME, ScalarIndexType::NGram),
+        (BLOOM_FILTER_NAME, ScalarIndexType::BloomFilter),
+        (LABEL_LIST_LOOKUP_NAME, ScalarIndexType::LabelList),
+        (JSON_PATHS_NAME, ScalarIndexType::Json),
+    ] {
+        if dataset
+            .object_store
+            .exists(&index_dir.child(file_name))
+            .await?
+        {
+            return Ok(index_type);
+        }
+    }
+    Ok(ScalarIndexType::BTree)
+}
+
+/// The parameters the scalar index `uuid` was built with, to build a new delta of it.
+pub(crate) async fn scalar_index_params(
+    dataset: &Dataset,
+    uuid: &str,
+) -> Result<ScalarIndexParams> {
+    let index_type = detect_scalar_index_type(dataset, uuid).await?;
+    if index_type != ScalarIndexType::Json {
+        return Ok(ScalarIndexParams::new(index_type));
+    }
+    let index = open_scalar_index(dataset, uuid).await?;
+    let paths = index
+        .as_any()
+        .downcast_ref::<JsonIndex>()
+        .map(|index| index.paths().map(String::from).collect())
+        .unwrap_or_default();
+    Ok(ScalarIndexParams::json(paths))
 }
 
 pub async fn open_scalar_index(dataset: &Dataset, uuid: &str) -> Result<Arc<dyn ScalarIndex>> {
@@ -99,8 +355,18 @@ pub async fn open_scalar_index(dataset: &Dataset, uuid: &str) -> Result<Arc<dyn
         (*dataset.object_store).clone(),
         index_dir,
     ));
-    // Currently we assume all scalar indices are btree indices.  In the future, if this is not the
-    // case, we may need to store a metadata file in the index directory with scalar index metadata
-    let btree_index = BTreeIndex::load(index_store).await?;
-    Ok(btree_index as Arc<dyn ScalarIndex>)
+    match detect_scalar_index_type(dataset, uuid).await? {
+        ScalarIndexType::BTree => Ok(BTreeIndex::load(index_store).await? as Arc<dyn ScalarIndex>),
+        ScalarIndexType::Bitmap => {
+            Ok(BitmapIndex::load(index_store).await? as Arc<dyn ScalarIndex>)
+        }
+        ScalarIndexType::NGram => Ok(NGramIndex::load(index_store).await? as Arc<dyn ScalarIndex>),
+        ScalarIndexType::BloomFilter => {
+            Ok(BloomFilterIndex::load(index_store).await? as Arc<dyn ScalarIndex>)
+        }
+        ScalarIndexType::LabelList => {
+            Ok(LabelListIndex::load(index_store).await? as Arc<dyn ScalarIndex>)
+        }
+        ScalarIndexType::Json => Ok(JsonIndex::load(index_store).await? as Arc<dyn ScalarIndex>),
+    }
 }
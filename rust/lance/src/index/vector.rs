@@ -18,17 +18,24 @@
 use std::any::Any;
 use std::sync::Arc;
 
+#[cfg(feature = "cuda")]
+pub mod cagra;
 pub mod diskann;
+pub mod flat;
 #[allow(dead_code)]
 mod graph;
+pub mod hnsw;
 pub mod ivf;
-#[cfg(feature = "opq")]
+pub mod normalize;
 pub mod opq;
 pub mod pq;
+pub mod sparse;
+pub mod sq;
 mod traits;
 mod utils;
 
-use lance_core::io::Reader;
+use arrow_schema::DataType;
+use lance_core::io::{Reader, RecordBatchStream};
 use lance_index::vector::{ivf::IvfBuildParams, pq::PQBuildParams};
 use lance_linalg::distance::*;
 use nohash_hasher::IntMap;
@@ -38,19 +45,25 @@ use tracing::instrument;
 use uuid::Uuid;
 
 use self::{
-    ivf::{build_ivf_pq_index, remap_index_file, IVFIndex},
+    flat::FlatIndex,
+    ivf::{
+        build_ivf_flat_index, build_ivf_pq_index, build_ivf_pq_index_from_stream,
+        build_ivf_pq_indices, build_ivf_sq_index, remap_index_file, IVFIndex, IvfPqIndexBuild,
+    },
+    opq::{OPQIndex, OptimizedProductQuantizer},
     pq::PQIndex,
+    sparse::{build_sparse_index, SparseIndex},
+    sq::{SQBuildParams, SQIndex},
 };
 
 use super::{pb, DatasetIndexInternalExt, IndexParams};
-#[cfg(feature = "opq")]
-use crate::index::vector::opq::{OPQIndex, OptimizedProductQuantizer};
 use crate::{
     dataset::Dataset,
     index::{
         pb::vector_index_stage::Stage,
         vector::{
             diskann::{DiskANNIndex, DiskANNParams},
+            hnsw::{HNSWIndex, HNSWParams},
             ivf::Ivf,
         },
     },
@@ -58,6 +71,9 @@ use crate::{
 };
 pub use traits::*;
 
+#[cfg(feature = "cuda")]
+use self::cagra::{CagraIndex, CagraParams};
+
 /// Parameters of each index stage.
 #[derive(Debug, Clone)]
 pub enum StageParams {
@@ -65,7 +81,17 @@ pub enum StageParams {
 
     PQ(PQBuildParams),
 
+    SQ(SQBuildParams),
+
     DiskANN(DiskANNParams),
+
+    Hnsw(HNSWParams),
+
+    #[cfg(feature = "cuda")]
+    Cagra(CagraParams),
+
+    /// Inverted index of sparse vectors, there is no parameter to tune.
+    Sparse,
 }
 
 /// The parameters to build vector index.
@@ -75,6 +101,12 @@ pub struct VectorIndexParams {
 
     /// Vector distance metrics type.
     pub metric_type: MetricType,
+
+    /// Normalize the vectors to unit length when building a `Cosine` index, so the
+    /// index is searched with dot product. Queries are normalized by the index.
+    ///
+    /// Only `IVF_FLAT` supports it, `IVF_PQ` always normalizes cosine vectors.
+    pub normalize: bool,
 }
 
 impl VectorIndexParams {
@@ -110,6 +142,55 @@ impl VectorIndexParams {
         Self {
             stages,
             metric_type,
+            normalize: false,
+        }
+    }
+
+    /// Create index parameters for `IVF_FLAT` index.
+    ///
+    /// Parameters
+    ///
+    ///  - `num_partitions`: the number of IVF partitions.
+    ///  - `metric_type`: how to compute distance, i.e., `L2` or `Cosine`.
+    pub fn ivf_flat(num_partitions: usize, metric_type: MetricType) -> Self {
+        Self::with_ivf_flat_params(metric_type, IvfBuildParams::new(num_partitions))
+    }
+
+    /// Create index parameters with `IVF` parameters, without a PQ stage.
+    pub fn with_ivf_flat_params(metric_type: MetricType, ivf: IvfBuildParams) -> Self {
+        let stages = vec![StageParams::Ivf(ivf)];
+        Self {
+            stages,
+            metric_type,
+            normalize: false,
+        }
+    }
+
+    /// Create index parameters for `IVF_SQ` index, with 8-bit scalar quantization.
+    ///
+    /// Parameters
+    ///
+    ///  - `num_partitions`: the number of IVF partitions.
+    ///  - `metric_type`: how to compute distance, i.e., `L2` or `Cosine`.
+    pub fn ivf_sq(num_partitions: usize, metric_type: MetricType) -> Self {
+        Self::with_ivf_sq_params(
+            metric_type,
+            IvfBuildParams::new(num_partitions),
+            SQBuildParams::default(),
+        )
+    }
+
+    /// Create index parameters with `IVF` and `SQ` parameters, respectively.
+    pub fn with_ivf_sq_params(
+        metric_type: MetricType,
+        ivf: IvfBuildParams,
+        sq: SQBuildParams,
+    ) -> Self {
+        let stages = vec![StageParams::Ivf(ivf), StageParams::SQ(sq)];
+        Self {
+            stages,
+            metric_type,
+            normalize: false,
         }
     }
 
@@ -123,6 +204,7 @@ impl VectorIndexParams {
         Self {
             stages,
             metric_type,
+            normalize: false,
         }
     }
 
@@ -131,6 +213,47 @@ impl VectorIndexParams {
         Self {
             stages,
             metric_type,
+            normalize: false,
+        }
+    }
+
+    /// Create index parameters for `HNSW` index.
+    pub fn with_hnsw_params(metric_type: MetricType, hnsw: HNSWParams) -> Self {
+        let stages = vec![StageParams::Hnsw(hnsw)];
+        Self {
+            stages,
+            metric_type,
+            normalize: false,
+        }
+    }
+
+    /// Normalize the vectors of a `Cosine` index when building it.
+    pub fn normalize(&mut self, normalize: bool) -> &mut Self {
+        self.normalize = normalize;
+        self
+    }
+
+    /// Create index parameters for `CAGRA` index.
+    #[cfg(feature = "cuda")]
+    pub fn with_cagra_params(metric_type: MetricType, cagra: CagraParams) -> Self {
+        let stages = vec![StageParams::Cagra(cagra)];
+        Self {
+            stages,
+            metric_type,
+            normalize: false,
+        }
+    }
+}
+
+impl VectorIndexParams {
+    /// Create index parameters for the inverted index of a sparse vector column.
+    ///
+    /// Sparse vectors are ranked by dot product.
+    pub fn sparse() -> Self {
+        Self {
+            stages: vec![StageParams::Sparse],
+            metric_type: MetricType::Dot,
+            normalize: false,
         }
     }
 }
@@ -151,6 +274,14 @@ fn is_ivf_pq(stages: &[StageParams]) -> bool {
         && matches!(&stages[len - 2], StageParams::Ivf(_))
 }
 
+fn is_ivf_flat(stages: &[StageParams]) -> bool {
+    matches!(stages, [StageParams::Ivf(_)])
+}
+
+fn is_ivf_sq(stages: &[StageParams]) -> bool {
+    matches!(stages, [StageParams::Ivf(_), StageParams::SQ(_)])
+}
+
 fn is_diskann(stages: &[StageParams]) -> bool {
     if stages.is_empty() {
         return false;
@@ -159,6 +290,20 @@ fn is_diskann(stages: &[StageParams]) -> bool {
     matches!(last, StageParams::DiskANN(_))
 }
 
+fn is_hnsw(stages: &[StageParams]) -> bool {
+    matches!(stages, [StageParams::Hnsw(_)])
+}
+
+fn is_sparse(stages: &[StageParams]) -> bool {
+    matches!(stages, [StageParams::Sparse])
+}
+
+/// Whether `data_type` is a vector, multivector or sparse vector column.
+pub fn is_vector_type(data_type: &DataType) -> bool {
+    matches!(data_type, DataType::FixedSizeList(..) | DataType::List(_))
+        || lance_index::vector::sparse::is_sparse_vector_type(data_type)
+}
+
 /// Build a Vector Index
 #[instrument(level = "debug", skip(dataset))]
 pub(crate) async fn build_vector_index(
@@ -176,6 +321,48 @@ pub(crate) async fn build_vector_index(
             location: location!(),
         });
     };
+    if params.normalize && params.metric_type != MetricType::Cosine {
+        return Err(Error::Index {
+            message: format!(
+                "Build Vector Index: only cosine vectors can be normalized, got {}",
+                params.metric_type
+            ),
+            location: location!(),
+        });
+    }
+    if params.normalize && !(is_ivf_flat(stages) || is_ivf_pq(stages)) {
+        return Err(Error::Index {
+            message: format!(
+                "Build Vector Index: normalization is not supported by stages: {:?}",
+                stages
+            ),
+            location: location!(),
+        });
+    }
+    // The index file can not tell which function a custom distance refers to.
+    if let MetricType::Custom(name) = params.metric_type {
+        return Err(Error::Index {
+            message: format!(
+                "Build Vector Index: custom distance {} is only supported by flat search",
+                name.as_str()
+            ),
+            location: location!(),
+        });
+    }
+
+    #[cfg(feature = "cuda")]
+    if let [StageParams::Cagra(cagra_params)] = stages.as_slice() {
+        use self::cagra::build_cagra_index;
+        return build_cagra_index(
+            dataset,
+            column,
+            name,
+            uuid,
+            params.metric_type,
+            cagra_params,
+        )
+        .await;
+    }
 
     if is_ivf_pq(stages) {
         // This is a IVF PQ index.
@@ -202,16 +389,63 @@ pub(crate) async fn build_vector_index(
             pq_params,
         )
         .await?
+    } else if is_ivf_flat(stages) {
+        let StageParams::Ivf(ivf_params) = &stages[0] else {
+            return Err(Error::Index {
+                message: format!("Build Vector Index: invalid stages: {:?}", stages),
+                location: location!(),
+            });
+        };
+        build_ivf_flat_index(
+            dataset,
+            column,
+            name,
+            uuid,
+            params.metric_type,
+            ivf_params,
+            params.normalize,
+        )
+        .await?
+    } else if is_ivf_sq(stages) {
+        let [StageParams::Ivf(ivf_params), StageParams::SQ(sq_params)] = stages.as_slice() else {
+            return Err(Error::Index {
+                message: format!("Build Vector Index: invalid stages: {:?}", stages),
+                location: location!(),
+            });
+        };
+        build_ivf_sq_index(
+            dataset,
+            column,
+            name,
+            uuid,
+            params.metric_type,
+            ivf_params,
+            sq_params,
+        )
+        .await?
     } else if is_diskann(stages) {
         // This is DiskANN index.
         use self::diskann::build_diskann_index;
-        let StageParams::DiskANN(params) = stages.last().unwrap() else {
+        let StageParams::DiskANN(diskann_params) = stages.last().unwrap() else {
             return Err(Error::Index {
                 message: format!("Build Vector Index: invalid stages: {:?}", stages),
                 location: location!(),
             });
         };
-        build_diskann_index(dataset, column, name, uuid, params.clone()).await?;
+        let mut diskann_params = diskann_params.clone();
+        diskann_params.metric_type = params.metric_type;
+        build_diskann_index(dataset, column, name, uuid, diskann_params).await?;
+    } else if is_hnsw(stages) {
+        use self::hnsw::build_hnsw_index;
+        let StageParams::Hnsw(hnsw_params) = &stages[0] else {
+            return Err(Error::Index {
+                message: format!("Build Vector Index: invalid stages: {:?}", stages),
+                location: location!(),
+            });
+        };
+        build_hnsw_index(dataset, column, name, uuid, params.metric_type, hnsw_params).await?;
+    } else if is_sparse(stages) {
+        build_sparse_index(dataset, column, name, uuid, params.metric_type).await?;
     } else {
         return Err(Error::Index {
             message: format!("Build Vector Index: invalid stages: {:?}", stages),
@@ -222,6 +456,101 @@ pub(crate) async fn build_vector_index(
     Ok(())
 }
 
+/// Build Vector Indices on several columns with one scan of the dataset.
+///
+/// Each of `builds` is the column, the name, the uuid and the parameters of an
+/// index. Only IVF_PQ indices can be built together.
+#[instrument(level = "debug", skip_all)]
+pub(crate) async fn build_vector_indices(
+    dataset: &Dataset,
+    builds: &[(&str, &str, &str, &VectorIndexParams)],
+) -> Result<()> {
+    let mut ivf_pq_builds = Vec::with_capacity(builds.len());
+    for &(column, index_name, uuid, params) in builds {
+        let stages = &params.stages;
+        if !is_ivf_pq(stages) {
+            return Err(Error::Index {
+                message: format!(
+                    "Build Vector Indices: only IVF_PQ is supported, got stages: {:?}",
+                    stages
+                ),
+                location: location!(),
+            });
+        }
+        if let MetricType::Custom(name) = params.metric_type {
+            return Err(Error::Index {
+                message: format!(
+                    "Build Vector Index: custom distance {} is only supported by flat search",
+                    name.as_str()
+                ),
+                location: location!(),
+            });
+        }
+        let len = stages.len();
+        let (StageParams::Ivf(ivf_params), StageParams::PQ(pq_params)) =
+            (&stages[len - 2], &stages[len - 1])
+        else {
+            return Err(Error::Index {
+                message: format!("Build Vector Index: invalid stages: {:?}", stages),
+                location: location!(),
+            });
+        };
+        ivf_pq_builds.push(IvfPqIndexBuild {
+            column,
+            index_name,
+            uuid,
+            metric_type: params.metric_type,
+            ivf_params,
+            pq_params,
+        });
+    }
+    build_ivf_pq_indices(dataset, &ivf_pq_builds).await
+}
+
+/// Build a Vector Index over the `_rowid` and `column` batches of `stream`.
+///
+/// Only IVF_PQ indices can be built from a stream.
+#[instrument(level = "debug", skip(dataset, stream))]
+pub(crate) async fn build_vector_index_from_stream(
+    dataset: &Dataset,
+    column: &str,
+    name: &str,
+    uuid: &str,
+    params: &VectorIndexParams,
+    stream: impl RecordBatchStream + Unpin + 'static,
+) -> Result<()> {
+    let stages = &params.stages;
+    if !is_ivf_pq(stages) {
+        return Err(Error::Index {
+            message: format!(
+                "Build Vector Index from stream: only IVF_PQ is supported, got stages: {:?}",
+                stages
+            ),
+            location: location!(),
+        });
+    }
+    let len = stages.len();
+    let (StageParams::Ivf(ivf_params), StageParams::PQ(pq_params)) =
+        (&stages[len - 2], &stages[len - 1])
+    else {
+        return Err(Error::Index {
+            message: format!("Build Vector Index: invalid stages: {:?}", stages),
+            location: location!(),
+        });
+    };
+    build_ivf_pq_index_from_stream(
+        dataset,
+        column,
+        name,
+        uuid,
+        params.metric_type,
+        ivf_params,
+        pq_params,
+        stream,
+    )
+    .await
+}
+
 #[instrument(level = "debug", skip_all, fields(old_uuid = old_uuid.to_string(), new_uuid = new_uuid.to_string(), num_rows = mapping.len()))]
 pub(crate) async fn remap_vector_index(
     dataset: Arc<Dataset>,
@@ -253,10 +582,14 @@ pub(crate) async fn remap_vector_index(
         mapping,
         old_metadata.name.clone(),
         column.to_string(),
-        // We can safely assume there are no transforms today.  We assert above that the
-        // top stage is IVF and IVF does not support transforms between IVF and PQ.  This
+        // We assert above that the top stage is IVF, so normalization is the only
+        // transform, and IVF does not support transforms between IVF and PQ.  This
         // will be fixed in the future.
-        vec![],
+        if ivf_index.normalized {
+            vec![normalize::L2Normalizer::to_proto()]
+        } else {
+            vec![]
+        },
     )
     .await?;
     Ok(())
@@ -273,12 +606,22 @@ pub(crate) async fn open_vector_index(
     reader: Arc<dyn Reader>,
 ) -> Result<Arc<dyn VectorIndex>> {
     let metric_type = pb::VectorMetricType::try_from(vec_idx.metric_type)?.into();
+    // Normalized vectors are searched with L2 in IVF and its PQ sub-index, and with
+    // dot product by the flat sub-index.
+    let normalized = vec_idx.stages.iter().any(|stg| {
+        matches!(stg.stage.as_ref(),
+            Some(Stage::Transform(tf)) if tf.r#type() == pb::TransformType::Normalize)
+    });
+    let sub_index_metric_type = if normalized {
+        MetricType::L2
+    } else {
+        metric_type
+    };
 
     let mut last_stage: Option<Arc<dyn VectorIndex>> = None;
 
     for stg in vec_idx.stages.iter().rev() {
         match stg.stage.as_ref() {
-            #[allow(unused_variables)]
             Some(Stage::Transform(tf)) => {
                 if last_stage.is_none() {
                     return Err(Error::Index {
@@ -286,7 +629,6 @@ pub(crate) async fn open_vector_index(
                         location: location!(),
                     });
                 }
-                #[cfg(feature = "opq")]
                 match tf.r#type() {
                     pb::TransformType::Opq => {
                         let opq = OptimizedProductQuantizer::load(
@@ -304,6 +646,8 @@ pub(crate) async fn open_vector_index(
                             opq,
                         )));
                     }
+                    // IVF normalizes the query itself, see below.
+                    pb::TransformType::Normalize => {}
                 }
             }
             Some(Stage::Ivf(ivf_pb)) => {
@@ -321,6 +665,7 @@ pub(crate) async fn open_vector_index(
                     reader.clone(),
                     last_stage.unwrap(),
                     metric_type,
+                    normalized,
                 )?));
             }
             Some(Stage::Pq(pq_proto)) => {
@@ -330,8 +675,49 @@ pub(crate) async fn open_vector_index(
                         location: location!(),
                     });
                 };
-                let pq = lance_index::vector::pq::builder::from_proto(pq_proto, metric_type)?;
-                last_stage = Some(Arc::new(PQIndex::new(pq, metric_type)));
+                let pq =
+                    lance_index::vector::pq::builder::from_proto(pq_proto, sub_index_metric_type)?;
+                last_stage = Some(Arc::new(PQIndex::new(pq, sub_index_metric_type)));
+            }
+            Some(Stage::Sq(sq_proto)) => {
+                if last_stage.is_some() {
+                    return Err(Error::Index {
+                        message: format!("Invalid vector index stages: {:?}", vec_idx.stages),
+                        location: location!(),
+                    });
+                };
+                let sq = lance_index::vector::sq::from_proto(sq_proto)?;
+                last_stage = Some(Arc::new(SQIndex::new(sq, metric_type)));
+            }
+            Some(Stage::Flat(_)) => {
+                if last_stage.is_some() {
+                    return Err(Error::Index {
+                        message: format!("Invalid vector index stages: {:?}", vec_idx.stages),
+                        location: location!(),
+                    });
+                };
+                let field = dataset.schema().field(column).ok_or_else(|| Error::Index {
+                    message: format!("Column {} does not exist in dataset", column),
+                    location: location!(),
+                })?;
+                let DataType::FixedSizeList(value_field, _) = field.data_type() else {
+                    return Err(Error::Index {
+                        message: format!(
+                            "Flat index requires the column to be fixed size list, got {}",
+                            field.data_type()
+                        ),
+                        location: location!(),
+                    });
+                };
+                last_stage = Some(Arc::new(FlatIndex::new(
+                    vec_idx.dimension as usize,
+                    value_field.data_type().clone(),
+                    if normalized {
+                        MetricType::Dot
+                    } else {
+                        metric_type
+                    },
+                )));
             }
             Some(Stage::Diskann(diskann_proto)) => {
                 if last_stage.is_some() {
@@ -344,10 +730,77 @@ pub(crate) async fn open_vector_index(
                     });
                 };
                 let graph_path = index_dir.child(diskann_proto.filename.as_str());
-                let diskann =
-                    Arc::new(DiskANNIndex::try_new(dataset.clone(), column, &graph_path).await?);
+                let diskann = Arc::new(
+                    DiskANNIndex::try_new(
+                        dataset.clone(),
+                        column,
+                        &graph_path,
+                        diskann_proto,
+                        metric_type,
+                    )
+                    .await?,
+                );
                 last_stage = Some(diskann);
             }
+            Some(Stage::Hnsw(hnsw_proto)) => {
+                if last_stage.is_some() {
+                    return Err(Error::Index {
+                        message: format!(
+                            "HNSW should be the only stage, but we got stages: {:?}",
+                            vec_idx.stages
+                        ),
+                        location: location!(),
+                    });
+                };
+                let graph_path = index_dir.child(hnsw_proto.filename.as_str());
+                let hnsw =
+                    HNSWIndex::load(dataset.object_store(), &graph_path, hnsw_proto, metric_type)
+                        .await?;
+                last_stage = Some(Arc::new(hnsw));
+            }
+            #[cfg(feature = "cuda")]
+            Some(Stage::Cagra(cagra_proto)) => {
+                if last_stage.is_some() {
+                    return Err(Error::Index {
+                        message: format!(
+                            "CAGRA should be the only stage, but we got stages: {:?}",
+                            vec_idx.stages
+                        ),
+                        location: location!(),
+                    });
+                };
+                let graph_path = index_dir.child(cagra_proto.filename.as_str());
+                let cagra = CagraIndex::load(
+                    dataset.object_store(),
+                    &graph_path,
+                    cagra_proto,
+                    metric_type,
+                )
+                .await?;
+                last_stage = Some(Arc::new(cagra));
+            }
+            #[cfg(not(feature = "cuda"))]
+            Some(Stage::Cagra(_)) => {
+                return Err(Error::NotSupported {
+                    source: "CAGRA index requires `cuda` feature to be enabled".into(),
+                    location: location!(),
+                });
+            }
+            Some(Stage::Sparse(sparse_proto)) => {
+                if last_stage.is_some() {
+                    return Err(Error::Index {
+                        message: format!(
+                            "Sparse index should be the only stage, but we got stages: {:?}",
+                            vec_idx.stages
+                        ),
+                        location: location!(),
+                    });
+                };
+                let postings_path = index_dir.child(sparse_proto.filename.as_str());
+                let sparse =
+                    SparseIndex::load(dataset.object_store(), &postings_path, sparse_proto).await?;
+                last_stage = Some(Arc::new(sparse));
+            }
             _ => {}
         }
     }
@@ -0,0 +1,206 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// CAGRA: Highly Parallel Graph Construction and Approximate Nearest Neighbor Search
+/// for GPUs.
+///
+/// CAGRA prunes a k-NN graph into a graph where every vertex has the same number of
+/// neighbors. The graph can be built here, or built on GPUs by RAFT and imported.
+/// It is searched on CPUs, with the graph and the vectors kept in memory.
+mod builder;
+mod search;
+
+pub(crate) use builder::build_cagra_index;
+pub(crate) use search::CagraIndex;
+
+#[derive(Clone, Debug)]
+pub struct CagraParams {
+    /// Number of neighbors per vertex.
+    pub graph_degree: usize,
+
+    /// Number of neighbors per vertex of the k-NN graph, which is pruned to
+    /// `graph_degree` neighbors.
+    pub intermediate_graph_degree: usize,
+
+    /// URI of a graph built by RAFT to import, instead of building the graph.
+    ///
+    /// The graph is a `.npy` file of a `uint32` matrix, e.g., saved by
+    /// `numpy.save(uri, cupy.asnumpy(index.graph))`. Row `i` is the neighbors of the
+    /// `i`-th vector of the column, in the order of the dataset, skipping nulls.
+    pub graph_uri: Option<String>,
+}
+
+// Default values from RAFT.
+impl Default for CagraParams {
+    fn default() -> Self {
+        Self {
+            graph_degree: 64,
+            intermediate_graph_degree: 128,
+            graph_uri: None,
+        }
+    }
+}
+
+impl CagraParams {
+    pub fn new(graph_degree: usize, intermediate_graph_degree: usize) -> Self {
+        Self {
+            graph_degree,
+            intermediate_graph_degree,
+            graph_uri: None,
+        }
+    }
+
+    /// Import the graph built by RAFT at `graph_uri`, see [Self::graph_uri].
+    pub fn import(graph_uri: impl Into<String>) -> Self {
+        Self {
+            graph_uri: Some(graph_uri.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn graph_degree(&mut self, graph_degree: usize) -> &mut Self {
+        self.graph_degree = graph_degree;
+        self
+    }
+
+    pub fn intermediate_graph_degree(&mut self, intermediate_graph_degree: usize) -> &mut Self {
+        self.intermediate_graph_degree = intermediate_graph_degree;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{cast::AsArray, types::UInt64Type, Array, Float32Array};
+    use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+    use futures::TryStreamExt;
+    use lance_linalg::distance::{l2_distance_batch, MetricType};
+    use lance_testing::datagen::generate_random_array;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        arrow::*,
+        dataset::{Dataset, ROW_ID},
+        index::{vector::VectorIndexParams, DatasetIndexExt, IndexType},
+    };
+
+    const DIM: i32 = 16;
+
+    async fn create_dataset(test_uri: &str, num_rows: usize) -> (Dataset, FixedSizeListArray) {
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), DIM),
+            false,
+        )]));
+        let float_arr = generate_random_array(num_rows * DIM as usize);
+        let vectors = FixedSizeListArray::try_new_from_values(float_arr, DIM).unwrap();
+        let batches =
+            vec![RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors.clone())]).unwrap()];
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        (
+            Dataset::write(reader, test_uri, None).await.unwrap(),
+            vectors,
+        )
+    }
+
+    async fn assert_finds_itself(dataset: &Dataset, vectors: &FixedSizeListArray) {
+        for row in [0, 123, 499] {
+            let query = vectors.value(row);
+            let query: &Float32Array = query.as_primitive();
+            let results = dataset
+                .scan()
+                .nearest("embeddings", query, 10)
+                .unwrap()
+                .ef_search(64)
+                .with_row_id()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(results[0].num_rows(), 10);
+            let row_ids = results[0][ROW_ID].as_primitive::<UInt64Type>();
+            assert_eq!(row_ids.value(0), row as u64);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_and_search_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let (mut dataset, vectors) = create_dataset(test_uri, 500).await;
+
+        let params = VectorIndexParams::with_cagra_params(MetricType::L2, CagraParams::new(16, 32));
+        dataset
+            .create_index(&["embeddings"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+        assert_eq!(dataset.load_indices().await.unwrap().len(), 1);
+
+        assert_finds_itself(&dataset, &vectors).await;
+    }
+
+    #[tokio::test]
+    async fn test_import_graph() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let (mut dataset, vectors) = create_dataset(test_uri, 500).await;
+
+        // An exact k-NN graph, saved as numpy does.
+        let degree = 16;
+        let values = vectors
+            .values()
+            .as_primitive::<arrow_array::types::Float32Type>();
+        let mut graph: Vec<u32> = vec![];
+        for i in 0..vectors.len() {
+            let query = &values.values()[i * DIM as usize..(i + 1) * DIM as usize];
+            let distances =
+                l2_distance_batch(query, values.values(), DIM as usize).collect::<Vec<_>>();
+            let mut ids = (0..vectors.len() as u32)
+                .filter(|&j| j as usize != i)
+                .collect::<Vec<_>>();
+            ids.sort_by(|a, b| distances[*a as usize].total_cmp(&distances[*b as usize]));
+            graph.extend_from_slice(&ids[..degree]);
+        }
+        let header = format!(
+            "{{'descr': '<u4', 'fortran_order': False, 'shape': ({}, {}), }}",
+            vectors.len(),
+            degree
+        );
+        let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+        npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        npy.extend_from_slice(header.as_bytes());
+        for id in graph {
+            npy.extend_from_slice(&id.to_le_bytes());
+        }
+        let graph_path = test_dir.path().join("graph.npy");
+        std::fs::write(&graph_path, npy).unwrap();
+
+        let params = VectorIndexParams::with_cagra_params(
+            MetricType::L2,
+            CagraParams::import(graph_path.to_str().unwrap()),
+        );
+        dataset
+            .create_index(&["embeddings"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        assert_finds_itself(&dataset, &vectors).await;
+    }
+}
@@ -0,0 +1,404 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use arrow_array::types::Float32Type;
+use lance_core::io::{object_store::ObjectStore, WriteExt};
+use lance_linalg::{distance::MetricType, MatrixView};
+use rand::{rngs::SmallRng, SeedableRng};
+use snafu::{location, Location};
+
+use super::CagraParams;
+use crate::dataset::Dataset;
+use crate::index::vector::hnsw::{build_graph, load_vectors, write_graph, HNSWParams, HnswGraph};
+use crate::index::{pb, INDEX_FILE_NAME};
+use crate::utils::tokio::spawn_cpu;
+use crate::{Error, Result};
+
+pub(super) const GRAPH_FILE_NAME: &str = "cagra_graph.lance";
+
+/// Build a CAGRA index on the vector column, or import the graph built by RAFT.
+pub async fn build_cagra_index(
+    dataset: &Dataset,
+    column: &str,
+    name: &str,
+    uuid: &str,
+    metric_type: MetricType,
+    params: &CagraParams,
+) -> Result<()> {
+    if params.graph_uri.is_none()
+        && (params.graph_degree < 2 || params.intermediate_graph_degree < params.graph_degree)
+    {
+        return Err(Error::Index {
+            message: format!(
+                "CAGRA: graph_degree must be at least 2 and at most intermediate_graph_degree, got {} and {}",
+                params.graph_degree, params.intermediate_graph_degree
+            ),
+            location: location!(),
+        });
+    }
+    let (row_ids, vectors) = load_vectors(dataset, column, "CAGRA").await?;
+
+    let (graph, intermediate_graph_degree) = if let Some(uri) = &params.graph_uri {
+        let neighbors = read_npy_graph(uri, row_ids.len()).await?;
+        let graph = HnswGraph::new(vectors, row_ids, vec![neighbors], 0, metric_type);
+        // The intermediate graph of an imported graph is unknown.
+        (graph, 0)
+    } else {
+        let build_params = params.clone();
+        let graph = spawn_cpu(move || {
+            Ok(build_cagra_graph(
+                vectors,
+                row_ids,
+                metric_type,
+                &build_params,
+            ))
+        })
+        .await?;
+        (graph, params.intermediate_graph_degree)
+    };
+    let graph_degree = graph.levels[0].iter().map(|n| n.len()).max().unwrap_or(0);
+
+    let index_dir = dataset.indices_dir().child(uuid);
+    let graph_file = index_dir.child(GRAPH_FILE_NAME);
+    write_graph(&graph, dataset.object_store(), &graph_file).await?;
+
+    let cagra = pb::Cagra {
+        filename: GRAPH_FILE_NAME.to_string(),
+        graph_degree: graph_degree as u32,
+        intermediate_graph_degree: intermediate_graph_degree as u32,
+    };
+    write_index_file(dataset, column, name, uuid, &graph, cagra).await
+}
+
+/// Build the k-NN graph with `intermediate_graph_degree` neighbors per vertex, and
+/// optimize it into a single level graph with `graph_degree` neighbors per vertex.
+///
+/// RAFT builds the k-NN graph with IVF-PQ or NN-descent on GPUs. On CPUs, the k-NN
+/// graph is searched from a HNSW graph instead.
+fn build_cagra_graph(
+    vectors: MatrixView<Float32Type>,
+    row_ids: Vec<u64>,
+    metric_type: MetricType,
+    params: &CagraParams,
+) -> HnswGraph {
+    let k = params.intermediate_graph_degree;
+    let hnsw_params = HNSWParams::new(std::cmp::max(k / 2, 2), std::cmp::max(2 * k, 200));
+    let mut rng = SmallRng::from_entropy();
+    let hnsw = build_graph(vectors, row_ids, metric_type, &hnsw_params, &mut rng);
+
+    let knn = (0..hnsw.len() as u32)
+        .map(|id| {
+            let vector = hnsw.vectors.row(id as usize).unwrap();
+            hnsw.search(vector, k + 1)
+                .into_iter()
+                .map(|(_, n)| n)
+                .filter(|&n| n != id)
+                .take(k)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let neighbors = optimize_graph(&knn, params.graph_degree);
+
+    HnswGraph::new(hnsw.vectors, hnsw.row_ids, vec![neighbors], 0, metric_type)
+}
+
+/// Optimize the k-NN graph into a graph with `degree` neighbors per vertex, as CAGRA
+/// does without distances:
+///
+/// 1. Reorder the neighbors of each vertex by the number of detours, i.e., the number
+///    of closer neighbors that have this neighbor as an even closer neighbor, and
+///    keep the `degree` neighbors with the fewest detours.
+/// 2. Add the reverse edges, keeping the first half of the pruned neighbors, then the
+///    reverse edges by their rank, then the rest of the pruned neighbors.
+///
+/// The neighbors of each vertex in `knn` are sorted by distance.
+fn optimize_graph(knn: &[Vec<u32>], degree: usize) -> Vec<Vec<u32>> {
+    let pruned = knn
+        .iter()
+        .map(|neighbors| {
+            let rank_of = neighbors
+                .iter()
+                .enumerate()
+                .map(|(rank, &n)| (n, rank))
+                .collect::<HashMap<_, _>>();
+            let mut detours = vec![0_usize; neighbors.len()];
+            for (rank_z, &z) in neighbors.iter().enumerate() {
+                for (rank_zy, y) in knn[z as usize].iter().enumerate() {
+                    // The detour x -> z -> y is shorter than the edge x -> y.
+                    if let Some(&rank_y) = rank_of.get(y) {
+                        if std::cmp::max(rank_z, rank_zy) < rank_y {
+                            detours[rank_y] += 1;
+                        }
+                    }
+                }
+            }
+            let mut edges = neighbors
+                .iter()
+                .enumerate()
+                .map(|(rank, &n)| (detours[rank], rank, n))
+                .collect::<Vec<_>>();
+            edges.sort();
+            edges
+                .into_iter()
+                .take(degree)
+                .map(|(_, _, n)| n)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut reversed = vec![vec![]; pruned.len()];
+    for (id, neighbors) in pruned.iter().enumerate() {
+        for (rank, &n) in neighbors.iter().enumerate() {
+            reversed[n as usize].push((rank, id as u32));
+        }
+    }
+
+    pruned
+        .iter()
+        .zip(reversed.iter_mut())
+        .map(|(neighbors, reversed)| {
+            reversed.sort();
+            let half = degree / 2;
+            let mut merged = Vec::with_capacity(degree);
+            for &n in neighbors
+                .iter()
+                .take(half)
+                .chain(reversed.iter().map(|(_, n)| n))
+                .chain(neighbors.iter().skip(half))
+            {
+                if merged.len() == degree {
+                    break;
+                }
+                if !merged.contains(&n) {
+                    merged.push(n);
+                }
+            }
+            merged
+        })
+        .collect()
+}
+
+/// Read the graph of `num_vertices` vertices from a `.npy` file, see
+/// [CagraParams::graph_uri].
+async fn read_npy_graph(uri: &str, num_vertices: usize) -> Result<Vec<Vec<u32>>> {
+    let (object_store, path) = ObjectStore::from_uri(uri).await?;
+    let size = object_store.size(&path).await?;
+    let data = object_store.open(&path).await?.get_range(0..size).await?;
+    let graph = parse_npy_graph(&data)?;
+
+    if graph.len() != num_vertices {
+        return Err(Error::Index {
+            message: format!(
+                "CAGRA: graph {} has {} vertices, but the column has {} vectors",
+                uri,
+                graph.len(),
+                num_vertices
+            ),
+            location: location!(),
+        });
+    }
+    if let Some(n) = graph
+        .iter()
+        .flatten()
+        .find(|&&n| n as usize >= num_vertices)
+    {
+        return Err(Error::Index {
+            message: format!(
+                "CAGRA: graph {} has a neighbor {} out of the {} vertices",
+                uri, n, num_vertices
+            ),
+            location: location!(),
+        });
+    }
+    Ok(graph)
+}
+
+/// Parse a 2-D `uint32` matrix in the numpy `.npy` format into the neighbors of each
+/// vertex.
+fn parse_npy_graph(data: &[u8]) -> Result<Vec<Vec<u32>>> {
+    let invalid = |reason: String| Error::Index {
+        message: format!("CAGRA: invalid .npy graph: {}", reason),
+        location: location!(),
+    };
+
+    if data.len() < 10 || &data[..6] != b"\x93NUMPY" {
+        return Err(invalid("not a .npy file".to_string()));
+    }
+    let (header_len, header_start) = match data[6] {
+        1 => (u16::from_le_bytes([data[8], data[9]]) as usize, 10),
+        2 | 3 if data.len() >= 12 => (
+            u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize,
+            12,
+        ),
+        version => return Err(invalid(format!("unsupported version {}", version))),
+    };
+    let header = data
+        .get(header_start..header_start + header_len)
+        .and_then(|h| std::str::from_utf8(h).ok())
+        .ok_or_else(|| invalid("truncated header".to_string()))?;
+    // The header is a python dict literal, i.e.,
+    // "{'descr': '<u4', 'fortran_order': False, 'shape': (1000, 64), }".
+    let header = header
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    if !header.contains("'descr':'<u4'") {
+        return Err(invalid(format!("expect a uint32 matrix, got {}", header)));
+    }
+    if !header.contains("'fortran_order':False") {
+        return Err(invalid("expect a C order matrix".to_string()));
+    }
+    let shape = header
+        .split_once("'shape':(")
+        .and_then(|(_, rest)| rest.split_once(')'))
+        .map(|(shape, _)| {
+            shape
+                .split(',')
+                .filter(|dim| !dim.is_empty())
+                .map(|dim| dim.parse::<usize>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+        });
+    let (num_vertices, degree) = match shape {
+        Some(Ok(shape)) if shape.len() == 2 && shape[1] > 0 => (shape[0], shape[1]),
+        _ => return Err(invalid(format!("expect a 2-D matrix, got {}", header))),
+    };
+
+    let body = &data[header_start + header_len..];
+    let size = num_vertices * degree * std::mem::size_of::<u32>();
+    if body.len() < size {
+        return Err(invalid(format!(
+            "expect {} bytes of data, got {}",
+            size,
+            body.len()
+        )));
+    }
+    let neighbors = body[..size]
+        .chunks_exact(std::mem::size_of::<u32>())
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect::<Vec<_>>();
+    Ok(neighbors.chunks(degree).map(|n| n.to_vec()).collect())
+}
+
+async fn write_index_file(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    graph: &HnswGraph,
+    cagra: pb::Cagra,
+) -> Result<()> {
+    let object_store = dataset.object_store();
+    let path = dataset.indices_dir().child(uuid).child(INDEX_FILE_NAME);
+    let mut writer = object_store.create(&path).await?;
+
+    let stages = vec![pb::VectorIndexStage {
+        stage: Some(pb::vector_index_stage::Stage::Cagra(cagra)),
+    }];
+    let metadata = pb::Index {
+        name: index_name.to_string(),
+        columns: vec![column.to_string()],
+        dataset_version: dataset.version().version,
+        index_type: pb::IndexType::Vector.into(),
+        implementation: Some(pb::index::Implementation::VectorIndex(pb::VectorIndex {
+            spec_version: 1,
+            dimension: graph.vectors.num_columns() as u32,
+            stages,
+            metric_type: pb::VectorMetricType::try_from(graph.metric_type)?.into(),
+        })),
+    };
+
+    let pos = writer.write_protobuf(&metadata).await?;
+    writer.write_magics(pos).await?;
+    writer.shutdown().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_optimize_graph() {
+        // Vertices on a line, each with all the others as neighbors, sorted by distance.
+        let num_vertices = 20_i64;
+        let knn = (0..num_vertices)
+            .map(|i| {
+                let mut neighbors = (0..num_vertices).filter(|&j| j != i).collect::<Vec<_>>();
+                neighbors.sort_by_key(|&j| ((j - i).abs(), j));
+                neighbors.into_iter().map(|j| j as u32).collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let graph = optimize_graph(&knn, 4);
+        assert_eq!(graph.len(), num_vertices as usize);
+        for (i, neighbors) in graph.iter().enumerate() {
+            assert_eq!(neighbors.len(), 4);
+            assert!(!neighbors.contains(&(i as u32)));
+            let mut dedup = neighbors.clone();
+            dedup.sort();
+            dedup.dedup();
+            assert_eq!(dedup.len(), 4);
+        }
+        // Both direct neighbors on the line have no detour.
+        assert!(graph[10].contains(&9) && graph[10].contains(&11));
+        // The ends are reachable through the reverse edges.
+        assert!(graph[1].contains(&0));
+    }
+
+    fn npy(header: &str, data: &[u32]) -> Vec<u8> {
+        let mut npy = b"\x93NUMPY\x01\x00".to_vec();
+        npy.extend_from_slice(&(header.len() as u16).to_le_bytes());
+        npy.extend_from_slice(header.as_bytes());
+        for v in data {
+            npy.extend_from_slice(&v.to_le_bytes());
+        }
+        npy
+    }
+
+    #[test]
+    fn test_parse_npy_graph() {
+        let graph = parse_npy_graph(&npy(
+            "{'descr': '<u4', 'fortran_order': False, 'shape': (3, 2), }   \n",
+            &[1, 2, 0, 2, 0, 1],
+        ))
+        .unwrap();
+        assert_eq!(graph, vec![vec![1, 2], vec![0, 2], vec![0, 1]]);
+
+        assert!(parse_npy_graph(b"not a npy file").is_err());
+        assert!(parse_npy_graph(&npy(
+            "{'descr': '<f4', 'fortran_order': False, 'shape': (3, 2), }",
+            &[1, 2, 0, 2, 0, 1],
+        ))
+        .is_err());
+        assert!(parse_npy_graph(&npy(
+            "{'descr': '<u4', 'fortran_order': True, 'shape': (3, 2), }",
+            &[1, 2, 0, 2, 0, 1],
+        ))
+        .is_err());
+        assert!(parse_npy_graph(&npy(
+            "{'descr': '<u4', 'fortran_order': False, 'shape': (6,), }",
+            &[1, 2, 0, 2, 0, 1],
+        ))
+        .is_err());
+        // Truncated data.
+        assert!(parse_npy_graph(&npy(
+            "{'descr': '<u4', 'fortran_order': False, 'shape': (3, 2), }",
+            &[1, 2, 0, 2],
+        ))
+        .is_err());
+    }
+}
@@ -0,0 +1,204 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, sync::Arc};
+
+use arrow_array::{cast::AsArray, types::Float32Type, RecordBatch};
+use async_trait::async_trait;
+use lance_core::{
+    format::RowAddress,
+    io::{object_store::ObjectStore, Reader},
+    Error, Result,
+};
+use lance_index::{vector::Query, Index, IndexType};
+use lance_linalg::distance::MetricType;
+use nohash_hasher::IntMap;
+use object_store::path::Path;
+use roaring::RoaringBitmap;
+use serde::Serialize;
+use snafu::{location, Location};
+use tracing::instrument;
+
+use crate::index::vector::hnsw::{read_graph, search_results, HnswGraph};
+use crate::index::{pb, prefilter::PreFilter, vector::VectorIndex};
+use crate::utils::tokio::spawn_cpu;
+
+/// Max number of vertices a search starts from.
+///
+/// CAGRA starts from random vertices, as the graph has no entry point. Evenly spaced
+/// vertices are used instead, so that the results are deterministic.
+const NUM_ENTRY_POINTS: usize = 32;
+
+/// CAGRA index. The whole graph is loaded into memory.
+pub struct CagraIndex {
+    graph: Arc<HnswGraph>,
+
+    entry_points: Vec<u32>,
+
+    /// Parameters used to build the graph.
+    graph_degree: usize,
+    intermediate_graph_degree: usize,
+}
+
+impl std::fmt::Debug for CagraIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "CAGRA(graph_degree={}, intermediate_graph_degree={}, {})",
+            self.graph_degree, self.intermediate_graph_degree, self.graph.metric_type
+        )
+    }
+}
+
+impl CagraIndex {
+    /// Load the CAGRA graph from the graph file.
+    pub async fn load(
+        object_store: &ObjectStore,
+        graph_path: &Path,
+        proto: &pb::Cagra,
+        metric_type: MetricType,
+    ) -> Result<Self> {
+        let graph = read_graph(object_store, graph_path, 1, 0, metric_type).await?;
+        let step = std::cmp::max(graph.len() / NUM_ENTRY_POINTS, 1);
+        let entry_points = (0..graph.len() as u32)
+            .step_by(step)
+            .take(NUM_ENTRY_POINTS)
+            .collect();
+        Ok(Self {
+            graph: Arc::new(graph),
+            entry_points,
+            graph_degree: proto.graph_degree as usize,
+            intermediate_graph_degree: proto.intermediate_graph_degree as usize,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct CagraIndexStatistics {
+    index_type: String,
+    metric_type: String,
+    num_vertices: usize,
+    graph_degree: usize,
+    intermediate_graph_degree: usize,
+}
+
+#[async_trait]
+impl Index for CagraIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Vector
+    }
+
+    fn memory_size(&self) -> usize {
+        let graph = &self.graph;
+        let vectors = graph.vectors.num_rows() * graph.vectors.num_columns();
+        let neighbors = graph.levels[0].iter().map(|n| n.len()).sum::<usize>();
+        vectors * std::mem::size_of::<f32>()
+            + graph.row_ids.len() * std::mem::size_of::<u64>()
+            + neighbors * std::mem::size_of::<u32>()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        Ok(serde_json::to_string(&CagraIndexStatistics {
+            index_type: "CAGRA".to_string(),
+            metric_type: self.graph.metric_type.to_string(),
+            num_vertices: self.graph.len(),
+            graph_degree: self.graph_degree,
+            intermediate_graph_degree: self.intermediate_graph_degree,
+        })?)
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        let mut frag_ids = self
+            .graph
+            .row_ids
+            .iter()
+            .map(|&row_id| RowAddress::new_from_id(row_id).fragment_id())
+            .collect::<Vec<_>>();
+        frag_ids.sort();
+        frag_ids.dedup();
+        Ok(RoaringBitmap::from_sorted_iter(frag_ids).unwrap())
+    }
+}
+
+#[async_trait]
+impl VectorIndex for CagraIndex {
+    #[instrument(level = "debug", skip_all, name = "CagraIndex::search")]
+    async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch> {
+        let key = query
+            .key
+            .as_primitive_opt::<Float32Type>()
+            .ok_or_else(|| Error::Index {
+                message: format!(
+                    "CAGRA only supports float32 query vectors, got {}",
+                    query.key.data_type()
+                ),
+                location: location!(),
+            })?
+            .clone();
+        let k = query.k * query.refine_factor.unwrap_or(1) as usize;
+        let ef = std::cmp::max(query.ef_search.unwrap_or(k), k);
+
+        pre_filter.wait_for_ready().await?;
+        let graph = self.graph.clone();
+        let entry_points = self.entry_points.clone();
+        let query = query.clone();
+        spawn_cpu(move || {
+            let candidates = graph.search_from(key.values(), &entry_points, ef);
+            search_results(&graph, candidates, &query, &pre_filter, k)
+        })
+        .await
+    }
+
+    fn is_loadable(&self) -> bool {
+        false
+    }
+
+    async fn load(
+        &self,
+        _reader: &dyn Reader,
+        _offset: usize,
+        _length: usize,
+    ) -> Result<Box<dyn VectorIndex>> {
+        Err(Error::Index {
+            message: "CagraIndex is not loadable".to_string(),
+            location: location!(),
+        })
+    }
+
+    fn check_can_remap(&self) -> Result<()> {
+        Err(Error::NotSupported {
+            source: "CagraIndex does not yet support remap".into(),
+            location: location!(),
+        })
+    }
+
+    fn remap(&mut self, _mapping: &IntMap<u64, Option<u64>>) -> Result<()> {
+        Err(Error::NotSupported {
+            source: "CagraIndex does not yet support remap".into(),
+            location: location!(),
+        })
+    }
+
+    fn metric_type(&self) -> MetricType {
+        self.graph.metric_type
+    }
+}
@@ -44,6 +44,10 @@ pub struct DiskANNParams {
 
     /// Metric type.
     pub metric_type: MetricType,
+
+    /// The maximum number of vectors of a graph built in memory. The vectors of a
+    /// larger dataset are clustered, and the graphs of the clusters are merged.
+    pub sub_graph_size: usize,
 }
 
 // Default values from DiskANN paper.
@@ -55,6 +59,7 @@ impl Default for DiskANNParams {
             l: 70,
             pq_params: PQBuildParams::default(),
             metric_type: MetricType::L2,
+            sub_graph_size: 1_000_000,
         }
     }
 }
@@ -67,6 +72,7 @@ impl DiskANNParams {
             l,
             pq_params: PQBuildParams::default(),
             metric_type: MetricType::L2,
+            sub_graph_size: 1_000_000,
         }
     }
 
@@ -105,21 +111,28 @@ impl DiskANNParams {
         self.metric_type = metric_type;
         self
     }
+
+    pub fn sub_graph_size(&mut self, sub_graph_size: usize) -> &mut Self {
+        self.sub_graph_size = sub_graph_size;
+        self
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use std::sync::Arc;
 
+    use arrow_array::{cast::AsArray, types::UInt64Type, Float32Array};
     use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator};
     use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+    use futures::TryStreamExt;
     use lance_testing::datagen::generate_random_array;
     use tempfile::tempdir;
 
     use super::*;
     use crate::{
         arrow::*,
-        dataset::Dataset,
+        dataset::{Dataset, WriteParams, ROW_ID},
         index::{
             DatasetIndexExt,
             {vector::VectorIndexParams, IndexType},
@@ -164,4 +177,76 @@ mod tests {
         let expected = dataset.manifest.version - 1;
         assert_eq!(actual, expected);
     }
+
+    async fn check_search_index(params: DiskANNParams) {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+
+        let float_arr = generate_random_array(512 * dimension as usize);
+        let vectors =
+            Arc::new(FixedSizeListArray::try_new_from_values(float_arr, dimension).unwrap());
+        let batches = vec![RecordBatch::try_new(schema.clone(), vec![vectors.clone()]).unwrap()];
+
+        // Multiple fragments, so the vectors are streamed in several batches.
+        let write_params = WriteParams {
+            max_rows_per_file: 128,
+            max_rows_per_group: 64,
+            ..Default::default()
+        };
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, Some(write_params))
+            .await
+            .unwrap();
+
+        let params = VectorIndexParams::with_diskann_params(MetricType::L2, params);
+        dataset
+            .create_index(&["embeddings"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        // Each vector should find itself as the nearest neighbor.
+        for row in [0, 200, 511] {
+            let query = vectors.value(row);
+            let query: &Float32Array = query.as_primitive();
+            let results = dataset
+                .scan()
+                .nearest("embeddings", query, 10)
+                .unwrap()
+                .beam_width(2)
+                .with_row_id()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(results[0].num_rows(), 10);
+            let row_ids = results[0][ROW_ID].as_primitive::<UInt64Type>();
+            let expected = ((row / 128) as u64) << 32 | (row % 128) as u64;
+            assert_eq!(row_ids.value(0), expected);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_index() {
+        check_search_index(DiskANNParams::new(32, 1.2, 64)).await;
+    }
+
+    #[tokio::test]
+    async fn test_search_merged_index() {
+        // The graphs of the clusters are built apart and merged.
+        let mut params = DiskANNParams::new(32, 1.2, 64);
+        params.sub_graph_size(200);
+        check_search_index(params).await;
+    }
 }
@@ -12,23 +12,32 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BinaryHeap, HashSet};
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::sync::Arc;
 
+use arrow::compute::concat_batches;
 use arrow_array::{
+    builder::{ListBuilder, UInt32Builder},
     cast::AsArray,
-    types::{Float32Type, UInt64Type},
-    UInt32Array,
+    types::{Float32Type, UInt32Type, UInt64Type},
+    FixedSizeListArray, Float32Array, RecordBatch, UInt32Array, UInt64Array,
 };
-use arrow_select::concat::concat_batches;
+use arrow_schema::{DataType, Field, Schema as ArrowSchema};
 use futures::stream::{self, StreamExt, TryStreamExt};
 use lance_arrow::*;
 use lance_core::io::WriteExt;
+use lance_index::vector::kmeans::train_kmeans;
 use lance_linalg::kernels::argmin;
+use lance_linalg::kmeans::KMeanInit;
 use lance_linalg::{
-    distance::{cosine_distance_batch, dot_distance_batch, l2_distance, l2_distance_batch},
+    distance::{
+        cosine_distance_batch, custom::custom_distance_batch, dot_distance_batch,
+        l2_distance_batch, DistanceFunc,
+    },
     matrix::MatrixView,
 };
+use log::info;
+use object_store::path::Path;
 use ordered_float::OrderedFloat;
 use rand::{distributions::Uniform, prelude::*, Rng, SeedableRng};
 use snafu::{location, Location};
@@ -37,16 +46,37 @@ use crate::dataset::{Dataset, ROW_ID};
 use crate::index::vector::diskann::row_vertex::RowVertexSerDe;
 use crate::index::vector::diskann::DiskANNParams;
 use crate::index::vector::graph::{
-    builder::GraphBuilder, write_graph, VertexWithDistance, WriteGraphParams,
+    builder::{GraphBuilder, Node},
+    write_graph, GraphWriter, VertexSerDe, VertexWithDistance, WriteGraphParams,
 };
 use crate::index::vector::graph::{Graph, Vertex};
-use crate::index::vector::MetricType;
+use crate::index::vector::ivf::{Shuffler, ShufflerBuilder};
+use crate::index::vector::{
+    utils::{filter_null_vectors, maybe_sample_training_data},
+    MetricType,
+};
 use crate::index::{pb, INDEX_FILE_NAME};
 use crate::{Error, Result};
 
 use super::row_vertex::RowVertex;
 use super::search::greedy_search;
 
+/// The id of a vertex in the graph, in the shuffled vectors and edges.
+const VERTEX_ID: &str = "_vertex_id";
+
+/// The neighbors of a vertex in the shuffled edges.
+const NEIGHBORS: &str = "neighbors";
+
+/// Each vector is in the graphs of its nearest clusters, so that the graphs overlap
+/// and are connected once merged.
+const CLUSTERS_PER_VECTOR: usize = 2;
+
+/// The number of vectors sampled per cluster to train the clusters.
+const CLUSTER_SAMPLE_RATE: usize = 256;
+
+/// The number of rows of a key buffered by the shufflers before flushing them.
+const FLUSH_THRESHOLD: usize = 40 * 1024;
+
 pub async fn build_diskann_index(
     dataset: &Dataset,
     column: &str,
@@ -54,58 +84,51 @@ pub async fn build_diskann_index(
     uuid: &str,
     params: DiskANNParams,
 ) -> Result<()> {
+    // The graph reads the vectors of its vertices by their row ids, which must be the
+    // addresses of the rows.
+    if dataset.has_stable_row_ids() {
+        return Err(Error::NotSupported {
+            source: "DiskANN indices on datasets with stable row ids are not supported".into(),
+            location: location!(),
+        });
+    }
     let rng = rand::rngs::SmallRng::from_entropy();
 
-    // Randomly initialize the graph with r random neighbors for each vertex.
-    let mut graph = init_graph(dataset, column, params.r, params.metric_type, rng.clone()).await?;
-
-    // Find medoid
-    let medoid = {
-        let vectors = graph.data.clone();
-        find_medoid(&vectors, params.metric_type).await?
-    };
-
-    // First pass.
-    let now = std::time::Instant::now();
-    index_once(&mut graph, medoid, 1.0, params.r, params.l, rng.clone()).await?;
-    println!("DiskANN: first pass: {}s", now.elapsed().as_secs_f32());
-    // Second pass.
-    let now = std::time::Instant::now();
-    index_once(
-        &mut graph,
-        medoid,
-        params.alpha,
-        params.r,
-        params.l,
-        rng.clone(),
-    )
-    .await?;
-    println!("DiskANN: second pass: {}s", now.elapsed().as_secs_f32());
-
     let index_dir = dataset.indices_dir().child(uuid);
     let filename = "diskann_graph.lance";
     let graph_file = index_dir.child(filename);
 
-    let write_params = WriteGraphParams {
-        batch_size: 2048 * 10,
-    };
-    let serde = RowVertexSerDe {};
+    let num_rows = dataset.count_rows().await?;
+    let (dimension, medoid) = if num_rows <= params.sub_graph_size {
+        // Randomly initialize the graph with r random neighbors for each vertex.
+        let mut graph =
+            init_graph(dataset, column, params.r, params.metric_type, rng.clone()).await?;
+        let medoid = index_graph(&mut graph, &params, rng).await?;
 
-    write_graph(
-        &graph,
-        dataset.object_store(),
-        &graph_file,
-        &write_params,
-        &serde,
-    )
-    .await?;
+        let write_params = WriteGraphParams {
+            batch_size: 2048 * 10,
+        };
+        let serde = RowVertexSerDe {};
+
+        write_graph(
+            &graph,
+            dataset.object_store(),
+            &graph_file,
+            &write_params,
+            &serde,
+        )
+        .await?;
+        (graph.data.num_columns(), medoid)
+    } else {
+        build_merged_graph(dataset, column, num_rows, &params, rng, &graph_file).await?
+    };
 
     write_index_file(
         dataset,
         column,
         name,
         uuid,
-        graph.data.num_columns(),
+        dimension,
         filename,
         &[medoid],
         params.metric_type,
@@ -116,6 +139,52 @@ pub async fn build_diskann_index(
     Ok(())
 }
 
+/// Build the graph of the vectors in `graph`, returning the id of its medoid.
+async fn index_graph(
+    graph: &mut GraphBuilder<RowVertex>,
+    params: &DiskANNParams,
+    rng: impl Rng + Clone,
+) -> Result<usize> {
+    // Find medoid
+    let medoid = {
+        let vectors = graph.data.clone();
+        find_medoid(&vectors, params.metric_type).await?
+    };
+
+    // First pass.
+    let now = std::time::Instant::now();
+    index_once(graph, medoid, 1.0, params.r, params.l, rng.clone()).await?;
+    info!("DiskANN: first pass: {}s", now.elapsed().as_secs_f32());
+    // Second pass.
+    let now = std::time::Instant::now();
+    index_once(graph, medoid, params.alpha, params.r, params.l, rng).await?;
+    info!("DiskANN: second pass: {}s", now.elapsed().as_secs_f32());
+
+    Ok(medoid)
+}
+
+/// The row ids and vectors of the non-null vectors of a batch.
+fn batch_vectors<'a>(
+    batch: &'a RecordBatch,
+    column: &str,
+) -> Result<(&'a UInt64Array, MatrixView<Float32Type>)> {
+    let row_ids = batch
+        .column_by_qualified_name(ROW_ID)
+        .ok_or(Error::Index {
+            message: "row_id not found".to_string(),
+            location: location!(),
+        })?
+        .as_primitive::<UInt64Type>();
+    let vectors = batch
+        .column_by_qualified_name(column)
+        .ok_or(Error::Index {
+            message: format!("column {} not found", column),
+            location: location!(),
+        })?
+        .as_fixed_size_list();
+    Ok((row_ids, MatrixView::<Float32Type>::try_from(vectors)?))
+}
+
 /// Randomly initialize the graph with r random neighbors for each vertex.
 ///
 /// Parameters
@@ -130,43 +199,56 @@ async fn init_graph(
     column: &str,
     r: usize,
     metric_type: MetricType,
-    mut rng: impl Rng,
+    rng: impl Rng,
 ) -> Result<GraphBuilder<RowVertex>> {
-    let stream = dataset
+    let mut stream = dataset
         .scan()
         .project(&[column])?
         .with_row_id()
         .try_into_stream()
-        .await
-        .unwrap();
+        .await?;
+
+    // Stream the vectors into one flat buffer, instead of collecting all the batches
+    // and concatenating them, which would hold two copies of the vectors in memory.
+    let mut row_ids: Vec<u64> = vec![];
+    let mut values: Vec<f32> = vec![];
+    let mut dimension = 0;
+    while let Some(batch) = stream.try_next().await? {
+        // Null vectors are not indexed.
+        let batch = filter_null_vectors(&batch, column)?;
+        let (batch_row_ids, matrix) = batch_vectors(&batch, column)?;
+        dimension = matrix.num_columns();
+        row_ids.extend_from_slice(batch_row_ids.values());
+        values.extend_from_slice(matrix.data().values());
+    }
+    if row_ids.is_empty() {
+        return Err(Error::Index {
+            message: "DiskANN: can not build index on an empty dataset".to_string(),
+            location: location!(),
+        });
+    }
 
-    let batches = stream.try_collect::<Vec<_>>().await?;
-    let batch = concat_batches(&batches[0].schema(), &batches)?;
+    let matrix = MatrixView::<Float32Type>::new(Arc::new(Float32Array::from(values)), dimension);
+    random_graph(&row_ids, matrix, r, metric_type, rng).await
+}
 
-    let row_ids = batch
-        .column_by_qualified_name(ROW_ID)
-        .ok_or(Error::Index {
-            message: "row_id not found".to_string(),
-            location: location!(),
-        })?
-        .as_primitive::<UInt64Type>();
-    let vectors = batch
-        .column_by_qualified_name(column)
-        .ok_or(Error::Index {
-            message: format!("column {} not found", column),
-            location: location!(),
-        })?
-        .as_fixed_size_list();
-    let matrix = MatrixView::<Float32Type>::try_from(vectors)?;
+/// A graph of the vectors of `matrix`, with r random neighbors for each vertex.
+async fn random_graph(
+    row_ids: &[u64],
+    matrix: MatrixView<Float32Type>,
+    r: usize,
+    metric_type: MetricType,
+    mut rng: impl Rng,
+) -> Result<GraphBuilder<RowVertex>> {
     let nodes = row_ids
-        .values()
         .iter()
         .map(|&row_id| RowVertex::new(row_id, None))
         .collect::<Vec<_>>();
     let mut graph = GraphBuilder::new(&nodes, matrix, metric_type);
 
-    let distribution = Uniform::new(0, batch.num_rows());
+    let distribution = Uniform::new(0, graph.len());
     // Randomly connect to r neighbors.
+    let r = std::cmp::min(r, graph.len() - 1);
     for i in 0..graph.len() {
         let mut neighbor_ids: HashSet<u32> =
             graph.neighbors(i).await?.values().iter().copied().collect();
@@ -187,8 +269,272 @@ async fn init_graph(
     Ok(graph)
 }
 
+/// Build the graph of a dataset too large for memory, like the merged Vamana index
+/// of the DiskANN paper, and write it to `graph_file`.
+///
+/// The vectors are clustered, and shuffled to disk by their nearest clusters. The
+/// graph of each cluster is built in memory, and their edges are shuffled by vertex,
+/// so that the merged graph is written a range of vertices at a time.
+///
+/// Returns the dimension of the vectors and the id of the medoid.
+async fn build_merged_graph(
+    dataset: &Dataset,
+    column: &str,
+    num_rows: usize,
+    params: &DiskANNParams,
+    rng: impl Rng + Clone,
+    graph_file: &Path,
+) -> Result<(usize, usize)> {
+    let num_clusters =
+        (num_rows * CLUSTERS_PER_VECTOR + params.sub_graph_size - 1) / params.sub_graph_size;
+    let sample =
+        maybe_sample_training_data(dataset, column, num_clusters * CLUSTER_SAMPLE_RATE, None)
+            .await?;
+    let dimension = sample.value_length() as usize;
+    const MAX_ITERATIONS: u32 = 50;
+    const REDOS: usize = 1;
+    let centroids = train_kmeans::<Float32Type>(
+        sample.values().as_primitive::<Float32Type>(),
+        None,
+        dimension,
+        num_clusters,
+        MAX_ITERATIONS,
+        REDOS,
+        rng.clone(),
+        params.metric_type,
+        CLUSTER_SAMPLE_RATE,
+        KMeanInit::Random,
+        None,
+    )
+    .await?;
+    let centroids = MatrixView::<Float32Type>::new(Arc::new(centroids), dimension);
+
+    let (vectors, num_vertices, mean) =
+        shuffle_vectors(dataset, column, &centroids, params.metric_type).await?;
+    let (edges, medoid) = shuffle_edges(&vectors, column, num_clusters, &mean, params, rng).await?;
+    drop(vectors);
+
+    let serde = RowVertexSerDe {};
+    let mut writer = GraphWriter::try_new(dataset.object_store(), graph_file, serde.size()).await?;
+    let chunk_size = params.sub_graph_size;
+    for (key, start) in (0..num_vertices).step_by(chunk_size).enumerate() {
+        let mut nodes = vec![(0, vec![]); std::cmp::min(chunk_size, num_vertices - start)];
+        if let Some(mut stream) = edges.key_iter(key as u32).await? {
+            while let Some(batch) = stream.try_next().await? {
+                let vertex_ids = batch[VERTEX_ID].as_primitive::<UInt32Type>();
+                let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+                let neighbors = batch[NEIGHBORS].as_list::<i32>();
+                for idx in 0..batch.num_rows() {
+                    let node = &mut nodes[vertex_ids.value(idx) as usize - start];
+                    node.0 = row_ids.value(idx);
+                    node.1.extend_from_slice(
+                        neighbors.value(idx).as_primitive::<UInt32Type>().values(),
+                    );
+                }
+            }
+        }
+        // The edges of a vertex in several clusters are the union of its edges in the
+        // graphs of the clusters.
+        let nodes = nodes
+            .into_iter()
+            .map(|(row_id, mut neighbors)| {
+                neighbors.sort_unstable();
+                neighbors.dedup();
+                Node {
+                    vertex: RowVertex::new(row_id, None),
+                    neighbors: Arc::new(UInt32Array::from(neighbors)),
+                }
+            })
+            .collect::<Vec<_>>();
+        writer.write(&nodes, &serde).await?;
+    }
+    writer.finish().await?;
+
+    Ok((dimension, medoid))
+}
+
+/// Shuffle the vectors of the dataset by their nearest clusters, with their row id
+/// and vertex id, i.e., their position among the non-null vectors.
+///
+/// Returns the shuffled vectors, the number of vertices and the mean of the vectors.
+async fn shuffle_vectors(
+    dataset: &Dataset,
+    column: &str,
+    centroids: &MatrixView<Float32Type>,
+    metric_type: MetricType,
+) -> Result<(Shuffler, usize, Vec<f32>)> {
+    let dimension = centroids.num_columns();
+    let schema = Arc::new(ArrowSchema::new(vec![
+        Field::new(ROW_ID, DataType::UInt64, false),
+        Field::new(VERTEX_ID, DataType::UInt32, false),
+        Field::new(
+            column,
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension as i32,
+            ),
+            false,
+        ),
+    ]));
+    let mut shuffler = ShufflerBuilder::try_new(&schema, FLUSH_THRESHOLD, 1, None, None).await?;
+
+    let mut stream = dataset
+        .scan()
+        .project(&[column])?
+        .with_row_id()
+        .try_into_stream()
+        .await?;
+    let distance_func = metric_type.func();
+    let num_clusters = std::cmp::min(CLUSTERS_PER_VECTOR, centroids.num_rows());
+    let mut num_vertices = 0;
+    let mut sum = vec![0.0_f64; dimension];
+    while let Some(batch) = stream.try_next().await? {
+        // Null vectors are not indexed.
+        let batch = filter_null_vectors(&batch, column)?;
+        let (row_ids, matrix) = batch_vectors(&batch, column)?;
+        let vertex_ids = (num_vertices..num_vertices + batch.num_rows())
+            .map(|id| id as u32)
+            .collect::<UInt32Array>();
+        num_vertices += batch.num_rows();
+
+        let mut clusters: HashMap<u32, Vec<u32>> = HashMap::new();
+        for idx in 0..matrix.num_rows() {
+            let vector = matrix.row(idx).unwrap();
+            for (total, value) in sum.iter_mut().zip(vector) {
+                *total += *value as f64;
+            }
+            let mut dists = (0..centroids.num_rows())
+                .map(|c| (distance_func(vector, centroids.row(c).unwrap()), c as u32))
+                .collect::<Vec<_>>();
+            dists.select_nth_unstable_by(num_clusters - 1, |a, b| a.0.total_cmp(&b.0));
+            for (_, cluster) in &dists[..num_clusters] {
+                clusters.entry(*cluster).or_default().push(idx as u32);
+            }
+        }
+
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(row_ids.clone()),
+                Arc::new(vertex_ids),
+                Arc::new(FixedSizeListArray::try_new_from_values(
+                    matrix.data().as_ref().clone(),
+                    dimension as i32,
+                )?),
+            ],
+        )?;
+        for (cluster, indices) in clusters {
+            let rows = batch.take(&UInt32Array::from(indices))?;
+            shuffler.insert(cluster, rows).await?;
+        }
+    }
+    if num_vertices == 0 {
+        return Err(Error::Index {
+            message: "DiskANN: can not build index on an empty dataset".to_string(),
+            location: location!(),
+        });
+    }
+
+    let mean: Vec<f32> = sum
+        .iter()
+        .map(|total| (total / num_vertices as f64) as f32)
+        .collect();
+    Ok((shuffler.finish().await?, num_vertices, mean))
+}
+
+/// Build the graph of each cluster of the shuffled `vectors` in memory, and shuffle
+/// their edges by range of `sub_graph_size` vertices.
+///
+/// Returns the shuffled edges, and the id of the vertex nearest to `mean`.
+async fn shuffle_edges(
+    vectors: &Shuffler,
+    column: &str,
+    num_clusters: usize,
+    mean: &[f32],
+    params: &DiskANNParams,
+    rng: impl Rng + Clone,
+) -> Result<(Shuffler, usize)> {
+    let schema = Arc::new(ArrowSchema::new(vec![
+        Field::new(VERTEX_ID, DataType::UInt32, false),
+        Field::new(ROW_ID, DataType::UInt64, false),
+        Field::new(
+            NEIGHBORS,
+            DataType::List(Arc::new(Field::new("item", DataType::UInt32, true))),
+            false,
+        ),
+    ]));
+    let mut shuffler = ShufflerBuilder::try_new(&schema, FLUSH_THRESHOLD, 1, None, None).await?;
+
+    let distance_func = params.metric_type.func();
+    let mut medoid: Option<(f32, u32)> = None;
+    for cluster in 0..num_clusters as u32 {
+        let Some(stream) = vectors.key_iter(cluster).await? else {
+            continue;
+        };
+        let batches = stream.try_collect::<Vec<_>>().await?;
+        let batch = concat_batches(&Arc::new(vectors.schema().clone()), &batches)?;
+        drop(batches);
+        let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+        let vertex_ids = batch[VERTEX_ID].as_primitive::<UInt32Type>();
+        let matrix = MatrixView::<Float32Type>::try_from(batch[column].as_fixed_size_list())?;
+
+        for (idx, vertex_id) in vertex_ids.values().iter().enumerate() {
+            let dist = distance_func(mean, matrix.row(idx).unwrap());
+            if medoid.map_or(true, |(nearest, _)| dist < nearest) {
+                medoid = Some((dist, *vertex_id));
+            }
+        }
+
+        let mut graph = random_graph(
+            row_ids.values(),
+            matrix,
+            params.r,
+            params.metric_type,
+            rng.clone(),
+        )
+        .await?;
+        index_graph(&mut graph, params, rng.clone()).await?;
+
+        let mut keys: HashMap<u32, Vec<u32>> = HashMap::new();
+        let mut neighbors = ListBuilder::new(UInt32Builder::new());
+        for (idx, node) in graph.nodes.iter().enumerate() {
+            let vertex_id = vertex_ids.value(idx);
+            keys.entry(vertex_id / params.sub_graph_size as u32)
+                .or_default()
+                .push(idx as u32);
+            neighbors.append_value(
+                node.neighbors
+                    .values()
+                    .iter()
+                    .map(|&n| Some(vertex_ids.value(n as usize))),
+            );
+        }
+        let edges = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(vertex_ids.clone()),
+                Arc::new(row_ids.clone()),
+                Arc::new(neighbors.finish()),
+            ],
+        )?;
+        for (key, indices) in keys {
+            let rows = edges.take(&UInt32Array::from(indices))?;
+            shuffler.insert(key, rows).await?;
+        }
+    }
+
+    // Every vertex is in a cluster.
+    let (_, medoid) = medoid.unwrap();
+    Ok((shuffler.finish().await?, medoid as usize))
+}
+
 /// Distance between two vectors in the matrix.
-fn distance(matrix: &MatrixView<Float32Type>, i: usize, j: usize) -> Result<f32> {
+fn distance(
+    matrix: &MatrixView<Float32Type>,
+    distance_func: DistanceFunc,
+    i: usize,
+    j: usize,
+) -> Result<f32> {
     let vector_i = matrix.row(i).ok_or(Error::Index {
         message: "Invalid row index".to_string(),
         location: location!(),
@@ -198,7 +544,7 @@ fn distance(matrix: &MatrixView<Float32Type>, i: usize, j: usize) -> Result<f32>
         location: location!(),
     })?;
 
-    Ok(l2_distance(vector_i, vector_j))
+    Ok(distance_func(vector_i, vector_j))
 }
 
 /// Algorithm 2 in the paper.
@@ -211,12 +557,13 @@ async fn robust_prune<V: Vertex + Clone + Sync + Send>(
 ) -> Result<Vec<u32>> {
     visited.remove(&id);
     let neighbors = graph.neighbors(id).await?;
+    let distance_func = graph.metric_type().func();
     visited.extend(neighbors.values().iter().map(|id| *id as usize));
 
     let mut heap: BinaryHeap<VertexWithDistance> = visited
         .iter()
         .map(|v| {
-            let dist = distance(&graph.data, id, *v).unwrap();
+            let dist = distance(&graph.data, distance_func, id, *v).unwrap();
             VertexWithDistance {
                 id: *v,
                 distance: OrderedFloat(dist),
@@ -241,8 +588,8 @@ async fn robust_prune<V: Vertex + Clone + Sync + Send>(
             }
             let mut to_remove: HashSet<usize> = HashSet::new();
             for pv in visited.iter() {
-                let dist_prime = distance(&matrix, p.id, *pv)?;
-                let dist_query = distance(&matrix, id, *pv)?;
+                let dist_prime = distance(&matrix, distance_func, p.id, *pv)?;
+                let dist_query = distance(&matrix, distance_func, id, *pv)?;
                 if alpha * dist_prime <= dist_query {
                     to_remove.insert(*pv);
                 }
@@ -273,6 +620,9 @@ async fn find_medoid(vectors: &MatrixView<Float32Type>, metric_type: MetricType)
         MetricType::L2 => l2_distance_batch(centrodis, data.values(), dim),
         MetricType::Cosine => cosine_distance_batch(centrodis, data.values(), dim),
         MetricType::Dot => dot_distance_batch(centrodis, data.values(), dim),
+        MetricType::Custom(name) => {
+            Box::new(custom_distance_batch(name, centrodis, data.values(), dim).into_iter())
+        }
     };
     let medoid_idx = argmin(dists).unwrap();
     Ok(medoid_idx as usize)
@@ -290,8 +640,8 @@ async fn index_once<V: Vertex + Clone + Sync + Send>(
     let mut ids = (0..graph.len()).collect::<Vec<_>>();
     ids.shuffle(&mut rng);
 
-    for (i, &id) in ids.iter().enumerate() {
-        let vector = graph.data.row(i).ok_or_else(|| Error::Index {
+    for &id in ids.iter() {
+        let vector = graph.data.row(id).ok_or_else(|| Error::Index {
             message: format!("Cannot find vector with id {}", id),
             location: location!(),
         })?;
@@ -372,11 +722,7 @@ async fn write_index_file(
             spec_version: 1,
             dimension: dimension as u32,
             stages,
-            metric_type: match metric_type {
-                MetricType::L2 => pb::VectorMetricType::L2.into(),
-                MetricType::Cosine => pb::VectorMetricType::Cosine.into(),
-                MetricType::Dot => pb::VectorMetricType::Dot.into(),
-            },
+            metric_type: pb::VectorMetricType::try_from(metric_type)?.into(),
         })),
     };
 
@@ -24,11 +24,13 @@ use arrow_array::types::Float32Type;
 use arrow_array::{ArrayRef, Float32Array, RecordBatch, UInt64Array};
 use arrow_schema::{DataType, Field, Schema};
 use async_trait::async_trait;
+use futures::future::try_join_all;
 use lance_core::{io::Reader, Error, Result, ROW_ID_FIELD};
 use lance_index::{
     vector::{Query, DIST_COL},
     Index, IndexType,
 };
+use lance_linalg::distance::MetricType;
 use nohash_hasher::IntMap;
 use object_store::path::Path;
 use ordered_float::OrderedFloat;
@@ -45,6 +47,7 @@ use crate::index::{
 use crate::{
     dataset::Dataset,
     index::{
+        pb,
         prefilter::PreFilter,
         vector::graph::{GraphReadParams, PersistedGraph},
     },
@@ -149,6 +152,29 @@ pub async fn greedy_search(
     query: &[f32],
     k: usize,
     search_size: usize, // L in the paper.
+) -> Result<SearchState> {
+    beam_search(graph, start, query, k, search_size, 1).await
+}
+
+/// Beam search.
+///
+/// Same as [`greedy_search`], but expands up to `beam_width` closest unvisited vertices
+/// in each round, so that the neighbors and vectors of them can be fetched from the
+/// storage concurrently.
+///
+/// Parameters:
+/// - start: The starting vertex.
+/// - query: The query vector.
+/// - k: The number of nearest neighbors to return.
+/// - search_size: Search list size, L in the paper.
+/// - beam_width: The number of vertices to expand in each round, W in the paper.
+pub async fn beam_search(
+    graph: &(dyn Graph + Send + Sync),
+    start: usize,
+    query: &[f32],
+    k: usize,
+    search_size: usize, // L in the paper.
+    beam_width: usize,  // W in the paper.
 ) -> Result<SearchState> {
     // L in the paper.
     // A map from distance to vertex id.
@@ -156,17 +182,29 @@ pub async fn greedy_search(
 
     let dist = graph.distance_to(query, start).await?;
     state.push(start, dist);
-    while let Some(id) = state.pop() {
-        state.visit(id);
+    loop {
+        let beam = (0..beam_width.max(1))
+            .map_while(|_| state.pop())
+            .collect::<Vec<_>>();
+        if beam.is_empty() {
+            break;
+        }
+        beam.iter().for_each(|id| state.visit(*id));
 
-        let neighbors = graph.neighbors(id).await?;
-        for neighbor_id in neighbors.values() {
+        let neighbors = try_join_all(beam.iter().map(|id| graph.neighbors(*id))).await?;
+        let mut unvisited = vec![];
+        let mut seen = HashSet::new();
+        for neighbor_id in neighbors.iter().flat_map(|n| n.values().iter()) {
             let neighbor_id = *neighbor_id as usize;
-            if state.is_visited(neighbor_id) {
+            if state.is_visited(neighbor_id) || !seen.insert(neighbor_id) {
                 // Already visited.
                 continue;
             }
-            let dist = graph.distance_to(query, neighbor_id).await?;
+            unvisited.push(neighbor_id);
+        }
+        let distances =
+            try_join_all(unvisited.iter().map(|id| graph.distance_to(query, *id))).await?;
+        for (neighbor_id, dist) in unvisited.into_iter().zip(distances) {
             state.push(neighbor_id, dist);
         }
     }
@@ -174,8 +212,19 @@ pub async fn greedy_search(
     Ok(state)
 }
 
+/// Default beam width for searching the persisted graph, from the DiskANN paper.
+const DEFAULT_BEAM_WIDTH: usize = 4;
+
 pub struct DiskANNIndex {
     graph: PersistedGraph<RowVertex>,
+
+    /// Entry points to the graph, i.e., the medoid.
+    entries: Vec<usize>,
+
+    /// Default search list size, `L` in the paper.
+    l: usize,
+
+    metric_type: MetricType,
 }
 
 impl std::fmt::Debug for DiskANNIndex {
@@ -186,17 +235,30 @@ impl std::fmt::Debug for DiskANNIndex {
 
 impl DiskANNIndex {
     /// Creates a new DiskANN index.
-
     pub async fn try_new(
         dataset: Arc<Dataset>,
         index_column: &str,
         graph_path: &Path,
+        proto: &pb::DiskAnn,
+        metric_type: MetricType,
     ) -> Result<Self> {
         let params = GraphReadParams::default();
         let serde = Arc::new(RowVertexSerDe::new());
-        let graph =
-            PersistedGraph::try_new(dataset, index_column, graph_path, params, serde).await?;
-        Ok(Self { graph })
+        let graph = PersistedGraph::try_new(
+            dataset,
+            index_column,
+            graph_path,
+            params,
+            serde,
+            metric_type,
+        )
+        .await?;
+        Ok(Self {
+            graph,
+            entries: proto.entries.iter().map(|e| *e as usize).collect(),
+            l: proto.l as usize,
+            metric_type,
+        })
     }
 }
 
@@ -220,6 +282,11 @@ impl Index for DiskANNIndex {
         IndexType::Vector
     }
 
+    fn memory_size(&self) -> usize {
+        // The graph is read from disk on demand.
+        self.entries.len() * std::mem::size_of::<usize>()
+    }
+
     fn statistics(&self) -> Result<String> {
         Ok(serde_json::to_string(&DiskANNIndexStatistics {
             index_type: "DiskANNIndex".to_string(),
@@ -239,43 +306,57 @@ impl Index for DiskANNIndex {
 impl VectorIndex for DiskANNIndex {
     #[instrument(level = "debug", skip_all, name = "DiskANNIndex::search")]
     async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch> {
-        let state = greedy_search(
+        let key = query
+            .key
+            .as_primitive_opt::<Float32Type>()
+            .ok_or_else(|| Error::Index {
+                message: format!(
+                    "DiskANN only supports float32 query vectors, got {}",
+                    query.key.data_type()
+                ),
+                location: location!(),
+            })?;
+        let k = query.k * query.refine_factor.unwrap_or(1) as usize;
+        let search_size = std::cmp::max(query.ef_search.unwrap_or(self.l), k);
+        let start = self.entries.first().copied().unwrap_or(0);
+        let state = beam_search(
             &self.graph,
-            0,
-            query.key.as_primitive::<Float32Type>().values(),
-            query.k,
-            query.k * 2,
+            start,
+            key.values(),
+            k,
+            search_size,
+            query.beam_width.unwrap_or(DEFAULT_BEAM_WIDTH),
         )
         .await?;
         let schema = Arc::new(Schema::new(vec![
-            ROW_ID_FIELD.clone(),
             Field::new(DIST_COL, DataType::Float32, true),
+            ROW_ID_FIELD.clone(),
         ]));
 
         pre_filter.wait_for_ready().await?;
 
-        let mut candidates = Vec::with_capacity(query.k);
-        for (distance, row) in state.candidates {
-            if candidates.len() == query.k {
+        let mut candidates = Vec::with_capacity(k);
+        for (distance, vertex_id) in state.candidates {
+            if candidates.len() == k {
                 break;
             }
-            if pre_filter.check_one(row as u64) {
-                candidates.push((distance, row));
+            if !query.in_range(*distance) {
+                continue;
+            }
+            let row_id = self.graph.vertex(vertex_id as u32).await?.row_id;
+            if pre_filter.is_empty() || pre_filter.check_one(row_id) {
+                candidates.push((distance, row_id));
             }
         }
 
-        let row_ids: UInt64Array = candidates
-            .iter()
-            .take(query.k)
-            .map(|(_, id)| *id as u64)
-            .collect();
-        let distances: Float32Array = candidates.iter().take(query.k).map(|(d, _)| **d).collect();
+        let row_ids: UInt64Array = candidates.iter().map(|(_, id)| *id).collect();
+        let distances: Float32Array = candidates.iter().map(|(d, _)| **d).collect();
 
         let batch = RecordBatch::try_new(
             schema,
             vec![
-                Arc::new(row_ids) as ArrayRef,
                 Arc::new(distances) as ArrayRef,
+                Arc::new(row_ids) as ArrayRef,
             ],
         )?;
         Ok(batch)
@@ -310,6 +391,10 @@ impl VectorIndex for DiskANNIndex {
             location: location!(),
         })
     }
+
+    fn metric_type(&self) -> MetricType {
+        self.metric_type
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,288 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Flat sub-index, which keeps the original vectors of an IVF partition.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::{as_primitive_array, AsArray},
+    Array, FixedSizeListArray, RecordBatch, UInt64Array,
+};
+use arrow_ord::sort::sort_to_indices;
+use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema, SortOptions};
+use arrow_select::take::take;
+use async_trait::async_trait;
+use lance_arrow::*;
+use lance_core::{
+    format::RowAddress,
+    io::{read_fixed_stride_array, Reader},
+    ROW_ID_FIELD,
+};
+use lance_index::{
+    vector::{Query, DIST_COL},
+    Index, IndexType,
+};
+use lance_linalg::distance::MetricType;
+use nohash_hasher::IntMap;
+use roaring::RoaringBitmap;
+use serde::Serialize;
+use snafu::{location, Location};
+use tracing::instrument;
+
+use super::VectorIndex;
+use crate::index::prefilter::PreFilter;
+use crate::utils::tokio::spawn_cpu;
+use crate::{Error, Result};
+
+/// Flat index that computes the exact distance to every vector in the partition.
+///
+/// Used as the sub-index of `IVF_FLAT`, which trades index size for
+/// the recall lost by quantization.
+#[derive(Clone)]
+pub struct FlatIndex {
+    /// Vector dimension.
+    dimension: usize,
+
+    /// Data type of the vector elements.
+    value_type: DataType,
+
+    /// Original vectors.
+    pub vectors: Option<Arc<FixedSizeListArray>>,
+
+    /// ROW Id used to refer to the actual row in dataset.
+    pub row_ids: Option<Arc<UInt64Array>>,
+
+    /// Metric type.
+    metric_type: MetricType,
+}
+
+impl std::fmt::Debug for FlatIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Flat(dim={}, {}, {})",
+            self.dimension, self.value_type, self.metric_type
+        )
+    }
+}
+
+impl FlatIndex {
+    pub(crate) fn new(dimension: usize, value_type: DataType, metric_type: MetricType) -> Self {
+        Self {
+            dimension,
+            value_type,
+            vectors: None,
+            row_ids: None,
+            metric_type,
+        }
+    }
+
+    /// Filter the row id and vector arrays based on the pre-filter.
+    fn filter_arrays(
+        pre_filter: &PreFilter,
+        vectors: Arc<FixedSizeListArray>,
+        row_ids: Arc<UInt64Array>,
+    ) -> Result<(Arc<FixedSizeListArray>, Arc<UInt64Array>)> {
+        let indices_to_keep = pre_filter.filter_row_ids(row_ids.values());
+        let indices_to_keep = UInt64Array::from(indices_to_keep);
+
+        let row_ids = take(row_ids.as_ref(), &indices_to_keep, None)?;
+        let row_ids = Arc::new(as_primitive_array(&row_ids).clone());
+
+        let vectors = take(vectors.as_ref(), &indices_to_keep, None)?;
+        let vectors = Arc::new(as_fixed_size_list_array(&vectors).clone());
+
+        Ok((vectors, row_ids))
+    }
+}
+
+#[derive(Serialize)]
+pub struct FlatIndexStatistics {
+    index_type: String,
+    dimension: usize,
+    metric_type: String,
+}
+
+#[async_trait]
+impl Index for FlatIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Vector
+    }
+
+    fn memory_size(&self) -> usize {
+        self.vectors
+            .as_ref()
+            .map_or(0, |v| v.get_array_memory_size())
+            + self
+                .row_ids
+                .as_ref()
+                .map_or(0, |r| r.get_array_memory_size())
+    }
+
+    fn statistics(&self) -> Result<String> {
+        Ok(serde_json::to_string(&FlatIndexStatistics {
+            index_type: "FLAT".to_string(),
+            dimension: self.dimension,
+            metric_type: self.metric_type.to_string(),
+        })?)
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        if let Some(row_ids) = &self.row_ids {
+            let mut frag_ids = row_ids
+                .values()
+                .iter()
+                .map(|&row_id| RowAddress::new_from_id(row_id).fragment_id())
+                .collect::<Vec<_>>();
+            frag_ids.sort();
+            frag_ids.dedup();
+            Ok(RoaringBitmap::from_sorted_iter(frag_ids).unwrap())
+        } else {
+            Err(Error::Index {
+                message: "FlatIndex::calculate_included_frags: index is not loaded".to_string(),
+                location: location!(),
+            })
+        }
+    }
+}
+
+#[async_trait]
+impl VectorIndex for FlatIndex {
+    /// Search top-k nearest neighbors for `key` within one partition.
+    ///
+    /// Distances are computed against the original vectors, there is no decoding step.
+    #[instrument(level = "debug", skip_all, name = "FlatIndex::search")]
+    async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch> {
+        if self.vectors.is_none() || self.row_ids.is_none() {
+            return Err(Error::Index {
+                message: "FlatIndex::search: index is not loaded".to_string(),
+                location: location!(),
+            });
+        }
+        pre_filter.wait_for_ready().await?;
+
+        let vectors = self.vectors.as_ref().unwrap().clone();
+        let row_ids = self.row_ids.as_ref().unwrap().clone();
+
+        let query = query.clone();
+        let metric_type = self.metric_type;
+        spawn_cpu(move || {
+            let (vectors, row_ids) = if pre_filter.is_empty() {
+                Ok((vectors, row_ids))
+            } else {
+                Self::filter_arrays(pre_filter.as_ref(), vectors, row_ids)
+            }?;
+
+            let distances = metric_type.arrow_batch_func()(query.key.as_ref(), &vectors)?;
+            let (distances, row_ids) = query.filter_distance_range(distances, row_ids)?;
+
+            let limit = query.k * query.refine_factor.unwrap_or(1) as usize;
+            let sort_options = SortOptions {
+                nulls_first: false,
+                ..Default::default()
+            };
+            let indices = sort_to_indices(distances.as_ref(), Some(sort_options), Some(limit))?;
+            let distances = take(distances.as_ref(), &indices, None)?;
+            let row_ids = take(row_ids.as_ref(), &indices, None)?;
+
+            let schema = Arc::new(ArrowSchema::new(vec![
+                ArrowField::new(DIST_COL, DataType::Float32, true),
+                ROW_ID_FIELD.clone(),
+            ]));
+            Ok(RecordBatch::try_new(schema, vec![distances, row_ids])?)
+        })
+        .await
+    }
+
+    fn is_loadable(&self) -> bool {
+        true
+    }
+
+    /// Load the vectors and row ids of one partition from the disk.
+    async fn load(
+        &self,
+        reader: &dyn Reader,
+        offset: usize,
+        length: usize,
+    ) -> Result<Box<dyn VectorIndex>> {
+        let byte_width = self
+            .value_type
+            .primitive_width()
+            .ok_or_else(|| Error::Index {
+                message: format!(
+                    "FlatIndex: unsupported vector element type {}",
+                    self.value_type
+                ),
+                location: location!(),
+            })?;
+        let num_values = self.dimension * length;
+        let values =
+            read_fixed_stride_array(reader, &self.value_type, offset, num_values, ..).await?;
+        let vectors = FixedSizeListArray::try_new_from_values(values, self.dimension as i32)?;
+
+        let row_id_offset = offset + num_values * byte_width;
+        let row_ids =
+            read_fixed_stride_array(reader, &DataType::UInt64, row_id_offset, length, ..).await?;
+
+        Ok(Box::new(Self {
+            vectors: Some(Arc::new(vectors)),
+            row_ids: Some(Arc::new(row_ids.as_primitive().clone())),
+            ..self.clone()
+        }))
+    }
+
+    fn check_can_remap(&self) -> Result<()> {
+        Ok(())
+    }
+
+    fn remap(&mut self, mapping: &IntMap<u64, Option<u64>>) -> Result<()> {
+        let row_ids = self.row_ids.as_ref().unwrap();
+        let (indices, new_row_ids): (Vec<u64>, Vec<u64>) = row_ids
+            .values()
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, old_row_id)| {
+                // If the row id is not in the mapping then this row is not remapped and we keep as is
+                let new_row_id = mapping
+                    .get(old_row_id)
+                    .cloned()
+                    .unwrap_or(Some(*old_row_id));
+                new_row_id.map(|new_row_id| (idx as u64, new_row_id))
+            })
+            .unzip();
+
+        let vectors = take(
+            self.vectors.as_ref().unwrap().as_ref(),
+            &UInt64Array::from(indices),
+            None,
+        )?;
+        self.vectors = Some(Arc::new(as_fixed_size_list_array(&vectors).clone()));
+        self.row_ids = Some(Arc::new(UInt64Array::from(new_row_ids)));
+        Ok(())
+    }
+
+    fn metric_type(&self) -> MetricType {
+        self.metric_type
+    }
+}
@@ -69,6 +69,10 @@ impl<V: Vertex + Clone + Sync + Send> GraphBuilder<V> {
         }
     }
 
+    pub fn metric_type(&self) -> MetricType {
+        self.metric_type
+    }
+
     pub fn len(&self) -> usize {
         self.nodes.len()
     }
@@ -28,12 +28,15 @@ use lance_core::{
     io::{object_store::ObjectStore, FileReader, FileWriter},
     Error, Result,
 };
-use lance_linalg::distance::l2::l2;
+use lance_linalg::distance::{DistanceFunc, MetricType};
 use lru_time_cache::LruCache;
 use object_store::path::Path;
 use snafu::{location, Location};
 
-use super::{builder::GraphBuilder, Graph};
+use super::{
+    builder::{GraphBuilder, Node},
+    Graph,
+};
 use super::{Vertex, VertexSerDe};
 use crate::dataset::Dataset;
 use crate::index::vector::diskann::RowVertex;
@@ -90,6 +93,9 @@ pub struct PersistedGraph<V: Vertex + Debug> {
 
     /// SerDe for vertex.
     serde: Arc<dyn VertexSerDe<V> + Send + Sync>,
+
+    /// Distance function between the vectors.
+    distance_func: DistanceFunc,
 }
 
 impl<V: Vertex + Debug> PersistedGraph<V> {
@@ -100,6 +106,7 @@ impl<V: Vertex + Debug> PersistedGraph<V> {
         path: &Path,
         params: GraphReadParams,
         serde: Arc<dyn VertexSerDe<V> + Send + Sync>,
+        metric_type: MetricType,
     ) -> Result<Self> {
         let object_store = dataset.object_store();
         let file_reader = FileReader::try_new(object_store, path).await?;
@@ -144,6 +151,7 @@ impl<V: Vertex + Debug> PersistedGraph<V> {
             neighbors_projection,
             params,
             serde,
+            distance_func: metric_type.func(),
         })
     }
 
@@ -234,7 +242,7 @@ impl<V: Vertex + Send + Sync + Debug> Graph for PersistedGraph<V> {
 
     async fn distance_to(&self, query: &[f32], idx: usize) -> Result<f32> {
         let vertex = self.vertex(idx as u32).await?;
-        Ok(l2(vertex.vector(), query))
+        Ok((self.distance_func)(vertex.vector(), query))
     }
 
     /// Get the neighbors of a vertex, specified by its id.
@@ -293,25 +301,62 @@ pub async fn write_graph<V: Vertex + Clone + Sync + Send>(
             location: location!(),
         });
     }
-    let binary_size = serde.size();
-    let arrow_schema = Arc::new(ArrowSchema::new(vec![
-        Field::new(
-            VERTEX_COL,
-            DataType::FixedSizeBinary(binary_size as i32),
-            false,
-        ),
-        Field::new(
-            NEIGHBORS_COL,
-            DataType::List(Arc::new(Field::new("item", DataType::UInt32, true))),
-            false,
-        ),
-    ]));
-    let schema = Schema::try_from(arrow_schema.as_ref())?;
-
-    let mut writer = FileWriter::try_new(object_store, path, schema, &Default::default()).await?;
+    let mut writer = GraphWriter::try_new(object_store, path, serde.size()).await?;
     for nodes in graph.nodes.as_slice().chunks(params.batch_size) {
+        writer.write(nodes, serde).await?;
+    }
+    writer.finish().await
+}
+
+/// Writes the nodes of a graph to a file in the order of their ids, so that a graph
+/// too large for memory can be written in pieces.
+pub struct GraphWriter {
+    writer: FileWriter,
+    arrow_schema: Arc<ArrowSchema>,
+    binary_size: usize,
+    num_nodes: usize,
+}
+
+impl GraphWriter {
+    /// Create a writer of a graph whose vertices are serialized to `binary_size` bytes.
+    pub async fn try_new(
+        object_store: &ObjectStore,
+        path: &Path,
+        binary_size: usize,
+    ) -> Result<Self> {
+        let arrow_schema = Arc::new(ArrowSchema::new(vec![
+            Field::new(
+                VERTEX_COL,
+                DataType::FixedSizeBinary(binary_size as i32),
+                false,
+            ),
+            Field::new(
+                NEIGHBORS_COL,
+                DataType::List(Arc::new(Field::new("item", DataType::UInt32, true))),
+                false,
+            ),
+        ]));
+        let schema = Schema::try_from(arrow_schema.as_ref())?;
+        let writer = FileWriter::try_new(object_store, path, schema, &Default::default()).await?;
+        Ok(Self {
+            writer,
+            arrow_schema,
+            binary_size,
+            num_nodes: 0,
+        })
+    }
+
+    /// Write the next nodes of the graph.
+    pub async fn write<V: Vertex>(
+        &mut self,
+        nodes: &[Node<V>],
+        serde: &impl VertexSerDe<V>,
+    ) -> Result<()> {
+        if nodes.is_empty() {
+            return Ok(());
+        }
         let mut vertex_builder =
-            FixedSizeBinaryBuilder::with_capacity(nodes.len(), binary_size as i32);
+            FixedSizeBinaryBuilder::with_capacity(nodes.len(), self.binary_size as i32);
         let total_neighbors = nodes.iter().map(|node| node.neighbors.len()).sum();
         let inner_builder = UInt32Builder::with_capacity(total_neighbors);
         let mut neighbors_builder = ListBuilder::with_capacity(inner_builder, nodes.len());
@@ -324,19 +369,28 @@ pub async fn write_graph<V: Vertex + Clone + Sync + Send>(
             neighbors_builder.append(true);
         }
         let batch = RecordBatch::try_new(
-            arrow_schema.clone(),
+            self.arrow_schema.clone(),
             vec![
                 Arc::new(vertex_builder.finish()),
                 Arc::new(neighbors_builder.finish()),
             ],
         )?;
 
-        writer.write(&[batch]).await?;
+        self.writer.write(&[batch]).await?;
+        self.num_nodes += nodes.len();
+        Ok(())
     }
 
-    writer.finish().await?;
-
-    Ok(())
+    pub async fn finish(mut self) -> Result<()> {
+        if self.num_nodes == 0 {
+            return Err(Error::Index {
+                message: "Invalid graph".to_string(),
+                location: location!(),
+            });
+        }
+        self.writer.finish().await?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -459,6 +513,7 @@ mod tests {
             &graph_path,
             GraphReadParams::default(),
             serde,
+            MetricType::L2,
         )
         .await
         .unwrap();
@@ -0,0 +1,163 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// HNSW: Efficient and robust approximate nearest neighbor search using
+/// Hierarchical Navigable Small World graphs.
+///
+/// The graph and the vectors are kept in memory, so it suits datasets that fit in memory
+/// and require high recall with low latency.
+mod builder;
+mod graph;
+mod search;
+
+pub(crate) use builder::build_hnsw_index;
+pub(crate) use search::HNSWIndex;
+
+// The graph of CAGRA is stored and searched as a single level HNSW graph.
+#[cfg(feature = "cuda")]
+pub(super) use builder::{build_graph, load_vectors, write_graph};
+#[cfg(feature = "cuda")]
+pub(super) use graph::HnswGraph;
+#[cfg(feature = "cuda")]
+pub(super) use search::{read_graph, search_results};
+
+/// Column of the vectors in the persisted graph file.
+const VECTOR_COL: &str = "vector";
+
+/// Column of the neighbors on `level` in the persisted graph file.
+fn neighbors_column(level: usize) -> String {
+    format!("neighbors_{}", level)
+}
+
+#[derive(Clone, Debug)]
+pub struct HNSWParams {
+    /// Max number of neighbors per vertex on the upper levels.
+    /// The bottom level allows `2 * m` neighbors.
+    pub m: usize,
+
+    /// Size of the dynamic candidate list during construction.
+    pub ef_construction: usize,
+
+    /// The highest level a vertex can reach.
+    pub max_level: u16,
+}
+
+// Default values from the hnswlib.
+impl Default for HNSWParams {
+    fn default() -> Self {
+        Self {
+            m: 16,
+            ef_construction: 200,
+            max_level: 7,
+        }
+    }
+}
+
+impl HNSWParams {
+    pub fn new(m: usize, ef_construction: usize) -> Self {
+        Self {
+            m,
+            ef_construction,
+            ..Default::default()
+        }
+    }
+
+    pub fn m(&mut self, m: usize) -> &mut Self {
+        self.m = m;
+        self
+    }
+
+    pub fn ef_construction(&mut self, ef_construction: usize) -> &mut Self {
+        self.ef_construction = ef_construction;
+        self
+    }
+
+    pub fn max_level(&mut self, max_level: u16) -> &mut Self {
+        self.max_level = max_level;
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow_array::{cast::AsArray, types::UInt64Type, Float32Array};
+    use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+    use futures::TryStreamExt;
+    use lance_linalg::distance::MetricType;
+    use lance_testing::datagen::generate_random_array;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::{
+        arrow::*,
+        dataset::{Dataset, ROW_ID},
+        index::{vector::VectorIndexParams, DatasetIndexExt, IndexType},
+    };
+
+    #[tokio::test]
+    async fn test_create_and_search_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let dimension = 16;
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "embeddings",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dimension,
+            ),
+            false,
+        )]));
+
+        let float_arr = generate_random_array(1000 * dimension as usize);
+        let vectors =
+            Arc::new(FixedSizeListArray::try_new_from_values(float_arr, dimension).unwrap());
+        let batches = vec![RecordBatch::try_new(schema.clone(), vec![vectors.clone()]).unwrap()];
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        let params = VectorIndexParams::with_hnsw_params(MetricType::L2, HNSWParams::default());
+        dataset
+            .create_index(&["embeddings"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let indices = dataset.load_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+
+        // Each vector should find itself as the nearest neighbor.
+        for row in [0, 123, 999] {
+            let query = vectors.value(row);
+            let query: &Float32Array = query.as_primitive();
+            let results = dataset
+                .scan()
+                .nearest("embeddings", query, 10)
+                .unwrap()
+                .ef_search(64)
+                .with_row_id()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(results[0].num_rows(), 10);
+            let row_ids = results[0][ROW_ID].as_primitive::<UInt64Type>();
+            assert_eq!(row_ids.value(0), row as u64);
+        }
+    }
+}
@@ -0,0 +1,350 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{ListBuilder, UInt32Builder},
+    cast::AsArray,
+    types::{Float32Type, UInt64Type},
+    ArrayRef, FixedSizeListArray, RecordBatch, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+use arrow_select::concat::concat_batches;
+use futures::TryStreamExt;
+use lance_arrow::*;
+use lance_core::{
+    datatypes::Schema,
+    io::{object_store::ObjectStore, FileWriter, WriteExt},
+};
+use lance_linalg::{distance::MetricType, MatrixView};
+use object_store::path::Path;
+use ordered_float::OrderedFloat;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use snafu::{location, Location};
+
+use super::graph::HnswGraph;
+use super::{neighbors_column, HNSWParams, VECTOR_COL};
+use crate::dataset::{Dataset, ROW_ID};
+use crate::index::{pb, vector::utils::filter_null_vectors, INDEX_FILE_NAME};
+use crate::utils::tokio::spawn_cpu;
+use crate::{Error, Result};
+
+const GRAPH_FILE_NAME: &str = "hnsw_graph.lance";
+
+/// Build a HNSW index on the vector column.
+pub async fn build_hnsw_index(
+    dataset: &Dataset,
+    column: &str,
+    name: &str,
+    uuid: &str,
+    metric_type: MetricType,
+    params: &HNSWParams,
+) -> Result<()> {
+    if params.m < 2 {
+        return Err(Error::Index {
+            message: format!("HNSW: m must be at least 2, got {}", params.m),
+            location: location!(),
+        });
+    }
+    let (row_ids, vectors) = load_vectors(dataset, column, "HNSW").await?;
+
+    let build_params = params.clone();
+    let graph = spawn_cpu(move || {
+        let mut rng = SmallRng::from_entropy();
+        Ok(build_graph(
+            vectors,
+            row_ids,
+            metric_type,
+            &build_params,
+            &mut rng,
+        ))
+    })
+    .await?;
+
+    let index_dir = dataset.indices_dir().child(uuid);
+    let graph_file = index_dir.child(GRAPH_FILE_NAME);
+    write_graph(&graph, dataset.object_store(), &graph_file).await?;
+
+    write_index_file(dataset, column, name, uuid, &graph, params).await
+}
+
+/// Load all the vectors with their row ids from the dataset, to build the graph of
+/// the `index_type` index.
+pub async fn load_vectors(
+    dataset: &Dataset,
+    column: &str,
+    index_type: &str,
+) -> Result<(Vec<u64>, MatrixView<Float32Type>)> {
+    let field = dataset.schema().field(column).ok_or_else(|| Error::Index {
+        message: format!("{}: column {} does not exist", index_type, column),
+        location: location!(),
+    })?;
+    match field.data_type() {
+        DataType::FixedSizeList(f, _) if f.data_type() == &DataType::Float32 => {}
+        _ => {
+            return Err(Error::Index {
+                message: format!(
+                    "{} requires the column to be fixed size list of float32s, got {}",
+                    index_type,
+                    field.data_type()
+                ),
+                location: location!(),
+            })
+        }
+    };
+
+    let batches = dataset
+        .scan()
+        .project(&[column])?
+        .with_index_row_id()
+        .try_into_stream()
+        .await?
+        .try_collect::<Vec<_>>()
+        .await?
+        .iter()
+        // Null vectors are not indexed.
+        .map(|batch| filter_null_vectors(batch, column))
+        .collect::<Result<Vec<_>>>()?;
+    if batches.iter().all(|batch| batch.num_rows() == 0) {
+        return Err(Error::Index {
+            message: format!("{}: can not build index on an empty dataset", index_type),
+            location: location!(),
+        });
+    }
+    let batch = concat_batches(&batches[0].schema(), &batches)?;
+    let row_ids = batch
+        .column_by_name(ROW_ID)
+        .ok_or_else(|| Error::Index {
+            message: format!("{}: row id column not found", index_type),
+            location: location!(),
+        })?
+        .as_primitive::<UInt64Type>()
+        .values()
+        .to_vec();
+    let vectors = batch
+        .column_by_name(column)
+        .ok_or_else(|| Error::Index {
+            message: format!("{}: column {} not found", index_type, column),
+            location: location!(),
+        })?
+        .as_fixed_size_list();
+    Ok((row_ids, MatrixView::<Float32Type>::try_from(vectors)?))
+}
+
+/// Randomly pick the top level of a new vertex, `floor(-ln(U) * mL)` in the paper.
+fn random_level(rng: &mut impl Rng, ml: f64, max_level: usize) -> usize {
+    let uniform: f64 = rng.gen_range(f64::EPSILON..1.0);
+    std::cmp::min((-uniform.ln() * ml).floor() as usize, max_level)
+}
+
+/// Insert vertices one by one, Algorithm 1 in the paper.
+pub fn build_graph(
+    vectors: MatrixView<Float32Type>,
+    row_ids: Vec<u64>,
+    metric_type: MetricType,
+    params: &HNSWParams,
+    rng: &mut impl Rng,
+) -> HnswGraph {
+    let num_vertices = row_ids.len();
+    let ml = 1.0 / (params.m as f64).ln();
+    let mut graph = HnswGraph::new(vectors, row_ids, vec![], 0, metric_type);
+
+    for id in 0..num_vertices as u32 {
+        let level = random_level(rng, ml, params.max_level as usize);
+        // The entry point always lives on the top level.
+        let top_level = graph.levels.len().saturating_sub(1);
+        while graph.levels.len() <= level {
+            graph.levels.push(vec![vec![]; num_vertices]);
+        }
+        if id == 0 {
+            graph.entry_point = id;
+            continue;
+        }
+
+        let vector = graph.vectors.row(id as usize).unwrap().to_vec();
+        let mut entry = vec![(
+            OrderedFloat(graph.distance_to(&vector, graph.entry_point)),
+            graph.entry_point,
+        )];
+        for l in (level + 1..=top_level).rev() {
+            entry = graph.search_level(&vector, &entry, 1, l);
+        }
+        for l in (0..=std::cmp::min(level, top_level)).rev() {
+            let candidates = graph.search_level(&vector, &entry, params.ef_construction, l);
+            let max_neighbors = if l == 0 { params.m * 2 } else { params.m };
+            let neighbors = candidates
+                .iter()
+                .take(params.m)
+                .map(|(_, n)| *n)
+                .collect::<Vec<_>>();
+            for &neighbor in neighbors.iter() {
+                graph.levels[l][neighbor as usize].push(id);
+                if graph.levels[l][neighbor as usize].len() > max_neighbors {
+                    prune(&mut graph, neighbor, l, max_neighbors);
+                }
+            }
+            graph.levels[l][id as usize] = neighbors;
+            entry = candidates;
+        }
+        if level > top_level {
+            graph.entry_point = id;
+        }
+    }
+    graph
+}
+
+/// Shrink the neighbors of `id` on `level` to the `max_neighbors` closest ones.
+fn prune(graph: &mut HnswGraph, id: u32, level: usize, max_neighbors: usize) {
+    let mut neighbors = graph.levels[level][id as usize]
+        .iter()
+        .map(|&n| (OrderedFloat(graph.distance(id, n)), n))
+        .collect::<Vec<_>>();
+    neighbors.sort();
+    neighbors.truncate(max_neighbors);
+    graph.levels[level][id as usize] = neighbors.into_iter().map(|(_, n)| n).collect();
+}
+
+/// Persist the graph, including vectors and row ids, into a lance file.
+pub async fn write_graph(graph: &HnswGraph, object_store: &ObjectStore, path: &Path) -> Result<()> {
+    let dim = graph.vectors.num_columns();
+    let mut fields = vec![
+        Field::new(ROW_ID, DataType::UInt64, false),
+        Field::new(
+            VECTOR_COL,
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                dim as i32,
+            ),
+            false,
+        ),
+    ];
+    for level in 0..graph.num_levels() {
+        fields.push(Field::new(
+            neighbors_column(level),
+            DataType::List(Arc::new(Field::new("item", DataType::UInt32, true))),
+            false,
+        ));
+    }
+    let arrow_schema = Arc::new(ArrowSchema::new(fields));
+    let schema = Schema::try_from(arrow_schema.as_ref())?;
+
+    let mut columns: Vec<ArrayRef> = vec![
+        Arc::new(UInt64Array::from(graph.row_ids.clone())),
+        Arc::new(FixedSizeListArray::try_new_from_values(
+            graph.vectors.data().as_ref().clone(),
+            dim as i32,
+        )?),
+    ];
+    for level in graph.levels.iter() {
+        let total = level.iter().map(|n| n.len()).sum();
+        let mut builder =
+            ListBuilder::with_capacity(UInt32Builder::with_capacity(total), level.len());
+        for neighbors in level {
+            builder.values().append_slice(neighbors);
+            builder.append(true);
+        }
+        columns.push(Arc::new(builder.finish()));
+    }
+    let batch = RecordBatch::try_new(arrow_schema, columns)?;
+
+    let mut writer = FileWriter::try_new(object_store, path, schema, &Default::default()).await?;
+    writer.write(&[batch]).await?;
+    writer.finish().await?;
+    Ok(())
+}
+
+async fn write_index_file(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    graph: &HnswGraph,
+    params: &HNSWParams,
+) -> Result<()> {
+    let object_store = dataset.object_store();
+    let path = dataset.indices_dir().child(uuid).child(INDEX_FILE_NAME);
+    let mut writer = object_store.create(&path).await?;
+
+    let stages = vec![pb::VectorIndexStage {
+        stage: Some(pb::vector_index_stage::Stage::Hnsw(pb::Hnsw {
+            filename: GRAPH_FILE_NAME.to_string(),
+            m: params.m as u32,
+            ef_construction: params.ef_construction as u32,
+            num_levels: graph.num_levels() as u32,
+            entry_point: graph.entry_point as u64,
+        })),
+    }];
+    let metadata = pb::Index {
+        name: index_name.to_string(),
+        columns: vec![column.to_string()],
+        dataset_version: dataset.version().version,
+        index_type: pb::IndexType::Vector.into(),
+        implementation: Some(pb::index::Implementation::VectorIndex(pb::VectorIndex {
+            spec_version: 1,
+            dimension: graph.vectors.num_columns() as u32,
+            stages,
+            metric_type: pb::VectorMetricType::try_from(graph.metric_type)?.into(),
+        })),
+    };
+
+    let pos = writer.write_protobuf(&metadata).await?;
+    writer.write_magics(pos).await?;
+    writer.shutdown().await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use lance_linalg::distance::l2_distance_batch;
+    use lance_linalg::kernels::argmin;
+
+    #[test]
+    fn test_build_graph() {
+        let num_vertices = 500;
+        let vectors = MatrixView::<Float32Type>::random(num_vertices, 16);
+        let params = HNSWParams::default();
+        let mut rng = SmallRng::seed_from_u64(42);
+        let graph = build_graph(
+            vectors.clone(),
+            (0..num_vertices as u64).collect(),
+            MetricType::L2,
+            &params,
+            &mut rng,
+        );
+
+        assert_eq!(graph.len(), num_vertices);
+        for (l, level) in graph.levels.iter().enumerate() {
+            let max_neighbors = if l == 0 { params.m * 2 } else { params.m };
+            assert!(level.iter().all(|n| n.len() <= max_neighbors));
+        }
+        assert!(graph.levels[0].iter().all(|n| !n.is_empty()));
+
+        // Every vector should find itself.
+        for i in (0..num_vertices).step_by(50) {
+            let query = vectors.row(i).unwrap();
+            let results = graph.search(query, 20);
+            let expected = argmin(l2_distance_batch(
+                query,
+                vectors.data().values(),
+                vectors.ndim(),
+            ))
+            .unwrap();
+            assert_eq!(results[0].1, expected);
+        }
+    }
+}
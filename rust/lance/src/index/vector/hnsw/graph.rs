@@ -0,0 +1,165 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! In-memory HNSW graph.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+
+use arrow_array::types::Float32Type;
+use lance_linalg::distance::{DistanceFunc, MetricType};
+use lance_linalg::MatrixView;
+use ordered_float::OrderedFloat;
+
+/// A candidate vertex, ordered by its distance to the query.
+pub type Candidate = (OrderedFloat<f32>, u32);
+
+/// Hierarchical Navigable Small World graph.
+///
+/// All vectors are kept in memory. Vertex `i` refers to the `i`-th row of `vectors`
+/// and to the `i`-th element of `row_ids`.
+pub struct HnswGraph {
+    /// Vectors of all vertices.
+    pub vectors: MatrixView<Float32Type>,
+
+    /// Row ID of each vertex in the dataset.
+    pub row_ids: Vec<u64>,
+
+    /// `levels[l][i]` is the neighbors of vertex `i` on level `l`.
+    ///
+    /// A vertex that does not reach level `l` has no neighbors on that level.
+    pub levels: Vec<Vec<Vec<u32>>>,
+
+    /// Entry point on the top level.
+    pub entry_point: u32,
+
+    /// Metric type.
+    pub metric_type: MetricType,
+
+    distance_func: DistanceFunc,
+}
+
+impl HnswGraph {
+    pub fn new(
+        vectors: MatrixView<Float32Type>,
+        row_ids: Vec<u64>,
+        levels: Vec<Vec<Vec<u32>>>,
+        entry_point: u32,
+        metric_type: MetricType,
+    ) -> Self {
+        Self {
+            vectors,
+            row_ids,
+            levels,
+            entry_point,
+            metric_type,
+            distance_func: metric_type.func(),
+        }
+    }
+
+    /// Number of vertices in the graph.
+    pub fn len(&self) -> usize {
+        self.row_ids.len()
+    }
+
+    /// Number of levels in the graph.
+    pub fn num_levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// Distance from the query vector to vertex `id`.
+    pub fn distance_to(&self, query: &[f32], id: u32) -> f32 {
+        // Vertex ids are always in range, they are assigned from `0..len()`.
+        (self.distance_func)(query, self.vectors.row(id as usize).unwrap())
+    }
+
+    /// Distance between two vertices.
+    pub fn distance(&self, a: u32, b: u32) -> f32 {
+        (self.distance_func)(
+            self.vectors.row(a as usize).unwrap(),
+            self.vectors.row(b as usize).unwrap(),
+        )
+    }
+
+    /// Greedy search on one level, Algorithm 2 in the paper.
+    ///
+    /// Returns at most `ef` closest vertices to the query, sorted by distance.
+    pub fn search_level(
+        &self,
+        query: &[f32],
+        entry_points: &[Candidate],
+        ef: usize,
+        level: usize,
+    ) -> Vec<Candidate> {
+        let neighbors = &self.levels[level];
+        let mut visited: HashSet<u32> = entry_points.iter().map(|(_, id)| *id).collect();
+        let mut candidates: BinaryHeap<Reverse<Candidate>> =
+            entry_points.iter().copied().map(Reverse).collect();
+        let mut results: BinaryHeap<Candidate> = entry_points.iter().copied().collect();
+
+        while let Some(Reverse((dist, id))) = candidates.pop() {
+            if let Some((furthest, _)) = results.peek() {
+                if dist > *furthest && results.len() >= ef {
+                    break;
+                }
+            }
+            for &neighbor in neighbors[id as usize].iter() {
+                if !visited.insert(neighbor) {
+                    continue;
+                }
+                let neighbor_dist = OrderedFloat(self.distance_to(query, neighbor));
+                let furthest = results.peek().map(|(d, _)| *d);
+                if results.len() < ef || furthest.map_or(true, |d| neighbor_dist < d) {
+                    candidates.push(Reverse((neighbor_dist, neighbor)));
+                    results.push((neighbor_dist, neighbor));
+                    if results.len() > ef {
+                        results.pop();
+                    }
+                }
+            }
+        }
+
+        results.into_sorted_vec()
+    }
+
+    /// Search the `ef` nearest vertices to the query vector, sorted by distance.
+    pub fn search(&self, query: &[f32], ef: usize) -> Vec<Candidate> {
+        if self.is_empty() {
+            return vec![];
+        }
+        let mut entry = vec![(
+            OrderedFloat(self.distance_to(query, self.entry_point)),
+            self.entry_point,
+        )];
+        for level in (1..self.num_levels()).rev() {
+            entry = self.search_level(query, &entry, 1, level);
+        }
+        self.search_level(query, &entry, ef, 0)
+    }
+
+    /// Search the `ef` nearest vertices to the query vector on the bottom level,
+    /// starting from the `entry_points`, sorted by distance.
+    #[cfg(feature = "cuda")]
+    pub fn search_from(&self, query: &[f32], entry_points: &[u32], ef: usize) -> Vec<Candidate> {
+        let entry = entry_points
+            .iter()
+            .map(|&id| (OrderedFloat(self.distance_to(query, id)), id))
+            .collect::<Vec<_>>();
+        self.search_level(query, &entry, ef, 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.row_ids.is_empty()
+    }
+}
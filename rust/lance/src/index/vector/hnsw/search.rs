@@ -0,0 +1,304 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::{any::Any, sync::Arc};
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float32Type, UInt32Type, UInt64Type},
+    ArrayRef, Float32Array, RecordBatch, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema};
+use async_trait::async_trait;
+use lance_core::{
+    format::RowAddress,
+    io::{object_store::ObjectStore, FileReader, Reader},
+    Error, Result, ROW_ID_FIELD,
+};
+use lance_index::{
+    vector::{Query, DIST_COL},
+    Index, IndexType,
+};
+use lance_linalg::{distance::MetricType, MatrixView};
+use nohash_hasher::IntMap;
+use object_store::path::Path;
+use roaring::RoaringBitmap;
+use serde::Serialize;
+use snafu::{location, Location};
+use tracing::instrument;
+
+use super::graph::{Candidate, HnswGraph};
+use super::{neighbors_column, VECTOR_COL};
+use crate::dataset::ROW_ID;
+use crate::index::{pb, prefilter::PreFilter, vector::VectorIndex};
+use crate::utils::tokio::spawn_cpu;
+
+/// HNSW index. The whole graph is loaded into memory.
+pub struct HNSWIndex {
+    graph: Arc<HnswGraph>,
+
+    /// Parameters used to build the graph.
+    m: usize,
+    ef_construction: usize,
+}
+
+impl std::fmt::Debug for HNSWIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "HNSW(m={}, ef_construction={}, {})",
+            self.m, self.ef_construction, self.graph.metric_type
+        )
+    }
+}
+
+/// Read a graph with `num_levels` levels from the graph file.
+pub async fn read_graph(
+    object_store: &ObjectStore,
+    graph_path: &Path,
+    num_levels: usize,
+    entry_point: u32,
+    metric_type: MetricType,
+) -> Result<HnswGraph> {
+    let reader = FileReader::try_new(object_store, graph_path).await?;
+    let batch = reader.read_range(0..reader.len(), reader.schema()).await?;
+
+    let row_ids = batch
+        .column_by_name(ROW_ID)
+        .ok_or_else(|| Error::Index {
+            message: format!("Graph file {}: row id column not found", graph_path),
+            location: location!(),
+        })?
+        .as_primitive::<UInt64Type>()
+        .values()
+        .to_vec();
+    let vectors = batch
+        .column_by_name(VECTOR_COL)
+        .ok_or_else(|| Error::Index {
+            message: format!("Graph file {}: vector column not found", graph_path),
+            location: location!(),
+        })?
+        .as_fixed_size_list();
+    let vectors = MatrixView::<Float32Type>::try_from(vectors)?;
+
+    let levels = (0..num_levels)
+        .map(|level| {
+            let column = batch
+                .column_by_name(&neighbors_column(level))
+                .ok_or_else(|| Error::Index {
+                    message: format!(
+                        "Graph file {}: neighbors of level {} not found",
+                        graph_path, level
+                    ),
+                    location: location!(),
+                })?
+                .as_list::<i32>();
+            Ok(column
+                .iter()
+                .map(|neighbors| {
+                    neighbors
+                        .map(|n| n.as_primitive::<UInt32Type>().values().to_vec())
+                        .unwrap_or_default()
+                })
+                .collect())
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(HnswGraph::new(
+        vectors,
+        row_ids,
+        levels,
+        entry_point,
+        metric_type,
+    ))
+}
+
+/// The `k` closest of the `candidates` found in `graph` that are in the distance range
+/// of the query and pass the `pre_filter`, as a batch of the distance and the row id.
+pub fn search_results(
+    graph: &HnswGraph,
+    candidates: Vec<Candidate>,
+    query: &Query,
+    pre_filter: &PreFilter,
+    k: usize,
+) -> Result<RecordBatch> {
+    let (distances, row_ids): (Vec<f32>, Vec<u64>) = candidates
+        .into_iter()
+        .map(|(dist, id)| (dist.0, graph.row_ids[id as usize]))
+        .filter(|(dist, _)| query.in_range(*dist))
+        .filter(|(_, row_id)| pre_filter.is_empty() || pre_filter.check_one(*row_id))
+        .take(k)
+        .unzip();
+
+    let schema = Arc::new(Schema::new(vec![
+        Field::new(DIST_COL, DataType::Float32, true),
+        ROW_ID_FIELD.clone(),
+    ]));
+    Ok(RecordBatch::try_new(
+        schema,
+        vec![
+            Arc::new(Float32Array::from(distances)) as ArrayRef,
+            Arc::new(UInt64Array::from(row_ids)) as ArrayRef,
+        ],
+    )?)
+}
+
+impl HNSWIndex {
+    /// Load the HNSW graph from the graph file.
+    pub async fn load(
+        object_store: &ObjectStore,
+        graph_path: &Path,
+        proto: &pb::Hnsw,
+        metric_type: MetricType,
+    ) -> Result<Self> {
+        let graph = read_graph(
+            object_store,
+            graph_path,
+            proto.num_levels as usize,
+            proto.entry_point as u32,
+            metric_type,
+        )
+        .await?;
+        Ok(Self {
+            graph: Arc::new(graph),
+            m: proto.m as usize,
+            ef_construction: proto.ef_construction as usize,
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct HNSWIndexStatistics {
+    index_type: String,
+    metric_type: String,
+    num_vertices: usize,
+    num_levels: usize,
+    m: usize,
+    ef_construction: usize,
+}
+
+#[async_trait]
+impl Index for HNSWIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Vector
+    }
+
+    fn memory_size(&self) -> usize {
+        let graph = &self.graph;
+        let vectors = graph.vectors.num_rows() * graph.vectors.num_columns();
+        let neighbors = graph
+            .levels
+            .iter()
+            .flatten()
+            .map(|n| n.len())
+            .sum::<usize>();
+        vectors * std::mem::size_of::<f32>()
+            + graph.row_ids.len() * std::mem::size_of::<u64>()
+            + neighbors * std::mem::size_of::<u32>()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        Ok(serde_json::to_string(&HNSWIndexStatistics {
+            index_type: "HNSW".to_string(),
+            metric_type: self.graph.metric_type.to_string(),
+            num_vertices: self.graph.len(),
+            num_levels: self.graph.num_levels(),
+            m: self.m,
+            ef_construction: self.ef_construction,
+        })?)
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        let mut frag_ids = self
+            .graph
+            .row_ids
+            .iter()
+            .map(|&row_id| RowAddress::new_from_id(row_id).fragment_id())
+            .collect::<Vec<_>>();
+        frag_ids.sort();
+        frag_ids.dedup();
+        Ok(RoaringBitmap::from_sorted_iter(frag_ids).unwrap())
+    }
+}
+
+#[async_trait]
+impl VectorIndex for HNSWIndex {
+    #[instrument(level = "debug", skip_all, name = "HNSWIndex::search")]
+    async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch> {
+        let key = query
+            .key
+            .as_primitive_opt::<Float32Type>()
+            .ok_or_else(|| Error::Index {
+                message: format!(
+                    "HNSW only supports float32 query vectors, got {}",
+                    query.key.data_type()
+                ),
+                location: location!(),
+            })?
+            .clone();
+        let k = query.k * query.refine_factor.unwrap_or(1) as usize;
+        let ef = std::cmp::max(query.ef_search.unwrap_or(k), k);
+
+        pre_filter.wait_for_ready().await?;
+        let graph = self.graph.clone();
+        let query = query.clone();
+        spawn_cpu(move || {
+            let candidates = graph.search(key.values(), ef);
+            search_results(&graph, candidates, &query, &pre_filter, k)
+        })
+        .await
+    }
+
+    fn is_loadable(&self) -> bool {
+        false
+    }
+
+    async fn load(
+        &self,
+        _reader: &dyn Reader,
+        _offset: usize,
+        _length: usize,
+    ) -> Result<Box<dyn VectorIndex>> {
+        Err(Error::Index {
+            message: "HNSWIndex is not loadable".to_string(),
+            location: location!(),
+        })
+    }
+
+    fn check_can_remap(&self) -> Result<()> {
+        Err(Error::NotSupported {
+            source: "HNSWIndex does not yet support remap".into(),
+            location: location!(),
+        })
+    }
+
+    fn remap(&mut self, _mapping: &IntMap<u64, Option<u64>>) -> Result<()> {
+        Err(Error::NotSupported {
+            source: "HNSWIndex does not yet support remap".into(),
+            location: location!(),
+        })
+    }
+
+    fn metric_type(&self) -> MetricType {
+        self.graph.metric_type
+    }
+}
@@ -16,58 +16,86 @@
 
 use std::{
     any::Any,
+    collections::BTreeMap,
     sync::{Arc, Weak},
 };
 
 use arrow_arith::numeric::sub;
 use arrow_array::{
-    cast::{as_primitive_array, as_struct_array, AsArray},
+    cast::{as_struct_array, AsArray},
     types::{Float16Type, Float32Type, Float64Type},
     Array, FixedSizeListArray, Float32Array, RecordBatch, StructArray, UInt32Array,
 };
 use arrow_ord::sort::sort_to_indices;
-use arrow_schema::DataType;
+use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
 use arrow_select::{concat::concat_batches, take::take};
 use async_trait::async_trait;
 use futures::{
-    stream::{self, StreamExt},
-    TryStreamExt,
+    channel::mpsc,
+    future,
+    stream::{self, BoxStream, StreamExt},
+    SinkExt, TryStreamExt,
 };
 use lance_arrow::*;
 use lance_core::io::{
-    local::to_local_path, ObjectWriter, Reader, RecordBatchStream, WriteExt, Writer,
+    local::to_local_path, ObjectWriter, Reader, RecordBatchStream, RecordBatchStreamAdapter,
+    WriteExt, Writer,
 };
 use lance_core::{
-    datatypes::Field, encodings::plain::PlainEncoder, format::Index as IndexMetadata, Error, Result,
+    datatypes::Field, encodings::plain::PlainEncoder, format::Index as IndexMetadata, Error,
+    Result, ROW_ID, ROW_ID_FIELD,
 };
 use lance_index::{
+    progress::{IndexBuildProgress, NoopIndexBuildProgress},
     vector::{
         ivf::IvfBuildParams,
         pq::{PQBuildParams, ProductQuantizer, ProductQuantizerImpl},
-        Query, DIST_COL,
+        residual::ResidualTransform,
+        sq::{transform::SQTransformer, SQBuildParams},
+        trace::PartitionTrace,
+        Query, DIST_COL, PART_ID_COLUMN, RESIDUAL_COLUMN, SQ_CODE_COLUMN,
     },
     Index, IndexType,
 };
 use lance_linalg::distance::{Cosine, Dot, MetricType, L2};
+use lance_linalg::kernels::argmin_value_float;
+use lance_linalg::kmeans::{KMeans, MiniBatchKMeans};
+use lance_linalg::MatrixView;
 use log::{debug, info};
 use nohash_hasher::IntMap;
-use rand::{rngs::SmallRng, SeedableRng};
+use num_traits::AsPrimitive;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
 use roaring::RoaringBitmap;
 use serde::Serialize;
 use snafu::{location, Location};
 use tracing::{instrument, span, Level};
 use uuid::Uuid;
 
-#[cfg(feature = "opq")]
-use super::opq::train_opq;
-use super::{pq::PQIndex, utils::maybe_sample_training_data, VectorIndex};
+use super::{
+    flat::FlatIndex,
+    normalize::{normalize, L2Normalizer},
+    opq::train_opq,
+    pq::PQIndex,
+    sq::SQIndex,
+    utils::{
+        filter_null_vectors, flatten_multivector_batch, int8_to_float32,
+        maybe_sample_training_data, sample_training_data_from_stream, to_vectors,
+    },
+    VectorIndex,
+};
 use crate::{
     dataset::Dataset,
     index::{
         pb,
         prefilter::PreFilter,
         vector::{
-            ivf::{builder::shuffle_dataset, io::write_index_partitions},
+            ivf::{
+                builder::{
+                    shuffle_dataset, shuffle_mode, shuffle_vectors, shuffle_with_schema,
+                    ShuffleLimits,
+                },
+                io::{write_column_partitions, write_index_partitions},
+            },
             Transformer,
         },
         INDEX_FILE_NAME,
@@ -76,9 +104,13 @@ use crate::{
 };
 
 mod builder;
+pub mod distributed;
 mod io;
 mod shuffler;
 
+pub use shuffler::{ShuffleCompression, ShuffleMode, ShuffleSpillLocation};
+pub(crate) use shuffler::{Shuffler, ShufflerBuilder};
+
 /// IVF Index.
 pub struct IVFIndex {
     uuid: String,
@@ -93,6 +125,10 @@ pub struct IVFIndex {
 
     metric_type: MetricType,
 
+    /// Whether the vectors are normalized to unit length, so cosine distance
+    /// is computed with dot product by the flat sub-index, and with L2 otherwise.
+    pub(crate) normalized: bool,
+
     // The session cache holds an Arc to this object so we need to
     // hold a weak pointer to avoid cycles
     /// The session cache, used when fetching pages
@@ -108,6 +144,7 @@ impl IVFIndex {
         reader: Arc<dyn Reader>,
         sub_index: Arc<dyn VectorIndex>,
         metric_type: MetricType,
+        normalized: bool,
     ) -> Result<Self> {
         if !sub_index.is_loadable() {
             return Err(Error::Index {
@@ -122,9 +159,37 @@ impl IVFIndex {
             reader,
             sub_index,
             metric_type,
+            normalized,
         })
     }
 
+    /// The metric used to search the centroids and the sub-index.
+    fn internal_metric_type(&self) -> MetricType {
+        if !self.normalized {
+            self.metric_type
+        } else if self.sub_index.as_any().is::<FlatIndex>() {
+            MetricType::Dot
+        } else {
+            MetricType::L2
+        }
+    }
+
+    /// Convert a cosine distance between unit vectors to the internal metric.
+    fn to_internal_distance(&self, distance: f32) -> f32 {
+        match self.internal_metric_type() {
+            MetricType::Dot => L2Normalizer::to_dot_distance(distance),
+            _ => L2Normalizer::to_l2_distance(distance),
+        }
+    }
+
+    /// Convert a distance of the internal metric between unit vectors to cosine distance.
+    fn to_cosine_distance(&self, distance: f32) -> f32 {
+        match self.internal_metric_type() {
+            MetricType::Dot => L2Normalizer::dot_to_cosine_distance(distance),
+            _ => L2Normalizer::to_cosine_distance(distance),
+        }
+    }
+
     /// Load one partition of the IVF sub-index.
     ///
     /// Parameters
@@ -159,6 +224,51 @@ impl IVFIndex {
         Ok(part_index)
     }
 
+    /// The PQ sub-index of an IVF_PQ index, which holds the PQ codebook.
+    pub(crate) fn pq_index(&self) -> Option<&PQIndex> {
+        self.sub_index.as_any().downcast_ref::<PQIndex>()
+    }
+
+    /// Read the PQ codes of an IVF_PQ index, one batch per partition, with the columns
+    /// `_rowid`, `__pq_code` and `__ivf_part_id`.
+    pub(crate) async fn pq_codes(&self) -> Result<Vec<RecordBatch>> {
+        stream::iter(0..self.ivf.num_partitions())
+            .map(|part_id| async move {
+                let part_index = self.load_partition(part_id, false).await?;
+                let Some(pq_index) = part_index.as_any().downcast_ref::<PQIndex>() else {
+                    return Err(Error::Index {
+                        message: format!("Expect an IVF_PQ index, got: {:?}", self.sub_index),
+                        location: location!(),
+                    });
+                };
+                let batch = pq_index.codes_to_batch()?;
+                let part_ids = UInt32Array::from(vec![part_id as u32; batch.num_rows()]);
+                Ok(batch.try_with_column(
+                    ArrowField::new(PART_ID_COLUMN, DataType::UInt32, false),
+                    Arc::new(part_ids),
+                )?)
+            })
+            .buffered(num_cpus::get())
+            .try_collect()
+            .await
+    }
+
+    /// Load the `num_partitions` largest partitions into the session cache.
+    ///
+    /// The largest partitions hold the most vectors, so they are the most likely to be
+    /// probed by a query.
+    pub(crate) async fn prewarm_partitions(&self, num_partitions: usize) -> Result<()> {
+        let mut part_ids = (0..self.ivf.num_partitions()).collect::<Vec<_>>();
+        part_ids.sort_by_key(|&part_id| std::cmp::Reverse(self.ivf.lengths[part_id]));
+        part_ids.truncate(num_partitions);
+        stream::iter(part_ids)
+            .map(|part_id| self.load_partition(part_id, true))
+            .buffer_unordered(num_cpus::get())
+            .try_collect::<Vec<_>>()
+            .await?;
+        Ok(())
+    }
+
     async fn search_in_partition(
         &self,
         partition_id: usize,
@@ -166,8 +276,49 @@ impl IVFIndex {
         pre_filter: Arc<PreFilter>,
     ) -> Result<RecordBatch> {
         let part_index = self.load_partition(partition_id, true).await?;
+        self.search_in_loaded_partition(part_index.as_ref(), partition_id, query, pre_filter)
+            .await
+    }
+
+    /// Search `query` in the sub-index of a partition, which is already loaded.
+    async fn search_in_loaded_partition(
+        &self,
+        part_index: &dyn VectorIndex,
+        partition_id: usize,
+        query: &Query,
+        pre_filter: Arc<PreFilter>,
+    ) -> Result<RecordBatch> {
+        // The flat sub-index keeps the original vectors, while PQ and SQ are trained on
+        // residuals unless the index is built without them.
+        if self.sub_index.as_any().is::<FlatIndex>() || !self.ivf.use_residual {
+            return part_index.search(query, pre_filter).await;
+        }
 
         let partition_centroids = self.ivf.centroids.value(partition_id);
+        if self.internal_metric_type() == MetricType::Dot {
+            // Dot product does not preserve under translation, but it is linear:
+            // `-q * x = -q * c - q * (x - c)`, so the sub-index searches the original
+            // query against the residuals, then shifts by the partition centroid.
+            let centroid_dist = MetricType::Dot.arrow_batch_func()(
+                partition_centroids.as_ref(),
+                &FixedSizeListArray::try_new_from_values(
+                    query.key.clone(),
+                    query.key.len() as i32,
+                )?,
+            )?
+            .value(0);
+            let mut part_query = query.clone();
+            part_query.lower_bound = query.lower_bound.map(|d| d - centroid_dist);
+            part_query.upper_bound = query.upper_bound.map(|d| d - centroid_dist);
+            let batch = part_index.search(&part_query, pre_filter).await?;
+            let dist_idx = batch.schema().index_of(DIST_COL)?;
+            let dists = batch.column(dist_idx).as_primitive::<Float32Type>();
+            let dists = Float32Array::from_iter(dists.iter().map(|d| d.map(|d| d + centroid_dist)));
+            let mut columns = batch.columns().to_vec();
+            columns[dist_idx] = Arc::new(dists);
+            return Ok(RecordBatch::try_new(batch.schema(), columns)?);
+        }
+
         let residual_key = sub(&query.key, &partition_centroids)?;
         // Query in partition.
         let mut part_query = query.clone();
@@ -176,10 +327,152 @@ impl IVFIndex {
         Ok(batch)
     }
 
+    /// Convert the distance range of the query to the internal metric if normalized.
+    fn to_internal_range(&self, query: &mut Query) {
+        if self.normalized {
+            query.lower_bound = query.lower_bound.map(|d| self.to_internal_distance(d));
+            query.upper_bound = query.upper_bound.map(|d| self.to_internal_distance(d));
+        }
+    }
+
+    /// Find the partitions to search for `key`.
+    ///
+    /// If the query has an upper bound of the L2 distance, the partitions that can not
+    /// have any vector within it are skipped. A vector in partition `j` is closer to its
+    /// centroid `c_j` than to `c_0`, the nearest centroid of the query, so its distance
+    /// to the query is at least the distance from the query to the bisector of `c_0` and
+    /// `c_j`, which is `(|q - c_j|^2 - |q - c_0|^2) / (2 * |c_j - c_0|)`.
+    fn probe_partitions(&self, key: &dyn Array, query: &Query) -> Result<Vec<u32>> {
+        let metric_type = self.internal_metric_type();
+        let nprobes = self.num_probes(key, query)?;
+        let partition_ids = self.ivf.find_partitions(key, nprobes, metric_type)?;
+        assert!(partition_ids.len() <= nprobes);
+        let partition_ids = partition_ids.values().to_vec();
+        let Some(upper_bound) = query.upper_bound else {
+            return Ok(partition_ids);
+        };
+        if metric_type != MetricType::L2 || partition_ids.is_empty() {
+            return Ok(partition_ids);
+        }
+
+        let l2 = MetricType::L2.arrow_batch_func();
+        let centroid_dists = l2(key, &self.ivf.centroids)?;
+        let nearest = sort_to_indices(centroid_dists.as_ref(), None, Some(1))?.value(0) as usize;
+        let dists_to_nearest = l2(
+            self.ivf.centroids.value(nearest).as_ref(),
+            &self.ivf.centroids,
+        )?;
+        Ok(partition_ids
+            .into_iter()
+            .filter(|&part_id| {
+                let part_id = part_id as usize;
+                let gap = centroid_dists.value(part_id) - centroid_dists.value(nearest);
+                if gap <= 0.0 {
+                    return true;
+                }
+                let lower_bound = gap / (2.0 * dists_to_nearest.value(part_id).sqrt());
+                lower_bound * lower_bound < upper_bound
+            })
+            .collect())
+    }
+
+    /// The number of partitions to probe for `key`.
+    ///
+    /// With a target recall, the partitions whose centroid is within the gap of that
+    /// percentile from the nearest centroid are probed. With a probe budget, the nearest
+    /// partitions are probed as long as their vectors are within the budget. Otherwise,
+    /// or if the index has no probing statistics, `nprobes` partitions are probed.
+    fn num_probes(&self, key: &dyn Array, query: &Query) -> Result<usize> {
+        let target_recall = query
+            .target_recall
+            .filter(|_| !self.ivf.probe_gaps.is_empty());
+        if target_recall.is_none() && query.max_probe_rows.is_none() {
+            return Ok(query.nprobes);
+        }
+
+        let centroid_dists =
+            self.internal_metric_type().arrow_batch_func()(key, &self.ivf.centroids)?;
+        let order = sort_to_indices(centroid_dists.as_ref(), None, None)?;
+        let mut nprobes = match target_recall {
+            Some(recall) => {
+                let gaps = &self.ivf.probe_gaps;
+                let percentile = (recall * gaps.len() as f32).ceil() as usize;
+                let max_gap = gaps[percentile.clamp(1, gaps.len()) - 1];
+                let nearest = centroid_dists.value(order.value(0) as usize);
+                order
+                    .values()
+                    .iter()
+                    .take_while(|&&part_id| {
+                        centroid_dists.value(part_id as usize) - nearest <= max_gap
+                    })
+                    .count()
+            }
+            None => query.nprobes,
+        };
+        if let Some(max_rows) = query.max_probe_rows {
+            let mut num_rows = 0;
+            let within_budget = order
+                .values()
+                .iter()
+                .take_while(|&&part_id| {
+                    num_rows += self.ivf.lengths[part_id as usize] as usize;
+                    num_rows <= max_rows
+                })
+                .count();
+            nprobes = if target_recall.is_some() {
+                nprobes.min(within_budget)
+            } else {
+                within_budget
+            };
+        }
+        Ok(nprobes.max(1))
+    }
+
+    /// Select the `limit` nearest results of the partitions searched for one query.
+    fn select_top_k(&self, batches: &[RecordBatch], limit: usize) -> Result<RecordBatch> {
+        if batches.is_empty() {
+            return Ok(RecordBatch::new_empty(Arc::new(ArrowSchema::new(vec![
+                ArrowField::new(DIST_COL, DataType::Float32, true),
+                ROW_ID_FIELD.clone(),
+            ]))));
+        }
+        let batch = concat_batches(&batches[0].schema(), batches)?;
+
+        let dist_col = batch.column_by_name(DIST_COL).ok_or_else(|| Error::IO {
+            message: format!(
+                "_distance column does not exist in batch: {}",
+                batch.schema()
+            ),
+            location: location!(),
+        })?;
+
+        // TODO: Use a heap sort to get the top-k.
+        let selection = sort_to_indices(dist_col, None, Some(limit))?;
+        let struct_arr = StructArray::from(batch);
+        let taken_distances = take(&struct_arr, &selection, None)?;
+        let batch: RecordBatch = as_struct_array(&taken_distances).into();
+        if !self.normalized {
+            return Ok(batch);
+        }
+
+        let dist_idx = batch.schema().index_of(DIST_COL)?;
+        let dists = batch.column(dist_idx).as_primitive::<Float32Type>();
+        let dists =
+            Float32Array::from_iter(dists.iter().map(|d| d.map(|d| self.to_cosine_distance(d))));
+        let mut columns = batch.columns().to_vec();
+        columns[dist_idx] = Arc::new(dists);
+        Ok(RecordBatch::try_new(batch.schema(), columns)?)
+    }
+
+    /// Write a new index file with the `data` appended to the partitions of `merged`.
+    ///
+    /// The new data is partitioned and encoded with the IVF model and PQ codebook of this
+    /// index, which must be shared by all the `merged` indices.
     pub(crate) async fn append(
         &self,
         dataset: &Dataset,
-        data: impl RecordBatchStream + Unpin,
+        data: impl RecordBatchStream + Unpin + 'static,
+        merged: &[&Self],
         metadata: &IndexMetadata,
         column: &str,
     ) -> Result<Uuid> {
@@ -199,20 +492,66 @@ impl IVFIndex {
                 message: "Only support append to IVF_PQ".to_string(),
                 location: location!(),
             })?;
+        for other in merged {
+            let same_pq = other
+                .sub_index
+                .as_any()
+                .downcast_ref::<PQIndex>()
+                .map(|other_pq| other_pq.pq.codebook_as_fsl() == pq_index.pq.codebook_as_fsl())
+                .unwrap_or(false);
+            if other.ivf.centroids != self.ivf.centroids
+                || !same_pq
+                || other.normalized != self.normalized
+            {
+                return Err(Error::Index {
+                    message:
+                        "Can only merge IVF_PQ indices with the same IVF model and PQ codebook"
+                            .to_string(),
+                    location: location!(),
+                });
+            }
+        }
 
         // TODO: merge two IVF implementations.
         let ivf = lance_index::vector::ivf::new_ivf_with_pq(
             self.ivf.centroids.values(),
             self.ivf.dimension(),
-            self.metric_type,
+            self.internal_metric_type(),
             column,
             pq_index.pq.clone(),
+            self.ivf.use_residual,
             None,
         )?;
-        let shuffler = shuffle_dataset(data, column, ivf, pq_index.pq.num_sub_vectors()).await?;
+        let transforms: Vec<Arc<dyn Transformer>> = if self.normalized {
+            vec![Arc::new(L2Normalizer::default())]
+        } else {
+            vec![]
+        };
+        let data = apply_transforms(data, column, transforms);
+        let progress: Arc<dyn IndexBuildProgress> = Arc::new(NoopIndexBuildProgress::new());
+        let shuffler = shuffle_dataset(
+            data,
+            column,
+            ivf,
+            pq_index.pq.num_sub_vectors(),
+            None,
+            shuffle_mode(self.ivf.num_partitions()),
+            ShuffleLimits::default(),
+            progress.clone(),
+        )
+        .await?;
 
         let mut ivf_mut = Ivf::new(self.ivf.centroids.clone());
-        write_index_partitions(&mut writer, &mut ivf_mut, &shuffler, Some(self)).await?;
+        ivf_mut.probe_gaps = self.ivf.probe_gaps.clone();
+        ivf_mut.use_residual = self.ivf.use_residual;
+        write_index_partitions(
+            &mut writer,
+            &mut ivf_mut,
+            Some(&shuffler),
+            merged,
+            progress.as_ref(),
+        )
+        .await?;
         let metadata = IvfPQIndexMetadata {
             name: metadata.name.clone(),
             column: column.to_string(),
@@ -221,7 +560,11 @@ impl IVFIndex {
             metric_type: self.metric_type,
             ivf: ivf_mut,
             pq: pq_index.pq.clone(),
-            transforms: vec![],
+            transforms: if self.normalized {
+                vec![L2Normalizer::to_proto()]
+            } else {
+                vec![]
+            },
         };
 
         let metadata = pb::Index::try_from(&metadata)?;
@@ -254,6 +597,13 @@ pub struct IvfIndexStatistics {
     uri: String,
     metric_type: String,
     num_partitions: usize,
+    /// The size of the largest partition divided by the average size, which is 1.0 if the
+    /// vectors are evenly distributed to the partitions.
+    partition_skew: f64,
+    /// Whether the sub-index encodes the residuals to the partition centroids.
+    use_residual: bool,
+    /// Whether the vectors are normalized to unit length.
+    normalized: bool,
     sub_index: serde_json::Value,
     partitions: Vec<IvfIndexPartitionStatistics>,
 }
@@ -272,6 +622,14 @@ impl Index for IVFIndex {
         IndexType::Vector
     }
 
+    fn memory_size(&self) -> usize {
+        // Partitions are cached separately, so only count the IVF model.
+        self.ivf.centroids.get_array_memory_size()
+            + self.ivf.offsets.len() * std::mem::size_of::<usize>()
+            + self.ivf.lengths.len() * std::mem::size_of::<u32>()
+            + self.ivf.probe_gaps.len() * std::mem::size_of::<f32>()
+    }
+
     fn statistics(&self) -> Result<String> {
         let partitions_statistics = self
             .ivf
@@ -279,16 +637,16 @@ impl Index for IVFIndex {
             .iter()
             .enumerate()
             .map(|(i, &len)| {
-                let centroid = self.ivf.centroids.value(i);
-                let centroid_arr: &Float32Array = as_primitive_array(centroid.as_ref());
-                IvfIndexPartitionStatistics {
+                // The centroids are of the same float type as the vectors.
+                let centroid = to_float32_array(self.ivf.centroids.value(i).as_ref())?;
+                Ok(IvfIndexPartitionStatistics {
                     index: i,
                     length: len,
                     offset: self.ivf.offsets[i],
-                    centroid: centroid_arr.values().to_vec(),
-                }
+                    centroid: centroid.values().to_vec(),
+                })
             })
-            .collect::<Vec<_>>();
+            .collect::<Result<Vec<_>>>()?;
 
         Ok(serde_json::to_string(&IvfIndexStatistics {
             index_type: "IVF".to_string(),
@@ -296,6 +654,9 @@ impl Index for IVFIndex {
             uri: to_local_path(self.reader.path()),
             metric_type: self.metric_type.to_string(),
             num_partitions: self.ivf.num_partitions(),
+            partition_skew: self.ivf.partition_skew(),
+            use_residual: self.ivf.use_residual,
+            normalized: self.normalized,
             // TODO: Not ideal that we have to re-parse the JSON here
             sub_index: serde_json::from_str(&self.sub_index.statistics()?)?,
             partitions: partitions_statistics,
@@ -317,32 +678,100 @@ impl Index for IVFIndex {
 impl VectorIndex for IVFIndex {
     #[instrument(level = "debug", skip_all, name = "IVFIndex::search")]
     async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch> {
-        let partition_ids =
-            self.ivf
-                .find_partitions(&query.key, query.nprobes, self.metric_type)?;
-        assert!(partition_ids.len() <= query.nprobes);
-        let part_ids = partition_ids.values().to_vec();
+        let mut query = query.clone();
+        if self.normalized || query.key.data_type() == &DataType::Int8 {
+            let key =
+                FixedSizeListArray::try_new_from_values(query.key.clone(), query.key.len() as i32)?;
+            let key = int8_to_float32(&key)?;
+            query.key = if self.normalized {
+                normalize(&key)?
+            } else {
+                key
+            }
+            .values()
+            .clone();
+        }
+        self.to_internal_range(&mut query);
+        let query = &query;
+
+        let part_ids = self.probe_partitions(&query.key, query)?;
+        if let Some(trace) = &query.trace {
+            trace.record_partitions(part_ids.iter().map(|&part_id| PartitionTrace {
+                partition_id: part_id,
+                candidates: self.ivf.lengths[part_id as usize] as usize,
+            }));
+        }
         let batches = stream::iter(part_ids)
             .map(|part_id| self.search_in_partition(part_id as usize, query, pre_filter.clone()))
             .buffer_unordered(num_cpus::get())
             .try_collect::<Vec<_>>()
             .await?;
-        let batch = concat_batches(&batches[0].schema(), &batches)?;
+        let limit = query.k * query.refine_factor.unwrap_or(1) as usize;
+        self.select_top_k(&batches, limit)
+    }
 
-        let dist_col = batch.column_by_name(DIST_COL).ok_or_else(|| Error::IO {
-            message: format!(
-                "_distance column does not exist in batch: {}",
-                batch.schema()
-            ),
-            location: location!(),
-        })?;
+    /// Search all the queries together. The queries are grouped by the partitions
+    /// they probe, so that each partition is loaded only once.
+    #[instrument(level = "debug", skip_all, name = "IVFIndex::search_batch")]
+    async fn search_batch(
+        &self,
+        queries: &FixedSizeListArray,
+        query: &Query,
+        pre_filter: Arc<PreFilter>,
+    ) -> Result<Vec<RecordBatch>> {
+        let queries = int8_to_float32(queries)?;
+        let queries = if self.normalized {
+            normalize(&queries)?
+        } else {
+            queries
+        };
+        let mut query = query.clone();
+        self.to_internal_range(&mut query);
+        let query = &query;
+
+        let mut partition_queries: BTreeMap<u32, Vec<usize>> = BTreeMap::new();
+        for query_id in 0..queries.len() {
+            for part_id in self.probe_partitions(&queries.value(query_id), query)? {
+                partition_queries.entry(part_id).or_default().push(query_id);
+            }
+        }
 
-        // TODO: Use a heap sort to get the top-k.
+        let queries = &queries;
+        let partition_results = stream::iter(partition_queries)
+            .map(|(part_id, query_ids)| {
+                let pre_filter = pre_filter.clone();
+                async move {
+                    let part_index = self.load_partition(part_id as usize, true).await?;
+                    let mut batches = Vec::with_capacity(query_ids.len());
+                    for query_id in query_ids {
+                        let mut query = query.clone();
+                        query.key = queries.value(query_id);
+                        let batch = self
+                            .search_in_loaded_partition(
+                                part_index.as_ref(),
+                                part_id as usize,
+                                &query,
+                                pre_filter.clone(),
+                            )
+                            .await?;
+                        batches.push((query_id, batch));
+                    }
+                    Result::Ok(batches)
+                }
+            })
+            .buffer_unordered(num_cpus::get())
+            .try_collect::<Vec<_>>()
+            .await?;
+
+        let mut query_batches = vec![vec![]; queries.len()];
+        for (query_id, batch) in partition_results.into_iter().flatten() {
+            query_batches[query_id].push(batch);
+        }
         let limit = query.k * query.refine_factor.unwrap_or(1) as usize;
-        let selection = sort_to_indices(dist_col, None, Some(limit))?;
-        let struct_arr = StructArray::from(batch);
-        let taken_distances = take(&struct_arr, &selection, None)?;
-        Ok(as_struct_array(&taken_distances).into())
+        query_batches
+            .iter()
+            .map(|batches| self.select_top_k(batches, limit))
+            .collect()
     }
 
     fn is_loadable(&self) -> bool {
@@ -362,7 +791,7 @@ impl VectorIndex for IVFIndex {
     }
 
     fn check_can_remap(&self) -> Result<()> {
-        Ok(())
+        self.sub_index.check_can_remap()
     }
 
     fn remap(&mut self, _mapping: &IntMap<u64, Option<u64>>) -> Result<()> {
@@ -377,6 +806,10 @@ impl VectorIndex for IVFIndex {
             location: location!(),
         })
     }
+
+    fn metric_type(&self) -> MetricType {
+        self.metric_type
+    }
 }
 
 /// Ivf PQ index metadata.
@@ -446,11 +879,7 @@ impl TryFrom<&IvfPQIndexMetadata> for pb::Index {
                 spec_version: 1,
                 dimension: idx.dimension,
                 stages,
-                metric_type: match idx.metric_type {
-                    MetricType::L2 => pb::VectorMetricType::L2.into(),
-                    MetricType::Cosine => pb::VectorMetricType::Cosine.into(),
-                    MetricType::Dot => pb::VectorMetricType::Dot.into(),
-                },
+                metric_type: pb::VectorMetricType::try_from(idx.metric_type)?.into(),
             })),
         })
     }
@@ -470,6 +899,16 @@ pub(crate) struct Ivf {
 
     /// Number of vectors in each partition.
     lengths: Vec<u32>,
+
+    /// The percentiles of the gaps between the distance to the centroid of the partition
+    /// of a nearest neighbor and the distance to the nearest centroid, see [`probe_gaps`].
+    ///
+    /// Empty if the index was built without these statistics.
+    probe_gaps: Vec<f32>,
+
+    /// Whether the sub-index encodes the residuals of the vectors to the centroid of
+    /// their partition, rather than the vectors themselves.
+    use_residual: bool,
 }
 
 impl Ivf {
@@ -478,6 +917,8 @@ impl Ivf {
             centroids,
             offsets: vec![],
             lengths: vec![],
+            probe_gaps: vec![],
+            use_residual: true,
         }
     }
 
@@ -508,6 +949,16 @@ impl Ivf {
         internal.find_partitions(query, nprobes)
     }
 
+    /// The size of the largest partition divided by the average size.
+    fn partition_skew(&self) -> f64 {
+        let num_rows = self.lengths.iter().map(|&len| len as u64).sum::<u64>();
+        if num_rows == 0 {
+            return 1.0;
+        }
+        let max_len = self.lengths.iter().copied().max().unwrap_or_default();
+        max_len as f64 * self.lengths.len() as f64 / num_rows as f64
+    }
+
     /// Add the offset and length of one partition.
     fn add_partition(&mut self, offset: usize, len: u32) {
         self.offsets.push(offset);
@@ -531,6 +982,8 @@ impl TryFrom<&Ivf> for pb::Ivf {
             offsets: ivf.offsets.iter().map(|o| *o as u64).collect(),
             lengths: ivf.lengths.clone(),
             centroids_tensor: Some(ivf.centroids.as_ref().try_into()?),
+            probe_gaps: ivf.probe_gaps.clone(),
+            raw_vectors: !ivf.use_residual,
         })
     }
 }
@@ -558,6 +1011,8 @@ impl TryFrom<&pb::Ivf> for Ivf {
             centroids,
             offsets: proto.offsets.iter().map(|o| *o as usize).collect(),
             lengths: proto.lengths.clone(),
+            probe_gaps: proto.probe_gaps.clone(),
+            use_residual: !proto.raw_vectors,
         })
     }
 }
@@ -572,11 +1027,11 @@ fn sanity_check<'a>(dataset: &'a Dataset, column: &str) -> Result<&'a Field> {
             location: location!(),
         });
     };
-    if let DataType::FixedSizeList(elem_type, _) = field.data_type() {
-        if !elem_type.data_type().is_floating() {
+    if let DataType::FixedSizeList(elem_type, _) = vector_type(&field.data_type()) {
+        if !elem_type.data_type().is_floating() && elem_type.data_type() != &DataType::Int8 {
             return Err(Error::Index{
                 message:format!(
-                    "VectorIndex requires the column data type to be fixed size list of f16/f32/f64, got {}",
+                    "VectorIndex requires the column data type to be fixed size list of f16/f32/f64/i8, got {}",
                     elem_type.data_type()
                 ),
                 location: location!()
@@ -594,6 +1049,27 @@ fn sanity_check<'a>(dataset: &'a Dataset, column: &str) -> Result<&'a Field> {
     Ok(field)
 }
 
+/// The type of the vectors in a column.
+///
+/// The vectors of a multivector column, i.e., `List<FixedSizeList>`, are indexed.
+fn vector_type(data_type: &DataType) -> &DataType {
+    match data_type {
+        DataType::List(field) => field.data_type(),
+        _ => data_type,
+    }
+}
+
+/// The vectors to build an index over.
+enum VectorSource {
+    /// Scan the column of the dataset.
+    Scan,
+    /// Batches of `_rowid` and the column, with a training sample read from their head.
+    Stream {
+        sample: FixedSizeListArray,
+        stream: RecordBatchStreamAdapter<BoxStream<'static, Result<RecordBatch>>>,
+    },
+}
+
 /// Build IVF(PQ) index
 pub async fn build_ivf_pq_index(
     dataset: &Dataset,
@@ -604,6 +1080,141 @@ pub async fn build_ivf_pq_index(
     ivf_params: &IvfBuildParams,
     pq_params: &PQBuildParams,
 ) -> Result<()> {
+    do_build_ivf_pq_index(
+        dataset,
+        column,
+        index_name,
+        uuid,
+        metric_type,
+        ivf_params,
+        pq_params,
+        VectorSource::Scan,
+    )
+    .await
+}
+
+/// Build IVF(PQ) index over the `_rowid` and `column` batches of `stream`, instead
+/// of a scan of the dataset.
+///
+/// IVF and PQ are trained on the first vectors of the stream, so the stream should
+/// not be ordered by anything correlated with the vectors.
+#[allow(clippy::too_many_arguments)]
+pub async fn build_ivf_pq_index_from_stream(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    metric_type: MetricType,
+    ivf_params: &IvfBuildParams,
+    pq_params: &PQBuildParams,
+    mut stream: impl RecordBatchStream + Unpin + 'static,
+) -> Result<()> {
+    if ivf_params.mini_batch_size.is_some() {
+        return Err(Error::Index {
+            message: "Mini-batch IVF training is not supported when building from a stream"
+                .to_string(),
+            location: location!(),
+        });
+    }
+    let field = sanity_check(dataset, column)?;
+    let schema = stream.schema();
+    let Ok(stream_field) = schema.field_with_name(column) else {
+        return Err(Error::Index {
+            message: format!("Column {column} does not exist in the stream"),
+            location: location!(),
+        });
+    };
+    if stream_field.data_type() != &field.data_type() {
+        return Err(Error::Index {
+            message: format!(
+                "Column {column} has type {} in the stream, but {} in the dataset",
+                stream_field.data_type(),
+                field.data_type()
+            ),
+            location: location!(),
+        });
+    }
+    if schema.field_with_name(ROW_ID).ok().map(|f| f.data_type()) != Some(&DataType::UInt64) {
+        return Err(Error::Index {
+            message: format!("Column {ROW_ID} of type UInt64 does not exist in the stream"),
+            location: location!(),
+        });
+    }
+
+    let num_centroids = lance_index::vector::pq::num_centroids(pq_params.num_bits as u32);
+    let sample_size = [
+        ivf_params.sample_size(std::cmp::max(ivf_params.num_partitions, num_centroids)),
+        pq_params.sample_size(),
+        PROBE_SAMPLE_SIZE,
+    ]
+    .into_iter()
+    .max()
+    .unwrap();
+    let (sample, head) = sample_training_data_from_stream(&mut stream, column, sample_size).await?;
+
+    // Use the row id field of a dataset scan, which is nullable.
+    let schema = Arc::new(ArrowSchema::new(
+        schema
+            .fields()
+            .iter()
+            .map(|f| {
+                if f.name() == ROW_ID {
+                    Arc::new(ROW_ID_FIELD.clone())
+                } else {
+                    f.clone()
+                }
+            })
+            .collect::<Vec<_>>(),
+    ));
+    let stream = stream::iter(head.into_iter().map(Ok))
+        .chain(stream)
+        .map({
+            let schema = schema.clone();
+            move |batch| {
+                Ok(RecordBatch::try_new(
+                    schema.clone(),
+                    batch?.columns().to_vec(),
+                )?)
+            }
+        })
+        .boxed();
+    let stream = RecordBatchStreamAdapter::new(schema, stream);
+
+    do_build_ivf_pq_index(
+        dataset,
+        column,
+        index_name,
+        uuid,
+        metric_type,
+        ivf_params,
+        pq_params,
+        VectorSource::Stream { sample, stream },
+    )
+    .await
+}
+
+/// The models of an IVF_PQ index, before the vectors are partitioned.
+struct IvfPqModel {
+    ivf: Ivf,
+    pq: Arc<dyn ProductQuantizer>,
+    /// The transforms applied to the vectors before IVF and PQ, in order.
+    transforms: Vec<Arc<dyn Transformer>>,
+    /// The metric type of IVF and PQ, which is L2 for normalized vectors.
+    ivf_metric_type: MetricType,
+}
+
+/// Train the IVF and PQ models of an IVF_PQ index.
+///
+/// The models are trained on samples of the dataset, or on `stream_sample` when the
+/// index is built from a stream.
+async fn train_ivf_pq_model(
+    dataset: &Dataset,
+    column: &str,
+    metric_type: MetricType,
+    ivf_params: &IvfBuildParams,
+    pq_params: &PQBuildParams,
+    stream_sample: Option<&FixedSizeListArray>,
+) -> Result<IvfPqModel> {
     info!(
         "Building vector index: IVF{},{}PQ{}, metric={}",
         ivf_params.num_partitions,
@@ -613,8 +1224,8 @@ pub async fn build_ivf_pq_index(
     );
 
     let field = sanity_check(dataset, column)?;
-    let dim = if let DataType::FixedSizeList(_, d) = field.data_type() {
-        d as usize
+    let dim = if let DataType::FixedSizeList(_, d) = vector_type(&field.data_type()) {
+        *d as usize
     } else {
         return Err(Error::Index {
             message: format!(
@@ -625,19 +1236,27 @@ pub async fn build_ivf_pq_index(
         });
     };
 
-    // Maximum to train [IvfBuildParams::sample_size](default 256) vectors per centroid, see Faiss.
-    let sample_size_hint = std::cmp::max(
+    // Maximum to train [IvfBuildParams::sample_rate](default 256) vectors per centroid, see Faiss.
+    let sample_size_hint = ivf_params.sample_size(std::cmp::max(
         ivf_params.num_partitions,
         lance_index::vector::pq::num_centroids(pq_params.num_bits as u32),
-    ) * ivf_params.sample_rate;
-
-    let mut training_data = if ivf_params.centroids.is_none() {
+    ));
+
+    // With mini-batch training, IVF is trained over the streamed vectors, but OPQ
+    // still needs the sample.
+    let mut training_data = if let Some(sample) = stream_sample {
+        Some(sample.clone())
+    } else if ivf_params.centroids.is_none()
+        && (ivf_params.mini_batch_size.is_none() || pq_params.use_opq)
+    {
         let start = std::time::Instant::now();
         log::info!(
             "Loading training data for IVF. Sample size: {}",
             sample_size_hint
         );
-        let data = Some(maybe_sample_training_data(dataset, column, sample_size_hint).await?);
+        let data = Some(
+            maybe_sample_training_data(dataset, column, sample_size_hint, ivf_params.seed).await?,
+        );
         log::info!(
             "Finished loading training data in {:02} seconds",
             start.elapsed().as_secs_f32()
@@ -647,14 +1266,34 @@ pub async fn build_ivf_pq_index(
         None
     };
 
-    #[cfg(feature = "opq")]
-    let mut transforms: Vec<Box<dyn Transformer>> = vec![];
-    #[cfg(not(feature = "opq"))]
-    let transforms: Vec<Box<dyn Transformer>> = vec![];
+    if pq_params.use_opq && (ivf_params.centroids.is_some() || pq_params.codebook.is_some()) {
+        return Err(Error::Index {
+            message: "OPQ can not be used with pre-trained IVF centroids or PQ codebook"
+                .to_string(),
+            location: location!(),
+        });
+    }
+    let mut transforms: Vec<Arc<dyn Transformer>> = vec![];
+
+    // Cosine distance is not preserved between residuals, so the vectors are
+    // normalized and IVF and PQ are trained with L2 instead.
+    let normalized = metric_type == MetricType::Cosine;
+    let ivf_metric_type = if normalized {
+        MetricType::L2
+    } else {
+        metric_type
+    };
+    if normalized {
+        let normalizer = L2Normalizer::default();
+        if let Some(training_data) = &mut training_data {
+            *training_data = normalizer.transform(training_data).await?;
+        }
+        transforms.push(Arc::new(normalizer));
+    }
 
     let start = std::time::Instant::now();
     // Train IVF partitions.
-    let ivf_model = if let Some(centroids) = &ivf_params.centroids {
+    let mut ivf_model = if let Some(centroids) = &ivf_params.centroids {
         if centroids.values().len() != ivf_params.num_partitions * dim {
             return Err(Error::Index {
                 message: format!(
@@ -665,36 +1304,53 @@ pub async fn build_ivf_pq_index(
                 location: location!(),
             });
         }
-        Ivf::new(centroids.clone())
+        if normalized {
+            // Unit vectors are ranked by L2 to unit centroids in the cosine order.
+            Ivf::new(Arc::new(normalize(centroids)?))
+        } else {
+            Ivf::new(centroids.clone())
+        }
     } else {
         // Pre-transforms
         if pq_params.use_opq {
-            #[cfg(not(feature = "opq"))]
-            return Err(Error::Index {
-                message: "Feature 'opq' is not installed.".to_string(),
-                location: location!(),
-            });
-            #[cfg(feature = "opq")]
-            {
-                let opq = train_opq(&training_data, pq_params).await?;
-                transforms.push(Box::new(opq));
-            }
-        }
-
-        // Transform training data if necessary.
-        for transform in transforms.iter() {
+            info!("Start to train OPQ rotation");
+            let opq = train_opq(training_data.as_ref().unwrap(), pq_params).await?;
             if let Some(training_data) = &mut training_data {
-                *training_data = transform.transform(training_data).await?;
+                *training_data = opq.transform(training_data).await?;
             }
+            transforms.push(Arc::new(opq));
         }
 
         info!("Start to train IVF model");
-        train_ivf_model(training_data.as_ref().unwrap(), metric_type, ivf_params).await?
+        if let Some(batch_size) = ivf_params.mini_batch_size {
+            train_ivf_model_mini_batch(
+                dataset,
+                column,
+                ivf_metric_type,
+                ivf_params,
+                batch_size,
+                &transforms,
+            )
+            .await?
+        } else {
+            train_ivf_model(training_data.as_ref().unwrap(), ivf_metric_type, ivf_params).await?
+        }
     };
     info!(
         "Traied IVF model in {:02} seconds",
         start.elapsed().as_secs_f32()
     );
+    ivf_model.use_residual = ivf_params.use_residual;
+    gather_probe_gaps(
+        dataset,
+        column,
+        stream_sample,
+        &mut ivf_model,
+        ivf_metric_type,
+        &transforms,
+        ivf_params.seed,
+    )
+    .await?;
 
     let start = std::time::Instant::now();
     let pq: Arc<dyn ProductQuantizer> = if let Some(codebook) = &pq_params.codebook {
@@ -703,19 +1359,21 @@ pub async fn build_ivf_pq_index(
             pq_params.num_bits as u32,
             dim,
             Arc::new(codebook.as_primitive().clone()),
-            metric_type,
+            ivf_metric_type,
         ))
     } else {
         info!(
             "Start to train PQ code: PQ{}, bits={}",
             pq_params.num_sub_vectors, pq_params.num_bits
         );
-        let expected_sample_size =
-            lance_index::vector::pq::num_centroids(pq_params.num_bits as u32)
-                * pq_params.sample_rate;
+        let expected_sample_size = pq_params.sample_size();
         let training_data = if let Some(training_data) = training_data {
             if training_data.value_length() as usize > expected_sample_size {
-                training_data.sample(expected_sample_size)?
+                let mut rng = match pq_params.seed {
+                    Some(seed) => SmallRng::seed_from_u64(seed),
+                    None => SmallRng::from_entropy(),
+                };
+                training_data.sample_with(expected_sample_size, &mut rng)?
             } else {
                 training_data
             }
@@ -725,11 +1383,16 @@ pub async fn build_ivf_pq_index(
                 "Loading training data for PQ. Sample size: {}",
                 expected_sample_size
             );
-            let data = maybe_sample_training_data(dataset, column, expected_sample_size).await?;
+            let mut data =
+                maybe_sample_training_data(dataset, column, expected_sample_size, pq_params.seed)
+                    .await?;
             log::info!(
                 "Finished loading training data in {:02} seconds",
                 start.elapsed().as_secs_f32()
             );
+            for transform in transforms.iter() {
+                data = transform.transform(&data).await?;
+            }
             data
         };
 
@@ -737,46 +1400,534 @@ pub async fn build_ivf_pq_index(
         let ivf2 = lance_index::vector::ivf::new_ivf(
             ivf_model.centroids.values(),
             ivf_model.dimension(),
-            metric_type,
+            ivf_metric_type,
             vec![],
             None,
         )?;
 
-        info!(
-            "starting to compute partitions for PQ training, sample size: {}",
-            training_data.value_length()
-        );
-        // Compute the residual vector to train Product Quantizer.
-        let part_ids = ivf2.compute_partitions(&training_data).await?;
+        let training_data = if ivf_model.use_residual {
+            info!(
+                "starting to compute partitions for PQ training, sample size: {}",
+                training_data.value_length()
+            );
+            // Compute the residual vector to train Product Quantizer.
+            let part_ids = ivf2.compute_partitions(&training_data).await?;
 
-        let residuals = span!(Level::INFO, "compute residual for PQ training")
-            .in_scope(|| ivf2.compute_residual(&training_data, Some(&part_ids)))
-            .await?;
+            span!(Level::INFO, "compute residual for PQ training")
+                .in_scope(|| ivf2.compute_residual(&training_data, Some(&part_ids)))
+                .await?
+        } else {
+            training_data
+        };
         info!("Start train PQ: params={:#?}", pq_params);
-        pq_params.build(&residuals, metric_type).await?
+        pq_params.build(&training_data, ivf_metric_type).await?
     };
     info!("Trained PQ in: {} seconds", start.elapsed().as_secs_f32());
 
+    Ok(IvfPqModel {
+        ivf: ivf_model,
+        pq,
+        transforms,
+        ivf_metric_type,
+    })
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn do_build_ivf_pq_index(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    metric_type: MetricType,
+    ivf_params: &IvfBuildParams,
+    pq_params: &PQBuildParams,
+    source: VectorSource,
+) -> Result<()> {
+    let limits = ShuffleLimits::try_from_params(ivf_params)?;
+    let IvfPqModel {
+        ivf: ivf_model,
+        pq,
+        transforms,
+        ivf_metric_type,
+    } = train_ivf_pq_model(
+        dataset,
+        column,
+        metric_type,
+        ivf_params,
+        pq_params,
+        match &source {
+            VectorSource::Scan => None,
+            VectorSource::Stream { sample, .. } => Some(sample),
+        },
+    )
+    .await?;
+
     // Transform data, compute residuals and sort by partition ids.
+    let stream = match source {
+        VectorSource::Scan => {
+            let mut scanner = dataset.scan();
+            scanner.batch_readahead(num_cpus::get() * 2);
+            scanner.project(&[column])?;
+            scanner.with_index_row_id();
+
+            // Scan the dataset and compute residual, pq with with partition ID.
+            // For now, it loads all data into memory.
+            let stream = scanner.try_into_stream().await?;
+            RecordBatchStreamAdapter::new(stream.schema(), stream.boxed())
+        }
+        VectorSource::Stream { stream, .. } => stream,
+    };
+    // The IVF centroids and PQ codebook are trained in the transformed space.
+    let stream = apply_transforms(stream, column, transforms.clone());
+
+    write_index_file(
+        dataset,
+        column,
+        index_name,
+        uuid,
+        &transforms,
+        ivf_model,
+        pq,
+        metric_type,
+        ivf_metric_type,
+        stream,
+        limits,
+        ivf_params.progress.clone(),
+    )
+    .await
+}
+
+/// One of the IVF_PQ indices built by [build_ivf_pq_indices].
+pub(crate) struct IvfPqIndexBuild<'a> {
+    pub column: &'a str,
+    pub index_name: &'a str,
+    pub uuid: &'a str,
+    pub metric_type: MetricType,
+    pub ivf_params: &'a IvfBuildParams,
+    pub pq_params: &'a PQBuildParams,
+}
+
+/// Build IVF_PQ indices on several columns with one scan of the dataset.
+///
+/// The models are trained one index after another. Then the batches of the scan
+/// are sent to the builds of all the indices, which shuffle their column concurrently.
+pub(crate) async fn build_ivf_pq_indices(
+    dataset: &Dataset,
+    builds: &[IvfPqIndexBuild<'_>],
+) -> Result<()> {
+    let mut limits = Vec::with_capacity(builds.len());
+    let mut models = Vec::with_capacity(builds.len());
+    for build in builds {
+        limits.push(ShuffleLimits::try_from_params(build.ivf_params)?);
+        models.push(
+            train_ivf_pq_model(
+                dataset,
+                build.column,
+                build.metric_type,
+                build.ivf_params,
+                build.pq_params,
+                None,
+            )
+            .await?,
+        );
+    }
+    let transforms = models
+        .iter()
+        .map(|model| model.transforms.clone())
+        .collect::<Vec<_>>();
+
+    let columns = builds.iter().map(|build| build.column).collect::<Vec<_>>();
     let mut scanner = dataset.scan();
     scanner.batch_readahead(num_cpus::get() * 2);
-    scanner.project(&[column])?;
-    scanner.with_row_id();
+    scanner.project(&columns)?;
+    scanner.with_index_row_id();
+    let mut scan = scanner.try_into_stream().await?;
+    let scan_schema = scan.schema();
+
+    let mut senders = Vec::with_capacity(builds.len());
+    let mut writers = Vec::with_capacity(builds.len());
+    for (((build, model), limits), transforms) in
+        builds.iter().zip(models).zip(limits).zip(&transforms)
+    {
+        let projection = [
+            scan_schema.index_of(build.column)?,
+            scan_schema.index_of(ROW_ID)?,
+        ];
+        let schema = Arc::new(scan_schema.project(&projection)?);
+        let (tx, rx) = mpsc::channel::<Result<RecordBatch>>(2);
+        senders.push((tx, projection));
+        let stream = RecordBatchStreamAdapter::new(schema, rx.boxed());
+        let stream = apply_transforms(stream, build.column, transforms.clone());
+        writers.push(write_index_file(
+            dataset,
+            build.column,
+            build.index_name,
+            build.uuid,
+            transforms,
+            model.ivf,
+            model.pq,
+            build.metric_type,
+            model.ivf_metric_type,
+            stream,
+            limits,
+            build.ivf_params.progress.clone(),
+        ));
+    }
+
+    let scan = async move {
+        while let Some(batch) = scan.try_next().await? {
+            for (tx, projection) in senders.iter_mut() {
+                // A failed build drops its receiver, and its writer returns the error.
+                let _ = tx.send(Ok(batch.project(projection.as_slice())?)).await;
+            }
+        }
+        // Dropping the senders ends the streams of the builds.
+        Ok(())
+    };
+    futures::try_join!(scan, future::try_join_all(writers))?;
+    Ok(())
+}
+
+/// Build IVF_FLAT index.
+///
+/// Each partition keeps the original vectors, so no PQ model is trained.
+///
+/// If `normalize` is set, the vectors of a `Cosine` index are normalized to unit
+/// length, and the centroids as well, so that the index is searched with dot product.
+pub async fn build_ivf_flat_index(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    metric_type: MetricType,
+    ivf_params: &IvfBuildParams,
+    normalize: bool,
+) -> Result<()> {
+    info!(
+        "Building vector index: IVF{},FLAT, metric={}, normalize={}",
+        ivf_params.num_partitions, metric_type, normalize,
+    );
+    let limits = ShuffleLimits::try_from_params(ivf_params)?;
+    let transforms: Vec<Arc<dyn Transformer>> = if normalize {
+        vec![Arc::new(L2Normalizer::default())]
+    } else {
+        vec![]
+    };
+
+    let field = sanity_check(dataset, column)?;
+    let DataType::FixedSizeList(elem_type, dim) = field.data_type() else {
+        return Err(Error::Index {
+            message: format!(
+                "VectorIndex requires the column data type to be fixed size list of floats, got {}",
+                field.data_type()
+            ),
+            location: location!(),
+        });
+    };
+    if !elem_type.data_type().is_floating() {
+        return Err(Error::Index {
+            message: format!(
+                "IVF_FLAT index requires float vectors, got {}, use IVF_PQ for int8 vectors",
+                elem_type.data_type()
+            ),
+            location: location!(),
+        });
+    }
+    let dim = dim as usize;
+
+    let start = std::time::Instant::now();
+    let mut ivf_model = if let Some(centroids) = &ivf_params.centroids {
+        if centroids.values().len() != ivf_params.num_partitions * dim {
+            return Err(Error::Index {
+                message: format!(
+                    "IVF centroids length mismatch: {} != {}",
+                    centroids.len(),
+                    ivf_params.num_partitions * dim,
+                ),
+                location: location!(),
+            });
+        }
+        Ivf::new(centroids.clone())
+    } else if let Some(batch_size) = ivf_params.mini_batch_size {
+        info!("Start to train IVF model with mini-batches");
+        train_ivf_model_mini_batch(
+            dataset,
+            column,
+            metric_type,
+            ivf_params,
+            batch_size,
+            &transforms,
+        )
+        .await?
+    } else {
+        let sample_size_hint = ivf_params.sample_size(ivf_params.num_partitions);
+        let mut training_data =
+            maybe_sample_training_data(dataset, column, sample_size_hint, ivf_params.seed).await?;
+        for transform in transforms.iter() {
+            training_data = transform.transform(&training_data).await?;
+        }
+        info!("Start to train IVF model");
+        train_ivf_model(&training_data, metric_type, ivf_params).await?
+    };
+    info!(
+        "Trained IVF model in {:02} seconds",
+        start.elapsed().as_secs_f32()
+    );
+    // Unit vectors are ranked by dot product to unit centroids in the cosine order.
+    let ivf_metric_type = if normalize {
+        ivf_model.centroids = Arc::new(super::normalize::normalize(&ivf_model.centroids)?);
+        MetricType::Dot
+    } else {
+        metric_type
+    };
+    gather_probe_gaps(
+        dataset,
+        column,
+        None,
+        &mut ivf_model,
+        ivf_metric_type,
+        &transforms,
+        ivf_params.seed,
+    )
+    .await?;
 
-    // Scan the dataset and compute residual, pq with with partition ID.
-    // For now, it loads all data into memory.
+    let mut scanner = dataset.scan();
+    scanner.batch_readahead(num_cpus::get() * 2);
+    scanner.project(&[column])?;
+    scanner.with_index_row_id();
     let stream = scanner.try_into_stream().await?;
+    let stream = RecordBatchStreamAdapter::new(stream.schema(), stream.boxed());
+    let stream = apply_transforms(stream, column, transforms);
 
-    write_index_file(
+    let start = std::time::Instant::now();
+    let ivf = lance_index::vector::ivf::new_ivf(
+        ivf_model.centroids.values(),
+        ivf_model.dimension(),
+        ivf_metric_type,
+        vec![],
+        None,
+    )?;
+    let shuffler = shuffle_vectors(
+        stream,
+        column,
+        ivf,
+        shuffle_mode(ivf_model.num_partitions()),
+        limits,
+        ivf_params.progress.clone(),
+    )
+    .await?;
+    info!(
+        "Shuffled IVF partitions: {}s",
+        start.elapsed().as_secs_f32()
+    );
+
+    write_ivf_sub_index_file(
         dataset,
         column,
         index_name,
         uuid,
-        &transforms,
         ivf_model,
-        pq,
         metric_type,
+        if normalize {
+            vec![L2Normalizer::to_proto()]
+        } else {
+            vec![]
+        },
+        &shuffler,
+        column,
+        pb::vector_index_stage::Stage::Flat(pb::Flat {}),
+        ivf_params.progress.as_ref(),
+    )
+    .await
+}
+
+/// Build IVF_SQ index.
+///
+/// The residual vectors to the IVF centroids are encoded with 8-bit scalar quantization.
+pub async fn build_ivf_sq_index(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    metric_type: MetricType,
+    ivf_params: &IvfBuildParams,
+    sq_params: &SQBuildParams,
+) -> Result<()> {
+    info!(
+        "Building vector index: IVF{},SQ{}, metric={}",
+        ivf_params.num_partitions, sq_params.num_bits, metric_type,
+    );
+    let limits = ShuffleLimits::try_from_params(ivf_params)?;
+
+    let field = sanity_check(dataset, column)?;
+    let DataType::FixedSizeList(value_field, dim) = field.data_type() else {
+        return Err(Error::Index {
+            message: format!(
+                "VectorIndex requires the column data type to be fixed size list of floats, got {}",
+                field.data_type()
+            ),
+            location: location!(),
+        });
+    };
+    if value_field.data_type() != &DataType::Float32 {
+        return Err(Error::Index {
+            message: format!(
+                "IVF_SQ requires the column to be fixed size list of float32s, got {}",
+                field.data_type()
+            ),
+            location: location!(),
+        });
+    }
+    let dim = dim as usize;
+
+    let sample_size_hint =
+        std::cmp::max(ivf_params.num_partitions, 1 << sq_params.num_bits as usize)
+            * std::cmp::max(ivf_params.sample_rate, sq_params.sample_rate);
+    // SQ is trained on the same sample as IVF, so it is also bounded by IVF.
+    let sample_size_hint = ivf_params
+        .max_training_rows
+        .map_or(sample_size_hint, |max_rows| {
+            std::cmp::min(sample_size_hint, max_rows)
+        });
+    let training_data =
+        maybe_sample_training_data(dataset, column, sample_size_hint, ivf_params.seed).await?;
+
+    let start = std::time::Instant::now();
+    let mut ivf_model = if let Some(centroids) = &ivf_params.centroids {
+        if centroids.values().len() != ivf_params.num_partitions * dim {
+            return Err(Error::Index {
+                message: format!(
+                    "IVF centroids length mismatch: {} != {}",
+                    centroids.len(),
+                    ivf_params.num_partitions * dim,
+                ),
+                location: location!(),
+            });
+        }
+        Ivf::new(centroids.clone())
+    } else if let Some(batch_size) = ivf_params.mini_batch_size {
+        info!("Start to train IVF model with mini-batches");
+        train_ivf_model_mini_batch(dataset, column, metric_type, ivf_params, batch_size, &[])
+            .await?
+    } else {
+        info!("Start to train IVF model");
+        train_ivf_model(&training_data, metric_type, ivf_params).await?
+    };
+    info!(
+        "Trained IVF model in {:02} seconds",
+        start.elapsed().as_secs_f32()
+    );
+    gather_probe_gaps(
+        dataset,
+        column,
+        None,
+        &mut ivf_model,
+        metric_type,
+        &[],
+        ivf_params.seed,
+    )
+    .await?;
+    ivf_model.use_residual = ivf_params.use_residual;
+    let centroids = ivf_model
+        .centroids
+        .values()
+        .as_primitive_opt::<Float32Type>()
+        .ok_or_else(|| Error::Index {
+            message: format!(
+                "IVF_SQ requires float32 centroids, got {}",
+                ivf_model.centroids.value_type()
+            ),
+            location: location!(),
+        })?
+        .clone();
+    let centroids = MatrixView::<Float32Type>::new(Arc::new(centroids), dim);
+
+    // Train the range of each dimension on the residual vectors.
+    let sq = if ivf_model.use_residual {
+        let ivf = lance_index::vector::ivf::new_ivf(
+            ivf_model.centroids.values(),
+            dim,
+            metric_type,
+            vec![],
+            None,
+        )?;
+        let residuals = ivf.compute_residual(&training_data, None).await?;
+        Arc::new(sq_params.build(&residuals)?)
+    } else {
+        Arc::new(sq_params.build(&training_data)?)
+    };
+
+    let mut scanner = dataset.scan();
+    scanner.batch_readahead(num_cpus::get() * 2);
+    scanner.project(&[column])?;
+    scanner.with_index_row_id();
+    let stream = scanner.try_into_stream().await?;
+
+    let start = std::time::Instant::now();
+    let transforms: Vec<Arc<dyn lance_index::vector::transform::Transformer>> =
+        if ivf_model.use_residual {
+            vec![
+                Arc::new(ResidualTransform::new(centroids, PART_ID_COLUMN, column)),
+                Arc::new(SQTransformer::new(
+                    sq.clone(),
+                    RESIDUAL_COLUMN,
+                    SQ_CODE_COLUMN,
+                )),
+            ]
+        } else {
+            vec![Arc::new(SQTransformer::new(
+                sq.clone(),
+                column,
+                SQ_CODE_COLUMN,
+            ))]
+        };
+    let ivf = lance_index::vector::ivf::new_ivf(
+        ivf_model.centroids.values(),
+        dim,
+        metric_type,
+        transforms,
+        None,
+    )?;
+    let schema = ArrowSchema::new(vec![
+        ROW_ID_FIELD.clone(),
+        ArrowField::new(PART_ID_COLUMN, DataType::UInt32, false),
+        ArrowField::new(
+            SQ_CODE_COLUMN,
+            DataType::FixedSizeList(
+                Arc::new(ArrowField::new("item", DataType::UInt8, true)),
+                dim as i32,
+            ),
+            false,
+        ),
+    ]);
+    let shuffler = shuffle_with_schema(
         stream,
+        column,
+        ivf,
+        schema,
+        None,
+        shuffle_mode(ivf_model.num_partitions()),
+        limits,
+        ivf_params.progress.clone(),
+    )
+    .await?;
+    info!(
+        "Shuffled IVF partitions: {}s",
+        start.elapsed().as_secs_f32()
+    );
+
+    write_ivf_sub_index_file(
+        dataset,
+        column,
+        index_name,
+        uuid,
+        ivf_model,
+        metric_type,
+        vec![],
+        &shuffler,
+        SQ_CODE_COLUMN,
+        pb::vector_index_stage::Stage::Sq(pb::Sq::from(sq.as_ref())),
+        ivf_params.progress.as_ref(),
     )
     .await
 }
@@ -815,15 +1966,33 @@ impl RemapPageTask {
 
     async fn write(self, writer: &mut ObjectWriter, ivf: &mut Ivf) -> Result<()> {
         let page = self.page.as_ref().expect("Load was not called");
-        let page: &PQIndex = page
-            .as_any()
-            .downcast_ref()
-            .expect("Generic index writing not supported yet");
+        // Each sub-index stores its codes, or the original vectors, followed by the row ids.
+        let (data, row_ids): (&dyn Array, &dyn Array) =
+            if let Some(pq) = page.as_any().downcast_ref::<PQIndex>() {
+                (
+                    pq.code.as_ref().unwrap().as_ref(),
+                    pq.row_ids.as_ref().unwrap().as_ref(),
+                )
+            } else if let Some(flat) = page.as_any().downcast_ref::<FlatIndex>() {
+                (
+                    flat.vectors.as_ref().unwrap().values(),
+                    flat.row_ids.as_ref().unwrap().as_ref(),
+                )
+            } else if let Some(sq) = page.as_any().downcast_ref::<SQIndex>() {
+                (
+                    sq.code.as_ref().unwrap().as_ref(),
+                    sq.row_ids.as_ref().unwrap().as_ref(),
+                )
+            } else {
+                return Err(Error::NotSupported {
+                    source: format!("Remapping sub-index {:?}", page).into(),
+                    location: location!(),
+                });
+            };
         ivf.offsets.push(writer.tell().await?);
-        ivf.lengths
-            .push(page.row_ids.as_ref().unwrap().len() as u32);
-        PlainEncoder::write(writer, &[page.code.as_ref().unwrap().as_ref()]).await?;
-        PlainEncoder::write(writer, &[page.row_ids.as_ref().unwrap().as_ref()]).await?;
+        ivf.lengths.push(row_ids.len() as u32);
+        PlainEncoder::write(writer, &[data]).await?;
+        PlainEncoder::write(writer, &[row_ids]).await?;
         Ok(())
     }
 }
@@ -867,32 +2036,48 @@ pub(crate) async fn remap_index_file(
         centroids: index.ivf.centroids.clone(),
         offsets: Vec::with_capacity(index.ivf.offsets.len()),
         lengths: Vec::with_capacity(index.ivf.lengths.len()),
+        probe_gaps: index.ivf.probe_gaps.clone(),
+        use_residual: index.ivf.use_residual,
     };
     while let Some(write_task) = task_stream.try_next().await? {
         write_task.write(&mut writer, &mut ivf).await?;
     }
 
-    let pq_sub_index = index
-        .sub_index
-        .as_any()
-        .downcast_ref::<PQIndex>()
-        .ok_or_else(|| Error::NotSupported {
-            source: "Remapping a non-pq sub-index".into(),
-            location: location!(),
-        })?;
-
-    let metadata = IvfPQIndexMetadata {
-        name,
-        column,
-        dimension: index.ivf.dimension() as u32,
-        dataset_version: old_version,
-        ivf,
-        metric_type: index.metric_type,
-        pq: pq_sub_index.pq.clone(),
-        transforms,
+    let sub_index = index.sub_index.as_any();
+    let metadata = if let Some(pq_sub_index) = sub_index.downcast_ref::<PQIndex>() {
+        let metadata = IvfPQIndexMetadata {
+            name,
+            column,
+            dimension: index.ivf.dimension() as u32,
+            dataset_version: old_version,
+            ivf,
+            metric_type: index.metric_type,
+            pq: pq_sub_index.pq.clone(),
+            transforms,
+        };
+        pb::Index::try_from(&metadata)?
+    } else {
+        let sub_index_stage = if sub_index.is::<FlatIndex>() {
+            pb::vector_index_stage::Stage::Flat(pb::Flat {})
+        } else if let Some(sq_sub_index) = sub_index.downcast_ref::<SQIndex>() {
+            pb::vector_index_stage::Stage::Sq(pb::Sq::from(sq_sub_index.sq.as_ref()))
+        } else {
+            return Err(Error::NotSupported {
+                source: format!("Remapping sub-index {:?}", index.sub_index).into(),
+                location: location!(),
+            });
+        };
+        ivf_sub_index_metadata(
+            &name,
+            &column,
+            old_version,
+            &ivf,
+            index.metric_type,
+            transforms,
+            sub_index_stage,
+        )?
     };
 
-    let metadata = pb::Index::try_from(&metadata)?;
     let pos = writer.write_protobuf(&metadata).await?;
     writer.write_magics(pos).await?;
     writer.shutdown().await?;
@@ -900,19 +2085,82 @@ pub(crate) async fn remap_index_file(
     Ok(())
 }
 
+/// Apply the pre-transforms, i.e., normalization and the OPQ rotation, on the vector
+/// column of the stream.
+///
+/// The vectors of a multivector column are flattened first, so that each of them is
+/// assigned to its own partition.
+fn apply_transforms(
+    stream: impl RecordBatchStream + Unpin + 'static,
+    column: &str,
+    transforms: Vec<Arc<dyn Transformer>>,
+) -> impl RecordBatchStream + Unpin {
+    let schema = stream.schema();
+    let column = column.to_string();
+    let stream = stream.and_then(move |batch| {
+        let column = column.clone();
+        let transforms = transforms.clone();
+        async move {
+            let batch = filter_null_vectors(&flatten_multivector_batch(&batch, &column)?, &column)?;
+            let field = batch.schema().field_with_name(&column)?.clone();
+            let vectors = batch
+                .column_by_name(&column)
+                .ok_or_else(|| Error::Index {
+                    message: format!("column {} does not exist in data stream", column),
+                    location: location!(),
+                })?
+                .as_fixed_size_list();
+            if transforms.is_empty() && vectors.value_type() != DataType::Int8 {
+                return Ok(batch);
+            }
+            // Int8 vectors are partitioned and quantized in float32.
+            let mut vectors = int8_to_float32(vectors)?;
+            for transform in transforms.iter() {
+                vectors = transform.transform(&vectors).await?;
+            }
+            // The transforms may change the float type, i.e., OPQ rotates in float32.
+            // The column is kept at its position, as declared by the stream schema.
+            let field = ArrowField::new(
+                field.name(),
+                vectors.data_type().clone(),
+                field.is_nullable(),
+            );
+            let schema = batch.schema();
+            let idx = schema.index_of(&column)?;
+            let mut fields = schema.fields().to_vec();
+            fields[idx] = Arc::new(field);
+            let mut columns = batch.columns().to_vec();
+            columns[idx] = Arc::new(vectors);
+            Ok(RecordBatch::try_new(
+                Arc::new(ArrowSchema::new_with_metadata(
+                    fields,
+                    schema.metadata().clone(),
+                )),
+                columns,
+            )?)
+        }
+    });
+    RecordBatchStreamAdapter::new(schema, Box::pin(stream))
+}
+
 /// Write the index to the index file.
 ///
+/// `metric_type` is recorded in the index metadata, while `ivf_metric_type` is used to
+/// assign the vectors to partitions. They differ if the vectors are normalized.
 #[allow(clippy::too_many_arguments)]
 async fn write_index_file(
     dataset: &Dataset,
     column: &str,
     index_name: &str,
     uuid: &str,
-    transformers: &[Box<dyn Transformer>],
+    transformers: &[Arc<dyn Transformer>],
     mut ivf: Ivf,
     pq: Arc<dyn ProductQuantizer>,
     metric_type: MetricType,
+    ivf_metric_type: MetricType,
     stream: impl RecordBatchStream + Unpin,
+    limits: ShuffleLimits,
+    progress: Arc<dyn IndexBuildProgress>,
 ) -> Result<()> {
     let object_store = dataset.object_store();
     let path = dataset.indices_dir().child(uuid).child(INDEX_FILE_NAME);
@@ -926,16 +2174,45 @@ async fn write_index_file(
         column,
         &mut ivf,
         pq.clone(),
-        metric_type,
+        ivf_metric_type,
         0..num_partitions,
+        None,
+        limits,
+        progress,
     )
     .await?;
     info!("Built IVF partitions: {}s", start.elapsed().as_secs_f32());
 
+    write_ivf_pq_metadata(
+        &mut writer,
+        dataset,
+        column,
+        index_name,
+        transformers,
+        ivf,
+        pq,
+        metric_type,
+    )
+    .await
+}
+
+/// Write the transforms and the metadata of an IVF_PQ index after its partitions,
+/// and close the index file.
+#[allow(clippy::too_many_arguments)]
+async fn write_ivf_pq_metadata(
+    writer: &mut ObjectWriter,
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    transformers: &[Arc<dyn Transformer>],
+    ivf: Ivf,
+    pq: Arc<dyn ProductQuantizer>,
+    metric_type: MetricType,
+) -> Result<()> {
     // Convert [`Transformer`] to metadata.
     let mut transforms = vec![];
     for t in transformers {
-        let t = t.save(&mut writer).await?;
+        let t = t.save(writer).await?;
         transforms.push(t);
     }
 
@@ -958,13 +2235,174 @@ async fn write_index_file(
     Ok(())
 }
 
+/// Write the shuffled partitions of an IVF index with a single sub-index stage,
+/// i.e., `IVF_FLAT` or `IVF_SQ`, to the index file.
+#[allow(clippy::too_many_arguments)]
+async fn write_ivf_sub_index_file(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    mut ivf: Ivf,
+    metric_type: MetricType,
+    transforms: Vec<pb::Transform>,
+    shuffler: &Shuffler,
+    shuffled_column: &str,
+    sub_index: pb::vector_index_stage::Stage,
+    progress: &dyn IndexBuildProgress,
+) -> Result<()> {
+    let object_store = dataset.object_store();
+    let path = dataset.indices_dir().child(uuid).child(INDEX_FILE_NAME);
+    let mut writer = object_store.create(&path).await?;
+
+    write_column_partitions(&mut writer, &mut ivf, shuffler, shuffled_column, progress).await?;
+
+    let metadata = ivf_sub_index_metadata(
+        index_name,
+        column,
+        dataset.version().version,
+        &ivf,
+        metric_type,
+        transforms,
+        sub_index,
+    )?;
+    let pos = writer.write_protobuf(&metadata).await?;
+    writer.write_magics(pos).await?;
+    writer.shutdown().await?;
+
+    Ok(())
+}
+
+/// Metadata of an IVF index with a single sub-index stage, i.e., `IVF_FLAT` or `IVF_SQ`.
+fn ivf_sub_index_metadata(
+    index_name: &str,
+    column: &str,
+    dataset_version: u64,
+    ivf: &Ivf,
+    metric_type: MetricType,
+    transforms: Vec<pb::Transform>,
+    sub_index: pb::vector_index_stage::Stage,
+) -> Result<pb::Index> {
+    let mut stages = transforms
+        .into_iter()
+        .map(|tf| pb::VectorIndexStage {
+            stage: Some(pb::vector_index_stage::Stage::Transform(tf)),
+        })
+        .collect::<Vec<_>>();
+    stages.extend([
+        pb::VectorIndexStage {
+            stage: Some(pb::vector_index_stage::Stage::Ivf(pb::Ivf::try_from(ivf)?)),
+        },
+        pb::VectorIndexStage {
+            stage: Some(sub_index),
+        },
+    ]);
+    Ok(pb::Index {
+        name: index_name.to_string(),
+        columns: vec![column.to_string()],
+        dataset_version,
+        index_type: pb::IndexType::Vector.into(),
+        implementation: Some(pb::index::Implementation::VectorIndex(pb::VectorIndex {
+            spec_version: 1,
+            dimension: ivf.dimension() as u32,
+            stages,
+            metric_type: pb::VectorMetricType::try_from(metric_type)?.into(),
+        })),
+    })
+}
+
+/// The number of vectors sampled to gather the probing statistics of the partitions.
+const PROBE_SAMPLE_SIZE: usize = 4096;
+
+/// The number of sampled vectors searched as queries to gather the probing statistics.
+const PROBE_NUM_QUERIES: usize = 64;
+
+/// The number of nearest neighbors of each query to gather the probing statistics.
+const PROBE_K: usize = 10;
+
+/// The number of percentiles of the probing statistics.
+const PROBE_PERCENTILES: usize = 100;
+
+/// Gather the statistics to pick the number of partitions to probe for a target recall.
+///
+/// The vectors are sampled from the dataset, or taken from `stream_sample` when the
+/// index is built from a stream. The sampled vectors are transformed by `transforms`,
+/// as the centroids are trained in the transformed space.
+async fn gather_probe_gaps(
+    dataset: &Dataset,
+    column: &str,
+    stream_sample: Option<&FixedSizeListArray>,
+    ivf: &mut Ivf,
+    metric_type: MetricType,
+    transforms: &[Arc<dyn Transformer>],
+    seed: Option<u64>,
+) -> Result<()> {
+    let mut sample = match stream_sample {
+        Some(sample) => sample.slice(0, std::cmp::min(sample.len(), PROBE_SAMPLE_SIZE)),
+        None => maybe_sample_training_data(dataset, column, PROBE_SAMPLE_SIZE, seed).await?,
+    };
+    for transform in transforms.iter() {
+        sample = transform.transform(&sample).await?;
+    }
+    ivf.probe_gaps = probe_gaps(&sample, &ivf.centroids, metric_type)?;
+    Ok(())
+}
+
+/// The percentiles of the gap between the distance from a query to the centroid of the
+/// partition of each of its nearest neighbors and the distance to its nearest centroid.
+///
+/// The first vectors of `sample` are the queries, whose nearest neighbors are searched
+/// exactly among the other sampled vectors. Probing the partitions within the `p`-th
+/// percentile of the gaps from the nearest centroid finds about `p`% of the neighbors.
+fn probe_gaps(
+    sample: &FixedSizeListArray,
+    centroids: &FixedSizeListArray,
+    metric_type: MetricType,
+) -> Result<Vec<f32>> {
+    if sample.len() <= PROBE_K {
+        return Ok(vec![]);
+    }
+    let distance = metric_type.arrow_batch_func();
+    let partitions = (0..sample.len())
+        .map(|i| {
+            let dists = distance(sample.value(i).as_ref(), centroids)?;
+            Ok(argmin_value_float(dists.values().iter().copied()).0)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut gaps = Vec::with_capacity(PROBE_NUM_QUERIES * PROBE_K);
+    for i in 0..std::cmp::min(sample.len(), PROBE_NUM_QUERIES) {
+        let query = sample.value(i);
+        let centroid_dists = distance(query.as_ref(), centroids)?;
+        let nearest = centroid_dists.value(partitions[i] as usize);
+        let dists = distance(query.as_ref(), sample)?;
+        // The query is a neighbor of itself.
+        let neighbors = sort_to_indices(dists.as_ref(), None, Some(PROBE_K + 1))?;
+        gaps.extend(
+            neighbors
+                .values()
+                .iter()
+                .filter(|&&j| j as usize != i)
+                .take(PROBE_K)
+                .map(|&j| centroid_dists.value(partitions[j as usize] as usize) - nearest),
+        );
+    }
+    gaps.sort_by(|a, b| a.total_cmp(b));
+    Ok((1..=PROBE_PERCENTILES)
+        .map(|p| gaps[(p * gaps.len() + PROBE_PERCENTILES - 1) / PROBE_PERCENTILES - 1])
+        .collect())
+}
+
 async fn do_train_ivf_model<T: ArrowFloatType + Dot + Cosine + L2 + 'static>(
     data: &T::ArrayType,
     dimension: usize,
     metric_type: MetricType,
     params: &IvfBuildParams,
 ) -> Result<Ivf> {
-    let rng = SmallRng::from_entropy();
+    let rng = match params.seed {
+        Some(seed) => SmallRng::seed_from_u64(seed),
+        None => SmallRng::from_entropy(),
+    };
     const REDOS: usize = 1;
     let centroids = lance_index::vector::kmeans::train_kmeans::<T>(
         data,
@@ -973,17 +2411,47 @@ async fn do_train_ivf_model<T: ArrowFloatType + Dot + Cosine + L2 + 'static>(
         params.num_partitions,
         params.max_iters as u32,
         REDOS,
-        rng,
+        rng.clone(),
         metric_type,
         params.sample_rate,
+        params.init,
+        params.num_threads,
     )
     .await?;
+    let mut kmeans = KMeans::<T>::with_centroids(
+        Arc::new(centroids),
+        params.num_partitions,
+        dimension,
+        metric_type,
+    );
+    if let Some(num_threads) = params.num_threads {
+        kmeans.num_threads = num_threads;
+    }
+    let training_data = MatrixView::<T>::new(Arc::new(data.clone()), dimension);
+    let centroids = balance_partitions(kmeans, &training_data, params, rng).await?;
     Ok(Ivf::new(Arc::new(FixedSizeListArray::try_new_from_values(
         centroids,
         dimension as i32,
     )?)))
 }
 
+/// Split the oversized partitions if `max_partition_skew` is set, and return the centroids.
+async fn balance_partitions<T: ArrowFloatType + Dot + Cosine + L2>(
+    kmeans: KMeans<T>,
+    data: &MatrixView<T>,
+    params: &IvfBuildParams,
+    rng: impl Rng,
+) -> Result<T::ArrayType>
+where
+    T::Native: AsPrimitive<f32>,
+{
+    let kmeans = match params.max_partition_skew {
+        Some(max_skew) => kmeans.split_oversized(data, max_skew, rng).await?,
+        None => kmeans,
+    };
+    Ok(kmeans.centroids.as_ref().clone())
+}
+
 /// Train IVF partitions using kmeans.
 async fn train_ivf_model(
     data: &FixedSizeListArray,
@@ -1009,16 +2477,190 @@ async fn train_ivf_model(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
+async fn do_train_ivf_model_mini_batch<T: ArrowFloatType + Dot + Cosine + L2 + 'static>(
+    dataset: &Dataset,
+    column: &str,
+    init: &T::ArrayType,
+    dimension: usize,
+    metric_type: MetricType,
+    params: &IvfBuildParams,
+    batch_size: usize,
+    transforms: &[Arc<dyn Transformer>],
+) -> Result<Ivf> {
+    const TOLERANCE: f64 = 1e-4;
+
+    let rng = match params.seed {
+        Some(seed) => SmallRng::seed_from_u64(seed),
+        None => SmallRng::from_entropy(),
+    };
+    let init = MatrixView::<T>::new(Arc::new(init.clone()), dimension);
+    let mut kmeans = KMeans::init(
+        &init,
+        params.num_partitions,
+        metric_type,
+        params.init,
+        rng.clone(),
+    )
+    .await?;
+    if let Some(num_threads) = params.num_threads {
+        kmeans.num_threads = num_threads;
+    }
+    let mut mini_batch = MiniBatchKMeans::new(kmeans);
+
+    let mut dist_sum = f64::MAX;
+    for i in 1..=params.max_iters {
+        let mut scanner = dataset.scan();
+        scanner.project(&[column])?;
+        scanner.batch_size(batch_size);
+        let mut stream = scanner.try_into_stream().await?;
+
+        let mut last_dist_sum = 0.0;
+        while let Some(batch) = stream.try_next().await? {
+            let vectors = batch.column_by_name(column).ok_or_else(|| Error::Index {
+                message: format!("column {} does not exist in data stream", column),
+                location: location!(),
+            })?;
+            let mut vectors = int8_to_float32(&to_vectors(vectors)?)?;
+            for transform in transforms.iter() {
+                vectors = transform.transform(&vectors).await?;
+            }
+            let values = vectors
+                .values()
+                .as_any()
+                .downcast_ref::<T::ArrayType>()
+                .ok_or_else(|| Error::Index {
+                    message: format!(
+                        "IVF mini-batch training: expected {} vectors, got {}",
+                        T::FLOAT_TYPE,
+                        vectors.value_type()
+                    ),
+                    location: location!(),
+                })?;
+            let batch = MatrixView::<T>::new(Arc::new(values.clone()), dimension);
+            last_dist_sum += mini_batch.update(&batch).await;
+        }
+        info!(
+            "IVF mini-batch training: pass {} / {}, distance sum {}",
+            i, params.max_iters, last_dist_sum
+        );
+        if (dist_sum - last_dist_sum).abs() / last_dist_sum.abs() < TOLERANCE {
+            break;
+        }
+        dist_sum = last_dist_sum;
+    }
+
+    // The partitions are balanced on the initial sample, as the dataset may not fit in memory.
+    let centroids = balance_partitions(mini_batch.into_kmeans(), &init, params, rng).await?;
+    Ok(Ivf::new(Arc::new(FixedSizeListArray::try_new_from_values(
+        centroids,
+        dimension as i32,
+    )?)))
+}
+
+/// Train IVF partitions with mini-batch kmeans over the vectors streamed from the dataset.
+///
+/// Only one batch of vectors is in memory at a time. The vectors are transformed by
+/// `transforms` before clustering, like the training sample in [`train_ivf_model`].
+async fn train_ivf_model_mini_batch(
+    dataset: &Dataset,
+    column: &str,
+    metric_type: MetricType,
+    params: &IvfBuildParams,
+    batch_size: usize,
+    transforms: &[Arc<dyn Transformer>],
+) -> Result<Ivf> {
+    if batch_size == 0 {
+        return Err(Error::Index {
+            message: "IVF mini-batch training: mini_batch_size must be greater than 0".to_string(),
+            location: location!(),
+        });
+    }
+    let num_rows = dataset.count_rows().await?;
+    if num_rows < params.num_partitions {
+        return Err(Error::Index {
+            message: format!(
+                "KMeans: can not train {} centroids with {} vectors, choose a smaller number of partitions",
+                params.num_partitions, num_rows
+            ),
+            location: location!(),
+        });
+    }
+
+    // The initial centroids are chosen from a sample of the dataset, which is of
+    // 3 batches or 3 vectors per partition, as in scikit-learn.
+    let init_size = 3 * std::cmp::max(batch_size, params.num_partitions);
+    let mut init = maybe_sample_training_data(dataset, column, init_size, params.seed).await?;
+    for transform in transforms.iter() {
+        init = transform.transform(&init).await?;
+    }
+
+    let values = init.values();
+    let dim = init.value_length() as usize;
+    match values.data_type() {
+        DataType::Float16 => {
+            do_train_ivf_model_mini_batch::<Float16Type>(
+                dataset,
+                column,
+                values.as_primitive(),
+                dim,
+                metric_type,
+                params,
+                batch_size,
+                transforms,
+            )
+            .await
+        }
+        DataType::Float32 => {
+            do_train_ivf_model_mini_batch::<Float32Type>(
+                dataset,
+                column,
+                values.as_primitive(),
+                dim,
+                metric_type,
+                params,
+                batch_size,
+                transforms,
+            )
+            .await
+        }
+        DataType::Float64 => {
+            do_train_ivf_model_mini_batch::<Float64Type>(
+                dataset,
+                column,
+                values.as_primitive(),
+                dim,
+                metric_type,
+                params,
+                batch_size,
+                transforms,
+            )
+            .await
+        }
+        _ => Err(Error::Index {
+            message: "Unsupported data type".to_string(),
+            location: location!(),
+        }),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    use std::collections::HashMap;
+    use std::collections::{HashMap, HashSet};
     use std::iter::repeat;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
-    use arrow_array::{cast::AsArray, RecordBatchIterator, RecordBatchReader, UInt64Array};
+    use arrow_array::{
+        cast::AsArray,
+        types::{Int32Type, Int8Type, UInt64Type},
+        Int32Array, Int8Array, RecordBatchIterator, RecordBatchReader, UInt64Array,
+    };
     use arrow_schema::{DataType, Field, Schema};
-    use lance_linalg::distance::l2_distance_batch;
+    use lance_linalg::distance::{cosine_distance, dot, l2_distance_batch};
+    use lance_linalg::kmeans::KMeanInit;
     use lance_testing::datagen::{
         generate_random_array, generate_random_array_with_seed, generate_scaled_random_array,
         sample_without_replacement,
@@ -1028,6 +2670,10 @@ mod tests {
     use uuid::Uuid;
 
     use crate::{
+        dataset::{
+            optimize::{compact_files, CompactionOptions},
+            WriteParams,
+        },
         format::RowAddress,
         index::{vector::VectorIndexParams, DatasetIndexExt, DatasetIndexInternalExt, IndexType},
     };
@@ -1215,6 +2861,13 @@ mod tests {
                     k: 5,
                     nprobes: 1,
                     refine_factor: None,
+                    ef_search: None,
+                    beam_width: None,
+                    target_recall: None,
+                    max_probe_rows: None,
+                    trace: None,
+                    lower_bound: None,
+                    upper_bound: None,
                     metric_type: MetricType::L2,
                     use_index: true,
                 };
@@ -1267,68 +2920,310 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_create_ivf_pq_with_centroids() {
+    async fn test_build_ivf_pq_with_seed() {
         let test_dir = tempdir().unwrap();
         let test_uri = test_dir.path().to_str().unwrap();
 
-        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+        let (mut dataset, _) = generate_test_dataset(test_uri).await;
+
+        // Small sample rates so that both the training data and kmeans are sampled.
+        let build_params = |seed| {
+            let mut ivf_params = IvfBuildParams::new(4);
+            ivf_params.sample_rate = 2;
+            ivf_params.seed = seed;
+            let mut pq_params = PQBuildParams::new(4, 8);
+            pq_params.sample_rate = 2;
+            pq_params.max_iters = 5;
+            pq_params.seed = seed;
+            VectorIndexParams::with_ivf_pq_params(MetricType::L2, ivf_params, pq_params)
+        };
+        for (name, seed) in [("a", Some(42)), ("b", Some(42)), ("c", Some(7))] {
+            dataset
+                .create_index(
+                    &["vector"],
+                    IndexType::Vector,
+                    Some(name.to_string()),
+                    &build_params(seed),
+                    false,
+                )
+                .await
+                .unwrap();
+        }
 
-        let centroids = generate_random_array(2 * DIM);
-        let ivf_centroids = FixedSizeListArray::try_new_from_values(centroids, DIM as i32).unwrap();
-        let ivf_params = IvfBuildParams::try_with_centroids(2, Arc::new(ivf_centroids)).unwrap();
+        let mut models = vec![];
+        for name in ["a", "b", "c"] {
+            let uuid = dataset.load_index_by_name(name).await.unwrap().uuid;
+            let index = dataset
+                .open_vector_index("vector", &uuid.to_string())
+                .await
+                .unwrap();
+            let ivf_index = index.as_any().downcast_ref::<IVFIndex>().unwrap();
+            let pq_index = ivf_index
+                .sub_index
+                .as_any()
+                .downcast_ref::<PQIndex>()
+                .unwrap();
+            models.push((
+                ivf_index.ivf.centroids.as_ref().clone(),
+                pq_index.pq.codebook_as_fsl(),
+            ));
+        }
+        assert_eq!(models[0], models[1]);
+        assert_ne!(models[0].0, models[2].0);
+    }
 
-        let codebook = Arc::new(generate_random_array(256 * DIM));
-        let pq_params = PQBuildParams::with_codebook(4, 8, codebook);
+    #[tokio::test]
+    async fn test_train_ivf_with_mini_batches() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
 
-        let params = VectorIndexParams::with_ivf_pq_params(MetricType::L2, ivf_params, pq_params);
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
 
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.mini_batch_size = Some(100);
+        ivf_params.max_iters = 5;
+        let params = VectorIndexParams::with_ivf_flat_params(MetricType::L2, ivf_params.clone());
         dataset
             .create_index(&["vector"], IndexType::Vector, None, &params, false)
             .await
             .unwrap();
 
-        let sample_query = vector_array.value(10);
-        let query = sample_query.as_primitive::<Float32Type>();
+        let stats: serde_json::Value = serde_json::from_str(
+            &dataset
+                .index_statistics("vector_idx")
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        let partitions = stats["partitions"].as_array().unwrap();
+        assert_eq!(partitions.len(), 4);
+        let num_indexed = partitions
+            .iter()
+            .map(|p| p["length"].as_u64().unwrap())
+            .sum::<u64>();
+        assert_eq!(num_indexed, 1000);
+
+        // Searching all the partitions of IVF_FLAT is exact.
+        let query = vector_array.value(0);
+        let query = query.as_primitive::<Float32Type>();
+        let mut expected = l2_distance_batch(
+            query.values(),
+            vector_array.values().as_primitive::<Float32Type>().values(),
+            DIM,
+        )
+        .enumerate()
+        .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1));
         let results = dataset
             .scan()
-            .nearest("vector", query, 5)
+            .nearest("vector", query, 10)
             .unwrap()
+            .nprobs(4)
+            .with_row_id()
             .try_into_stream()
             .await
             .unwrap()
             .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        assert_eq!(1, results.len());
-        assert_eq!(5, results[0].num_rows());
-    }
+        let row_ids = results[0]["_rowid"].as_primitive::<UInt64Type>().values();
+        assert_eq!(
+            row_ids.to_vec(),
+            expected[..10]
+                .iter()
+                .map(|(i, _)| *i as u64)
+                .collect::<Vec<_>>()
+        );
 
-    fn partition_ids(mut ids: Vec<u64>, num_parts: u32) -> Vec<Vec<u64>> {
-        if num_parts > ids.len() as u32 {
-            panic!("Not enough ids to break into {num_parts} parts");
+        // The streamed vectors are normalized for a cosine IVF_PQ index.
+        let params = VectorIndexParams::with_ivf_pq_params(
+            MetricType::Cosine,
+            ivf_params,
+            PQBuildParams::new(4, 8),
+        );
+        dataset
+            .create_index(
+                &["vector"],
+                IndexType::Vector,
+                Some("pq_idx".to_string()),
+                &params,
+                false,
+            )
+            .await
+            .unwrap();
+        let stats: serde_json::Value =
+            serde_json::from_str(&dataset.index_statistics("pq_idx").await.unwrap().unwrap())
+                .unwrap();
+        for partition in stats["partitions"].as_array().unwrap() {
+            let centroid = partition["centroid"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| v.as_f64().unwrap() as f32)
+                .collect::<Vec<_>>();
+            let norm = centroid.iter().map(|v| v * v).sum::<f32>().sqrt();
+            // Means of unit vectors are inside the unit ball.
+            assert!(norm <= 1.0 + 1e-3, "norm = {norm}");
         }
-        let mut rng = thread_rng();
-        ids.shuffle(&mut rng);
+    }
 
-        let values_per_part = ids.len() / num_parts as usize;
-        let parts_with_one_extra = ids.len() % num_parts as usize;
+    #[tokio::test]
+    async fn test_train_ivf_with_kmeans_plus_plus() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
 
-        let mut parts = Vec::with_capacity(num_parts as usize);
-        let mut offset = 0;
-        for part_size in (0..num_parts).map(|part_idx| {
-            if part_idx < parts_with_one_extra as u32 {
-                values_per_part + 1
-            } else {
-                values_per_part
-            }
-        }) {
-            parts.push(Vec::from_iter(
-                ids[offset..(offset + part_size)].iter().copied(),
-            ));
-            offset += part_size;
+        let (mut dataset, _) = generate_test_dataset(test_uri).await;
+
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.init = KMeanInit::KMeanPlusPlus;
+        for (name, mini_batch_size) in [("in_memory", None), ("mini_batch", Some(100))] {
+            ivf_params.mini_batch_size = mini_batch_size;
+            let params =
+                VectorIndexParams::with_ivf_flat_params(MetricType::L2, ivf_params.clone());
+            dataset
+                .create_index(
+                    &["vector"],
+                    IndexType::Vector,
+                    Some(name.to_string()),
+                    &params,
+                    false,
+                )
+                .await
+                .unwrap();
+
+            let stats: serde_json::Value =
+                serde_json::from_str(&dataset.index_statistics(name).await.unwrap().unwrap())
+                    .unwrap();
+            let partitions = stats["partitions"].as_array().unwrap();
+            assert_eq!(partitions.len(), 4);
+            let num_indexed = partitions
+                .iter()
+                .map(|p| p["length"].as_u64().unwrap())
+                .sum::<u64>();
+            assert_eq!(num_indexed, 1000);
         }
-
-        parts
+    }
+
+    #[tokio::test]
+    async fn test_train_ivf_with_max_partition_skew() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, _) = generate_test_dataset(test_uri).await;
+
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.max_partition_skew = Some(1.5);
+        for (name, mini_batch_size) in [("in_memory", None), ("mini_batch", Some(100))] {
+            ivf_params.mini_batch_size = mini_batch_size;
+            let params =
+                VectorIndexParams::with_ivf_flat_params(MetricType::L2, ivf_params.clone());
+            dataset
+                .create_index(
+                    &["vector"],
+                    IndexType::Vector,
+                    Some(name.to_string()),
+                    &params,
+                    false,
+                )
+                .await
+                .unwrap();
+
+            let stats: serde_json::Value =
+                serde_json::from_str(&dataset.index_statistics(name).await.unwrap().unwrap())
+                    .unwrap();
+            let partitions = stats["partitions"].as_array().unwrap();
+            assert_eq!(partitions.len(), 4);
+            let num_indexed = partitions
+                .iter()
+                .map(|p| p["length"].as_u64().unwrap())
+                .sum::<u64>();
+            assert_eq!(num_indexed, 1000);
+        }
+
+        // The whole dataset is the training data when it is trained in memory.
+        let stats: serde_json::Value = serde_json::from_str(
+            &dataset
+                .index_statistics("in_memory")
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert!(stats["partition_skew"].as_f64().unwrap() <= 1.5);
+
+        ivf_params.mini_batch_size = None;
+        ivf_params.max_partition_skew = Some(0.5);
+        let params = VectorIndexParams::with_ivf_flat_params(MetricType::L2, ivf_params);
+        assert!(dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, true)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_ivf_pq_with_centroids() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+
+        let centroids = generate_random_array(2 * DIM);
+        let ivf_centroids = FixedSizeListArray::try_new_from_values(centroids, DIM as i32).unwrap();
+        let ivf_params = IvfBuildParams::try_with_centroids(2, Arc::new(ivf_centroids)).unwrap();
+
+        let codebook = Arc::new(generate_random_array(256 * DIM));
+        let pq_params = PQBuildParams::with_codebook(4, 8, codebook);
+
+        let params = VectorIndexParams::with_ivf_pq_params(MetricType::L2, ivf_params, pq_params);
+
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let sample_query = vector_array.value(10);
+        let query = sample_query.as_primitive::<Float32Type>();
+        let results = dataset
+            .scan()
+            .nearest("vector", query, 5)
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(1, results.len());
+        assert_eq!(5, results[0].num_rows());
+    }
+
+    fn partition_ids(mut ids: Vec<u64>, num_parts: u32) -> Vec<Vec<u64>> {
+        if num_parts > ids.len() as u32 {
+            panic!("Not enough ids to break into {num_parts} parts");
+        }
+        let mut rng = thread_rng();
+        ids.shuffle(&mut rng);
+
+        let values_per_part = ids.len() / num_parts as usize;
+        let parts_with_one_extra = ids.len() % num_parts as usize;
+
+        let mut parts = Vec::with_capacity(num_parts as usize);
+        let mut offset = 0;
+        for part_size in (0..num_parts).map(|part_idx| {
+            if part_idx < parts_with_one_extra as u32 {
+                values_per_part + 1
+            } else {
+                values_per_part
+            }
+        }) {
+            parts.push(Vec::from_iter(
+                ids[offset..(offset + part_size)].iter().copied(),
+            ));
+            offset += part_size;
+        }
+
+        parts
     }
 
     const BIG_OFFSET: u64 = 10000;
@@ -1446,43 +3341,728 @@ mod tests {
         .await
         .unwrap();
 
-        let remapped = dataset
-            .open_vector_index(WellKnownIvfPqData::COLUMN, &new_uuid.to_string())
+        let remapped = dataset
+            .open_vector_index(WellKnownIvfPqData::COLUMN, &new_uuid.to_string())
+            .await
+            .unwrap();
+        let ivf_remapped = remapped.as_any().downcast_ref::<IVFIndex>().unwrap();
+
+        // If the ids were remapped then make sure the new row id is in the results
+        test_data
+            .check_index(
+                ivf_remapped,
+                prefilter.clone(),
+                row_ids_to_modify,
+                is_remapped,
+            )
+            .await;
+        // If the ids were removed then make sure the old row id isn't in the results
+        test_data
+            .check_index(
+                ivf_remapped,
+                prefilter.clone(),
+                row_ids_to_remove,
+                is_removed,
+            )
+            .await;
+        // If the ids were not remapped then make sure they still return the old id
+        test_data
+            .check_index(
+                ivf_remapped,
+                prefilter.clone(),
+                row_ids_to_remain,
+                is_not_remapped,
+            )
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_create_ivf_pq_cosine() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+
+        let centroids = generate_random_array(2 * DIM);
+        let ivf_centroids = FixedSizeListArray::try_new_from_values(centroids, DIM as i32).unwrap();
+        let ivf_params = IvfBuildParams::try_with_centroids(2, Arc::new(ivf_centroids)).unwrap();
+
+        let codebook = Arc::new(generate_random_array(256 * DIM));
+        let pq_params = PQBuildParams::with_codebook(4, 8, codebook);
+
+        let params =
+            VectorIndexParams::with_ivf_pq_params(MetricType::Cosine, ivf_params, pq_params);
+
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let sample_query = vector_array.value(10);
+        let query = sample_query.as_primitive::<Float32Type>();
+        let results = dataset
+            .scan()
+            .nearest("vector", query, 5)
+            .unwrap()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(1, results.len());
+        assert_eq!(5, results[0].num_rows());
+        for batch in results.iter() {
+            let dist = &batch["_distance"];
+            assert!(dist
+                .as_primitive::<Float32Type>()
+                .values()
+                .iter()
+                .all(|v| (0.0..2.0).contains(v)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ivf_pq_cosine_without_normalized_input() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        // Vectors of very different lengths, pointing in all directions.
+        let values = generate_random_array(1000 * DIM);
+        let values = Float32Array::from_iter_values(
+            values
+                .values()
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (v - 0.5) * (1 + (i / DIM) % 100) as f32),
+        );
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                DIM as i32,
+            ),
+            true,
+        )]));
+        let vector_array =
+            Arc::new(FixedSizeListArray::try_new_from_values(values, DIM as i32).unwrap());
+        let batch = RecordBatch::try_new(schema.clone(), vec![vector_array.clone()]).unwrap();
+        let batches = RecordBatchIterator::new(vec![batch].into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+
+        let params = VectorIndexParams::with_ivf_pq_params(
+            MetricType::Cosine,
+            IvfBuildParams::new(4),
+            PQBuildParams::new(8, 8),
+        );
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        // The index records that the vectors are normalized.
+        let dataset = Arc::new(dataset);
+        let indices = dataset.load_indices().await.unwrap();
+        let index = dataset
+            .open_vector_index("vector", indices[0].uuid.to_string().as_str())
+            .await
+            .unwrap();
+        let ivf_idx = index.as_any().downcast_ref::<IVFIndex>().unwrap();
+        assert!(ivf_idx.normalized);
+        assert_eq!(index.metric_type(), MetricType::Cosine);
+
+        let query = vector_array.value(10);
+        let query = query.as_primitive::<Float32Type>();
+        let mut cosine_distances = vector_array
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let v = v.unwrap();
+                (
+                    i as u64,
+                    cosine_distance(query.values(), v.as_primitive::<Float32Type>().values()),
+                )
+            })
+            .collect::<Vec<_>>();
+        cosine_distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        let results = dataset
+            .scan()
+            .nearest("vector", query, 10)
+            .unwrap()
+            .nprobs(4)
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        assert_eq!(batch.num_rows(), 10);
+        let row_ids = batch["_rowid"].as_primitive::<UInt64Type>();
+        assert!(row_ids.values().contains(&10));
+        let dists = batch["_distance"].as_primitive::<Float32Type>().values();
+        assert!(dists.windows(2).all(|w| w[0] <= w[1]));
+        assert!(dists.iter().all(|d| (0.0..2.0).contains(d)));
+
+        // With refine, the results and the distances are exact.
+        let results = dataset
+            .scan()
+            .nearest("vector", query, 10)
+            .unwrap()
+            .nprobs(4)
+            .refine(100)
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        let row_ids = batch["_rowid"].as_primitive::<UInt64Type>();
+        let dists = batch["_distance"].as_primitive::<Float32Type>();
+        for ((row_id, dist), (expected_id, expected_dist)) in row_ids
+            .values()
+            .iter()
+            .zip(dists.values().iter())
+            .zip(cosine_distances[..10].iter())
+        {
+            assert_eq!(row_id, expected_id);
+            assert!((dist - expected_dist).abs() < 1e-4);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_ivf_flat() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+
+        let params = VectorIndexParams::ivf_flat(4, MetricType::L2);
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let index = dataset
+            .open_vector_index(
+                "vector",
+                &dataset.load_indices().await.unwrap()[0].uuid.to_string(),
+            )
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&index.statistics().unwrap()).unwrap();
+        assert_eq!(stats["sub_index"]["index_type"], "FLAT");
+
+        // Without quantization, each vector finds itself with an exact zero distance.
+        for row in [0, 10, 999] {
+            let sample_query = vector_array.value(row);
+            let query = sample_query.as_primitive::<Float32Type>();
+            let results = dataset
+                .scan()
+                .nearest("vector", query, 5)
+                .unwrap()
+                .with_row_id()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(1, results.len());
+            assert_eq!(5, results[0].num_rows());
+            let row_ids = results[0]["_rowid"].as_primitive::<UInt64Type>();
+            assert_eq!(row_ids.value(0), row as u64);
+            let dist = results[0]["_distance"].as_primitive::<Float32Type>();
+            assert_eq!(dist.value(0), 0.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_ivf_sq() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+
+        let params = VectorIndexParams::ivf_sq(4, MetricType::L2);
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let index = dataset
+            .open_vector_index(
+                "vector",
+                &dataset.load_indices().await.unwrap()[0].uuid.to_string(),
+            )
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&index.statistics().unwrap()).unwrap();
+        assert_eq!(stats["sub_index"]["index_type"], "SQ");
+
+        for row in [0, 10, 999] {
+            let sample_query = vector_array.value(row);
+            let query = sample_query.as_primitive::<Float32Type>();
+            let results = dataset
+                .scan()
+                .nearest("vector", query, 5)
+                .unwrap()
+                .with_row_id()
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(1, results.len());
+            assert_eq!(5, results[0].num_rows());
+            let row_ids = results[0]["_rowid"].as_primitive::<UInt64Type>();
+            assert!(row_ids.values().contains(&(row as u64)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_build_progress() {
+        #[derive(Debug, Default)]
+        struct CountingProgress {
+            rows_shuffled: AtomicUsize,
+            bytes_flushed: AtomicUsize,
+            partitions_indexed: Mutex<Vec<(u32, usize)>>,
+        }
+
+        impl IndexBuildProgress for CountingProgress {
+            fn rows_shuffled(&self, num_rows: usize) {
+                self.rows_shuffled.fetch_add(num_rows, Ordering::Relaxed);
+            }
+
+            fn partition_flushed(&self, _partition_id: u32, num_bytes: usize) {
+                self.bytes_flushed.fetch_add(num_bytes, Ordering::Relaxed);
+            }
+
+            fn partition_indexed(&self, partition_id: u32, num_rows: usize) {
+                self.partitions_indexed
+                    .lock()
+                    .unwrap()
+                    .push((partition_id, num_rows));
+            }
+        }
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, _) = generate_test_dataset(test_uri).await;
+
+        let progress = Arc::new(CountingProgress::default());
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.progress = progress.clone();
+        let params = VectorIndexParams::with_ivf_pq_params(
+            MetricType::L2,
+            ivf_params,
+            PQBuildParams::new(8, 8),
+        );
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        assert_eq!(progress.rows_shuffled.load(Ordering::Relaxed), 1000);
+        assert!(progress.bytes_flushed.load(Ordering::Relaxed) > 0);
+        let indexed = progress.partitions_indexed.lock().unwrap();
+        assert_eq!(
+            indexed.iter().map(|(id, _)| *id).collect::<Vec<_>>(),
+            vec![0, 1, 2, 3]
+        );
+        assert_eq!(indexed.iter().map(|(_, rows)| rows).sum::<usize>(), 1000);
+    }
+
+    #[tokio::test]
+    async fn test_build_with_max_training_rows() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vectors) = generate_test_dataset(test_uri).await;
+
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.max_training_rows = Some(300);
+        assert_eq!(ivf_params.sample_size(1), 256);
+        assert_eq!(ivf_params.sample_size(256), 300);
+        let mut pq_params = PQBuildParams::new(8, 8);
+        pq_params.max_training_rows = Some(300);
+        assert_eq!(pq_params.sample_size(), 300);
+        pq_params.sample_rate = 1;
+        assert_eq!(pq_params.sample_size(), 256);
+        pq_params.sample_rate = 256;
+
+        let training_data = maybe_sample_training_data(&dataset, "vector", 300, Some(42))
+            .await
+            .unwrap();
+        assert_eq!(training_data.len(), 300);
+
+        let params = VectorIndexParams::with_ivf_pq_params(MetricType::L2, ivf_params, pq_params);
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        let query = vectors.value(0);
+        let results = dataset
+            .scan()
+            .nearest("vector", query.as_primitive::<Float32Type>(), 5)
+            .unwrap()
+            .nprobs(4)
+            .refine(10)
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let row_ids = results[0]["_rowid"].as_primitive::<UInt64Type>();
+        assert_eq!(row_ids.value(0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_build_with_resource_limits() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vectors) = generate_test_dataset(test_uri).await;
+
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.num_threads = Some(0);
+        let params = VectorIndexParams::with_ivf_flat_params(MetricType::L2, ivf_params);
+        assert!(dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, true)
+            .await
+            .is_err());
+
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.num_threads = Some(1);
+        ivf_params.num_parallel_partitions = Some(1);
+        // Spill the shuffled vectors after every few batches.
+        ivf_params.shuffle_memory_budget = Some(64 * 1024);
+        let mut pq_params = PQBuildParams::new(8, 8);
+        pq_params.num_threads = Some(1);
+        let params = VectorIndexParams::with_ivf_pq_params(MetricType::L2, ivf_params, pq_params);
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, true)
+            .await
+            .unwrap();
+
+        let query = vectors.value(0);
+        let results = dataset
+            .scan()
+            .nearest("vector", query.as_primitive::<Float32Type>(), 5)
+            .unwrap()
+            .nprobs(4)
+            .refine(10)
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let row_ids = results[0]["_rowid"].as_primitive::<UInt64Type>();
+        assert_eq!(row_ids.value(0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_build_without_residual() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+
+        let mut ivf_params = IvfBuildParams::new(4);
+        ivf_params.use_residual = false;
+        let pq_params = VectorIndexParams::with_ivf_pq_params(
+            MetricType::L2,
+            ivf_params.clone(),
+            PQBuildParams::new(8, 8),
+        );
+        let sq_params = VectorIndexParams::with_ivf_sq_params(
+            MetricType::L2,
+            ivf_params,
+            SQBuildParams::default(),
+        );
+        for params in [pq_params, sq_params] {
+            dataset
+                .create_index(&["vector"], IndexType::Vector, None, &params, true)
+                .await
+                .unwrap();
+
+            // The mode is recorded in the index metadata.
+            let dataset = Dataset::open(test_uri).await.unwrap();
+            let uuid = dataset.load_indices().await.unwrap()[0].uuid.to_string();
+            let index = dataset.open_vector_index("vector", &uuid).await.unwrap();
+            let ivf_index = index.as_any().downcast_ref::<IVFIndex>().unwrap();
+            assert!(!ivf_index.ivf.use_residual);
+            let stats: serde_json::Value =
+                serde_json::from_str(&index.statistics().unwrap()).unwrap();
+            assert_eq!(stats["use_residual"], false);
+
+            for row in [0, 10, 999] {
+                let query = vector_array.value(row);
+                let results = dataset
+                    .scan()
+                    .nearest("vector", query.as_primitive::<Float32Type>(), 5)
+                    .unwrap()
+                    .nprobs(4)
+                    .with_row_id()
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                let row_ids = results[0]["_rowid"].as_primitive::<UInt64Type>();
+                assert!(row_ids.values().contains(&(row as u64)));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prune_partitions_by_upper_bound() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+        let params = VectorIndexParams::ivf_flat(8, MetricType::L2);
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+        let index_meta = dataset.load_indices().await.unwrap()[0].clone();
+        let index = dataset
+            .open_vector_index("vector", &index_meta.uuid.to_string())
+            .await
+            .unwrap();
+        let ivf_index = index.as_any().downcast_ref::<IVFIndex>().unwrap();
+
+        let mut query = Query {
+            column: "vector".to_string(),
+            key: vector_array.value(0),
+            k: 10,
+            nprobes: 8,
+            refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: None,
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
+            metric_type: MetricType::L2,
+            use_index: true,
+        };
+        let all_partitions = ivf_index.probe_partitions(&query.key, &query).unwrap();
+        assert_eq!(all_partitions.len(), 8);
+
+        // Only the vector itself is within the range, so the far partitions are skipped.
+        query.upper_bound = Some(1e-3);
+        let partitions = ivf_index.probe_partitions(&query.key, &query).unwrap();
+        assert!(!partitions.is_empty());
+        assert!(partitions.len() < 8);
+
+        let pre_filter = Arc::new(PreFilter::new(Arc::new(dataset.clone()), index_meta, None));
+        let results = ivf_index.search(&query, pre_filter).await.unwrap();
+        assert_eq!(results.num_rows(), 1);
+        assert_eq!(results["_rowid"].as_primitive::<UInt64Type>().value(0), 0);
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_nprobes() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
+        let params = VectorIndexParams::ivf_flat(8, MetricType::L2);
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
             .await
             .unwrap();
-        let ivf_remapped = remapped.as_any().downcast_ref::<IVFIndex>().unwrap();
+        let index_meta = dataset.load_indices().await.unwrap()[0].clone();
+        let index = dataset
+            .open_vector_index("vector", &index_meta.uuid.to_string())
+            .await
+            .unwrap();
+        let ivf_index = index.as_any().downcast_ref::<IVFIndex>().unwrap();
+        assert_eq!(ivf_index.ivf.probe_gaps.len(), 100);
+
+        let mut query = Query {
+            column: "vector".to_string(),
+            key: vector_array.value(0),
+            k: 10,
+            nprobes: 1,
+            refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: Some(1.0),
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
+            metric_type: MetricType::L2,
+            use_index: true,
+        };
+        let full_recall = ivf_index.num_probes(&query.key, &query).unwrap();
+        query.target_recall = Some(0.5);
+        let half_recall = ivf_index.num_probes(&query.key, &query).unwrap();
+        assert!(half_recall >= 1);
+        assert!(half_recall <= full_recall);
+
+        // The budget bounds the number of partitions, but at least one is probed.
+        query.max_probe_rows = Some(0);
+        assert_eq!(ivf_index.num_probes(&query.key, &query).unwrap(), 1);
+        query.target_recall = None;
+        query.max_probe_rows = Some(1000);
+        assert_eq!(ivf_index.num_probes(&query.key, &query).unwrap(), 8);
+
+        let mut num_found = 0;
+        for row in (0..1000).step_by(50) {
+            let key = vector_array.value(row);
+            let key = key.as_primitive::<Float32Type>();
+            let search = |use_index: bool| {
+                let mut scanner = dataset.scan();
+                scanner
+                    .nearest("vector", key, 10)
+                    .unwrap()
+                    .use_index(use_index)
+                    .target_recall(1.0)
+                    .unwrap()
+                    .with_row_id();
+                async move {
+                    let batches = scanner
+                        .try_into_stream()
+                        .await
+                        .unwrap()
+                        .try_collect::<Vec<_>>()
+                        .await
+                        .unwrap();
+                    batches
+                        .iter()
+                        .flat_map(|batch| {
+                            batch[ROW_ID_FIELD.name()]
+                                .as_primitive::<UInt64Type>()
+                                .values()
+                                .to_vec()
+                        })
+                        .collect::<HashSet<_>>()
+                }
+            };
+            let expected = search(false).await;
+            let actual = search(true).await;
+            num_found += actual.intersection(&expected).count();
+        }
+        assert!(num_found as f32 / 200.0 >= 0.9, "found {}", num_found);
 
-        // If the ids were remapped then make sure the new row id is in the results
-        test_data
-            .check_index(
-                ivf_remapped,
-                prefilter.clone(),
-                row_ids_to_modify,
-                is_remapped,
-            )
-            .await;
-        // If the ids were removed then make sure the old row id isn't in the results
-        test_data
-            .check_index(
-                ivf_remapped,
-                prefilter.clone(),
-                row_ids_to_remove,
-                is_removed,
+        assert!(dataset
+            .scan()
+            .nearest(
+                "vector",
+                vector_array.value(0).as_primitive::<Float32Type>(),
+                10
             )
-            .await;
-        // If the ids were not remapped then make sure they still return the old id
-        test_data
-            .check_index(
-                ivf_remapped,
-                prefilter.clone(),
-                row_ids_to_remain,
-                is_not_remapped,
+            .unwrap()
+            .target_recall(0.0)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_remap_ivf_flat_and_sq_after_compaction() {
+        for params in [
+            VectorIndexParams::ivf_flat(4, MetricType::L2),
+            VectorIndexParams::ivf_sq(4, MetricType::L2),
+        ] {
+            let test_dir = tempdir().unwrap();
+            let test_uri = test_dir.path().to_str().unwrap();
+
+            let vectors = generate_random_array(1000 * DIM);
+            let schema = Arc::new(Schema::new(vec![
+                Field::new("id", DataType::Int32, false),
+                Field::new(
+                    "vector",
+                    DataType::FixedSizeList(
+                        Arc::new(Field::new("item", DataType::Float32, true)),
+                        DIM as i32,
+                    ),
+                    true,
+                ),
+            ]));
+            let vector_array =
+                FixedSizeListArray::try_new_from_values(vectors, DIM as i32).unwrap();
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![
+                    Arc::new(Int32Array::from_iter_values(0..1000)),
+                    Arc::new(vector_array.clone()),
+                ],
             )
-            .await;
+            .unwrap();
+            let batches = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+            let write_params = WriteParams {
+                max_rows_per_file: 250,
+                ..Default::default()
+            };
+            let mut dataset = Dataset::write(batches, test_uri, Some(write_params))
+                .await
+                .unwrap();
+
+            dataset
+                .create_index(&["vector"], IndexType::Vector, None, &params, false)
+                .await
+                .unwrap();
+            dataset.delete("id < 100").await.unwrap();
+
+            // Compaction merges the four fragments into one, which changes all the row ids.
+            let metrics = compact_files(&mut dataset, CompactionOptions::default(), None)
+                .await
+                .unwrap();
+            assert_eq!(metrics.fragments_removed, 4);
+            assert_eq!(metrics.fragments_added, 1);
+
+            let indices = dataset.load_indices().await.unwrap();
+            assert_eq!(indices.len(), 1);
+            assert_eq!(
+                dataset
+                    .count_unindexed_rows(&indices[0].uuid.to_string())
+                    .await
+                    .unwrap(),
+                Some(0)
+            );
+
+            for id in [50, 500, 999] {
+                let sample_query = vector_array.value(id);
+                let query = sample_query.as_primitive::<Float32Type>();
+                let results = dataset
+                    .scan()
+                    .nearest("vector", query, 5)
+                    .unwrap()
+                    .try_into_stream()
+                    .await
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                let ids = results[0]["id"].as_primitive::<Int32Type>();
+                assert_eq!(ids.len(), 5);
+                if id < 100 {
+                    assert!(!ids.values().contains(&(id as i32)));
+                } else {
+                    assert!(ids.values().contains(&(id as i32)));
+                }
+            }
+        }
     }
 
     #[tokio::test]
-    async fn test_create_ivf_pq_cosine() {
+    async fn test_create_ivf_pq_dot() {
         let test_dir = tempdir().unwrap();
         let test_uri = test_dir.path().to_str().unwrap();
 
@@ -1495,8 +4075,7 @@ mod tests {
         let codebook = Arc::new(generate_random_array(256 * DIM));
         let pq_params = PQBuildParams::with_codebook(4, 8, codebook);
 
-        let params =
-            VectorIndexParams::with_ivf_pq_params(MetricType::Cosine, ivf_params, pq_params);
+        let params = VectorIndexParams::with_ivf_pq_params(MetricType::Dot, ivf_params, pq_params);
 
         dataset
             .create_index(&["vector"], IndexType::Vector, None, &params, false)
@@ -1517,60 +4096,100 @@ mod tests {
             .unwrap();
         assert_eq!(1, results.len());
         assert_eq!(5, results[0].num_rows());
+
         for batch in results.iter() {
             let dist = &batch["_distance"];
             assert!(dist
                 .as_primitive::<Float32Type>()
                 .values()
                 .iter()
-                .all(|v| (0.0..2.0).contains(v)));
+                .all(|v| (-2.0 * DIM as f32..0.0).contains(v)));
         }
     }
 
     #[tokio::test]
-    async fn test_create_ivf_pq_dot() {
+    async fn test_ivf_pq_dot_max_inner_product() {
         let test_dir = tempdir().unwrap();
         let test_uri = test_dir.path().to_str().unwrap();
 
         let (mut dataset, vector_array) = generate_test_dataset(test_uri).await;
 
-        let centroids = generate_random_array(2 * DIM);
-        let ivf_centroids = FixedSizeListArray::try_new_from_values(centroids, DIM as i32).unwrap();
-        let ivf_params = IvfBuildParams::try_with_centroids(2, Arc::new(ivf_centroids)).unwrap();
-
-        let codebook = Arc::new(generate_random_array(256 * DIM));
-        let pq_params = PQBuildParams::with_codebook(4, 8, codebook);
-
+        let ivf_params = IvfBuildParams::new(4);
+        let pq_params = PQBuildParams::new(8, 8);
         let params = VectorIndexParams::with_ivf_pq_params(MetricType::Dot, ivf_params, pq_params);
-
         dataset
             .create_index(&["vector"], IndexType::Vector, None, &params, false)
             .await
             .unwrap();
 
-        let sample_query = vector_array.value(10);
-        let query = sample_query.as_primitive::<Float32Type>();
+        let query = vector_array.value(10);
+        let query = query.as_primitive::<Float32Type>();
+        let mut inner_products = vector_array
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let v = v.unwrap();
+                (
+                    i as u64,
+                    dot(query.values(), v.as_primitive::<Float32Type>().values()),
+                )
+            })
+            .collect::<Vec<_>>();
+        inner_products.sort_by(|a, b| b.1.total_cmp(&a.1));
+
         let results = dataset
             .scan()
-            .nearest("vector", query, 5)
+            .nearest("vector", query, 10)
             .unwrap()
+            .nprobs(4)
+            .with_row_id()
             .try_into_stream()
             .await
             .unwrap()
             .try_collect::<Vec<_>>()
             .await
             .unwrap();
-        assert_eq!(1, results.len());
-        assert_eq!(5, results[0].num_rows());
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        assert_eq!(batch.num_rows(), 10);
 
-        for batch in results.iter() {
-            let dist = &batch["_distance"];
-            assert!(dist
-                .as_primitive::<Float32Type>()
-                .values()
-                .iter()
-                .all(|v| (-2.0 * DIM as f32..0.0).contains(v)));
-        }
+        // Ordered by the largest inner product first.
+        let dists = batch["_distance"].as_primitive::<Float32Type>().values();
+        assert!(dists.windows(2).all(|w| w[0] <= w[1]));
+
+        // Most of the results are among the true top 50 by inner product.
+        let top_50 = inner_products[..50]
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<HashSet<_>>();
+        let row_ids = batch["_rowid"].as_primitive::<UInt64Type>();
+        let hits = row_ids
+            .values()
+            .iter()
+            .filter(|id| top_50.contains(id))
+            .count();
+        assert!(hits >= 8, "hits={}", hits);
+
+        // With refine, the results are exact.
+        let results = dataset
+            .scan()
+            .nearest("vector", query, 10)
+            .unwrap()
+            .nprobs(4)
+            .refine(100)
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        let row_ids = batch["_rowid"].as_primitive::<UInt64Type>();
+        let expected = inner_products[..10]
+            .iter()
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        assert_eq!(row_ids.values(), expected.as_slice());
     }
 
     #[tokio::test]
@@ -1636,4 +4255,280 @@ mod tests {
             ]))
         );
     }
+
+    #[tokio::test]
+    async fn test_ivf_flat_normalized() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+
+        // Vectors of very different lengths.
+        let values = generate_random_array(1000 * DIM);
+        let values = Float32Array::from_iter_values(
+            values
+                .values()
+                .iter()
+                .enumerate()
+                .map(|(i, v)| (v - 0.5) * (1 + (i / DIM) % 100) as f32),
+        );
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float32, true)),
+                DIM as i32,
+            ),
+            true,
+        )]));
+        let vector_array =
+            Arc::new(FixedSizeListArray::try_new_from_values(values, DIM as i32).unwrap());
+        let batch = RecordBatch::try_new(schema.clone(), vec![vector_array.clone()]).unwrap();
+        let batches = RecordBatchIterator::new(vec![batch].into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+
+        let mut params = VectorIndexParams::ivf_flat(4, MetricType::L2);
+        params.normalize(true);
+        assert!(dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .is_err());
+
+        let mut params = VectorIndexParams::ivf_flat(4, MetricType::Cosine);
+        params.normalize(true);
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let dataset = Arc::new(dataset);
+        let indices = dataset.load_indices().await.unwrap();
+        let index = dataset
+            .open_vector_index("vector", indices[0].uuid.to_string().as_str())
+            .await
+            .unwrap();
+        let ivf_idx = index.as_any().downcast_ref::<IVFIndex>().unwrap();
+        assert!(ivf_idx.normalized);
+        assert_eq!(ivf_idx.internal_metric_type(), MetricType::Dot);
+        assert_eq!(index.metric_type(), MetricType::Cosine);
+
+        let query = vector_array.value(10);
+        let query = query.as_primitive::<Float32Type>();
+        let mut cosine_distances = vector_array
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let v = v.unwrap();
+                (
+                    i as u64,
+                    cosine_distance(query.values(), v.as_primitive::<Float32Type>().values()),
+                )
+            })
+            .collect::<Vec<_>>();
+        cosine_distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        // All partitions are probed, so the results are exact.
+        let results = dataset
+            .scan()
+            .nearest("vector", query, 10)
+            .unwrap()
+            .nprobs(4)
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        let batch = concat_batches(&results[0].schema(), &results).unwrap();
+        let row_ids = batch["_rowid"].as_primitive::<UInt64Type>();
+        let dists = batch["_distance"].as_primitive::<Float32Type>();
+        assert_eq!(row_ids.len(), 10);
+        for (i, (row_id, dist)) in cosine_distances.iter().take(10).enumerate() {
+            assert_eq!(row_ids.value(i), *row_id);
+            assert!((dists.value(i) - dist).abs() < 1e-4);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_ivf_flat_and_opq_f16() {
+        const DIM: usize = 32;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Float16, true)),
+                DIM as i32,
+            ),
+            true,
+        )]));
+        let arr = generate_random_array_with_seed::<Float16Type>(1000 * DIM, [22; 32]);
+        let fsl = FixedSizeListArray::try_new_from_values(arr, DIM as i32).unwrap();
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(fsl)]).unwrap();
+
+        let mut opq_params = PQBuildParams::new(4, 8);
+        opq_params.use_opq = true;
+        let cases = [
+            VectorIndexParams::with_ivf_flat_params(MetricType::Cosine, IvfBuildParams::new(2)),
+            VectorIndexParams::with_ivf_pq_params(
+                MetricType::L2,
+                IvfBuildParams::new(2),
+                opq_params,
+            ),
+        ];
+        for params in cases {
+            let test_dir = tempdir().unwrap();
+            let test_uri = test_dir.path().to_str().unwrap();
+            let batches =
+                RecordBatchIterator::new(vec![batch.clone()].into_iter().map(Ok), schema.clone());
+            let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+            dataset
+                .create_index(&["vector"], IndexType::Vector, None, &params, false)
+                .await
+                .unwrap();
+            let name = dataset.load_indices().await.unwrap()[0].name.clone();
+            assert!(dataset.index_statistics(&name).await.unwrap().is_some());
+
+            let results = dataset
+                .scan()
+                .nearest(
+                    "vector",
+                    &Float32Array::from_iter_values(repeat(0.5).take(DIM)),
+                    5,
+                )
+                .unwrap()
+                .refine(2)
+                .try_into_stream()
+                .await
+                .unwrap()
+                .try_collect::<Vec<_>>()
+                .await
+                .unwrap();
+            assert_eq!(results[0].num_rows(), 5);
+            let dists = results[0][DIST_COL].as_primitive::<Float32Type>();
+            assert!(dists.values().iter().all(|&d| d >= 0.0));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_create_ivf_pq_int8() {
+        const DIM: usize = 32;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::Int8, true)),
+                DIM as i32,
+            ),
+            true,
+        )]));
+        let mut rng = SmallRng::seed_from_u64(26);
+        let values = Int8Array::from_iter_values((0..1000 * DIM).map(|_| rng.gen::<i8>()));
+        let fsl = FixedSizeListArray::try_new_from_values(values, DIM as i32).unwrap();
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(fsl.clone())]).unwrap();
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let batches =
+            RecordBatchIterator::new(vec![batch.clone()].into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+
+        let params =
+            VectorIndexParams::with_ivf_flat_params(MetricType::L2, IvfBuildParams::new(2));
+        assert!(dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .is_err());
+
+        let params = VectorIndexParams::with_ivf_pq_params(
+            MetricType::L2,
+            IvfBuildParams::new(2),
+            PQBuildParams::new(4, 8),
+        );
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+
+        let expected = fsl.value(42);
+        let query = Float32Array::from_iter_values(
+            expected
+                .as_primitive::<Int8Type>()
+                .values()
+                .iter()
+                .map(|&v| v as f32),
+        );
+        let results = dataset
+            .scan()
+            .nearest("vector", &query, 5)
+            .unwrap()
+            .refine(10)
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results[0].num_rows(), 5);
+        let dists = results[0][DIST_COL].as_primitive::<Float32Type>();
+        assert_eq!(dists.value(0), 0.0);
+        assert_eq!(
+            &results[0]["vector"].as_fixed_size_list().value(0),
+            &expected
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_null_vectors() {
+        const DIM: usize = 16;
+        const NUM_ROWS: usize = 1000;
+        let field = Arc::new(Field::new("item", DataType::Float32, true));
+        let schema = Arc::new(Schema::new(vec![
+            ROW_ID_FIELD.clone(),
+            Field::new(
+                "vector",
+                DataType::FixedSizeList(field.clone(), DIM as i32),
+                true,
+            ),
+        ]));
+        // Every tenth vector is null.
+        let nulls = arrow_buffer::NullBuffer::from_iter((0..NUM_ROWS).map(|i| i % 10 != 0));
+        let values = generate_random_array(NUM_ROWS * DIM);
+        let fsl = FixedSizeListArray::try_new(field, DIM as i32, Arc::new(values), Some(nulls));
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![
+                Arc::new(UInt64Array::from_iter_values(0..NUM_ROWS as u64)),
+                Arc::new(fsl.unwrap()),
+            ],
+        )
+        .unwrap();
+
+        // Null vectors are neither partitioned nor quantized.
+        let stream =
+            RecordBatchStreamAdapter::new(schema.clone(), stream::iter(vec![Ok(batch.clone())]));
+        let batches = apply_transforms(stream, "vector", vec![])
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(batches[0].num_rows(), 900);
+        assert_eq!(batches[0]["vector"].null_count(), 0);
+        let row_ids = batches[0][ROW_ID_FIELD.name()].as_primitive::<UInt64Type>();
+        assert!(row_ids.values().iter().all(|row_id| row_id % 10 != 0));
+        assert_eq!(to_vectors(&batch["vector"]).unwrap().len(), 900);
+
+        // The vector index is not used to filter the vector column.
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let batch = batch.drop_column(ROW_ID_FIELD.name()).unwrap();
+        let batches = RecordBatchIterator::new(vec![Ok(batch.clone())], batch.schema());
+        let mut dataset = Dataset::write(batches, test_uri, None).await.unwrap();
+        let params = VectorIndexParams::with_ivf_pq_params(
+            MetricType::L2,
+            IvfBuildParams::new(2),
+            PQBuildParams::new(4, 8),
+        );
+        dataset
+            .create_index(&["vector"], IndexType::Vector, None, &params, false)
+            .await
+            .unwrap();
+        let mut scanner = dataset.scan();
+        scanner.filter("vector IS NOT NULL").unwrap();
+        assert_eq!(scanner.count_rows().await.unwrap(), NUM_ROWS as u64);
+    }
 }
@@ -18,22 +18,88 @@ use std::{collections::BTreeMap, ops::Range};
 use arrow_array::types::UInt32Type;
 use arrow_array::{cast::AsArray, UInt32Array};
 use arrow_schema::{DataType, Field, Schema};
-use futures::{stream::repeat_with, StreamExt};
-use lance_arrow::RecordBatchExt;
+use futures::{future::try_join_all, stream::repeat_with, StreamExt};
+use lance_arrow::{RecordBatchExt, SchemaExt};
 use lance_core::{io::Writer, ROW_ID, ROW_ID_FIELD};
+use lance_index::progress::IndexBuildProgress;
+use lance_index::vector::ivf::IvfBuildParams;
 use lance_index::vector::pq::ProductQuantizer;
 use lance_index::vector::{PART_ID_COLUMN, PQ_CODE_COLUMN};
 use lance_linalg::distance::MetricType;
+use log::info;
 use snafu::{location, Location};
 use tracing::instrument;
 
 use crate::index::vector::ivf::{
     io::write_index_partitions,
-    shuffler::{Shuffler, ShufflerBuilder},
+    shuffler::{ShuffleMode, ShuffleSpillLocation, Shuffler, ShufflerBuilder},
     Ivf,
 };
 use crate::{io::RecordBatchStream, Error, Result};
 
+/// Shuffle with an external sort from this many partitions. With fewer partitions,
+/// the rows of each partition are buffered separately.
+const SORT_SHUFFLE_MIN_PARTITIONS: usize = 4096;
+
+/// Bound the memory used by thousands of partitions that stay below the flush threshold.
+const DEFAULT_SHUFFLE_MEMORY_BUDGET: usize = 512 * 1024 * 1024;
+
+/// Limits of the resources used by a shuffle.
+#[derive(Debug, Clone, Copy)]
+pub struct ShuffleLimits {
+    /// Number of batches assigned to partitions concurrently.
+    pub num_threads: usize,
+
+    /// Number of shards of the shuffle, whose partitions are written concurrently.
+    pub num_shards: usize,
+
+    /// Max bytes of rows buffered in memory before they are spilled.
+    pub memory_budget: usize,
+}
+
+impl Default for ShuffleLimits {
+    fn default() -> Self {
+        Self {
+            num_threads: num_cpus::get(),
+            num_shards: num_cpus::get(),
+            memory_budget: DEFAULT_SHUFFLE_MEMORY_BUDGET,
+        }
+    }
+}
+
+impl ShuffleLimits {
+    /// The limits set by the resource limits of `params`.
+    pub fn try_from_params(params: &IvfBuildParams) -> Result<Self> {
+        let default = Self::default();
+        let limits = Self {
+            num_threads: params.num_threads.unwrap_or(default.num_threads),
+            num_shards: params.num_parallel_partitions.unwrap_or(default.num_shards),
+            memory_budget: params
+                .shuffle_memory_budget
+                .unwrap_or(default.memory_budget),
+        };
+        if limits.num_threads == 0 || limits.num_shards == 0 {
+            return Err(Error::Index {
+                message: format!(
+                    "the number of threads and of parallel partitions must be positive, got {} and {}",
+                    limits.num_threads, limits.num_shards
+                ),
+                location: location!(),
+            });
+        }
+        Ok(limits)
+    }
+}
+
+/// The [ShuffleMode] to shuffle the rows into `num_partitions` partitions.
+pub fn shuffle_mode(num_partitions: usize) -> ShuffleMode {
+    if num_partitions >= SORT_SHUFFLE_MIN_PARTITIONS {
+        ShuffleMode::Sort
+    } else {
+        ShuffleMode::Buffered
+    }
+}
+
 /// Disk-based shuffle a stream of [RecordBatch] into each IVF partition.
 /// Sub-quantizer will be applied if provided.
 ///
@@ -41,12 +107,17 @@ use crate::{io::RecordBatchStream, Error, Result};
 /// ----------
 ///   *data*: input data stream.
 ///   *ivf*: IVF model.
+///   *spill*: where to spill the shuffle buffers, a local temporary directory if `None`.
+///   *mode*: how the shuffle buffers the rows, see [shuffle_mode].
+///   *limits*: the threads and the memory used by the shuffle.
+///   *progress*: reports the rows shuffled and the buffers spilled.
 ///
 /// Returns
 /// -------
 ///   Shuffler: a shuffler that stored the shuffled data.
 ///
 /// TODO: move this to `lance-index` crate.
+#[allow(clippy::too_many_arguments)]
 pub async fn shuffle_dataset(
     data: impl RecordBatchStream + Unpin,
     column: &str,
@@ -54,7 +125,84 @@ pub async fn shuffle_dataset(
     // TODO: Once the transformer can generate schema automatically,
     // we can remove `num_sub_vectors`.
     num_sub_vectors: usize,
+    spill: Option<&ShuffleSpillLocation>,
+    mode: ShuffleMode,
+    limits: ShuffleLimits,
+    progress: Arc<dyn IndexBuildProgress>,
+) -> Result<Shuffler> {
+    // TODO: dynamically detect schema from the transforms.
+    let schema = Schema::new(vec![
+        ROW_ID_FIELD.clone(),
+        Field::new(PART_ID_COLUMN, DataType::UInt32, false),
+        Field::new(
+            PQ_CODE_COLUMN,
+            DataType::FixedSizeList(
+                Arc::new(Field::new("item", DataType::UInt8, true)),
+                num_sub_vectors as i32,
+            ),
+            false,
+        ),
+    ]);
+    shuffle_with_schema(data, column, ivf, schema, spill, mode, limits, progress).await
+}
+
+/// Disk-based shuffle a stream of [RecordBatch] into each IVF partition, keeping the
+/// original vectors.
+///
+/// The shuffled data has the same columns as the input, plus the partition ID column.
+pub async fn shuffle_vectors(
+    data: impl RecordBatchStream + Unpin,
+    column: &str,
+    ivf: Arc<dyn lance_index::vector::ivf::Ivf>,
+    mode: ShuffleMode,
+    limits: ShuffleLimits,
+    progress: Arc<dyn IndexBuildProgress>,
 ) -> Result<Shuffler> {
+    let schema = data
+        .schema()
+        .try_with_column(Field::new(PART_ID_COLUMN, DataType::UInt32, false))?
+        .with_metadata(Default::default());
+    shuffle_with_schema(data, column, ivf, schema, None, mode, limits, progress).await
+}
+
+/// Disk-based shuffle a stream of [RecordBatch] into each IVF partition.
+///
+/// `schema` is the schema of the batches after the transforms of `ivf`. The shuffle
+/// buffers are spilled to `spill`, or to a local temporary directory if `None`.
+/// The rows are buffered as `mode` within `limits`, and the rows shuffled and the
+/// buffers spilled are reported to `progress`.
+///
+/// If `spill` is resumable and already holds a finished shuffle, it is reopened
+/// instead of shuffling `data` again.
+#[allow(clippy::too_many_arguments)]
+pub async fn shuffle_with_schema(
+    data: impl RecordBatchStream + Unpin,
+    column: &str,
+    ivf: Arc<dyn lance_index::vector::ivf::Ivf>,
+    schema: Schema,
+    spill: Option<&ShuffleSpillLocation>,
+    mode: ShuffleMode,
+    limits: ShuffleLimits,
+    progress: Arc<dyn IndexBuildProgress>,
+) -> Result<Shuffler> {
+    if let Some(spill) = spill.filter(|s| s.resumable) {
+        if let Some(shuffler) = Shuffler::try_open(spill).await? {
+            if shuffler.schema() != &schema {
+                return Err(Error::Index {
+                    message: format!(
+                        "the shuffle in {} has schema {:?}, expected {:?}",
+                        spill.base_path,
+                        shuffler.schema(),
+                        schema
+                    ),
+                    location: location!(),
+                });
+            }
+            info!("Resumed the shuffle in {}", spill.base_path);
+            return Ok(shuffler);
+        }
+    }
+
     let mut stream = data
         .zip(repeat_with(|| ivf.clone()))
         .map(|(b, ivf)| async move {
@@ -62,7 +210,7 @@ pub async fn shuffle_dataset(
             // TODO: Make CPU bound to a future.
             ivf.partition_transform(&batch, column).await
         })
-        .buffer_unordered(num_cpus::get() * 2)
+        .buffer_unordered(limits.num_threads * 2)
         .map(|batch| async move {
             let batch = batch?;
             // Collecting partition ID and row ID.
@@ -90,41 +238,40 @@ pub async fn shuffle_dataset(
                 location: location!(),
             })
         })
-        .buffer_unordered(num_cpus::get())
+        .buffer_unordered(limits.num_threads)
         .boxed();
 
-    // TODO: dynamically detect schema from the transforms.
-    let schema = Schema::new(vec![
-        ROW_ID_FIELD.clone(),
-        Field::new(PART_ID_COLUMN, DataType::UInt32, false),
-        Field::new(
-            PQ_CODE_COLUMN,
-            DataType::FixedSizeList(
-                Arc::new(Field::new("item", DataType::UInt8, true)),
-                num_sub_vectors as i32,
-            ),
-            false,
-        ),
-    ]);
     const FLUSH_THRESHOLD: usize = 40 * 1024;
 
-    let mut shuffler_builder = ShufflerBuilder::try_new(&schema, FLUSH_THRESHOLD).await?;
+    let mut shuffler_builder =
+        ShufflerBuilder::try_new(&schema, FLUSH_THRESHOLD, limits.num_shards, spill, None)
+            .await?
+            .with_memory_budget(limits.memory_budget)
+            .with_mode(mode)
+            .with_progress(progress);
     while let Some(result) = stream.next().await {
         let batches = result??;
         if batches.is_empty() {
             continue;
         }
-        for (part_id, batch) in batches {
-            shuffler_builder.insert(part_id, batch).await?;
-        }
+        // Partitions in different shards are written concurrently.
+        try_join_all(
+            batches
+                .into_iter()
+                .map(|(part_id, batch)| shuffler_builder.insert(part_id, batch)),
+        )
+        .await?;
     }
     shuffler_builder.finish().await
 }
 
 /// Build specific partitions of IVF index.
 ///
-///
-#[instrument(level = "debug", skip(writer, data, ivf, pq))]
+/// The shuffle buffers are spilled to `spill`, or to a local temporary directory if
+/// `None`. The shuffle is bounded by `limits`. The progress of shuffling and writing
+/// the partitions is reported to `progress`.
+#[allow(clippy::too_many_arguments)]
+#[instrument(level = "debug", skip(writer, data, ivf, pq, spill, progress))]
 pub(super) async fn build_partitions(
     writer: &mut dyn Writer,
     data: impl RecordBatchStream + Unpin,
@@ -133,6 +280,9 @@ pub(super) async fn build_partitions(
     pq: Arc<dyn ProductQuantizer>,
     metric_type: MetricType,
     part_range: Range<u32>,
+    spill: Option<&ShuffleSpillLocation>,
+    limits: ShuffleLimits,
+    progress: Arc<dyn IndexBuildProgress>,
 ) -> Result<()> {
     let schema = data.schema();
     if schema.column_with_name(column).is_none() {
@@ -148,16 +298,28 @@ pub(super) async fn build_partitions(
         });
     }
 
+    let mode = shuffle_mode(part_range.len());
     let ivf_model = lance_index::vector::ivf::new_ivf_with_pq(
         ivf.centroids.values(),
         ivf.centroids.value_length() as usize,
         metric_type,
         column,
         pq.clone(),
+        ivf.use_residual,
         Some(part_range),
     )?;
-    let shuffler = shuffle_dataset(data, column, ivf_model, pq.num_sub_vectors()).await?;
-    write_index_partitions(writer, ivf, &shuffler, None).await?;
+    let shuffler = shuffle_dataset(
+        data,
+        column,
+        ivf_model,
+        pq.num_sub_vectors(),
+        spill,
+        mode,
+        limits,
+        progress.clone(),
+    )
+    .await?;
+    write_index_partitions(writer, ivf, Some(&shuffler), &[], progress.as_ref()).await?;
 
-    Ok(())
+    shuffler.remove().await
 }
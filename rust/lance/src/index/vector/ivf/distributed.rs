@@ -0,0 +1,562 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Distributed IVF_PQ index build.
+//!
+//! 1. The coordinator trains the IVF and PQ models with [`DistributedIvfPqBuild::train`],
+//!    which writes them to the directory of the new index.
+//! 2. Each worker builds a range of the IVF partitions into a partial index file, with
+//!    [`DistributedIvfPqBuild::build_partitions`]. A worker only needs the
+//!    [`DistributedIvfPqBuild`] returned by the coordinator, and the same version of
+//!    the dataset.
+//! 3. Once all the partitions are built, the coordinator stitches the partial files
+//!    into the final index and commits it, with [`DistributedIvfPqBuild::commit`].
+
+use std::ops::Range;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use lance_core::io::{Reader, RecordBatchStream, RecordBatchStreamAdapter, Writer};
+use lance_index::pb::index::Implementation;
+use lance_index::progress::NoopIndexBuildProgress;
+use lance_linalg::distance::MetricType;
+use object_store::path::Path;
+use snafu::{location, Location};
+use uuid::Uuid;
+
+use super::{
+    apply_transforms, builder, io::write_index_partitions, sanity_check, train_ivf_pq_model,
+    write_ivf_pq_metadata, IVFIndex, Ivf, IvfPqModel, ShuffleSpillLocation,
+};
+use crate::dataset::Dataset;
+use crate::index::vector::{
+    normalize::L2Normalizer, opq::OptimizedProductQuantizer, pq::PQIndex, StageParams, Transformer,
+    VectorIndexParams,
+};
+use crate::index::{commit_new_index, indices_to_replace, open_index_proto, pb, INDEX_FILE_NAME};
+use crate::{Error, Result};
+
+/// The file with the trained models, written by the coordinator.
+const MODEL_FILE_NAME: &str = "model.idx";
+
+/// The prefix of the partial index files written by the workers.
+const PARTIAL_FILE_PREFIX: &str = "partitions-";
+
+/// A distributed build of an IVF_PQ index.
+///
+/// It is created by the coordinator, and passed to the workers to identify the build.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DistributedIvfPqBuild {
+    /// The vector column to index.
+    pub column: String,
+
+    /// The name of the index.
+    pub index_name: String,
+
+    /// The UUID of the index being built.
+    pub uuid: String,
+
+    /// The number of IVF partitions.
+    pub num_partitions: u32,
+}
+
+impl DistributedIvfPqBuild {
+    /// Train the IVF and PQ models on the coordinator.
+    ///
+    /// The models are written to the directory of the new index, where the workers
+    /// read them from. The index is named `{column}_idx` if `name` is `None`.
+    pub async fn train(
+        dataset: &Dataset,
+        column: &str,
+        name: Option<String>,
+        params: &VectorIndexParams,
+    ) -> Result<Self> {
+        let [.., StageParams::Ivf(ivf_params), StageParams::PQ(pq_params)] =
+            params.stages.as_slice()
+        else {
+            return Err(Error::Index {
+                message: format!(
+                    "Distributed index build only supports IVF_PQ, got stages: {:?}",
+                    params.stages
+                ),
+                location: location!(),
+            });
+        };
+        sanity_check(dataset, column)?;
+
+        let model = train_ivf_pq_model(
+            dataset,
+            column,
+            params.metric_type,
+            ivf_params,
+            pq_params,
+            None,
+        )
+        .await?;
+        let build = Self {
+            column: column.to_string(),
+            index_name: name.unwrap_or(format!("{column}_idx")),
+            uuid: Uuid::new_v4().to_string(),
+            num_partitions: model.ivf.num_partitions() as u32,
+        };
+
+        // The model file is an IVF_PQ index whose partitions are all empty.
+        let mut ivf = model.ivf;
+        let mut writer = dataset
+            .object_store()
+            .create(&build.model_path(dataset))
+            .await?;
+        for _ in 0..build.num_partitions {
+            ivf.add_partition(writer.tell().await?, 0);
+        }
+        write_ivf_pq_metadata(
+            &mut writer,
+            dataset,
+            column,
+            &build.index_name,
+            &model.transforms,
+            ivf,
+            model.pq,
+            params.metric_type,
+        )
+        .await?;
+
+        Ok(build)
+    }
+
+    /// Split the IVF partitions into `num_workers` ranges of about the same size.
+    pub fn partition_ranges(&self, num_workers: usize) -> Vec<Range<u32>> {
+        let num_workers = num_workers.clamp(1, std::cmp::max(self.num_partitions as usize, 1));
+        (0..num_workers)
+            .map(|i| {
+                let start = self.num_partitions as usize * i / num_workers;
+                let end = self.num_partitions as usize * (i + 1) / num_workers;
+                start as u32..end as u32
+            })
+            .collect()
+    }
+
+    /// Build the IVF partitions in `partitions` on a worker.
+    ///
+    /// The whole column is scanned, and the vectors assigned to `partitions` are
+    /// written to a partial index file in the directory of the new index. The shuffle
+    /// buffers are spilled to `spill`, or to a local temporary directory if `None`.
+    pub async fn build_partitions(
+        &self,
+        dataset: &Dataset,
+        partitions: Range<u32>,
+        spill: Option<&ShuffleSpillLocation>,
+    ) -> Result<()> {
+        if partitions.is_empty() || partitions.end > self.num_partitions {
+            return Err(Error::Index {
+                message: format!(
+                    "Invalid partition range {:?}, the index has {} partitions",
+                    partitions, self.num_partitions
+                ),
+                location: location!(),
+            });
+        }
+        let (model, metric_type) = self.load_model(dataset).await?;
+
+        let mut scanner = dataset.scan();
+        scanner.batch_readahead(num_cpus::get() * 2);
+        scanner.project(&[self.column.as_str()])?;
+        scanner.with_index_row_id();
+        let stream = scanner.try_into_stream().await?;
+        let stream = RecordBatchStreamAdapter::new(stream.schema(), stream.boxed());
+        let stream = apply_transforms(stream, &self.column, model.transforms.clone());
+
+        let path = self.index_dir(dataset).child(format!(
+            "{PARTIAL_FILE_PREFIX}{}-{}.idx",
+            partitions.start, partitions.end
+        ));
+        let mut writer = dataset.object_store().create(&path).await?;
+        let mut ivf = model.ivf;
+        builder::build_partitions(
+            &mut writer,
+            stream,
+            &self.column,
+            &mut ivf,
+            model.pq.clone(),
+            model.ivf_metric_type,
+            partitions,
+            spill,
+            builder::ShuffleLimits::default(),
+            Arc::new(NoopIndexBuildProgress::new()),
+        )
+        .await?;
+        write_ivf_pq_metadata(
+            &mut writer,
+            dataset,
+            &self.column,
+            &self.index_name,
+            &model.transforms,
+            ivf,
+            model.pq,
+            metric_type,
+        )
+        .await
+    }
+
+    /// Stitch the partial index files of the workers into the final index, and commit
+    /// it to the dataset on the coordinator.
+    ///
+    /// Fails if the partial files do not cover every partition exactly once. The
+    /// model and the partial files are removed once the index is committed.
+    pub async fn commit(&self, dataset: &mut Dataset, replace: bool) -> Result<()> {
+        let Some(field) = dataset.schema().field(&self.column) else {
+            return Err(Error::Index {
+                message: format!("CreateIndex: column '{}' does not exist", self.column),
+                location: location!(),
+            });
+        };
+        let field_id = field.id;
+        let removed_indices =
+            indices_to_replace(dataset, &self.index_name, field_id, replace).await?;
+
+        let (model, metric_type) = self.load_model(dataset).await?;
+        let partial_files = self.partial_files(dataset).await?;
+
+        let object_store = dataset.object_store();
+        let index_dir = self.index_dir(dataset);
+        let mut partials = Vec::with_capacity(partial_files.len());
+        for file_name in partial_files.iter() {
+            let reader: Arc<dyn Reader> = object_store
+                .open(&index_dir.child(file_name.as_str()))
+                .await?
+                .into();
+            let ivf = read_ivf_pq_proto(dataset, reader.as_ref(), self)
+                .await?
+                .stages
+                .iter()
+                .find_map(|stage| match stage.stage.as_ref() {
+                    Some(pb::vector_index_stage::Stage::Ivf(ivf)) => Some(Ivf::try_from(ivf)),
+                    _ => None,
+                })
+                .ok_or_else(|| Error::Index {
+                    message: format!("Partial index file {file_name} has no IVF stage"),
+                    location: location!(),
+                })??;
+            let sub_index = Arc::new(PQIndex::new(model.pq.clone(), model.ivf_metric_type));
+            partials.push(IVFIndex::try_new(
+                dataset.session.clone(),
+                &format!("{}/{}", self.uuid, file_name),
+                ivf,
+                reader,
+                sub_index,
+                metric_type,
+                model.ivf_metric_type != metric_type,
+            )?);
+        }
+
+        let path = index_dir.child(INDEX_FILE_NAME);
+        let mut writer = object_store.create(&path).await?;
+        let mut ivf = model.ivf;
+        let partials = partials.iter().collect::<Vec<_>>();
+        write_index_partitions(
+            &mut writer,
+            &mut ivf,
+            None,
+            &partials,
+            &NoopIndexBuildProgress::new(),
+        )
+        .await?;
+        write_ivf_pq_metadata(
+            &mut writer,
+            dataset,
+            &self.column,
+            &self.index_name,
+            &model.transforms,
+            ivf,
+            model.pq,
+            metric_type,
+        )
+        .await?;
+
+        let index_id = Uuid::parse_str(&self.uuid).map_err(|e| Error::Index {
+            message: format!("Invalid index UUID {}: {}", self.uuid, e),
+            location: location!(),
+        })?;
+        commit_new_index(
+            dataset,
+            index_id,
+            self.index_name.clone(),
+            field_id,
+            removed_indices,
+        )
+        .await?;
+
+        let object_store = dataset.object_store();
+        object_store.delete(&self.model_path(dataset)).await?;
+        for file_name in partial_files.iter() {
+            object_store
+                .delete(&index_dir.child(file_name.as_str()))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn index_dir(&self, dataset: &Dataset) -> Path {
+        dataset.indices_dir().child(self.uuid.as_str())
+    }
+
+    fn model_path(&self, dataset: &Dataset) -> Path {
+        self.index_dir(dataset).child(MODEL_FILE_NAME)
+    }
+
+    /// Load the models trained by the coordinator, and the metric type of the index.
+    ///
+    /// The IVF model has no partitions, so that they can be added as they are written.
+    async fn load_model(&self, dataset: &Dataset) -> Result<(IvfPqModel, MetricType)> {
+        let reader = dataset
+            .object_store()
+            .open(&self.model_path(dataset))
+            .await?;
+        let vector_index = read_ivf_pq_proto(dataset, reader.as_ref(), self).await?;
+
+        let metric_type: MetricType =
+            pb::VectorMetricType::try_from(vector_index.metric_type)?.into();
+        let mut transforms: Vec<Arc<dyn Transformer>> = vec![];
+        let mut normalized = false;
+        let mut ivf = None;
+        let mut pq_proto = None;
+        for stage in vector_index.stages.iter() {
+            match stage.stage.as_ref() {
+                Some(pb::vector_index_stage::Stage::Transform(tf)) => match tf.r#type() {
+                    pb::TransformType::Opq => {
+                        let shape = tf.shape.iter().map(|s| *s as usize).collect::<Vec<_>>();
+                        let opq = OptimizedProductQuantizer::load(
+                            reader.as_ref(),
+                            tf.position as usize,
+                            &shape,
+                        )
+                        .await?;
+                        transforms.push(Arc::new(opq));
+                    }
+                    pb::TransformType::Normalize => {
+                        normalized = true;
+                        transforms.push(Arc::new(L2Normalizer::default()));
+                    }
+                },
+                Some(pb::vector_index_stage::Stage::Ivf(ivf_pb)) => {
+                    ivf = Some(Ivf::try_from(ivf_pb)?);
+                }
+                Some(pb::vector_index_stage::Stage::Pq(pq_pb)) => pq_proto = Some(pq_pb),
+                _ => {}
+            }
+        }
+        let (Some(mut ivf), Some(pq_proto)) = (ivf, pq_proto) else {
+            return Err(Error::Index {
+                message: format!("Invalid IVF_PQ model stages: {:?}", vector_index.stages),
+                location: location!(),
+            });
+        };
+        ivf.offsets.clear();
+        ivf.lengths.clear();
+
+        // Normalized vectors are partitioned and quantized with L2.
+        let ivf_metric_type = if normalized {
+            MetricType::L2
+        } else {
+            metric_type
+        };
+        let pq = lance_index::vector::pq::builder::from_proto(pq_proto, ivf_metric_type)?;
+        Ok((
+            IvfPqModel {
+                ivf,
+                pq,
+                transforms,
+                ivf_metric_type,
+            },
+            metric_type,
+        ))
+    }
+
+    /// The partial index files written by the workers.
+    ///
+    /// Fails unless their partition ranges cover every partition exactly once.
+    async fn partial_files(&self, dataset: &Dataset) -> Result<Vec<String>> {
+        let mut ranges = dataset
+            .object_store()
+            .read_dir(self.index_dir(dataset))
+            .await?
+            .into_iter()
+            .filter_map(|file_name| {
+                let range = file_name
+                    .strip_prefix(PARTIAL_FILE_PREFIX)?
+                    .strip_suffix(".idx")?
+                    .split_once('-')?;
+                let range = range.0.parse::<u32>().ok()?..range.1.parse::<u32>().ok()?;
+                Some((range, file_name))
+            })
+            .collect::<Vec<_>>();
+        ranges.sort_by_key(|(range, _)| range.start);
+
+        let mut next = 0;
+        for (range, _) in ranges.iter() {
+            if range.start != next {
+                return Err(Error::Index {
+                    message: format!(
+                        "Distributed index build {}: partitions {}..{} are {}",
+                        self.uuid,
+                        next.min(range.start),
+                        next.max(range.start),
+                        if range.start > next {
+                            "not built yet"
+                        } else {
+                            "built more than once"
+                        }
+                    ),
+                    location: location!(),
+                });
+            }
+            next = range.end;
+        }
+        if next != self.num_partitions {
+            return Err(Error::Index {
+                message: format!(
+                    "Distributed index build {}: partitions {}..{} are not built yet",
+                    self.uuid, next, self.num_partitions
+                ),
+                location: location!(),
+            });
+        }
+        Ok(ranges.into_iter().map(|(_, file_name)| file_name).collect())
+    }
+}
+
+/// Read the vector index metadata of a file written by a distributed build.
+///
+/// Fails if the file was written for another version of the dataset.
+async fn read_ivf_pq_proto(
+    dataset: &Dataset,
+    reader: &dyn Reader,
+    build: &DistributedIvfPqBuild,
+) -> Result<pb::VectorIndex> {
+    let proto = open_index_proto(dataset, reader).await?;
+    if proto.dataset_version != dataset.version().version {
+        return Err(Error::Index {
+            message: format!(
+                "Distributed index build {} is for dataset version {}, got version {}",
+                build.uuid,
+                proto.dataset_version,
+                dataset.version().version
+            ),
+            location: location!(),
+        });
+    }
+    match proto.implementation {
+        Some(Implementation::VectorIndex(vector_index)) => Ok(vector_index),
+        None => Err(Error::Internal {
+            message: "Index proto was missing implementation field".into(),
+            location: location!(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{FixedSizeListArray, RecordBatch, RecordBatchIterator};
+    use arrow_schema::{DataType, Field, Schema};
+    use futures::TryStreamExt;
+    use lance_arrow::*;
+    use lance_testing::datagen::generate_random_array;
+    use tempfile::tempdir;
+
+    use crate::index::DatasetIndexExt;
+
+    #[tokio::test]
+    async fn test_distributed_build() {
+        const DIM: i32 = 16;
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "v",
+            DataType::FixedSizeList(Arc::new(Field::new("item", DataType::Float32, true)), DIM),
+            true,
+        )]));
+        let data = generate_random_array(1000 * DIM as usize);
+        let batches = vec![RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(
+                FixedSizeListArray::try_new_from_values(data.clone(), DIM).unwrap(),
+            )],
+        )
+        .unwrap()];
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(batches.into_iter().map(Ok), schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        let params = VectorIndexParams::ivf_pq(4, 8, 2, false, MetricType::L2, 2);
+        let build = DistributedIvfPqBuild::train(&dataset, "v", None, &params)
+            .await
+            .unwrap();
+        assert_eq!(build.index_name, "v_idx");
+        let ranges = build.partition_ranges(3);
+        assert_eq!(ranges, vec![0..1, 1..2, 2..4]);
+
+        // Each worker opens the dataset itself.
+        let worker_dataset = Dataset::open(test_uri).await.unwrap();
+        // Workers can spill the shuffle buffers to an object store.
+        let spill = ShuffleSpillLocation {
+            object_store: Arc::new(lance_core::io::object_store::ObjectStore::memory()),
+            base_path: Path::from("spill"),
+            resumable: false,
+        };
+        build
+            .build_partitions(&worker_dataset, ranges[0].clone(), None)
+            .await
+            .unwrap();
+        build
+            .build_partitions(&worker_dataset, ranges[2].clone(), Some(&spill))
+            .await
+            .unwrap();
+        assert!(build.commit(&mut dataset, false).await.is_err());
+        build
+            .build_partitions(&worker_dataset, ranges[1].clone(), None)
+            .await
+            .unwrap();
+        build.commit(&mut dataset, false).await.unwrap();
+
+        let indices = dataset.load_indices().await.unwrap();
+        assert_eq!(indices.len(), 1);
+        assert_eq!(indices[0].uuid.to_string(), build.uuid);
+        let files = dataset
+            .object_store()
+            .read_dir(build.index_dir(&dataset))
+            .await
+            .unwrap();
+        assert_eq!(files, vec![INDEX_FILE_NAME.to_string()]);
+
+        let codes = dataset.pq_codes("v_idx").await.unwrap();
+        assert_eq!(codes.len(), 4);
+        assert_eq!(codes.iter().map(|b| b.num_rows()).sum::<usize>(), 1000);
+
+        let q = data.slice(0, DIM as usize);
+        let results = dataset
+            .scan()
+            .nearest("v", &q, 10)
+            .unwrap()
+            .nprobs(4)
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.iter().map(|b| b.num_rows()).sum::<usize>(), 10);
+    }
+}
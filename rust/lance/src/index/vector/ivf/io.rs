@@ -18,7 +18,7 @@ use arrow_array::{Array, FixedSizeListArray};
 use futures::StreamExt;
 use lance_arrow::*;
 use lance_core::io::Writer;
-use lance_index::vector::PQ_CODE_COLUMN;
+use lance_index::{progress::IndexBuildProgress, vector::PQ_CODE_COLUMN};
 
 use super::{shuffler::Shuffler, IVFIndex, Ivf};
 use crate::dataset::ROW_ID;
@@ -28,18 +28,21 @@ use crate::Result;
 
 /// Write each partition of IVF_PQ index to the index file.
 ///
-/// Partitioned index data is already sorted in the [Shuffler].
+/// Partitioned index data is already sorted in the [Shuffler], if any. The partitions
+/// of the `existing_indices` are written before the new data. Each written partition
+/// is reported to `progress`.
 pub(super) async fn write_index_partitions(
     writer: &mut dyn Writer,
     ivf: &mut Ivf,
-    shuffler: &Shuffler,
-    existing_partitions: Option<&IVFIndex>,
+    shuffler: Option<&Shuffler>,
+    existing_indices: &[&IVFIndex],
+    progress: &dyn IndexBuildProgress,
 ) -> Result<()> {
     for part_id in 0..ivf.num_partitions() as u32 {
         let mut pq_array = Vec::<Arc<dyn Array>>::new();
         let mut row_id_array = Vec::<Arc<dyn Array>>::new();
 
-        if let Some(existing_idx) = existing_partitions.as_ref() {
+        for existing_idx in existing_indices {
             let part = existing_idx.load_partition(part_id as usize, true).await?;
             let pq_idx = part.as_any().downcast_ref::<PQIndex>().unwrap();
             if pq_idx.code.is_some() {
@@ -53,7 +56,11 @@ pub(super) async fn write_index_partitions(
             }
         }
 
-        if let Some(mut stream) = shuffler.key_iter(part_id).await? {
+        let stream = match shuffler {
+            Some(shuffler) => shuffler.key_iter(part_id).await?,
+            None => None,
+        };
+        if let Some(mut stream) = stream {
             while let Some(batch) = stream.next().await {
                 let batch = batch?;
                 let arr = batch.column_by_name(PQ_CODE_COLUMN).unwrap();
@@ -72,6 +79,47 @@ pub(super) async fn write_index_partitions(
             let row_ids_refs = row_id_array.iter().map(|a| a.as_ref()).collect::<Vec<_>>();
             PlainEncoder::write(writer, row_ids_refs.as_slice()).await?;
         }
+        progress.partition_indexed(part_id, total_records);
+    }
+    Ok(())
+}
+
+/// Write each partition of the shuffled `column` to the index file.
+///
+/// Each partition stores the values of `column`, i.e., the original vectors for IVF_FLAT
+/// or the SQ codes for IVF_SQ, followed by their row ids. Each written partition is
+/// reported to `progress`.
+pub(super) async fn write_column_partitions(
+    writer: &mut dyn Writer,
+    ivf: &mut Ivf,
+    shuffler: &Shuffler,
+    column: &str,
+    progress: &dyn IndexBuildProgress,
+) -> Result<()> {
+    for part_id in 0..ivf.num_partitions() as u32 {
+        let mut vector_array = Vec::<Arc<dyn Array>>::new();
+        let mut row_id_array = Vec::<Arc<dyn Array>>::new();
+
+        if let Some(mut stream) = shuffler.key_iter(part_id).await? {
+            while let Some(batch) = stream.next().await {
+                let batch = batch?;
+                let arr = batch.column_by_name(column).unwrap();
+                vector_array.push(arr.clone());
+                let arr = batch.column_by_name(ROW_ID).unwrap();
+                row_id_array.push(arr.clone());
+            }
+        }
+
+        let total_records = row_id_array.iter().map(|a| a.len()).sum::<usize>();
+        ivf.add_partition(writer.tell().await?, total_records as u32);
+        if total_records > 0 {
+            let vector_refs = vector_array.iter().map(|a| a.as_ref()).collect::<Vec<_>>();
+            PlainEncoder::write(writer, &vector_refs).await?;
+
+            let row_ids_refs = row_id_array.iter().map(|a| a.as_ref()).collect::<Vec<_>>();
+            PlainEncoder::write(writer, row_ids_refs.as_slice()).await?;
+        }
+        progress.partition_indexed(part_id, total_records);
     }
     Ok(())
 }
@@ -12,32 +12,90 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap};
+use std::io::Cursor;
+use std::ops::Range;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
 use arrow_array::RecordBatch;
+use arrow_ipc::{reader::StreamReader, writer::StreamWriter};
 use arrow_schema::Schema as ArrowSchema;
+use arrow_select::concat::concat_batches;
 use dashmap::DashMap;
+use futures::{stream, StreamExt, TryStreamExt};
 use lance_core::{
     datatypes::Schema,
     io::{
-        object_store::ObjectStore, reader::batches_stream, FileReader, FileWriter,
-        RecordBatchStream,
+        object_store::ObjectStore, FileReader, FileWriter, ObjectWriter, ReadBatchParams, Reader,
+        RecordBatchStream, RecordBatchStreamAdapter, Writer,
     },
     Error, Result,
 };
+use lance_index::progress::{IndexBuildProgress, NoopIndexBuildProgress};
 use object_store::path::Path;
+use serde::{Deserialize, Serialize};
 use snafu::{location, Location};
 use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
+use uuid::Uuid;
 
-const BUFFER_FILE_NAME: &str = "buffer.lance";
+use crate::arrow::json::JsonSchema;
+
+/// Name of the buffer file written by a shard.
+fn buffer_file_name(shard: usize) -> String {
+    format!("buffer-{}.lance", shard)
+}
+
+/// The shard that buffers the [RecordBatch]s of `key`.
+fn shard_of(key: u32, num_shards: usize) -> usize {
+    key as usize % num_shards
+}
+
+/// Default limit, in bytes, of the [RecordBatch]s buffered in memory across all keys.
+const DEFAULT_MEMORY_BUDGET: usize = 1024 * 1024 * 1024;
+
+/// How a shuffler buffers the [RecordBatch]s of each key before writing them to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ShuffleMode {
+    /// Buffer the batches of each key separately, and flush a key as one group once
+    /// it has `flush_threshold` rows, or is the largest key when the memory budget
+    /// is exceeded.
+    #[default]
+    Buffered,
+
+    /// External sort: buffer the batches of all keys in one run, which is sorted by
+    /// key and written as one group once the memory budget is exceeded. Reading a
+    /// key merges its slice of every run.
+    ///
+    /// With many partitions, the buffered mode flushes many tiny groups; each run
+    /// here is written at once regardless of the number of keys.
+    Sort,
+}
+
+/// Name of the file next to the buffer files of a resumable shuffle, which persists
+/// the partition / group mapping.
+const MAPPING_FILE_NAME: &str = "mapping.json";
+
+/// The rows of a key in a group of a buffer file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Segment {
+    shard: usize,
+    group_id: u32,
+    /// The rows of the key in the group, or the whole group if `None`.
+    rows: Option<Range<usize>>,
+}
 
 /// Shuffle [RecordBatch] based on their IVF partition.
 ///
 /// Internally, we shuffle several partitions of [RecordBatch]s into a single LanceFile,
 /// and keep tracks of the partition ID to file-group ID mapping in memory.
 ///
+/// The partitions can be sharded across several buffer files, each with its own
+/// writer, so that concurrent inserts of different partitions do not wait on a
+/// single writer.
+///
 /// The IVF partition / group id mapping then be passed to [Shuffler] to retrieve the
 /// all the [RecordBatch]s for a given IVF partition.
 ///
@@ -47,60 +105,341 @@ const BUFFER_FILE_NAME: &str = "buffer.lance";
 ///  3. Each worker takes parts of the IVF partitions, i.e., `IVF / num_workers` of partitions.
 ///  4. Each worker shuffle the [RecordBatch]s of the assigned partitions into a single LanceFile,
 ///     and later aggregated to create the final index file.
+///
+/// See [`super::distributed`] for the distributed build of IVF_PQ indices.
+///
+/// The buffer file is written to a local temporary directory by default, or to a
+/// [`ShuffleSpillLocation`] for workers with little local disk space. The buffers
+/// can be compressed with [`ShuffleCompression`] to reduce the spill IO.
+///
+/// Besides the per-key row threshold, the total size of the buffered batches is
+/// bounded by a memory budget. Once the budget is exceeded, the largest keys are
+/// flushed until the buffers fit again, and concurrent inserts wait for the flush.
+/// See [`ShuffleMode`] for the external sort alternative.
 #[allow(dead_code)]
 pub struct ShufflerBuilder {
     buffer: DashMap<u32, Vec<RecordBatch>>,
 
+    mode: ShuffleMode,
+
+    /// The batches of the current run, in [`ShuffleMode::Sort`].
+    run: std::sync::Mutex<Vec<(u32, RecordBatch)>>,
+
+    /// The number of runs written, to spread them over the shards.
+    num_runs: AtomicUsize,
+
     /// The size, as number of rows, of each partition in memory before flushing to disk.
     flush_size: usize,
 
+    /// The maximum size, in bytes, of all the batches buffered in memory.
+    memory_budget: usize,
+
+    /// The size, in bytes, of the batches currently buffered in memory.
+    buffered_bytes: AtomicUsize,
+
     /// Partition ID to file-group ID mapping, in memory.
     /// No external dependency is required, because we don't need to guarantee the
     /// persistence of this mapping, as well as the temp files.
-    parted_groups: DashMap<u32, Vec<u32>>,
+    parted_groups: DashMap<u32, Vec<Segment>>,
 
-    /// We need to keep the temp_dir with Shuffler because ObjectStore crate does not
-    /// work with a NamedTempFile.
-    temp_dir: Arc<TempDir>,
+    /// Where the buffer file is written.
+    buffer_location: BufferLocation,
 
     /// Schema we are writing. Used for validation.
     schema: ArrowSchema,
 
-    writer: Arc<Mutex<FileWriter>>,
+    /// One writer per shard, indexed by [`shard_of`].
+    writers: Vec<Mutex<BufferWriter>>,
+
+    /// Serializes flushing when the memory budget is exceeded.
+    budget_lock: Mutex<()>,
+
+    /// Reports the rows inserted and the groups flushed to disk.
+    progress: Arc<dyn IndexBuildProgress>,
 }
 
-fn lance_buffer_path(dir: &TempDir) -> Result<Path> {
+fn lance_buffer_path(dir: &TempDir, shard: usize) -> Result<Path> {
     let tmp_dir_path = Path::from_filesystem_path(dir.path()).map_err(|e| Error::IO {
         message: format!("failed to get buffer path in shuffler: {}", e),
         location: location!(),
     })?;
-    Ok(tmp_dir_path.child(BUFFER_FILE_NAME))
+    Ok(tmp_dir_path.child(buffer_file_name(shard)))
 }
 
-impl ShufflerBuilder {
-    pub async fn try_new(schema: &ArrowSchema, flush_threshold: usize) -> Result<Self> {
-        let temp_dir = Arc::new(tempfile::tempdir()?);
+/// A directory in an object store, e.g., S3 or GCS, to spill the shuffle buffers to,
+/// instead of a local temporary directory.
+///
+/// Each shuffler writes its buffer file to a unique sub-directory, and removes it
+/// once the partitions are written.
+#[derive(Debug, Clone)]
+pub struct ShuffleSpillLocation {
+    pub object_store: Arc<ObjectStore>,
+    pub base_path: Path,
+
+    /// Write the buffer files directly in `base_path`, next to the partition / group
+    /// mapping, so that a finished shuffle can be reopened with [Shuffler::try_open],
+    /// e.g., by a worker restarted after a crash. `base_path` must then be unique
+    /// to the shuffle.
+    pub resumable: bool,
+}
+
+/// Compression codec of the shuffle buffers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShuffleCompression {
+    Lz4,
+    Zstd,
+}
+
+impl ShuffleCompression {
+    fn compress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Lz4 => lz4::block::compress(data, None, true)?,
+            Self::Zstd => zstd::bulk::compress(data, zstd::DEFAULT_COMPRESSION_LEVEL)?,
+        })
+    }
+
+    fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        Ok(match self {
+            Self::Lz4 => lz4::block::decompress(data, None)?,
+            Self::Zstd => zstd::stream::decode_all(data)?,
+        })
+    }
+}
+
+/// Writes the groups of [RecordBatch]s to the buffer file.
+enum BufferWriter {
+    /// Uncompressed, each group is a batch of a Lance file.
+    Lance(Box<FileWriter>),
+
+    /// Each group is an Arrow IPC stream, compressed as one block.
+    Compressed {
+        writer: ObjectWriter,
+        compression: ShuffleCompression,
+        schema: Arc<ArrowSchema>,
+        /// Byte range of each group in the buffer file.
+        blocks: Vec<Range<usize>>,
+    },
+}
+
+impl BufferWriter {
+    async fn try_new(
+        object_store: &ObjectStore,
+        path: &Path,
+        schema: &ArrowSchema,
+        compression: Option<ShuffleCompression>,
+    ) -> Result<Self> {
+        let object_writer = object_store.create(path).await?;
+        Ok(match compression {
+            Some(compression) => Self::Compressed {
+                writer: object_writer,
+                compression,
+                schema: Arc::new(schema.clone()),
+                blocks: vec![],
+            },
+            None => Self::Lance(Box::new(FileWriter::with_object_writer(
+                object_writer,
+                Schema::try_from(schema)?,
+                &Default::default(),
+            )?)),
+        })
+    }
+
+    fn num_groups(&self) -> usize {
+        match self {
+            Self::Lance(writer) => writer.next_batch_id() as usize,
+            Self::Compressed { blocks, .. } => blocks.len(),
+        }
+    }
+
+    /// Write `batches` as one group, and return the group ID.
+    async fn write_group(&mut self, batches: &[RecordBatch]) -> Result<u32> {
+        match self {
+            Self::Lance(writer) => {
+                let group_id = writer.next_batch_id() as u32;
+                writer.write(batches).await?;
+                Ok(group_id)
+            }
+            Self::Compressed {
+                writer,
+                compression,
+                schema,
+                blocks,
+            } => {
+                let mut ipc_writer = StreamWriter::try_new(vec![], schema)?;
+                for batch in batches {
+                    ipc_writer.write(batch)?;
+                }
+                ipc_writer.finish()?;
+                let block = compression.compress(&ipc_writer.into_inner()?)?;
+
+                let start = writer.tell().await?;
+                writer.write_all(&block).await?;
+                blocks.push(start..start + block.len());
+                Ok(blocks.len() as u32 - 1)
+            }
+        }
+    }
+
+    async fn finish(&mut self) -> Result<()> {
+        match self {
+            Self::Lance(writer) => {
+                writer.finish().await?;
+            }
+            Self::Compressed { writer, .. } => writer.shutdown().await?,
+        };
+        Ok(())
+    }
+
+    /// The compression codec and the byte range of each group, if compressed.
+    fn compressed_blocks(&self) -> Option<(ShuffleCompression, Vec<Range<usize>>)> {
+        match self {
+            Self::Lance(_) => None,
+            Self::Compressed {
+                compression,
+                blocks,
+                ..
+            } => Some((*compression, blocks.clone())),
+        }
+    }
+}
+
+/// A buffer file written by one shard of a [ShufflerBuilder].
+#[derive(Clone, Serialize, Deserialize)]
+struct BufferShard {
+    /// Whether any group was written. Otherwise the file does not exist.
+    written: bool,
+
+    /// The compression codec and the byte range of each group, if the buffer file
+    /// is compressed.
+    compressed_blocks: Option<(ShuffleCompression, Vec<Range<usize>>)>,
+}
 
-        let object_store = ObjectStore::local();
-        let path = lance_buffer_path(&temp_dir)?;
-        let writer = object_store.create(&path).await?;
+/// The state of a finished resumable shuffle, persisted in [MAPPING_FILE_NAME].
+#[derive(Serialize, Deserialize)]
+struct ShuffleMapping {
+    schema: JsonSchema,
+    shards: Vec<BufferShard>,
+    parted_groups: BTreeMap<u32, Vec<Segment>>,
+}
+
+/// Where the buffer files of a shuffler are written.
+#[derive(Clone)]
+enum BufferLocation {
+    /// We need to keep the temp_dir with Shuffler because ObjectStore crate does not
+    /// work with a NamedTempFile.
+    TempDir(Arc<TempDir>),
+    ObjectStore {
+        object_store: Arc<ObjectStore>,
+        dir: Path,
+        /// Whether the mapping is persisted next to the buffer files.
+        resumable: bool,
+    },
+}
+
+impl BufferLocation {
+    fn try_new(spill: Option<&ShuffleSpillLocation>) -> Result<Self> {
+        Ok(match spill {
+            Some(spill) => Self::ObjectStore {
+                object_store: spill.object_store.clone(),
+                dir: if spill.resumable {
+                    spill.base_path.clone()
+                } else {
+                    spill.base_path.child(format!("shuffle-{}", Uuid::new_v4()))
+                },
+                resumable: spill.resumable,
+            },
+            None => Self::TempDir(Arc::new(tempfile::tempdir()?)),
+        })
+    }
+
+    fn object_store_and_path(&self, shard: usize) -> Result<(Arc<ObjectStore>, Path)> {
+        match self {
+            Self::TempDir(temp_dir) => Ok((
+                Arc::new(ObjectStore::local()),
+                lance_buffer_path(temp_dir, shard)?,
+            )),
+            Self::ObjectStore {
+                object_store, dir, ..
+            } => Ok((object_store.clone(), dir.child(buffer_file_name(shard)))),
+        }
+    }
+
+    /// The object store and the path of the persisted mapping, if resumable.
+    fn mapping_path(&self) -> Option<(Arc<ObjectStore>, Path)> {
+        match self {
+            Self::ObjectStore {
+                object_store,
+                dir,
+                resumable: true,
+            } => Some((object_store.clone(), dir.child(MAPPING_FILE_NAME))),
+            _ => None,
+        }
+    }
+}
+
+impl ShufflerBuilder {
+    /// Create a shuffler that writes `num_shards` buffer files concurrently.
+    pub async fn try_new(
+        schema: &ArrowSchema,
+        flush_threshold: usize,
+        num_shards: usize,
+        spill: Option<&ShuffleSpillLocation>,
+        compression: Option<ShuffleCompression>,
+    ) -> Result<Self> {
+        if num_shards == 0 {
+            return Err(Error::Index {
+                message: "shuffler must have at least one shard".to_string(),
+                location: location!(),
+            });
+        }
+        let buffer_location = BufferLocation::try_new(spill)?;
+        let mut writers = Vec::with_capacity(num_shards);
+        for shard in 0..num_shards {
+            let (object_store, path) = buffer_location.object_store_and_path(shard)?;
+            writers.push(Mutex::new(
+                BufferWriter::try_new(&object_store, &path, schema, compression).await?,
+            ));
+        }
         let schema = schema.clone();
-        let lance_schema = Schema::try_from(&schema)?;
         Ok(Self {
             buffer: DashMap::new(),
+            mode: ShuffleMode::default(),
+            run: std::sync::Mutex::new(vec![]),
+            num_runs: AtomicUsize::new(0),
             flush_size: flush_threshold, // TODO: change to parameterized value later.
-            temp_dir,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            buffered_bytes: AtomicUsize::new(0),
+            buffer_location,
             parted_groups: DashMap::new(),
             schema,
-            writer: Arc::new(Mutex::new(FileWriter::with_object_writer(
-                writer,
-                lance_schema,
-                &Default::default(),
-            )?)),
+            writers,
+            budget_lock: Mutex::new(()),
+            progress: Arc::new(NoopIndexBuildProgress::new()),
         })
     }
 
+    /// Set the maximum size, in bytes, of the batches buffered in memory.
+    pub fn with_memory_budget(mut self, memory_budget: usize) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// Set how the batches are buffered before writing them to disk.
+    pub fn with_mode(mut self, mode: ShuffleMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Report the progress of shuffling to `progress`.
+    pub fn with_progress(mut self, progress: Arc<dyn IndexBuildProgress>) -> Self {
+        self.progress = progress;
+        self
+    }
+
     /// Insert a [RecordBatch] with the same key (Partition ID).
+    ///
+    /// If the memory budget is exceeded, this waits until enough buffered batches
+    /// are flushed to disk.
     pub async fn insert(&self, key: u32, batch: RecordBatch) -> Result<()> {
         // Compare with metadata reset
         debug_assert_eq!(
@@ -111,42 +450,175 @@ impl ShufflerBuilder {
                 .with_metadata(HashMap::new()),
             &self.schema
         );
-        let mut batches = self.buffer.entry(key).or_default();
-        batches.push(batch);
-        let total = batches.iter().map(|b| b.num_rows()).sum::<usize>();
-        // If there are more than `flush_size` rows in the buffer, flush them to disk
-        // as one group.
-        if total >= self.flush_size {
-            let mut writer = self.writer.lock().await;
-            self.parted_groups
-                .entry(key)
-                .or_default()
-                .push(writer.next_batch_id() as u32);
-            writer.write(batches.as_slice()).await?;
-            batches.clear();
+        self.progress.rows_shuffled(batch.num_rows());
+        self.buffered_bytes
+            .fetch_add(batch.get_array_memory_size(), Ordering::Relaxed);
+        if self.mode == ShuffleMode::Sort {
+            self.run.lock().unwrap().push((key, batch));
+            if self.buffered_bytes.load(Ordering::Relaxed) > self.memory_budget {
+                // Other inserts wait on the budget lock while the run is written.
+                let _guard = self.budget_lock.lock().await;
+                if self.buffered_bytes.load(Ordering::Relaxed) > self.memory_budget {
+                    let run = std::mem::take(&mut *self.run.lock().unwrap());
+                    self.flush_run(run).await?;
+                }
+            }
+            return Ok(());
+        }
+
+        // Do not hold the buffer entry across an await.
+        let to_flush = {
+            let mut batches = self.buffer.entry(key).or_default();
+            batches.push(batch);
+            let total = batches.iter().map(|b| b.num_rows()).sum::<usize>();
+            // If there are more than `flush_size` rows in the buffer, flush them to disk
+            // as one group.
+            if total >= self.flush_size {
+                std::mem::take(batches.value_mut())
+            } else {
+                vec![]
+            }
         };
+        if !to_flush.is_empty() {
+            self.flush_group(key, to_flush).await?;
+        }
+
+        if self.buffered_bytes.load(Ordering::Relaxed) > self.memory_budget {
+            // Other inserts wait on the budget lock while the largest keys are flushed.
+            let _guard = self.budget_lock.lock().await;
+            while self.buffered_bytes.load(Ordering::Relaxed) > self.memory_budget {
+                let Some(key) = self.largest_key() else {
+                    break;
+                };
+                let batches = self
+                    .buffer
+                    .get_mut(&key)
+                    .map(|mut b| std::mem::take(b.value_mut()))
+                    .unwrap_or_default();
+                self.flush_group(key, batches).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The key with the most bytes buffered in memory.
+    fn largest_key(&self) -> Option<u32> {
+        self.buffer
+            .iter()
+            .filter(|r| !r.is_empty())
+            .max_by_key(|r| r.iter().map(|b| b.get_array_memory_size()).sum::<usize>())
+            .map(|r| *r.key())
+    }
+
+    /// Write `batches` of `key` to the buffer file of its shard as one group.
+    async fn flush_group(&self, key: u32, batches: Vec<RecordBatch>) -> Result<()> {
+        if batches.is_empty() {
+            return Ok(());
+        }
+        let shard = shard_of(key, self.writers.len());
+        let group_id = self.writers[shard]
+            .lock()
+            .await
+            .write_group(batches.as_slice())
+            .await?;
+        self.parted_groups.entry(key).or_default().push(Segment {
+            shard,
+            group_id,
+            rows: None,
+        });
+        let size = batches
+            .iter()
+            .map(|b| b.get_array_memory_size())
+            .sum::<usize>();
+        self.buffered_bytes.fetch_sub(size, Ordering::Relaxed);
+        self.progress.partition_flushed(key, size);
+        Ok(())
+    }
+
+    /// Sort the batches of a run by key, and write them to the next shard as one group.
+    async fn flush_run(&self, mut run: Vec<(u32, RecordBatch)>) -> Result<()> {
+        if run.is_empty() {
+            return Ok(());
+        }
+        run.sort_by_key(|(key, _)| *key);
+        let shard = self.num_runs.fetch_add(1, Ordering::Relaxed) % self.writers.len();
+        let batches = run.iter().map(|(_, b)| b.clone()).collect::<Vec<_>>();
+        let group_id = self.writers[shard]
+            .lock()
+            .await
+            .write_group(batches.as_slice())
+            .await?;
+
+        // (number of rows, size in bytes) of each key in the run.
+        let mut key_sizes = BTreeMap::<u32, (usize, usize)>::new();
+        for (key, batch) in &run {
+            let (num_rows, size) = key_sizes.entry(*key).or_default();
+            *num_rows += batch.num_rows();
+            *size += batch.get_array_memory_size();
+        }
+        let mut offset = 0;
+        for (key, (num_rows, size)) in key_sizes {
+            self.parted_groups.entry(key).or_default().push(Segment {
+                shard,
+                group_id,
+                rows: Some(offset..offset + num_rows),
+            });
+            offset += num_rows;
+            self.buffered_bytes.fetch_sub(size, Ordering::Relaxed);
+            self.progress.partition_flushed(key, size);
+        }
         Ok(())
     }
 
     pub async fn finish(&mut self) -> Result<Shuffler> {
-        let mut writer = self.writer.lock().await;
+        let num_shards = self.writers.len();
+        let run = std::mem::take(self.run.get_mut().unwrap());
+        self.flush_run(run).await?;
         for batches in self.buffer.iter() {
             if !batches.is_empty() {
+                let shard = shard_of(*batches.key(), num_shards);
+                let group_id = self.writers[shard]
+                    .get_mut()
+                    .write_group(batches.as_slice())
+                    .await?;
                 self.parted_groups
                     .entry(*batches.key())
                     .or_default()
-                    .push(writer.next_batch_id() as u32);
-                writer.write(batches.as_slice()).await?;
+                    .push(Segment {
+                        shard,
+                        group_id,
+                        rows: None,
+                    });
+                self.progress.partition_flushed(
+                    *batches.key(),
+                    batches.iter().map(|b| b.get_array_memory_size()).sum(),
+                );
             }
         }
-        writer.finish().await?;
-        Ok(Shuffler::new(
+        let mut shards = Vec::with_capacity(num_shards);
+        for writer in self.writers.iter_mut() {
+            let writer = writer.get_mut();
+            let written = writer.num_groups() > 0;
+            // A file without any batch can not be written, and there is nothing to read.
+            if written {
+                writer.finish().await?;
+            }
+            shards.push(BufferShard {
+                written,
+                compressed_blocks: writer.compressed_blocks(),
+            });
+        }
+        let shuffler = Shuffler::new(
             self.parted_groups
                 .iter()
                 .map(|r| (*r.key(), r.to_vec()))
                 .collect(),
-            self.temp_dir.clone(),
-        ))
+            self.buffer_location.clone(),
+            Arc::new(self.schema.clone()),
+            shards,
+        );
+        shuffler.persist_mapping().await?;
+        Ok(shuffler)
     }
 }
 
@@ -154,46 +626,191 @@ pub struct Shuffler {
     /// Partition ID to file-group ID mapping, in memory.
     /// No external dependency is required, because we don't need to guarantee the
     /// persistence of this mapping, as well as the temp files.
-    parted_groups: BTreeMap<u32, Vec<u32>>,
+    parted_groups: BTreeMap<u32, Vec<Segment>>,
 
-    /// We need to keep the temp_dir with Shuffler because ObjectStore crate does not
-    /// work with a NamedTempFile.
-    temp_dir: Arc<TempDir>,
+    /// Where the buffer file was written.
+    buffer_location: BufferLocation,
+
+    schema: Arc<ArrowSchema>,
+
+    /// The buffer file of each shard.
+    shards: Vec<BufferShard>,
 }
 
 impl Shuffler {
-    fn new(parted_groups: BTreeMap<u32, Vec<u32>>, temp_dir: Arc<TempDir>) -> Self {
+    fn new(
+        parted_groups: BTreeMap<u32, Vec<Segment>>,
+        buffer_location: BufferLocation,
+        schema: Arc<ArrowSchema>,
+        shards: Vec<BufferShard>,
+    ) -> Self {
         Self {
             parted_groups,
-            temp_dir,
+            buffer_location,
+            schema,
+            shards,
         }
     }
 
+    /// Reopen a finished shuffle in a resumable [ShuffleSpillLocation].
+    ///
+    /// Returns `None` if the shuffle was not finished, i.e., its mapping was not
+    /// persisted.
+    pub async fn try_open(spill: &ShuffleSpillLocation) -> Result<Option<Self>> {
+        if !spill.resumable {
+            return Err(Error::Index {
+                message: "only a resumable shuffle can be reopened".to_string(),
+                location: location!(),
+            });
+        }
+        let buffer_location = BufferLocation::try_new(Some(spill))?;
+        let (object_store, path) = buffer_location.mapping_path().unwrap(); // Resumable.
+        if !object_store.exists(&path).await? {
+            return Ok(None);
+        }
+        let size = object_store.size(&path).await?;
+        let data = object_store.open(&path).await?.get_range(0..size).await?;
+        let mapping: ShuffleMapping = serde_json::from_slice(&data)?;
+        Ok(Some(Self::new(
+            mapping.parted_groups,
+            buffer_location,
+            Arc::new(ArrowSchema::try_from(mapping.schema)?),
+            mapping.shards,
+        )))
+    }
+
+    /// Persist the partition / group mapping next to the buffer files, if resumable.
+    async fn persist_mapping(&self) -> Result<()> {
+        let Some((object_store, path)) = self.buffer_location.mapping_path() else {
+            return Ok(());
+        };
+        let mapping = ShuffleMapping {
+            schema: JsonSchema::try_from(self.schema.as_ref())?,
+            shards: self.shards.clone(),
+            parted_groups: self.parted_groups.clone(),
+        };
+        object_store
+            .put(&path, serde_json::to_vec(&mapping)?.as_slice())
+            .await
+    }
+
+    /// Schema of the shuffled [RecordBatch]s.
+    pub fn schema(&self) -> &ArrowSchema {
+        &self.schema
+    }
+
+    /// Remove the buffer file spilled to an object store.
+    ///
+    /// A local buffer file is removed when the [Shuffler] is dropped.
+    pub async fn remove(self) -> Result<()> {
+        // The mapping goes first, so that a partially removed shuffle is not reopened.
+        if let Some((object_store, path)) = self.buffer_location.mapping_path() {
+            object_store.delete(&path).await?;
+        }
+        if let BufferLocation::ObjectStore { .. } = &self.buffer_location {
+            for (shard, buffer) in self.shards.iter().enumerate() {
+                if buffer.written {
+                    let (object_store, path) = self.buffer_location.object_store_and_path(shard)?;
+                    object_store.delete(&path).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// Iterate over the shuffled [RecordBatch]s for a given partition key.
     pub async fn key_iter(&self, key: u32) -> Result<Option<impl RecordBatchStream + '_>> {
-        if !self.parted_groups.contains_key(&key) {
+        let Some(segments) = self.parted_groups.get(&key) else {
             return Ok(None);
+        };
+
+        let mut readers = HashMap::new();
+        for segment in segments {
+            if let Entry::Vacant(entry) = readers.entry(segment.shard) {
+                entry.insert(Arc::new(self.open_shard(segment.shard).await?));
+            }
+        }
+        let stream = stream::iter(segments.clone())
+            .then(move |segment| {
+                let reader = readers[&segment.shard].clone();
+                async move {
+                    let batches = reader.read_segment(&segment).await?;
+                    Ok::<_, Error>(stream::iter(batches.into_iter().map(Ok)))
+                }
+            })
+            .try_flatten()
+            .boxed();
+        Ok(Some(RecordBatchStreamAdapter::new(
+            self.schema.clone(),
+            stream,
+        )))
+    }
+
+    async fn open_shard(&self, shard: usize) -> Result<ShardReader> {
+        let (object_store, path) = self.buffer_location.object_store_and_path(shard)?;
+        if let Some((compression, blocks)) = &self.shards[shard].compressed_blocks {
+            return Ok(ShardReader::Compressed {
+                reader: object_store.open(&path).await?,
+                compression: *compression,
+                blocks: blocks.clone(),
+                schema: self.schema.clone(),
+            });
         }
 
-        let object_store = ObjectStore::local();
-        let path = lance_buffer_path(self.temp_dir.as_ref())?;
         let reader = FileReader::try_new(&object_store, &path)
             .await
             .map_err(|e| Error::IO {
                 message: format!("failed to open shuffler buffer file: {}, {}", path, e),
                 location: location!(),
             })?;
-        let schema = reader.schema().clone();
+        Ok(ShardReader::Lance(reader))
+    }
+}
 
-        let group_ids = self
-            .parted_groups
-            .get(&key)
-            .unwrap() // Checked existence already.
-            .iter()
-            .copied()
-            .collect::<HashSet<_>>();
-        let stream = batches_stream(reader, schema, move |id| group_ids.contains(&(*id as u32)));
-        Ok(Some(stream))
+/// Reads the groups of the buffer file of a shard.
+enum ShardReader {
+    Lance(FileReader),
+    Compressed {
+        reader: Box<dyn Reader>,
+        compression: ShuffleCompression,
+        blocks: Vec<Range<usize>>,
+        schema: Arc<ArrowSchema>,
+    },
+}
+
+impl ShardReader {
+    async fn read_segment(&self, segment: &Segment) -> Result<Vec<RecordBatch>> {
+        match self {
+            Self::Lance(reader) => {
+                let params = match &segment.rows {
+                    Some(rows) => ReadBatchParams::Range(rows.clone()),
+                    None => ReadBatchParams::RangeFull,
+                };
+                let batch = reader
+                    .read_batch(segment.group_id as i32, params, reader.schema())
+                    .await?;
+                Ok(vec![batch])
+            }
+            Self::Compressed {
+                reader,
+                compression,
+                blocks,
+                schema,
+            } => {
+                let block = reader
+                    .get_range(blocks[segment.group_id as usize].clone())
+                    .await?;
+                let data = compression.decompress(&block)?;
+                let batches = StreamReader::try_new(Cursor::new(data), None)?
+                    .collect::<std::result::Result<Vec<_>, _>>()?;
+                Ok(match &segment.rows {
+                    Some(rows) => {
+                        vec![concat_batches(schema, &batches)?.slice(rows.start, rows.len())]
+                    }
+                    None => batches,
+                })
+            }
+        }
     }
 }
 
@@ -203,14 +820,16 @@ mod tests {
 
     use std::sync::Arc;
 
-    use arrow_array::UInt32Array;
+    use arrow_array::{cast::AsArray, types::UInt32Type, UInt32Array};
     use arrow_schema::{DataType, Field, Schema};
     use futures::TryStreamExt;
 
     #[tokio::test]
     async fn test_shuffler() {
         let schema = Schema::new(vec![Field::new("a", DataType::UInt32, false)]);
-        let mut shuffler = ShufflerBuilder::try_new(&schema, 4).await.unwrap();
+        let mut shuffler = ShufflerBuilder::try_new(&schema, 4, 1, None, None)
+            .await
+            .unwrap();
         for i in 0..20 {
             shuffler
                 .insert(
@@ -233,4 +852,258 @@ mod tests {
 
         assert!(reader.key_iter(5).await.unwrap().is_none())
     }
+
+    #[tokio::test]
+    async fn test_shuffler_sort_mode() {
+        let schema = Schema::new(vec![Field::new("a", DataType::UInt32, false)]);
+        let batch_size = RecordBatch::try_new(
+            Arc::new(schema.clone()),
+            vec![Arc::new(UInt32Array::from(vec![0]))],
+        )
+        .unwrap()
+        .get_array_memory_size();
+        for compression in [None, Some(ShuffleCompression::Lz4)] {
+            let mut shuffler = ShufflerBuilder::try_new(&schema, 4, 2, None, compression)
+                .await
+                .unwrap()
+                .with_mode(ShuffleMode::Sort)
+                .with_memory_budget(batch_size * 30);
+            // Every batch goes to a different key than the previous one.
+            for i in 0..500 {
+                shuffler
+                    .insert(
+                        (i * 7) % 50,
+                        RecordBatch::try_new(
+                            Arc::new(schema.clone()),
+                            vec![Arc::new(UInt32Array::from(vec![i]))],
+                        )
+                        .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let reader = shuffler.finish().await.unwrap();
+            // Each run is written as one group, shared by the keys.
+            let num_groups = reader
+                .parted_groups
+                .values()
+                .flatten()
+                .map(|s| (s.shard, s.group_id))
+                .collect::<std::collections::HashSet<_>>()
+                .len();
+            assert!(num_groups <= 500 / 30 + 1, "{} groups", num_groups);
+
+            for key in 0..50 {
+                let stream = reader.key_iter(key).await.unwrap().expect("key exists");
+                let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+                let values = batches
+                    .iter()
+                    .flat_map(|b| b["a"].as_primitive::<UInt32Type>().values().to_vec())
+                    .collect::<Vec<_>>();
+                let expected = (0..500).filter(|i| (i * 7) % 50 == key).collect::<Vec<_>>();
+                assert_eq!(values, expected);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shuffler_concurrent_shards() {
+        let schema = Schema::new(vec![Field::new("a", DataType::UInt32, false)]);
+        let mut shuffler = ShufflerBuilder::try_new(&schema, 4, 4, None, None)
+            .await
+            .unwrap();
+        futures::future::try_join_all((0..100).map(|i| {
+            shuffler.insert(
+                i % 10,
+                RecordBatch::try_new(
+                    Arc::new(schema.clone()),
+                    vec![Arc::new(UInt32Array::from(vec![i]))],
+                )
+                .unwrap(),
+            )
+        }))
+        .await
+        .unwrap();
+
+        let reader = shuffler.finish().await.unwrap();
+        assert!(reader.shards.iter().all(|s| s.written));
+        for i in 0..10 {
+            let stream = reader.key_iter(i).await.unwrap().expect("key exists");
+            let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+            let mut values = batches
+                .iter()
+                .flat_map(|b| b["a"].as_primitive::<UInt32Type>().values().to_vec())
+                .collect::<Vec<_>>();
+            values.sort();
+            assert_eq!(values, (i..100).step_by(10).collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shuffler_memory_budget() {
+        let schema = Schema::new(vec![Field::new("a", DataType::UInt32, false)]);
+        let batch_size = RecordBatch::try_new(
+            Arc::new(schema.clone()),
+            vec![Arc::new(UInt32Array::from(vec![0]))],
+        )
+        .unwrap()
+        .get_array_memory_size();
+        // The row threshold is never reached, only the budget triggers flushing.
+        let mut shuffler = ShufflerBuilder::try_new(&schema, 1000, 1, None, None)
+            .await
+            .unwrap()
+            .with_memory_budget(batch_size * 5);
+        for i in 0..100 {
+            shuffler
+                .insert(
+                    i % 10,
+                    RecordBatch::try_new(
+                        Arc::new(schema.clone()),
+                        vec![Arc::new(UInt32Array::from(vec![i]))],
+                    )
+                    .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert!(shuffler.buffered_bytes.load(Ordering::Relaxed) <= batch_size * 5);
+        }
+        assert!(!shuffler.parted_groups.is_empty());
+
+        let reader = shuffler.finish().await.unwrap();
+        for i in 0..10 {
+            let stream = reader.key_iter(i).await.unwrap().expect("key exists");
+            let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+            let mut values = batches
+                .iter()
+                .flat_map(|b| b["a"].as_primitive::<UInt32Type>().values().to_vec())
+                .collect::<Vec<_>>();
+            values.sort();
+            assert_eq!(values, (i..100).step_by(10).collect::<Vec<_>>());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_shuffler_compression() {
+        let schema = Schema::new(vec![Field::new("a", DataType::UInt32, false)]);
+        for compression in [ShuffleCompression::Lz4, ShuffleCompression::Zstd] {
+            let mut shuffler = ShufflerBuilder::try_new(&schema, 4, 2, None, Some(compression))
+                .await
+                .unwrap();
+            for i in 0..20 {
+                shuffler
+                    .insert(
+                        i % 3,
+                        RecordBatch::try_new(
+                            Arc::new(schema.clone()),
+                            vec![Arc::new(UInt32Array::from(vec![i]))],
+                        )
+                        .unwrap(),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let reader = shuffler.finish().await.unwrap();
+            for i in 0..3 {
+                let stream = reader.key_iter(i).await.unwrap().expect("key exists");
+                let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+                let values = batches
+                    .iter()
+                    .flat_map(|b| b["a"].as_primitive::<UInt32Type>().values().to_vec())
+                    .collect::<Vec<_>>();
+                assert_eq!(values, (i..20).step_by(3).collect::<Vec<_>>());
+            }
+            assert!(reader.key_iter(5).await.unwrap().is_none())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resume_shuffle() {
+        let schema = Schema::new(vec![Field::new("a", DataType::UInt32, false)]);
+        let object_store = Arc::new(ObjectStore::memory());
+        let spill = ShuffleSpillLocation {
+            object_store: object_store.clone(),
+            base_path: Path::from("worker-0"),
+            resumable: true,
+        };
+        assert!(Shuffler::try_open(&spill).await.unwrap().is_none());
+
+        let mut shuffler =
+            ShufflerBuilder::try_new(&schema, 4, 2, Some(&spill), Some(ShuffleCompression::Zstd))
+                .await
+                .unwrap()
+                .with_mode(ShuffleMode::Sort);
+        for i in 0..20 {
+            shuffler
+                .insert(
+                    i % 3,
+                    RecordBatch::try_new(
+                        Arc::new(schema.clone()),
+                        vec![Arc::new(UInt32Array::from(vec![i]))],
+                    )
+                    .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+        // The shuffler is lost, e.g., the worker crashed.
+        drop(shuffler.finish().await.unwrap());
+
+        let reader = Shuffler::try_open(&spill).await.unwrap().expect("finished");
+        assert_eq!(reader.schema(), &schema);
+        for i in 0..3 {
+            let stream = reader.key_iter(i).await.unwrap().expect("key exists");
+            let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+            let values = batches
+                .iter()
+                .flat_map(|b| b["a"].as_primitive::<UInt32Type>().values().to_vec())
+                .collect::<Vec<_>>();
+            assert_eq!(values, (i..20).step_by(3).collect::<Vec<_>>());
+        }
+
+        reader.remove().await.unwrap();
+        assert!(object_store.read_dir("worker-0").await.unwrap().is_empty());
+        assert!(Shuffler::try_open(&spill).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_shuffler_spill_to_object_store() {
+        let schema = Schema::new(vec![Field::new("a", DataType::UInt32, false)]);
+        let object_store = Arc::new(ObjectStore::memory());
+        let spill = ShuffleSpillLocation {
+            object_store: object_store.clone(),
+            base_path: Path::from("spill"),
+            resumable: false,
+        };
+        let mut shuffler = ShufflerBuilder::try_new(&schema, 4, 2, Some(&spill), None)
+            .await
+            .unwrap();
+        for i in 0..20 {
+            shuffler
+                .insert(
+                    i % 3,
+                    RecordBatch::try_new(
+                        Arc::new(schema.clone()),
+                        vec![Arc::new(UInt32Array::from(vec![i]))],
+                    )
+                    .unwrap(),
+                )
+                .await
+                .unwrap();
+        }
+        let reader = shuffler.finish().await.unwrap();
+        let spilled = object_store.read_dir("spill").await.unwrap();
+        assert_eq!(spilled.len(), 1);
+
+        let stream = reader.key_iter(1).await.unwrap().expect("key exists");
+        let batches = stream.try_collect::<Vec<_>>().await.unwrap();
+        let values = batches
+            .iter()
+            .flat_map(|b| b["a"].as_primitive::<UInt32Type>().values().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(values, (1..20).step_by(3).collect::<Vec<_>>());
+
+        reader.remove().await.unwrap();
+        let path = Path::from("spill").child(spilled[0].as_str());
+        assert!(object_store.read_dir(path).await.unwrap().is_empty());
+    }
 }
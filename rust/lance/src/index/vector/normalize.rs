@@ -0,0 +1,162 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! L2 normalization of vectors.
+//!
+//! For unit vectors `x` and `y`, `|x - y|^2 = 2 * (1 - cos(x, y))`, so a cosine index
+//! can normalize its vectors once and use L2 for IVF and PQ, which are not
+//! correct with cosine on residuals. Likewise `x * y = cos(x, y)`, so a flat
+//! sub-index searches normalized vectors with dot product.
+
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float16Type, Float32Type, Float64Type},
+    ArrayRef, FixedSizeListArray,
+};
+use arrow_schema::DataType;
+use async_trait::async_trait;
+use lance_arrow::{ArrowFloatType, FixedSizeListArrayExt, FloatArray};
+use lance_core::io::ObjectWriter;
+use lance_linalg::{distance::norm_l2, MatrixView};
+use num_traits::{AsPrimitive, FromPrimitive};
+use snafu::{location, Location};
+
+use super::Transformer;
+use crate::index::pb::{Transform, TransformType};
+use crate::{Error, Result};
+
+/// Normalizes each vector to unit length.
+///
+/// Zero vectors are kept as they are.
+#[derive(Debug, Clone, Default)]
+pub struct L2Normalizer {}
+
+impl L2Normalizer {
+    /// The metadata of this transform. There is no data to write to the index file.
+    pub(crate) fn to_proto() -> Transform {
+        Transform {
+            position: 0,
+            shape: vec![],
+            r#type: TransformType::Normalize.into(),
+        }
+    }
+
+    /// Convert a L2 distance between two unit vectors to their cosine distance.
+    pub(crate) fn to_cosine_distance(l2_distance: f32) -> f32 {
+        l2_distance / 2.0
+    }
+
+    /// Convert a cosine distance between two unit vectors to their L2 distance.
+    pub(crate) fn to_l2_distance(cosine_distance: f32) -> f32 {
+        cosine_distance * 2.0
+    }
+
+    /// Convert a dot distance, i.e., the negative dot product, between two unit
+    /// vectors to their cosine distance.
+    pub(crate) fn dot_to_cosine_distance(dot_distance: f32) -> f32 {
+        1.0 + dot_distance
+    }
+
+    /// Convert a cosine distance between two unit vectors to their dot distance.
+    pub(crate) fn to_dot_distance(cosine_distance: f32) -> f32 {
+        cosine_distance - 1.0
+    }
+}
+
+fn do_normalize<T: ArrowFloatType>(data: &T::ArrayType, dimension: usize) -> ArrayRef {
+    let values = data
+        .as_slice()
+        .chunks_exact(dimension)
+        .flat_map(|vector| {
+            let norm = norm_l2(vector);
+            vector.iter().map(move |&v| {
+                if norm > 0.0 {
+                    T::Native::from_f32(AsPrimitive::<f32>::as_(v) / norm).unwrap()
+                } else {
+                    v
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    Arc::new(T::ArrayType::from(values))
+}
+
+/// Normalize each vector of `data` to unit length.
+pub(crate) fn normalize(data: &FixedSizeListArray) -> Result<FixedSizeListArray> {
+    let dimension = data.value_length() as usize;
+    let values = data.values();
+    let normalized = match values.data_type() {
+        DataType::Float16 => do_normalize::<Float16Type>(values.as_primitive(), dimension),
+        DataType::Float32 => do_normalize::<Float32Type>(values.as_primitive(), dimension),
+        DataType::Float64 => do_normalize::<Float64Type>(values.as_primitive(), dimension),
+        _ => {
+            return Err(Error::Index {
+                message: format!(
+                    "Normalize: only supports float vectors, got {}",
+                    values.data_type()
+                ),
+                location: location!(),
+            })
+        }
+    };
+    Ok(FixedSizeListArray::try_new_from_values(
+        normalized,
+        dimension as i32,
+    )?)
+}
+
+#[async_trait]
+impl Transformer for L2Normalizer {
+    /// Normalization does not need training.
+    async fn train(&mut self, _data: &MatrixView<Float32Type>) -> Result<()> {
+        Ok(())
+    }
+
+    async fn transform(&self, data: &FixedSizeListArray) -> Result<FixedSizeListArray> {
+        normalize(data)
+    }
+
+    async fn save(&self, _writer: &mut ObjectWriter) -> Result<Transform> {
+        Ok(Self::to_proto())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::assert_relative_eq;
+    use arrow_array::{Float32Array, Float64Array};
+
+    #[test]
+    fn test_normalize() {
+        let data = FixedSizeListArray::try_new_from_values(
+            Float32Array::from(vec![3.0, 4.0, 0.0, 0.0, -2.0, 0.0]),
+            2,
+        )
+        .unwrap();
+        let normalized = normalize(&data).unwrap();
+        let values = normalized.values().as_primitive::<Float32Type>();
+        assert_eq!(values.values(), &[0.6, 0.8, 0.0, 0.0, -1.0, 0.0]);
+
+        let data =
+            FixedSizeListArray::try_new_from_values(Float64Array::from(vec![1.0, 1.0]), 2).unwrap();
+        let normalized = normalize(&data).unwrap();
+        let values = normalized.values().as_primitive::<Float64Type>();
+        assert_relative_eq!(values.value(0), 1.0 / 2.0_f64.sqrt(), epsilon = 1e-6);
+        assert_relative_eq!(values.value(1), 1.0 / 2.0_f64.sqrt(), epsilon = 1e-6);
+    }
+}
@@ -18,35 +18,46 @@
 use std::any::Any;
 use std::sync::Arc;
 
-use arrow::array::as_primitive_array;
 use arrow_array::{
-    builder::Float32Builder, cast::AsArray, types::Float32Type, Array, FixedSizeListArray,
-    Float32Array, RecordBatch, UInt8Array,
+    cast::AsArray,
+    types::{Float32Type, UInt8Type},
+    Array, FixedSizeListArray, Float32Array, RecordBatch,
 };
 use arrow_schema::DataType;
 use async_trait::async_trait;
-use lance_linalg::{distance::*, MatrixView};
+use lance_arrow::*;
+use lance_core::io::{object_writer::ObjectWriter, read_fixed_stride_array, Reader};
+use lance_index::{
+    vector::{
+        pq::{num_centroids, PQBuildParams, ProductQuantizer, ProductQuantizerImpl},
+        Query,
+    },
+    Index, IndexType,
+};
+use lance_linalg::{distance::MetricType, MatrixView};
+use log::debug;
+use nohash_hasher::IntMap;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use roaring::RoaringBitmap;
 use serde::Serialize;
+use snafu::{location, Location};
+use tracing::instrument;
 
-use super::{
-    pq::{PQBuildParams, ProductQuantizer},
-    MetricType, Query, Transformer, VectorIndex,
-};
+use super::{Transformer, VectorIndex};
+use crate::encodings::plain::PlainEncoder;
 use crate::index::pb::{Transform, TransformType};
 use crate::index::prefilter::PreFilter;
-use crate::io::{
-    object_reader::{read_fixed_stride_array, ObjectReader},
-    object_writer::ObjectWriter,
-};
-use crate::{arrow::svd::*, index::Index};
 use crate::{Error, Result};
 
+/// Number of kmeans iterations to train the initial PQ codebook.
 const OPQ_PQ_INIT_ITERATIONS: usize = 10;
 
 /// Rotation matrix `R` described in Optimized Product Quantization.
 ///
 /// [Optimized Product Quantization for Approximate Nearest Neighbor Search
 /// (CVPR' 13)](https://www.microsoft.com/en-us/research/wp-content/uploads/2013/11/pami13opq.pdf)
+///
+/// Vectors are stored as rows, so a vector `x` is rotated by `x * R`.
 #[derive(Debug, Clone)]
 pub struct OptimizedProductQuantizer {
     num_sub_vectors: usize,
@@ -55,49 +66,44 @@ pub struct OptimizedProductQuantizer {
     num_bits: u32,
 
     /// OPQ rotation
-    pub rotation: Option<MatrixView>,
-
-    /// The offset where the matrix is stored in the index file.
-    pub file_position: Option<usize>,
-
-    /// The metric to compute the distance.
-    metric_type: MetricType,
+    pub rotation: Option<MatrixView<Float32Type>>,
 
     /// Number of iterations to train OPQ.
     num_iters: usize,
+
+    /// Seed of the random generator to train OPQ.
+    seed: Option<u64>,
 }
 
 impl OptimizedProductQuantizer {
-    /// Train a Optimized Product Quantization.
+    /// Create an untrained Optimized Product Quantizer.
     ///
     /// Parameters:
     ///
-    /// - *data*: training dataset.
-    /// - *dimension*: dimension of the training dataset.
     /// - *num_sub_vectors*: the number of sub vectors in the product quantization.
-    /// - *num_iterations*: The number of iterations to train on OPQ rotation matrix.
-    pub fn new(
-        num_sub_vectors: usize,
-        num_bits: u32,
-        metric_type: MetricType,
-        num_iters: usize,
-    ) -> Self {
+    /// - *num_bits*: the number of bits to present the centroids of one sub vector.
+    /// - *num_iters*: The number of iterations to train on OPQ rotation matrix.
+    pub fn new(num_sub_vectors: usize, num_bits: u32, num_iters: usize) -> Self {
         Self {
             num_sub_vectors,
             num_bits,
             rotation: None,
-            file_position: None,
-            metric_type,
             num_iters,
+            seed: None,
         }
     }
 
-    /// Load the optimized product quantizer.
-    pub async fn load(reader: &dyn ObjectReader, position: usize, shape: &[usize]) -> Result<Self> {
+    /// Load the rotation matrix of an optimized product quantizer.
+    pub async fn load(reader: &dyn Reader, position: usize, shape: &[usize]) -> Result<Self> {
+        if shape.len() != 2 || shape[0] != shape[1] {
+            return Err(Error::Index {
+                message: format!("OPQ: rotation matrix must be square, got shape {:?}", shape),
+                location: location!(),
+            });
+        }
         let dim = shape[0];
-        let length = dim * dim;
         let data =
-            read_fixed_stride_array(reader, &DataType::Float32, position, length, ..).await?;
+            read_fixed_stride_array(reader, &DataType::Float32, position, dim * dim, ..).await?;
         let rotation = Some(MatrixView::new(
             Arc::new(data.as_primitive::<Float32Type>().clone()),
             dim,
@@ -106,149 +112,244 @@ impl OptimizedProductQuantizer {
             num_sub_vectors: 0,
             num_bits: 0,
             rotation,
-            file_position: None,
-            metric_type: MetricType::L2,
             num_iters: 0,
+            seed: None,
         })
     }
 
-    /// Train once and return the rotation matrix and PQ codebook.
-    async fn train_once(
-        &self,
-        pq: &mut ProductQuantizer,
-        train: &MatrixView,
-        metric_type: MetricType,
-    ) -> Result<(MatrixView, FixedSizeListArray)> {
-        let dim = train.num_columns();
-
-        // Train few times to get a better rotation matrix. See Faiss.
-        pq.train(train, metric_type, 1).await?;
-        let pq_code = pq.transform(train, metric_type).await?;
-
-        // Reconstruct Y
-        let mut builder = Float32Builder::with_capacity(train.num_columns() * train.num_rows());
-        for i in 0..pq_code.len() {
-            let code_arr = pq_code.value(i);
-            let code: &UInt8Array = as_primitive_array(code_arr.as_ref());
-            let reconstructed_vector = pq.reconstruct(code.values());
-            builder.append_slice(reconstructed_vector.values());
-        }
-        // Reconstructed vectors, `Y` in Section 3.1 in CVPR' 13.
-        // X and Y are column major described in the paper.
-        let y_data = Arc::new(builder.finish());
-        let y = MatrixView::new(y_data, dim).transpose();
-        // Solving `min||RX - Y||` in Section 3.1 in CVPR' 13.
-        //
-        //  X * T(Y) = U * S * T(V)
-        //  R = V * T(U)
-        let x_yt = train.transpose().dot(&y.transpose())?;
-        let (u, _, vt) = x_yt.svd()?;
-        let rotation = vt.transpose().dot(&u.transpose())?;
-
-        Ok((rotation, pq_code))
+    fn rotation(&self) -> Result<&MatrixView<Float32Type>> {
+        self.rotation.as_ref().ok_or_else(|| Error::Index {
+            message: "OPQ is not trained".to_string(),
+            location: location!(),
+        })
     }
 }
 
 /// Train Optimized Product Quantization.
 pub(crate) async fn train_opq(
-    data: &MatrixView,
+    data: &FixedSizeListArray,
     params: &PQBuildParams,
 ) -> Result<OptimizedProductQuantizer> {
     let mut opq = OptimizedProductQuantizer::new(
         params.num_sub_vectors,
         params.num_bits as u32,
-        params.metric_type,
         params.max_opq_iters,
     );
+    opq.seed = params.seed;
 
-    opq.train(data).await?;
+    let data = to_f32_matrix(data)?;
+    opq.train(&data).await?;
 
     Ok(opq)
 }
 
-/// Initialize rotation matrix.
-fn init_rotation(dimension: usize) -> Result<MatrixView> {
-    let mat = MatrixView::random(dimension, dimension);
+/// The rotation is trained and applied in float32, so f16 and f64 vectors are
+/// converted to float32 first.
+fn to_f32_matrix(data: &FixedSizeListArray) -> Result<MatrixView<Float32Type>> {
+    let values = to_float32_array(data.values().as_ref())?;
+    Ok(MatrixView::new(
+        Arc::new(values),
+        data.value_length() as usize,
+    ))
+}
+
+/// Initialize rotation matrix as a random orthogonal matrix.
+fn init_rotation(dimension: usize, rng: impl Rng) -> Result<MatrixView<Float32Type>> {
+    let mat = MatrixView::<Float32Type>::random_with(dimension, dimension, rng);
     let (u, _, vt) = mat.svd()?;
-    let r = vt.transpose().dot(&u.transpose())?;
-    Ok(r)
+    Ok(u.dot(&vt)?)
+}
+
+/// Move each PQ centroid to the mean of the (rotated) sub-vectors assigned to it.
+///
+/// This is one step of kmeans, warm-started from the previous codebook.
+/// Centroids without any assigned sub-vector are kept as is.
+fn update_codebook(
+    data: &MatrixView<Float32Type>,
+    codes: &[u8],
+    codebook: &Float32Array,
+    num_sub_vectors: usize,
+    num_bits: u32,
+) -> Float32Array {
+    let dim = data.num_columns();
+    let sub_vector_width = dim / num_sub_vectors;
+    let num_centroids = num_centroids(num_bits);
+
+    let mut sums = vec![0_f64; codebook.len()];
+    let mut counts = vec![0_usize; num_centroids * num_sub_vectors];
+    for (vector, code) in data.iter().zip(codes.chunks_exact(num_sub_vectors)) {
+        for (sub_idx, (sub_vector, &c)) in vector
+            .chunks_exact(sub_vector_width)
+            .zip(code.iter())
+            .enumerate()
+        {
+            let centroid_idx = sub_idx * num_centroids + c as usize;
+            counts[centroid_idx] += 1;
+            sums[centroid_idx * sub_vector_width..(centroid_idx + 1) * sub_vector_width]
+                .iter_mut()
+                .zip(sub_vector.iter())
+                .for_each(|(s, &v)| *s += v as f64);
+        }
+    }
+
+    Float32Array::from_iter_values(
+        codebook
+            .values()
+            .chunks_exact(sub_vector_width)
+            .zip(sums.chunks_exact(sub_vector_width))
+            .zip(counts.iter())
+            .flat_map(|((old, sum), &count)| {
+                old.iter().zip(sum.iter()).map(move |(&o, &s)| {
+                    if count > 0 {
+                        (s / count as f64) as f32
+                    } else {
+                        o
+                    }
+                })
+            }),
+    )
 }
 
 #[async_trait]
 impl Transformer for OptimizedProductQuantizer {
-    async fn train(&mut self, data: &MatrixView) -> Result<()> {
+    /// Train the rotation matrix by alternating between the PQ codebook and the rotation.
+    ///
+    /// Minimizing the reconstruction error `||X * R - Y||` is an L2 objective regardless
+    /// of the metric of the index, and rotations preserve L2, cosine and dot distances.
+    async fn train(&mut self, data: &MatrixView<Float32Type>) -> Result<()> {
         let dim = data.num_columns();
+        if self.num_sub_vectors == 0 || dim % self.num_sub_vectors != 0 {
+            return Err(Error::Index {
+                message: format!(
+                    "OPQ: dimension {} is not divisible by the number of sub vectors {}",
+                    dim, self.num_sub_vectors
+                ),
+                location: location!(),
+            });
+        }
 
-        let num_centroids = ProductQuantizer::num_centroids(self.num_bits);
+        let mut rng = match self.seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        };
+        let num_centroids = num_centroids(self.num_bits);
         // See in Faiss, it does not train more than `256*n_centroids` samples
         let train = if data.num_rows() > num_centroids * 256 {
-            println!(
-                "Sample {} out of {} to train kmeans of {} dim, {} clusters",
+            debug!(
+                "Sample {} out of {} to train OPQ of {} dim, {} clusters",
                 256 * num_centroids,
                 data.num_rows(),
                 data.num_columns(),
                 num_centroids,
             );
-            data.sample(num_centroids * 256)
+            data.sample_with(num_centroids * 256, &mut rng)
         } else {
             data.clone()
         };
 
-        // Run a few iterations to get the initialized centroids.
-        let pq = ProductQuantizer::train(&train, self.metric_type, OPQ_PQ_INIT_ITERATIONS).await?;
-        let mut pq_code = pq.transform(&train, self.metric_type).await?;
+        // Initialize R (rotation matrix), and run a few iterations of kmeans to get
+        // the initial PQ codebook on the rotated data.
+        let mut rotation = init_rotation(dim, &mut rng)?;
+        let mut rotated = train.dot(&rotation)?;
+        let params = PQBuildParams {
+            num_sub_vectors: self.num_sub_vectors,
+            num_bits: self.num_bits as usize,
+            max_iters: OPQ_PQ_INIT_ITERATIONS,
+            seed: self.seed,
+            ..Default::default()
+        };
+        let pq = params.build_from_matrix(&rotated, MetricType::L2).await?;
+        let mut codebook = pq
+            .as_any()
+            .downcast_ref::<ProductQuantizerImpl<Float32Type>>()
+            .ok_or_else(|| Error::Index {
+                message: "OPQ: only supports float32 product quantizer".to_string(),
+                location: location!(),
+            })?
+            .codebook
+            .clone();
 
-        // Initialize R (rotation matrix)
-        let mut rotation = init_rotation(dim)?;
         for i in 0..self.num_iters {
-            // Training data, this is the `X`, described in CVPR' 13
-            let rotated_data = train.dot(&rotation)?;
-            // Reset the pq centroids after rotation.
-            pq.reset_centroids(&rotated_data, &pq_code)?;
-            (rotation, pq_code) = self
-                .train_once(&mut pq, &rotated_data, self.metric_type)
-                .await?;
+            let pq = ProductQuantizerImpl::<Float32Type>::new(
+                self.num_sub_vectors,
+                self.num_bits,
+                dim,
+                codebook.clone(),
+                MetricType::L2,
+            );
+            let vectors = FixedSizeListArray::try_new_from_values(
+                rotated.data().as_ref().clone(),
+                dim as i32,
+            )?;
+            let pq_code = pq.transform(&vectors).await?;
+            let pq_code = pq_code
+                .as_fixed_size_list()
+                .values()
+                .as_primitive::<UInt8Type>()
+                .clone();
+
             if (i + 1) % 5 == 0 {
-                println!(
+                debug!(
                     "Training OPQ iteration {}/{}, PQ distortion={}",
                     i + 1,
                     self.num_iters,
-                    pq.distortion(&rotated_data, self.metric_type).await?
+                    pq.distortion(&rotated, MetricType::L2).await?
                 );
             }
+
+            // Reconstructed vectors, `Y` in Section 3.1 in CVPR' 13.
+            let mut y = Vec::with_capacity(train.num_rows() * dim);
+            for code in pq_code.values().chunks_exact(self.num_sub_vectors) {
+                y.extend_from_slice(pq.reconstruct(code).values());
+            }
+            let y = MatrixView::<Float32Type>::new(Arc::new(Float32Array::from(y)), dim);
+
+            // Solving `min||X * R - Y||` over orthogonal `R` (Orthogonal Procrustes):
+            //
+            //  T(X) * Y = U * S * T(V)
+            //  R = U * T(V)
+            let (u, _, vt) = train.transpose().dot(&y)?.svd()?;
+            rotation = u.dot(&vt)?;
+
+            // Fit the codebook to the newly rotated data.
+            rotated = train.dot(&rotation)?;
+            codebook = Arc::new(update_codebook(
+                &rotated,
+                pq_code.values(),
+                &codebook,
+                self.num_sub_vectors,
+                self.num_bits,
+            ));
         }
         self.rotation = Some(rotation);
         Ok(())
     }
 
     /// Apply OPQ transform
-    async fn transform(&self, data: &MatrixView) -> Result<MatrixView> {
-        let result = self
-            .rotation
-            .as_ref()
-            .unwrap()
-            .dot(&data.transpose())?
-            .transpose();
-        Ok(MatrixView::new(result.data(), result.num_columns()))
+    async fn transform(&self, data: &FixedSizeListArray) -> Result<FixedSizeListArray> {
+        let rotation = self.rotation()?;
+        let mat = to_f32_matrix(data)?;
+        let rotated = mat.dot(rotation)?;
+        Ok(FixedSizeListArray::try_new_from_values(
+            rotated.data().as_ref().clone(),
+            rotated.num_columns() as i32,
+        )?)
     }
 
     /// Write the OPQ rotation matrix to disk.
     async fn save(&self, writer: &mut ObjectWriter) -> Result<Transform> {
-        let mut this = self.clone();
-        if this.rotation.is_none() {
-            return Err(Error::Index {
-                message: "OPQ is not trained".to_string(),
-            });
-        };
-        let rotation = this.rotation.as_ref().unwrap();
-        let position = writer
-            .write_plain_encoded_array(rotation.data().as_ref())
-            .await?;
-        this.file_position = Some(position);
-        (&this).try_into()
+        let rotation = self.rotation()?;
+        let data = rotation.data();
+        let position = PlainEncoder::write(writer, &[data.as_ref()]).await?;
+        Ok(Transform {
+            position: position as u64,
+            shape: vec![rotation.num_rows() as u32, rotation.num_columns() as u32],
+            r#type: TransformType::Opq.into(),
+        })
     }
 }
 
+/// Rotates the query vector with OPQ before searching the sub-index.
 pub struct OPQIndex {
     sub_index: Arc<dyn VectorIndex>,
 
@@ -259,6 +360,11 @@ impl OPQIndex {
     pub(crate) fn new(sub_index: Arc<dyn VectorIndex>, opq: OptimizedProductQuantizer) -> Self {
         Self { sub_index, opq }
     }
+
+    /// The index of the rotated vectors.
+    pub(crate) fn sub_index(&self) -> &Arc<dyn VectorIndex> {
+        &self.sub_index
+    }
 }
 
 impl std::fmt::Debug for OPQIndex {
@@ -283,6 +389,7 @@ pub struct OPQIndexStatistics {
     sub_index: serde_json::Value,
 }
 
+#[async_trait]
 impl Index for OPQIndex {
     fn as_any(&self) -> &dyn Any {
         self
@@ -292,28 +399,46 @@ impl Index for OPQIndex {
         self
     }
 
+    fn index_type(&self) -> IndexType {
+        IndexType::Vector
+    }
+
+    fn memory_size(&self) -> usize {
+        let rotation = self
+            .opq
+            .rotation
+            .as_ref()
+            .map_or(0, |m| m.num_rows() * m.num_columns());
+        self.sub_index.memory_size() + rotation * std::mem::size_of::<f32>()
+    }
+
     fn statistics(&self) -> Result<String> {
         Ok(serde_json::to_string(&OPQIndexStatistics {
-            index_type: "OPQIndex".to_string(),
+            index_type: "OPQ".to_string(),
             dim: self
                 .opq
                 .rotation
                 .as_ref()
                 .map(|m| m.num_columns())
                 .unwrap_or(0),
-            sub_index: self.sub_index.statistics()?,
+            sub_index: serde_json::from_str(&self.sub_index.statistics()?)?,
         })?)
     }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        self.sub_index.calculate_included_frags().await
+    }
 }
 
 #[async_trait]
 impl VectorIndex for OPQIndex {
     #[instrument(level = "debug", skip_all, name = "OPQIndex::search")]
-    async fn search(&self, query: &Query, pre_filter: &PreFilter) -> Result<RecordBatch> {
-        let mat = MatrixView::new(query.key.clone(), query.key.len());
-        let transformed = self.opq.transform(&mat).await?;
+    async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch> {
+        let key =
+            FixedSizeListArray::try_new_from_values(query.key.clone(), query.key.len() as i32)?;
+        let transformed = self.opq.transform(&key).await?;
         let mut transformed_query = query.clone();
-        transformed_query.key = transformed.data();
+        transformed_query.key = transformed.values().clone();
         self.sub_index.search(&transformed_query, pre_filter).await
     }
 
@@ -323,40 +448,33 @@ impl VectorIndex for OPQIndex {
 
     async fn load(
         &self,
-        _reader: &dyn ObjectReader,
+        _reader: &dyn Reader,
         _offset: usize,
         _length: usize,
-    ) -> Result<Arc<dyn VectorIndex>> {
+    ) -> Result<Box<dyn VectorIndex>> {
         Err(Error::Index {
             message: "OPQ does not support load".to_string(),
+            location: location!(),
         })
     }
 
     fn check_can_remap(&self) -> Result<()> {
         Err(Error::NotSupported {
-            source: "DiskANNIndex does not yet support remap".into(),
+            source: "OPQIndex does not yet support remap".into(),
+            location: location!(),
         })
     }
-}
-
-impl TryFrom<&OptimizedProductQuantizer> for Transform {
-    type Error = Error;
 
-    fn try_from(opq: &OptimizedProductQuantizer) -> Result<Self> {
-        if opq.file_position.is_none() {
-            return Err(Error::Index {
-                message: "OPQ has not been persisted yet".to_string(),
-            });
-        }
-        let rotation = opq.rotation.as_ref().ok_or(Error::Index {
-            message: "OPQ is not trained".to_string(),
-        })?;
-        Ok(Self {
-            position: opq.file_position.unwrap() as u64,
-            shape: vec![rotation.num_rows() as u32, rotation.num_columns() as u32],
-            r#type: TransformType::Opq.into(),
+    fn remap(&mut self, _mapping: &IntMap<u64, Option<u64>>) -> Result<()> {
+        Err(Error::NotSupported {
+            source: "OPQIndex does not yet support remap".into(),
+            location: location!(),
         })
     }
+
+    fn metric_type(&self) -> MetricType {
+        self.sub_index.metric_type()
+    }
 }
 
 #[cfg(test)]
@@ -365,15 +483,13 @@ mod tests {
 
     use approx::assert_relative_eq;
     use arrow::compute::{max, min};
-    use arrow_array::{FixedSizeListArray, Float32Array, RecordBatchIterator, UInt64Array};
+    use arrow_array::{cast::as_primitive_array, RecordBatchIterator, UInt64Array};
     use arrow_schema::{Field as ArrowField, Schema as ArrowSchema};
 
-    use crate::arrow::{linalg::matrix::MatrixView, *};
     use crate::dataset::{Dataset, ROW_ID};
-    use crate::index::DatasetIndexExt;
     use crate::index::{
-        vector::{ivf::IVFIndex, open_vector_index, opq::OPQIndex, VectorIndexParams},
-        IndexType,
+        vector::{ivf::IVFIndex, VectorIndexParams},
+        DatasetIndexExt, DatasetIndexInternalExt,
     };
 
     #[tokio::test]
@@ -382,7 +498,7 @@ mod tests {
         let data = Arc::new(Float32Array::from_iter((0..12800).map(|v| v as f32)));
         let matrix = MatrixView::new(data, DIM);
 
-        let mut opq = OptimizedProductQuantizer::new(4, 8, MetricType::L2, 10);
+        let mut opq = OptimizedProductQuantizer::new(4, 8, 10);
         opq.train(&matrix).await.unwrap();
 
         assert_eq!(opq.rotation.as_ref().unwrap().num_rows(), DIM);
@@ -420,21 +536,16 @@ mod tests {
             .await
             .unwrap();
 
-        let index_file = std::fs::read_dir(tmp_dir.path().join("_indices"))
-            .unwrap()
-            .next()
-            .unwrap()
-            .unwrap();
-        let uuid = index_file.file_name().to_str().unwrap().to_string();
-
         let dataset = Arc::new(dataset);
-        let index = open_vector_index(dataset.clone(), "vector", &uuid)
+        let index_meta = dataset.load_indices().await.unwrap()[0].clone();
+        let index = dataset
+            .open_vector_index(column, &index_meta.uuid.to_string())
             .await
             .unwrap();
 
         if with_opq {
             let opq_idx = index.as_any().downcast_ref::<OPQIndex>().unwrap();
-            assert!(opq_idx.opq.rotation.is_some());
+            assert!(opq_idx.sub_index.as_any().is::<IVFIndex>());
 
             let rotation = opq_idx.opq.rotation.as_ref().unwrap();
             assert_eq!(rotation.num_rows(), 64);
@@ -448,12 +559,21 @@ mod tests {
             k: 4,
             nprobes: 10,
             refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: None,
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
             metric_type: MetricType::L2,
             use_index: true,
-            key: Float32Array::from_iter_values((0..64).map(|x| x as f32 + 640.0)).into(),
+            key: Arc::new(Float32Array::from_iter_values(
+                (0..64).map(|x| x as f32 + 640.0),
+            )),
         };
-        let pre_filter = PreFilter::new(dataset);
-        let results = index.search(&query, &pre_filter).await.unwrap();
+        let pre_filter = Arc::new(PreFilter::new(dataset.clone(), index_meta, None));
+        let results = index.search(&query, pre_filter).await.unwrap();
         let row_ids: &UInt64Array = as_primitive_array(&results[ROW_ID]);
         assert_eq!(row_ids.len(), 4);
         assert!(row_ids.values().contains(&10));
@@ -473,12 +593,15 @@ mod tests {
     #[test]
     fn test_init_rotation() {
         let dim: usize = 64;
-        let r = init_rotation(dim).unwrap();
+        let r = init_rotation(dim, SmallRng::from_entropy()).unwrap();
         // R^T * R = I
         let i = r.transpose().dot(&r).unwrap();
 
         let expected = i.data().values().to_vec();
-        let result = MatrixView::identity(dim).data().values().to_vec();
+        let result = MatrixView::<Float32Type>::identity(dim)
+            .data()
+            .values()
+            .to_vec();
         expected.iter().zip(result).for_each(|(&e, r)| {
             assert_relative_eq!(e, r, epsilon = 0.001);
         });
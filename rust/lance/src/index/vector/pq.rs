@@ -17,7 +17,7 @@ use std::sync::Arc;
 
 use arrow_array::{
     cast::{as_primitive_array, AsArray},
-    Array, FixedSizeListArray, RecordBatch, UInt64Array, UInt8Array,
+    Array, FixedSizeListArray, RecordBatch, UInt32Array, UInt64Array, UInt8Array,
 };
 use arrow_ord::sort::sort_to_indices;
 use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
@@ -31,7 +31,7 @@ use lance_core::{
 };
 pub use lance_index::vector::pq::{PQBuildParams, ProductQuantizerImpl};
 use lance_index::{
-    vector::{pq::ProductQuantizer, Query, DIST_COL},
+    vector::{pq::ProductQuantizer, Query, DIST_COL, PQ_CODE_COLUMN},
     Index, IndexType,
 };
 use lance_linalg::distance::MetricType;
@@ -86,6 +86,29 @@ impl PQIndex {
         }
     }
 
+    /// The row ids and the PQ codes of the loaded vectors, with the columns `_rowid`
+    /// and `__pq_code`.
+    pub(crate) fn codes_to_batch(&self) -> Result<RecordBatch> {
+        let (Some(code), Some(row_ids)) = (&self.code, &self.row_ids) else {
+            return Err(Error::Index {
+                message: "PQIndex::codes_to_batch: PQ is not initialized".to_string(),
+                location: location!(),
+            });
+        };
+        let code = FixedSizeListArray::try_new_from_values(
+            code.as_ref().clone(),
+            self.pq.num_sub_vectors() as i32,
+        )?;
+        let schema = ArrowSchema::new(vec![
+            ROW_ID_FIELD.clone(),
+            ArrowField::new(PQ_CODE_COLUMN, code.data_type().clone(), false),
+        ]);
+        Ok(RecordBatch::try_new(
+            Arc::new(schema),
+            vec![row_ids.clone(), Arc::new(code)],
+        )?)
+    }
+
     /// Filter the row id and PQ code arrays based on the pre-filter.
     fn filter_arrays(
         pre_filter: &PreFilter,
@@ -109,6 +132,33 @@ impl PQIndex {
     }
 }
 
+/// The codebook of `pq`, one row per centroid, to inspect the quantization offline.
+///
+/// The columns are the `sub_vector` and the `centroid` ids, and the `values` of the
+/// centroid, i.e., `dimension / num_sub_vectors` floats.
+pub fn codebook_to_batch(pq: &dyn ProductQuantizer) -> Result<RecordBatch> {
+    let codebook = pq.codebook_as_fsl();
+    let sub_vector_dim = pq.dimension() / pq.num_sub_vectors();
+    let values =
+        FixedSizeListArray::try_new_from_values(codebook.values().clone(), sub_vector_dim as i32)?;
+    let num_centroids = values.len() / pq.num_sub_vectors();
+    let sub_vectors = UInt32Array::from_iter_values(
+        (0..pq.num_sub_vectors() as u32).flat_map(|i| std::iter::repeat(i).take(num_centroids)),
+    );
+    let centroids = UInt32Array::from_iter_values(
+        (0..pq.num_sub_vectors()).flat_map(|_| 0..num_centroids as u32),
+    );
+    let schema = ArrowSchema::new(vec![
+        ArrowField::new("sub_vector", DataType::UInt32, false),
+        ArrowField::new("centroid", DataType::UInt32, false),
+        ArrowField::new("values", values.data_type().clone(), false),
+    ]);
+    Ok(RecordBatch::try_new(
+        Arc::new(schema),
+        vec![Arc::new(sub_vectors), Arc::new(centroids), Arc::new(values)],
+    )?)
+}
+
 #[derive(Serialize)]
 pub struct PQIndexStatistics {
     index_type: String,
@@ -132,6 +182,14 @@ impl Index for PQIndex {
         IndexType::Vector
     }
 
+    fn memory_size(&self) -> usize {
+        self.code.as_ref().map_or(0, |c| c.get_array_memory_size())
+            + self
+                .row_ids
+                .as_ref()
+                .map_or(0, |r| r.get_array_memory_size())
+    }
+
     fn statistics(&self) -> Result<String> {
         Ok(serde_json::to_string(&PQIndexStatistics {
             index_type: "PQ".to_string(),
@@ -192,6 +250,7 @@ impl VectorIndex for PQIndex {
             let distances = pq.build_distance_table(query.key.as_ref(), &code)?;
 
             debug_assert_eq!(distances.len(), row_ids.len());
+            let (distances, row_ids) = query.filter_distance_range(distances, row_ids)?;
 
             let limit = query.k * query.refine_factor.unwrap_or(1) as usize;
             let indices = sort_to_indices(&distances, None, Some(limit))?;
@@ -264,6 +323,10 @@ impl VectorIndex for PQIndex {
         )));
         Ok(())
     }
+
+    fn metric_type(&self) -> MetricType {
+        self.metric_type
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,547 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Inverted index of sparse vectors, i.e., SPLADE or BM25-weighted embeddings.
+//!
+//! Each non-zero index (term) has a posting list of the rows with the term and
+//! their weights. The dot products are accumulated over the posting lists of the
+//! query terms, so the search is exact.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use arrow_array::{
+    builder::{Float32Builder, ListBuilder, UInt64Builder},
+    cast::AsArray,
+    types::{Float32Type, UInt32Type, UInt64Type},
+    ArrayRef, Float32Array, RecordBatch, UInt32Array, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema as ArrowSchema};
+use async_trait::async_trait;
+use futures::TryStreamExt;
+use lance_core::{
+    datatypes::Schema,
+    format::RowAddress,
+    io::{object_store::ObjectStore, FileReader, FileWriter, Reader, WriteExt},
+    ROW_ID_FIELD,
+};
+use lance_index::{
+    vector::{
+        sparse::{as_sparse_vectors, is_sparse_vector_type, sparse_vector_at},
+        Query, DIST_COL,
+    },
+    Index, IndexType,
+};
+use lance_linalg::distance::MetricType;
+use nohash_hasher::IntMap;
+use object_store::path::Path;
+use roaring::RoaringBitmap;
+use serde::Serialize;
+use snafu::{location, Location};
+use tracing::instrument;
+
+use super::VectorIndex;
+use crate::dataset::{Dataset, ROW_ID};
+use crate::index::{pb, prefilter::PreFilter, INDEX_FILE_NAME};
+use crate::utils::tokio::spawn_cpu;
+use crate::{Error, Result};
+
+const POSTINGS_FILE_NAME: &str = "sparse_postings.lance";
+
+/// Column of the terms in the postings file.
+const TERM_COL: &str = "term";
+
+/// Column of the row ids of each posting list.
+const ROW_IDS_COL: &str = "row_ids";
+
+/// Column of the weights of each posting list.
+const WEIGHTS_COL: &str = "weights";
+
+/// Rows with a term, and the weights of the term in these rows.
+#[derive(Debug, Default)]
+struct PostingList {
+    row_ids: Vec<u64>,
+    weights: Vec<f32>,
+}
+
+/// Inverted index of a sparse vector column. All the posting lists are loaded into memory.
+pub struct SparseIndex {
+    postings: Arc<IntMap<u32, PostingList>>,
+}
+
+impl std::fmt::Debug for SparseIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sparse(num_terms={})", self.postings.len())
+    }
+}
+
+/// Build an inverted index on the sparse vector column.
+pub(crate) async fn build_sparse_index(
+    dataset: &Dataset,
+    column: &str,
+    name: &str,
+    uuid: &str,
+    metric_type: MetricType,
+) -> Result<()> {
+    if metric_type != MetricType::Dot {
+        return Err(Error::Index {
+            message: format!(
+                "Sparse index only supports dot product, got {}",
+                metric_type
+            ),
+            location: location!(),
+        });
+    }
+    let field = dataset.schema().field(column).ok_or_else(|| Error::Index {
+        message: format!("Sparse index: column {} does not exist", column),
+        location: location!(),
+    })?;
+    if !is_sparse_vector_type(&field.data_type()) {
+        return Err(Error::Index {
+            message: format!(
+                "Sparse index requires the column to be struct of indices and values, got {}",
+                field.data_type()
+            ),
+            location: location!(),
+        });
+    }
+
+    let mut stream = dataset
+        .scan()
+        .project(&[column])?
+        .with_index_row_id()
+        .try_into_stream()
+        .await?;
+    let mut postings = IntMap::<u32, PostingList>::default();
+    while let Some(batch) = stream.try_next().await? {
+        let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+        let (indices, values) = as_sparse_vectors(batch[column].as_ref())?;
+        for (i, row_id) in row_ids.values().iter().enumerate() {
+            if batch[column].is_null(i) {
+                continue;
+            }
+            let Some((indices, values)) = sparse_vector_at(indices, values, i)? else {
+                continue;
+            };
+            for (term, weight) in indices.into_iter().zip(values) {
+                let posting = postings.entry(term).or_default();
+                posting.row_ids.push(*row_id);
+                posting.weights.push(weight);
+            }
+        }
+    }
+
+    let index_dir = dataset.indices_dir().child(uuid);
+    let postings_file = index_dir.child(POSTINGS_FILE_NAME);
+    write_postings(&postings, dataset.object_store(), &postings_file).await?;
+
+    write_index_file(dataset, column, name, uuid, &postings).await
+}
+
+/// Persist the posting lists into a lance file, one row per term.
+async fn write_postings(
+    postings: &IntMap<u32, PostingList>,
+    object_store: &ObjectStore,
+    path: &Path,
+) -> Result<()> {
+    let arrow_schema = Arc::new(ArrowSchema::new(vec![
+        Field::new(TERM_COL, DataType::UInt32, false),
+        Field::new(
+            ROW_IDS_COL,
+            DataType::List(Arc::new(Field::new("item", DataType::UInt64, true))),
+            false,
+        ),
+        Field::new(
+            WEIGHTS_COL,
+            DataType::List(Arc::new(Field::new("item", DataType::Float32, true))),
+            false,
+        ),
+    ]));
+    let schema = Schema::try_from(arrow_schema.as_ref())?;
+
+    let mut terms = postings.keys().copied().collect::<Vec<_>>();
+    terms.sort();
+    let mut row_ids_builder = ListBuilder::new(UInt64Builder::new());
+    let mut weights_builder = ListBuilder::new(Float32Builder::new());
+    for term in terms.iter() {
+        let posting = &postings[term];
+        row_ids_builder.values().append_slice(&posting.row_ids);
+        row_ids_builder.append(true);
+        weights_builder.values().append_slice(&posting.weights);
+        weights_builder.append(true);
+    }
+    let batch = RecordBatch::try_new(
+        arrow_schema,
+        vec![
+            Arc::new(UInt32Array::from(terms)) as ArrayRef,
+            Arc::new(row_ids_builder.finish()),
+            Arc::new(weights_builder.finish()),
+        ],
+    )?;
+
+    let mut writer = FileWriter::try_new(object_store, path, schema, &Default::default()).await?;
+    if batch.num_rows() > 0 {
+        writer.write(&[batch]).await?;
+    }
+    writer.finish().await?;
+    Ok(())
+}
+
+async fn write_index_file(
+    dataset: &Dataset,
+    column: &str,
+    index_name: &str,
+    uuid: &str,
+    postings: &IntMap<u32, PostingList>,
+) -> Result<()> {
+    let object_store = dataset.object_store();
+    let path = dataset.indices_dir().child(uuid).child(INDEX_FILE_NAME);
+    let mut writer = object_store.create(&path).await?;
+
+    let stages = vec![pb::VectorIndexStage {
+        stage: Some(pb::vector_index_stage::Stage::Sparse(pb::Sparse {
+            filename: POSTINGS_FILE_NAME.to_string(),
+            num_terms: postings.len() as u32,
+        })),
+    }];
+    let metadata = pb::Index {
+        name: index_name.to_string(),
+        columns: vec![column.to_string()],
+        dataset_version: dataset.version().version,
+        index_type: pb::IndexType::Vector.into(),
+        implementation: Some(pb::index::Implementation::VectorIndex(pb::VectorIndex {
+            spec_version: 1,
+            // The vocabulary size, i.e., the largest term plus one.
+            dimension: postings.keys().max().map_or(0, |t| t + 1),
+            stages,
+            metric_type: pb::VectorMetricType::try_from(MetricType::Dot)?.into(),
+        })),
+    };
+
+    let pos = writer.write_protobuf(&metadata).await?;
+    writer.write_magics(pos).await?;
+    writer.shutdown().await?;
+
+    Ok(())
+}
+
+impl SparseIndex {
+    /// Load the posting lists from the postings file.
+    pub async fn load(
+        object_store: &ObjectStore,
+        postings_path: &Path,
+        proto: &pb::Sparse,
+    ) -> Result<Self> {
+        let reader = FileReader::try_new(object_store, postings_path).await?;
+        let mut postings = IntMap::default();
+        if !reader.is_empty() {
+            let batch = reader.read_range(0..reader.len(), reader.schema()).await?;
+            let terms = batch[TERM_COL].as_primitive::<UInt32Type>();
+            let row_ids = batch[ROW_IDS_COL].as_list::<i32>();
+            let weights = batch[WEIGHTS_COL].as_list::<i32>();
+            for (i, term) in terms.values().iter().enumerate() {
+                postings.insert(
+                    *term,
+                    PostingList {
+                        row_ids: row_ids
+                            .value(i)
+                            .as_primitive::<UInt64Type>()
+                            .values()
+                            .to_vec(),
+                        weights: weights
+                            .value(i)
+                            .as_primitive::<Float32Type>()
+                            .values()
+                            .to_vec(),
+                    },
+                );
+            }
+        }
+        if postings.len() != proto.num_terms as usize {
+            return Err(Error::Index {
+                message: format!(
+                    "Sparse index: expect {} posting lists, got {}",
+                    proto.num_terms,
+                    postings.len()
+                ),
+                location: location!(),
+            });
+        }
+        Ok(Self {
+            postings: Arc::new(postings),
+        })
+    }
+}
+
+#[derive(Serialize)]
+pub struct SparseIndexStatistics {
+    index_type: String,
+    metric_type: String,
+    num_terms: usize,
+    num_postings: usize,
+}
+
+#[async_trait]
+impl Index for SparseIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn index_type(&self) -> IndexType {
+        IndexType::Vector
+    }
+
+    fn memory_size(&self) -> usize {
+        self.postings
+            .values()
+            .map(|p| {
+                p.row_ids.len() * std::mem::size_of::<u64>()
+                    + p.weights.len() * std::mem::size_of::<f32>()
+            })
+            .sum()
+    }
+
+    fn statistics(&self) -> Result<String> {
+        Ok(serde_json::to_string(&SparseIndexStatistics {
+            index_type: "SPARSE".to_string(),
+            metric_type: MetricType::Dot.to_string(),
+            num_terms: self.postings.len(),
+            num_postings: self.postings.values().map(|p| p.row_ids.len()).sum(),
+        })?)
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        let mut frag_ids = self
+            .postings
+            .values()
+            .flat_map(|p| p.row_ids.iter())
+            .map(|&row_id| RowAddress::new_from_id(row_id).fragment_id())
+            .collect::<Vec<_>>();
+        frag_ids.sort();
+        frag_ids.dedup();
+        Ok(RoaringBitmap::from_sorted_iter(frag_ids).unwrap())
+    }
+}
+
+#[async_trait]
+impl VectorIndex for SparseIndex {
+    #[instrument(level = "debug", skip_all, name = "SparseIndex::search")]
+    async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch> {
+        let (indices, values) = as_sparse_vectors(query.key.as_ref())?;
+        let Some((indices, values)) = sparse_vector_at(indices, values, 0)? else {
+            return Err(Error::Index {
+                message: "Sparse query vector must not be null".to_string(),
+                location: location!(),
+            });
+        };
+        let k = query.k * query.refine_factor.unwrap_or(1) as usize;
+
+        pre_filter.wait_for_ready().await?;
+        let postings = self.postings.clone();
+        let query = query.clone();
+        spawn_cpu(move || {
+            let mut scores = HashMap::<u64, f32>::new();
+            for (term, value) in indices.iter().zip(values.iter()) {
+                let Some(posting) = postings.get(term) else {
+                    continue;
+                };
+                for (row_id, weight) in posting.row_ids.iter().zip(posting.weights.iter()) {
+                    *scores.entry(*row_id).or_default() += value * weight;
+                }
+            }
+
+            // Dot distance is the negative dot product.
+            let mut candidates = scores
+                .into_iter()
+                .map(|(row_id, score)| (-score, row_id))
+                .filter(|(dist, _)| query.in_range(*dist))
+                .filter(|(_, row_id)| pre_filter.is_empty() || pre_filter.check_one(*row_id))
+                .collect::<Vec<_>>();
+            candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            candidates.truncate(k);
+            let (distances, row_ids): (Vec<f32>, Vec<u64>) = candidates.into_iter().unzip();
+
+            let schema = Arc::new(ArrowSchema::new(vec![
+                Field::new(DIST_COL, DataType::Float32, true),
+                ROW_ID_FIELD.clone(),
+            ]));
+            Ok(RecordBatch::try_new(
+                schema,
+                vec![
+                    Arc::new(Float32Array::from(distances)) as ArrayRef,
+                    Arc::new(UInt64Array::from(row_ids)) as ArrayRef,
+                ],
+            )?)
+        })
+        .await
+    }
+
+    fn is_loadable(&self) -> bool {
+        false
+    }
+
+    async fn load(
+        &self,
+        _reader: &dyn Reader,
+        _offset: usize,
+        _length: usize,
+    ) -> Result<Box<dyn VectorIndex>> {
+        Err(Error::Index {
+            message: "SparseIndex is not loadable".to_string(),
+            location: location!(),
+        })
+    }
+
+    fn check_can_remap(&self) -> Result<()> {
+        Err(Error::NotSupported {
+            source: "SparseIndex does not yet support remap".into(),
+            location: location!(),
+        })
+    }
+
+    fn remap(&mut self, _mapping: &IntMap<u64, Option<u64>>) -> Result<()> {
+        Err(Error::NotSupported {
+            source: "SparseIndex does not yet support remap".into(),
+            location: location!(),
+        })
+    }
+
+    fn metric_type(&self) -> MetricType {
+        MetricType::Dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use approx::assert_relative_eq;
+    use arrow_array::{types::Int32Type, Int32Array, RecordBatchIterator};
+    use lance_index::vector::sparse::{sparse_vector_array, sparse_vector_type};
+    use rand::{rngs::SmallRng, seq::index::sample, Rng, SeedableRng};
+    use tempfile::tempdir;
+
+    use crate::index::{vector::VectorIndexParams, DatasetIndexExt};
+
+    fn random_sparse_batch(
+        schema: Arc<ArrowSchema>,
+        ids: std::ops::Range<i32>,
+        rng: &mut impl Rng,
+    ) -> RecordBatch {
+        let vectors = sparse_vector_array(ids.clone().map(|_| {
+            let indices = sample(rng, 1000, 20)
+                .into_iter()
+                .map(|i| i as u32)
+                .collect();
+            let values = (0..20).map(|_| rng.gen::<f32>()).collect();
+            (indices, values)
+        }))
+        .unwrap();
+        RecordBatch::try_new(
+            schema,
+            vec![
+                Arc::new(Int32Array::from_iter_values(ids)),
+                Arc::new(vectors),
+            ],
+        )
+        .unwrap()
+    }
+
+    async fn search(dataset: &Dataset, indices: &[u32], values: &[f32]) -> RecordBatch {
+        let batches = dataset
+            .scan()
+            .nearest_sparse("sparse", indices, values, 10)
+            .unwrap()
+            .filter("id % 2 = 0")
+            .unwrap()
+            .prefilter(true)
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        arrow_select::concat::concat_batches(&batches[0].schema(), &batches).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_create_and_search_sparse_index() {
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let schema = Arc::new(ArrowSchema::new(vec![
+            Field::new("id", DataType::Int32, false),
+            Field::new("sparse", sparse_vector_type(), true),
+        ]));
+        let mut rng = SmallRng::seed_from_u64(28);
+        let batch = random_sparse_batch(schema.clone(), 0..500, &mut rng);
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let mut dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        let indices = (0..1000).step_by(10).collect::<Vec<u32>>();
+        let values = (0..100).map(|v| v as f32 / 100.0).collect::<Vec<_>>();
+        let expected = search(&dataset, &indices, &values).await;
+        assert_eq!(expected.num_rows(), 10);
+
+        let mut params = VectorIndexParams::sparse();
+        params.metric_type = MetricType::L2;
+        assert!(dataset
+            .create_index(&["sparse"], IndexType::Vector, None, &params, false)
+            .await
+            .is_err());
+        dataset
+            .create_index(
+                &["sparse"],
+                IndexType::Vector,
+                None,
+                &VectorIndexParams::sparse(),
+                false,
+            )
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_str(
+            &dataset
+                .index_statistics("sparse_idx")
+                .await
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(stats["index_type"], "SPARSE");
+        assert_eq!(stats["num_postings"], 500 * 20);
+
+        // The inverted index is exact.
+        let results = search(&dataset, &indices, &values).await;
+        assert_eq!(results["id"].as_ref(), expected["id"].as_ref());
+        let distances = results[DIST_COL].as_primitive::<Float32Type>();
+        let expected_distances = expected[DIST_COL].as_primitive::<Float32Type>();
+        for (d, e) in distances.values().iter().zip(expected_distances.values()) {
+            assert_relative_eq!(d, e, epsilon = 1e-5);
+        }
+        let ids = results["id"].as_primitive::<Int32Type>();
+        assert!(ids.values().iter().all(|id| id % 2 == 0));
+
+        // The appended rows are searched with the flat search.
+        let batch = random_sparse_batch(schema.clone(), 500..1000, &mut rng);
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        dataset.append(reader, None).await.unwrap();
+        let results = search(&dataset, &indices, &values).await;
+        let ids = results["id"].as_primitive::<Int32Type>();
+        assert!(ids.values().iter().any(|&id| id >= 500));
+    }
+}
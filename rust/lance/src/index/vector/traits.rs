@@ -17,7 +17,7 @@
 
 use std::sync::Arc;
 
-use arrow_array::{types::Float32Type, FixedSizeListArray, RecordBatch};
+use arrow_array::{types::Float32Type, Array, FixedSizeListArray, RecordBatch};
 use async_trait::async_trait;
 
 use lance_core::{
@@ -25,7 +25,7 @@ use lance_core::{
     Result,
 };
 use lance_index::{vector::Query, Index};
-use lance_linalg::MatrixView;
+use lance_linalg::{distance::MetricType, MatrixView};
 use nohash_hasher::IntMap;
 
 use crate::index::{pb::Transform, prefilter::PreFilter};
@@ -54,6 +54,28 @@ pub trait VectorIndex: Send + Sync + std::fmt::Debug + Index {
     ///  - Only supports `f32` now. Will add f64/f16 later.
     async fn search(&self, query: &Query, pre_filter: Arc<PreFilter>) -> Result<RecordBatch>;
 
+    /// Search the nearest neighbors of each vector in `queries`.
+    ///
+    /// `query` carries the search parameters, and its `key` is ignored. It returns one
+    /// [RecordBatch] per query, in the same order as `queries`, with the same schema
+    /// as [VectorIndex::search].
+    ///
+    /// The default implementation searches the queries one by one.
+    async fn search_batch(
+        &self,
+        queries: &FixedSizeListArray,
+        query: &Query,
+        pre_filter: Arc<PreFilter>,
+    ) -> Result<Vec<RecordBatch>> {
+        let mut results = Vec::with_capacity(queries.len());
+        for i in 0..queries.len() {
+            let mut query = query.clone();
+            query.key = queries.value(i);
+            results.push(self.search(&query, pre_filter.clone()).await?);
+        }
+        Ok(results)
+    }
+
     /// If the index is loadable by IVF, so it can be a sub-index that
     /// is loaded on demand by IVF.
     fn is_loadable(&self) -> bool;
@@ -79,6 +101,9 @@ pub trait VectorIndex: Send + Sync + std::fmt::Debug + Index {
     /// If an old row id is not in the mapping then it should be
     /// left alone.
     fn remap(&mut self, mapping: &IntMap<u64, Option<u64>>) -> Result<()>;
+
+    /// The metric type used to build the index, which defines the distances it returns.
+    fn metric_type(&self) -> MetricType;
 }
 
 /// Transformer on vectors.
@@ -14,10 +14,18 @@
 
 use std::sync::Arc;
 
-use arrow_array::{cast::AsArray, FixedSizeListArray};
-use arrow_schema::Schema as ArrowSchema;
-use arrow_select::concat::concat_batches;
-use futures::stream::TryStreamExt;
+use arrow_array::{
+    cast::AsArray, types::Int8Type, Array, ArrayRef, BooleanArray, FixedSizeListArray,
+    Float32Array, ListArray, RecordBatch, UInt32Array,
+};
+use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
+use arrow_select::{
+    concat::concat_batches,
+    filter::{filter, filter_record_batch},
+};
+use futures::stream::{StreamExt, TryStreamExt};
+use lance_arrow::{FixedSizeListArrayExt, RecordBatchExt};
+use lance_core::io::RecordBatchStream;
 use snafu::{location, Location};
 
 use crate::dataset::Dataset;
@@ -31,11 +39,12 @@ pub async fn maybe_sample_training_data(
     dataset: &Dataset,
     column: &str,
     sample_size_hint: usize,
+    seed: Option<u64>,
 ) -> Result<FixedSizeListArray> {
     let num_rows = dataset.count_rows().await?;
     let projection = dataset.schema().project(&[column])?;
     let batch = if num_rows > sample_size_hint {
-        dataset.sample(sample_size_hint, &projection).await?
+        dataset.sample(sample_size_hint, &projection, seed).await?
     } else {
         let mut scanner = dataset.scan();
         scanner.project(&[column])?;
@@ -54,5 +63,155 @@ pub async fn maybe_sample_training_data(
         ),
         location: location!(),
     })?;
-    Ok(array.as_fixed_size_list().clone())
+    int8_to_float32(&to_vectors(array)?)
+}
+
+/// Read the head of `stream` until it has `sample_size` vectors of `column`, to
+/// train an index before the stream is consumed.
+///
+/// Returns the sampled vectors, and the batches read from the stream, which still
+/// have to be indexed.
+pub async fn sample_training_data_from_stream(
+    stream: &mut (impl RecordBatchStream + Unpin),
+    column: &str,
+    sample_size: usize,
+) -> Result<(FixedSizeListArray, Vec<RecordBatch>)> {
+    let mut batches = vec![];
+    let mut vectors = vec![];
+    let mut num_vectors = 0;
+    while num_vectors < sample_size {
+        let Some(batch) = stream.next().await else {
+            break;
+        };
+        let batch = batch?;
+        let array = batch.column_by_name(column).ok_or(Error::Index {
+            message: format!("Sample training data: column {column} does not exist in stream"),
+            location: location!(),
+        })?;
+        let batch_vectors = to_vectors(array)?;
+        num_vectors += batch_vectors.len();
+        vectors.push(batch_vectors);
+        batches.push(batch);
+    }
+    if num_vectors == 0 {
+        return Err(Error::Index {
+            message: format!("Sample training data: no vectors of column {column} in stream"),
+            location: location!(),
+        });
+    }
+    let arrays = vectors.iter().map(|v| v as &dyn Array).collect::<Vec<_>>();
+    let sample = arrow_select::concat::concat(&arrays)?;
+    Ok((int8_to_float32(sample.as_fixed_size_list())?, batches))
+}
+
+/// The non-null vectors of a vector column.
+///
+/// The vectors of a multivector column, i.e., `List<FixedSizeList>`, are flattened.
+pub fn to_vectors(array: &ArrayRef) -> Result<FixedSizeListArray> {
+    match array.data_type() {
+        DataType::FixedSizeList(..) => non_null_vectors(array.as_fixed_size_list()),
+        DataType::List(_) => Ok(flatten_multivectors(array.as_list())?.0),
+        _ => Err(Error::Index {
+            message: format!(
+                "Expect a vector or multivector column, got {}",
+                array.data_type()
+            ),
+            location: location!(),
+        }),
+    }
+}
+
+/// Flatten a multivector array, i.e., `List<FixedSizeList>`, to its vectors.
+///
+/// Returns the vectors and the index of the row of each vector. Null vectors are skipped.
+pub fn flatten_multivectors(multivectors: &ListArray) -> Result<(FixedSizeListArray, UInt32Array)> {
+    let Some(vectors) = multivectors.values().as_fixed_size_list_opt() else {
+        return Err(Error::Index {
+            message: format!(
+                "Multivector column must be a list of fixed size lists, got {}",
+                multivectors.data_type()
+            ),
+            location: location!(),
+        });
+    };
+    let offsets = multivectors.value_offsets();
+    let (rows, indices): (Vec<u32>, Vec<u32>) = (0..multivectors.len())
+        .filter(|&i| multivectors.is_valid(i))
+        .flat_map(|i| (offsets[i] as u32..offsets[i + 1] as u32).map(move |j| (i as u32, j)))
+        .filter(|&(_, j)| vectors.is_valid(j as usize))
+        .unzip();
+    let vectors = arrow_select::take::take(vectors, &UInt32Array::from(indices), None)?;
+    Ok((
+        vectors.as_fixed_size_list().clone(),
+        UInt32Array::from(rows),
+    ))
+}
+
+/// Flatten the multivector `column` of the batch, so each row has one vector.
+///
+/// The other columns, i.e., the row ids, are repeated for each vector of the row.
+pub fn flatten_multivector_batch(batch: &RecordBatch, column: &str) -> Result<RecordBatch> {
+    let Some(multivectors) = batch
+        .column_by_name(column)
+        .and_then(|c| c.as_list_opt::<i32>())
+    else {
+        return Ok(batch.clone());
+    };
+    let field = batch.schema().field_with_name(column)?.clone();
+    let (vectors, rows) = flatten_multivectors(multivectors)?;
+    let field = ArrowField::new(
+        field.name(),
+        vectors.data_type().clone(),
+        field.is_nullable(),
+    );
+    Ok(batch
+        .drop_column(column)?
+        .take(&rows)?
+        .try_with_column(field, Arc::new(vectors))?)
+}
+
+/// Drop the null vectors.
+fn non_null_vectors(vectors: &FixedSizeListArray) -> Result<FixedSizeListArray> {
+    let Some(nulls) = vectors.nulls().filter(|n| n.null_count() > 0) else {
+        return Ok(vectors.clone());
+    };
+    let predicate = BooleanArray::new(nulls.inner().clone(), None);
+    Ok(filter(vectors, &predicate)?.as_fixed_size_list().clone())
+}
+
+/// Drop the rows of the batch whose vector `column` is null.
+///
+/// Null vectors are never indexed, so they are not returned by the index searches.
+pub fn filter_null_vectors(batch: &RecordBatch, column: &str) -> Result<RecordBatch> {
+    let Some(nulls) = batch
+        .column_by_name(column)
+        .and_then(|c| c.nulls())
+        .filter(|n| n.null_count() > 0)
+    else {
+        return Ok(batch.clone());
+    };
+    let predicate = BooleanArray::new(nulls.inner().clone(), None);
+    Ok(filter_record_batch(batch, &predicate)?)
+}
+
+/// Convert int8 vectors, i.e., quantized embeddings, to float32.
+///
+/// IVF and PQ are trained and searched in float32 for int8 vectors.
+/// Float vectors are returned as is.
+pub fn int8_to_float32(vectors: &FixedSizeListArray) -> Result<FixedSizeListArray> {
+    if vectors.value_type() != DataType::Int8 {
+        return Ok(vectors.clone());
+    }
+    let values = Float32Array::from_iter_values(
+        vectors
+            .values()
+            .as_primitive::<Int8Type>()
+            .values()
+            .iter()
+            .map(|&v| v as f32),
+    );
+    Ok(FixedSizeListArray::try_new_from_values(
+        values,
+        vectors.value_length(),
+    )?)
 }
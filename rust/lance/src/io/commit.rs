@@ -56,14 +56,14 @@ use crate::format::{DeletionFile, Fragment};
 use crate::index::DatasetIndexInternalExt;
 use crate::Dataset;
 
-#[cfg(all(target_feature = "dynamodb", tests))]
+#[cfg(feature = "dynamodb")]
 mod dynamodb;
-#[cfg(tests)]
+#[cfg(test)]
 mod external_manifest;
 pub use lance_core::io::commit::latest_manifest_path;
 
 /// Read the transaction data from a transaction file.
-async fn read_transaction_file(
+pub(crate) async fn read_transaction_file(
     object_store: &ObjectStore,
     base_path: &Path,
     transaction_file: &str,
@@ -95,6 +95,7 @@ fn check_transaction(
     transaction: &Transaction,
     other_version: u64,
     other_transaction: &Option<Transaction>,
+    has_unique_key: bool,
 ) -> Result<()> {
     if other_transaction.is_none() {
         return Err(crate::Error::Internal {
@@ -107,7 +108,12 @@ fn check_transaction(
         });
     }
 
-    if transaction.conflicts_with(other_transaction.as_ref().unwrap()) {
+    let other = other_transaction.as_ref().unwrap();
+    // The unique keys of the rows written by concurrent transactions are only checked
+    // against their read version, not against each other.
+    let unique_key_conflict =
+        has_unique_key && transaction.operation.adds_rows() && other.operation.adds_rows();
+    if unique_key_conflict || transaction.conflicts_with(other) {
         return Err(crate::Error::CommitConflict {
             version: other_version,
             source: format!(
@@ -243,22 +249,25 @@ pub(crate) async fn migrate_fragments(
     new_fragments.try_collect().await
 }
 
+/// Whether the fragment bitmap of an index must be recalculated.
+pub(crate) fn index_needs_migration(dataset: &Dataset, index: &Index) -> bool {
+    // If the fragment bitmap is missing we need to recalculate it
+    index.fragment_bitmap.is_none()
+        // If the fragment bitmap was written by an old version of lance then we need to recalculate
+        // it because it could be corrupt due to a bug in versions < 0.8.15
+        || if let Some(writer_version) = &dataset.manifest.writer_version {
+            writer_version.older_than(0, 8, 15)
+        } else {
+            true
+        }
+}
+
 /// Update indices with new fields.
 ///
 /// Indices might be missing `fragment_bitmap`, so this function will add it.
 async fn migrate_indices(dataset: &Dataset, indices: &mut [Index]) -> Result<()> {
     for index in indices {
-        // If the fragment bitmap is missing we need to recalculate it
-        let mut must_recalculate_fragment_bitmap = index.fragment_bitmap.is_none();
-        // If the fragment bitmap was written by an old version of lance then we need to recalculate
-        // it because it could be corrupt due to a bug in versions < 0.8.15
-        must_recalculate_fragment_bitmap |=
-            if let Some(writer_version) = &dataset.manifest.writer_version {
-                writer_version.older_than(0, 8, 15)
-            } else {
-                true
-            };
-        if must_recalculate_fragment_bitmap {
+        if index_needs_migration(dataset, index) {
             debug_assert_eq!(index.fields.len(), 1);
             let idx_field = dataset.schema().field_by_id(index.fields[0]).ok_or_else(|| Error::Internal { message: format!("Index with uuid {} referred to field with id {} which did not exist in dataset", index.uuid, index.fields[0]), location: location!() })?;
             // We need to calculate the fragments covered by the index
@@ -310,25 +319,44 @@ pub(crate) async fn commit_transaction(
     }
 
     let mut target_version = version;
+    let has_unique_key = dataset
+        .schema()
+        .fields
+        .iter()
+        .any(|field| field.is_unique());
 
     // If any of them conflict with the transaction, return an error
     for (version_offset, other_transaction) in other_transactions.iter().enumerate() {
         let other_version = transaction.read_version + version_offset as u64 + 1;
-        check_transaction(transaction, other_version, other_transaction)?;
+        check_transaction(
+            transaction,
+            other_version,
+            other_transaction,
+            has_unique_key,
+        )?;
     }
 
-    for _ in 0..commit_config.num_retries {
+    // The first attempt, and then the `num_retries` retries.
+    for _ in 0..=commit_config.num_retries {
         // Build an up-to-date manifest from the transaction and current manifest
         let (mut manifest, mut indices) = match transaction.operation {
             Operation::Restore { version } => {
-                Transaction::restore_old_manifest(
+                let (mut manifest, indices) = Transaction::restore_old_manifest(
                     object_store,
                     &dataset.base,
                     version,
                     write_config,
                     &transaction_file,
                 )
-                .await?
+                .await?;
+                // Don't reuse the ids of the fragments and rows created after the
+                // restored version.
+                let latest_manifest = dataset.latest_manifest().await?;
+                manifest.max_fragment_id = manifest
+                    .max_fragment_id
+                    .max(latest_manifest.max_fragment_id);
+                manifest.next_row_id = manifest.next_row_id.max(latest_manifest.next_row_id);
+                (manifest, indices)
             }
             _ => transaction.build_manifest(
                 Some(dataset.manifest.as_ref()),
@@ -371,7 +399,14 @@ pub(crate) async fn commit_transaction(
             }
             Err(CommitError::CommitConflict) => {
                 // See if we can retry the commit
-                dataset = dataset.checkout_version(target_version).await?;
+                match dataset.checkout_version(target_version).await {
+                    Ok(next_dataset) => dataset = next_dataset,
+                    // The version was locked by another writer, which did not commit it.
+                    // Try the same version again.
+                    Err(crate::Error::NotFound { .. })
+                    | Err(crate::Error::DatasetNotFound { .. }) => continue,
+                    Err(e) => return Err(e),
+                }
 
                 let other_transaction =
                     if let Some(txn_file) = dataset.manifest.transaction_file.as_ref() {
@@ -379,7 +414,12 @@ pub(crate) async fn commit_transaction(
                     } else {
                         None
                     };
-                check_transaction(transaction, target_version, &other_transaction)?;
+                check_transaction(
+                    transaction,
+                    target_version,
+                    &other_transaction,
+                    has_unique_key,
+                )?;
                 target_version += 1;
             }
             Err(CommitError::OtherError(err)) => {
@@ -403,6 +443,7 @@ pub(crate) async fn commit_transaction(
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
+    use std::sync::atomic::{AtomicU32, Ordering};
     use std::sync::{Arc, Mutex};
 
     use arrow_array::{Int32Array, Int64Array, RecordBatch, RecordBatchIterator};
@@ -419,7 +460,7 @@ mod tests {
 
     use super::*;
 
-    use crate::dataset::{transaction::Operation, WriteMode, WriteParams};
+    use crate::dataset::{builder::DatasetBuilder, transaction::Operation, WriteMode, WriteParams};
     use crate::index::{vector::VectorIndexParams, DatasetIndexExt};
     use crate::io::object_store::ObjectStoreParams;
     use crate::Dataset;
@@ -743,4 +784,132 @@ mod tests {
             dataset.validate().await.unwrap()
         }
     }
+
+    #[tokio::test]
+    async fn test_commit_retries() {
+        // A lock that is always held by another writer, which never commits.
+        #[derive(Debug)]
+        struct BusyCommitLock {
+            num_attempts: Arc<AtomicU32>,
+        }
+
+        struct NoLease;
+
+        #[async_trait::async_trait]
+        impl CommitLock for BusyCommitLock {
+            type Lease = NoLease;
+
+            async fn lock(&self, _version: u64) -> std::result::Result<Self::Lease, CommitError> {
+                self.num_attempts.fetch_add(1, Ordering::SeqCst);
+                Err(CommitError::CommitConflict)
+            }
+        }
+
+        #[async_trait::async_trait]
+        impl CommitLease for NoLease {
+            async fn release(&self, _success: bool) -> std::result::Result<(), CommitError> {
+                Ok(())
+            }
+        }
+
+        let test_dir = tempfile::tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let schema = Arc::new(ArrowSchema::new(vec![Field::new(
+            "i",
+            DataType::Int32,
+            false,
+        )]));
+        let data = || {
+            let batch = RecordBatch::try_new(
+                schema.clone(),
+                vec![Arc::new(Int32Array::from(vec![1, 2, 3]))],
+            )
+            .unwrap();
+            RecordBatchIterator::new(vec![Ok(batch)], schema.clone())
+        };
+        let mut dataset = Dataset::write(data(), test_uri, None).await.unwrap();
+
+        // An append is rebased on a concurrent append.
+        let mut stale = dataset.clone();
+        dataset.append(data(), None).await.unwrap();
+        stale.append(data(), None).await.unwrap();
+        assert_eq!(stale.version().version, 3);
+        assert_eq!(stale.count_rows().await.unwrap(), 9);
+
+        // Deletes from the same fragment are not compatible.
+        let mut dataset = stale;
+        let mut stale = dataset.clone();
+        dataset.delete("i = 1").await.unwrap();
+        assert!(matches!(
+            stale.delete("i = 2").await,
+            Err(crate::Error::CommitConflict { .. })
+        ));
+
+        // The commit is retried as many times as requested.
+        let num_attempts = Arc::new(AtomicU32::new(0));
+        let params = WriteParams {
+            mode: WriteMode::Append,
+            commit_retries: 2,
+            store_params: Some(ObjectStoreParams {
+                commit_handler: Some(Arc::new(BusyCommitLock {
+                    num_attempts: num_attempts.clone(),
+                })),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        assert!(matches!(
+            dataset.append(data(), Some(params)).await,
+            Err(crate::Error::CommitConflict { .. })
+        ));
+        assert_eq!(num_attempts.load(Ordering::SeqCst), 3);
+        assert_eq!(dataset.count_rows().await.unwrap(), 6);
+
+        // Concurrent deletes from different fragments are rebased on each other.
+        let mut dataset = DatasetBuilder::from_uri(test_uri)
+            .with_commit_retries(10)
+            .load()
+            .await
+            .unwrap();
+        let batch = RecordBatch::try_new(
+            schema.clone(),
+            vec![Arc::new(Int32Array::from(vec![4, 5, 6]))],
+        )
+        .unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        dataset.append(reader, None).await.unwrap();
+        let deletes = ["i = 2", "i = 5"].map(|predicate| {
+            let mut dataset = dataset.clone();
+            async move {
+                dataset.delete(predicate).await.unwrap();
+            }
+        });
+        join_all(deletes).await;
+        let dataset = dataset
+            .checkout_version(dataset.version().version + 2)
+            .await
+            .unwrap();
+        assert_eq!(dataset.count_rows().await.unwrap(), 5);
+
+        // The other commits use the retries the dataset is opened with.
+        let num_attempts = Arc::new(AtomicU32::new(0));
+        let mut dataset = DatasetBuilder::from_uri(test_uri)
+            .with_commit_handler(Arc::new(BusyCommitLock {
+                num_attempts: num_attempts.clone(),
+            }))
+            .with_commit_retries(1)
+            .load()
+            .await
+            .unwrap();
+        assert!(matches!(
+            dataset.delete("i = 1").await,
+            Err(crate::Error::CommitConflict { .. })
+        ));
+        assert_eq!(num_attempts.load(Ordering::SeqCst), 2);
+        assert!(matches!(
+            dataset.update(None, &[("i", "i + 1")]).await,
+            Err(crate::Error::CommitConflict { .. })
+        ));
+        assert_eq!(num_attempts.load(Ordering::SeqCst), 4);
+    }
 }
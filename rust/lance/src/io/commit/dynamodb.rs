@@ -30,22 +30,24 @@ mod test {
     use aws_sdk_dynamodb::{
         config::Region,
         types::{
-            AttributeDefinition, KeySchemaElement, ProvisionedThroughput, ScalarAttributeType,
+            AttributeDefinition, KeySchemaElement, KeyType, ProvisionedThroughput,
+            ScalarAttributeType,
         },
+        Client,
     };
     use futures::{future::join_all, StreamExt, TryStreamExt};
     use lance_testing::datagen::{BatchGenerator, IncrementingInt32};
     use object_store::local::LocalFileSystem;
 
+    use lance_core::io::commit::{
+        dynamodb::DynamoDBExternalManifestStore,
+        external_manifest::{ExternalManifestCommitHandler, ExternalManifestStore},
+        latest_manifest_path, manifest_path, CommitHandler,
+    };
+
     use crate::{
-        dataset::{ReadParams, WriteMode, WriteParams},
-        io::{
-            commit::{
-                external_manifest::ExternalManifestCommitHandler, latest_manifest_path,
-                manifest_path, CommitHandler,
-            },
-            object_store::ObjectStoreParams,
-        },
+        dataset::{builder::DatasetBuilder, ReadParams, WriteMode, WriteParams},
+        io::object_store::ObjectStoreParams,
         Dataset,
     };
 
@@ -87,25 +89,25 @@ mod test {
             .table_name(&table_name)
             .key_schema(
                 KeySchemaElement::builder()
-                    .attribute_name(base_uri!())
+                    .attribute_name("base_uri")
                     .key_type(KeyType::Hash)
                     .build(),
             )
             .key_schema(
                 KeySchemaElement::builder()
-                    .attribute_name(version!())
+                    .attribute_name("version")
                     .key_type(KeyType::Range)
                     .build(),
             )
             .attribute_definitions(
                 AttributeDefinition::builder()
-                    .attribute_name(base_uri!())
+                    .attribute_name("base_uri")
                     .attribute_type(ScalarAttributeType::S)
                     .build(),
             )
             .attribute_definitions(
                 AttributeDefinition::builder()
-                    .attribute_name(version!())
+                    .attribute_name("version")
                     .attribute_type(ScalarAttributeType::N)
                     .build(),
             )
@@ -196,7 +198,9 @@ mod test {
             external_manifest_store: store,
         };
         let options = read_params(Arc::new(handler));
-        Dataset::open_with_params(ds_uri, &options).await.expect(
+        DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(options)
+            .load().await.expect(
             "If this fails, it means the external store handler does not correctly handle the case when a dataset exist, but it has never used external store before."
         );
     }
@@ -219,7 +223,9 @@ mod test {
             .unwrap();
 
         // load the data and check the content
-        let ds = Dataset::open_with_params(ds_uri, &read_params(handler))
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(read_params(handler))
+            .load()
             .await
             .unwrap();
         assert_eq!(ds.count_rows().await.unwrap(), 100);
@@ -267,7 +273,9 @@ mod test {
         assert!(errors.is_empty(), "{:?}", errors);
 
         // load the data and check the content
-        let ds = Dataset::open_with_params(ds_uri, &read_params(handler))
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(read_params(handler))
+            .load()
             .await
             .unwrap();
         assert_eq!(ds.count_rows().await.unwrap(), 60);
@@ -328,12 +336,18 @@ mod test {
 
         // Open without external store handler, should not see the out-of-sync commit
         let params = ReadParams::default();
-        let ds = Dataset::open_with_params(ds_uri, &params).await.unwrap();
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(params)
+            .load()
+            .await
+            .unwrap();
         assert_eq!(ds.version().version, 5);
         assert_eq!(ds.count_rows().await.unwrap(), 50);
 
         // Open with external store handler, should sync the out-of-sync commit on open
-        let ds = Dataset::open_with_params(ds_uri, &read_params(handler))
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(read_params(handler))
+            .load()
             .await
             .unwrap();
         assert_eq!(ds.version().version, 6);
@@ -341,7 +355,11 @@ mod test {
 
         // Open without external store handler again, should see the newly sync'd commit
         let params = ReadParams::default();
-        let ds = Dataset::open_with_params(ds_uri, &params).await.unwrap();
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(params)
+            .load()
+            .await
+            .unwrap();
         assert_eq!(ds.version().version, 6);
         assert_eq!(ds.count_rows().await.unwrap(), 60);
     }
@@ -30,7 +30,7 @@ mod test {
     use tokio::sync::Mutex;
 
     use crate::{
-        dataset::{ReadParams, WriteMode, WriteParams},
+        dataset::{builder::DatasetBuilder, ReadParams, WriteMode, WriteParams},
         io::object_store::ObjectStoreParams,
         Dataset,
     };
@@ -157,7 +157,9 @@ mod test {
             external_manifest_store: Arc::new(sleepy_store),
         });
         let options = read_params(handler.clone());
-        Dataset::open_with_params(ds_uri, &options).await.expect(
+        DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(options)
+            .load().await.expect(
             "If this fails, it means the external store handler does not correctly handle the case when a dataset exist, but it has never used external store before."
         );
 
@@ -195,7 +197,9 @@ mod test {
             .unwrap();
 
         // load the data and check the content
-        let ds = Dataset::open_with_params(ds_uri, &read_params(handler))
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(read_params(handler))
+            .load()
             .await
             .unwrap();
         assert_eq!(ds.count_rows().await.unwrap(), 100);
@@ -246,7 +250,9 @@ mod test {
             assert!(errors.is_empty(), "{:?}", errors);
 
             // load the data and check the content
-            let ds = Dataset::open_with_params(ds_uri, &read_params(handler))
+            let ds = DatasetBuilder::from_uri(ds_uri)
+                .with_read_params(read_params(handler))
+                .load()
                 .await
                 .unwrap();
             assert_eq!(ds.count_rows().await.unwrap(), 60);
@@ -312,12 +318,18 @@ mod test {
 
         // Open without external store handler, should not see the out-of-sync commit
         let params = ReadParams::default();
-        let ds = Dataset::open_with_params(ds_uri, &params).await.unwrap();
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(params)
+            .load()
+            .await
+            .unwrap();
         assert_eq!(ds.version().version, 5);
         assert_eq!(ds.count_rows().await.unwrap(), 50);
 
         // Open with external store handler, should sync the out-of-sync commit on open
-        let ds = Dataset::open_with_params(ds_uri, &read_params(handler))
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(read_params(handler))
+            .load()
             .await
             .unwrap();
         assert_eq!(ds.version().version, 6);
@@ -325,7 +337,11 @@ mod test {
 
         // Open without external store handler again, should see the newly sync'd commit
         let params = ReadParams::default();
-        let ds = Dataset::open_with_params(ds_uri, &params).await.unwrap();
+        let ds = DatasetBuilder::from_uri(ds_uri)
+            .with_read_params(params)
+            .load()
+            .await
+            .unwrap();
         assert_eq!(ds.version().version, 6);
         assert_eq!(ds.count_rows().await.unwrap(), 60);
     }
@@ -12,18 +12,24 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+mod fts;
+mod fusion;
 mod knn;
 mod planner;
 mod projection;
+mod sample;
 mod scalar_index;
 mod scan;
 mod take;
 #[cfg(test)]
 pub mod testing;
 
+pub use fts::FtsExec;
+pub use fusion::FusionExec;
 pub use knn::*;
 pub use planner::{FilterPlan, Planner};
 pub use projection::ProjectionExec;
+pub use sample::SampleExec;
 pub use scalar_index::{MaterializeIndexExec, ScalarIndexExec};
 pub use scan::LanceScanExec;
 pub use take::TakeExec;
@@ -0,0 +1,275 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_array::{
+    cast::AsArray,
+    types::{Float32Type, UInt64Type},
+    Array, ArrayRef, Float32Array, RecordBatch, UInt64Array,
+};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::execution::context::TaskContext;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, SendableRecordBatchStream,
+    Statistics,
+};
+use futures::{stream, TryStreamExt};
+use lance_core::{ROW_ID, ROW_ID_FIELD};
+use lance_index::scalar::inverted::InvertedIndex;
+use lance_index::vector::fusion::SCORE_COL;
+use roaring::RoaringBitmap;
+
+use super::PreFilterSource;
+use crate::dataset::Dataset;
+use crate::format::Index;
+use crate::index::prefilter::{FilterLoader, PreFilter};
+use crate::index::DatasetIndexInternalExt;
+use crate::Result;
+
+/// [ExecutionPlan] of a full text search, which ranks the rows that contain any token
+/// of the query by BM25, with an inverted index.
+///
+/// The rows of the fragments appended after the index was built are read from the
+/// `unindexed` input, i.e., the text column and the row ids, and scored with the
+/// statistics of the index.
+///
+/// The output has the score and the row id columns, the best scored row first.
+#[derive(Debug)]
+pub struct FtsExec {
+    dataset: Arc<Dataset>,
+    /// The inverted index.
+    index: Index,
+    column: String,
+    query: String,
+    prefilter_source: PreFilterSource,
+    /// If set, only the rows of these fragments are searched.
+    fragments: Option<RoaringBitmap>,
+    unindexed: Option<Arc<dyn ExecutionPlan>>,
+    /// If set, at most this number of rows are returned.
+    fetch: Option<usize>,
+}
+
+impl FtsExec {
+    pub fn new(
+        dataset: Arc<Dataset>,
+        index: Index,
+        column: String,
+        query: String,
+        prefilter_source: PreFilterSource,
+    ) -> Self {
+        Self {
+            dataset,
+            index,
+            column,
+            query,
+            prefilter_source,
+            fragments: None,
+            unindexed: None,
+            fetch: None,
+        }
+    }
+
+    /// Only search the rows of the given fragments.
+    pub fn with_fragments(mut self, fragments: RoaringBitmap) -> Self {
+        self.fragments = Some(fragments);
+        self
+    }
+
+    /// Also score the rows of `unindexed`, which has the text column and the row ids.
+    pub fn with_unindexed(mut self, unindexed: Arc<dyn ExecutionPlan>) -> Self {
+        self.unindexed = Some(unindexed);
+        self
+    }
+
+    /// Return at most `fetch` rows.
+    pub fn with_fetch(mut self, fetch: Option<usize>) -> Self {
+        self.fetch = fetch;
+        self
+    }
+
+    pub fn fts_schema() -> Schema {
+        Schema::new(vec![
+            Field::new(SCORE_COL, DataType::Float32, false),
+            ROW_ID_FIELD.clone(),
+        ])
+    }
+
+    async fn search(
+        dataset: Arc<Dataset>,
+        index_meta: Index,
+        column: String,
+        query: String,
+        prefilter_loader: Option<Box<dyn FilterLoader>>,
+        unindexed: Option<SendableRecordBatchStream>,
+        fetch: Option<usize>,
+    ) -> Result<RecordBatch> {
+        let index = dataset
+            .open_inverted_index(&column, &index_meta.uuid.to_string())
+            .await?;
+        let tokens = index.query_tokens(&query);
+        let prefilter = PreFilter::new(dataset, index_meta, prefilter_loader);
+        prefilter.wait_for_ready().await?;
+        let (row_ids, scores) =
+            index.bm25_search(&tokens, fetch, |row_id| prefilter.check_one(row_id));
+        // The index may be keyed by the stable row ids of the rows.
+        let results = prefilter.resolve_row_ids(Self::results(row_ids, scores)?)?;
+
+        let Some(unindexed) = unindexed else {
+            return Ok(results);
+        };
+        let mut row_ids = results[ROW_ID]
+            .as_primitive::<UInt64Type>()
+            .values()
+            .to_vec();
+        let mut scores = results[SCORE_COL]
+            .as_primitive::<Float32Type>()
+            .values()
+            .to_vec();
+        let batches = unindexed.try_collect::<Vec<_>>().await?;
+        for batch in batches {
+            score_unindexed(&index, &tokens, &column, &batch, &mut row_ids, &mut scores);
+        }
+        let mut results = row_ids.into_iter().zip(scores).collect::<Vec<_>>();
+        results.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        if let Some(fetch) = fetch {
+            results.truncate(fetch);
+        }
+        let (row_ids, scores) = results.into_iter().unzip();
+        Self::results(row_ids, scores)
+    }
+
+    fn results(row_ids: Vec<u64>, scores: Vec<f32>) -> Result<RecordBatch> {
+        let columns: Vec<ArrayRef> = vec![
+            Arc::new(Float32Array::from(scores)),
+            Arc::new(UInt64Array::from(row_ids)),
+        ];
+        Ok(RecordBatch::try_new(Arc::new(Self::fts_schema()), columns)?)
+    }
+}
+
+/// Score the rows of `batch` which contain any of the tokens.
+fn score_unindexed(
+    index: &InvertedIndex,
+    tokens: &[String],
+    column: &str,
+    batch: &RecordBatch,
+    row_ids: &mut Vec<u64>,
+    scores: &mut Vec<f32>,
+) {
+    let batch_row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+    let texts = &batch[column];
+    let mut score = |texts: &mut dyn Iterator<Item = Option<&str>>| {
+        for (text, row_id) in texts.zip(batch_row_ids.values()) {
+            let score = text.map_or(0.0, |text| index.bm25_score(tokens, text));
+            if score > 0.0 {
+                row_ids.push(*row_id);
+                scores.push(score);
+            }
+        }
+    };
+    match texts.data_type() {
+        DataType::LargeUtf8 => score(&mut texts.as_string::<i64>().iter()),
+        _ => score(&mut texts.as_string::<i32>().iter()),
+    }
+}
+
+impl DisplayAs for FtsExec {
+    fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match t {
+            DisplayFormatType::Default | DisplayFormatType::Verbose => {
+                write!(
+                    f,
+                    "FullTextSearch: column={}, query={:?}, name={}",
+                    self.column, self.query, self.index.uuid
+                )
+            }
+        }
+    }
+}
+
+impl ExecutionPlan for FtsExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(Self::fts_schema())
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::RoundRobinBatch(1)
+    }
+
+    fn output_ordering(&self) -> Option<&[datafusion::physical_expr::PhysicalSortExpr]> {
+        None
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        let mut children = self.prefilter_source.children();
+        children.extend(self.unindexed.clone());
+        children
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        _children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        Ok(self)
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        let prefilter_loader = self.prefilter_source.execute_loader(
+            self.fragments.as_ref(),
+            partition,
+            context.clone(),
+        )?;
+        let unindexed = self
+            .unindexed
+            .as_ref()
+            .map(|unindexed| unindexed.execute(partition, context))
+            .transpose()?;
+        let search = Self::search(
+            self.dataset.clone(),
+            self.index.clone(),
+            self.column.clone(),
+            self.query.clone(),
+            prefilter_loader,
+            unindexed,
+            self.fetch,
+        );
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            stream::once(async move {
+                search
+                    .await
+                    .map_err(|e| DataFusionError::Execution(format!("Full text search: {e}")))
+            }),
+        )))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: self.fetch,
+            ..Default::default()
+        }
+    }
+}
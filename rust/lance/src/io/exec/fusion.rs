@@ -0,0 +1,142 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_array::RecordBatch;
+use arrow_schema::SchemaRef;
+use arrow_select::concat::concat_batches;
+use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::execution::context::TaskContext;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, SendableRecordBatchStream,
+    Statistics,
+};
+use futures::{stream, TryStreamExt};
+use lance_index::vector::fusion::{fuse, fusion_schema, FusionMethod};
+
+/// [ExecutionPlan] that fuses the results of a dense vector search and of a sparse
+/// vector search into the `k` best scored results of a hybrid search.
+///
+/// The output has the fused score and the row id columns, the best result first.
+#[derive(Debug)]
+pub struct FusionExec {
+    vector: Arc<dyn ExecutionPlan>,
+    sparse: Arc<dyn ExecutionPlan>,
+    method: FusionMethod,
+    k: usize,
+}
+
+impl FusionExec {
+    pub fn new(
+        vector: Arc<dyn ExecutionPlan>,
+        sparse: Arc<dyn ExecutionPlan>,
+        method: FusionMethod,
+        k: usize,
+    ) -> Self {
+        Self {
+            vector,
+            sparse,
+            method,
+            k,
+        }
+    }
+}
+
+impl DisplayAs for FusionExec {
+    fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match t {
+            DisplayFormatType::Default | DisplayFormatType::Verbose => {
+                write!(f, "Fusion: method={:?}, k={}", self.method, self.k)
+            }
+        }
+    }
+}
+
+async fn collect(input: SendableRecordBatchStream) -> DataFusionResult<RecordBatch> {
+    let schema = input.schema();
+    let batches = input.try_collect::<Vec<_>>().await?;
+    // The columns of the batches are not always in the order of the input schema.
+    match batches.first() {
+        Some(batch) => Ok(concat_batches(&batch.schema(), &batches)?),
+        None => Ok(RecordBatch::new_empty(schema)),
+    }
+}
+
+impl ExecutionPlan for FusionExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        Arc::new(fusion_schema())
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::RoundRobinBatch(1)
+    }
+
+    fn output_ordering(&self) -> Option<&[datafusion::physical_expr::PhysicalSortExpr]> {
+        None
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.vector.clone(), self.sparse.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let [vector, sparse] = children.as_slice() else {
+            return Err(DataFusionError::Internal(
+                "FusionExec node must have exactly two children".to_string(),
+            ));
+        };
+        Ok(Arc::new(Self::new(
+            vector.clone(),
+            sparse.clone(),
+            self.method,
+            self.k,
+        )))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        let vector = self.vector.execute(partition, context.clone())?;
+        let sparse = self.sparse.execute(partition, context)?;
+        let method = self.method;
+        let k = self.k;
+        let fused = async move {
+            let (vector, sparse) = futures::try_join!(collect(vector), collect(sparse))?;
+            Ok(fuse(&vector, &sparse, method, k)?)
+        };
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            stream::once(fused),
+        )))
+    }
+
+    fn statistics(&self) -> Statistics {
+        Statistics {
+            num_rows: Some(self.k),
+            ..Default::default()
+        }
+    }
+}
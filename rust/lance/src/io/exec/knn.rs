@@ -13,24 +13,37 @@
 // limitations under the License.
 
 use std::any::Any;
+use std::collections::HashSet;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Instant;
 
 use arrow_array::cast::AsArray;
-use arrow_array::{RecordBatch, UInt64Array};
+use arrow_array::types::UInt64Type;
+use arrow_array::{Array, RecordBatch, UInt32Array, UInt64Array};
 use arrow_schema::{DataType, Field, Schema};
+use arrow_select::concat::concat_batches;
 use async_trait::async_trait;
 use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
 use datafusion::physical_plan::{
     DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning,
     RecordBatchStream as DFRecordBatchStream, SendableRecordBatchStream, Statistics,
 };
 use futures::stream::Stream;
 use futures::{FutureExt, StreamExt, TryStreamExt};
+use lance_arrow::RecordBatchExt;
 use lance_core::utils::mask::{RowIdMask, RowIdTreeMap};
 use lance_core::{ROW_ID, ROW_ID_FIELD};
-use lance_index::vector::{flat::flat_search, Query, DIST_COL};
+use lance_index::vector::{
+    flat::flat_search,
+    sparse::is_sparse_vector_type,
+    trace::{SearchStage, SearchTrace, StageTrace},
+    Query, DIST_COL,
+};
+use roaring::RoaringBitmap;
 use snafu::{location, Location};
 use tokio::sync::mpsc::Receiver;
 use tokio::task::JoinHandle;
@@ -40,6 +53,7 @@ use crate::dataset::scanner::DatasetRecordBatchStream;
 use crate::dataset::Dataset;
 use crate::format::Index;
 use crate::index::prefilter::{FilterLoader, PreFilter};
+use crate::index::vector::VectorIndex;
 use crate::index::DatasetIndexInternalExt;
 use crate::io::RecordBatchStream;
 use crate::{Error, Result};
@@ -53,17 +67,37 @@ pub struct KNNFlatStream {
 impl KNNFlatStream {
     /// Construct a [`KNNFlatStream`] node.
     #[instrument(level = "debug", skip_all, name = "KNNFlatStream::new")]
-    pub(crate) fn new(child: SendableRecordBatchStream, query: &Query) -> Self {
+    pub(crate) fn new(child: SendableRecordBatchStream, query: &Query, stage: SearchStage) -> Self {
+        // Count the candidates if the search is traced.
+        let num_candidates = Arc::new(AtomicUsize::new(0));
+        let child = if query.trace.is_some() {
+            let counter = num_candidates.clone();
+            let schema = child.schema();
+            Box::pin(RecordBatchStreamAdapter::new(
+                schema,
+                child.inspect_ok(move |batch| {
+                    counter.fetch_add(batch.num_rows(), Ordering::Relaxed);
+                }),
+            ))
+        } else {
+            child
+        };
         let stream = DatasetRecordBatchStream::new(child);
-        Self::from_stream(stream, query)
+        Self::from_stream(stream, query, stage, num_candidates)
     }
 
-    fn from_stream(stream: impl RecordBatchStream + 'static, query: &Query) -> Self {
+    fn from_stream(
+        stream: impl RecordBatchStream + 'static,
+        query: &Query,
+        stage: SearchStage,
+        num_candidates: Arc<AtomicUsize>,
+    ) -> Self {
         let (tx, rx) = tokio::sync::mpsc::channel(2);
 
         let q = query.clone();
         let bg_thread = tokio::spawn(
             async move {
+                let start = Instant::now();
                 let batch = match flat_search(stream, &q).await {
                     Ok(b) => b,
                     Err(e) => {
@@ -75,6 +109,14 @@ impl KNNFlatStream {
                         return;
                     }
                 };
+                if let Some(trace) = &q.trace {
+                    trace.record_stage(StageTrace {
+                        stage,
+                        candidates: num_candidates.load(Ordering::Relaxed),
+                        results: batch.num_rows(),
+                        elapsed: start.elapsed(),
+                    });
+                }
 
                 if !tx.is_closed() {
                     if let Err(e) = tx.send(Ok(batch)).await {
@@ -144,6 +186,9 @@ pub struct KNNFlatExec {
 
     /// The query to execute.
     query: Query,
+
+    /// The stage of the search, recorded in the trace of the query.
+    stage: SearchStage,
 }
 
 impl DisplayAs for KNNFlatExec {
@@ -177,11 +222,17 @@ impl KNNFlatExec {
             })?;
         match field.data_type() {
             DataType::FixedSizeList(list_field, _)
-                if matches!(list_field.data_type(), DataType::Float32) => {}
+                if list_field.data_type().is_floating()
+                    || list_field.data_type() == &DataType::Int8 => {}
+            // Sparse vector column, ranked by dot product.
+            dt if is_sparse_vector_type(dt) => {}
+            // Multivector column, ranked by MaxSim.
+            DataType::List(list_field) if matches!(list_field.data_type(), DataType::FixedSizeList(f, _) if f.data_type().is_floating()) =>
+                {}
             _ => {
                 return Err(Error::IO {
                     message: format!(
-                        "KNNFlatExec node: query column {} is not a vector. Expect FixedSizeList<Float32>, got {}",
+                        "KNNFlatExec node: query column {} is not a vector. Expect FixedSizeList<Float16/Float32/Float64/Int8>, got {}",
                         query.column, field.data_type()
                     ),
                     location: location!(),
@@ -189,7 +240,17 @@ impl KNNFlatExec {
             }
         }
 
-        Ok(Self { input, query })
+        Ok(Self {
+            input,
+            query,
+            stage: SearchStage::Flat,
+        })
+    }
+
+    /// Set the stage of the search this node runs, i.e., refine the index results.
+    pub fn with_stage(mut self, stage: SearchStage) -> Self {
+        self.stage = stage;
+        self
     }
 }
 
@@ -239,6 +300,7 @@ impl ExecutionPlan for KNNFlatExec {
         Ok(Box::pin(KNNFlatStream::new(
             self.input.execute(partition, context)?,
             &self.query,
+            self.stage,
         )))
     }
 
@@ -299,6 +361,27 @@ impl FilterLoader for SelectionVectorToPrefilter {
     }
 }
 
+// Utility to restrict a prefilter to the rows of some fragments
+struct FragmentsToPrefilter {
+    fragments: RoaringBitmap,
+    inner: Option<Box<dyn FilterLoader>>,
+}
+
+#[async_trait]
+impl FilterLoader for FragmentsToPrefilter {
+    async fn load(self: Box<Self>) -> Result<RowIdMask> {
+        let mut allow_list = RowIdTreeMap::new();
+        for fragment_id in self.fragments.iter() {
+            allow_list.insert_fragment(fragment_id);
+        }
+        let mask = RowIdMask::from_allowed(allow_list);
+        match self.inner {
+            Some(inner) => Ok(inner.load().await? & mask),
+            None => Ok(mask),
+        }
+    }
+}
+
 /// KNN Node from reading a vector index.
 pub struct KNNIndexStream {
     rx: Receiver<datafusion::error::Result<RecordBatch>>,
@@ -306,6 +389,38 @@ pub struct KNNIndexStream {
 }
 
 impl KNNIndexStream {
+    /// Search the index, and record the stage in the trace of the query.
+    async fn traced_knn_stream(
+        mut query: Query,
+        dataset: Arc<Dataset>,
+        index_meta: Index,
+        allow_list_input: Option<Box<dyn FilterLoader>>,
+    ) -> Result<RecordBatch> {
+        let Some(trace) = query.trace.take() else {
+            return Self::knn_stream(query, dataset, index_meta, allow_list_input).await;
+        };
+        // The deltas of the index are searched concurrently, so each search records
+        // its partitions in its own trace first.
+        let index_trace = Arc::new(SearchTrace::default());
+        query.trace = Some(index_trace.clone());
+        let start = Instant::now();
+        let batch = Self::knn_stream(query, dataset, index_meta, allow_list_input).await?;
+        let partitions = index_trace.partitions();
+        let candidates = if partitions.is_empty() {
+            batch.num_rows()
+        } else {
+            partitions.iter().map(|p| p.candidates).sum()
+        };
+        trace.record_partitions(partitions);
+        trace.record_stage(StageTrace {
+            stage: SearchStage::Index,
+            candidates,
+            results: batch.num_rows(),
+            elapsed: start.elapsed(),
+        });
+        Ok(batch)
+    }
+
     async fn knn_stream(
         query: Query,
         dataset: Arc<Dataset>,
@@ -316,7 +431,41 @@ impl KNNIndexStream {
             .open_vector_index(&query.column, &index_meta.uuid.to_string())
             .await?;
         let pre_filter = Arc::new(PreFilter::new(dataset, index_meta, allow_list_input));
-        index.search(&query, pre_filter).await
+        let batch = Self::search(index.as_ref(), query, pre_filter.clone()).await?;
+        // The index may be keyed by the stable row ids of the rows.
+        pre_filter.wait_for_ready().await?;
+        pre_filter.resolve_row_ids(batch)
+    }
+
+    async fn search(
+        index: &dyn VectorIndex,
+        query: Query,
+        pre_filter: Arc<PreFilter>,
+    ) -> Result<RecordBatch> {
+        let Some(keys) = query.key.as_fixed_size_list_opt() else {
+            return index.search(&query, pre_filter).await;
+        };
+
+        // Multivector query: the index is searched with each query vector, and the
+        // union of the candidates is re-ranked by the flat search afterwards.
+        let mut batches = Vec::with_capacity(keys.len());
+        for i in 0..keys.len() {
+            let sub_query = Query {
+                key: keys.value(i),
+                // The distance range applies to the aggregated distance only.
+                lower_bound: None,
+                upper_bound: None,
+                ..query.clone()
+            };
+            batches.push(index.search(&sub_query, pre_filter.clone()).await?);
+        }
+        let batch = concat_batches(&batches[0].schema(), &batches)?;
+        let row_ids = batch[ROW_ID].as_primitive::<UInt64Type>();
+        let mut seen = HashSet::with_capacity(row_ids.len());
+        let indices = UInt32Array::from_iter_values(
+            (0..row_ids.len() as u32).filter(|&i| seen.insert(row_ids.value(i as usize))),
+        );
+        Ok(batch.take(&indices)?)
     }
 
     #[instrument(level = "debug", skip_all, name = "KNNIndexStream::new")]
@@ -331,7 +480,7 @@ impl KNNIndexStream {
         let index = index.clone();
         let bg_thread = tokio::spawn(
             async move {
-                let result = match Self::knn_stream(q, dataset, index, allow_list).await {
+                let result = match Self::traced_knn_stream(q, dataset, index, allow_list).await {
                     Ok(b) => b,
                     Err(e) => {
                         tx.send(Err(datafusion::error::DataFusionError::Execution(format!(
@@ -398,7 +547,7 @@ impl Stream for KNNIndexStream {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum PreFilterSource {
     /// The prefilter input is an array of row ids that match the filter condition
     FilteredRowIds(Arc<dyn ExecutionPlan>),
@@ -408,6 +557,45 @@ pub enum PreFilterSource {
     None,
 }
 
+impl PreFilterSource {
+    /// The plan which feeds the prefilter, if any.
+    pub fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        match self {
+            Self::None => vec![],
+            Self::FilteredRowIds(src) => vec![src.clone()],
+            Self::ScalarIndexQuery(src) => vec![src.clone()],
+        }
+    }
+
+    /// Execute the input of the prefilter, restricted to the rows of `fragments`
+    /// if given.
+    pub(crate) fn execute_loader(
+        &self,
+        fragments: Option<&RoaringBitmap>,
+        partition: usize,
+        context: Arc<datafusion::execution::context::TaskContext>,
+    ) -> DataFusionResult<Option<Box<dyn FilterLoader>>> {
+        let prefilter_loader = match self {
+            Self::FilteredRowIds(src_node) => {
+                let stream = src_node.execute(partition, context)?;
+                Some(Box::new(FilteredRowIdsToPrefilter(stream)) as Box<dyn FilterLoader>)
+            }
+            Self::ScalarIndexQuery(src_node) => {
+                let stream = src_node.execute(partition, context)?;
+                Some(Box::new(SelectionVectorToPrefilter(stream)) as Box<dyn FilterLoader>)
+            }
+            Self::None => None,
+        };
+        Ok(match fragments {
+            Some(fragments) => Some(Box::new(FragmentsToPrefilter {
+                fragments: fragments.clone(),
+                inner: prefilter_loader,
+            }) as Box<dyn FilterLoader>),
+            None => prefilter_loader,
+        })
+    }
+}
+
 /// [ExecutionPlan] for KNNIndex node.
 #[derive(Debug)]
 pub struct KNNIndexExec {
@@ -419,6 +607,8 @@ pub struct KNNIndexExec {
     index: Index,
     /// The vector query to execute.
     query: Query,
+    /// If set, only the rows of these fragments are searched.
+    fragments: Option<RoaringBitmap>,
 }
 
 impl DisplayAs for KNNIndexExec {
@@ -455,8 +645,15 @@ impl KNNIndexExec {
             index,
             query: query.clone(),
             prefilter_source,
+            fragments: None,
         })
     }
+
+    /// Only search the rows of the given fragments.
+    pub fn with_fragments(mut self, fragments: RoaringBitmap) -> Self {
+        self.fragments = Some(fragments);
+        self
+    }
 }
 
 impl ExecutionPlan for KNNIndexExec {
@@ -480,11 +677,7 @@ impl ExecutionPlan for KNNIndexExec {
     }
 
     fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
-        match &self.prefilter_source {
-            PreFilterSource::None => vec![],
-            PreFilterSource::FilteredRowIds(src) => vec![src.clone()],
-            PreFilterSource::ScalarIndexQuery(src) => vec![src.clone()],
-        }
+        self.prefilter_source.children()
     }
 
     fn with_new_children(
@@ -499,17 +692,9 @@ impl ExecutionPlan for KNNIndexExec {
         partition: usize,
         context: Arc<datafusion::execution::context::TaskContext>,
     ) -> DataFusionResult<datafusion::physical_plan::SendableRecordBatchStream> {
-        let prefilter_loader = match &self.prefilter_source {
-            PreFilterSource::FilteredRowIds(src_node) => {
-                let stream = src_node.execute(partition, context)?;
-                Some(Box::new(FilteredRowIdsToPrefilter(stream)) as Box<dyn FilterLoader>)
-            }
-            PreFilterSource::ScalarIndexQuery(src_node) => {
-                let stream = src_node.execute(partition, context)?;
-                Some(Box::new(SelectionVectorToPrefilter(stream)) as Box<dyn FilterLoader>)
-            }
-            PreFilterSource::None => None,
-        };
+        let prefilter_loader =
+            self.prefilter_source
+                .execute_loader(self.fragments.as_ref(), partition, context)?;
 
         Ok(Box::pin(KNNIndexStream::new(
             self.dataset.clone(),
@@ -533,12 +718,15 @@ mod tests {
 
     use std::sync::Arc;
 
+    use arrow_array::types::{Float16Type, Float32Type, Int32Type, UInt64Type};
     use arrow_array::RecordBatchIterator;
-    use arrow_array::{cast::as_primitive_array, FixedSizeListArray, Int32Array, StringArray};
+    use arrow_array::{
+        cast::as_primitive_array, FixedSizeListArray, Float32Array, Int32Array, StringArray,
+    };
     use arrow_schema::{DataType, Field as ArrowField, Schema as ArrowSchema};
     use futures::TryStreamExt;
-    use lance_linalg::distance::MetricType;
-    use lance_testing::datagen::generate_random_array;
+    use lance_linalg::distance::{l2_distance_batch, MetricType};
+    use lance_testing::datagen::{generate_random_array, generate_random_array_with_seed};
     use tempfile::tempdir;
 
     use crate::arrow::*;
@@ -621,6 +809,13 @@ mod tests {
                 k: 10,
                 nprobes: 0,
                 refine_factor: None,
+                ef_search: None,
+                beam_width: None,
+                target_recall: None,
+                max_probe_rows: None,
+                trace: None,
+                lower_bound: None,
+                upper_bound: None,
                 metric_type: MetricType::L2,
                 use_index: false,
             },
@@ -631,6 +826,161 @@ mod tests {
         assert_eq!(expected, results[0]);
     }
 
+    #[tokio::test]
+    async fn knn_flat_search_chunks() {
+        const DIM: i32 = 8;
+        let schema = Arc::new(ArrowSchema::new(vec![
+            ArrowField::new("key", DataType::Int32, false),
+            ArrowField::new(
+                "vector",
+                DataType::FixedSizeList(
+                    Arc::new(ArrowField::new("item", DataType::Float32, true)),
+                    DIM,
+                ),
+                true,
+            ),
+        ]));
+        // A batch larger than a chunk, and many small batches.
+        let mut sizes = vec![20_000];
+        sizes.extend(std::iter::repeat(100).take(30));
+        let mut offset = 0;
+        let batches = sizes
+            .into_iter()
+            .map(|size| {
+                let keys = Int32Array::from_iter_values(offset..offset + size);
+                offset += size;
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![
+                        Arc::new(keys),
+                        Arc::new(
+                            FixedSizeListArray::try_new_from_values(
+                                generate_random_array(size as usize * DIM as usize),
+                                DIM,
+                            )
+                            .unwrap(),
+                        ),
+                    ],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+
+        let key = generate_random_array(DIM as usize);
+        let mut expected = batches
+            .iter()
+            .flat_map(|batch| {
+                let keys = batch["key"].as_primitive::<Int32Type>().clone();
+                let vectors = batch["vector"].as_fixed_size_list().values().clone();
+                let distances = l2_distance_batch(
+                    key.values(),
+                    vectors.as_primitive::<Float32Type>().values(),
+                    DIM as usize,
+                )
+                .collect::<Vec<_>>();
+                keys.values()
+                    .iter()
+                    .copied()
+                    .zip(distances)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+        for k in [1, 25, 500] {
+            let query = Query {
+                column: "vector".to_string(),
+                key: Arc::new(key.clone()),
+                k,
+                nprobes: 0,
+                refine_factor: None,
+                ef_search: None,
+                beam_width: None,
+                target_recall: None,
+                max_probe_rows: None,
+                trace: None,
+                lower_bound: None,
+                upper_bound: None,
+                metric_type: MetricType::L2,
+                use_index: false,
+            };
+            let stream = Box::pin(RecordBatchStreamAdapter::new(
+                schema.clone(),
+                futures::stream::iter(batches.clone().into_iter().map(Ok)),
+            ));
+            let results = flat_search(DatasetRecordBatchStream::new(stream), &query)
+                .await
+                .unwrap();
+            let keys = results["key"].as_primitive::<Int32Type>().values().to_vec();
+            let expected_keys = expected[..k]
+                .iter()
+                .map(|(key, _)| *key)
+                .collect::<Vec<_>>();
+            assert_eq!(keys, expected_keys);
+        }
+    }
+
+    #[tokio::test]
+    async fn knn_flat_search_f16() {
+        const DIM: usize = 32;
+        let schema = Arc::new(ArrowSchema::new(vec![ArrowField::new(
+            "vector",
+            DataType::FixedSizeList(
+                Arc::new(ArrowField::new("item", DataType::Float16, true)),
+                DIM as i32,
+            ),
+            true,
+        )]));
+        let values = generate_random_array_with_seed::<Float16Type>(100 * DIM, [7; 32]);
+        let vectors = FixedSizeListArray::try_new_from_values(values.clone(), DIM as i32).unwrap();
+        let batch = RecordBatch::try_new(schema.clone(), vec![Arc::new(vectors)]).unwrap();
+
+        let test_dir = tempdir().unwrap();
+        let test_uri = test_dir.path().to_str().unwrap();
+        let reader = RecordBatchIterator::new(vec![Ok(batch)], schema.clone());
+        let dataset = Dataset::write(reader, test_uri, None).await.unwrap();
+
+        let q = Float32Array::from_iter_values((0..DIM).map(|v| v as f32 / DIM as f32));
+        let results = dataset
+            .scan()
+            .nearest("vector", &q, 5)
+            .unwrap()
+            .with_row_id()
+            .try_into_stream()
+            .await
+            .unwrap()
+            .try_collect::<Vec<_>>()
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(
+            results[0]["vector"].data_type(),
+            schema.field(0).data_type()
+        );
+
+        let mut expected = values
+            .values()
+            .chunks_exact(DIM)
+            .map(|v| {
+                v.iter()
+                    .zip(q.values())
+                    .map(|(x, y)| (x.to_f32() - y).powi(2))
+                    .sum::<f32>()
+            })
+            .enumerate()
+            .collect::<Vec<_>>();
+        expected.sort_by(|a, b| a.1.total_cmp(&b.1));
+        let row_ids = results[0][ROW_ID].as_primitive::<UInt64Type>();
+        assert_eq!(
+            row_ids.values(),
+            expected[..5]
+                .iter()
+                .map(|(id, _)| *id as u64)
+                .collect::<Vec<_>>()
+                .as_slice()
+        );
+    }
+
     #[test]
     fn test_create_knn_flat() {
         let dim: usize = 128;
@@ -654,6 +1004,13 @@ mod tests {
             k: 10,
             nprobes: 0,
             refine_factor: None,
+            ef_search: None,
+            beam_width: None,
+            target_recall: None,
+            max_probe_rows: None,
+            trace: None,
+            lower_bound: None,
+            upper_bound: None,
             metric_type: MetricType::L2,
             use_index: false,
         };
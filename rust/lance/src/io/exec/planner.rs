@@ -29,12 +29,16 @@ use datafusion::sql::sqlparser::ast::{
 };
 use datafusion::{
     common::Column,
-    logical_expr::{col, expr::ScalarFunction, BinaryExpr, BuiltinScalarFunction, Like, Operator},
+    logical_expr::{
+        col, expr::ScalarFunction, expr::ScalarUDF, Between, BinaryExpr, BuiltinScalarFunction,
+        Like, Operator,
+    },
     physical_expr::execution_props::ExecutionProps,
     physical_plan::PhysicalExpr,
     prelude::Expr,
     scalar::ScalarValue,
 };
+use lance_datafusion::json::{json_extract_udf, JsonPath, JSON_EXTRACT};
 use lance_index::scalar::expression::{
     apply_scalar_indices, IndexInformationProvider, ScalarIndexExpr,
 };
@@ -222,6 +226,96 @@ impl Planner {
                 fun: BuiltinScalarFunction::RegexpMatch,
                 args: args_vec,
             }));
+        } else if func.name.to_string() == "contains" {
+            if func.args.len() != 2 {
+                return Err(Error::IO {
+                    message: format!("contains only supports 2 args, got {}", func.args.len()),
+                    location: location!(),
+                });
+            }
+            let column = self.parse_function_args(&func.args[0])?;
+            let substring = match self.parse_function_args(&func.args[1])? {
+                Expr::Literal(ScalarValue::Utf8(Some(substring))) => substring,
+                arg => {
+                    return Err(Error::IO {
+                        message: format!("contains only supports a string literal, got {arg}"),
+                        location: location!(),
+                    })
+                }
+            };
+            // `LIKE` can use an n-gram index, but the wildcards can not be escaped.
+            if substring.contains(['%', '_']) {
+                let pattern = regex_escape(&substring);
+                return Ok(Expr::IsNotNull(Box::new(Expr::ScalarFunction(
+                    ScalarFunction {
+                        fun: BuiltinScalarFunction::RegexpMatch,
+                        args: vec![column, Expr::Literal(ScalarValue::Utf8(Some(pattern)))],
+                    },
+                ))));
+            }
+            return Ok(Expr::Like(Like::new(
+                false,
+                Box::new(column),
+                Box::new(Expr::Literal(ScalarValue::Utf8(Some(format!(
+                    "%{substring}%"
+                ))))),
+                None,
+                false,
+            )));
+        }
+        // The labels of a list column, e.g., `array_has_any(tags, ['a', 'b'])`.
+        let array_function = match func.name.to_string().as_str() {
+            "array_has" => Some(BuiltinScalarFunction::ArrayHas),
+            "array_has_any" => Some(BuiltinScalarFunction::ArrayHasAny),
+            "array_has_all" => Some(BuiltinScalarFunction::ArrayHasAll),
+            _ => None,
+        };
+        if let Some(fun) = array_function {
+            if func.args.len() != 2 {
+                return Err(Error::IO {
+                    message: format!(
+                        "{} only supports 2 args, got {}",
+                        func.name,
+                        func.args.len()
+                    ),
+                    location: location!(),
+                });
+            }
+            let args = func
+                .args
+                .iter()
+                .map(|arg| self.parse_function_args(arg))
+                .collect::<Result<Vec<_>>>()?;
+            return Ok(Expr::ScalarFunction(ScalarFunction { fun, args }));
+        }
+        // The value at a JSON path of a JSON string, e.g., `json_extract(metadata, '$.type')`.
+        if func.name.to_string() == JSON_EXTRACT {
+            if func.args.len() != 2 {
+                return Err(Error::IO {
+                    message: format!(
+                        "{JSON_EXTRACT} only supports 2 args, got {}",
+                        func.args.len()
+                    ),
+                    location: location!(),
+                });
+            }
+            let json = self.parse_function_args(&func.args[0])?;
+            let path = match self.parse_function_args(&func.args[1])? {
+                Expr::Literal(ScalarValue::Utf8(Some(path))) => path,
+                arg => {
+                    return Err(Error::IO {
+                        message: format!(
+                            "{JSON_EXTRACT} only supports a string literal path, got {arg}"
+                        ),
+                        location: location!(),
+                    })
+                }
+            };
+            JsonPath::try_new(&path)?;
+            return Ok(Expr::ScalarUDF(ScalarUDF::new(
+                Arc::new(json_extract_udf()),
+                vec![json, Expr::Literal(ScalarValue::Utf8(Some(path)))],
+            )));
         }
         Err(Error::IO {
             message: format!("function '{}' is not supported", func.name),
@@ -370,7 +464,27 @@ impl Planner {
                     .collect::<Result<Vec<_>>>()?;
                 Ok(value_expr.in_list(list_exprs, *negated))
             }
+            SQLExpr::Between {
+                expr,
+                negated,
+                low,
+                high,
+            } => Ok(Expr::Between(Between::new(
+                Box::new(self.parse_sql_expr(expr)?),
+                *negated,
+                Box::new(self.parse_sql_expr(low)?),
+                Box::new(self.parse_sql_expr(high)?),
+            ))),
             SQLExpr::Nested(inner) => self.parse_sql_expr(inner.as_ref()),
+            // For example, ['a', 'b']
+            SQLExpr::Array(array) => Ok(Expr::ScalarFunction(ScalarFunction {
+                fun: BuiltinScalarFunction::MakeArray,
+                args: array
+                    .elem
+                    .iter()
+                    .map(|e| self.parse_sql_expr(e))
+                    .collect::<Result<Vec<_>>>()?,
+            })),
             SQLExpr::Function(func) => self.parse_function(func),
             SQLExpr::ILike {
                 negated,
@@ -421,6 +535,17 @@ impl Planner {
         coerce_filter_type_to_boolean(resolved)
     }
 
+    /// Create Logical [Expr] from a SQL expression, e.g., `price * 1.1`.
+    ///
+    /// Note: the returned expression must be passed through [optimize_expr()]
+    /// before being passed to [create_physical_expr()].
+    pub fn parse_expr(&self, expr: &str) -> Result<Expr> {
+        let ast_expr = parse_sql_filter(expr)?;
+        let expr = self.parse_sql_expr(&ast_expr)?;
+        let schema = Schema::try_from(self.schema.as_ref())?;
+        resolve_expr(&expr, &schema)
+    }
+
     /// Optimize the filter expression and coerce data types.
     pub fn optimize_expr(&self, expr: Expr) -> Result<Expr> {
         let df_schema = Arc::new(DFSchema::try_from(self.schema.as_ref().clone())?);
@@ -449,6 +574,16 @@ impl Planner {
         )?)
     }
 
+    /// The expression of a column, or of a field of a struct column, e.g., `metadata.source`.
+    pub fn column_path_expr(path: &str) -> Expr {
+        let mut names = path.split('.');
+        let mut column = Expr::Column(Column::new_unqualified(names.next().unwrap_or_default()));
+        for name in names {
+            column = column.field(name);
+        }
+        column
+    }
+
     /// Collect the columns in the expression.
     ///
     /// The columns are returned in sorted order.
@@ -490,6 +625,18 @@ impl Planner {
     }
 }
 
+/// Escape the characters of `text` which have a meaning in a regular expression.
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
 struct ColumnCapturingVisitor {
     // Current column path. If this is empty, we are not in a column expression.
     current_path: VecDeque<String>,
@@ -532,6 +679,7 @@ mod tests {
     use std::sync::Arc;
 
     use arrow_array::{
+        builder::{ListBuilder, StringBuilder},
         ArrayRef, BooleanArray, Float32Array, Int32Array, Int64Array, RecordBatch, StringArray,
         StructArray, TimestampMicrosecondArray, TimestampMillisecondArray,
         TimestampNanosecondArray, TimestampSecondArray,
@@ -775,6 +923,147 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sql_contains() {
+        let schema = Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)]));
+
+        let planner = Planner::new(schema.clone());
+
+        let expr = planner.parse_filter("contains(s, 'r-1')").unwrap();
+        assert_eq!(expr, col("s").like(lit("%r-1%")));
+
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(StringArray::from(vec![
+                Some("str-1"),
+                Some("str-2"),
+                None,
+                Some("str_1.5%"),
+                Some("strx1.5%"),
+            ]))],
+        )
+        .unwrap();
+        let predicates = planner
+            .create_physical_expr(&expr)
+            .unwrap()
+            .evaluate(&batch)
+            .unwrap();
+        assert_eq!(
+            predicates.into_array(0).as_ref(),
+            &BooleanArray::from(vec![
+                Some(true),
+                Some(false),
+                None,
+                Some(false),
+                Some(false)
+            ])
+        );
+
+        // The wildcards of LIKE are matched literally.
+        let expr = planner.parse_filter("contains(s, 'r_1.5%')").unwrap();
+        let predicates = planner
+            .create_physical_expr(&expr)
+            .unwrap()
+            .evaluate(&batch)
+            .unwrap();
+        assert_eq!(
+            predicates.into_array(0).as_ref(),
+            &BooleanArray::from(vec![false, false, false, true, false])
+        );
+    }
+
+    #[test]
+    fn test_sql_array_has() {
+        let schema = Arc::new(Schema::new(vec![Field::new(
+            "tags",
+            DataType::List(Arc::new(Field::new("item", DataType::Utf8, true))),
+            true,
+        )]));
+
+        let planner = Planner::new(schema.clone());
+
+        let mut builder = ListBuilder::new(StringBuilder::new());
+        for list in [vec!["a", "b"], vec!["b"], vec![], vec!["c", "a"]] {
+            for tag in list {
+                builder.values().append_value(tag);
+            }
+            builder.append(true);
+        }
+        let batch = RecordBatch::try_new(schema, vec![Arc::new(builder.finish())]).unwrap();
+
+        for (filter, expected) in [
+            (
+                "array_has_any(tags, ['a', 'b'])",
+                vec![true, true, false, true],
+            ),
+            (
+                "array_has_all(tags, ['a', 'b'])",
+                vec![true, false, false, false],
+            ),
+            ("array_has(tags, 'c')", vec![false, false, false, true]),
+        ] {
+            let expr = planner.parse_filter(filter).unwrap();
+            let expr = planner.optimize_expr(expr).unwrap();
+            let predicates = planner
+                .create_physical_expr(&expr)
+                .unwrap()
+                .evaluate(&batch)
+                .unwrap();
+            assert_eq!(
+                predicates.into_array(0).as_ref(),
+                &BooleanArray::from(expected),
+                "{filter}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sql_json_extract() {
+        let schema = Arc::new(Schema::new(vec![Field::new("m", DataType::Utf8, true)]));
+
+        let planner = Planner::new(schema.clone());
+
+        let batch = RecordBatch::try_new(
+            schema,
+            vec![Arc::new(StringArray::from(vec![
+                Some(r#"{"type": "a", "n": 1}"#),
+                Some(r#"{"type": "b"}"#),
+                None,
+                Some("{}"),
+            ]))],
+        )
+        .unwrap();
+
+        for (filter, expected) in [
+            (
+                "json_extract(m, '$.type') = 'a'",
+                vec![Some(true), Some(false), None, None],
+            ),
+            (
+                "json_extract(m, '$.n') IS NULL",
+                vec![Some(false), Some(true), Some(true), Some(true)],
+            ),
+        ] {
+            let expr = planner.parse_filter(filter).unwrap();
+            let expr = planner.optimize_expr(expr).unwrap();
+            let predicates = planner
+                .create_physical_expr(&expr)
+                .unwrap()
+                .evaluate(&batch)
+                .unwrap();
+            assert_eq!(
+                predicates.into_array(0).as_ref(),
+                &BooleanArray::from(expected),
+                "{filter}"
+            );
+        }
+
+        assert!(planner
+            .parse_filter("json_extract(m, 'type') = 'a'")
+            .is_err());
+        assert!(planner.parse_filter("json_extract(m, m) = 'a'").is_err());
+    }
+
     #[test]
     fn test_sql_is_in() {
         let schema = Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)]));
@@ -803,6 +1092,37 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_sql_between() {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, true)]));
+
+        let planner = Planner::new(schema.clone());
+
+        let expected = col("i").between(lit(3_i64), lit(5_i64));
+        let expr = planner.parse_filter("i BETWEEN 3 AND 5").unwrap();
+        assert_eq!(expr, expected);
+        let expr = planner.optimize_expr(expr).unwrap();
+        let physical_expr = planner.create_physical_expr(&expr).unwrap();
+
+        let batch =
+            RecordBatch::try_new(schema, vec![Arc::new(Int32Array::from_iter_values(0..8))])
+                .unwrap();
+        let predicates = physical_expr.evaluate(&batch).unwrap();
+        assert_eq!(
+            predicates.into_array(0).as_ref(),
+            &BooleanArray::from(vec![false, false, false, true, true, true, false, false])
+        );
+
+        let expr = planner.parse_filter("i NOT BETWEEN 3 AND 5").unwrap();
+        let expr = planner.optimize_expr(expr).unwrap();
+        let physical_expr = planner.create_physical_expr(&expr).unwrap();
+        let predicates = physical_expr.evaluate(&batch).unwrap();
+        assert_eq!(
+            predicates.into_array(0).as_ref(),
+            &BooleanArray::from(vec![true, true, true, false, false, false, true, true])
+        );
+    }
+
     #[test]
     fn test_sql_is_null() {
         let schema = Arc::new(Schema::new(vec![Field::new("s", DataType::Utf8, true)]));
@@ -0,0 +1,246 @@
+// Copyright 2023 Lance Developers.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::any::Any;
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, RecordBatch, UInt64Array};
+use arrow_schema::SchemaRef;
+use arrow_select::{interleave::interleave, take::take};
+use datafusion::error::{DataFusionError, Result as DataFusionResult};
+use datafusion::execution::context::TaskContext;
+use datafusion::physical_plan::stream::RecordBatchStreamAdapter;
+use datafusion::physical_plan::{
+    DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, SendableRecordBatchStream,
+    Statistics,
+};
+use futures::{stream, StreamExt};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+/// [ExecutionPlan] that samples `n` rows of its input uniformly at random, with
+/// reservoir sampling.
+///
+/// The input is read once, and at most `n` rows are kept in memory. The sampled rows
+/// are returned in one batch, in the order of the input.
+#[derive(Debug)]
+pub struct SampleExec {
+    input: Arc<dyn ExecutionPlan>,
+    n: usize,
+    seed: Option<u64>,
+}
+
+impl SampleExec {
+    /// Sample `n` rows of `input`, with a random generator seeded with `seed`, or from
+    /// the OS if it is None.
+    pub fn new(input: Arc<dyn ExecutionPlan>, n: usize, seed: Option<u64>) -> Self {
+        Self { input, n, seed }
+    }
+}
+
+impl DisplayAs for SampleExec {
+    fn fmt_as(&self, t: DisplayFormatType, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match t {
+            DisplayFormatType::Default | DisplayFormatType::Verbose => {
+                write!(f, "Sample: n={}", self.n)
+            }
+        }
+    }
+}
+
+/// The rows sampled so far, and the position of each of them in the input.
+struct Reservoir {
+    columns: Vec<ArrayRef>,
+    positions: Vec<u64>,
+    num_seen: u64,
+    capacity: usize,
+    rng: SmallRng,
+}
+
+impl Reservoir {
+    fn new(schema: &SchemaRef, capacity: usize, seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        };
+        Self {
+            columns: RecordBatch::new_empty(schema.clone()).columns().to_vec(),
+            positions: Vec::with_capacity(capacity),
+            num_seen: 0,
+            capacity,
+            rng,
+        }
+    }
+
+    fn add(&mut self, batch: &RecordBatch) -> DataFusionResult<()> {
+        // Each slot refers to a row of the reservoir (0) or of the batch (1).
+        let mut slots = (0..self.positions.len())
+            .map(|i| (0, i))
+            .collect::<Vec<_>>();
+        for row in 0..batch.num_rows() {
+            if slots.len() < self.capacity {
+                slots.push((1, row));
+                self.positions.push(self.num_seen);
+            } else {
+                let slot = self.rng.gen_range(0..=self.num_seen) as usize;
+                if slot < self.capacity {
+                    slots[slot] = (1, row);
+                    self.positions[slot] = self.num_seen;
+                }
+            }
+            self.num_seen += 1;
+        }
+        self.columns = self
+            .columns
+            .iter()
+            .zip(batch.columns())
+            .map(|(sampled, column)| interleave(&[sampled.as_ref(), column.as_ref()], &slots))
+            .collect::<std::result::Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn finish(self, schema: SchemaRef) -> DataFusionResult<RecordBatch> {
+        let mut order = (0..self.positions.len() as u64).collect::<Vec<_>>();
+        order.sort_by_key(|i| self.positions[*i as usize]);
+        let order = UInt64Array::from(order);
+        let columns = self
+            .columns
+            .iter()
+            .map(|column| take(column.as_ref(), &order, None))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(RecordBatch::try_new(schema, columns)?)
+    }
+}
+
+impl ExecutionPlan for SampleExec {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn schema(&self) -> SchemaRef {
+        self.input.schema()
+    }
+
+    fn output_partitioning(&self) -> Partitioning {
+        Partitioning::RoundRobinBatch(1)
+    }
+
+    fn output_ordering(&self) -> Option<&[datafusion::physical_expr::PhysicalSortExpr]> {
+        self.input.output_ordering()
+    }
+
+    fn children(&self) -> Vec<Arc<dyn ExecutionPlan>> {
+        vec![self.input.clone()]
+    }
+
+    fn with_new_children(
+        self: Arc<Self>,
+        children: Vec<Arc<dyn ExecutionPlan>>,
+    ) -> DataFusionResult<Arc<dyn ExecutionPlan>> {
+        let [input] = children.as_slice() else {
+            return Err(DataFusionError::Internal(
+                "SampleExec node must have exactly one child".to_string(),
+            ));
+        };
+        Ok(Arc::new(Self::new(input.clone(), self.n, self.seed)))
+    }
+
+    fn execute(
+        &self,
+        partition: usize,
+        context: Arc<TaskContext>,
+    ) -> DataFusionResult<SendableRecordBatchStream> {
+        let mut input = self.input.execute(partition, context)?;
+        let schema = self.schema();
+        let mut reservoir = Reservoir::new(&schema, self.n, self.seed);
+        let sampled = async move {
+            while let Some(batch) = input.next().await {
+                reservoir.add(&batch?)?;
+            }
+            reservoir.finish(schema)
+        };
+        Ok(Box::pin(RecordBatchStreamAdapter::new(
+            self.schema(),
+            stream::once(sampled),
+        )))
+    }
+
+    fn statistics(&self) -> Statistics {
+        let num_rows = self
+            .input
+            .statistics()
+            .num_rows
+            .map(|num_rows| num_rows.min(self.n));
+        Statistics {
+            num_rows,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use arrow_array::{cast::AsArray, types::Int32Type, Int32Array};
+    use arrow_schema::{DataType, Field, Schema};
+    use datafusion::physical_plan::memory::MemoryExec;
+    use futures::TryStreamExt;
+
+    #[tokio::test]
+    async fn test_sample() {
+        let schema = Arc::new(Schema::new(vec![Field::new("i", DataType::Int32, false)]));
+        let batches = (0..10)
+            .map(|b| {
+                RecordBatch::try_new(
+                    schema.clone(),
+                    vec![Arc::new(Int32Array::from_iter_values(
+                        b * 100..(b + 1) * 100,
+                    ))],
+                )
+                .unwrap()
+            })
+            .collect::<Vec<_>>();
+        let input = Arc::new(MemoryExec::try_new(&[batches], schema.clone(), None).unwrap());
+
+        let sample = |n: usize, seed: u64| {
+            let exec = SampleExec::new(input.clone(), n, Some(seed));
+            async move {
+                let batches = exec
+                    .execute(0, Arc::new(TaskContext::default()))
+                    .unwrap()
+                    .try_collect::<Vec<_>>()
+                    .await
+                    .unwrap();
+                assert_eq!(batches.len(), 1);
+                batches[0]["i"]
+                    .as_primitive::<Int32Type>()
+                    .values()
+                    .to_vec()
+            }
+        };
+
+        let values = sample(50, 42).await;
+        assert_eq!(values.len(), 50);
+        // In the order of the input, without duplicates.
+        assert!(values.windows(2).all(|w| w[0] < w[1]));
+        // The samples are spread over the input.
+        assert!(values.iter().any(|v| *v < 500));
+        assert!(values.iter().any(|v| *v >= 500));
+        assert_eq!(sample(50, 42).await, values);
+        assert_ne!(sample(50, 7).await, values);
+
+        assert_eq!(sample(2000, 42).await, (0..1000).collect::<Vec<_>>());
+        assert!(sample(0, 42).await.is_empty());
+    }
+}
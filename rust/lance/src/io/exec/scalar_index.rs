@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::any::Any;
 use std::sync::Arc;
 
 use arrow_array::{RecordBatch, UInt64Array};
@@ -19,6 +20,7 @@ use arrow_schema::{DataType, Field, Schema, SchemaRef};
 use async_trait::async_trait;
 use datafusion::physical_plan::{
     DisplayAs, DisplayFormatType, ExecutionPlan, Partitioning, RecordBatchStream,
+    SendableRecordBatchStream,
 };
 use futures::{stream::BoxStream, Stream, StreamExt, TryFutureExt};
 use lance_core::{
@@ -27,11 +29,15 @@ use lance_core::{
 };
 use lance_index::scalar::{
     expression::{ScalarIndexExpr, ScalarIndexLoader},
-    ScalarIndex,
+    IndexStore, ScalarIndex, ScalarQuery,
 };
+use lance_index::{Index, IndexType};
+use nohash_hasher::IntMap;
 use pin_project::pin_project;
+use roaring::RoaringBitmap;
 use snafu::{location, Location};
 
+use crate::dataset::IndexRowIds;
 use crate::{index::DatasetIndexInternalExt, Dataset};
 
 lazy_static::lazy_static! {
@@ -40,15 +46,102 @@ lazy_static::lazy_static! {
 
 #[async_trait]
 impl ScalarIndexLoader for Dataset {
-    async fn load_index(&self, name: &str) -> Result<Arc<dyn ScalarIndex>> {
-        let idx = self
-            .load_scalar_index_for_column(name)
-            .await?
-            .ok_or_else(|| Error::Internal {
+    async fn load_indices(&self, name: &str) -> Result<Vec<Arc<dyn ScalarIndex>>> {
+        let deltas = self.load_scalar_index_deltas_for_column(name).await?;
+        if deltas.is_empty() {
+            return Err(Error::Internal {
                 message: format!("Scanner created plan for index query on {} but no index on dataset for that column", name),
                 location: location!()
-            })?;
-        self.open_scalar_index(name, &idx.uuid.to_string()).await
+            });
+        }
+        let mut indices = Vec::with_capacity(deltas.len());
+        for idx in deltas {
+            let index = self.open_scalar_index(name, &idx.uuid.to_string()).await?;
+            // The deltas cover different fragments, so each resolves its own row ids.
+            let index: Arc<dyn ScalarIndex> = match self.index_row_ids(&idx).await? {
+                Some(row_ids) => Arc::new(StableRowIdIndex { index, row_ids }),
+                None => index,
+            };
+            indices.push(index);
+        }
+        Ok(indices)
+    }
+}
+
+/// A scalar index of a dataset with stable row ids, which is keyed by the stable row
+/// ids, and whose search returns the addresses of the rows it still covers.
+#[derive(Debug)]
+struct StableRowIdIndex {
+    index: Arc<dyn ScalarIndex>,
+    row_ids: IndexRowIds,
+}
+
+#[async_trait]
+impl Index for StableRowIdIndex {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_index(self: Arc<Self>) -> Arc<dyn Index> {
+        self
+    }
+
+    fn statistics(&self) -> Result<String> {
+        self.index.statistics()
+    }
+
+    fn index_type(&self) -> IndexType {
+        self.index.index_type()
+    }
+
+    fn memory_size(&self) -> usize {
+        self.index.memory_size()
+    }
+
+    async fn calculate_included_frags(&self) -> Result<RoaringBitmap> {
+        Err(Error::NotSupported {
+            source: "Indices keyed by stable row ids do not know their fragments".into(),
+            location: location!(),
+        })
+    }
+}
+
+#[async_trait]
+impl ScalarIndex for StableRowIdIndex {
+    async fn search(&self, query: &ScalarQuery) -> Result<UInt64Array> {
+        let row_ids = self.index.search(query).await?;
+        Ok(row_ids
+            .values()
+            .iter()
+            .filter_map(|row_id| self.row_ids.address(*row_id))
+            .collect())
+    }
+
+    async fn load(_store: Arc<dyn IndexStore>) -> Result<Arc<Self>> {
+        Err(Error::NotSupported {
+            source: "Indices keyed by stable row ids are opened by the dataset".into(),
+            location: location!(),
+        })
+    }
+
+    async fn remap(
+        &self,
+        mapping: &IntMap<u64, Option<u64>>,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        self.index.remap(mapping, dest_store).await
+    }
+
+    async fn update(
+        &self,
+        new_data: SendableRecordBatchStream,
+        dest_store: &dyn IndexStore,
+    ) -> Result<()> {
+        self.index.update(new_data, dest_store).await
+    }
+
+    fn estimate_selectivity(&self, query: &ScalarQuery) -> Option<f64> {
+        self.index.estimate_selectivity(query)
     }
 }
 
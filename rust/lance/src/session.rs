@@ -12,10 +12,46 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::time::Duration;
+
 use lance_core::cache::FileMetadataCache;
 
 use crate::dataset::{DEFAULT_INDEX_CACHE_SIZE, DEFAULT_METADATA_CACHE_SIZE};
 use crate::index::cache::IndexCache;
+pub use crate::index::cache::IndexCacheStats;
+
+/// Configuration of the index cache.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexCacheConfig {
+    /// The maximum number of cached indices. Ignored if `max_bytes` is set.
+    pub max_entries: usize,
+
+    /// The maximum number of bytes held by the cached indices, as estimated by
+    /// [`lance_index::Index::memory_size`].
+    pub max_bytes: Option<usize>,
+
+    /// How long an index stays cached after it is loaded.
+    pub time_to_live: Option<Duration>,
+}
+
+impl Default for IndexCacheConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: DEFAULT_INDEX_CACHE_SIZE,
+            max_bytes: None,
+            time_to_live: None,
+        }
+    }
+}
+
+impl IndexCacheConfig {
+    pub fn new(max_entries: usize) -> Self {
+        Self {
+            max_entries,
+            ..Default::default()
+        }
+    }
+}
 
 /// A user session tracks the runtime state.
 #[derive(Clone)]
@@ -40,8 +76,19 @@ impl Session {
     ///
     /// - ***index_cache_size***: the size of the index cache.
     pub fn new(index_cache_size: usize, metadata_cache_size: usize) -> Self {
+        Self::with_index_cache_config(
+            &IndexCacheConfig::new(index_cache_size),
+            metadata_cache_size,
+        )
+    }
+
+    /// Create a new session with an index cache bounded by `index_cache_config`.
+    pub fn with_index_cache_config(
+        index_cache_config: &IndexCacheConfig,
+        metadata_cache_size: usize,
+    ) -> Self {
         Self {
-            index_cache: IndexCache::new(index_cache_size),
+            index_cache: IndexCache::new(index_cache_config),
             file_metadata_cache: FileMetadataCache::new(metadata_cache_size),
         }
     }
@@ -50,7 +97,7 @@ impl Session {
 impl Default for Session {
     fn default() -> Self {
         Self {
-            index_cache: IndexCache::new(DEFAULT_INDEX_CACHE_SIZE),
+            index_cache: IndexCache::new(&IndexCacheConfig::default()),
             file_metadata_cache: FileMetadataCache::new(DEFAULT_METADATA_CACHE_SIZE),
         }
     }
@@ -60,7 +107,7 @@ impl Default for Session {
 mod tests {
     use super::*;
 
-    use arrow_array::types::Float32Type;
+    use arrow_array::{types::Float32Type, UInt8Array};
     use std::sync::Arc;
 
     use crate::index::vector::pq::PQIndex;
@@ -132,4 +179,59 @@ mod tests {
         // Capacity is 10 so there should be at most 10 items
         assert_eq!(session.index_cache.len_vector(), 10);
     }
+
+    fn pq_index(num_codes: usize) -> Arc<PQIndex> {
+        let pq = Arc::new(ProductQuantizerImpl::<Float32Type>::new(
+            1,
+            8,
+            1,
+            Arc::new(vec![0.0f32; 8].into()),
+            MetricType::L2,
+        ));
+        let mut idx = PQIndex::new(pq, MetricType::L2);
+        idx.code = Some(Arc::new(UInt8Array::from(vec![0; num_codes])));
+        Arc::new(idx)
+    }
+
+    #[test]
+    fn test_index_cache_max_bytes() {
+        let config = IndexCacheConfig {
+            max_bytes: Some(16 * 1024),
+            ..Default::default()
+        };
+        let session = Session::with_index_cache_config(&config, 1);
+        for i in 0..64 {
+            session
+                .index_cache
+                .insert_vector(&i.to_string(), pq_index(1024));
+        }
+
+        let stats = session.index_cache.stats();
+        assert!(stats.entry_count > 0);
+        assert!(stats.entry_count < 16);
+        assert!(stats.size_bytes <= 16 * 1024);
+    }
+
+    #[test]
+    fn test_index_cache_ttl() {
+        let config = IndexCacheConfig {
+            time_to_live: Some(Duration::from_millis(100)),
+            ..Default::default()
+        };
+        let session = Session::with_index_cache_config(&config, 1);
+        session.index_cache.insert_vector("abc", pq_index(8));
+        assert!(session.index_cache.get_vector("abc").is_some());
+
+        std::thread::sleep(Duration::from_millis(200));
+        assert!(session.index_cache.get_vector("abc").is_none());
+        assert_eq!(
+            session.index_cache.stats(),
+            IndexCacheStats {
+                hits: 1,
+                misses: 1,
+                entry_count: 0,
+                size_bytes: 0,
+            }
+        );
+    }
 }